@@ -105,7 +105,20 @@ fn write_wavetable(wt: Wavetable, csv: &str, header: &str, fval: &str, fpval: &s
     }
 }
 
+/// Sample one period of `f` for the `fast-math` lookup tables, repeating the first point at the
+/// end.
+fn write_table(f: impl Fn(f32) -> f32, fval: &str) {
+    let vals: Vec<f32> = (0..=1024).map(|i| f(i as f32 / 1024.0)).collect();
+    File::create(fval)
+        .unwrap()
+        .write_all(vals.as_bytes())
+        .unwrap();
+}
+
 fn main() {
+    write_table(|x| (2.0 * PI * x).sin(), "wt/sin.lut");
+    write_table(|x| x.exp2(), "wt/exp2.lut");
+
     let mut sin = [0.0; 2048];
     for i in 0..2048 {
         sin[i] = (i as f32 * 2.0 * PI / 2048.0).sin();
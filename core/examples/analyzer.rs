@@ -0,0 +1,262 @@
+use apiary_core::{
+    time::{Duration, StdClock},
+    Module, Uuid, CHANNELS, SAMPLE_RATE,
+};
+use eframe::egui::{
+    self,
+    plot::{Line, Plot, Value, Values},
+};
+use rustfft::{num_complex::Complex, FftPlanner};
+use std::{
+    collections::VecDeque,
+    f32::consts::PI,
+    sync::{
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    common::{Jack, SelectedInterface},
+    display_module::DisplayHandler,
+};
+
+const FFT_SIZE: usize = 4096;
+const UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+const MIN_FREQ: f32 = 20.0;
+const FLOOR_DB: f32 = -120.0;
+const PROBE_RENEW_INTERVAL: Duration = Duration::from_secs(1);
+
+enum AnalyzerUpdate {
+    Input(bool),
+    Probe(Option<(Uuid, u32)>),
+}
+
+struct Spectrum {
+    averaging: f32,
+    peak_hold: bool,
+    avg: Vec<f32>,
+    peak: Vec<f32>,
+}
+
+impl Default for Spectrum {
+    fn default() -> Self {
+        Spectrum {
+            averaging: 0.8,
+            peak_hold: true,
+            avg: vec![FLOOR_DB; FFT_SIZE / 2],
+            peak: vec![FLOOR_DB; FFT_SIZE / 2],
+        }
+    }
+}
+
+pub struct Analyzer {
+    width: f32,
+    open: bool,
+    tx: Sender<AnalyzerUpdate>,
+    input_checked: bool,
+    probe_checked: bool,
+    probe_uuid: std::string::String,
+    probe_jack: u32,
+    data: Arc<Mutex<Spectrum>>,
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        let (ui_tx, ui_rx): (Sender<AnalyzerUpdate>, Receiver<AnalyzerUpdate>) = channel();
+        let data: Arc<Mutex<Spectrum>> = Default::default();
+        let thread_data = data.clone();
+
+        thread::spawn(move || {
+            let mut module: Module<_, _, 2, 0, _> = Module::with_clock(
+                SelectedInterface::new().unwrap(),
+                rand::thread_rng(),
+                "Analyzer".into(),
+                Default::default(),
+                StdClock::new(),
+            );
+            let input_jack = module.add_input_jack().unwrap();
+            let monitor_jack = module.add_monitor_jack().unwrap();
+            let mut probe: Option<(Uuid, u32)> = None;
+
+            let fft = FftPlanner::<f32>::new().plan_fft_forward(FFT_SIZE);
+            let window: Vec<f32> = (0..FFT_SIZE)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / FFT_SIZE as f32).cos())
+                .collect();
+            let mut history: VecDeque<f32> = VecDeque::from(vec![0.0; FFT_SIZE]);
+            let mut buffer = vec![Complex { re: 0.0, im: 0.0 }; FFT_SIZE];
+
+            'outer: loop {
+                while let Some(time) = module.next_tick() {
+                    match ui_rx.try_recv() {
+                        Ok(AnalyzerUpdate::Input(checked)) => {
+                            if let Err(e) = module.set_input_patch_enabled(input_jack, checked) {
+                                info!("Error in connecting jack: {:?}", e);
+                            }
+                        }
+                        Ok(AnalyzerUpdate::Probe(target)) => {
+                            if target.is_none() {
+                                if let Err(e) = module.probe_cancel(monitor_jack, time) {
+                                    info!("Error in cancelling probe: {:?}", e);
+                                }
+                            }
+                            probe = target;
+                        }
+                        Err(TryRecvError::Empty) => {}
+                        Err(TryRecvError::Disconnected) => break 'outer,
+                    }
+
+                    // Keep renewing the probe while it is enabled, otherwise it is torn down on
+                    // timeout
+                    if let Some((uuid, jack_id)) = &probe {
+                        if time.is_multiple_of(PROBE_RENEW_INTERVAL) && module.can_send() {
+                            if let Err(e) = module.probe(monitor_jack, uuid.clone(), *jack_id, time)
+                            {
+                                info!("Error in probing jack: {:?}", e);
+                            }
+                        }
+                    }
+
+                    let mut pkt = Default::default();
+                    module
+                        .poll(time, |block| {
+                            pkt = if probe.is_some() {
+                                *block.get_monitor(monitor_jack)
+                            } else {
+                                *block.get_input(input_jack)
+                            };
+                        })
+                        .unwrap();
+                    for frame in pkt.data {
+                        let mix = frame.data.iter().map(|x| *x as f32).sum::<f32>()
+                            / (CHANNELS as f32 * i16::MAX as f32);
+                        history.pop_front();
+                        history.push_back(mix);
+                    }
+
+                    if time.is_multiple_of(UPDATE_INTERVAL) {
+                        for ((b, h), w) in buffer.iter_mut().zip(history.iter()).zip(window.iter())
+                        {
+                            *b = Complex { re: h * w, im: 0.0 };
+                        }
+                        fft.process(&mut buffer);
+
+                        let mut spectrum = thread_data.lock().unwrap();
+                        let averaging = spectrum.averaging;
+                        let peak_hold = spectrum.peak_hold;
+                        let Spectrum { avg, peak, .. } = &mut *spectrum;
+                        for ((b, a), p) in buffer.iter().zip(avg.iter_mut()).zip(peak.iter_mut()) {
+                            // Normalize so that a full-scale sine reads 0 dB (Hann window has a
+                            // coherent gain of 0.5)
+                            let mag = b.norm() * 4.0 / FFT_SIZE as f32;
+                            let db = (20.0 * mag.log10()).max(FLOOR_DB);
+                            *a = averaging * *a + (1.0 - averaging) * db;
+                            *p = if peak_hold { p.max(*a) } else { *a };
+                        }
+                    }
+                }
+                thread::sleep(std::time::Duration::from_millis(0));
+            }
+        });
+
+        Analyzer {
+            width: 25.0,
+            open: true,
+            tx: ui_tx,
+            input_checked: false,
+            probe_checked: false,
+            probe_uuid: Default::default(),
+            probe_jack: 0,
+            data,
+        }
+    }
+}
+
+fn bin_values(vals: &[f32]) -> Vec<Value> {
+    vals.iter()
+        .enumerate()
+        .filter_map(|(i, v)| {
+            let freq = i as f32 * SAMPLE_RATE / FFT_SIZE as f32;
+            if freq < MIN_FREQ {
+                None
+            } else {
+                Some(Value::new(freq.log10() as f64, *v as f64))
+            }
+        })
+        .collect()
+}
+
+impl DisplayHandler for Analyzer {
+    fn width(&self) -> f32 {
+        self.width
+    }
+
+    fn name(&self) -> &str {
+        "Analyzer"
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn update(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Analyzer");
+        ui.add_space(20.0);
+
+        let mut spectrum = self.data.lock().unwrap();
+        Plot::new("analyzer_plot")
+            .width(350.0)
+            .height(350.0)
+            .include_y(FLOOR_DB)
+            .include_y(0.0)
+            .x_axis_formatter(|x, _| format!("{:.0} Hz", 10.0_f64.powf(x)))
+            .show(ui, |plot_ui| {
+                plot_ui.line(Line::new(Values::from_values(bin_values(&spectrum.avg))));
+                if spectrum.peak_hold {
+                    plot_ui.line(Line::new(Values::from_values(bin_values(&spectrum.peak))));
+                }
+            });
+
+        ui.add(egui::Slider::new(&mut spectrum.averaging, 0.0..=0.99).text("Averaging"));
+        if ui.checkbox(&mut spectrum.peak_hold, "Peak hold").clicked() && !spectrum.peak_hold {
+            spectrum.peak.fill(FLOOR_DB);
+        }
+        drop(spectrum);
+
+        if ui
+            .add(Jack::new(
+                &mut self.input_checked,
+                "Input",
+                Default::default(),
+            ))
+            .changed()
+        {
+            self.tx
+                .send(AnalyzerUpdate::Input(self.input_checked))
+                .unwrap();
+        }
+
+        ui.add_space(10.0);
+        ui.label("Probe");
+        ui.add_enabled_ui(!self.probe_checked, |ui| {
+            ui.text_edit_singleline(&mut self.probe_uuid);
+            ui.add(egui::DragValue::new(&mut self.probe_jack).prefix("Jack "));
+        });
+        if ui.checkbox(&mut self.probe_checked, "Enabled").changed() {
+            let target = if self.probe_checked {
+                let mut uuid = Uuid::new();
+                match uuid.push_str(&self.probe_uuid) {
+                    Ok(_) => Some((uuid, self.probe_jack)),
+                    Err(_) => {
+                        self.probe_checked = false;
+                        None
+                    }
+                }
+            } else {
+                None
+            };
+            self.tx.send(AnalyzerUpdate::Probe(target)).unwrap();
+        }
+    }
+}
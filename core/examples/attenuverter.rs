@@ -0,0 +1,77 @@
+use apiary_core::{
+    dsp::mix::attenuvert, BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE, CHANNELS,
+};
+
+use crate::display_module::DisplayModule;
+
+pub struct Attenuverter;
+
+const NUM_LANES: usize = 4;
+
+const GAIN_PARAM: usize = 0;
+const OFFSET_PARAM: usize = NUM_LANES;
+const NUM_PARAMS: usize = 2 * NUM_LANES;
+
+const IN_INPUT: usize = 0;
+const NUM_INPUTS: usize = NUM_LANES;
+
+const OUT_OUTPUT: usize = 0;
+const MIX_OUTPUT: usize = NUM_LANES;
+const NUM_OUTPUTS: usize = NUM_LANES + 1;
+
+impl Attenuverter {
+    pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
+        let mut module = DisplayModule::new().name(name);
+        for lane in 0..NUM_LANES {
+            module = module
+                .input(IN_INPUT + lane, &format!("Input {}", lane + 1))
+                .param(
+                    GAIN_PARAM + lane,
+                    -1.0,
+                    1.0,
+                    1.0,
+                    &format!("Gain {}", lane + 1),
+                    "",
+                    false,
+                )
+                .param(
+                    OFFSET_PARAM + lane,
+                    -1.0,
+                    1.0,
+                    0.0,
+                    &format!("Offset {}", lane + 1),
+                    "",
+                    false,
+                )
+                .output(OUT_OUTPUT + lane, &format!("Output {}", lane + 1));
+        }
+        module.output(MIX_OUTPUT, "Mix Out").start(Attenuverter)
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Attenuverter {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
+    ) {
+        let mut mix = [[0; CHANNELS]; BLOCK_SIZE];
+        for lane in 0..NUM_LANES {
+            for (i, input, output) in block.zip_io(IN_INPUT + lane, OUT_OUTPUT + lane) {
+                let gain = params.at(GAIN_PARAM + lane, i);
+                let offset = params.at(OFFSET_PARAM + lane, i);
+                let channels = input.channels().zip(output.channels_mut());
+                for ((x, y), mix) in channels.zip(mix[i].iter_mut()) {
+                    *y = attenuvert(*x, gain, offset);
+                    *mix += *y as i32;
+                }
+            }
+        }
+        for (frame, mix) in block.outputs()[MIX_OUTPUT].frames_mut().zip(mix) {
+            for (y, mix) in frame.channels_mut().zip(mix) {
+                *y = mix.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+            }
+        }
+    }
+}
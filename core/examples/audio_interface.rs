@@ -1,55 +1,247 @@
-use apiary_core::{softclip, AudioFrame, AudioPacket};
+use apiary_core::{
+    dsp::mix::{mixdown, mixdown_pairs},
+    softclip, BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE, SAMPLE_RATE,
+};
 use cpal::{
     traits::{DeviceTrait, HostTrait, StreamTrait},
-    Device, Sample, SampleFormat, Stream, StreamConfig,
+    BufferSize, Device, Host, HostId, Sample, SampleFormat, SampleRate, Stream, StreamConfig,
+    SupportedBufferSize,
 };
+use eframe::egui;
+use itertools::izip;
 use std::{
     error::Error,
     io,
     io::ErrorKind,
-    sync::mpsc::{sync_channel, Receiver, SyncSender, TryRecvError, TrySendError},
-    time::Instant,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::{channel, Receiver, Sender, TrySendError},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use crate::{
+    display_module::{DisplayModule, Renderer},
+    resampler::{frame_queue, FrameQueue, Resampler},
 };
 
-use crate::display_module::{DisplayModule, Processor};
+/// Number of extra devices that can be opened alongside the main one, each with its own input.
+const AUX_OUTPUTS: usize = 2;
+
+/// Sample rates offered in the settings. Anything other than the network rate is resampled on the
+/// way out.
+const SAMPLE_RATES: [u32; 4] = [44100, 48000, 88200, 96000];
+
+/// Buffer sizes offered in the settings, in frames, with `None` leaving it up to the host.
+const BUFFER_SIZES: [Option<u32>; 7] = [
+    None,
+    Some(32),
+    Some(64),
+    Some(128),
+    Some(256),
+    Some(512),
+    Some(1024),
+];
+
+/// Which output the main stream is opened on and how. ASIO shows up as a host on Windows when cpal
+/// is built with its `asio` feature.
+#[derive(Clone, Debug, PartialEq)]
+struct AudioSettings {
+    host: HostId,
+    device: Option<String>,
+    sample_rate: u32,
+    buffer_size: Option<u32>,
+    low_latency: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            host: cpal::default_host().id(),
+            device: None,
+            sample_rate: SAMPLE_RATE as u32,
+            buffer_size: None,
+            low_latency: false,
+        }
+    }
+}
+
+/// An open main output stream and the queue feeding it.
+struct Output {
+    // Only held to keep the stream playing
+    _stream: Stream,
+    channels: u16,
+    description: String,
+    audio_tx: FrameQueue<4>,
+}
+
+/// Tells the processor about queues for streams that were opened or closed from the ui.
+enum OutputUpdate {
+    Main(FrameQueue<4>),
+    Cue(Option<FrameQueue<2>>),
+    Aux(usize, Option<FrameQueue<2>>),
+}
+
+fn device_names(host: HostId) -> Vec<String> {
+    cpal::host_from_id(host)
+        .ok()
+        .and_then(|host| host.output_devices().ok())
+        .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+/// Opens and starts the main output stream, reporting the worst callback jitter it sees through
+/// `jitter`.
+fn open(settings: &AudioSettings, jitter: &Arc<AtomicU32>) -> Result<Output, Box<dyn Error>> {
+    let host = cpal::host_from_id(settings.host)?;
+    let device = match &settings.device {
+        Some(name) => host
+            .output_devices()?
+            .find(|d| d.name().map_or(false, |n| &n == name))
+            .ok_or(io::Error::new(ErrorKind::NotFound, "Device not found"))?,
+        None => host.default_output_device().ok_or(io::Error::new(
+            ErrorKind::NotFound,
+            "No default host device found",
+        ))?,
+    };
+    let sample_rate = SampleRate(settings.sample_rate);
+    let supported_config = device
+        .supported_output_configs()?
+        .find(|c| c.min_sample_rate() <= sample_rate && sample_rate <= c.max_sample_rate())
+        .ok_or(io::Error::new(
+            ErrorKind::NotFound,
+            "No supported configs found",
+        ))?
+        .with_sample_rate(sample_rate);
+
+    // Low latency asks for the smallest buffer the device allows, otherwise the requested size is
+    // kept inside what it supports
+    let buffer_size = match (supported_config.buffer_size(), settings.low_latency) {
+        (SupportedBufferSize::Range { min, .. }, true) => Some(*min),
+        (SupportedBufferSize::Range { min, max }, false) => {
+            settings.buffer_size.map(|b| b.clamp(*min, *max))
+        }
+        (SupportedBufferSize::Unknown, _) => settings.buffer_size,
+    };
+    let sample_format = supported_config.sample_format();
+    let mut config: StreamConfig = supported_config.into();
+    if let Some(frames) = buffer_size {
+        config.buffer_size = BufferSize::Fixed(frames);
+    }
+    let description = format!(
+        "{} ({}, {} Hz, {} channels, {} buffer)",
+        device.name()?,
+        settings.host.name(),
+        config.sample_rate.0,
+        config.channels,
+        buffer_size.map_or("default".into(), |b| format!("{} frame", b))
+    );
+    info!("Selecting device: {}", description);
+
+    // The stream keeps its queue half full, so low latency only keeps a block waiting instead
+    // of 10 ms
+    let (audio_tx, audio_rx) = frame_queue(
+        if settings.low_latency {
+            2 * BLOCK_SIZE
+        } else {
+            960
+        },
+        SAMPLE_RATE,
+        config.sample_rate.0 as f32,
+    );
+    let stream = match sample_format {
+        SampleFormat::F32 => run::<f32, 4>(&device, &config, audio_rx, jitter.clone()),
+        SampleFormat::I16 => run::<i16, 4>(&device, &config, audio_rx, jitter.clone()),
+        SampleFormat::U16 => run::<u16, 4>(&device, &config, audio_rx, jitter.clone()),
+    };
+    stream.play()?;
+
+    Ok(Output {
+        _stream: stream,
+        channels: config.channels,
+        description,
+        audio_tx,
+    })
+}
+
+/// Opens and starts a stream playing one stereo pair on a device at its default config, for the
+/// cue and aux outputs.
+fn open_pair(device: &Device) -> Result<(Stream, FrameQueue<2>), Box<dyn Error>> {
+    let config = device.default_output_config()?;
+    info!("Selecting device: {:?}: {:?}", device.name()?, config);
+    let (tx, rx) = frame_queue(960, SAMPLE_RATE, config.sample_rate().0 as f32);
+    let stream = match config.sample_format() {
+        SampleFormat::F32 => run::<f32, 2>(device, &config.into(), rx, Arc::default()),
+        SampleFormat::I16 => run::<i16, 2>(device, &config.into(), rx, Arc::default()),
+        SampleFormat::U16 => run::<u16, 2>(device, &config.into(), rx, Arc::default()),
+    };
+    stream.play()?;
+    Ok((stream, tx))
+}
+
+fn find_device(host: &Host, name: &str) -> Result<Device, Box<dyn Error>> {
+    Ok(host
+        .output_devices()?
+        .find(|d| d.name().map_or(false, |n| n.contains(name)))
+        .ok_or(io::Error::new(ErrorKind::NotFound, "Device not found"))?)
+}
 
-fn run<T>(device: &Device, config: &StreamConfig, audio_rx: Receiver<AudioFrame>) -> Stream
+/// Plays frames on a device, with channel `c` of each frame going to channel `c` of the device
+/// where it has it. The largest difference between when a callback ran and when it was due is
+/// stored in `jitter` in microseconds every ten seconds.
+fn run<T, const N: usize>(
+    device: &Device,
+    config: &StreamConfig,
+    mut audio_rx: Resampler<N>,
+    jitter: Arc<AtomicU32>,
+) -> Stream
 where
     T: Sample,
 {
+    let channels = config.channels as usize;
+    let sample_rate = config.sample_rate.0 as f32;
     let mut start = Instant::now();
-    let mut dropped_frames = 0;
+    let mut last_callback: Option<Instant> = None;
+    let mut max_jitter = Duration::ZERO;
     device
         .build_output_stream(
             &config,
             move |data: &mut [T], _| {
+                let now = Instant::now();
+                if let Some(last) = last_callback {
+                    // Each callback should follow the last by the length of the buffer it fills
+                    let expected =
+                        Duration::from_secs_f32((data.len() / channels) as f32 / sample_rate);
+                    let interval = now - last;
+                    max_jitter = max_jitter.max(if interval > expected {
+                        interval - expected
+                    } else {
+                        expected - interval
+                    });
+                }
+                last_callback = Some(now);
+                audio_rx.update();
                 if start.elapsed().as_secs() >= 10 {
+                    let dropped_frames = audio_rx.take_dropped_frames();
                     if dropped_frames != 0 {
                         info!("Audio dropped frames: {:?}", dropped_frames);
-                        dropped_frames = 0;
                     }
+                    info!(
+                        "Audio callback jitter: {:?}, drift: {:.1} ppm",
+                        max_jitter,
+                        audio_rx.drift()
+                    );
+                    jitter.store(max_jitter.as_micros() as u32, Ordering::Relaxed);
+                    max_jitter = Duration::ZERO;
                     start = Instant::now();
                 }
-                for i in 0..(data.len() / 2) {
-                    let sample = match audio_rx.try_recv() {
-                        Ok(v) => {
-                            let mut avg: f32 = 0.0;
-                            for val in v.data {
-                                avg += val as f32;
-                            }
-                            let sample = (softclip(avg / i16::MAX as f32) * i16::MAX as f32) as i16;
-                            Sample::from(&sample)
-                        }
-                        Err(TryRecvError::Empty) => {
-                            dropped_frames += 1;
-                            Sample::from(&0.0)
-                        }
-                        Err(TryRecvError::Disconnected) => {
-                            panic!("Audio channel disconnected")
-                        }
-                    };
-                    data[2 * i] = sample;
-                    data[2 * i + 1] = sample;
+                for frame in data.chunks_mut(channels) {
+                    let x = audio_rx.next_frame();
+                    for (channel, sample) in frame.iter_mut().enumerate() {
+                        let x = x.get(channel).copied().unwrap_or(0.0);
+                        *sample = Sample::from(&((softclip(x) * i16::MAX as f32) as i16));
+                    }
                 }
             },
             |err| info!("Audio stream error: {:?}", err),
@@ -60,94 +252,311 @@ where
 pub struct AudioInterface {
     time: i64,
     dropped_frames: i64,
-    audio_tx: SyncSender<AudioFrame>,
+    audio_tx: FrameQueue<4>,
+    cue_tx: Option<FrameQueue<2>>,
+    aux_tx: [Option<FrameQueue<2>>; AUX_OUTPUTS],
+    output_rx: Receiver<OutputUpdate>,
+}
+
+/// Device and buffer settings for the main output, and the devices picked for the cue and aux
+/// outputs. The streams live here on the ui thread, and opening one hands the processor its queue.
+struct OutputSettings {
+    applied: AudioSettings,
+    pending: AudioSettings,
+    devices: Vec<String>,
+    output: Output,
+    jitter: Arc<AtomicU32>,
+    /// Where the cue bus goes when the main device doesn't have channels 3 and 4 free for it
+    cue_device: Option<String>,
+    cue_stream: Option<Stream>,
+    cue_route: String,
+    aux_devices: [Option<String>; AUX_OUTPUTS],
+    aux_streams: [Option<Stream>; AUX_OUTPUTS],
+    output_tx: Sender<OutputUpdate>,
+}
+
+impl OutputSettings {
+    fn apply(&mut self) {
+        match open(&self.pending, &self.jitter) {
+            Ok(output) => {
+                let _ = self
+                    .output_tx
+                    .send(OutputUpdate::Main(output.audio_tx.clone()));
+                // Dropping the old stream closes it
+                self.output = output;
+                self.applied = self.pending.clone();
+                self.jitter.store(0, Ordering::Relaxed);
+                // The new device may or may not have channels 3 and 4 free for the cue bus
+                self.route_cue();
+            }
+            Err(err) => info!("Could not open audio device: {}", err),
+        }
+    }
+
+    /// Plays the cue bus on channels 3 and 4 of the main device if it has them, and otherwise on
+    /// the cue device.
+    fn route_cue(&mut self) {
+        self.cue_stream = None;
+        let _ = self.output_tx.send(OutputUpdate::Cue(None));
+        if self.output.channels >= 4 {
+            self.cue_route = "Cue on channels 3 and 4".into();
+        } else if let Some(name) = &self.cue_device {
+            let opened = cpal::host_from_id(self.applied.host)
+                .map_err(|err| err.into())
+                .and_then(|host| find_device(&host, name))
+                .and_then(|device| open_pair(&device));
+            match opened {
+                Ok((stream, tx)) => {
+                    self.cue_stream = Some(stream);
+                    let _ = self.output_tx.send(OutputUpdate::Cue(Some(tx)));
+                    self.cue_route = format!("Cue on {}", name);
+                }
+                Err(err) => {
+                    info!("Could not open cue device: {}", err);
+                    self.cue_device = None;
+                    self.cue_route = "Cue device unavailable".into();
+                }
+            }
+        } else {
+            self.cue_route = "No channels free for the cue bus".into();
+        }
+        info!("{}", self.cue_route);
+    }
+
+    fn open_aux(&mut self, aux: usize) {
+        // Close the old stream before telling the processor, so nothing is left waiting on it
+        self.aux_streams[aux] = None;
+        let _ = self.output_tx.send(OutputUpdate::Aux(aux, None));
+        let name = match &self.aux_devices[aux] {
+            Some(name) => name,
+            None => return,
+        };
+        let opened = cpal::host_from_id(self.applied.host)
+            .map_err(|err| err.into())
+            .and_then(|host| find_device(&host, name))
+            .and_then(|device| open_pair(&device));
+        match opened {
+            Ok((stream, tx)) => {
+                self.aux_streams[aux] = Some(stream);
+                let _ = self.output_tx.send(OutputUpdate::Aux(aux, Some(tx)));
+            }
+            Err(err) => {
+                info!("Could not open audio device: {}", err);
+                self.aux_devices[aux] = None;
+            }
+        }
+    }
+}
+
+impl Renderer<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for OutputSettings {
+    fn render(
+        &mut self,
+        _disp: &mut DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS>,
+        ui: &mut egui::Ui,
+    ) {
+        let host = self.pending.host;
+        egui::ComboBox::from_label("Host")
+            .selected_text(host.name())
+            .show_ui(ui, |ui| {
+                for id in cpal::available_hosts() {
+                    ui.selectable_value(&mut self.pending.host, id, id.name());
+                }
+            });
+        if self.pending.host != host {
+            self.pending.device = None;
+            self.devices = device_names(self.pending.host);
+        }
+        egui::ComboBox::from_label("Device")
+            .selected_text(self.pending.device.as_deref().unwrap_or("Default"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.pending.device, None, "Default");
+                for name in &self.devices {
+                    ui.selectable_value(&mut self.pending.device, Some(name.clone()), name);
+                }
+            });
+        egui::ComboBox::from_label("Sample Rate")
+            .selected_text(format!("{} Hz", self.pending.sample_rate))
+            .show_ui(ui, |ui| {
+                for rate in SAMPLE_RATES {
+                    ui.selectable_value(
+                        &mut self.pending.sample_rate,
+                        rate,
+                        format!("{} Hz", rate),
+                    );
+                }
+            });
+        ui.add_enabled_ui(!self.pending.low_latency, |ui| {
+            egui::ComboBox::from_label("Buffer Size")
+                .selected_text(buffer_size_text(self.pending.buffer_size))
+                .show_ui(ui, |ui| {
+                    for size in BUFFER_SIZES {
+                        ui.selectable_value(
+                            &mut self.pending.buffer_size,
+                            size,
+                            buffer_size_text(size),
+                        );
+                    }
+                });
+        });
+        ui.checkbox(&mut self.pending.low_latency, "Low Latency");
+        if ui
+            .add_enabled(self.pending != self.applied, egui::Button::new("Apply"))
+            .clicked()
+        {
+            self.apply();
+        }
+        ui.label(&self.output.description);
+        ui.label(format!(
+            "Callback jitter: {} µs",
+            self.jitter.load(Ordering::Relaxed)
+        ));
+
+        ui.add_space(20.0);
+        let selected = self.cue_device.clone();
+        egui::ComboBox::from_label("Cue Device")
+            .selected_text(selected.as_deref().unwrap_or("None"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut self.cue_device, None, "None");
+                for name in &self.devices {
+                    ui.selectable_value(&mut self.cue_device, Some(name.clone()), name);
+                }
+            });
+        if self.cue_device != selected {
+            self.route_cue();
+        }
+        ui.label(&self.cue_route);
+
+        for aux in 0..AUX_OUTPUTS {
+            let selected = self.aux_devices[aux].clone();
+            egui::ComboBox::from_label(format!("Aux {}", aux + 1))
+                .selected_text(selected.as_deref().unwrap_or("None"))
+                .show_ui(ui, |ui| {
+                    ui.selectable_value(&mut self.aux_devices[aux], None, "None");
+                    for name in &self.devices {
+                        ui.selectable_value(&mut self.aux_devices[aux], Some(name.clone()), name);
+                    }
+                });
+            if self.aux_devices[aux] != selected {
+                self.open_aux(aux);
+            }
+        }
+    }
 }
 
-const NUM_PARAMS: usize = 0;
+fn buffer_size_text(size: Option<u32>) -> String {
+    size.map_or("Default".into(), |frames| format!("{} frames", frames))
+}
+
+const CUE_LEVEL_PARAM: usize = 0;
+const NUM_PARAMS: usize = 1;
 
 const IN_INPUT: usize = 0;
-const NUM_INPUTS: usize = 1;
+const STEREO_INPUT: usize = 1;
+const CUE_INPUT: usize = 2;
+const AUX_INPUT: usize = 3;
+const AUDITION_INPUT: usize = AUX_INPUT + AUX_OUTPUTS;
+const NUM_INPUTS: usize = AUDITION_INPUT + 1;
 
 const NUM_OUTPUTS: usize = 0;
 
 impl AudioInterface {
     pub fn init() -> Result<DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS>, Box<dyn Error>> {
-        let host = cpal::default_host();
-        let mut found_device = None;
-        for d in host.output_devices()? {
-            info!("{:?}", d.name()?);
+        let settings = AudioSettings::default();
+        let devices = device_names(settings.host);
+        for name in &devices {
+            info!("{:?}", name);
         }
-        // for d in host.output_devices()? {
-        //     if d.name()?.contains("CABLE") {
-        //         found_device = Some(d);
-        //         break;
-        //     }
-        // }
-        let device = match found_device {
-            Some(d) => d,
-            None => host.default_output_device().ok_or(io::Error::new(
-                ErrorKind::NotFound,
-                "No default host device found",
-            ))?,
-        };
-        let mut configs = device.supported_output_configs()?;
-        let supported_config = configs
-            .next()
-            .ok_or(io::Error::new(
-                ErrorKind::NotFound,
-                "No supported configs found",
-            ))?
-            .with_max_sample_rate();
-        info!(
-            "Selecting device: {:?}: {:?}",
-            device.name()?,
-            supported_config
-        );
-
-        let sample_format = supported_config.sample_format();
-        let config = supported_config.into();
-
-        // Currently, the audio interface seems to be running every-so-slightly slower than the
-        // expected 48,000 Hz (Dropping 48 frames or 1 ms every ten seconds on average), so we
-        // increase the buffer size here to compensate.
-        let (audio_tx, audio_rx): (SyncSender<AudioFrame>, Receiver<AudioFrame>) =
-            sync_channel(960);
-
-        let audio_stream = match sample_format {
-            SampleFormat::F32 => run::<f32>(&device, &config, audio_rx),
-            SampleFormat::I16 => run::<i16>(&device, &config, audio_rx),
-            SampleFormat::U16 => run::<u16>(&device, &config, audio_rx),
-        };
+        let jitter = Arc::new(AtomicU32::new(0));
+        let output = open(&settings, &jitter)?;
+        let audio_tx = output.audio_tx.clone();
+        let (output_tx, output_rx) = channel();
 
-        audio_stream.play()?;
+        let mut output_settings = OutputSettings {
+            applied: settings.clone(),
+            pending: settings,
+            devices,
+            output,
+            jitter,
+            cue_device: None,
+            cue_stream: None,
+            cue_route: String::new(),
+            aux_devices: Default::default(),
+            aux_streams: Default::default(),
+            output_tx,
+        };
+        output_settings.route_cue();
 
-        Ok(DisplayModule::new()
+        let mut module = DisplayModule::new()
             .name("Audio Interface")
             .input(IN_INPUT, "Input")
-            .stream_store(audio_stream)
-            .start(AudioInterface {
-                time: 0,
-                dropped_frames: 0,
-                audio_tx,
-            }))
+            .input(STEREO_INPUT, "Stereo Input")
+            .input(CUE_INPUT, "Cue")
+            .audition(AUDITION_INPUT)
+            .param(CUE_LEVEL_PARAM, 0.0, 1.0, 1.0, "Cue Level", "", false);
+        for aux in 0..AUX_OUTPUTS {
+            module = module.input(AUX_INPUT + aux, &format!("Aux {}", aux + 1));
+        }
+
+        Ok(module.renderer(output_settings).start(AudioInterface {
+            time: 0,
+            dropped_frames: 0,
+            audio_tx,
+            cue_tx: None,
+            aux_tx: Default::default(),
+            output_rx,
+        }))
     }
 }
 
 impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for AudioInterface {
     fn process(
         &mut self,
-        input: [&AudioPacket; NUM_INPUTS],
-        _output: &mut [AudioPacket; NUM_OUTPUTS],
-        _params: &[f32; NUM_PARAMS],
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
     ) {
+        let input = block.inputs();
+        // Pick up the queues for outputs opened from the ui
+        while let Ok(update) = self.output_rx.try_recv() {
+            match update {
+                OutputUpdate::Main(audio_tx) => self.audio_tx = audio_tx,
+                OutputUpdate::Cue(cue_tx) => self.cue_tx = cue_tx,
+                OutputUpdate::Aux(aux, aux_tx) => self.aux_tx[aux] = aux_tx,
+            }
+        }
         if self.time % 10000 == 0 {
             if self.dropped_frames != 0 {
                 info!("Module dropped frames: {:?}", self.dropped_frames);
                 self.dropped_frames = 0;
             }
         }
-        for frame in input[IN_INPUT].data {
-            match self.audio_tx.try_send(frame) {
+        for (i, (frame, stereo, cue, audition)) in izip!(
+            input[IN_INPUT].data,
+            input[STEREO_INPUT].data,
+            input[CUE_INPUT].data,
+            input[AUDITION_INPUT].data
+        )
+        .enumerate()
+        {
+            // Mono input goes to both sides, and every pair of the stereo input is summed in
+            let mono = mixdown(&frame);
+            let (left, right) = mixdown_pairs(&stereo);
+            // Whatever output is held down on the network is heard on the cue bus too
+            let cue = (mixdown(&cue) + mixdown(&audition)) * params.at(CUE_LEVEL_PARAM, i);
+            if let Some(cue_tx) = &self.cue_tx {
+                // The cue device runs on its own clock, so it just drops frames when behind
+                let _ = cue_tx.try_send([cue, cue]);
+            }
+            for (aux, aux_tx) in self.aux_tx.iter().enumerate() {
+                if let Some(aux_tx) = aux_tx {
+                    let x = mixdown(&input[AUX_INPUT + aux].data[i]);
+                    let _ = aux_tx.try_send([x, x]);
+                }
+            }
+            match self
+                .audio_tx
+                .try_send([mono + left, mono + right, cue, cue])
+            {
                 Ok(()) => {}
                 Err(TrySendError::Full(_)) => {
                     self.dropped_frames += 1;
@@ -12,6 +12,11 @@ use apiary_core::socket_native::NativeInterface;
 #[cfg(feature = "network-native")]
 pub type SelectedInterface<const I: usize, const O: usize> = NativeInterface<I, O>;
 
+#[cfg(feature = "network-ipc")]
+use apiary_core::socket_ipc::IpcInterface;
+#[cfg(feature = "network-ipc")]
+pub type SelectedInterface<const I: usize, const O: usize> = IpcInterface<I, O>;
+
 pub struct Jack<'a> {
     on: &'a mut bool,
     text: egui::WidgetText,
@@ -0,0 +1,85 @@
+use apiary_core::{
+    dsp::logic::{gate, Comparator as GateComparator, WindowComparator},
+    BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE, CHANNELS,
+};
+
+use crate::display_module::DisplayModule;
+
+pub struct Comparator {
+    comparators: [GateComparator; CHANNELS],
+    windows: [WindowComparator; CHANNELS],
+}
+
+const THRESHOLD_PARAM: usize = 0;
+const HYSTERESIS_PARAM: usize = 1;
+const WIDTH_PARAM: usize = 2;
+const NUM_PARAMS: usize = 3;
+
+const IN_INPUT: usize = 0;
+const THRESHOLD_INPUT: usize = 1;
+const WIDTH_INPUT: usize = 2;
+const NUM_INPUTS: usize = 3;
+
+const GATE_OUTPUT: usize = 0;
+const INSIDE_OUTPUT: usize = 1;
+const ABOVE_OUTPUT: usize = 2;
+const BELOW_OUTPUT: usize = 3;
+const NUM_OUTPUTS: usize = 4;
+
+impl Comparator {
+    pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
+        DisplayModule::new()
+            .name(name)
+            .param(THRESHOLD_PARAM, -1.0, 1.0, 0.0, "Threshold", "", false)
+            .param(HYSTERESIS_PARAM, 0.0, 0.5, 0.02, "Hysteresis", "", false)
+            .param(WIDTH_PARAM, 0.0, 1.0, 0.5, "Window", "", false)
+            .input(IN_INPUT, "Input")
+            .input(THRESHOLD_INPUT, "Threshold")
+            .input(WIDTH_INPUT, "Window")
+            .output(GATE_OUTPUT, "Gate")
+            .output(INSIDE_OUTPUT, "Inside")
+            .output(ABOVE_OUTPUT, "Above")
+            .output(BELOW_OUTPUT, "Below")
+            .start(Comparator {
+                comparators: Default::default(),
+                windows: Default::default(),
+            })
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Comparator {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
+    ) {
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            for j in 0..CHANNELS {
+                let x = input[IN_INPUT].data[i].data[j];
+                let threshold = params.at(THRESHOLD_PARAM, i)
+                    + input[THRESHOLD_INPUT].data[i].data[j] as f32 / i16::MAX as f32;
+                let hysteresis = params.at(HYSTERESIS_PARAM, i);
+                let width = (params.at(WIDTH_PARAM, i)
+                    + input[WIDTH_INPUT].data[i].data[j] as f32 / i16::MAX as f32)
+                    .max(0.0);
+
+                output[GATE_OUTPUT].data[i].data[j] =
+                    gate(self.comparators[j].process(x, threshold, hysteresis));
+
+                // The window is centered on the threshold
+                let window = self.windows[j].process(
+                    x,
+                    threshold - width / 2.0,
+                    threshold + width / 2.0,
+                    hysteresis,
+                );
+                output[INSIDE_OUTPUT].data[i].data[j] = window.inside;
+                output[ABOVE_OUTPUT].data[i].data[j] = window.above;
+                output[BELOW_OUTPUT].data[i].data[j] = window.below;
+            }
+        }
+    }
+}
@@ -1,25 +1,42 @@
-use apiary_core::{AudioPacket, Module};
-use cpal::Stream;
+use apiary_core::{
+    knob_position, knob_value, time::MonotonicTime, CatchUpPolicy, CompareAction, InputJackHandle,
+    Module, ModuleDescription, ModuleSpec, OutputJackHandle, ParamBlock, ParamChange,
+    ParamDescription, Processor, RamStorage,
+};
 use eframe::egui;
 use palette::Srgb;
-use rand::Rng;
-use std::{
-    iter::zip,
-    sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError, TrySendError},
-    thread,
-    time::{Duration, Instant},
+use rand::{rngs::ThreadRng, Rng};
+use std::sync::{
+    mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError, TrySendError},
+    Arc,
+};
+
+use crate::{
+    common::{Jack, Knob, SelectedInterface},
+    preset::{ModulePreset, ParamMeta, WindowPreset},
+    realtime::Priority,
+    scheduler::{self, Task, TaskLoad},
 };
 
-use crate::common::{Jack, Knob, SelectedInterface};
+/// Blocks sent per millisecond while catching up after the scheduler falls behind
+const CATCH_UP_POLLS: u32 = 2;
 
 pub struct DisplayModule<const I: usize, const O: usize, const P: usize> {
     name: String,
     color: u16,
     width: f32,
+    latency_compensation: bool,
+    priority: Priority,
+    audition: Option<usize>,
     open: bool,
     tx: Option<Sender<PatchUpdate>>,
     rx: Option<Receiver<([Srgb<u8>; I], [Srgb<u8>; O])>>,
-    s: Option<Stream>,
+    remote_rx: Option<Receiver<RemoteUpdate>>,
+    ab: AbCompare,
+    load: Option<Arc<TaskLoad>>,
+    /// Whether the module is muted and bypassed, as last heard from it
+    muted: bool,
+    bypassed: bool,
     renderer: Option<Box<dyn Renderer<I, O, P>>>,
     params: Vec<Option<Param>>,
     inputs: Vec<String>,
@@ -36,11 +53,18 @@ impl<const I: usize, const O: usize, const P: usize> DisplayModule<I, O, P> {
         DisplayModule {
             name: "".into(),
             width: 5.0,
+            latency_compensation: false,
+            priority: Priority::Realtime,
+            audition: None,
             color: rng.gen_range(0..360),
             open: true,
             tx: None,
             rx: None,
-            s: None,
+            remote_rx: None,
+            ab: Default::default(),
+            load: None,
+            muted: false,
+            bypassed: false,
             renderer: None,
             params: (0..P).map(|_| None).collect(),
             inputs: (0..I).map(|i| format!("Input {}", i)).collect(),
@@ -67,6 +91,29 @@ impl<const I: usize, const O: usize, const P: usize> DisplayModule<I, O, P> {
         self
     }
 
+    /// Line up inputs that arrive through paths of different lengths (see
+    /// `Module::set_latency_compensation`).
+    pub fn latency_compensation(mut self, enabled: bool) -> Self {
+        self.latency_compensation = enabled;
+        self
+    }
+
+    /// Run the processing at normal priority, for modules that only display or analyze and
+    /// shouldn't hold up the ones making audio.
+    pub fn priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    /// Use input `id` to hear whichever output is held down on the network (see
+    /// `Module::set_audition`), rather than as a jack of its own.
+    pub fn audition(mut self, id: usize) -> Self {
+        if id < I {
+            self.audition = Some(id);
+        }
+        self
+    }
+
     pub fn param(
         mut self,
         id: usize,
@@ -90,6 +137,21 @@ impl<const I: usize, const O: usize, const P: usize> DisplayModule<I, O, P> {
         self
     }
 
+    /// Take the color, jack names and knobs from a `define_module!` description.
+    pub fn spec(mut self, spec: &ModuleSpec<I, O, P>) -> Self {
+        self = self.color(spec.color);
+        for (id, name) in spec.inputs.iter().enumerate() {
+            self = self.input(id, name);
+        }
+        for (id, name) in spec.outputs.iter().enumerate() {
+            self = self.output(id, name);
+        }
+        for (id, p) in spec.params.iter().enumerate() {
+            self = self.param(id, p.min, p.max, p.default, p.name, p.unit, p.log);
+        }
+        self
+    }
+
     pub fn input(mut self, id: usize, name: &str) -> Self {
         if id < I {
             self.inputs[id] = name.into();
@@ -104,13 +166,6 @@ impl<const I: usize, const O: usize, const P: usize> DisplayModule<I, O, P> {
         self
     }
 
-    pub fn stream_store(mut self, s: Stream) -> Self {
-        // The handle to the audio interface is not able to be passed to the processing thread, so
-        // we need to have a place to keep it so that it does not drop when it falls out of scope.
-        self.s = Some(s);
-        self
-    }
-
     pub fn renderer<R>(mut self, renderer: R) -> Self
     where
         R: Renderer<I, O, P> + 'static,
@@ -125,19 +180,65 @@ impl<const I: usize, const O: usize, const P: usize> DisplayModule<I, O, P> {
     {
         let (ui_tx, ui_rx): (Sender<PatchUpdate>, Receiver<PatchUpdate>) = channel();
         let (color_tx, color_rx) = sync_channel(1);
+        let (remote_tx, remote_rx) = channel();
         self.tx = Some(ui_tx);
         self.rx = Some(color_rx);
+        self.remote_rx = Some(remote_rx);
         let name = self.name.clone();
         let mut params = [0.0; P];
+        let mut ranges = [(0.0, 1.0, false); P];
         for i in 0..P {
             if let Some(v) = &self.params[i] {
                 params[i] = v.val;
+                ranges[i] = (v.min, v.max, v.log);
             }
         }
-        thread::spawn(move || process(ui_rx, color_tx, &name, self.color, params, p));
+        let latency_compensation = self.latency_compensation;
+        let audition = self.audition;
+        let color = self.color;
+        let description = self.describe();
+        self.load = Some(scheduler::spawn(self.priority, move |time| {
+            Box::new(ModuleTask::new(
+                ui_rx,
+                color_tx,
+                remote_tx,
+                &name,
+                color,
+                latency_compensation,
+                audition,
+                description,
+                params,
+                ranges,
+                p,
+                time,
+            ))
+        }));
         self
     }
 
+    /// The front panel as seen from the manager's network panel. Windows opened more than once
+    /// are told apart by a number after the kind, which is left off.
+    fn describe(&self) -> ModuleDescription {
+        let kind = self.name.split(':').next().unwrap_or_default();
+        let mut description = ModuleDescription::new(kind, self.color);
+        for (id, name) in self.inputs.iter().enumerate() {
+            if self.audition != Some(id) {
+                description.add_input(name);
+            }
+        }
+        for name in &self.outputs {
+            description.add_output(name);
+        }
+        // Knobs are sent by position, so gaps are kept
+        for param in &self.params {
+            description.add_param(match param {
+                Some(p) => ParamDescription::new(&p.name, &p.unit, p.min, p.max, p.val, p.log),
+                None => ParamDescription::new("", "", 0.0, 1.0, 0.0, false),
+            });
+        }
+        description
+    }
+
     pub fn input_jack(&mut self, id: usize, ui: &mut egui::Ui) {
         if let Some(tx) = &self.tx {
             if ui
@@ -158,19 +259,80 @@ impl<const I: usize, const O: usize, const P: usize> DisplayModule<I, O, P> {
     pub fn param_knob(&mut self, id: usize, ui: &mut egui::Ui) {
         if let Some(tx) = &self.tx {
             if let Some(p) = &mut self.params[id] {
-                ui.add(Knob::new(
-                    &mut p.val,
-                    p.name.clone(),
-                    p.unit.clone(),
-                    p.min,
-                    p.max,
-                    p.log,
-                ));
-                self.open &= tx.send(PatchUpdate::Param(id, p.val)).is_ok();
+                if ui
+                    .add(Knob::new(
+                        &mut p.val,
+                        p.name.clone(),
+                        p.unit.clone(),
+                        p.min,
+                        p.max,
+                        p.log,
+                    ))
+                    .changed()
+                {
+                    self.open &= tx.send(PatchUpdate::Param(id, p.val)).is_ok();
+                }
             }
         }
     }
 
+    /// Take a step in comparing the knobs before and after an edit. Works on the same settings
+    /// as a preset, so anything a preset restores is compared too.
+    pub fn compare(&mut self, action: CompareAction) {
+        let current = self.save_preset();
+        let a = match action {
+            CompareAction::Store => {
+                self.ab = AbCompare {
+                    hidden: Some(current),
+                    showing_a: false,
+                };
+                return;
+            }
+            CompareAction::Compare => match self.ab.hidden.take() {
+                Some(hidden) => {
+                    self.load_preset(&hidden);
+                    self.ab = AbCompare {
+                        hidden: Some(current),
+                        showing_a: !self.ab.showing_a,
+                    };
+                    return;
+                }
+                None => return,
+            },
+            CompareAction::Revert => match self.ab.hidden.take() {
+                Some(_) if self.ab.showing_a => current,
+                Some(hidden) => hidden,
+                None => return,
+            },
+        };
+        self.load_preset(&a);
+        self.ab = AbCompare {
+            hidden: Some(a),
+            showing_a: false,
+        };
+    }
+
+    fn compare_buttons(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            if ui.button("Store A").clicked() {
+                self.compare(CompareAction::Store);
+            }
+            let label = if self.ab.showing_a { "A" } else { "B" };
+            if ui
+                .add_enabled(self.ab.hidden.is_some(), egui::Button::new(label))
+                .clicked()
+            {
+                self.compare(CompareAction::Compare);
+            }
+            if ui
+                .add_enabled(self.ab.hidden.is_some(), egui::Button::new("Revert"))
+                .clicked()
+            {
+                self.compare(CompareAction::Revert);
+            }
+        });
+    }
+
     pub fn output_jack(&mut self, id: usize, ui: &mut egui::Ui) {
         if let Some(tx) = &self.tx {
             if ui
@@ -198,66 +360,174 @@ struct Param {
     log: bool,
 }
 
-fn process<const I: usize, const O: usize, const P: usize, T: Processor<I, O, P>>(
+/// A knob's value with a modulation offset added along its travel.
+fn modulated(value: f32, offset: f32, (min, max, log): (f32, f32, bool)) -> f32 {
+    if offset == 0.0 {
+        value
+    } else {
+        knob_value(knob_position(value, min, max, log) + offset, min, max, log)
+    }
+}
+
+/// A module's processing, ticked by the shared scheduler.
+struct ModuleTask<const I: usize, const O: usize, const P: usize, T> {
+    module: Module<SelectedInterface<I, O>, ThreadRng, I, O>,
     rx: Receiver<PatchUpdate>,
     tx: SyncSender<([Srgb<u8>; I], [Srgb<u8>; O])>,
-    name: &str,
-    color: u16,
-    mut params: [f32; P],
-    mut p: T,
-) {
-    let start = Instant::now();
-    let mut time: i64 = 0;
-
-    let mut module: Module<_, _, I, O> = Module::new(
-        SelectedInterface::new().unwrap(),
-        rand::thread_rng(),
-        name.into(),
-        color,
-        time,
-    );
-    let input_handles = [0; I].map(|_| module.add_input_jack().unwrap());
-    let output_handles = [0; O].map(|_| module.add_output_jack().unwrap());
-
-    'outer: loop {
-        while time < start.elapsed().as_millis() as i64 {
-            match rx.try_recv() {
-                Ok(PatchUpdate::Input(id, on)) => {
-                    if let Err(e) = module.set_input_patch_enabled(input_handles[id], on) {
+    remote_tx: Sender<RemoteUpdate>,
+    /// Knob positions as last set from the ui or over the network, before modulation
+    knobs: [f32; P],
+    modulation: [f32; P],
+    params: ParamBlock<P>,
+    ranges: [(f32, f32, bool); P],
+    /// Empty for the audition input, which is a monitor jack instead
+    input_handles: [Option<InputJackHandle>; I],
+    output_handles: [OutputJackHandle; O],
+    /// Mute and bypass as last sent to the window
+    state: (bool, bool),
+    /// Presets of the knobs stored over the network, which only last as long as the window
+    storage: RamStorage<0>,
+    p: T,
+}
+
+impl<const I: usize, const O: usize, const P: usize, T: Processor<I, O, P>> ModuleTask<I, O, P, T> {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        rx: Receiver<PatchUpdate>,
+        tx: SyncSender<([Srgb<u8>; I], [Srgb<u8>; O])>,
+        remote_tx: Sender<RemoteUpdate>,
+        name: &str,
+        color: u16,
+        latency_compensation: bool,
+        audition: Option<usize>,
+        description: ModuleDescription,
+        params: [f32; P],
+        ranges: [(f32, f32, bool); P],
+        p: T,
+        time: MonotonicTime,
+    ) -> Self {
+        let mut module = Module::new(
+            SelectedInterface::new().unwrap(),
+            rand::thread_rng(),
+            name.into(),
+            color,
+            time,
+        );
+        module.set_latency_compensation(latency_compensation);
+        module.set_description(description);
+        module.set_catch_up(CatchUpPolicy {
+            max_polls_per_ms: CATCH_UP_POLLS,
+            skip_audio: false,
+        });
+        let mut input_handles = [None; I];
+        for (id, handle) in input_handles.iter_mut().enumerate() {
+            if audition == Some(id) {
+                let monitor = module.add_monitor_jack().unwrap();
+                module.set_audition(Some(monitor));
+            } else {
+                *handle = Some(module.add_input_jack().unwrap());
+            }
+        }
+        let output_handles = [0; O].map(|_| module.add_output_jack().unwrap());
+        ModuleTask {
+            module,
+            rx,
+            tx,
+            remote_tx,
+            knobs: params,
+            modulation: [0.0; P],
+            params: ParamBlock::new(params),
+            ranges,
+            input_handles,
+            output_handles,
+            state: (false, false),
+            storage: Default::default(),
+            p,
+        }
+    }
+}
+
+impl<const I: usize, const O: usize, const P: usize, T: Processor<I, O, P>> Task
+    for ModuleTask<I, O, P, T>
+{
+    fn tick(&mut self, time: MonotonicTime, now: MonotonicTime) -> bool {
+        self.module.set_now(now);
+        match self.rx.try_recv() {
+            Ok(PatchUpdate::Input(id, on)) => {
+                if let Some(handle) = self.input_handles[id] {
+                    if let Err(e) = self.module.set_input_patch_enabled(handle, on) {
                         info!("Error {:?}", e);
                     }
                 }
-                Ok(PatchUpdate::Output(id, on)) => {
-                    if let Err(e) = module.set_output_patch_enabled(output_handles[id], on) {
-                        info!("Error {:?}", e);
-                    }
+            }
+            Ok(PatchUpdate::Output(id, on)) => {
+                if let Err(e) = self
+                    .module
+                    .set_output_patch_enabled(self.output_handles[id], on)
+                {
+                    info!("Error {:?}", e);
                 }
-                Ok(PatchUpdate::Param(id, val)) => {
-                    params[id] = val;
+            }
+            Ok(PatchUpdate::Param(id, val)) => {
+                self.knobs[id] = val;
+                self.params
+                    .set(id, modulated(val, self.modulation[id], self.ranges[id]));
+            }
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => return false,
+        }
+        let (p, params) = (&mut self.p, &mut self.params);
+        let res = self
+            .module
+            .poll(time, |block| {
+                for output in block.outputs().iter_mut() {
+                    **output = Default::default();
                 }
-                Err(TryRecvError::Empty) => {}
-                Err(TryRecvError::Disconnected) => break 'outer,
+                let context = block.context();
+                p.process(block, params, &context);
+                params.next_block();
+            })
+            .unwrap();
+        for (id, change) in res.param_changes().filter(|(id, _)| *id < P) {
+            let (min, max, log) = self.ranges[id];
+            match change {
+                ParamChange::Set(position) => {
+                    self.knobs[id] = knob_value(position, min, max, log);
+                    let _ = self.remote_tx.send(RemoteUpdate::Param(id, self.knobs[id]));
+                }
+                ParamChange::Modulate(offset) => self.modulation[id] = offset,
             }
-            let res = module
-                .poll(time, |block| {
-                    let input = input_handles.map(|h| block.get_input(h));
-                    let mut output = [Default::default(); O];
-                    p.process(input, &mut output, &params);
-                    for (h, o) in zip(output_handles, output) {
-                        block.set_output(h, o);
-                    }
-                })
-                .unwrap();
-            let colors = (
-                input_handles.map(|h| res.get_input_color(h)),
-                output_handles.map(|h| res.get_output_color(h)),
+            self.params.set(
+                id,
+                modulated(self.knobs[id], self.modulation[id], self.ranges[id]),
             );
-            if let Err(TrySendError::Disconnected(_)) = tx.try_send(colors) {
-                break 'outer;
+        }
+        for action in res.compare_actions() {
+            let _ = self.remote_tx.send(RemoteUpdate::Compare(action));
+        }
+        match res.apply_presets(&mut self.storage, &self.knobs) {
+            Ok(Some(values)) => {
+                for (id, val) in values.into_iter().enumerate() {
+                    self.knobs[id] = val;
+                    self.params
+                        .set(id, modulated(val, self.modulation[id], self.ranges[id]));
+                    let _ = self.remote_tx.send(RemoteUpdate::Param(id, val));
+                }
             }
-            time += 1;
+            Ok(None) => {}
+            Err(e) => info!("Error {:?}", e),
+        }
+        let state = (res.is_muted(), res.is_bypassed());
+        if state != self.state {
+            self.state = state;
+            let _ = self.remote_tx.send(RemoteUpdate::State(state.0, state.1));
         }
-        thread::yield_now();
+        let colors = (
+            self.input_handles
+                .map(|h| h.map_or(Srgb::new(0, 0, 0), |h| res.get_input_color(h))),
+            self.output_handles.map(|h| res.get_output_color(h)),
+        );
+        !matches!(self.tx.try_send(colors), Err(TrySendError::Disconnected(_)))
     }
 }
 
@@ -285,20 +555,89 @@ impl<const I: usize, const O: usize, const P: usize> DisplayHandler for DisplayM
                 Err(TryRecvError::Disconnected) => self.open = false,
             }
         }
+        let remote: Vec<RemoteUpdate> = match &self.remote_rx {
+            Some(rx) => rx.try_iter().collect(),
+            None => Vec::new(),
+        };
+        for update in remote {
+            match update {
+                RemoteUpdate::Param(id, val) => {
+                    if let Some(p) = &mut self.params[id] {
+                        p.val = val;
+                    }
+                }
+                RemoteUpdate::Compare(action) => self.compare(action),
+                RemoteUpdate::State(muted, bypassed) => {
+                    self.muted = muted;
+                    self.bypassed = bypassed;
+                }
+            }
+        }
         ui.heading(self.name.clone());
+        if let Some(load) = &self.load {
+            ui.label(format!("CPU {:.1}%", load.percent()));
+        }
+        if self.muted {
+            ui.colored_label(egui::Color32::from_rgb(255, 0, 128), "● Muted");
+        } else if self.bypassed {
+            ui.colored_label(egui::Color32::from_rgb(255, 192, 0), "● Bypassed");
+        }
         ui.add_space(20.0);
         // Add ui and message transmission
-        for i in 0..I {
+        for i in (0..I).filter(|&i| self.audition != Some(i)) {
             self.input_jack(i, ui);
         }
         ui.add_space(20.0);
         for i in 0..P {
             self.param_knob(i, ui);
         }
+        if P > 0 {
+            self.compare_buttons(ui);
+        }
         ui.add_space(20.0);
         for i in 0..O {
             self.output_jack(i, ui);
         }
+        if let Some(mut renderer) = self.renderer.take() {
+            ui.add_space(20.0);
+            renderer.render(self, ui);
+            self.renderer = Some(renderer);
+        }
+    }
+
+    fn save_preset(&self) -> ModulePreset {
+        ModulePreset {
+            params: self
+                .params
+                .iter()
+                .map(|p| p.as_ref().map_or(0.0, |p| p.val))
+                .collect(),
+            param_meta: self
+                .params
+                .iter()
+                .map(|p| {
+                    p.as_ref().map(|p| ParamMeta {
+                        name: p.name.clone(),
+                        unit: p.unit.clone(),
+                        min: p.min,
+                        max: p.max,
+                        log: p.log,
+                    })
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn load_preset(&mut self, preset: &ModulePreset) {
+        for (id, val) in preset.params.iter().enumerate().take(P) {
+            if let Some(p) = &mut self.params[id] {
+                p.val = val.clamp(p.min, p.max);
+                if let Some(tx) = &self.tx {
+                    self.open &= tx.send(PatchUpdate::Param(id, p.val)).is_ok();
+                }
+            }
+        }
     }
 }
 
@@ -307,6 +646,18 @@ pub trait DisplayHandler {
     fn name(&self) -> &str;
     fn is_open(&self) -> bool;
     fn update(&mut self, ui: &mut egui::Ui);
+
+    /// Settings to keep in a preset, beyond which window this is and its name.
+    fn save_preset(&self) -> ModulePreset {
+        Default::default()
+    }
+
+    fn load_preset(&mut self, _preset: &ModulePreset) {}
+
+    /// Settings this window wants loaded into other windows, by name, since the last frame.
+    fn preset_requests(&mut self) -> Vec<WindowPreset> {
+        Vec::new()
+    }
 }
 
 pub trait AsDisplayModule<const I: usize, const O: usize, const P: usize> {
@@ -317,15 +668,6 @@ pub trait AsMutDisplayModule<const I: usize, const O: usize, const P: usize> {
     fn as_mut_display_module(&mut self) -> &mut DisplayModule<I, O, P>;
 }
 
-pub trait Processor<const I: usize, const O: usize, const P: usize> {
-    fn process(
-        &mut self,
-        input: [&AudioPacket; I],
-        output: &mut [AudioPacket; O],
-        params: &[f32; P],
-    );
-}
-
 pub trait Renderer<const I: usize, const O: usize, const P: usize> {
     fn render(&mut self, disp: &mut DisplayModule<I, O, P>, ui: &mut egui::Ui);
 }
@@ -336,3 +678,20 @@ enum PatchUpdate {
     Output(usize, bool),
     Param(usize, f32),
 }
+
+/// Changes made over the network, passed from the processing thread back to the ui.
+#[derive(Debug)]
+enum RemoteUpdate {
+    Param(usize, f32),
+    Compare(CompareAction),
+    /// Muted and bypassed
+    State(bool, bool),
+}
+
+/// Knob settings set aside for an A/B comparison, kept the same way as in a preset.
+#[derive(Default)]
+struct AbCompare {
+    /// Whichever of A and B isn't showing on the knobs, once A has been stored
+    hidden: Option<ModulePreset>,
+    showing_a: bool,
+}
@@ -0,0 +1,65 @@
+use apiary_core::{
+    define_module,
+    dsp::triggers::{EdgeTrigger, GateStretcher, MinimumGate},
+    BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE, CHANNELS,
+};
+
+use crate::display_module::DisplayModule;
+
+pub struct Gates {
+    edges: [EdgeTrigger; CHANNELS],
+    stretchers: [GateStretcher; CHANNELS],
+    minimums: [MinimumGate; CHANNELS],
+}
+
+define_module! {
+    name: "gates",
+    color: 30,
+    inputs: {
+        GATE_INPUT: "Gate" as Gate,
+    },
+    outputs: {
+        TRIGGER_OUTPUT: "Trigger" as Gate,
+        STRETCH_OUTPUT: "Stretched" as Gate,
+        MINIMUM_OUTPUT: "Min Gate" as Gate,
+    },
+    params: {
+        TRIGGER_PARAM: "Trigger" { min: 0.0005, max: 0.05, default: 0.001, unit: "s", log: true },
+        LENGTH_PARAM: "Length" { min: 0.001, max: 4.0, default: 0.1, unit: "s", log: true },
+    },
+}
+
+impl Gates {
+    pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
+        DisplayModule::new().name(name).spec(&SPEC).start(Gates {
+            edges: Default::default(),
+            stretchers: Default::default(),
+            minimums: Default::default(),
+        })
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Gates {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            let trigger = params.at(TRIGGER_PARAM, i);
+            let length = params.at(LENGTH_PARAM, i);
+            for j in 0..CHANNELS {
+                let x = input[GATE_INPUT].data[i].data[j];
+                output[TRIGGER_OUTPUT].data[i].data[j] =
+                    self.edges[j].process(x, trigger, context.sample_rate);
+                output[STRETCH_OUTPUT].data[i].data[j] =
+                    self.stretchers[j].process(x, length, context.sample_rate);
+                output[MINIMUM_OUTPUT].data[i].data[j] =
+                    self.minimums[j].process(x, length, context.sample_rate);
+            }
+        }
+    }
+}
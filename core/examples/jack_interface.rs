@@ -0,0 +1,192 @@
+use apiary_core::{
+    dsp::sample::Sample, softclip, BlockContext, ParamBlock, ProcessBlock, Processor, CHANNELS,
+    SAMPLE_RATE,
+};
+use eframe::egui;
+use jack::{
+    AsyncClient, AudioIn, AudioOut, Client, ClientOptions, Control, NotificationHandler, Port,
+    ProcessHandler, ProcessScope,
+};
+use std::{
+    error::Error,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        mpsc::TrySendError,
+        Arc,
+    },
+};
+
+use crate::{
+    display_module::{DisplayModule, Renderer},
+    resampler::{frame_queue, FrameQueue, Resampler},
+};
+
+const NUM_PARAMS: usize = 0;
+
+const TO_JACK_INPUT: usize = 0;
+const NUM_INPUTS: usize = 1;
+
+const FROM_JACK_OUTPUT: usize = 0;
+const NUM_OUTPUTS: usize = 1;
+
+/// Frames queued in each direction, enough to ride out the JACK clock drifting from the network's.
+const QUEUE_SIZE: usize = 960;
+
+/// Runs in the JACK process callback, moving whole frames between the ports and the queues to and
+/// from the module so every channel stays lined up with the others.
+struct Ports {
+    playback: Vec<Port<AudioOut>>,
+    capture: Vec<Port<AudioIn>>,
+    from_network: Resampler<CHANNELS>,
+    to_network: FrameQueue<CHANNELS>,
+}
+
+impl ProcessHandler for Ports {
+    fn process(&mut self, _: &Client, ps: &ProcessScope) -> Control {
+        self.from_network.update();
+        let mut playback: Vec<&mut [f32]> = self
+            .playback
+            .iter_mut()
+            .map(|p| p.as_mut_slice(ps))
+            .collect();
+        for i in 0..ps.n_frames() as usize {
+            for (port, x) in playback.iter_mut().zip(self.from_network.next_frame()) {
+                port[i] = softclip(x);
+            }
+        }
+        let capture: Vec<&[f32]> = self.capture.iter().map(|p| p.as_slice(ps)).collect();
+        for i in 0..ps.n_frames() as usize {
+            let mut frame = [0.0; CHANNELS];
+            for (x, port) in frame.iter_mut().zip(&capture) {
+                *x = port[i];
+            }
+            // Only full when the module has stopped reading, so there's nothing to do with the rest
+            let _ = self.to_network.try_send(frame);
+        }
+        Control::Continue
+    }
+}
+
+struct Notifications {
+    xruns: Arc<AtomicU32>,
+}
+
+impl NotificationHandler for Notifications {
+    fn xrun(&mut self, _: &Client) -> Control {
+        self.xruns.fetch_add(1, Ordering::Relaxed);
+        Control::Continue
+    }
+}
+
+/// Shows which JACK server the module is connected to and how many xruns it has seen.
+struct JackStatus {
+    description: String,
+    xruns: Arc<AtomicU32>,
+}
+
+impl Renderer<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for JackStatus {
+    fn render(
+        &mut self,
+        _disp: &mut DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS>,
+        ui: &mut egui::Ui,
+    ) {
+        ui.label(&self.description);
+        ui.label(format!("Xruns: {}", self.xruns.load(Ordering::Relaxed)));
+    }
+}
+
+/// Bridges jacks to a JACK server, so the network can be patched to and from anything else running
+/// on it. Channel `n` of the input jack plays on port `out_n`, and port `in_n` is carried on channel
+/// `n` of the output jack. The network and JACK run on their own clocks, possibly at different
+/// rates, so both directions are resampled to keep their queues half full.
+pub struct JackInterface {
+    time: i64,
+    dropped_frames: usize,
+    to_jack: FrameQueue<CHANNELS>,
+    from_jack: Resampler<CHANNELS>,
+    _client: AsyncClient<Notifications, Ports>,
+}
+
+impl JackInterface {
+    pub fn init(
+        id: &str,
+    ) -> Result<DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS>, Box<dyn Error>> {
+        let (client, status) =
+            Client::new(&format!("apiary {}", id), ClientOptions::NO_START_SERVER)?;
+        info!("Connected to JACK as {:?}: {:?}", client.name(), status);
+        let description = format!(
+            "{} ({} Hz, {} frame buffer)",
+            client.name(),
+            client.sample_rate(),
+            client.buffer_size()
+        );
+
+        let jack_rate = client.sample_rate() as f32;
+        let (to_jack, from_network) = frame_queue(QUEUE_SIZE, SAMPLE_RATE, jack_rate);
+        let (to_network, from_jack) = frame_queue(QUEUE_SIZE, jack_rate, SAMPLE_RATE);
+        let mut ports = Ports {
+            playback: Vec::new(),
+            capture: Vec::new(),
+            from_network,
+            to_network,
+        };
+        for i in 0..CHANNELS {
+            ports
+                .playback
+                .push(client.register_port(&format!("out_{}", i + 1), AudioOut::default())?);
+            ports
+                .capture
+                .push(client.register_port(&format!("in_{}", i + 1), AudioIn::default())?);
+        }
+
+        let xruns = Arc::new(AtomicU32::new(0));
+        let client = client.activate_async(
+            Notifications {
+                xruns: xruns.clone(),
+            },
+            ports,
+        )?;
+
+        Ok(DisplayModule::new()
+            .name("JACK")
+            .input(TO_JACK_INPUT, "To JACK")
+            .output(FROM_JACK_OUTPUT, "From JACK")
+            .renderer(JackStatus { description, xruns })
+            .start(JackInterface {
+                time: 0,
+                dropped_frames: 0,
+                to_jack,
+                from_jack,
+                _client: client,
+            }))
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for JackInterface {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        _params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
+    ) {
+        let input = block.inputs();
+        let output = block.outputs();
+        if self.time % 10000 == 0 {
+            self.dropped_frames += self.from_jack.take_dropped_frames();
+            if self.dropped_frames != 0 {
+                info!("Module dropped frames: {:?}", self.dropped_frames);
+                self.dropped_frames = 0;
+            }
+        }
+        for frame in input[TO_JACK_INPUT].data {
+            if let Err(TrySendError::Full(_)) = self.to_jack.try_send(frame.data.map(i16::to_f32)) {
+                self.dropped_frames += 1;
+            }
+        }
+        self.from_jack.update();
+        for frame in output[FROM_JACK_OUTPUT].data.iter_mut() {
+            frame.data = self.from_jack.next_frame().map(i16::from_f32_soft);
+        }
+        self.time += 1;
+    }
+}
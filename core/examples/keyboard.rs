@@ -0,0 +1,163 @@
+use apiary_core::{AudioPacket, BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE};
+use eframe::egui::{self, Key};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::{
+    display_module::{DisplayModule, Renderer},
+    voices::VoiceAllocator,
+};
+
+/// Keys played as notes, laid out like a piano across the home row with the black keys above,
+/// from C up to the D# an octave and a bit higher
+const NOTE_KEYS: [Key; 16] = [
+    Key::A,
+    Key::W,
+    Key::S,
+    Key::E,
+    Key::D,
+    Key::F,
+    Key::T,
+    Key::G,
+    Key::Y,
+    Key::H,
+    Key::U,
+    Key::J,
+    Key::K,
+    Key::O,
+    Key::L,
+    Key::P,
+];
+/// MIDI note of the A key with no octave shift
+const BASE_NOTE: i16 = 60;
+const MAX_OCTAVE_SHIFT: i16 = 4;
+const VELOCITY_STEP: u8 = 16;
+
+#[derive(Debug)]
+enum NoteEvent {
+    On(u8, u8),
+    Off(u8),
+}
+
+/// Reads the computer keyboard while the manager window has focus.
+struct KeyboardInput {
+    tx: Sender<NoteEvent>,
+    octave: i16,
+    velocity: u8,
+    /// Keys down and the notes they started, so that shifting octave doesn't leave notes stuck
+    held: Vec<(Key, u8)>,
+}
+
+impl KeyboardInput {
+    fn press(&mut self, key: Key) {
+        if let Some(i) = NOTE_KEYS.iter().position(|k| *k == key) {
+            // Keys repeat while held, but only the first press is a new note
+            if self.held.iter().any(|(k, _)| *k == key) {
+                return;
+            }
+            let note = (BASE_NOTE + 12 * self.octave + i as i16).clamp(0, 127) as u8;
+            self.held.push((key, note));
+            let _ = self.tx.send(NoteEvent::On(note, self.velocity));
+            return;
+        }
+        match key {
+            Key::Z => self.octave = (self.octave - 1).max(-MAX_OCTAVE_SHIFT),
+            Key::X => self.octave = (self.octave + 1).min(MAX_OCTAVE_SHIFT),
+            Key::C => self.velocity = self.velocity.saturating_sub(VELOCITY_STEP).max(1),
+            Key::V => self.velocity = self.velocity.saturating_add(VELOCITY_STEP).min(127),
+            _ => {}
+        }
+    }
+
+    fn release(&mut self, key: Key) {
+        if let Some(i) = self.held.iter().position(|(k, _)| *k == key) {
+            let (_, note) = self.held.remove(i);
+            let _ = self.tx.send(NoteEvent::Off(note));
+        }
+    }
+}
+
+impl Renderer<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for KeyboardInput {
+    fn render(
+        &mut self,
+        _disp: &mut DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS>,
+        ui: &mut egui::Ui,
+    ) {
+        let events = ui.input().events.clone();
+        for event in events {
+            if let egui::Event::Key { key, pressed, .. } = event {
+                if pressed {
+                    self.press(key);
+                } else {
+                    self.release(key);
+                }
+            }
+        }
+        ui.label(format!("Octave {:+}", self.octave));
+        ui.label(format!("Velocity {}", self.velocity));
+        ui.small("A-K play, W E T Y U sharps");
+        ui.small("Z/X octave, C/V velocity");
+    }
+}
+
+/// Plays notes from the computer keyboard, for trying out a patch without any MIDI hardware.
+pub struct TypingKeyboard {
+    voices: VoiceAllocator,
+    rx: Receiver<NoteEvent>,
+}
+
+const NUM_PARAMS: usize = 0;
+
+const NUM_INPUTS: usize = 0;
+
+const NOTE_OUTPUT: usize = 0;
+const GATE_OUTPUT: usize = 1;
+const VEL_OUTPUT: usize = 2;
+const NUM_OUTPUTS: usize = 3;
+
+impl TypingKeyboard {
+    pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
+        let (tx, rx) = channel();
+        DisplayModule::new()
+            .name(name)
+            .output(NOTE_OUTPUT, "Note")
+            .output(GATE_OUTPUT, "Gate")
+            .output(VEL_OUTPUT, "Velocity")
+            .renderer(KeyboardInput {
+                tx,
+                octave: 0,
+                velocity: 100,
+                held: vec![],
+            })
+            .start(TypingKeyboard {
+                voices: Default::default(),
+                rx,
+            })
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for TypingKeyboard {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        _params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
+    ) {
+        while let Ok(event) = self.rx.try_recv() {
+            match event {
+                NoteEvent::On(note, vel) => self.voices.note_on(note, vel),
+                NoteEvent::Off(note) => self.voices.note_off(note),
+            }
+        }
+        let output = block.outputs();
+        let (note_frame, gate_frame, vel_frame) = self.voices.next_block();
+        *output[NOTE_OUTPUT] = AudioPacket {
+            data: [note_frame; BLOCK_SIZE],
+        };
+        *output[GATE_OUTPUT] = AudioPacket {
+            data: [gate_frame; BLOCK_SIZE],
+        };
+        *output[VEL_OUTPUT] = AudioPacket {
+            data: [vel_frame; BLOCK_SIZE],
+        };
+    }
+}
@@ -0,0 +1,58 @@
+use apiary_core::{
+    define_module, dsp::logic::GateLogic, BlockContext, ParamBlock, ProcessBlock, Processor,
+    BLOCK_SIZE, CHANNELS,
+};
+
+use crate::display_module::DisplayModule;
+
+pub struct Logic {
+    logic: [GateLogic; CHANNELS],
+}
+
+define_module! {
+    name: "logic",
+    color: 300,
+    inputs: {
+        A_INPUT: "Gate A" as Gate,
+        B_INPUT: "Gate B" as Gate,
+    },
+    outputs: {
+        AND_OUTPUT: "And" as Gate,
+        OR_OUTPUT: "Or" as Gate,
+        XOR_OUTPUT: "Xor" as Gate,
+        FLIP_FLOP_OUTPUT: "Flip-Flop" as Gate,
+    },
+    params: {},
+}
+
+impl Logic {
+    pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
+        DisplayModule::new().name(name).spec(&SPEC).start(Logic {
+            logic: Default::default(),
+        })
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Logic {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        _params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
+    ) {
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            for j in 0..CHANNELS {
+                let out = self.logic[j].process(
+                    input[A_INPUT].data[i].data[j],
+                    input[B_INPUT].data[i].data[j],
+                );
+                output[AND_OUTPUT].data[i].data[j] = out.and;
+                output[OR_OUTPUT].data[i].data[j] = out.or;
+                output[XOR_OUTPUT].data[i].data[j] = out.xor;
+                output[FLIP_FLOP_OUTPUT].data[i].data[j] = out.flip_flop;
+            }
+        }
+    }
+}
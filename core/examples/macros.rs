@@ -0,0 +1,304 @@
+use apiary_core::{
+    time::{Clock, Duration, StdClock},
+    Module, ParamChange, Uuid,
+};
+use eframe::egui;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    common::{Knob, SelectedInterface},
+    display_module::DisplayHandler,
+    preset::ModulePreset,
+};
+
+const NUM_MACROS: usize = 4;
+const NUM_TARGETS: usize = 4;
+const COLOR: u16 = 90;
+
+/// How often changed knob positions are sent to their targets
+const SEND_INTERVAL: Duration = Duration::from_millis(10);
+/// How often the list of modules to choose targets from is updated
+const PEER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How a target follows its macro between the ends of its range.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+enum Curve {
+    #[default]
+    Linear,
+    EaseIn,
+    EaseOut,
+    SCurve,
+}
+
+impl Curve {
+    const ALL: [Curve; 4] = [Curve::Linear, Curve::EaseIn, Curve::EaseOut, Curve::SCurve];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Curve::Linear => "Linear",
+            Curve::EaseIn => "Ease in",
+            Curve::EaseOut => "Ease out",
+            Curve::SCurve => "S-curve",
+        }
+    }
+
+    fn apply(&self, x: f32) -> f32 {
+        let x = x.clamp(0.0, 1.0);
+        match self {
+            Curve::Linear => x,
+            Curve::EaseIn => x * x,
+            Curve::EaseOut => 1.0 - (1.0 - x) * (1.0 - x),
+            Curve::SCurve => x * x * (3.0 - 2.0 * x),
+        }
+    }
+}
+
+/// A knob moved by a macro, and the part of its travel the macro sweeps.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct Target {
+    module: String,
+    param: usize,
+    /// Knob position with the macro all the way down
+    from: f32,
+    /// Knob position with the macro all the way up, which may be below `from`
+    to: f32,
+    curve: Curve,
+}
+
+impl Default for Target {
+    fn default() -> Self {
+        Target {
+            module: String::new(),
+            param: 0,
+            from: 0.0,
+            to: 1.0,
+            curve: Curve::Linear,
+        }
+    }
+}
+
+impl Target {
+    fn position(&self, amount: f32) -> f32 {
+        self.from + (self.to - self.from) * self.curve.apply(amount)
+    }
+}
+
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Macro {
+    amount: f32,
+    targets: [Target; NUM_TARGETS],
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Macros {
+    macros: [Macro; NUM_MACROS],
+}
+
+/// Knob position last sent to a target.
+#[derive(PartialEq)]
+struct Sent {
+    uuid: Uuid,
+    param: usize,
+    position: f32,
+}
+
+/// Knobs that each turn up to `NUM_TARGETS` knobs elsewhere at once, through `SetParam`
+/// directives. Targets can be any module on the network, including the other windows in this
+/// manager, and each sweeps its own part of its knob's travel along its own curve.
+///
+/// Positions are only sent when a macro or its mapping changes, so a target's knob can still be
+/// turned by hand until the macro moves again.
+pub struct MacroKnobs {
+    name: String,
+    open: bool,
+    // Dropped along with the window to stop the processing thread
+    _tx: Sender<()>,
+    macros: Arc<Mutex<Macros>>,
+    peers: Arc<Mutex<Vec<String>>>,
+}
+
+impl MacroKnobs {
+    pub fn init(name: &str) -> Self {
+        let (tx, rx) = channel();
+        let macros: Arc<Mutex<Macros>> = Default::default();
+        let peers: Arc<Mutex<Vec<String>>> = Default::default();
+        let thread_macros = macros.clone();
+        let thread_peers = peers.clone();
+        let thread_name = name.to_owned();
+        thread::spawn(move || process(rx, thread_macros, thread_peers, &thread_name));
+
+        MacroKnobs {
+            name: name.to_owned(),
+            open: true,
+            _tx: tx,
+            macros,
+            peers,
+        }
+    }
+}
+
+fn process(
+    rx: Receiver<()>,
+    macros: Arc<Mutex<Macros>>,
+    peers: Arc<Mutex<Vec<String>>>,
+    name: &str,
+) {
+    let mut module: Module<_, _, 0, 0, _> = Module::with_clock(
+        SelectedInterface::new().unwrap(),
+        rand::thread_rng(),
+        name.into(),
+        COLOR,
+        StdClock::new(),
+    );
+    let mut sent: [[Option<Sent>; NUM_TARGETS]; NUM_MACROS] = Default::default();
+
+    'outer: loop {
+        while let Some(time) = module.next_tick() {
+            if let Err(TryRecvError::Disconnected) = rx.try_recv() {
+                break 'outer;
+            }
+            module.poll(time, |_| {}).unwrap();
+            if time.is_multiple_of(PEER_INTERVAL) {
+                *peers.lock().unwrap() = module.peers().map(|uuid| uuid.to_string()).collect();
+            }
+            if time.is_multiple_of(SEND_INTERVAL) {
+                let macros = macros.lock().unwrap().clone();
+                for (m, sent) in macros.macros.iter().zip(sent.iter_mut()) {
+                    for (target, sent) in m.targets.iter().zip(sent.iter_mut()) {
+                        send_position(&mut module, target, m.amount, sent);
+                    }
+                }
+            }
+        }
+        thread::sleep(std::time::Duration::from_millis(0));
+    }
+}
+
+fn send_position<T: apiary_core::Network<0, 0>, R: rand::RngCore, C: Clock>(
+    module: &mut Module<T, R, 0, 0, C>,
+    target: &Target,
+    amount: f32,
+    sent: &mut Option<Sent>,
+) {
+    let mut uuid = Uuid::new();
+    if target.module.is_empty() || uuid.push_str(&target.module).is_err() {
+        *sent = None;
+        return;
+    }
+    let next = Sent {
+        uuid,
+        param: target.param,
+        position: target.position(amount),
+    };
+    if sent.as_ref() == Some(&next) {
+        return;
+    }
+    if let Err(e) = module.send_set_param(
+        next.uuid.clone(),
+        next.param,
+        ParamChange::Set(next.position),
+    ) {
+        info!("Error sending macro: {:?}", e);
+    }
+    *sent = Some(next);
+}
+
+impl DisplayHandler for MacroKnobs {
+    fn width(&self) -> f32 {
+        20.0
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn update(&mut self, ui: &mut egui::Ui) {
+        ui.heading(self.name.clone());
+        ui.add_space(20.0);
+
+        let peers = self.peers.lock().unwrap().clone();
+        let mut macros = self.macros.lock().unwrap();
+        for (i, m) in macros.macros.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.vertical(|ui| {
+                    ui.add(Knob::new(
+                        &mut m.amount,
+                        format!("Macro {}", i + 1),
+                        "".to_owned(),
+                        0.0,
+                        1.0,
+                        false,
+                    ));
+                });
+                egui::Grid::new(format!("{} macro {}", self.name, i)).show(ui, |ui| {
+                    ui.label("Target");
+                    ui.label("Param");
+                    ui.label("From");
+                    ui.label("To");
+                    ui.label("Curve");
+                    ui.end_row();
+                    for (row, target) in m.targets.iter_mut().enumerate() {
+                        let selected = if target.module.is_empty() {
+                            "None".to_owned()
+                        } else {
+                            target.module.clone()
+                        };
+                        egui::ComboBox::from_id_source(format!(
+                            "{} macro {} target {}",
+                            self.name, i, row
+                        ))
+                        .selected_text(selected)
+                        .show_ui(ui, |ui| {
+                            ui.selectable_value(&mut target.module, String::new(), "None");
+                            for peer in &peers {
+                                ui.selectable_value(&mut target.module, peer.clone(), peer);
+                            }
+                        });
+                        ui.add(egui::DragValue::new(&mut target.param).clamp_range(0..=255));
+                        for end in [&mut target.from, &mut target.to] {
+                            ui.add(egui::DragValue::new(end).speed(0.01).clamp_range(0.0..=1.0));
+                        }
+                        egui::ComboBox::from_id_source(format!(
+                            "{} macro {} curve {}",
+                            self.name, i, row
+                        ))
+                        .selected_text(target.curve.name())
+                        .show_ui(ui, |ui| {
+                            for curve in Curve::ALL {
+                                ui.selectable_value(&mut target.curve, curve, curve.name());
+                            }
+                        });
+                        ui.end_row();
+                    }
+                });
+            });
+            ui.add_space(10.0);
+        }
+    }
+
+    fn save_preset(&self) -> ModulePreset {
+        let macros = self.macros.lock().unwrap();
+        ModulePreset {
+            state: serde_json::to_value(&*macros).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    fn load_preset(&mut self, preset: &ModulePreset) {
+        match serde_json::from_value(preset.state.clone()) {
+            Ok(macros) => *self.macros.lock().unwrap() = macros,
+            Err(e) => info!("Error loading macro preset: {}", e),
+        }
+    }
+}
@@ -1,42 +1,108 @@
-use apiary_core::Module;
+use apiary_core::{
+    journal::PatchJournal,
+    time::{Duration, MonotonicTime, StdClock},
+    topology::Topology,
+    AudioPacket, Capability, FeedbackPolicy, Module, ModuleDescription, MonitorJackHandle,
+    ParamChange, PatchState, ScaleTable, PRESET_NAME_LEN, PRESET_SLOTS,
+};
 use eframe::egui;
+use rand::rngs::ThreadRng;
 use simple_logger::SimpleLogger;
 use std::{
-    sync::mpsc::{channel, Sender, TryRecvError},
+    collections::{BTreeMap, HashMap},
+    path::Path,
+    sync::mpsc::{channel, Receiver, Sender, TryRecvError},
     thread,
-    time::{Duration, Instant},
 };
 
+mod analyzer;
+mod attenuverter;
 mod audio_interface;
 mod common;
+mod comparator;
 mod display_module;
 mod envelope;
 mod filter;
+mod gates;
+mod jack_interface;
+mod keyboard;
+mod logic;
+mod macros;
 mod midi_to_cv;
 mod mixer;
+mod mod_matrix;
+mod morph;
 mod oscillator;
 mod oscilloscope;
+mod piano;
+mod plugin;
+mod preset;
+mod realtime;
+mod recorder;
+mod resampler;
 mod reverb;
+mod scheduler;
+mod switch;
+mod triggers;
+mod vocoder;
+mod voices;
 
+use analyzer::Analyzer;
+use attenuverter::Attenuverter;
 use audio_interface::AudioInterface;
 use common::SelectedInterface;
+use comparator::Comparator;
 use display_module::DisplayHandler;
-use envelope::Envelope;
-use filter::Filter;
+use gates::Gates;
+use jack_interface::JackInterface;
+use keyboard::TypingKeyboard;
+use logic::Logic;
+use macros::MacroKnobs;
 use midi_to_cv::MidiToCv;
 use mixer::Mixer;
-use oscillator::Oscillator;
+use mod_matrix::ModMatrix;
+use morph::Morph;
 use oscilloscope::Oscilloscope;
+use piano::Piano;
+use plugin::Plugin;
+use preset::{Preset, WindowPreset};
+use recorder::Recorder;
 use reverb::Reverb;
+use switch::{Router, Switch};
+use triggers::Triggers;
+use vocoder::Vocoder;
+
+const PRESET_PATH: &str = "preset.json";
+/// Connections made while the manager runs, restored if it is restarted after a crash
+const JOURNAL_PATH: &str = "patch.journal";
+/// Where the patch graph is exported to, for Graphviz and Mermaid
+const DOT_PATH: &str = "patch.dot";
+const MERMAID_PATH: &str = "patch.mmd";
+/// Scala scale to microtune the patch to, with an optional keyboard mapping beside it
+const SCL_PATH: &str = "tuning.scl";
+const KBM_PATH: &str = "tuning.kbm";
+/// How often the list of modules on the network is refreshed, and undescribed ones asked again
+const DISCOVERY_INTERVAL: Duration = Duration::from_secs(1);
+/// How often the levels of the module picked in the network panel are sent to the window
+const METER_INTERVAL: Duration = Duration::from_millis(50);
+/// Outputs of the picked module that are metered, each taking a monitor jack
+const METER_JACKS: usize = 4;
+/// Pixels per unit of `DisplayHandler::width`
+const HP: f32 = 15.0;
+/// Height a window is given in the rack until it has been drawn
+const ROW_HEIGHT: f32 = 450.0;
+/// Space left between windows in the rack
+const RACK_GAP: f32 = 8.0;
 
-fn window_build(name: &str, num: u32) -> Result<Box<dyn DisplayHandler>, ()> {
-    let id = format!("{}:{}", name, num);
+fn window_build(name: &str, id: &str) -> Result<Box<dyn DisplayHandler>, ()> {
     match name {
         "Midi to CV" => Ok(Box::new(MidiToCv::init())),
-        "Oscillator" => Ok(Box::new(Oscillator::init(&id))),
-        "Envelope" => Ok(Box::new(Envelope::init(&id))),
-        "Mixer" => Ok(Box::new(Mixer::init(&id))),
-        "Filter" => Ok(Box::new(Filter::init())),
+        "Keyboard" => Ok(Box::new(TypingKeyboard::init(id))),
+        "Piano" => Ok(Box::new(Piano::init(id))),
+        "Oscillator" => Ok(Box::new(oscillator::init(id))),
+        "Envelope" => Ok(Box::new(envelope::init(id))),
+        "Mixer" => Ok(Box::new(Mixer::init(id))),
+        "Filter" => Ok(Box::new(filter::init())),
         "Audio Interface" => match AudioInterface::init() {
             Ok(a) => Ok(Box::new(a)),
             Err(e) => {
@@ -44,14 +110,37 @@ fn window_build(name: &str, num: u32) -> Result<Box<dyn DisplayHandler>, ()> {
                 Err(())
             }
         },
-        "Reverb" => Ok(Box::new(Reverb::init(&id))),
+        "Reverb" => Ok(Box::new(Reverb::init(id))),
         "Oscilloscope" => Ok(Box::new(Oscilloscope::new())),
+        "Vocoder" => Ok(Box::new(Vocoder::init(id))),
+        "Analyzer" => Ok(Box::new(Analyzer::new())),
+        "Attenuverter" => Ok(Box::new(Attenuverter::init(id))),
+        "Logic" => Ok(Box::new(Logic::init(id))),
+        "Triggers" => Ok(Box::new(Triggers::init(id))),
+        "Gates" => Ok(Box::new(Gates::init(id))),
+        "Recorder" => Ok(Box::new(Recorder::init(id))),
+        "Comparator" => Ok(Box::new(Comparator::init(id))),
+        "Switch" => Ok(Box::new(Switch::init(id))),
+        "Router" => Ok(Box::new(Router::init(id))),
+        "Plugin" => Ok(Box::new(Plugin::init(id))),
+        "Mod Matrix" => Ok(Box::new(ModMatrix::init(id))),
+        "Macros" => Ok(Box::new(MacroKnobs::init(id))),
+        "Morph" => Ok(Box::new(Morph::init(id))),
+        "JACK" => match JackInterface::init(id) {
+            Ok(a) => Ok(Box::new(a)),
+            Err(e) => {
+                info!("Failed to open JackInterface: {:?}", e);
+                Err(())
+            }
+        },
         _ => Err(()),
     }
 }
 
-const WINDOWS: [&str; 8] = [
+const WINDOWS: [&str; 25] = [
     "Midi to CV",
+    "Keyboard",
+    "Piano",
     "Oscillator",
     "Envelope",
     "Mixer",
@@ -59,11 +148,56 @@ const WINDOWS: [&str; 8] = [
     "Audio Interface",
     "Reverb",
     "Oscilloscope",
+    "Vocoder",
+    "Analyzer",
+    "Attenuverter",
+    "Logic",
+    "Triggers",
+    "Gates",
+    "Recorder",
+    "Comparator",
+    "Switch",
+    "Router",
+    "Plugin",
+    "Mod Matrix",
+    "Macros",
+    "Morph",
+    "JACK",
 ];
 
 #[macro_use]
 extern crate log;
 
+enum Command {
+    Halt,
+    Audit,
+    Export,
+    /// Mute or bypass the module with this name
+    Mute(String, bool),
+    Bypass(String, bool),
+    /// Solo the module with this name in place, through the manager as leader
+    Solo(String, bool),
+    /// Keep the knobs of the module with this name in a preset slot under a name, or recall it
+    StorePreset(String, usize, String),
+    RecallPreset(String, usize),
+    FeedbackPolicy(FeedbackPolicy),
+    /// Microtune the whole patch, or go back to equal temperament
+    ScaleTable(Option<ScaleTable>),
+    /// Meter the outputs of the module with this name, or stop metering
+    Meter(Option<String>),
+    /// Move a knob of the module with this name to a position along its travel
+    SetParam(String, usize, f32),
+}
+
+/// What the manager has found out about the rest of the network, for the network panel.
+enum Discovery {
+    /// Every other module heard from recently
+    Peers(Vec<String>),
+    Description(String, ModuleDescription),
+    /// Peak level of each metered output, from 0 to 1
+    Levels(Vec<f32>),
+}
+
 fn main() {
     SimpleLogger::new()
         .with_level(log::LevelFilter::Info)
@@ -72,30 +206,188 @@ fn main() {
         .unwrap();
 
     let (tx, rx) = channel();
+    let (status_tx, status_rx) = channel();
+    let (discovery_tx, discovery_rx) = channel();
 
     thread::spawn(move || {
-        let mut module: Module<_, _, 0, 0> = Module::new(
+        let mut module: Module<_, _, METER_JACKS, 0, _> = Module::with_clock(
             SelectedInterface::new().unwrap(),
             rand::thread_rng(),
             "Manager".into(),
             0,
-            0,
+            StdClock::new(),
         );
-        let start = Instant::now();
-        let mut time: i64 = 0;
+        // The manager outlives the rest of the patch, so it coordinates for everyone
+        module.set_coordinator(true);
+        module.set_capability(Capability::Supervisor);
+        let meters = [(); METER_JACKS].map(|_| module.add_monitor_jack().unwrap());
+        match PatchJournal::open(JOURNAL_PATH) {
+            Ok(journal) => {
+                if !journal.is_empty() {
+                    info!(
+                        "Restoring {} connections from {}",
+                        journal.len(),
+                        JOURNAL_PATH
+                    );
+                }
+                module.set_journal(journal);
+            }
+            Err(e) => info!("Error opening patch journal: {}", e),
+        }
+        let mut auditing = false;
+        let mut feedback = false;
+        // Outputs of each module that has described itself, and the module being metered
+        let mut described: HashMap<String, usize> = HashMap::new();
+        let mut metered: Option<String> = None;
+        let mut levels = [0.0f32; METER_JACKS];
 
         'outer: loop {
-            while time < start.elapsed().as_millis() as i64 {
-                module.poll(time, |_| {}).unwrap();
+            while let Some(time) = module.next_tick() {
+                let update = module
+                    .poll(time, |block| {
+                        for (level, meter) in levels.iter_mut().zip(meters) {
+                            *level = level.max(peak(block.get_monitor(meter)));
+                        }
+                    })
+                    .unwrap();
+                if let Some((uuid, description)) = update.peer_description() {
+                    described.insert(uuid.to_string(), description.outputs.len());
+                    let found = Discovery::Description(uuid.to_string(), description.clone());
+                    if discovery_tx.send(found).is_err() {
+                        break 'outer;
+                    }
+                }
+                if time.is_multiple_of(DISCOVERY_INTERVAL) {
+                    let peers: Vec<String> = module.peers().map(|uuid| uuid.to_string()).collect();
+                    for uuid in peers.iter().filter(|uuid| !described.contains_key(*uuid)) {
+                        if let Err(e) = module.send_describe_request(uuid.as_str().into()) {
+                            info!("Describe request failed {:?}", e);
+                        }
+                    }
+                    if discovery_tx.send(Discovery::Peers(peers)).is_err() {
+                        break 'outer;
+                    }
+                    // Renew the probes well before they time out
+                    if let Some(uuid) = &metered {
+                        probe_outputs(&mut module, &meters, uuid, &described, time);
+                    }
+                }
+                if time.is_multiple_of(METER_INTERVAL) && metered.is_some() {
+                    let outputs = metered.as_ref().and_then(|uuid| described.get(uuid));
+                    let count = outputs.copied().unwrap_or(0).min(METER_JACKS);
+                    let found = Discovery::Levels(levels[..count].to_vec());
+                    if discovery_tx.send(found).is_err() {
+                        break 'outer;
+                    }
+                    levels = [0.0; METER_JACKS];
+                }
+                // Say so once per patch that would close a loop, so it isn't made by accident
+                if update.is_feedback() != feedback {
+                    feedback = update.is_feedback();
+                    if let (true, Some(input), Some(output)) =
+                        (feedback, update.held_input(), update.held_output())
+                    {
+                        let made = match update.patch_state() {
+                            PatchState::Failed => "refused",
+                            _ => "made",
+                        };
+                        let status =
+                            format!("Feedback loop {}:\n{} -> {}", made, output.uuid, input.uuid);
+                        if status_tx.send(status).is_err() {
+                            break 'outer;
+                        }
+                    }
+                }
                 match rx.try_recv() {
-                    Ok(true) => module.send_halt(),
-                    Ok(false) => {}
+                    Ok(Command::Halt) => {
+                        module.send_halt();
+                        // The patch is gone on purpose, so there's nothing to bring back
+                        if let Some(Err(e)) = module.journal_mut().map(|j| j.clear()) {
+                            info!("Error clearing patch journal: {}", e);
+                        }
+                    }
+                    Ok(Command::Audit) => match module.audit(time) {
+                        Ok(()) => auditing = true,
+                        Err(e) => info!("Audit failed {:?}", e),
+                    },
+                    Ok(Command::Export) => {
+                        let status = match module.journal_mut() {
+                            Some(journal) => export_patch(&journal.topology()),
+                            None => "No patch journal to export from".to_owned(),
+                        };
+                        if status_tx.send(status).is_err() {
+                            break 'outer;
+                        }
+                    }
+                    Ok(Command::Mute(name, on)) => {
+                        if let Err(e) = module.send_mute(name.as_str().into(), on) {
+                            info!("Mute command failed {:?}", e);
+                        }
+                    }
+                    Ok(Command::Bypass(name, on)) => {
+                        if let Err(e) = module.send_bypass(name.as_str().into(), on) {
+                            info!("Bypass command failed {:?}", e);
+                        }
+                    }
+                    Ok(Command::Solo(name, on)) => {
+                        if let Err(e) = module.send_solo(name.as_str().into(), on) {
+                            info!("Solo command failed {:?}", e);
+                        }
+                    }
+                    Ok(Command::StorePreset(name, slot, slot_name)) => {
+                        if let Err(e) =
+                            module.send_store_preset(name.as_str().into(), slot, &slot_name)
+                        {
+                            info!("Store preset command failed {:?}", e);
+                        }
+                    }
+                    Ok(Command::RecallPreset(name, slot)) => {
+                        if let Err(e) = module.send_recall_preset(name.as_str().into(), slot) {
+                            info!("Recall preset command failed {:?}", e);
+                        }
+                    }
+                    Ok(Command::FeedbackPolicy(policy)) => module.set_feedback_policy(policy),
+                    Ok(Command::ScaleTable(table)) => {
+                        if let Err(e) = module.send_scale_table(table) {
+                            info!("Scale table command failed {:?}", e);
+                        }
+                    }
+                    Ok(Command::Meter(uuid)) => {
+                        for meter in meters {
+                            if let Err(e) = module.probe_cancel(meter, time) {
+                                info!("Probe cancel failed {:?}", e);
+                            }
+                        }
+                        if let Some(uuid) = &uuid {
+                            probe_outputs(&mut module, &meters, uuid, &described, time);
+                        }
+                        metered = uuid;
+                    }
+                    Ok(Command::SetParam(uuid, param, position)) => {
+                        let change = ParamChange::Set(position);
+                        if let Err(e) = module.send_set_param(uuid.as_str().into(), param, change) {
+                            info!("Set param command failed {:?}", e);
+                        }
+                    }
                     Err(TryRecvError::Empty) => {}
                     Err(TryRecvError::Disconnected) => break 'outer,
                 }
-                time += 1;
+                if let (true, Some(report)) = (auditing, module.audit_report()) {
+                    auditing = false;
+                    let mut status = format!(
+                        "Audit: {} modules, {} mismatches",
+                        report.modules,
+                        report.mismatches.len()
+                    );
+                    for m in &report.mismatches {
+                        status += &format!("\n{} -> {}", m.output, m.input);
+                    }
+                    if status_tx.send(status).is_err() {
+                        break 'outer;
+                    }
+                }
             }
-            thread::sleep(Duration::from_millis(0));
+            thread::sleep(std::time::Duration::from_millis(0));
         }
     });
 
@@ -108,30 +400,348 @@ fn main() {
     eframe::run_native(
         "Module Test Sandbox",
         options,
-        Box::new(|_cc| Box::new(Manager::new(tx))),
+        Box::new(|_cc| Box::new(Manager::new(tx, status_rx, discovery_rx))),
     );
 }
 
+/// Listen in on the outputs of module `uuid` with the manager's monitor jacks, as many as fit.
+fn probe_outputs(
+    module: &mut Module<SelectedInterface<METER_JACKS, 0>, ThreadRng, METER_JACKS, 0, StdClock>,
+    meters: &[MonitorJackHandle; METER_JACKS],
+    uuid: &str,
+    described: &HashMap<String, usize>,
+    time: MonotonicTime,
+) {
+    let outputs = described.get(uuid).copied().unwrap_or(0);
+    for (jack_id, meter) in meters.iter().enumerate().take(outputs) {
+        if let Err(e) = module.probe(*meter, uuid.into(), jack_id as u32, time) {
+            info!("Probe failed {:?}", e);
+        }
+    }
+}
+
+/// Loudest sample of a block, from 0 to 1.
+fn peak(packet: &AudioPacket) -> f32 {
+    packet
+        .frames()
+        .flat_map(|frame| frame.channels())
+        .map(|x| x.unsigned_abs())
+        .max()
+        .unwrap_or(0) as f32
+        / i16::MAX as f32
+}
+
+/// Where each window goes in the rack, relative to its top left, filling rows left to right up
+/// to `width` across.
+fn rack_layout(sizes: &[egui::Vec2], width: f32) -> Vec<egui::Pos2> {
+    let mut slots = Vec::with_capacity(sizes.len());
+    let (mut x, mut y, mut row_height) = (0.0, 0.0, 0.0f32);
+    for size in sizes {
+        if x > 0.0 && x + size.x > width {
+            x = 0.0;
+            y += row_height + RACK_GAP;
+            row_height = 0.0;
+        }
+        slots.push(egui::pos2(x, y));
+        x += size.x + RACK_GAP;
+        row_height = row_height.max(size.y);
+    }
+    slots
+}
+
+/// The rack slot closest to `pos`.
+fn nearest_slot(slots: &[egui::Pos2], pos: egui::Pos2) -> usize {
+    slots
+        .iter()
+        .enumerate()
+        .min_by(|(_, a), (_, b)| a.distance(pos).total_cmp(&b.distance(pos)))
+        .map_or(0, |(i, _)| i)
+}
+
+/// Write the patch out for Graphviz and Mermaid, returning the status to show.
+fn export_patch(topology: &Topology) -> String {
+    let written = std::fs::write(DOT_PATH, topology.to_dot())
+        .and_then(|_| std::fs::write(MERMAID_PATH, topology.to_mermaid()));
+    match written {
+        Ok(()) => format!(
+            "Exported {} connections to {} and {}",
+            topology.edges.len(),
+            DOT_PATH,
+            MERMAID_PATH
+        ),
+        Err(e) => format!("Error exporting patch: {}", e),
+    }
+}
+
+struct Window {
+    kind: String,
+    handler: Box<dyn DisplayHandler>,
+    muted: bool,
+    bypassed: bool,
+    /// Preset slot picked on the module, and the names given to each
+    slot: usize,
+    slot_names: Vec<String>,
+    /// Size as last drawn, to lay out the rack with
+    size: egui::Vec2,
+    dragging: bool,
+}
+
 struct Manager {
     status: String,
-    tx: Sender<bool>,
-    windows: Vec<Box<dyn DisplayHandler>>,
+    tx: Sender<Command>,
+    status_rx: Receiver<String>,
+    windows: Vec<Window>,
     window_count: u32,
+    /// Name of the module soloed, if any
+    soloed: Option<String>,
+    /// Arrange the windows into rack rows, in order, rather than leaving them where dropped
+    rack: bool,
+    feedback_policy: FeedbackPolicy,
+    discovery_rx: Receiver<Discovery>,
+    /// Every other module on the network, with its description once it has sent one
+    network: BTreeMap<String, Option<ModuleDescription>>,
+    /// Module picked in the network panel, where its knobs are as last set from here, and the
+    /// levels of its outputs
+    picked: Option<String>,
+    remote_knobs: Vec<f32>,
+    levels: Vec<f32>,
 }
 
 impl Manager {
-    fn new(tx: Sender<bool>) -> Self {
+    fn new(
+        tx: Sender<Command>,
+        status_rx: Receiver<String>,
+        discovery_rx: Receiver<Discovery>,
+    ) -> Self {
         Self {
             status: "Loading...".to_owned(),
             tx,
+            status_rx,
             windows: vec![],
             window_count: 0,
+            soloed: None,
+            rack: false,
+            feedback_policy: Default::default(),
+            discovery_rx,
+            network: BTreeMap::new(),
+            picked: None,
+            remote_knobs: vec![],
+            levels: vec![],
+        }
+    }
+
+    fn receive_discovery(&mut self) {
+        while let Ok(found) = self.discovery_rx.try_recv() {
+            match found {
+                Discovery::Peers(peers) => {
+                    self.network.retain(|uuid, _| peers.contains(uuid));
+                    for uuid in peers {
+                        self.network.entry(uuid).or_insert(None);
+                    }
+                }
+                Discovery::Description(uuid, description) => {
+                    let picked = self.picked.as_ref() == Some(&uuid);
+                    self.network.insert(uuid, Some(description));
+                    // Metering needs to know how many outputs there are to listen in on
+                    if picked {
+                        self.pick(self.picked.clone());
+                    }
+                }
+                Discovery::Levels(levels) => self.levels = levels,
+            }
+        }
+    }
+
+    /// Show the knobs and meters of module `uuid` in the network panel.
+    fn pick(&mut self, uuid: Option<String>) {
+        let description = uuid
+            .as_ref()
+            .and_then(|uuid| self.network.get(uuid))
+            .cloned();
+        // Knobs start out at their defaults, since there's no asking where they are
+        self.remote_knobs = description
+            .flatten()
+            .map(|d| d.params.iter().map(|p| p.position(p.default)).collect())
+            .unwrap_or_default();
+        self.levels.clear();
+        self.tx.send(Command::Meter(uuid.clone())).unwrap();
+        self.picked = uuid;
+    }
+
+    /// Every module heard from on the network, whether opened here or not, with the jacks and
+    /// knobs of the one picked. Modules in other processes and on hardware can be played from
+    /// here too.
+    fn network_panel(&mut self, ui: &mut egui::Ui) {
+        ui.heading("Network");
+        ui.add_space(10.0);
+        let mut picked = None;
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            for (uuid, description) in &self.network {
+                let local = self.windows.iter().any(|w| w.handler.name() == uuid);
+                let label = match (description, local) {
+                    (Some(d), false) => format!("{} ({})", uuid, d.kind),
+                    (Some(d), true) => format!("{} ({}, here)", uuid, d.kind),
+                    (None, _) => uuid.clone(),
+                };
+                let selected = self.picked.as_ref() == Some(uuid);
+                if ui.selectable_label(selected, label).clicked() {
+                    picked = Some(if selected { None } else { Some(uuid.clone()) });
+                }
+            }
+        });
+        if let Some(uuid) = picked {
+            self.pick(uuid);
+        }
+        let description = match self.picked.as_ref().and_then(|uuid| self.network.get(uuid)) {
+            Some(Some(description)) => description,
+            Some(None) => {
+                ui.label("Waiting for a description...");
+                return;
+            }
+            None => return,
+        };
+        let uuid = self.picked.clone().unwrap_or_default();
+        ui.separator();
+        let inputs: Vec<&str> = description
+            .inputs
+            .iter()
+            .map(|name| name.as_str())
+            .collect();
+        ui.label(format!("Inputs: {}", inputs.join(", ")));
+        for (i, name) in description.outputs.iter().enumerate() {
+            match self.levels.get(i) {
+                Some(level) => {
+                    ui.add(egui::ProgressBar::new(*level).text(name.as_str()));
+                }
+                None => {
+                    ui.label(name.as_str());
+                }
+            }
+        }
+        for (i, (param, position)) in description
+            .params
+            .iter()
+            .zip(self.remote_knobs.iter_mut())
+            .enumerate()
+        {
+            if param.name.is_empty() {
+                continue;
+            }
+            let value = param.value(*position);
+            let slider = egui::Slider::new(position, 0.0..=1.0)
+                .show_value(false)
+                .text(format!("{} {:.2}{}", param.name, value, param.unit));
+            if ui.add(slider).changed() {
+                let _ = self.tx.send(Command::SetParam(uuid.clone(), i, *position));
+            }
+        }
+    }
+
+    fn open_window(&mut self, kind: &str, id: &str) -> Option<&mut Window> {
+        let handler = window_build(kind, id).ok()?;
+        // Keep new windows from taking the names of ones loaded from a preset
+        if let Some(num) = id.rsplit(':').next().and_then(|n| n.parse::<u32>().ok()) {
+            self.window_count = self.window_count.max(num);
+        }
+        self.window_count += 1;
+        let size = egui::vec2(handler.width() * HP, ROW_HEIGHT);
+        self.windows.push(Window {
+            kind: kind.to_owned(),
+            handler,
+            muted: false,
+            bypassed: false,
+            slot: 0,
+            slot_names: vec![String::new(); PRESET_SLOTS],
+            size,
+            dragging: false,
+        });
+        self.windows.last_mut()
+    }
+
+    fn save_preset(&mut self) {
+        let preset = Preset {
+            windows: self
+                .windows
+                .iter()
+                .map(|w| WindowPreset {
+                    kind: w.kind.clone(),
+                    name: w.handler.name().to_owned(),
+                    settings: w.handler.save_preset(),
+                    slot_names: w.slot_names.clone(),
+                })
+                .collect(),
+            rack: self.rack,
+        };
+        self.status = match preset.save(Path::new(PRESET_PATH)) {
+            Ok(()) => format!("Saved preset to {}", PRESET_PATH),
+            Err(e) => format!("Error saving preset: {}", e),
+        };
+    }
+
+    fn load_preset(&mut self) {
+        let preset = match Preset::load(Path::new(PRESET_PATH)) {
+            Ok(preset) => preset,
+            Err(e) => {
+                self.status = format!("Error loading preset: {}", e);
+                return;
+            }
+        };
+        self.windows.clear();
+        self.rack = preset.rack;
+        for w in &preset.windows {
+            if let Some(window) = self.open_window(&w.kind, &w.name) {
+                window.handler.load_preset(&w.settings);
+                for (name, saved) in window.slot_names.iter_mut().zip(&w.slot_names) {
+                    *name = saved.clone();
+                }
+            }
+        }
+        self.status = format!("Loaded preset from {}", PRESET_PATH);
+    }
+
+    fn load_scale_table(&mut self) {
+        let table = std::fs::read_to_string(SCL_PATH)
+            .map_err(|e| e.to_string())
+            .and_then(|scl| ScaleTable::from_scl(&scl).map_err(|e| e.to_string()))
+            .and_then(|table| match std::fs::read_to_string(KBM_PATH) {
+                Ok(kbm) => table.with_kbm(&kbm).map_err(|e| e.to_string()),
+                Err(_) => Ok(table),
+            });
+        self.status = match table {
+            Ok(table) => {
+                let status = format!("Tuned to {} degrees from {}", table.degrees.len(), SCL_PATH);
+                self.tx.send(Command::ScaleTable(Some(table))).unwrap();
+                status
+            }
+            Err(e) => format!("Error loading tuning: {}", e),
+        };
+    }
+
+    /// Pass settings between windows, such as routing switched by a morph.
+    fn apply_preset_requests(&mut self) {
+        let requests: Vec<WindowPreset> = self
+            .windows
+            .iter_mut()
+            .flat_map(|w| w.handler.preset_requests())
+            .collect();
+        for request in &requests {
+            if let Some(w) = self
+                .windows
+                .iter_mut()
+                .find(|w| w.kind == request.kind && w.handler.name() == request.name)
+            {
+                w.handler.load_preset(&request.settings);
+            }
         }
     }
 }
 
 impl eframe::App for Manager {
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Ok(status) = self.status_rx.try_recv() {
+            self.status = status;
+        }
+        self.receive_discovery();
         egui::SidePanel::left("left_panel").show(ctx, |ui| {
             ui.heading("Manager");
             ui.add_space(20.0);
@@ -141,25 +751,56 @@ impl eframe::App for Manager {
                     if ui.button("🔌    Close All").clicked() {
                         // Send halt directive
                         info!("Close button clicked");
-                        self.tx.send(true).unwrap();
+                        self.tx.send(Command::Halt).unwrap();
                         self.windows.clear();
                     }
+                    if ui.button("Audit Patch").clicked() {
+                        self.status = "Auditing...".to_owned();
+                        self.tx.send(Command::Audit).unwrap();
+                    }
+                    if ui.button("Export Patch").clicked() {
+                        self.tx.send(Command::Export).unwrap();
+                    }
+                    ui.checkbox(&mut self.rack, "Arrange in Rack");
+                    let policy = self.feedback_policy;
+                    egui::ComboBox::from_label("Feedback")
+                        .selected_text(format!("{:?}", policy))
+                        .show_ui(ui, |ui| {
+                            for p in [
+                                FeedbackPolicy::Allow,
+                                FeedbackPolicy::Warn,
+                                FeedbackPolicy::Refuse,
+                            ] {
+                                ui.selectable_value(
+                                    &mut self.feedback_policy,
+                                    p,
+                                    format!("{:?}", p),
+                                );
+                            }
+                        });
+                    if self.feedback_policy != policy {
+                        self.tx
+                            .send(Command::FeedbackPolicy(self.feedback_policy))
+                            .unwrap();
+                    }
                     if ui.button("Save Preset").clicked() {
-                        // Gather preset information
+                        self.save_preset();
                     }
                     if ui.button("Load Preset").clicked() {
-                        // Send preset information
+                        self.load_preset();
+                    }
+                    if ui.button("Load Tuning").clicked() {
+                        self.load_scale_table();
+                    }
+                    if ui.button("Equal Temperament").clicked() {
+                        self.tx.send(Command::ScaleTable(None)).unwrap();
+                        self.status = "Back to equal temperament".to_owned();
                     }
                     ui.add_space(20.0);
                     for w in WINDOWS {
                         if ui.button(w).clicked() {
-                            match window_build(w, self.window_count) {
-                                Ok(a) => {
-                                    self.windows.push(a);
-                                    self.window_count += 1;
-                                }
-                                Err(_) => {}
-                            }
+                            let id = format!("{}:{}", w, self.window_count);
+                            self.open_window(w, &id);
                         }
                     }
                     ui.add_space(100.0);
@@ -167,29 +808,110 @@ impl eframe::App for Manager {
                 },
             );
         });
-        self.windows.retain(|w| w.is_open());
+        egui::SidePanel::right("network_panel").show(ctx, |ui| self.network_panel(ui));
+        self.windows.retain(|w| w.handler.is_open());
+        self.apply_preset_requests();
+        let (tx, soloed) = (&self.tx, &mut self.soloed);
+        let rack = self.rack;
+        let mut moved = None;
         egui::CentralPanel::default().show(ctx, |ui| {
-            ui.horizontal(|ui| {
-                for w in &mut self.windows {
-                    egui::Area::new(w.name())
-                        // egui::containers::Resize::default()
-                        //    .fixed_size((15.0 * w.width(), 450.0))
-                        .show(ctx, |ui| {
-                            ui.vertical(|ui| {
-                                egui::containers::Frame::none()
-                                    .rounding(2.0)
-                                    .stroke((1.0, egui::Color32::BLACK).into())
-                                    .inner_margin(4.0)
-                                    .show(ui, |mut ui| {
-                                        w.update(&mut ui);
-                                        // ui.allocate_space(ui.available_size());
-                                    });
-                            });
+            let origin = ui.min_rect().min;
+            let sizes: Vec<egui::Vec2> = self.windows.iter().map(|w| w.size).collect();
+            let slots = rack_layout(&sizes, ui.available_width());
+            for (i, w) in self.windows.iter_mut().enumerate() {
+                let mut area = egui::Area::new(w.handler.name());
+                // Leave the window being dragged to follow the pointer until it is dropped
+                if rack && !w.dragging {
+                    area = area.current_pos(origin + slots[i].to_vec2());
+                }
+                let response = area
+                    .show(ctx, |ui| {
+                        ui.vertical(|ui| {
+                            egui::containers::Frame::none()
+                                .rounding(2.0)
+                                .stroke((1.0, egui::Color32::BLACK).into())
+                                .inner_margin(4.0)
+                                .show(ui, |mut ui| {
+                                    mute_buttons(w, tx, soloed, ui);
+                                    preset_slots(w, tx, ui);
+                                    w.handler.update(&mut ui);
+                                });
                         });
+                    })
+                    .response;
+                w.size = response.rect.size();
+                w.dragging = response.dragged();
+                if rack && response.drag_released() {
+                    let dropped = response.rect.min - origin;
+                    moved = Some((i, nearest_slot(&slots, dropped.to_pos2())));
                 }
-                ui.allocate_space(ui.available_size());
-            });
+            }
+            ui.allocate_space(ui.available_size());
         });
+        // Snap the dropped window into the slot it was let go over
+        if let Some((from, to)) = moved {
+            let w = self.windows.remove(from);
+            self.windows.insert(to, w);
+        }
         ctx.request_repaint();
     }
 }
+
+/// Mute, bypass and solo toggles for a window's module, sent over the network from the manager
+/// so that they work the same on modules that aren't running in this process. Soloing one
+/// module lets go of any other solo.
+fn mute_buttons(
+    w: &mut Window,
+    tx: &Sender<Command>,
+    soloed: &mut Option<String>,
+    ui: &mut egui::Ui,
+) {
+    let name = w.handler.name().to_owned();
+    ui.horizontal(|ui| {
+        if ui.selectable_label(w.muted, "Mute").clicked() {
+            w.muted = !w.muted;
+            let _ = tx.send(Command::Mute(name.clone(), w.muted));
+        }
+        if ui.selectable_label(w.bypassed, "Bypass").clicked() {
+            w.bypassed = !w.bypassed;
+            let _ = tx.send(Command::Bypass(name.clone(), w.bypassed));
+        }
+        let solo = soloed.as_ref() == Some(&name);
+        if ui.selectable_label(solo, "Solo").clicked() {
+            *soloed = if solo { None } else { Some(name.clone()) };
+            let _ = tx.send(Command::Solo(name, !solo));
+        }
+    });
+}
+
+/// Store and recall the presets a module keeps of its own knobs, which works the same for
+/// hardware modules. The slots are numbered on the module, and named here.
+fn preset_slots(w: &mut Window, tx: &Sender<Command>, ui: &mut egui::Ui) {
+    let name = w.handler.name().to_owned();
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source((&name, "preset"))
+            .width(40.0)
+            .selected_text(format!("{}", w.slot + 1))
+            .show_ui(ui, |ui| {
+                for (slot, slot_name) in w.slot_names.iter().enumerate() {
+                    ui.selectable_value(&mut w.slot, slot, format!("{} {}", slot + 1, slot_name));
+                }
+            });
+        let slot_name = &mut w.slot_names[w.slot];
+        ui.add(egui::TextEdit::singleline(slot_name).desired_width(60.0));
+        // The module cuts longer names short anyway, so don't let them be typed
+        while slot_name.len() > PRESET_NAME_LEN {
+            slot_name.pop();
+        }
+        if ui.button("Store").clicked() {
+            let _ = tx.send(Command::StorePreset(
+                name.clone(),
+                w.slot,
+                slot_name.clone(),
+            ));
+        }
+        if ui.button("Recall").clicked() {
+            let _ = tx.send(Command::RecallPreset(name, w.slot));
+        }
+    });
+}
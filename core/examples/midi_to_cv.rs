@@ -1,8 +1,8 @@
-use apiary_core::{midi_note_to_voct, AudioFrame, AudioPacket, BLOCK_SIZE, CHANNELS};
+use apiary_core::{AudioPacket, BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE};
 use midir::{MidiInput, MidiInputConnection};
 use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
 
-use crate::display_module::{DisplayModule, Processor};
+use crate::{display_module::DisplayModule, voices::VoiceAllocator};
 
 #[derive(Debug)]
 enum MidiMessage {
@@ -11,15 +11,6 @@ enum MidiMessage {
     Unimplemented,
 }
 
-#[derive(Copy, Clone, Default, Debug)]
-struct Voice {
-    note: u8,
-    on: bool,
-    just_on: bool,
-    vel: u8,
-    timestamp: i64,
-}
-
 fn midi_dispatch(message: &[u8], tx: &Sender<MidiMessage>) {
     let result = if message.len() != 3 {
         MidiMessage::Unimplemented
@@ -34,8 +25,7 @@ fn midi_dispatch(message: &[u8], tx: &Sender<MidiMessage>) {
 }
 
 pub struct MidiToCv {
-    voices: [Voice; CHANNELS],
-    time: i64,
+    voices: VoiceAllocator,
     rx: Receiver<MidiMessage>,
     _midi_connections: Vec<MidiInputConnection<()>>,
 }
@@ -86,7 +76,6 @@ impl MidiToCv {
             .output(MDWH_OUTPUT, "Mod Wheel")
             .start(MidiToCv {
                 voices: Default::default(),
-                time: 0,
                 rx: midi_rx,
                 _midi_connections: midi_connections,
             })
@@ -96,67 +85,32 @@ impl MidiToCv {
 impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for MidiToCv {
     fn process(
         &mut self,
-        _input: [&AudioPacket; NUM_INPUTS],
-        output: &mut [AudioPacket; NUM_OUTPUTS],
-        _params: &[f32; NUM_PARAMS],
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        _params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
     ) {
+        let output = block.outputs();
         match self.rx.try_recv() {
             Ok(message) => {
                 trace!("{:?}", message);
                 match message {
-                    MidiMessage::NoteOff(_, note, _) => {
-                        for v in self.voices.iter_mut().filter(|v| v.note == note && v.on) {
-                            v.on = false;
-                            v.timestamp = self.time;
-                        }
-                    }
-                    MidiMessage::NoteOn(_, note, vel) => {
-                        // First, see if we can take the oldest voice that has been
-                        // released. Otherwise, steal a voice. In this case, take the
-                        // oldest note played. We also have a choice of whether to just
-                        // change the pitch (done here), or to shut the note off and
-                        // retrigger.
-                        if let Some(v) = self.voices.iter_mut().min_by_key(|v| (v.on, v.timestamp))
-                        {
-                            v.note = note;
-                            v.on = true;
-                            v.just_on = true;
-                            v.vel = vel;
-                            v.timestamp = self.time;
-                        }
-                    }
+                    MidiMessage::NoteOff(_, note, _) => self.voices.note_off(note),
+                    MidiMessage::NoteOn(_, note, vel) => self.voices.note_on(note, vel),
                     _ => {}
                 }
-                for v in self.voices {
-                    trace!("{:?}", v);
-                }
             }
             Err(TryRecvError::Empty) => {}
             Err(TryRecvError::Disconnected) => panic!("Midi Disconnected"),
         }
-        let mut note_frame: AudioFrame = Default::default();
-        let mut gate_frame: AudioFrame = Default::default();
-        let mut vel_frame: AudioFrame = Default::default();
-        for i in 0..CHANNELS {
-            note_frame.data[i] = midi_note_to_voct(self.voices[i].note);
-            if self.voices[i].on {
-                if self.voices[i].just_on {
-                    self.voices[i].just_on = false;
-                } else {
-                    gate_frame.data[i] = 16000;
-                }
-            }
-            vel_frame.data[i] = (self.voices[i].vel as i16) << 7;
-        }
-        output[NOTE_OUTPUT] = AudioPacket {
+        let (note_frame, gate_frame, vel_frame) = self.voices.next_block();
+        *output[NOTE_OUTPUT] = AudioPacket {
             data: [note_frame; BLOCK_SIZE],
         };
-        output[GATE_OUTPUT] = AudioPacket {
+        *output[GATE_OUTPUT] = AudioPacket {
             data: [gate_frame; BLOCK_SIZE],
         };
-        output[VEL_OUTPUT] = AudioPacket {
+        *output[VEL_OUTPUT] = AudioPacket {
             data: [vel_frame; BLOCK_SIZE],
         };
-        self.time += 1;
     }
 }
@@ -1,14 +1,21 @@
-use apiary_core::{AudioPacket, CHANNELS};
+use apiary_core::{
+    dsp::mix::{pan, stereo_width},
+    BlockContext, ParamBlock, ProcessBlock, Processor, CHANNELS, STEREO_PAIRS,
+};
 use itertools::izip;
 
-use crate::display_module::{DisplayModule, Processor};
+use crate::display_module::DisplayModule;
 
 pub struct Mixer {
     level: [[f32; 3]; CHANNELS],
 }
 
 const SCALE_PARAM: usize = 0;
-const NUM_PARAMS: usize = 1;
+const PAN0_PARAM: usize = 1;
+const PAN1_PARAM: usize = 2;
+const PAN2_PARAM: usize = 3;
+const WIDTH_PARAM: usize = 4;
+const NUM_PARAMS: usize = 5;
 
 const IN0_INPUT: usize = 0;
 const LEVEL0_INPUT: usize = 1;
@@ -19,12 +26,14 @@ const LEVEL2_INPUT: usize = 5;
 const NUM_INPUTS: usize = 6;
 
 const MIX_OUTPUT: usize = 0;
-const NUM_OUTPUTS: usize = 1;
+const STEREO_OUTPUT: usize = 1;
+const NUM_OUTPUTS: usize = 2;
 
 impl Mixer {
     pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
         DisplayModule::new()
             .name(name)
+            .latency_compensation(true)
             .input(IN0_INPUT, "Input 0")
             .input(LEVEL0_INPUT, "Level 0")
             .input(IN1_INPUT, "Input 1")
@@ -32,9 +41,14 @@ impl Mixer {
             .input(IN2_INPUT, "Input 2")
             .input(LEVEL2_INPUT, "Level 2")
             .param(SCALE_PARAM, 0.0, 100.0, 100.0, "Scale", "%", false)
+            .param(PAN0_PARAM, -1.0, 1.0, 0.0, "Pan 0", "", false)
+            .param(PAN1_PARAM, -1.0, 1.0, 0.0, "Pan 1", "", false)
+            .param(PAN2_PARAM, -1.0, 1.0, 0.0, "Pan 2", "", false)
+            .param(WIDTH_PARAM, 0.0, 2.0, 1.0, "Width", "", false)
             .output(MIX_OUTPUT, "Mix Out")
+            .output(STEREO_OUTPUT, "Stereo Out")
             .start(Mixer {
-                level: [[0.0; 3]; 8],
+                level: [[0.0; 3]; CHANNELS],
             })
     }
 }
@@ -42,19 +56,32 @@ impl Mixer {
 impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Mixer {
     fn process(
         &mut self,
-        input: [&AudioPacket; NUM_INPUTS],
-        output: &mut [AudioPacket; NUM_OUTPUTS],
-        params: &[f32; NUM_PARAMS],
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
     ) {
-        for (in0, l0, in1, l1, in2, l2, o) in izip!(
+        let input = block.inputs();
+        let output = block.outputs();
+        let (mix_output, stereo_output) = output.split_at_mut(STEREO_OUTPUT);
+        for (i, (in0, l0, in1, l1, in2, l2, o, st)) in izip!(
             input[IN0_INPUT].data,
             input[LEVEL0_INPUT].data,
             input[IN1_INPUT].data,
             input[LEVEL1_INPUT].data,
             input[IN2_INPUT].data,
             input[LEVEL2_INPUT].data,
-            output[MIX_OUTPUT].data.iter_mut()
-        ) {
+            mix_output[MIX_OUTPUT].data.iter_mut(),
+            stereo_output[0].data.iter_mut()
+        )
+        .enumerate()
+        {
+            let scale = params.at(SCALE_PARAM, i) as i32;
+            let pans = [
+                params.at(PAN0_PARAM, i),
+                params.at(PAN1_PARAM, i),
+                params.at(PAN2_PARAM, i),
+            ];
+            let (mut left, mut right) = (0.0, 0.0);
             for (fin0, fl0, fin1, fl1, fin2, fl2, fo, flev) in izip!(
                 in0.data,
                 l0.data,
@@ -68,10 +95,22 @@ impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Mixer {
                 flev[0] += 0.01 * (fl0 as f32 - flev[0]);
                 flev[1] += 0.01 * (fl1 as f32 - flev[1]);
                 flev[2] += 0.01 * (fl2 as f32 - flev[2]);
-                *fo = ((fin0 as i32 * flev[0] as i32 / 100 * params[SCALE_PARAM] as i32
-                    + fin1 as i32 * flev[1] as i32 / 100 * params[SCALE_PARAM] as i32
-                    + fin2 as i32 * flev[2] as i32 / 100 * params[SCALE_PARAM] as i32)
+                *fo = ((fin0 as i32 * flev[0] as i32 / 100 * scale
+                    + fin1 as i32 * flev[1] as i32 / 100 * scale
+                    + fin2 as i32 * flev[2] as i32 / 100 * scale)
                     >> 16) as i16;
+
+                // Every voice is folded into a single stereo mix
+                for (x, lev, p) in izip!([fin0, fin1, fin2], flev.iter(), pans) {
+                    let (l, r) = pan(x as f32 * lev / 100.0 * scale as f32 / 65536.0, p);
+                    left += l;
+                    right += r;
+                }
+            }
+            // The stereo mix goes out on the first pair, leaving the rest silent
+            if STEREO_PAIRS > 0 {
+                let (l, r) = stereo_width(left, right, params.at(WIDTH_PARAM, i));
+                st.set_stereo(0, (l as i16, r as i16));
             }
         }
     }
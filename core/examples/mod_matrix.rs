@@ -0,0 +1,322 @@
+use apiary_core::{
+    time::{Clock, Duration, MonotonicTime, StdClock},
+    Module, ParamChange, Uuid, BLOCK_SIZE,
+};
+use eframe::egui;
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    common::{Jack, SelectedInterface},
+    display_module::DisplayHandler,
+    preset::ModulePreset,
+};
+
+const NUM_SOURCES: usize = 4;
+const NUM_DESTINATIONS: usize = 8;
+const COLOR: u16 = 270;
+
+/// How often modulation is sent to each destination
+const SEND_INTERVAL: Duration = Duration::from_millis(10);
+/// How often an unchanged offset is sent again, in case it was lost
+const REFRESH_INTERVAL: Duration = Duration::from_millis(500);
+/// Smallest change in an offset worth sending, as a fraction of a knob's travel
+const SEND_THRESHOLD: f32 = 1.0 / 512.0;
+/// How often the list of modules to choose destinations from is updated
+const PEER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How much of a source reaches a destination.
+#[derive(Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+struct Cell {
+    depth: f32,
+    inverted: bool,
+}
+
+impl Cell {
+    fn gain(&self) -> f32 {
+        if self.inverted {
+            -self.depth
+        } else {
+            self.depth
+        }
+    }
+}
+
+/// A knob on another module (or another window in this manager), and how much each source
+/// moves it.
+#[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Destination {
+    module: String,
+    param: usize,
+    cells: [Cell; NUM_SOURCES],
+}
+
+#[derive(Clone, Default, Serialize, Deserialize)]
+struct Matrix {
+    destinations: [Destination; NUM_DESTINATIONS],
+}
+
+/// Offset last sent to a destination.
+struct Sent {
+    uuid: Uuid,
+    param: usize,
+    offset: f32,
+    time: MonotonicTime,
+}
+
+/// Routes CV inputs to the knobs of any module on the network through `SetParam` directives, so
+/// that one cable into the matrix can move any number of knobs.
+///
+/// CV is read once per block from the first channel, with full scale moving a knob through its
+/// whole travel at a depth of 1. Offsets are sent at control rate, and only when they change.
+pub struct ModMatrix {
+    name: String,
+    open: bool,
+    tx: Sender<(usize, bool)>,
+    rx: Receiver<[Srgb<u8>; NUM_SOURCES]>,
+    input_checks: [bool; NUM_SOURCES],
+    input_colors: [Srgb<u8>; NUM_SOURCES],
+    matrix: Arc<Mutex<Matrix>>,
+    peers: Arc<Mutex<Vec<String>>>,
+}
+
+impl ModMatrix {
+    pub fn init(name: &str) -> Self {
+        let (ui_tx, ui_rx) = channel();
+        let (color_tx, color_rx) = sync_channel(1);
+        let matrix: Arc<Mutex<Matrix>> = Default::default();
+        let peers: Arc<Mutex<Vec<String>>> = Default::default();
+        let thread_matrix = matrix.clone();
+        let thread_peers = peers.clone();
+        let thread_name = name.to_owned();
+        thread::spawn(move || process(ui_rx, color_tx, thread_matrix, thread_peers, &thread_name));
+
+        ModMatrix {
+            name: name.to_owned(),
+            open: true,
+            tx: ui_tx,
+            rx: color_rx,
+            input_checks: [false; NUM_SOURCES],
+            input_colors: [Default::default(); NUM_SOURCES],
+            matrix,
+            peers,
+        }
+    }
+}
+
+fn process(
+    rx: Receiver<(usize, bool)>,
+    tx: SyncSender<[Srgb<u8>; NUM_SOURCES]>,
+    matrix: Arc<Mutex<Matrix>>,
+    peers: Arc<Mutex<Vec<String>>>,
+    name: &str,
+) {
+    let mut module: Module<_, _, NUM_SOURCES, 0, _> = Module::with_clock(
+        SelectedInterface::new().unwrap(),
+        rand::thread_rng(),
+        name.into(),
+        COLOR,
+        StdClock::new(),
+    );
+    let input_handles = [0; NUM_SOURCES].map(|_| module.add_input_jack().unwrap());
+    let mut sent: [Option<Sent>; NUM_DESTINATIONS] = Default::default();
+
+    'outer: loop {
+        while let Some(time) = module.next_tick() {
+            match rx.try_recv() {
+                Ok((id, on)) => {
+                    if let Err(e) = module.set_input_patch_enabled(input_handles[id], on) {
+                        info!("Error {:?}", e);
+                    }
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => break 'outer,
+            }
+            let mut cv = [0.0; NUM_SOURCES];
+            let res = module
+                .poll(time, |block| {
+                    for (c, h) in cv.iter_mut().zip(input_handles) {
+                        *c = block.get_input(h).data[BLOCK_SIZE - 1].data[0] as f32
+                            / i16::MAX as f32;
+                    }
+                })
+                .unwrap();
+            if let Err(TrySendError::Disconnected(_)) =
+                tx.try_send(input_handles.map(|h| res.get_input_color(h)))
+            {
+                break 'outer;
+            }
+            if time.is_multiple_of(PEER_INTERVAL) {
+                *peers.lock().unwrap() = module.peers().map(|uuid| uuid.to_string()).collect();
+            }
+            if time.is_multiple_of(SEND_INTERVAL) {
+                let matrix = matrix.lock().unwrap().clone();
+                for (dest, sent) in matrix.destinations.iter().zip(sent.iter_mut()) {
+                    send_modulation(&mut module, dest, &cv, sent, time);
+                }
+            }
+        }
+        thread::sleep(std::time::Duration::from_millis(0));
+    }
+
+    // Leave the knobs where their owners set them
+    for sent in sent.iter_mut() {
+        release(&mut module, sent);
+    }
+}
+
+fn send_modulation<T: apiary_core::Network<NUM_SOURCES, 0>, R: rand::RngCore, C: Clock>(
+    module: &mut Module<T, R, NUM_SOURCES, 0, C>,
+    dest: &Destination,
+    cv: &[f32; NUM_SOURCES],
+    sent: &mut Option<Sent>,
+    time: MonotonicTime,
+) {
+    let mut uuid = Uuid::new();
+    let target = match uuid.push_str(&dest.module) {
+        Ok(()) if !dest.module.is_empty() => Some(uuid),
+        _ => None,
+    };
+    let moved = sent.as_ref().map_or(false, |s| {
+        Some(&s.uuid) != target.as_ref() || s.param != dest.param
+    });
+    if moved {
+        release(module, sent);
+    }
+    let uuid = match target {
+        Some(uuid) => uuid,
+        None => return,
+    };
+
+    let offset: f32 = cv
+        .iter()
+        .zip(dest.cells.iter())
+        .map(|(cv, cell)| cv * cell.gain())
+        .sum::<f32>()
+        .clamp(-1.0, 1.0);
+    let due = match sent {
+        Some(s) => (offset - s.offset).abs() >= SEND_THRESHOLD || time - s.time >= REFRESH_INTERVAL,
+        None => true,
+    };
+    if !due {
+        return;
+    }
+    if let Err(e) = module.send_set_param(uuid.clone(), dest.param, ParamChange::Modulate(offset)) {
+        info!("Error sending modulation: {:?}", e);
+    }
+    *sent = Some(Sent {
+        uuid,
+        param: dest.param,
+        offset,
+        time,
+    });
+}
+
+fn release<T: apiary_core::Network<NUM_SOURCES, 0>, R: rand::RngCore, C: Clock>(
+    module: &mut Module<T, R, NUM_SOURCES, 0, C>,
+    sent: &mut Option<Sent>,
+) {
+    if let Some(s) = sent.take() {
+        let _ = module.send_set_param(s.uuid, s.param, ParamChange::Modulate(0.0));
+    }
+}
+
+impl DisplayHandler for ModMatrix {
+    fn width(&self) -> f32 {
+        20.0
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn update(&mut self, ui: &mut egui::Ui) {
+        match self.rx.try_recv() {
+            Ok(colors) => self.input_colors = colors,
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.open = false,
+        }
+        ui.heading(self.name.clone());
+        ui.add_space(20.0);
+        for i in 0..NUM_SOURCES {
+            if ui
+                .add(Jack::new(
+                    &mut self.input_checks[i],
+                    format!("CV {}", i + 1),
+                    self.input_colors[i],
+                ))
+                .changed()
+            {
+                self.open &= self.tx.send((i, self.input_checks[i])).is_ok();
+            }
+        }
+        ui.add_space(20.0);
+
+        let peers = self.peers.lock().unwrap().clone();
+        let mut matrix = self.matrix.lock().unwrap();
+        egui::Grid::new(format!("{} matrix", self.name)).show(ui, |ui| {
+            ui.label("Destination");
+            ui.label("Param");
+            for i in 0..NUM_SOURCES {
+                ui.label(format!("CV {}", i + 1));
+            }
+            ui.end_row();
+            for (row, dest) in matrix.destinations.iter_mut().enumerate() {
+                let selected = if dest.module.is_empty() {
+                    "None".to_owned()
+                } else {
+                    dest.module.clone()
+                };
+                egui::ComboBox::from_id_source(format!("{} destination {}", self.name, row))
+                    .selected_text(selected)
+                    .show_ui(ui, |ui| {
+                        ui.selectable_value(&mut dest.module, String::new(), "None");
+                        for peer in &peers {
+                            ui.selectable_value(&mut dest.module, peer.clone(), peer);
+                        }
+                    });
+                ui.add(egui::DragValue::new(&mut dest.param).clamp_range(0..=255));
+                for cell in dest.cells.iter_mut() {
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::DragValue::new(&mut cell.depth)
+                                .speed(0.01)
+                                .clamp_range(0.0..=1.0),
+                        );
+                        let polarity = if cell.inverted { "-" } else { "+" };
+                        if ui.button(polarity).clicked() {
+                            cell.inverted = !cell.inverted;
+                        }
+                    });
+                }
+                ui.end_row();
+            }
+        });
+    }
+
+    fn save_preset(&self) -> ModulePreset {
+        let matrix = self.matrix.lock().unwrap();
+        ModulePreset {
+            state: serde_json::to_value(&*matrix).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    fn load_preset(&mut self, preset: &ModulePreset) {
+        match serde_json::from_value(preset.state.clone()) {
+            Ok(matrix) => *self.matrix.lock().unwrap() = matrix,
+            Err(e) => info!("Error loading mod matrix preset: {}", e),
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use apiary_core::{
+    time::{Duration, StdClock},
+    Module, ParamChange, Uuid, BLOCK_SIZE,
+};
+use eframe::egui;
+use palette::Srgb;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::Path,
+    sync::{
+        mpsc::{channel, sync_channel, Receiver, Sender, SyncSender, TryRecvError, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use crate::{
+    common::{Jack, Knob, SelectedInterface},
+    display_module::DisplayHandler,
+    preset::{ModulePreset, Preset, WindowPreset},
+};
+
+const COLOR: u16 = 30;
+
+/// How often blended knob positions are sent to their modules
+const SEND_INTERVAL: Duration = Duration::from_millis(10);
+/// Smallest change in a knob position worth sending
+const SEND_THRESHOLD: f32 = 1.0 / 512.0;
+/// Where the morph stops using the routing from preset A and starts using the one from B
+const SWITCH_POINT: f32 = 0.5;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Settings {
+    path_a: String,
+    path_b: String,
+    amount: f32,
+    /// Seconds taken to cross the whole way from A to B
+    time: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            path_a: "preset.json".to_owned(),
+            path_b: "preset.json".to_owned(),
+            amount: 0.0,
+            time: 0.0,
+        }
+    }
+}
+
+/// A knob that both presets have a value and metadata for.
+struct Lane {
+    uuid: Uuid,
+    param: usize,
+    from: f32,
+    to: f32,
+}
+
+/// A window whose routing or other state differs between the presets, which can't be blended
+/// and is switched over at `SWITCH_POINT` instead.
+struct Switched {
+    kind: String,
+    name: String,
+    a: serde_json::Value,
+    b: serde_json::Value,
+}
+
+/// Everything that changes between two presets, matched up by window.
+#[derive(Default)]
+struct Morphing {
+    lanes: Vec<Lane>,
+    switched: Vec<Switched>,
+}
+
+impl Morphing {
+    /// Windows only in one of the presets are left alone.
+    fn new(a: &Preset, b: &Preset) -> Self {
+        let mut morphing = Morphing::default();
+        // The morph's own settings stay as they are, rather than switching out from under it
+        for wa in a.windows.iter().filter(|w| w.kind != "Morph") {
+            let wb = match b
+                .windows
+                .iter()
+                .find(|w| w.kind == wa.kind && w.name == wa.name)
+            {
+                Some(wb) => wb,
+                None => continue,
+            };
+            let mut uuid = Uuid::new();
+            if uuid.push_str(&wa.name).is_ok() {
+                for (param, from) in wa.settings.positions() {
+                    if let Some((_, to)) = wb.settings.positions().find(|(id, _)| *id == param) {
+                        morphing.lanes.push(Lane {
+                            uuid: uuid.clone(),
+                            param,
+                            from,
+                            to,
+                        });
+                    }
+                }
+            }
+            if wa.settings.state != wb.settings.state {
+                morphing.switched.push(Switched {
+                    kind: wa.kind.clone(),
+                    name: wa.name.clone(),
+                    a: wa.settings.state.clone(),
+                    b: wb.settings.state.clone(),
+                });
+            }
+        }
+        morphing
+    }
+}
+
+enum Command {
+    Input(bool),
+    Load(Morphing),
+}
+
+/// Crossfades the knobs of every module between two saved presets, driven by a knob plus a CV
+/// input. Knobs are blended along their travel using the parameter metadata in the presets, and
+/// reached through `SetParam` directives so that they can be on any module on the network.
+/// Routing kept in a window's state, such as a mod matrix's, can't be blended and switches over
+/// halfway across.
+///
+/// The time knob limits how fast the morph follows its control, so that a jump in the knob or
+/// CV turns into a sweep.
+pub struct Morph {
+    name: String,
+    open: bool,
+    status: String,
+    tx: Sender<Command>,
+    rx: Receiver<Srgb<u8>>,
+    input_check: bool,
+    input_color: Srgb<u8>,
+    settings: Arc<Mutex<Settings>>,
+    requests: Arc<Mutex<Vec<WindowPreset>>>,
+}
+
+impl Morph {
+    pub fn init(name: &str) -> Self {
+        let (ui_tx, ui_rx) = channel();
+        let (color_tx, color_rx) = sync_channel(1);
+        let settings: Arc<Mutex<Settings>> = Default::default();
+        let requests: Arc<Mutex<Vec<WindowPreset>>> = Default::default();
+        let thread_settings = settings.clone();
+        let thread_requests = requests.clone();
+        let thread_name = name.to_owned();
+        thread::spawn(move || {
+            process(
+                ui_rx,
+                color_tx,
+                thread_settings,
+                thread_requests,
+                &thread_name,
+            )
+        });
+
+        Morph {
+            name: name.to_owned(),
+            open: true,
+            status: "No presets loaded".to_owned(),
+            tx: ui_tx,
+            rx: color_rx,
+            input_check: false,
+            input_color: Default::default(),
+            settings,
+            requests,
+        }
+    }
+
+    fn load(&mut self) {
+        let (path_a, path_b) = {
+            let settings = self.settings.lock().unwrap();
+            (settings.path_a.clone(), settings.path_b.clone())
+        };
+        let presets = Preset::load(Path::new(&path_a))
+            .and_then(|a| Preset::load(Path::new(&path_b)).map(|b| (a, b)));
+        self.status = match presets {
+            Ok((a, b)) => {
+                let morphing = Morphing::new(&a, &b);
+                let status = format!(
+                    "{} knobs, {} switched",
+                    morphing.lanes.len(),
+                    morphing.switched.len()
+                );
+                self.open &= self.tx.send(Command::Load(morphing)).is_ok();
+                status
+            }
+            Err(e) => format!("Error loading presets: {}", e),
+        };
+    }
+}
+
+fn process(
+    rx: Receiver<Command>,
+    tx: SyncSender<Srgb<u8>>,
+    settings: Arc<Mutex<Settings>>,
+    requests: Arc<Mutex<Vec<WindowPreset>>>,
+    name: &str,
+) {
+    let mut module: Module<_, _, 1, 0, _> = Module::with_clock(
+        SelectedInterface::new().unwrap(),
+        rand::thread_rng(),
+        name.into(),
+        COLOR,
+        StdClock::new(),
+    );
+    let input = module.add_input_jack().unwrap();
+    let mut morphing = Morphing::default();
+    let mut position = 0.0;
+    let mut sent: Vec<Option<f32>> = Vec::new();
+    let mut side: Option<bool> = None;
+
+    'outer: loop {
+        while let Some(time) = module.next_tick() {
+            match rx.try_recv() {
+                Ok(Command::Input(on)) => {
+                    if let Err(e) = module.set_input_patch_enabled(input, on) {
+                        info!("Error {:?}", e);
+                    }
+                }
+                Ok(Command::Load(m)) => {
+                    morphing = m;
+                    sent = morphing.lanes.iter().map(|_| None).collect();
+                    side = None;
+                }
+                Err(TryRecvError::Empty) => {}
+                Err(TryRecvError::Disconnected) => break 'outer,
+            }
+            let mut cv = 0.0;
+            let res = module
+                .poll(time, |block| {
+                    cv = block.get_input(input).data[BLOCK_SIZE - 1].data[0] as f32
+                        / i16::MAX as f32;
+                })
+                .unwrap();
+            if let Err(TrySendError::Disconnected(_)) = tx.try_send(res.get_input_color(input)) {
+                break 'outer;
+            }
+
+            let (amount, crossfade) = {
+                let settings = settings.lock().unwrap();
+                (settings.amount, settings.time)
+            };
+            let target = (amount + cv).clamp(0.0, 1.0);
+            let step = if crossfade > 0.0 {
+                0.001 / crossfade
+            } else {
+                1.0
+            };
+            position += (target - position).clamp(-step, step);
+
+            if time.is_multiple_of(SEND_INTERVAL) {
+                for (lane, sent) in morphing.lanes.iter().zip(sent.iter_mut()) {
+                    let next = lane.from + (lane.to - lane.from) * position;
+                    if sent.map_or(false, |s| (next - s).abs() < SEND_THRESHOLD) {
+                        continue;
+                    }
+                    if let Err(e) =
+                        module.send_set_param(lane.uuid.clone(), lane.param, ParamChange::Set(next))
+                    {
+                        info!("Error sending morph: {:?}", e);
+                    }
+                    *sent = Some(next);
+                }
+            }
+
+            let b_side = position >= SWITCH_POINT;
+            if side != Some(b_side) {
+                side = Some(b_side);
+                let mut requests = requests.lock().unwrap();
+                for s in &morphing.switched {
+                    requests.push(WindowPreset {
+                        kind: s.kind.clone(),
+                        name: s.name.clone(),
+                        settings: ModulePreset {
+                            state: if b_side { s.b.clone() } else { s.a.clone() },
+                            ..Default::default()
+                        },
+                        slot_names: vec![],
+                    });
+                }
+            }
+        }
+        thread::sleep(std::time::Duration::from_millis(0));
+    }
+}
+
+impl DisplayHandler for Morph {
+    fn width(&self) -> f32 {
+        10.0
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn is_open(&self) -> bool {
+        self.open
+    }
+
+    fn update(&mut self, ui: &mut egui::Ui) {
+        match self.rx.try_recv() {
+            Ok(color) => self.input_color = color,
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => self.open = false,
+        }
+        ui.heading(self.name.clone());
+        ui.add_space(20.0);
+        if ui
+            .add(Jack::new(
+                &mut self.input_check,
+                "Morph CV".to_owned(),
+                self.input_color,
+            ))
+            .changed()
+        {
+            self.open &= self.tx.send(Command::Input(self.input_check)).is_ok();
+        }
+        ui.add_space(20.0);
+
+        let mut load = false;
+        {
+            let mut settings = self.settings.lock().unwrap();
+            ui.add(Knob::new(
+                &mut settings.amount,
+                "Morph".to_owned(),
+                "".to_owned(),
+                0.0,
+                1.0,
+                false,
+            ));
+            ui.add(Knob::new(
+                &mut settings.time,
+                "Time".to_owned(),
+                "s".to_owned(),
+                0.0,
+                10.0,
+                false,
+            ));
+            ui.add_space(20.0);
+            ui.label("Preset A");
+            ui.text_edit_singleline(&mut settings.path_a);
+            ui.label("Preset B");
+            ui.text_edit_singleline(&mut settings.path_b);
+            if ui.button("Load").clicked() {
+                load = true;
+            }
+        }
+        if load {
+            self.load();
+        }
+        ui.label(&self.status);
+    }
+
+    fn save_preset(&self) -> ModulePreset {
+        let settings = self.settings.lock().unwrap();
+        ModulePreset {
+            state: serde_json::to_value(&*settings).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+
+    fn load_preset(&mut self, preset: &ModulePreset) {
+        match serde_json::from_value(preset.state.clone()) {
+            Ok(settings) => *self.settings.lock().unwrap() = settings,
+            Err(e) => info!("Error loading morph preset: {}", e),
+        }
+    }
+
+    fn preset_requests(&mut self) -> Vec<WindowPreset> {
+        std::mem::take(&mut *self.requests.lock().unwrap())
+    }
+}
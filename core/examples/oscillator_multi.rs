@@ -1,8 +1,9 @@
 use apiary_core::{
-    dsp::oscillators::WtOscillator, voct_to_frequency_table, AudioPacket, BLOCK_SIZE, CHANNELS,
+    dsp::oscillators::WtOscillator, voct_to_frequency_table, AudioPacket, BlockContext, ParamBlock,
+    ProcessBlock, Processor, BLOCK_SIZE, CHANNELS,
 };
 
-use crate::display_module::{DisplayModule, Processor};
+use crate::display_module::DisplayModule;
 
 pub struct Oscillator {
     osc: [WtOscillator; CHANNELS],
@@ -45,10 +46,12 @@ impl Oscillator {
 impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Oscillator {
     fn process(
         &mut self,
-        input: [&AudioPacket; NUM_INPUTS],
-        output: &mut [AudioPacket; NUM_OUTPUTS],
-        params: &[f32; NUM_PARAMS],
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
     ) {
+        let input = block.inputs();
+        let output = block.outputs();
         for i in 0..BLOCK_SIZE {
             self.level += 0.0025 * (params[LEVEL_PARAM] - self.level);
             for j in 0..CHANNELS {
@@ -57,10 +60,16 @@ impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Oscillator {
                 //     input[LEVEL_INPUT].data[i].data[j],
                 //     params[RANGE_PARAM],
                 //     self.level,
+                //     &context.tuning,
+                //     context.note_frequencies.as_ref(),
                 // );
                 let (sin, tri, saw, sqr) = self.osc[j].process_approx_fp(
                     input[LEVEL_INPUT].data[i].data[j],
-                    voct_to_frequency_table(input[IN_INPUT].data[i].data[j]),
+                    voct_to_frequency_table(
+                        input[IN_INPUT].data[i].data[j],
+                        &context.tuning,
+                        context.note_frequencies.as_ref(),
+                    ),
                 );
                 output[SIN_OUTPUT].data[i].data[j] = sin;
                 output[TRI_OUTPUT].data[i].data[j] = tri;
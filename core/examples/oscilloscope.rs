@@ -1,4 +1,7 @@
-use apiary_core::{Module, CHANNELS};
+use apiary_core::{
+    time::{Duration, StdClock},
+    Module, CHANNELS,
+};
 use eframe::egui::{
     self,
     plot::{Line, Plot, Value, Values},
@@ -9,7 +12,6 @@ use std::{
         Arc, Mutex,
     },
     thread,
-    time::{Duration, Instant},
 };
 
 use crate::{
@@ -32,19 +34,17 @@ impl Oscilloscope {
         let thread_data = data.clone();
 
         thread::spawn(move || {
-            let mut module: Module<_, _, 1, 0> = Module::new(
+            let mut module: Module<_, _, 1, 0, _> = Module::with_clock(
                 SelectedInterface::new().unwrap(),
                 rand::thread_rng(),
                 "Oscilloscope".into(),
                 Default::default(),
-                0,
+                StdClock::new(),
             );
             let input_jack = module.add_input_jack().unwrap();
-            let start = Instant::now();
-            let mut time: i64 = 0;
 
             'outer: loop {
-                while time < start.elapsed().as_millis() as i64 {
+                while let Some(time) = module.next_tick() {
                     match ui_rx.try_recv() {
                         Ok(checked) => {
                             if let Err(e) = module.set_input_patch_enabled(input_jack, checked) {
@@ -56,24 +56,29 @@ impl Oscilloscope {
                     }
 
                     let mut pkt = Default::default();
-                    module
+                    let update = module
                         .poll(time, |block| {
                             pkt = *block.get_input(input_jack);
                         })
                         .unwrap();
-                    if time % 10 == 0 {
+                    // Start the trace over rather than mixing in whatever was patched before
+                    if update.jack_events().next().is_some() {
+                        for channel in thread_data.lock().unwrap().iter_mut() {
+                            channel.clear();
+                        }
+                    }
+                    if time.is_multiple_of(Duration::from_millis(10)) {
                         let mut data = thread_data.lock().unwrap();
                         for i in 0..CHANNELS {
-                            data[i]
-                                .push(Value::new(time as f64 / 1000.0, pkt.data[0].data[i] as f64));
+                            let seconds = time.as_millis() as f64 / 1000.0;
+                            data[i].push(Value::new(seconds, pkt.data[0].data[i] as f64));
                             if data[i].len() > 400 {
                                 data[i].remove(0);
                             }
                         }
                     }
-                    time += 1;
                 }
-                thread::sleep(Duration::from_millis(0));
+                thread::sleep(std::time::Duration::from_millis(0));
             }
         });
 
@@ -0,0 +1,249 @@
+use apiary_core::{
+    AudioFrame, AudioPacket, BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE,
+    CHANNELS,
+};
+use eframe::egui::{self, Color32, Rect, Sense, Stroke};
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+use crate::{
+    display_module::{DisplayModule, Renderer},
+    voices::VoiceAllocator,
+};
+
+const OCTAVES: u8 = 2;
+/// MIDI note of C1, which the octave shift counts up from
+const BASE_NOTE: u8 = 24;
+/// Starting at C3
+const DEFAULT_OCTAVE: u8 = 2;
+/// Highest shift that keeps the top key inside MIDI's range
+const MAX_OCTAVE: u8 = 6;
+const WHITE_KEY_WIDTH: f32 = 14.0;
+const WHITE_KEY_HEIGHT: f32 = 70.0;
+/// Black keys, by how far into the octave they start in white keys, and their semitone
+const BLACK_KEYS: [(f32, u8); 5] = [(0.65, 1), (1.65, 3), (3.65, 6), (4.65, 8), (5.65, 10)];
+const WHITE_KEYS: [u8; 7] = [0, 2, 4, 5, 7, 9, 11];
+/// Pitch bend at either end of the wheel, in semitones
+const BEND_RANGE: f32 = 2.0;
+/// Note output per semitone, as in `midi_note_to_voct`
+const VOCT_SEMITONE: f32 = 512.0;
+
+#[derive(Debug)]
+enum PianoEvent {
+    On(u8, u8),
+    Off(u8),
+    /// From -1 to 1
+    Bend(f32),
+    /// From 0 to 1
+    Mod(f32),
+}
+
+/// Draws the keys and wheels, and sends what is played to the processor.
+struct PianoInput {
+    tx: Sender<PianoEvent>,
+    octave: u8,
+    /// Note held down with the pointer, which changes as it slides across the keys
+    held: Option<u8>,
+    bend: f32,
+    /// Bend as last sent, since the wheel can spring back without being dragged
+    bend_sent: f32,
+    modulation: f32,
+}
+
+impl PianoInput {
+    /// The note and velocity under `pos`, with keys played harder towards their front edge.
+    fn key_at(&self, rect: Rect, pos: egui::Pos2) -> Option<(u8, u8)> {
+        if !rect.contains(pos) {
+            return None;
+        }
+        let x = (pos.x - rect.left()) / WHITE_KEY_WIDTH;
+        let depth = (pos.y - rect.top()) / rect.height();
+        let vel = (40.0 + 87.0 * depth) as u8;
+        let base = BASE_NOTE + 12 * self.octave;
+        let octave = (x / 7.0).floor();
+        if depth < 0.6 {
+            let within = x - octave * 7.0;
+            for (start, semitone) in BLACK_KEYS {
+                if within >= start && within < start + 0.7 {
+                    return Some((base + 12 * octave as u8 + semitone, vel));
+                }
+            }
+        }
+        let white = (x.floor() as usize).min(7 * OCTAVES as usize - 1);
+        Some((base + 12 * (white / 7) as u8 + WHITE_KEYS[white % 7], vel))
+    }
+
+    fn keys(&mut self, ui: &mut egui::Ui) {
+        let size = egui::vec2(WHITE_KEY_WIDTH * 7.0 * OCTAVES as f32, WHITE_KEY_HEIGHT);
+        let (rect, response) = ui.allocate_exact_size(size, Sense::click_and_drag());
+        let pressed = match response.interact_pointer_pos() {
+            Some(pos) if response.is_pointer_button_down_on() => self.key_at(rect, pos),
+            _ => None,
+        };
+        if pressed.map(|(note, _)| note) != self.held {
+            if let Some(note) = self.held.take() {
+                let _ = self.tx.send(PianoEvent::Off(note));
+            }
+            if let Some((note, vel)) = pressed {
+                self.held = Some(note);
+                let _ = self.tx.send(PianoEvent::On(note, vel));
+            }
+        }
+
+        let base = BASE_NOTE + 12 * self.octave;
+        let painter = ui.painter();
+        let stroke = Stroke::new(1.0, Color32::BLACK);
+        for white in 0..7 * OCTAVES as usize {
+            let note = base + 12 * (white / 7) as u8 + WHITE_KEYS[white % 7];
+            let left = rect.left() + white as f32 * WHITE_KEY_WIDTH;
+            let key = Rect::from_min_max(
+                egui::pos2(left, rect.top()),
+                egui::pos2(left + WHITE_KEY_WIDTH, rect.bottom()),
+            );
+            let fill = if self.held == Some(note) {
+                Color32::LIGHT_BLUE
+            } else {
+                Color32::WHITE
+            };
+            painter.rect(key, 1.0, fill, stroke);
+        }
+        for octave in 0..OCTAVES {
+            for (start, semitone) in BLACK_KEYS {
+                let note = base + 12 * octave + semitone;
+                let left = rect.left() + (octave as f32 * 7.0 + start) * WHITE_KEY_WIDTH;
+                let key = Rect::from_min_max(
+                    egui::pos2(left, rect.top()),
+                    egui::pos2(
+                        left + 0.7 * WHITE_KEY_WIDTH,
+                        rect.top() + 0.6 * rect.height(),
+                    ),
+                );
+                let fill = if self.held == Some(note) {
+                    Color32::BLUE
+                } else {
+                    Color32::BLACK
+                };
+                painter.rect(key, 1.0, fill, stroke);
+            }
+        }
+    }
+}
+
+impl Renderer<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for PianoInput {
+    fn render(
+        &mut self,
+        _disp: &mut DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS>,
+        ui: &mut egui::Ui,
+    ) {
+        self.keys(ui);
+        ui.horizontal(|ui| {
+            if ui.button("<").clicked() {
+                self.octave = self.octave.saturating_sub(1);
+            }
+            ui.label(format!("C{}", self.octave + 1));
+            if ui.button(">").clicked() {
+                self.octave = (self.octave + 1).min(MAX_OCTAVE);
+            }
+        });
+        // The bend wheel springs back to the middle when let go, and the mod wheel stays put
+        if !ui
+            .add(egui::Slider::new(&mut self.bend, -1.0..=1.0).text("Bend"))
+            .dragged()
+        {
+            self.bend = 0.0;
+        }
+        if self.bend != self.bend_sent {
+            self.bend_sent = self.bend;
+            let _ = self.tx.send(PianoEvent::Bend(self.bend));
+        }
+        if ui
+            .add(egui::Slider::new(&mut self.modulation, 0.0..=1.0).text("Mod"))
+            .changed()
+        {
+            let _ = self.tx.send(PianoEvent::Mod(self.modulation));
+        }
+    }
+}
+
+/// An on-screen keyboard with bend and mod wheels, for playing with the mouse or a touch screen.
+pub struct Piano {
+    voices: VoiceAllocator,
+    rx: Receiver<PianoEvent>,
+    bend: f32,
+    modulation: f32,
+}
+
+const NUM_PARAMS: usize = 0;
+
+const NUM_INPUTS: usize = 0;
+
+const NOTE_OUTPUT: usize = 0;
+const GATE_OUTPUT: usize = 1;
+const VEL_OUTPUT: usize = 2;
+const MOD_OUTPUT: usize = 3;
+const NUM_OUTPUTS: usize = 4;
+
+impl Piano {
+    pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
+        let (tx, rx) = channel();
+        DisplayModule::new()
+            .name(name)
+            .width(14.0)
+            .output(NOTE_OUTPUT, "Note")
+            .output(GATE_OUTPUT, "Gate")
+            .output(VEL_OUTPUT, "Velocity")
+            .output(MOD_OUTPUT, "Mod Wheel")
+            .renderer(PianoInput {
+                tx,
+                octave: DEFAULT_OCTAVE,
+                held: None,
+                bend: 0.0,
+                bend_sent: 0.0,
+                modulation: 0.0,
+            })
+            .start(Piano {
+                voices: Default::default(),
+                rx,
+                bend: 0.0,
+                modulation: 0.0,
+            })
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Piano {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        _params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
+    ) {
+        while let Ok(event) = self.rx.try_recv() {
+            match event {
+                PianoEvent::On(note, vel) => self.voices.note_on(note, vel),
+                PianoEvent::Off(note) => self.voices.note_off(note),
+                PianoEvent::Bend(bend) => self.bend = bend,
+                PianoEvent::Mod(modulation) => self.modulation = modulation,
+            }
+        }
+        let output = block.outputs();
+        let (mut note_frame, gate_frame, vel_frame) = self.voices.next_block();
+        let bend = (self.bend * BEND_RANGE * VOCT_SEMITONE) as i16;
+        for note in note_frame.data.iter_mut() {
+            *note = note.saturating_add(bend);
+        }
+        let mod_frame = AudioFrame {
+            data: [(self.modulation * i16::MAX as f32) as i16; CHANNELS],
+        };
+        *output[NOTE_OUTPUT] = AudioPacket {
+            data: [note_frame; BLOCK_SIZE],
+        };
+        *output[GATE_OUTPUT] = AudioPacket {
+            data: [gate_frame; BLOCK_SIZE],
+        };
+        *output[VEL_OUTPUT] = AudioPacket {
+            data: [vel_frame; BLOCK_SIZE],
+        };
+        *output[MOD_OUTPUT] = AudioPacket {
+            data: [mod_frame; BLOCK_SIZE],
+        };
+    }
+}
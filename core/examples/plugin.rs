@@ -0,0 +1,258 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process, ptr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, Sender, TryRecvError},
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use apiary_core::{
+    plugin::{
+        quality_to_abi, PluginCreateFn, PluginDestroyFn, PluginInfo, PluginInfoFn, PluginProcessFn,
+        PLUGIN_ABI_VERSION, PLUGIN_CREATE_SYMBOL, PLUGIN_DESTROY_SYMBOL, PLUGIN_INFO_SYMBOL,
+        PLUGIN_PROCESS_SYMBOL,
+    },
+    AudioPacket, BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE, CHANNELS,
+};
+use libloading::Library;
+
+use crate::display_module::DisplayModule;
+
+const NUM_INPUTS: usize = 4;
+const NUM_OUTPUTS: usize = 4;
+const NUM_PARAMS: usize = 4;
+
+/// How often the library is checked for a new build
+const RELOAD_INTERVAL: u64 = 500; // ms
+
+static NEXT_COPY: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs a processor from a dynamic library, loading it again whenever the library is rebuilt.
+/// The jacks belong to this module rather than the library, so the patch survives a reload; only
+/// the processor's own state starts over.
+///
+/// Watching, copying and opening the library happen on a thread of their own (see `watch`), so
+/// the processing thread only ever swaps in a library that is ready to run, and hands the one it
+/// replaces back to be closed there.
+pub struct Plugin {
+    path: PathBuf,
+    loaded: Option<Loaded>,
+    /// Each new build, or `None` if it couldn't be loaded
+    updates: Receiver<Option<Loaded>>,
+    retired: Sender<Loaded>,
+}
+
+struct Loaded {
+    info: PluginInfo,
+    state: *mut std::ffi::c_void,
+    process: PluginProcessFn,
+    destroy: PluginDestroyFn,
+    copy: PathBuf,
+    _library: Library,
+}
+
+// The state is only ever touched from the processing thread that owns the plugin
+unsafe impl Send for Loaded {}
+
+impl Drop for Loaded {
+    fn drop(&mut self) {
+        unsafe { (self.destroy)(self.state) };
+        let _ = fs::remove_file(&self.copy);
+    }
+}
+
+impl Loaded {
+    fn open(path: &Path) -> Result<Self, String> {
+        // Load a copy, as the library can't be opened again under a name that's already loaded,
+        // and the linker may write over the original while it's still mapped
+        let copy = env::temp_dir().join(format!(
+            "apiary-plugin-{}-{}-{}",
+            process::id(),
+            NEXT_COPY.fetch_add(1, Ordering::Relaxed),
+            path.file_name().unwrap_or_default().to_string_lossy()
+        ));
+        fs::copy(path, &copy).map_err(|e| e.to_string())?;
+        let res = unsafe { Self::open_copy(&copy) };
+        if res.is_err() {
+            let _ = fs::remove_file(&copy);
+        }
+        res
+    }
+
+    unsafe fn open_copy(copy: &Path) -> Result<Self, String> {
+        let library = Library::new(copy).map_err(|e| e.to_string())?;
+        let info = library
+            .get::<PluginInfoFn>(PLUGIN_INFO_SYMBOL)
+            .map_err(|e| e.to_string())?();
+        if info.abi_version != PLUGIN_ABI_VERSION {
+            return Err(format!(
+                "Plugin ABI version {}, expected {}",
+                info.abi_version, PLUGIN_ABI_VERSION
+            ));
+        }
+        // Packets of another size would be read and written past the end of the host's buffers
+        if info.channels as usize != CHANNELS || info.block_size as usize != BLOCK_SIZE {
+            return Err(format!(
+                "Plugin built for {} channels and blocks of {}, expected {} and {}",
+                info.channels, info.block_size, CHANNELS, BLOCK_SIZE
+            ));
+        }
+        if info.num_inputs as usize > NUM_INPUTS
+            || info.num_outputs as usize > NUM_OUTPUTS
+            || info.num_params as usize > NUM_PARAMS
+        {
+            return Err(format!(
+                "Plugin needs more jacks or knobs than the host has: {:?}",
+                info
+            ));
+        }
+        let create = *library
+            .get::<PluginCreateFn>(PLUGIN_CREATE_SYMBOL)
+            .map_err(|e| e.to_string())?;
+        let process = *library
+            .get::<PluginProcessFn>(PLUGIN_PROCESS_SYMBOL)
+            .map_err(|e| e.to_string())?;
+        let destroy = *library
+            .get::<PluginDestroyFn>(PLUGIN_DESTROY_SYMBOL)
+            .map_err(|e| e.to_string())?;
+        Ok(Loaded {
+            info,
+            state: create(),
+            process,
+            destroy,
+            copy: copy.to_path_buf(),
+            _library: library,
+        })
+    }
+}
+
+impl Plugin {
+    /// The library is taken from `$APIARY_PLUGIN`, or the example plugin built by
+    /// `cargo build --example plugin_gain` if that isn't set.
+    pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
+        let path = match env::var_os("APIARY_PLUGIN") {
+            Some(path) => PathBuf::from(path),
+            None => {
+                Path::new("target/debug/examples").join(libloading::library_filename("plugin_gain"))
+            }
+        };
+        info!("Plugin module loading from {:?}", path);
+
+        let mut module = DisplayModule::new().name(name);
+        for i in 0..NUM_INPUTS {
+            module = module.input(i, &format!("Input {}", i + 1));
+        }
+        for i in 0..NUM_OUTPUTS {
+            module = module.output(i, &format!("Output {}", i + 1));
+        }
+        for i in 0..NUM_PARAMS {
+            module = module.param(i, 0.0, 1.0, 0.5, &format!("Param {}", i + 1), "", false);
+        }
+        let (update_tx, update_rx) = channel();
+        let (retired_tx, retired_rx) = channel();
+        let thread_path = path.clone();
+        thread::spawn(move || watch(&thread_path, update_tx, retired_rx));
+        module.start(Plugin {
+            path,
+            loaded: None,
+            updates: update_rx,
+            retired: retired_tx,
+        })
+    }
+
+    /// Stop running the loaded library, if any, closing it on the watching thread.
+    fn unload(&mut self) {
+        if let Some(loaded) = self.loaded.take() {
+            // Closed here instead if the watching thread is already gone
+            let _ = self.retired.send(loaded);
+        }
+    }
+}
+
+/// Load the library at `path` again every time it changes, sending each build to the processing
+/// thread, and close the builds it sends back. Runs until the plugin module is dropped.
+fn watch(path: &Path, updates: Sender<Option<Loaded>>, retired: Receiver<Loaded>) {
+    let mut modified: Option<SystemTime> = None;
+    loop {
+        loop {
+            match retired.try_recv() {
+                Ok(loaded) => drop(loaded),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => return,
+            }
+        }
+        if let Ok(changed) = fs::metadata(path).and_then(|m| m.modified()) {
+            if modified != Some(changed) {
+                modified = Some(changed);
+                let loaded = match Loaded::open(path) {
+                    Ok(loaded) => {
+                        info!("Loaded plugin {:?}: {:?}", path, loaded.info);
+                        Some(loaded)
+                    }
+                    Err(e) => {
+                        info!("Error loading plugin {:?}: {}", path, e);
+                        None
+                    }
+                };
+                if updates.send(loaded).is_err() {
+                    return;
+                }
+            }
+        }
+        thread::sleep(Duration::from_millis(RELOAD_INTERVAL));
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Plugin {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        // Keep only the newest build, in case more than one turned up since the last block
+        while let Ok(update) = self.updates.try_recv() {
+            self.unload();
+            self.loaded = update;
+        }
+        let loaded = match &self.loaded {
+            Some(loaded) => loaded,
+            None => return,
+        };
+
+        let inputs = block.inputs().map(|p| p as *const AudioPacket);
+        let outputs = block
+            .outputs()
+            .each_mut()
+            .map(|p| &mut **p as *mut AudioPacket);
+        let previous: [f32; NUM_PARAMS] = std::array::from_fn(|i| params.previous(i));
+        let target: [f32; NUM_PARAMS] = std::array::from_fn(|i| params.target(i));
+        let res = unsafe {
+            (loaded.process)(
+                loaded.state,
+                inputs.as_ptr(),
+                outputs.as_ptr(),
+                previous.as_ptr(),
+                target.as_ptr(),
+                context.time,
+                context.sample_rate.0,
+                quality_to_abi(context.quality),
+                context.tuning,
+                context
+                    .note_frequencies
+                    .as_ref()
+                    .map_or(ptr::null(), |freqs| freqs.as_ptr()),
+                context.voices,
+            )
+        };
+        if res != 0 {
+            // Wait for the next build rather than running into the same bug every block
+            info!("Plugin {:?} failed, unloading", self.path);
+            self.unload();
+        }
+    }
+}
@@ -0,0 +1,37 @@
+//! Example plugin for the manager's plugin module: a gain stage with a soft clipper. Rebuild with
+//! `cargo build --example plugin_gain` while the manager is running to hear the change without
+//! losing the patch.
+
+use apiary_core::{
+    dsp::sample::Sample, export_processor, BlockContext, ParamBlock, ProcessBlock, Processor,
+};
+
+const IN_INPUT: usize = 0;
+const NUM_INPUTS: usize = 1;
+
+const OUT_OUTPUT: usize = 0;
+const NUM_OUTPUTS: usize = 1;
+
+const GAIN_PARAM: usize = 0;
+const NUM_PARAMS: usize = 1;
+
+#[derive(Default)]
+pub struct Gain;
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Gain {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
+    ) {
+        for (i, input, output) in block.zip_io(IN_INPUT, OUT_OUTPUT) {
+            let gain = 4.0 * params.at(GAIN_PARAM, i);
+            for (x, y) in input.channels().zip(output.channels_mut()) {
+                *y = i16::from_f32_soft(gain * x.to_f32());
+            }
+        }
+    }
+}
+
+export_processor!(Gain, NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS);
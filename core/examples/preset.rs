@@ -0,0 +1,82 @@
+use std::{fs, io, path::Path};
+
+use apiary_core::knob_position;
+use serde::{Deserialize, Serialize};
+
+/// The windows open in the manager and everything needed to bring them back as they were.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Preset {
+    /// In the order they are arranged in the rack
+    pub windows: Vec<WindowPreset>,
+    /// Whether the manager arranges the windows into rack rows
+    #[serde(default)]
+    pub rack: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WindowPreset {
+    /// Which button in the manager opens the window
+    pub kind: String,
+    /// Name of the module, which other modules may refer to it by
+    pub name: String,
+    pub settings: ModulePreset,
+    /// Names the manager gave the module's own preset slots, which the module keeps the
+    /// settings of
+    #[serde(default)]
+    pub slot_names: Vec<String>,
+}
+
+/// Settings saved by a single window.
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub struct ModulePreset {
+    /// Knob values, by parameter id
+    #[serde(default)]
+    pub params: Vec<f32>,
+    /// What each entry in `params` is, or `None` where the window has no knob with that id.
+    /// Presets saved without it still load, but can't be morphed.
+    #[serde(default)]
+    pub param_meta: Vec<Option<ParamMeta>>,
+    /// Anything else the window keeps, such as a mod matrix's routing
+    #[serde(default)]
+    pub state: serde_json::Value,
+}
+
+/// The range and scale of a knob, so that values from different presets can be blended along
+/// its travel rather than in its units.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct ParamMeta {
+    pub name: String,
+    pub unit: String,
+    pub min: f32,
+    pub max: f32,
+    pub log: bool,
+}
+
+impl ParamMeta {
+    pub fn position(&self, value: f32) -> f32 {
+        knob_position(value, self.min, self.max, self.log)
+    }
+}
+
+impl ModulePreset {
+    /// Knob positions for each parameter that has both a value and metadata.
+    pub fn positions(&self) -> impl Iterator<Item = (usize, f32)> + '_ {
+        self.params
+            .iter()
+            .zip(self.param_meta.iter())
+            .enumerate()
+            .filter_map(|(id, (val, meta))| meta.as_ref().map(|m| (id, m.position(*val))))
+    }
+}
+
+impl Preset {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let json = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&json)?)
+    }
+}
@@ -0,0 +1,43 @@
+use std::sync::Once;
+use thread_priority::{set_current_thread_priority, ThreadPriority};
+
+/// How urgently a module's processing needs to run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+    /// Audio that has to keep up with the 1 ms cadence, such as anything feeding a sound card
+    Realtime,
+    /// Displays and analysis, which can wait behind the audio
+    Normal,
+}
+
+static FALLBACK: Once = Once::new();
+
+/// Ask the OS to run the current thread ahead of everything else, using real-time scheduling
+/// where the host allows it and the highest normal priority where it doesn't. Carries on at the
+/// priority the thread already has if neither is allowed, such as without the rights to change
+/// it, and says so once.
+pub fn promote_current_thread(role: &str) -> bool {
+    #[cfg(target_os = "linux")]
+    {
+        use thread_priority::{
+            set_thread_priority_and_policy, thread_native_id, RealtimeThreadSchedulePolicy,
+            ThreadSchedulePolicy,
+        };
+        let policy = ThreadSchedulePolicy::Realtime(RealtimeThreadSchedulePolicy::Fifo);
+        if set_thread_priority_and_policy(thread_native_id(), ThreadPriority::Max, policy).is_ok() {
+            return true;
+        }
+    }
+    match set_current_thread_priority(ThreadPriority::Max) {
+        Ok(()) => true,
+        Err(e) => {
+            FALLBACK.call_once(|| {
+                info!(
+                    "Couldn't raise the priority of the {} ({:?}), running at normal priority",
+                    role, e
+                )
+            });
+            false
+        }
+    }
+}
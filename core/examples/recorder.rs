@@ -0,0 +1,68 @@
+use apiary_core::{
+    define_module,
+    dsp::recorder::{HeapRecorder, RecorderInputs},
+    BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE, CHANNELS,
+};
+
+use crate::display_module::DisplayModule;
+
+/// Steps in each channel's loop, which is one a millisecond for a minute
+const STEPS: usize = 60_000;
+
+pub struct Recorder {
+    recorders: Vec<HeapRecorder>,
+}
+
+define_module! {
+    name: "recorder",
+    color: 190,
+    inputs: {
+        CV_INPUT: "CV" as Bipolar,
+        CLOCK_INPUT: "Clock" as Gate,
+        RECORD_INPUT: "Record" as Gate,
+        OVERDUB_INPUT: "Overdub" as Gate,
+    },
+    outputs: {
+        CV_OUTPUT: "CV" as Bipolar,
+    },
+    params: {
+        BARS_PARAM: "Bars" { min: 1.0, max: 16.0, default: 4.0, unit: "", log: false },
+        SMOOTHING_PARAM: "Smoothing" { min: 0.0001, max: 0.5, default: 0.005, unit: "s", log: true },
+    },
+}
+
+impl Recorder {
+    pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
+        DisplayModule::new().name(name).spec(&SPEC).start(Recorder {
+            recorders: (0..CHANNELS)
+                .map(|_| HeapRecorder::with_capacity(STEPS))
+                .collect(),
+        })
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Recorder {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            let bars = params.at(BARS_PARAM, i).round() as u32;
+            let smoothing = params.at(SMOOTHING_PARAM, i);
+            for j in 0..CHANNELS {
+                let inputs = RecorderInputs {
+                    cv: input[CV_INPUT].data[i].data[j],
+                    clock: input[CLOCK_INPUT].data[i].data[j],
+                    record: input[RECORD_INPUT].data[i].data[j],
+                    overdub: input[OVERDUB_INPUT].data[i].data[j],
+                };
+                output[CV_OUTPUT].data[i].data[j] =
+                    self.recorders[j].process(inputs, bars, smoothing, context.sample_rate);
+            }
+        }
+    }
+}
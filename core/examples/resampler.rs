@@ -0,0 +1,115 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    mpsc::{sync_channel, Receiver, SyncSender, TryRecvError, TrySendError},
+    Arc,
+};
+
+/// Largest change in playback rate used to keep up with a device's clock.
+const MAX_DRIFT: f32 = 0.005;
+
+/// Sending half of a queue of frames, one sample per channel, counting the frames waiting in it
+/// so the reader can tell how far its clock has drifted from the writer's.
+#[derive(Clone)]
+pub struct FrameQueue<const N: usize> {
+    tx: SyncSender<[f32; N]>,
+    queued: Arc<AtomicUsize>,
+}
+
+impl<const N: usize> FrameQueue<N> {
+    pub fn try_send(&self, frame: [f32; N]) -> Result<(), TrySendError<[f32; N]>> {
+        self.tx.try_send(frame)?;
+        self.queued.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+/// Reads a queue filled at one rate out at another. Neither clock is quite at its nominal rate,
+/// so the reader resamples to whatever rate keeps the queue half full.
+pub struct Resampler<const N: usize> {
+    rx: Receiver<[f32; N]>,
+    queued: Arc<AtomicUsize>,
+    target: f32,
+    nominal_ratio: f32,
+    ratio: f32,
+    fill: f32,
+    phase: f32,
+    previous: [f32; N],
+    next: [f32; N],
+    dropped_frames: usize,
+}
+
+/// A queue holding up to `capacity` frames written at `from_rate` and read at `to_rate`.
+pub fn frame_queue<const N: usize>(
+    capacity: usize,
+    from_rate: f32,
+    to_rate: f32,
+) -> (FrameQueue<N>, Resampler<N>) {
+    let (tx, rx) = sync_channel(capacity);
+    let queued = Arc::new(AtomicUsize::new(0));
+    let nominal_ratio = from_rate / to_rate;
+    (
+        FrameQueue {
+            tx,
+            queued: queued.clone(),
+        },
+        Resampler {
+            rx,
+            queued,
+            target: capacity as f32 / 2.0,
+            nominal_ratio,
+            ratio: nominal_ratio,
+            fill: 0.0,
+            phase: 0.0,
+            previous: [0.0; N],
+            next: [0.0; N],
+            dropped_frames: 0,
+        },
+    )
+}
+
+impl<const N: usize> Resampler<N> {
+    /// Adjusts the rate to how full the queue is, once for every buffer read out of it. The queue
+    /// fills in bursts of a block, so only its average is followed.
+    pub fn update(&mut self) {
+        self.fill += 0.05 * (self.queued.load(Ordering::Relaxed) as f32 - self.fill);
+        self.ratio = self.nominal_ratio
+            * (1.0 + 0.01 * (self.fill - self.target) / self.target)
+                .clamp(1.0 - MAX_DRIFT, 1.0 + MAX_DRIFT);
+    }
+
+    /// The next frame at the reader's rate, interpolated between the two queued frames around it.
+    /// Silence stands in for frames that haven't arrived yet.
+    pub fn next_frame(&mut self) -> [f32; N] {
+        self.phase += self.ratio;
+        while self.phase >= 1.0 {
+            self.phase -= 1.0;
+            self.previous = self.next;
+            self.next = match self.rx.try_recv() {
+                Ok(v) => {
+                    self.queued.fetch_sub(1, Ordering::Relaxed);
+                    v
+                }
+                Err(TryRecvError::Empty) => {
+                    self.dropped_frames += 1;
+                    [0.0; N]
+                }
+                Err(TryRecvError::Disconnected) => panic!("Audio channel disconnected"),
+            };
+        }
+        let mut frame = self.previous;
+        for (x, next) in frame.iter_mut().zip(self.next) {
+            *x += (next - *x) * self.phase;
+        }
+        frame
+    }
+
+    /// How far the reader's clock is from its nominal rate, in parts per million.
+    pub fn drift(&self) -> f32 {
+        (self.ratio / self.nominal_ratio - 1.0) * 1e6
+    }
+
+    /// The number of frames missing from the queue since the last call.
+    pub fn take_dropped_frames(&mut self) -> usize {
+        std::mem::take(&mut self.dropped_frames)
+    }
+}
@@ -1,9 +1,9 @@
 use std::collections::VecDeque;
 
-use apiary_core::AudioPacket;
+use apiary_core::{AudioPacket, BlockContext, ParamBlock, ProcessBlock, Processor};
 use itertools::izip;
 
-use crate::display_module::{DisplayModule, Processor};
+use crate::display_module::DisplayModule;
 
 pub struct Reverb {
     buffer: VecDeque<AudioPacket>,
@@ -39,10 +39,12 @@ impl Reverb {
 impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Reverb {
     fn process(
         &mut self,
-        input: [&AudioPacket; NUM_INPUTS],
-        output: &mut [AudioPacket; NUM_OUTPUTS],
-        params: &[f32; NUM_PARAMS],
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
     ) {
+        let input = block.inputs();
+        let output = block.outputs();
         let mut feedback: AudioPacket = Default::default();
         for (input, output, buffer, fb) in izip!(
             input[IN_INPUT].data,
@@ -0,0 +1,151 @@
+use apiary_core::time::{Clock, Duration, MonotonicTime, StdClock, Ticker};
+use std::{
+    sync::{
+        atomic::{AtomicU32, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Instant,
+};
+
+use crate::realtime::{promote_current_thread, Priority};
+
+/// Real-time worker threads shared by the audio modules in the process
+const WORKERS: usize = 2;
+/// Worker threads for everything else, at normal priority
+const NORMAL_WORKERS: usize = 1;
+/// How often each task's share of its worker is worked out
+const LOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Work done by the scheduler once a millisecond, such as polling a module.
+pub trait Task {
+    /// Do the work for `time`, which is behind `now` while the worker catches up. Returns false
+    /// once the task is finished and can be dropped.
+    fn tick(&mut self, time: MonotonicTime, now: MonotonicTime) -> bool;
+}
+
+/// Makes a task on the worker it runs on, at the time it starts, so that it doesn't need to be
+/// `Send` itself.
+type Spawn = Box<dyn FnOnce(MonotonicTime) -> Box<dyn Task> + Send>;
+
+/// How much of a worker's time a task has been taking, for showing alongside the module.
+#[derive(Default)]
+pub struct TaskLoad {
+    /// Busy time over the last `LOAD_INTERVAL`, in parts per million
+    ppm: AtomicU32,
+}
+
+impl TaskLoad {
+    pub fn percent(&self) -> f32 {
+        self.ppm.load(Ordering::Relaxed) as f32 / 10_000.0
+    }
+}
+
+struct Running {
+    task: Box<dyn Task>,
+    load: Arc<TaskLoad>,
+    busy: std::time::Duration,
+}
+
+struct Worker {
+    priority: Priority,
+    pending: Mutex<Vec<(Spawn, Arc<TaskLoad>)>>,
+    tasks: AtomicUsize,
+}
+
+/// Ticks every task on a few worker threads, in turn each millisecond, rather than each module
+/// keeping its own thread and timer. Tasks go on whichever worker of their priority has the
+/// fewest.
+pub struct Scheduler {
+    workers: Vec<Arc<Worker>>,
+}
+
+lazy_static::lazy_static! {
+    static ref SCHEDULER: Scheduler = Scheduler::new();
+}
+
+/// Run `spawn`'s task on the shared scheduler.
+pub fn spawn<F>(priority: Priority, spawn: F) -> Arc<TaskLoad>
+where
+    F: FnOnce(MonotonicTime) -> Box<dyn Task> + Send + 'static,
+{
+    SCHEDULER.spawn(priority, Box::new(spawn))
+}
+
+impl Scheduler {
+    fn new() -> Self {
+        let realtime = (0..WORKERS).map(|_| Priority::Realtime);
+        let normal = (0..NORMAL_WORKERS).map(|_| Priority::Normal);
+        let workers: Vec<Arc<Worker>> = realtime
+            .chain(normal)
+            .map(|priority| {
+                Arc::new(Worker {
+                    priority,
+                    pending: Default::default(),
+                    tasks: Default::default(),
+                })
+            })
+            .collect();
+        for worker in &workers {
+            let worker = worker.clone();
+            thread::spawn(move || run(&worker));
+        }
+        Scheduler { workers }
+    }
+
+    fn spawn(&self, priority: Priority, spawn: Spawn) -> Arc<TaskLoad> {
+        let load: Arc<TaskLoad> = Default::default();
+        let worker = self
+            .workers
+            .iter()
+            .filter(|w| w.priority == priority)
+            .min_by_key(|w| w.tasks.load(Ordering::Relaxed))
+            .unwrap();
+        worker.tasks.fetch_add(1, Ordering::Relaxed);
+        worker.pending.lock().unwrap().push((spawn, load.clone()));
+        load
+    }
+}
+
+fn run(worker: &Worker) {
+    if worker.priority == Priority::Realtime {
+        promote_current_thread("module processing threads");
+    }
+    let mut ticker = Ticker::new(StdClock::new());
+    let mut running: Vec<Running> = Vec::new();
+    loop {
+        let time = match ticker.next_tick() {
+            Some(time) => time,
+            None => {
+                let wait = ticker.until_next().as_millis() as u64;
+                thread::sleep(std::time::Duration::from_millis(wait));
+                continue;
+            }
+        };
+        for (spawn, load) in worker.pending.lock().unwrap().drain(..) {
+            running.push(Running {
+                task: spawn(time),
+                load,
+                busy: Default::default(),
+            });
+        }
+        let now = ticker.clock_mut().now();
+        running.retain_mut(|r| {
+            let start = Instant::now();
+            let alive = r.task.tick(time, now);
+            r.busy += start.elapsed();
+            if !alive {
+                worker.tasks.fetch_sub(1, Ordering::Relaxed);
+            }
+            alive
+        });
+        if time.is_multiple_of(LOAD_INTERVAL) {
+            let interval = LOAD_INTERVAL.as_millis() as f64 / 1000.0;
+            for r in &mut running {
+                let ppm = r.busy.as_secs_f64() / interval * 1_000_000.0;
+                r.load.ppm.store(ppm as u32, Ordering::Relaxed);
+                r.busy = Default::default();
+            }
+        }
+    }
+}
@@ -0,0 +1,155 @@
+use apiary_core::{
+    dsp::{logic::GateDetector, mix::Crossfader},
+    BlockContext, ParamBlock, ProcessBlock, Processor, SampleRate, BLOCK_SIZE, CHANNELS,
+    SAMPLE_RATE,
+};
+
+use crate::display_module::DisplayModule;
+
+const NUM_SLOTS: usize = 4;
+
+/// Picks a slot for each channel. Rising edges on the step gate advance through the slots in
+/// turn, and the address CV offsets from there, with full scale spanning all of the slots.
+#[derive(Default)]
+struct Selector {
+    step: [usize; CHANNELS],
+    gates: [GateDetector; CHANNELS],
+}
+
+impl Selector {
+    fn select(&mut self, channel: usize, step: i16, address: i16, rate: SampleRate) -> usize {
+        if self.gates[channel].process(step, rate).rising {
+            self.step[channel] = (self.step[channel] + 1) % NUM_SLOTS;
+        }
+        let offset = (address.max(0) as f32 / i16::MAX as f32 * NUM_SLOTS as f32) as usize;
+        (self.step[channel] + offset) % NUM_SLOTS
+    }
+}
+
+fn fade_samples(ms: f32) -> usize {
+    (ms / 1000.0 * SAMPLE_RATE) as usize
+}
+
+const FADE_PARAM: usize = 0;
+const NUM_PARAMS: usize = 1;
+
+/// Selects one of several inputs to send to a single output.
+pub struct Switch {
+    selector: Selector,
+    faders: [Crossfader<NUM_SLOTS>; CHANNELS],
+}
+
+const SWITCH_IN_INPUT: usize = 0;
+const SWITCH_STEP_INPUT: usize = NUM_SLOTS;
+const SWITCH_ADDRESS_INPUT: usize = NUM_SLOTS + 1;
+const SWITCH_NUM_INPUTS: usize = NUM_SLOTS + 2;
+
+const SWITCH_OUT_OUTPUT: usize = 0;
+const SWITCH_NUM_OUTPUTS: usize = 1;
+
+impl Switch {
+    pub fn init(name: &str) -> DisplayModule<SWITCH_NUM_INPUTS, SWITCH_NUM_OUTPUTS, NUM_PARAMS> {
+        let mut module = DisplayModule::new().name(name);
+        for slot in 0..NUM_SLOTS {
+            module = module.input(SWITCH_IN_INPUT + slot, &format!("Input {}", slot + 1));
+        }
+        module
+            .input(SWITCH_STEP_INPUT, "Step")
+            .input(SWITCH_ADDRESS_INPUT, "Address")
+            .param(FADE_PARAM, 0.1, 100.0, 2.0, "Fade", " ms", true)
+            .output(SWITCH_OUT_OUTPUT, "Output")
+            .start(Switch {
+                selector: Default::default(),
+                faders: [(); CHANNELS].map(|_| Crossfader::new(fade_samples(2.0))),
+            })
+    }
+}
+
+impl Processor<SWITCH_NUM_INPUTS, SWITCH_NUM_OUTPUTS, NUM_PARAMS> for Switch {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<SWITCH_NUM_INPUTS, SWITCH_NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            for j in 0..CHANNELS {
+                let fader = &mut self.faders[j];
+                fader.set_fade(fade_samples(params.at(FADE_PARAM, i)));
+                fader.select(self.selector.select(
+                    j,
+                    input[SWITCH_STEP_INPUT].data[i].data[j],
+                    input[SWITCH_ADDRESS_INPUT].data[i].data[j],
+                    context.sample_rate,
+                ));
+                let mut sources = [0.0; NUM_SLOTS];
+                for (slot, x) in sources.iter_mut().enumerate() {
+                    *x = input[SWITCH_IN_INPUT + slot].data[i].data[j] as f32;
+                }
+                output[SWITCH_OUT_OUTPUT].data[i].data[j] = fader.process(sources) as i16;
+            }
+        }
+    }
+}
+
+/// Sends a single input to one of several outputs.
+pub struct Router {
+    selector: Selector,
+    faders: [Crossfader<NUM_SLOTS>; CHANNELS],
+}
+
+const ROUTER_IN_INPUT: usize = 0;
+const ROUTER_STEP_INPUT: usize = 1;
+const ROUTER_ADDRESS_INPUT: usize = 2;
+const ROUTER_NUM_INPUTS: usize = 3;
+
+const ROUTER_OUT_OUTPUT: usize = 0;
+const ROUTER_NUM_OUTPUTS: usize = NUM_SLOTS;
+
+impl Router {
+    pub fn init(name: &str) -> DisplayModule<ROUTER_NUM_INPUTS, ROUTER_NUM_OUTPUTS, NUM_PARAMS> {
+        let mut module = DisplayModule::new()
+            .name(name)
+            .input(ROUTER_IN_INPUT, "Input")
+            .input(ROUTER_STEP_INPUT, "Step")
+            .input(ROUTER_ADDRESS_INPUT, "Address")
+            .param(FADE_PARAM, 0.1, 100.0, 2.0, "Fade", " ms", true);
+        for slot in 0..NUM_SLOTS {
+            module = module.output(ROUTER_OUT_OUTPUT + slot, &format!("Output {}", slot + 1));
+        }
+        module.start(Router {
+            selector: Default::default(),
+            faders: [(); CHANNELS].map(|_| Crossfader::new(fade_samples(2.0))),
+        })
+    }
+}
+
+impl Processor<ROUTER_NUM_INPUTS, ROUTER_NUM_OUTPUTS, NUM_PARAMS> for Router {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<ROUTER_NUM_INPUTS, ROUTER_NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            for j in 0..CHANNELS {
+                let fader = &mut self.faders[j];
+                fader.set_fade(fade_samples(params.at(FADE_PARAM, i)));
+                fader.select(self.selector.select(
+                    j,
+                    input[ROUTER_STEP_INPUT].data[i].data[j],
+                    input[ROUTER_ADDRESS_INPUT].data[i].data[j],
+                    context.sample_rate,
+                ));
+                let x = input[ROUTER_IN_INPUT].data[i].data[j] as f32;
+                for (slot, gain) in fader.tick().into_iter().enumerate() {
+                    output[ROUTER_OUT_OUTPUT + slot].data[i].data[j] = (x * gain) as i16;
+                }
+            }
+        }
+    }
+}
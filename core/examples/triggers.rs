@@ -0,0 +1,69 @@
+use apiary_core::{
+    define_module,
+    dsp::triggers::{Burst, Chance, TriggerDelay},
+    BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE, CHANNELS,
+};
+
+use crate::display_module::DisplayModule;
+
+pub struct Triggers {
+    bursts: [Burst; CHANNELS],
+    delays: [TriggerDelay; CHANNELS],
+    chances: [Chance; CHANNELS],
+}
+
+define_module! {
+    name: "triggers",
+    color: 30,
+    inputs: {
+        TRIGGER_INPUT: "Trigger" as Gate,
+    },
+    outputs: {
+        BURST_OUTPUT: "Burst" as Gate,
+        DELAY_OUTPUT: "Delayed" as Gate,
+        CHANCE_OUTPUT: "Chance" as Gate,
+    },
+    params: {
+        COUNT_PARAM: "Count" { min: 1.0, max: 16.0, default: 4.0, unit: "", log: false },
+        RATE_PARAM: "Rate" { min: 1.0, max: 50.0, default: 12.0, unit: "Hz", log: true },
+        DELAY_PARAM: "Delay" { min: 0.001, max: 2.0, default: 0.25, unit: "s", log: true },
+        CHANCE_PARAM: "Chance" { min: 0.0, max: 1.0, default: 0.5, unit: "", log: false },
+    },
+}
+
+impl Triggers {
+    pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
+        DisplayModule::new().name(name).spec(&SPEC).start(Triggers {
+            bursts: Default::default(),
+            delays: Default::default(),
+            // Each channel rolls its own dice
+            chances: core::array::from_fn(|j| Chance::new(j as u32 + 1)),
+        })
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Triggers {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            let count = params.at(COUNT_PARAM, i).round() as u32;
+            let rate = params.at(RATE_PARAM, i);
+            let delay = params.at(DELAY_PARAM, i);
+            let chance = params.at(CHANCE_PARAM, i);
+            for j in 0..CHANNELS {
+                let x = input[TRIGGER_INPUT].data[i].data[j];
+                output[BURST_OUTPUT].data[i].data[j] =
+                    self.bursts[j].process(x, count, rate, context.sample_rate);
+                output[DELAY_OUTPUT].data[i].data[j] =
+                    self.delays[j].process(x, delay, context.sample_rate);
+                output[CHANCE_OUTPUT].data[i].data[j] = self.chances[j].process(x, chance);
+            }
+        }
+    }
+}
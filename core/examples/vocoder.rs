@@ -0,0 +1,89 @@
+use apiary_core::{
+    dsp::filters::FilterBank, softclip, BlockContext, ParamBlock, ProcessBlock, Processor,
+    BLOCK_SIZE, CHANNELS, SAMPLE_RATE,
+};
+
+use crate::display_module::DisplayModule;
+
+const NUM_BANDS: usize = 16;
+const LOW_BAND: f32 = 100.0;
+const HIGH_BAND: f32 = 8000.0;
+
+pub struct Vocoder {
+    modulator: [FilterBank<NUM_BANDS>; CHANNELS],
+    carrier: [FilterBank<NUM_BANDS>; CHANNELS],
+    envelope: [[f32; NUM_BANDS]; CHANNELS],
+    resonance: f32,
+}
+
+const RES_PARAM: usize = 0;
+const ATTACK_PARAM: usize = 1;
+const RELEASE_PARAM: usize = 2;
+const LEVEL_PARAM: usize = 3;
+const NUM_PARAMS: usize = 4;
+
+const MOD_INPUT: usize = 0;
+const CAR_INPUT: usize = 1;
+const NUM_INPUTS: usize = 2;
+
+const OUT_OUTPUT: usize = 0;
+const NUM_OUTPUTS: usize = 1;
+
+impl Vocoder {
+    pub fn init(name: &str) -> DisplayModule<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> {
+        DisplayModule::new()
+            .name(name)
+            .input(MOD_INPUT, "Modulator")
+            .input(CAR_INPUT, "Carrier")
+            .param(RES_PARAM, 0.0, 9.5, 8.0, "Resonance", "", false)
+            .param(ATTACK_PARAM, 0.001, 0.1, 0.005, "Attack", " s", true)
+            .param(RELEASE_PARAM, 0.001, 1.0, 0.05, "Release", " s", true)
+            .param(LEVEL_PARAM, 0.0, 4.0, 1.0, "Level", "", false)
+            .output(OUT_OUTPUT, "Output")
+            .start(Vocoder {
+                modulator: [(); CHANNELS].map(|_| FilterBank::new(LOW_BAND, HIGH_BAND, 8.0)),
+                carrier: [(); CHANNELS].map(|_| FilterBank::new(LOW_BAND, HIGH_BAND, 8.0)),
+                envelope: [[0.0; NUM_BANDS]; CHANNELS],
+                resonance: 8.0,
+            })
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Vocoder {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
+    ) {
+        let input = block.inputs();
+        let output = block.outputs();
+        if params[RES_PARAM] != self.resonance {
+            self.resonance = params[RES_PARAM];
+            for bank in self.modulator.iter_mut().chain(self.carrier.iter_mut()) {
+                bank.set_params(LOW_BAND, HIGH_BAND, self.resonance);
+            }
+        }
+        let attack = 1.0 - (-1.0 / (params[ATTACK_PARAM] * SAMPLE_RATE)).exp();
+        let release = 1.0 - (-1.0 / (params[RELEASE_PARAM] * SAMPLE_RATE)).exp();
+
+        for i in 0..BLOCK_SIZE {
+            for j in 0..CHANNELS {
+                let m = self.modulator[j]
+                    .process(input[MOD_INPUT].data[i].data[j] as f32 / i16::MAX as f32);
+                let c = self.carrier[j]
+                    .process(input[CAR_INPUT].data[i].data[j] as f32 / i16::MAX as f32);
+                let mut out = 0.0;
+                for (band, env) in self.envelope[j].iter_mut().enumerate() {
+                    // Envelope follower on the modulator band drives the carrier band's VCA
+                    let level = m[band].abs();
+                    let coeff = if level > *env { attack } else { release };
+                    *env += coeff * (level - *env);
+                    out += c[band] * *env;
+                }
+                output[OUT_OUTPUT].data[i].data[j] =
+                    (softclip(out * params.at(LEVEL_PARAM, i)) * i16::MAX as f32) as i16;
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+use apiary_core::{midi_note_to_voct, AudioFrame, CHANNELS};
+
+#[derive(Copy, Clone, Default, Debug)]
+struct Voice {
+    note: u8,
+    on: bool,
+    just_on: bool,
+    vel: u8,
+    timestamp: i64,
+}
+
+/// Hands out the polyphonic channels to notes as they are played, for the modules that turn
+/// notes from somewhere into note, gate and velocity jacks.
+#[derive(Default)]
+pub struct VoiceAllocator {
+    voices: [Voice; CHANNELS],
+    time: i64,
+}
+
+impl VoiceAllocator {
+    pub fn note_on(&mut self, note: u8, vel: u8) {
+        // First, see if we can take the oldest voice that has been released. Otherwise, steal a
+        // voice. In this case, take the oldest note played. We also have a choice of whether to
+        // just change the pitch (done here), or to shut the note off and retrigger.
+        if let Some(v) = self.voices.iter_mut().min_by_key(|v| (v.on, v.timestamp)) {
+            v.note = note;
+            v.on = true;
+            v.just_on = true;
+            v.vel = vel;
+            v.timestamp = self.time;
+        }
+        for v in self.voices {
+            trace!("{:?}", v);
+        }
+    }
+
+    pub fn note_off(&mut self, note: u8) {
+        for v in self.voices.iter_mut().filter(|v| v.note == note && v.on) {
+            v.on = false;
+            v.timestamp = self.time;
+        }
+    }
+
+    /// The note, gate and velocity of every voice for this block. Gates stay low for the first
+    /// block of a note, so that a stolen voice still retriggers.
+    pub fn next_block(&mut self) -> (AudioFrame, AudioFrame, AudioFrame) {
+        let mut note_frame: AudioFrame = Default::default();
+        let mut gate_frame: AudioFrame = Default::default();
+        let mut vel_frame: AudioFrame = Default::default();
+        for (i, v) in self.voices.iter_mut().enumerate() {
+            note_frame.data[i] = midi_note_to_voct(v.note);
+            if v.on {
+                if v.just_on {
+                    v.just_on = false;
+                } else {
+                    gate_frame.data[i] = 16000;
+                }
+            }
+            vel_frame.data[i] = (v.vel as i16) << 7;
+        }
+        self.time += 1;
+        (note_frame, gate_frame, vel_frame)
+    }
+}
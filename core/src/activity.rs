@@ -0,0 +1,50 @@
+use crate::{AudioPacket, CHANNELS};
+
+/// Samples no further from zero than this count as silence when looking for idle channels
+pub const IDLE_LEVEL: i16 = 16;
+/// Blocks in a row that a channel has to stay silent for before it counts as idle
+pub const IDLE_BLOCKS: u8 = 8;
+
+/// Which channels of each input jack have carried anything lately.
+///
+/// Every input is checked once per block as it arrives, which costs little next to processing
+/// it, and processors can then skip the channels that have been idle for a while (see
+/// `ProcessBlock::is_channel_active`). The hold over `IDLE_BLOCKS` keeps channels that are only
+/// quiet for a moment, like a slow LFO passing through zero, from switching on and off.
+pub(crate) struct Activity<const I: usize> {
+    quiet_blocks: [[u8; CHANNELS]; I],
+}
+
+impl<const I: usize> Activity<I> {
+    pub(crate) fn new() -> Self {
+        Activity {
+            // Nothing has been heard yet
+            quiet_blocks: [[IDLE_BLOCKS; CHANNELS]; I],
+        }
+    }
+
+    /// Count another block of every input.
+    pub(crate) fn update(&mut self, inputs: &[&AudioPacket; I]) {
+        for (quiet_blocks, packet) in self.quiet_blocks.iter_mut().zip(inputs) {
+            let loud = packet.loud_channels();
+            for (c, quiet) in quiet_blocks.iter_mut().enumerate() {
+                *quiet = if loud & (1 << c) != 0 {
+                    0
+                } else {
+                    quiet.saturating_add(1)
+                };
+            }
+        }
+    }
+
+    /// The channels of each input that aren't idle, as a bit per channel.
+    pub(crate) fn active(&self) -> [u16; I] {
+        self.quiet_blocks.map(|quiet_blocks| {
+            quiet_blocks
+                .iter()
+                .enumerate()
+                .filter(|(_, quiet)| **quiet < IDLE_BLOCKS)
+                .fold(0, |mask, (c, _)| mask | (1 << c))
+        })
+    }
+}
@@ -0,0 +1,88 @@
+//! Running modules as futures on a tokio runtime, rather than spinning a thread for each one.
+//!
+//! Every backend is non-blocking, so waiting on the network here means sleeping on the runtime's
+//! timer until the backend has something, one millisecond at a time. That is the same rate the
+//! modules are polled at, so dozens of them can share a single runtime thread:
+//!
+//! ```ignore
+//! let local = tokio::task::LocalSet::new();
+//! for mut module in modules {
+//!     local.spawn_local(async move {
+//!         module
+//!             .run(|block| { ... }, |_, _| ControlFlow::Continue(()))
+//!             .await
+//!     });
+//! }
+//! local.await;
+//! ```
+
+use core::ops::ControlFlow;
+
+use rand_core::RngCore;
+
+use crate::{
+    time::{Clock, Duration, MonotonicTime, Ticker},
+    Error, Module, Network, PollUpdate, ProcessBlock,
+};
+
+/// How long to wait before asking a backend again when it has nothing
+const RETRY_INTERVAL: Duration = Duration::from_millis(1);
+
+async fn sleep(duration: Duration) {
+    tokio::time::sleep(std::time::Duration::from_millis(duration.as_millis() as u64)).await;
+}
+
+impl<C: Clock> Ticker<C> {
+    /// Wait for the next millisecond to come due.
+    pub async fn tick(&mut self) -> MonotonicTime {
+        loop {
+            if let Some(time) = self.next_tick() {
+                return time;
+            }
+            sleep(self.until_next()).await;
+        }
+    }
+}
+
+/// Wait for a directive to arrive, and get its bytes.
+pub async fn recv_directive<T: Network<I, O>, const I: usize, const O: usize>(
+    interface: &mut T,
+    buf: &mut [u8],
+) -> Result<usize, Error> {
+    loop {
+        match interface.recv_directive(buf) {
+            Err(Error::NoData) => sleep(RETRY_INTERVAL).await,
+            res => return res,
+        }
+    }
+}
+
+/// Wait for the directive socket to be ready, and send `buf` on it.
+pub async fn send_directive<T: Network<I, O>, const I: usize, const O: usize>(
+    interface: &mut T,
+    buf: &[u8],
+) -> Result<(), Error> {
+    while !interface.can_send() {
+        sleep(RETRY_INTERVAL).await;
+    }
+    interface.send_directive(buf)
+}
+
+impl<T: Network<I, O>, R: RngCore, const I: usize, const O: usize, C: Clock> Module<T, R, I, O, C> {
+    /// Poll the module once a millisecond by its clock, catching up after falling behind, until
+    /// `update` breaks out. `process` fills in each block as in `poll`, and `update` gets the
+    /// result along with the module to patch jacks or change settings from.
+    pub async fn run<F, U>(&mut self, mut process: F, mut update: U) -> Result<(), Error>
+    where
+        F: FnMut(&mut ProcessBlock<I, O>),
+        U: FnMut(&mut Self, PollUpdate<I, O>) -> ControlFlow<()>,
+    {
+        loop {
+            let time = self.ticker.tick().await;
+            let res = self.poll(time, &mut process)?;
+            if update(self, res).is_break() {
+                return Ok(());
+            }
+        }
+    }
+}
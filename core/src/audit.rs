@@ -0,0 +1,169 @@
+use crate::{Directive, DirectiveAuditRequest, DirectiveAuditResponse, PatchConnection, Uuid};
+use heapless::FnvIndexMap;
+
+/// How long to wait for every module to answer an audit
+const AUDIT_TIMEOUT: i64 = 500; // ms
+const MAX_MODULES: usize = 16;
+/// Most modules a single module can report connections to or from
+const MAX_AUDIT_PEERS: usize = 8;
+const MAX_MISMATCHES: usize = 8;
+
+/// Digests of the connections between a module and each of its peers, keyed by a hash of the
+/// peer's uuid to keep the responses small.
+pub(crate) type Digests = heapless::Vec<(u32, u32), MAX_AUDIT_PEERS>;
+
+fn fnv1a(bytes: &[u8], hash: u32) -> u32 {
+    bytes
+        .iter()
+        .fold(hash, |hash, b| (hash ^ *b as u32).wrapping_mul(0x0100_0193))
+}
+
+fn uuid_hash(uuid: &Uuid) -> u32 {
+    fnv1a(uuid.as_bytes(), 0x811c_9dc5)
+}
+
+/// Add a connection, along with the multicast address its audio travels on, into the digest for
+/// `peer`. Digests are summed so that the order connections are added in doesn't matter.
+pub(crate) fn add_connection(
+    digests: &mut Digests,
+    peer: &Uuid,
+    connection: &PatchConnection,
+    addr: [u8; 4],
+) {
+    let mut buf = [0; 128];
+    let hash = match postcard::to_slice(connection, &mut buf) {
+        Ok(bytes) => fnv1a(&addr, fnv1a(bytes, 0x811c_9dc5)),
+        Err(_) => 0,
+    };
+    let peer = uuid_hash(peer);
+    match digests.iter_mut().find(|(p, _)| *p == peer) {
+        Some((_, digest)) => *digest = digest.wrapping_add(hash),
+        None => {
+            if digests.push((peer, hash)).is_err() {
+                info!("Audit digest table full");
+            }
+        }
+    }
+}
+
+/// Both ends of a connection that don't agree on it, or on the address it uses.
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuditMismatch {
+    pub output: Uuid,
+    pub input: Uuid,
+}
+
+/// Result of `Module::audit`.
+#[derive(Clone, Debug, Default)]
+pub struct AuditReport {
+    /// Modules that answered, this one included
+    pub modules: usize,
+    /// Pairs of modules whose views of the connections between them differ. Connections to
+    /// modules that didn't answer can't be checked and aren't listed.
+    pub mismatches: heapless::Vec<AuditMismatch, MAX_MISMATCHES>,
+}
+
+struct View {
+    uuid: Uuid,
+    inputs: Digests,
+    outputs: Digests,
+}
+
+/// Collects every module's view of the patch and compares the two ends of each connection.
+///
+/// There is no leader holding the true state of the patch, so instead each module reports a
+/// digest of the connections it has with each of its peers, once from the input side and once
+/// from the output side. Every digest an output module reports for an input module should match
+/// the one the input module reports for it.
+pub(crate) struct Audit {
+    id: Uuid,
+    audit: u32,
+    deadline: Option<i64>,
+    views: FnvIndexMap<u32, View, MAX_MODULES>,
+    report: Option<AuditReport>,
+}
+
+impl Audit {
+    pub(crate) fn new(id: Uuid) -> Self {
+        Audit {
+            id,
+            audit: 0,
+            deadline: None,
+            views: FnvIndexMap::new(),
+            report: None,
+        }
+    }
+
+    /// Start a new audit with this module's own view, returning the request to send out.
+    pub(crate) fn start(&mut self, inputs: Digests, outputs: Digests, time: i64) -> Directive {
+        self.audit = self.audit.wrapping_add(1);
+        self.deadline = Some(time + AUDIT_TIMEOUT);
+        self.views.clear();
+        self.report = None;
+        self.record(self.id.clone(), inputs, outputs);
+        Directive::AuditRequest(DirectiveAuditRequest {
+            uuid: self.id.clone(),
+            audit: self.audit,
+        })
+    }
+
+    pub(crate) fn process_response(&mut self, resp: DirectiveAuditResponse) {
+        if resp.requester == self.id && resp.audit == self.audit && self.deadline.is_some() {
+            self.record(resp.uuid, resp.inputs, resp.outputs);
+        }
+    }
+
+    fn record(&mut self, uuid: Uuid, inputs: Digests, outputs: Digests) {
+        let view = View {
+            uuid: uuid.clone(),
+            inputs,
+            outputs,
+        };
+        if self.views.insert(uuid_hash(&uuid), view).is_err() {
+            info!("Audit module table full");
+        }
+    }
+
+    /// Finish the audit once everyone has had a chance to answer.
+    pub(crate) fn poll(&mut self, time: i64) {
+        match self.deadline {
+            Some(deadline) if time >= deadline => self.deadline = None,
+            _ => return,
+        }
+        let mut report = AuditReport {
+            modules: self.views.len(),
+            mismatches: heapless::Vec::new(),
+        };
+        for (output_hash, output) in self.views.iter() {
+            for (input_hash, input) in self.views.iter() {
+                let claimed = |digests: &Digests, peer| {
+                    digests.iter().find(|(p, _)| *p == peer).map(|(_, d)| *d)
+                };
+                if claimed(&output.outputs, *input_hash) == claimed(&input.inputs, *output_hash) {
+                    continue;
+                }
+                warn!(
+                    "Audit mismatch between output module {} and input module {}",
+                    output.uuid, input.uuid
+                );
+                let mismatch = AuditMismatch {
+                    output: output.uuid.clone(),
+                    input: input.uuid.clone(),
+                };
+                if report.mismatches.push(mismatch).is_err() {
+                    break;
+                }
+            }
+        }
+        info!(
+            "Audit finished: {} modules, {} mismatches",
+            report.modules,
+            report.mismatches.len()
+        );
+        self.report = Some(report);
+    }
+
+    pub(crate) fn report(&self) -> Option<&AuditReport> {
+        self.report.as_ref()
+    }
+}
@@ -0,0 +1,245 @@
+use crate::{
+    AudioPacket, BandwidthStats, Directive, Directive::Stats, DirectiveStats, SampleRate, Uuid,
+    BLOCK_SIZE, CHANNELS,
+};
+use core::mem;
+use heapless::FnvIndexMap;
+
+const STATS_INTERVAL: i64 = 1000; // ms
+const HOST_TIMEOUT: i64 = 3 * STATS_INTERVAL;
+/// Stats are sent less often in standby, though still often enough that no one times us out
+const STANDBY_STATS_INTERVAL: i64 = 2500; // ms
+const MAX_HOSTS: usize = 16;
+
+pub(crate) const DEFAULT_BUDGET: u32 = 100_000_000; // bits/s
+
+// Ethernet framing (including preamble and interframe gap), IPv4 header, and UDP header
+const PACKET_OVERHEAD: usize = 38 + 20 + 8;
+
+/// Bits per second used on the wire by a single audio jack stream.
+fn stream_bandwidth(sample_rate: SampleRate) -> u32 {
+    let packets_per_second = sample_rate.0 / BLOCK_SIZE as u32;
+    ((mem::size_of::<AudioPacket>() + PACKET_OVERHEAD) * 8) as u32 * packets_per_second
+}
+
+/// How a module came back after not being heard from.
+pub(crate) enum Rejoin {
+    /// The module was restarted and lost its state
+    Restarted,
+    /// The module was unreachable for a while, like on the other side of a network partition
+    Reconnected,
+}
+
+struct HostStreams {
+    output_streams: u8,
+    input_streams: u8,
+    latency: u8,
+    voices: u8,
+    iteration: u32,
+    last_seen: i64,
+}
+
+/// Accounting of the multicast audio streams advertised by every module on the network.
+///
+/// Each module periodically broadcasts the number of streams it sends and receives. Since every
+/// multicast stream is potentially forwarded to every link, the sum of the advertised output
+/// streams is compared against the budget of the slowest link in the system when the patch
+/// coordinator answers an attempt at a new connection (see `PingPatch`).
+///
+/// The stats also carry the latency of each module's outputs, for lining up inputs that took
+/// different paths through the patch, and the number of voices they carry, for skipping unused
+/// channels.
+pub(crate) struct Bandwidth {
+    id: Uuid,
+    hosts: FnvIndexMap<Uuid, HostStreams, MAX_HOSTS>,
+    /// Modules that went quiet long enough to be dropped, oldest first, which are reconnecting
+    /// rather than joining for the first time if they are heard from again
+    expired: heapless::Vec<Uuid, MAX_HOSTS>,
+    budget: u32,
+    stats_timeout: i64,
+    iteration: u32,
+    over_budget: bool,
+    standby: bool,
+}
+
+impl Bandwidth {
+    pub(crate) fn new(id: Uuid, time: i64) -> Self {
+        Bandwidth {
+            id,
+            hosts: FnvIndexMap::new(),
+            expired: heapless::Vec::new(),
+            budget: DEFAULT_BUDGET,
+            stats_timeout: time,
+            iteration: 0,
+            over_budget: false,
+            standby: false,
+        }
+    }
+
+    pub(crate) fn set_budget(&mut self, budget: u32) {
+        self.budget = budget;
+    }
+
+    pub(crate) fn set_standby(&mut self, standby: bool) {
+        self.standby = standby;
+    }
+
+    /// Record the streams of another module, and report if it is (re)joining the network.
+    pub(crate) fn process_stats(&mut self, stats: DirectiveStats, time: i64) -> Option<Rejoin> {
+        if stats.uuid == self.id {
+            return None;
+        }
+        let joined = match self.hosts.get(&stats.uuid) {
+            Some(host) if stats.iteration <= host.iteration => Some(Rejoin::Restarted),
+            Some(_) => None,
+            None => match self.expired.iter().position(|uuid| *uuid == stats.uuid) {
+                Some(i) => {
+                    self.expired.remove(i);
+                    Some(Rejoin::Reconnected)
+                }
+                None => None,
+            },
+        };
+        let host = HostStreams {
+            output_streams: stats.output_streams,
+            input_streams: stats.input_streams,
+            latency: stats.latency,
+            voices: stats.voices,
+            iteration: stats.iteration,
+            last_seen: time,
+        };
+        if self.hosts.insert(stats.uuid, host).is_err() {
+            info!("Bandwidth accounting host table full");
+        }
+        joined
+    }
+
+    /// Update the local stream counts, returning a stats directive to broadcast if it is time.
+    pub(crate) fn poll(
+        &mut self,
+        output_streams: u8,
+        input_streams: u8,
+        latency: u8,
+        voices: u8,
+        sample_rate: SampleRate,
+        time: i64,
+    ) -> Option<Directive> {
+        if time < self.stats_timeout {
+            return None;
+        }
+        self.stats_timeout = time
+            + if self.standby {
+                STANDBY_STATS_INTERVAL
+            } else {
+                STATS_INTERVAL
+            };
+
+        let mut expired: heapless::Vec<Uuid, MAX_HOSTS> = heapless::Vec::new();
+        for (uuid, host) in self.hosts.iter() {
+            if uuid != &self.id && time - host.last_seen > HOST_TIMEOUT {
+                expired.push(uuid.clone()).ok();
+            }
+        }
+        for uuid in expired {
+            self.hosts.remove(&uuid);
+            if self.expired.is_full() {
+                self.expired.remove(0);
+            }
+            self.expired.push(uuid).ok();
+        }
+        self.iteration += 1;
+        let host = HostStreams {
+            output_streams,
+            input_streams,
+            latency,
+            voices,
+            iteration: self.iteration,
+            last_seen: time,
+        };
+        if self.hosts.insert(self.id.clone(), host).is_err() {
+            info!("Bandwidth accounting host table full");
+        }
+
+        let stats = self.stats(input_streams, sample_rate);
+        let over_budget = stats.network > stats.budget;
+        if over_budget && !self.over_budget {
+            warn!(
+                "Network bandwidth over budget: {} of {} bits/s",
+                stats.network, stats.budget
+            );
+        }
+        self.over_budget = over_budget;
+
+        Some(Stats(DirectiveStats {
+            uuid: self.id.clone(),
+            output_streams,
+            input_streams,
+            utilization: stats.network,
+            iteration: self.iteration,
+            latency,
+            voices,
+        }))
+    }
+
+    pub(crate) fn stats(&self, input_streams: u8, sample_rate: SampleRate) -> BandwidthStats {
+        let local_outputs = self
+            .hosts
+            .get(&self.id)
+            .map_or(0, |host| host.output_streams as u32);
+        let stream_bandwidth = stream_bandwidth(sample_rate);
+        BandwidthStats {
+            network: self.network_streams() * stream_bandwidth,
+            local: (local_outputs + input_streams as u32) * stream_bandwidth,
+            budget: self.budget,
+        }
+    }
+
+    /// Check whether a connection into module `input` keeps its link and the network within the
+    /// budget, taking another stream on that link if `new_input` and adding one to the network if
+    /// `new_stream`. Modules not heard from yet are taken to have no streams of their own.
+    pub(crate) fn admit(
+        &self,
+        input: &Uuid,
+        new_input: bool,
+        new_stream: bool,
+        sample_rate: SampleRate,
+    ) -> bool {
+        let link = self.hosts.get(input).map_or(0, |host| {
+            host.output_streams as u32 + host.input_streams as u32
+        }) + new_input as u32;
+        let network = self.network_streams() + new_stream as u32;
+        let stream_bandwidth = stream_bandwidth(sample_rate);
+        link * stream_bandwidth <= self.budget && network * stream_bandwidth <= self.budget
+    }
+
+    /// Blocks between the signals at the start of the patch and the outputs of module `uuid`, as
+    /// last advertised by it. Unknown modules are taken to be at the start.
+    pub(crate) fn latency(&self, uuid: &Uuid) -> u8 {
+        self.hosts.get(uuid).map_or(0, |host| host.latency)
+    }
+
+    /// Voices in use on the outputs of module `uuid`, as last advertised by it. Unknown modules
+    /// are taken to use every channel.
+    pub(crate) fn voices(&self, uuid: &Uuid) -> u8 {
+        self.hosts
+            .get(uuid)
+            .map_or(CHANNELS as u8, |host| host.voices)
+    }
+
+    /// Every module heard from recently, this one included.
+    pub(crate) fn hosts(&self) -> impl Iterator<Item = &Uuid> {
+        self.hosts.keys()
+    }
+
+    /// Number of other modules heard from recently.
+    pub(crate) fn peers(&self) -> usize {
+        self.hosts.keys().filter(|uuid| **uuid != self.id).count()
+    }
+
+    fn network_streams(&self) -> u32 {
+        self.hosts
+            .values()
+            .map(|host| host.output_streams as u32)
+            .sum()
+    }
+}
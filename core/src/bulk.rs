@@ -0,0 +1,432 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Directive, DirectiveBulkAccept, DirectiveBulkOffer, Error, Network, ParseError, Uuid};
+
+/// Largest payload carried by one transfer, which is enough for a whole user wavetable
+pub const MAX_TRANSFER_SIZE: usize = 4096;
+/// Payload bytes in each datagram sent to the control port
+const CONTROL_CHUNK: usize = 1024;
+/// Chunks sent ahead of the last one acknowledged
+const TRANSFER_WINDOW: u16 = 4;
+/// How long the sender waits for an offer to be accepted or for the next acknowledgement before
+/// going back to the first chunk that wasn't acknowledged
+const TRANSFER_RETRY: i64 = 50; // ms
+const MAX_TRANSFER_RETRIES: u8 = 8;
+/// How long the receiver waits for the next chunk before giving up on a transfer
+const TRANSFER_IDLE_TIMEOUT: i64 = 1000; // ms
+
+/// What a transfer holds, which tells the receiving module what to do with it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferKind {
+    /// A whole user wavetable for the slot, as little-endian samples
+    Wavetable(u8),
+    /// A `ParamPreset` for the slot
+    Preset(u8),
+    /// A `ModuleDescription` of the sending module, in answer to `Module::send_describe_request`
+    Description,
+    /// A piece of a firmware image, starting at the byte offset
+    Firmware(u32),
+}
+
+/// Where to reach the control port of a module. Modules sharing a host each have a port of their
+/// own.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ControlAddr {
+    pub addr: [u8; 4],
+    pub port: u16,
+}
+
+/// Progress of the last transfer sent with `Module::send_transfer`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferStatus {
+    Idle,
+    /// Waiting for the receiving module to accept the offer
+    Offered,
+    /// Bytes acknowledged so far, out of the whole transfer
+    Sending {
+        acked: usize,
+        size: usize,
+    },
+    Sent,
+    /// The receiving module never accepted, or stopped acknowledging
+    Failed,
+}
+
+/// A transfer received by this module, available until the next poll.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transfer<'a> {
+    pub source: &'a Uuid,
+    pub kind: TransferKind,
+    pub data: &'a [u8],
+}
+
+/// Datagrams sent between control ports once an offer has been accepted.
+#[derive(Serialize, Deserialize, Debug)]
+enum ControlPacket<'a> {
+    Chunk {
+        transfer: u16,
+        index: u16,
+        data: &'a [u8],
+    },
+    /// Every chunk before `next` has arrived
+    Ack { transfer: u16, next: u16 },
+}
+
+fn chunk_count(size: usize) -> u16 {
+    size.div_ceil(CONTROL_CHUNK).max(1) as u16
+}
+
+struct Outgoing {
+    transfer: u16,
+    uuid: Uuid,
+    kind: TransferKind,
+    data: heapless::Vec<u8, MAX_TRANSFER_SIZE>,
+    /// Control port of the receiver, once it has accepted
+    peer: Option<ControlAddr>,
+    addr: ControlAddr,
+    acked: u16,
+    sent: u16,
+    deadline: Option<i64>,
+    retries: u8,
+}
+
+impl Outgoing {
+    fn offer(&self, source: &Uuid) -> Directive {
+        Directive::BulkOffer(DirectiveBulkOffer {
+            uuid: self.uuid.clone(),
+            source: source.clone(),
+            transfer: self.transfer,
+            kind: self.kind,
+            size: self.data.len() as u32,
+            addr: self.addr,
+        })
+    }
+}
+
+struct Incoming {
+    transfer: u16,
+    source: Uuid,
+    kind: TransferKind,
+    size: usize,
+    peer: ControlAddr,
+    data: heapless::Vec<u8, MAX_TRANSFER_SIZE>,
+    next: u16,
+    deadline: i64,
+}
+
+impl Incoming {
+    fn complete(&self) -> bool {
+        self.next == chunk_count(self.size)
+    }
+}
+
+/// Moves payloads too large for a directive, one at a time in each direction, between the
+/// control ports of two modules.
+///
+/// The sender offers the transfer with a directive, and the receiver accepts it with one of its
+/// own giving its control port. The chunks then go straight to that port a few at a time, and
+/// anything not acknowledged in time is sent again, so that a transfer survives the packet loss
+/// that directives don't.
+pub(crate) struct Bulk {
+    id: Uuid,
+    next_transfer: u16,
+    outgoing: Option<Outgoing>,
+    status: TransferStatus,
+    incoming: Option<Incoming>,
+    /// The last transfer received and its number of chunks, to acknowledge again if the final
+    /// acknowledgement was lost
+    finished: Option<(u16, ControlAddr, u16)>,
+}
+
+impl Bulk {
+    pub(crate) fn new(id: Uuid) -> Self {
+        Bulk {
+            id,
+            next_transfer: 0,
+            outgoing: None,
+            status: TransferStatus::Idle,
+            incoming: None,
+            finished: None,
+        }
+    }
+
+    pub(crate) fn status(&self) -> TransferStatus {
+        self.status
+    }
+
+    /// Start sending `data` to module `uuid`, returning the offer to send out. Only one transfer
+    /// is sent at a time.
+    pub(crate) fn start(
+        &mut self,
+        uuid: Uuid,
+        kind: TransferKind,
+        data: &[u8],
+        addr: ControlAddr,
+    ) -> Result<Directive, Error> {
+        if self.outgoing.is_some() {
+            return Err(Error::StorageFull);
+        }
+        let data = heapless::Vec::from_slice(data).map_err(|_| Error::StorageFull)?;
+        self.next_transfer = self.next_transfer.wrapping_add(1);
+        let outgoing = Outgoing {
+            transfer: self.next_transfer,
+            uuid,
+            kind,
+            data,
+            peer: None,
+            addr,
+            acked: 0,
+            sent: 0,
+            deadline: None,
+            retries: 0,
+        };
+        let offer = outgoing.offer(&self.id);
+        self.outgoing = Some(outgoing);
+        self.status = TransferStatus::Offered;
+        Ok(offer)
+    }
+
+    /// Accept an offer made to this module if there is a control port to take it on, returning
+    /// the acceptance to send back. An offer heard again, because the acceptance was lost, is
+    /// accepted again.
+    pub(crate) fn process_offer(
+        &mut self,
+        offer: DirectiveBulkOffer,
+        addr: Option<ControlAddr>,
+        time: i64,
+    ) -> Option<Directive> {
+        if offer.uuid != self.id {
+            return None;
+        }
+        let addr = match addr {
+            Some(addr) => addr,
+            None => {
+                info!("No control port to take a transfer from {}", offer.source);
+                return None;
+            }
+        };
+        if offer.size as usize > MAX_TRANSFER_SIZE {
+            info!(
+                "Transfer of {} bytes from {} is too large",
+                offer.size, offer.source
+            );
+            return None;
+        }
+        let repeated = match &self.incoming {
+            Some(incoming) => {
+                incoming.source == offer.source && incoming.transfer == offer.transfer
+            }
+            None => false,
+        };
+        if !repeated {
+            if let Some(incoming) = &self.incoming {
+                if !incoming.complete() && time < incoming.deadline {
+                    info!("Busy receiving from {}", incoming.source);
+                    return None;
+                }
+            }
+            self.incoming = Some(Incoming {
+                transfer: offer.transfer,
+                source: offer.source.clone(),
+                kind: offer.kind,
+                size: offer.size as usize,
+                peer: offer.addr,
+                data: heapless::Vec::new(),
+                next: 0,
+                deadline: time + TRANSFER_IDLE_TIMEOUT,
+            });
+        }
+        Some(Directive::BulkAccept(DirectiveBulkAccept {
+            uuid: offer.source,
+            transfer: offer.transfer,
+            addr,
+        }))
+    }
+
+    pub(crate) fn process_accept(&mut self, accept: DirectiveBulkAccept) {
+        if accept.uuid != self.id {
+            return;
+        }
+        if let Some(outgoing) = &mut self.outgoing {
+            if outgoing.transfer == accept.transfer && outgoing.peer.is_none() {
+                outgoing.peer = Some(accept.addr);
+                outgoing.retries = 0;
+                outgoing.deadline = None;
+                self.status = TransferStatus::Sending {
+                    acked: 0,
+                    size: outgoing.data.len(),
+                };
+            }
+        }
+    }
+
+    /// The transfer finished during the last poll, if any.
+    pub(crate) fn received(&self) -> Option<Transfer<'_>> {
+        match &self.incoming {
+            Some(incoming) if incoming.complete() => Some(Transfer {
+                source: &incoming.source,
+                kind: incoming.kind,
+                data: &incoming.data,
+            }),
+            _ => None,
+        }
+    }
+
+    /// Handle everything that arrived on the control port and send whatever is due, returning an
+    /// offer to send again if it hasn't been accepted yet.
+    pub(crate) fn poll<N: Network<I, O>, const I: usize, const O: usize>(
+        &mut self,
+        interface: &mut N,
+        time: i64,
+    ) -> Result<Option<Directive>, Error> {
+        // A transfer received last poll has had its chance to be read
+        if self.incoming.as_ref().is_some_and(Incoming::complete) {
+            self.incoming = None;
+        }
+        let mut buf = [0; CONTROL_CHUNK + 32];
+        loop {
+            match interface.recv_control(&mut buf) {
+                Ok((size, from)) => self.process_packet(interface, &buf[..size], from, time)?,
+                Err(Error::NoData) => break,
+                Err(e) => {
+                    info!("Control port receive error: {:?}", e);
+                    break;
+                }
+            }
+        }
+        if let Some(incoming) = &self.incoming {
+            if !incoming.complete() && time >= incoming.deadline {
+                info!("Transfer from {} stopped arriving", incoming.source);
+                self.incoming = None;
+            }
+        }
+        self.send_chunks(interface, time)
+    }
+
+    fn process_packet<N: Network<I, O>, const I: usize, const O: usize>(
+        &mut self,
+        interface: &mut N,
+        buf: &[u8],
+        from: ControlAddr,
+        time: i64,
+    ) -> Result<(), Error> {
+        match postcard::from_bytes::<ControlPacket>(buf) {
+            Ok(ControlPacket::Chunk {
+                transfer,
+                index,
+                data,
+            }) => {
+                let next = match &mut self.incoming {
+                    Some(incoming) if incoming.transfer == transfer && incoming.peer == from => {
+                        if index == incoming.next && !incoming.complete() {
+                            if incoming.data.extend_from_slice(data).is_err()
+                                || incoming.data.len() > incoming.size
+                            {
+                                info!("Transfer from {} overran its size", incoming.source);
+                                self.incoming = None;
+                                return Ok(());
+                            }
+                            incoming.next += 1;
+                            incoming.deadline = time + TRANSFER_IDLE_TIMEOUT;
+                            if incoming.complete() {
+                                self.finished = Some((transfer, from, incoming.next));
+                            }
+                        }
+                        incoming.next
+                    }
+                    // Everything already arrived, but the sender hasn't heard
+                    _ => match self.finished {
+                        Some((t, f, chunks)) if t == transfer && f == from => chunks,
+                        _ => return Ok(()),
+                    },
+                };
+                send_packet(interface, from, &ControlPacket::Ack { transfer, next })
+            }
+            Ok(ControlPacket::Ack { transfer, next }) => {
+                if let Some(outgoing) = &mut self.outgoing {
+                    if outgoing.transfer == transfer
+                        && outgoing.peer == Some(from)
+                        && next > outgoing.acked
+                    {
+                        outgoing.acked = next.min(chunk_count(outgoing.data.len()));
+                        outgoing.sent = outgoing.sent.max(outgoing.acked);
+                        outgoing.retries = 0;
+                        outgoing.deadline = Some(time + TRANSFER_RETRY);
+                        let size = outgoing.data.len();
+                        if outgoing.acked == chunk_count(size) {
+                            self.status = TransferStatus::Sent;
+                            self.outgoing = None;
+                        } else {
+                            self.status = TransferStatus::Sending {
+                                acked: outgoing.acked as usize * CONTROL_CHUNK,
+                                size,
+                            };
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Err(e) => {
+                info!("Postcard Parse Error: {:?}", e);
+                Ok(())
+            }
+        }
+    }
+
+    fn send_chunks<N: Network<I, O>, const I: usize, const O: usize>(
+        &mut self,
+        interface: &mut N,
+        time: i64,
+    ) -> Result<Option<Directive>, Error> {
+        let outgoing = match &mut self.outgoing {
+            Some(outgoing) => outgoing,
+            None => return Ok(None),
+        };
+        let deadline = *outgoing.deadline.get_or_insert(time + TRANSFER_RETRY);
+        if time >= deadline {
+            if outgoing.retries == MAX_TRANSFER_RETRIES {
+                info!("Transfer to {} failed", outgoing.uuid);
+                self.status = TransferStatus::Failed;
+                self.outgoing = None;
+                return Ok(None);
+            }
+            outgoing.retries += 1;
+            outgoing.deadline = Some(time + TRANSFER_RETRY);
+            // Go back to the first chunk that wasn't acknowledged
+            outgoing.sent = outgoing.acked;
+        }
+        let peer = match outgoing.peer {
+            Some(peer) => peer,
+            None if time >= deadline => return Ok(Some(outgoing.offer(&self.id))),
+            None => return Ok(None),
+        };
+        let chunks = chunk_count(outgoing.data.len());
+        while outgoing.sent < chunks && outgoing.sent < outgoing.acked + TRANSFER_WINDOW {
+            let start = outgoing.sent as usize * CONTROL_CHUNK;
+            let end = (start + CONTROL_CHUNK).min(outgoing.data.len());
+            let chunk = ControlPacket::Chunk {
+                transfer: outgoing.transfer,
+                index: outgoing.sent,
+                data: &outgoing.data[start..end],
+            };
+            send_packet(interface, peer, &chunk)?;
+            outgoing.sent += 1;
+        }
+        Ok(None)
+    }
+}
+
+fn send_packet<N: Network<I, O>, const I: usize, const O: usize>(
+    interface: &mut N,
+    addr: ControlAddr,
+    packet: &ControlPacket,
+) -> Result<(), Error> {
+    let mut buf = [0; CONTROL_CHUNK + 32];
+    match postcard::to_slice(packet, &mut buf) {
+        Ok(res) => interface.send_control(addr, res),
+        Err(e) => {
+            info!("Postcard Parse Error: {:?}", e);
+            Err(Error::Parse(ParseError::Postcard(e)))
+        }
+    }
+}
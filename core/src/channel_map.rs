@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{AudioPacket, CHANNELS};
+
+/// How the channels of a connection are rearranged on the way into an input jack, so that a
+/// source and a destination that disagree about which channels carry what can still be patched
+/// together directly (see `Module::send_channel_map`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub enum ChannelMap {
+    /// Every channel arrives as sent
+    #[default]
+    Straight,
+    /// The first channel is copied to every channel, for a mono source into a polyphonic input
+    Broadcast,
+    /// Channel `n` arrives on channel `n + by`, wrapping around
+    Rotate(u8),
+    /// Each channel takes the channel given for it from the source
+    Map([u8; CHANNELS]),
+}
+
+impl ChannelMap {
+    /// The channel of the source that `channel` takes its samples from.
+    pub fn source(&self, channel: usize) -> usize {
+        match self {
+            ChannelMap::Straight => channel,
+            ChannelMap::Broadcast => 0,
+            ChannelMap::Rotate(by) => (channel + CHANNELS - *by as usize % CHANNELS) % CHANNELS,
+            ChannelMap::Map(map) => map[channel] as usize % CHANNELS,
+        }
+    }
+
+    /// Rearrange the channels of `input` into `output`.
+    pub fn apply(&self, input: &AudioPacket, output: &mut AudioPacket) {
+        let sources: [usize; CHANNELS] = core::array::from_fn(|c| self.source(c));
+        for (out, frame) in output.data.iter_mut().zip(input.data.iter()) {
+            out.data = sources.map(|c| frame.data[c]);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+/// Values that can be linearly interpolated between control-rate updates.
+pub trait Interpolate: Copy {
+    /// Blend from `self` at `t = 0` to `other` at `t = 1`.
+    fn lerp(&self, other: &Self, t: f32) -> Self;
+}
+
+impl Interpolate for f32 {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl<const N: usize> Interpolate for [f32; N] {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        let mut out = *self;
+        for (x, y) in out.iter_mut().zip(other) {
+            *x = x.lerp(y, t);
+        }
+        out
+    }
+}
+
+/// Runs an expensive calculation, such as filter coefficients, once every `K` samples and
+/// linearly interpolates between the results on the samples in between.
+///
+/// Updating coefficients once per block at the block edge is cheap but steps audibly on fast
+/// sweeps. This bounds the cost to one update per `K` samples while keeping the sweep smooth, at
+/// the price of the value trailing the control input by up to `K` samples.
+#[derive(Clone, Copy, Debug)]
+pub struct ControlRate<T, const K: usize> {
+    from: T,
+    to: T,
+    phase: usize,
+    /// Intervals since `update` was last called, when updating less often
+    interval: usize,
+    /// Whether there's a value to sweep from, rather than jumping to the first update
+    seeded: bool,
+}
+
+/// Starts at the first value calculated, rather than sweeping to it from `T::default()`.
+impl<T: Interpolate + Default, const K: usize> Default for ControlRate<T, K> {
+    fn default() -> Self {
+        ControlRate {
+            seeded: false,
+            ..ControlRate::new(T::default())
+        }
+    }
+}
+
+impl<T: Interpolate, const K: usize> ControlRate<T, K> {
+    pub fn new(value: T) -> Self {
+        ControlRate {
+            from: value,
+            to: value,
+            phase: 0,
+            interval: 0,
+            seeded: true,
+        }
+    }
+
+    /// Returns the value for the current sample. At the start of every `K` samples `update` is
+    /// called for the next target, which is reached at the start of the following update.
+    pub fn next(&mut self, update: impl FnOnce() -> T) -> T {
+        self.next_every(1, update)
+    }
+
+    /// As with `next`, but only calling `update` at the start of one in every `stride` intervals
+    /// and holding the last target through the rest, for when there isn't time to update as
+    /// often (see `Quality::control_stride`).
+    pub fn next_every(&mut self, stride: usize, update: impl FnOnce() -> T) -> T {
+        if self.phase == 0 {
+            self.from = self.to;
+            if self.interval == 0 {
+                self.to = update();
+                if !self.seeded {
+                    self.from = self.to;
+                    self.seeded = true;
+                }
+            }
+            self.interval = (self.interval + 1) % stride.max(1);
+        }
+        let t = self.phase as f32 / K as f32;
+        self.phase = (self.phase + 1) % K;
+        self.from.lerp(&self.to, t)
+    }
+
+    /// Jump straight to `value` without interpolating, restarting the update interval.
+    pub fn reset(&mut self, value: T) {
+        self.from = value;
+        self.to = value;
+        self.phase = 0;
+        self.interval = 0;
+        self.seeded = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_starts_at_the_first_update() {
+        let mut rate: ControlRate<f32, 4> = Default::default();
+        assert_eq!(rate.next(|| 1.0), 1.0);
+        for _ in 0..3 {
+            assert_eq!(rate.next(|| panic!("updated mid-interval")), 1.0);
+        }
+        // Later updates are swept to as usual
+        let swept: [f32; 4] = core::array::from_fn(|_| rate.next(|| 2.0));
+        assert_eq!(swept, [1.0, 1.25, 1.5, 1.75]);
+
+        // An explicit starting value is swept from
+        let mut rate: ControlRate<f32, 4> = ControlRate::new(0.0);
+        assert_eq!(rate.next(|| 1.0), 0.0);
+    }
+}
@@ -1,20 +1,63 @@
 use core::f32::consts::PI;
 
 use fixed::types::{I17F15, I1F15, I4F12, I4F28, I5F27, U4F12};
-use libm::{roundf, sinf, tanf};
+use libm::{expf, powf, roundf};
 
-use crate::{softclip, SAMPLE_RATE};
+use super::math::{sin2pi, tanpi};
 
-const PI_2: f32 = PI * PI;
-const PI_3: f32 = PI * PI_2;
+use super::control::Interpolate;
+use crate::{softclip, SampleRate, SAMPLE_RATE};
+
+/// One of the responses of a state variable filter.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Response {
+    Lowpass,
+    Bandpass,
+    Highpass,
+    Notch,
+}
+
+/// All responses of a state variable filter for a single sample.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SvfOutputs {
+    pub lowpass: f32,
+    pub bandpass: f32,
+    pub highpass: f32,
+    pub notch: f32,
+}
+
+impl SvfOutputs {
+    pub fn get(&self, response: Response) -> f32 {
+        match response {
+            Response::Lowpass => self.lowpass,
+            Response::Bandpass => self.bandpass,
+            Response::Highpass => self.highpass,
+            Response::Notch => self.notch,
+        }
+    }
+}
+
+/// Past a resonance of 4 the ladder self-oscillates, and the saturating stages pull the pitch flat
+/// as the oscillation grows. This returns the factor to raise the cutoff by so that the oscillation
+/// lands on the requested frequency. The pitch error levels off at `depth` with the resonance,
+/// fitted against the measured pitch of each ladder implementation.
+fn ladder_tuning(resonance: f32, depth: f32, width: f32) -> f32 {
+    if resonance <= 4.0 {
+        return 1.0;
+    }
+    1.0 / (1.0 - depth * (1.0 - expf(-(resonance - 4.0) / width)))
+}
 
 // https://www.native-instruments.com/fileadmin/ni_media/downloads/pdf/VAFilterDesign_1.1.1.pdf
 
 pub struct LadderFilter {
     omega0dt: f32,
     state: [f32; 4],
+    cutoff: f32,
     resonance: f32,
+    drive: f32,
     input: f32,
+    sample_rate: f32,
 }
 
 impl Default for LadderFilter {
@@ -22,18 +65,33 @@ impl Default for LadderFilter {
         LadderFilter {
             omega0dt: 2.0 * PI * 1000.0 / SAMPLE_RATE,
             state: [0.0; 4],
+            cutoff: 1000.0,
             resonance: 1.0,
+            drive: 1.0,
             input: 0.0,
+            sample_rate: SAMPLE_RATE,
         }
     }
 }
 
 impl LadderFilter {
     pub fn set_params(&mut self, cutoff: f32, resonance: f32) {
-        self.omega0dt = 2.0 * PI * cutoff.clamp(0.0, 8000.0) / SAMPLE_RATE;
+        self.omega0dt = 2.0 * PI * cutoff.clamp(0.0, 8000.0) * ladder_tuning(resonance, 0.07, 1.3)
+            / self.sample_rate;
+        self.cutoff = cutoff;
         self.resonance = resonance;
     }
 
+    /// Gain applied to the input ahead of the saturating first stage.
+    pub fn set_drive(&mut self, drive: f32) {
+        self.drive = drive;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate.hz();
+        self.set_params(self.cutoff, self.resonance);
+    }
+
     pub fn process(&mut self, input: f32, _dt: f32) -> f32 {
         let mut state = self.state.clone();
         self.rk4(&mut state, self.input, input);
@@ -45,7 +103,7 @@ impl LadderFilter {
     fn f(&self, x: [f32; 4], inputt: f32) -> [f32; 4] {
         let mut dxdt = [0.0; 4];
         // let inputt = input * (t / dt) + input_new * (1.0 - t / dt);
-        let inputc = softclip(inputt - self.resonance * x[3]);
+        let inputc = softclip(self.drive * inputt - self.resonance * x[3]);
         let yc = x.map(softclip);
 
         dxdt[0] = self.omega0dt * (inputc - yc[0]);
@@ -80,8 +138,11 @@ impl LadderFilter {
 pub struct LadderFilterFP {
     omega0dt: I1F15,
     state: [I1F15; 4],
+    cutoff: f32,
     resonance: I17F15,
+    drive: I17F15,
     input: I1F15,
+    sample_rate: f32,
 }
 
 impl Default for LadderFilterFP {
@@ -89,8 +150,11 @@ impl Default for LadderFilterFP {
         LadderFilterFP {
             omega0dt: I1F15::from_num(0.15_f32),
             state: [I1F15::from_num(0_i16); 4],
+            cutoff: 0.15 * SAMPLE_RATE / (2.0 * PI),
             resonance: I17F15::from_num(0_i16),
+            drive: I17F15::from_num(1_i16),
             input: I1F15::from_num(0_i16),
+            sample_rate: SAMPLE_RATE,
         }
     }
 }
@@ -106,15 +170,30 @@ pub fn softclipfp(x: I17F15) -> I1F15 {
     } else {
         x
     };
-    I1F15::from_num(y * (twenty_seven + y * y) / (twenty_seven + nine * y * y))
+    // Saturate since the curve reaches exactly one at the ends, just out of range of I1F15
+    I1F15::saturating_from_num(y * (twenty_seven + y * y) / (twenty_seven + nine * y * y))
 }
 
 impl LadderFilterFP {
     pub fn set_params(&mut self, cutoff: f32, resonance: f32) {
-        self.omega0dt = I1F15::from_num((2.0 * PI * cutoff / SAMPLE_RATE).clamp(0.0, 0.99));
+        self.omega0dt = I1F15::from_num(
+            (2.0 * PI * cutoff * ladder_tuning(resonance, 0.107, 2.2) / self.sample_rate)
+                .clamp(0.0, 0.99),
+        );
+        self.cutoff = cutoff;
         self.resonance = I17F15::from_num(resonance);
     }
 
+    /// Gain applied to the input ahead of the saturating first stage.
+    pub fn set_drive(&mut self, drive: f32) {
+        self.drive = I17F15::from_num(drive);
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate.hz();
+        self.set_params(self.cutoff, self.resonance.to_num());
+    }
+
     pub fn process(&mut self, input: i16) -> i16 {
         let mut state = self.state.clone();
         self.rk4(&mut state, self.input, I1F15::from_bits(input));
@@ -126,7 +205,8 @@ impl LadderFilterFP {
     fn f(&self, x: [I1F15; 4], inputt: I1F15) -> [I1F15; 4] {
         // let mut dxdt = [0; 4];
         // let inputt = input * (t / dt) + input_new * (1.0 - t / dt);
-        let inputc = softclipfp(I17F15::from(inputt) - self.resonance * I17F15::from(x[3]));
+        let inputc =
+            softclipfp(self.drive * I17F15::from(inputt) - self.resonance * I17F15::from(x[3]));
         // let yc = x.map(softclip);
         // let inputc = inputt;
         let yc = x;
@@ -155,50 +235,111 @@ impl LadderFilterFP {
             yi[i] = x[i] + k3[i];
         }
         let k4 = self.f(yi, input);
+        // Sum before dividing so rounding doesn't bleed energy out of the state at low cutoffs
         for i in 0..4 {
-            x[i] += k1[i] / 6 + k2[i] / 3 + k3[i] / 3 + k4[i] / 6;
+            let sum = I17F15::from(k1[i])
+                + I17F15::from(k2[i]) * 2
+                + I17F15::from(k3[i]) * 2
+                + I17F15::from(k4[i]);
+            x[i] += I1F15::from_num(sum / 6);
         }
     }
 }
 
-#[derive(Default)]
 pub struct Svf {
     g: f32,
     r: f32,
     h: f32,
     state_1: f32,
     state_2: f32,
+    cutoff: f32,
+    resonance: f32,
+    sample_rate: f32,
+}
+
+impl Default for Svf {
+    fn default() -> Self {
+        Svf {
+            g: 0.0,
+            r: 0.0,
+            h: 0.0,
+            state_1: 0.0,
+            state_2: 0.0,
+            cutoff: 0.0,
+            resonance: 0.0,
+            sample_rate: SAMPLE_RATE,
+        }
+    }
 }
 
 impl Svf {
     pub fn set_params(&mut self, cutoff: f32, resonance: f32) {
-        self.g = tanf(PI * cutoff.clamp(20.0, 8000.0) / SAMPLE_RATE);
+        self.g = tanpi(cutoff.clamp(20.0, 8000.0) / self.sample_rate);
         self.r = (10.0 - resonance) / 10.0;
         self.h = 1.0 / (1.0 + self.r * self.g + self.g * self.g);
+        self.cutoff = cutoff;
+        self.resonance = resonance;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate.hz();
+        self.set_params(self.cutoff, self.resonance);
     }
 
     pub fn process(&mut self, input: f32) -> f32 {
+        self.process_all(input).lowpass
+    }
+
+    pub fn process_all(&mut self, input: f32) -> SvfOutputs {
         let hp = (input - self.r * self.state_1 - self.g * self.state_1 - self.state_2) * self.h;
         let bp = self.g * hp + self.state_1;
         self.state_1 = self.g * hp + bp;
         let lp = self.g * bp + self.state_2;
         self.state_2 = self.g * bp + lp;
-        lp
+        SvfOutputs {
+            lowpass: lp,
+            bandpass: bp,
+            highpass: hp,
+            notch: lp + hp,
+        }
     }
 }
 
-#[derive(Default)]
 pub struct NaiveSvf {
     f: f32,
     damp: f32,
     lp: f32,
     bp: f32,
+    cutoff: f32,
+    resonance: f32,
+    sample_rate: f32,
+}
+
+impl Default for NaiveSvf {
+    fn default() -> Self {
+        NaiveSvf {
+            f: 0.0,
+            damp: 0.0,
+            lp: 0.0,
+            bp: 0.0,
+            cutoff: 0.0,
+            resonance: 0.0,
+            sample_rate: SAMPLE_RATE,
+        }
+    }
 }
 
 impl NaiveSvf {
     pub fn set_params(&mut self, cutoff: f32, resonance: f32) {
-        self.f = 2.0 * sinf(PI * cutoff.clamp(20.0, 8000.0) / SAMPLE_RATE);
+        self.f = 2.0 * sin2pi(cutoff.clamp(20.0, 8000.0) / (2.0 * self.sample_rate));
         self.damp = (10.0 - resonance) / 5.0;
+        self.cutoff = cutoff;
+        self.resonance = resonance;
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate.hz();
+        self.set_params(self.cutoff, self.resonance);
     }
 
     pub fn process(&mut self, input: f32) -> f32 {
@@ -212,7 +353,25 @@ impl NaiveSvf {
 }
 
 // https://www.cytomic.com/files/dsp/SvfLinearTrapOptimised2.pdf
-#[derive(Default)]
+/// Negative damping used by `LinearTrap` at full resonance so that it self-oscillates.
+const SELF_OSCILLATION_DAMPING: f32 = 0.02;
+
+/// The cutoff and damping terms of a `LinearTrap`, for updating it at control rate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TrapCoefficients {
+    g: f32,
+    k: f32,
+}
+
+impl Interpolate for TrapCoefficients {
+    fn lerp(&self, other: &Self, t: f32) -> Self {
+        TrapCoefficients {
+            g: self.g.lerp(&other.g, t),
+            k: self.k.lerp(&other.k, t),
+        }
+    }
+}
+
 pub struct LinearTrap {
     g: f32,
     k: f32,
@@ -221,24 +380,280 @@ pub struct LinearTrap {
     a3: f32,
     ic2eq: f32,
     ic1eq: f32,
+    cutoff: f32,
+    resonance: f32,
+    sample_rate: f32,
+}
+
+impl Default for LinearTrap {
+    fn default() -> Self {
+        LinearTrap {
+            g: 0.0,
+            k: 0.0,
+            a1: 0.0,
+            a2: 0.0,
+            a3: 0.0,
+            ic2eq: 0.0,
+            ic1eq: 0.0,
+            cutoff: 0.0,
+            resonance: 0.0,
+            sample_rate: SAMPLE_RATE,
+        }
+    }
 }
 
 impl LinearTrap {
     pub fn set_params(&mut self, cutoff: f32, resonance: f32) {
-        // self.g = tanf(PI * cutoff.clamp(20.0, 8000.0) / SAMPLE_RATE);
-        let f = cutoff.clamp(20.0, 8000.0) / SAMPLE_RATE;
-        self.g = f * (PI + 3.736e-1 * PI_3 * f * f);
-        self.k = 2.0 - 2.0 * (resonance / 10.0);
+        self.cutoff = cutoff;
+        self.resonance = resonance;
+        self.set_coefficients(self.coefficients(cutoff, resonance));
+    }
+
+    /// Calculate the coefficients for a cutoff and resonance without applying them, so they can
+    /// be interpolated with a `ControlRate`.
+    pub fn coefficients(&self, cutoff: f32, resonance: f32) -> TrapCoefficients {
+        // At full resonance, push the damping slightly negative so the filter self-oscillates. The
+        // amplitude is then held by saturating the bandpass state in `tick`.
+        let k = 2.0 - 2.0 * (resonance / 10.0);
+        TrapCoefficients {
+            // Prewarp so the resonant peak, and the self-oscillation, land on the cutoff
+            g: tanpi(cutoff.clamp(20.0, 8000.0) / self.sample_rate),
+            k: if k > 0.0 {
+                k
+            } else {
+                -SELF_OSCILLATION_DAMPING
+            },
+        }
+    }
+
+    /// Apply coefficients from `coefficients`. This costs a single division, so it is cheap
+    /// enough to call every sample.
+    pub fn set_coefficients(&mut self, coefficients: TrapCoefficients) {
+        self.g = coefficients.g;
+        self.k = coefficients.k;
         self.a1 = 1.0 / (1.0 + self.g * (self.g + self.k));
         self.a2 = self.g * self.a1;
         self.a3 = self.g * self.a2;
     }
-    pub fn process(&mut self, v0: f32) -> f32 {
+
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate.hz();
+        self.set_params(self.cutoff, self.resonance);
+    }
+    fn tick(&mut self, v0: f32) -> (f32, f32) {
         let v3 = v0 - self.ic2eq;
         let v1 = self.a1 * self.ic1eq + self.a2 * v3;
         let v2 = self.ic2eq + self.a2 * self.ic1eq + self.a3 * v3;
         self.ic1eq = 2.0 * v1 - self.ic1eq;
         self.ic2eq = 2.0 * v2 - self.ic2eq;
-        v2
+        if self.k < 0.0 {
+            self.ic1eq = softclip(self.ic1eq);
+        }
+        (v1, v2)
+    }
+
+    pub fn process(&mut self, v0: f32) -> f32 {
+        self.tick(v0).1
+    }
+
+    /// Normalize the bandpass to unity gain at the center frequency. The negative damping of a
+    /// self-oscillating filter is only for the state update, and the bandpass state is already
+    /// held near unity by the saturation.
+    fn bandpass(&self, v1: f32) -> f32 {
+        if self.k > 0.0 {
+            self.k * v1
+        } else {
+            v1
+        }
+    }
+
+    /// Same as `process`, but returns every response of the filter. The bandpass is normalized
+    /// to unity gain at the center frequency, like `process_bandpass`.
+    pub fn process_all(&mut self, v0: f32) -> SvfOutputs {
+        let (v1, v2) = self.tick(v0);
+        let bandpass = self.bandpass(v1);
+        SvfOutputs {
+            lowpass: v2,
+            bandpass,
+            highpass: v0 - bandpass - v2,
+            notch: v0 - bandpass,
+        }
+    }
+
+    /// Same as `process`, but returns the bandpass response normalized to unity gain at the
+    /// center frequency.
+    pub fn process_bandpass(&mut self, v0: f32) -> f32 {
+        let v1 = self.tick(v0).0;
+        self.bandpass(v1)
+    }
+}
+
+/// A bank of `N` bandpass filters with logarithmically spaced center frequencies.
+///
+/// Each band is a `LinearTrap` sharing the same resonance, so the bank can be used to split a
+/// signal into bands for a vocoder or to drive a spectral meter.
+pub struct FilterBank<const N: usize> {
+    bands: [LinearTrap; N],
+    centers: [f32; N],
+}
+
+impl<const N: usize> FilterBank<N> {
+    pub fn new(low: f32, high: f32, resonance: f32) -> Self {
+        let mut bank = FilterBank {
+            bands: [(); N].map(|_| Default::default()),
+            centers: [0.0; N],
+        };
+        bank.set_params(low, high, resonance);
+        bank
+    }
+
+    pub fn set_params(&mut self, low: f32, high: f32, resonance: f32) {
+        let ratio = if N > 1 {
+            powf(high / low, 1.0 / (N - 1) as f32)
+        } else {
+            1.0
+        };
+        let mut center = low;
+        for (band, c) in self.bands.iter_mut().zip(self.centers.iter_mut()) {
+            band.set_params(center, resonance);
+            *c = center;
+            center *= ratio;
+        }
+    }
+
+    /// Recompute the band coefficients for a new sample rate, keeping the same center frequencies.
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        for band in self.bands.iter_mut() {
+            band.set_sample_rate(sample_rate);
+        }
+    }
+
+    /// Center frequency of each band in Hz
+    pub fn centers(&self) -> &[f32; N] {
+        &self.centers
+    }
+
+    pub fn process(&mut self, input: f32) -> [f32; N] {
+        let mut out = [0.0; N];
+        for (band, o) in self.bands.iter_mut().zip(out.iter_mut()) {
+            *o = band.process_bandpass(input);
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Kicks the filter with an impulse, lets the oscillation settle, and measures its frequency
+    /// from the zero crossings over the following second.
+    fn self_oscillation(mut process: impl FnMut(f32) -> f32) -> f32 {
+        process(0.1);
+        for _ in 0..SAMPLE_RATE as usize {
+            process(0.0);
+        }
+        let mut prev = process(0.0);
+        let (mut first, mut last, mut count) = (0.0, 0.0, 0);
+        for i in 0..SAMPLE_RATE as usize {
+            let y = process(0.0);
+            if prev < 0.0 && y >= 0.0 {
+                last = i as f32 + prev / (prev - y);
+                if count == 0 {
+                    first = last;
+                }
+                count += 1;
+            }
+            prev = y;
+        }
+        assert!(count > 1, "filter did not self-oscillate");
+        (count - 1) as f32 * SAMPLE_RATE / (last - first)
+    }
+
+    const CUTOFFS: [f32; 7] = [50.0, 100.0, 250.0, 1000.0, 2000.0, 4000.0, 6000.0];
+
+    #[test]
+    fn ladder_tracks_cutoff() {
+        for resonance in [4.5, 6.0, 10.0] {
+            for cutoff in CUTOFFS {
+                let mut filter = LadderFilter::default();
+                filter.set_params(cutoff, resonance);
+                let freq = self_oscillation(|x| filter.process(x, 0.0));
+                assert!(
+                    (freq / cutoff - 1.0).abs() < 0.005,
+                    "{} Hz at resonance {} oscillated at {} Hz",
+                    cutoff,
+                    resonance,
+                    freq
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ladder_fp_tracks_cutoff() {
+        // The 16 bit state can't sustain an oscillation much further down
+        for cutoff in [500.0, 1000.0, 2000.0, 4000.0, 6000.0] {
+            let mut filter = LadderFilterFP::default();
+            filter.set_params(cutoff, 6.0);
+            let freq = self_oscillation(|x| {
+                filter.process((x * i16::MAX as f32) as i16) as f32 / i16::MAX as f32
+            });
+            assert!(
+                (freq / cutoff - 1.0).abs() < 0.005,
+                "{} Hz oscillated at {} Hz",
+                cutoff,
+                freq
+            );
+        }
+    }
+
+    #[test]
+    fn linear_trap_tracks_cutoff() {
+        for cutoff in CUTOFFS {
+            let mut filter = LinearTrap::default();
+            filter.set_params(cutoff, 10.0);
+            let freq = self_oscillation(|x| filter.process(x));
+            assert!(
+                (freq / cutoff - 1.0).abs() < 0.001,
+                "{} Hz oscillated at {} Hz",
+                cutoff,
+                freq
+            );
+        }
+    }
+
+    #[test]
+    fn linear_trap_self_oscillates_on_every_output() {
+        let mut filter = LinearTrap::default();
+        filter.set_params(1000.0, 10.0);
+        filter.process_all(1.0);
+        for _ in 0..SAMPLE_RATE as usize {
+            filter.process_all(0.0);
+        }
+        // Lowpass, bandpass, highpass and notch
+        let mut peaks = [0.0_f32; 4];
+        for _ in 0..SAMPLE_RATE as usize / 10 {
+            let out = filter.process_all(0.0);
+            let levels = [out.lowpass, out.bandpass, out.highpass, out.notch];
+            for (peak, level) in peaks.iter_mut().zip(levels) {
+                *peak = peak.max(level.abs());
+            }
+        }
+        assert!(
+            peaks[1..].iter().all(|peak| *peak > 0.5 * peaks[0]),
+            "{:?}",
+            peaks
+        );
+    }
+
+    #[test]
+    fn linear_trap_self_oscillation_is_bounded() {
+        let mut filter = LinearTrap::default();
+        filter.set_params(1000.0, 10.0);
+        filter.process(1.0);
+        for _ in 0..10 * SAMPLE_RATE as usize {
+            assert!(filter.process(0.0).abs() < 4.0);
+        }
     }
 }
@@ -0,0 +1,191 @@
+use crate::SampleRate;
+
+/// Level sent for a high gate, matching the MIDI to CV module.
+pub const GATE_HIGH: i16 = 16000;
+
+/// Samples at or above this read as a high gate.
+pub const GATE_THRESHOLD: i16 = 1024;
+
+/// How far below `GATE_THRESHOLD` a `GateDetector` has to fall before it reads low again.
+pub const GATE_HYSTERESIS: i16 = 512;
+/// Shortest time in seconds a `GateDetector` holds a state, which is as long as a trigger.
+pub const GATE_RETRIGGER: f32 = 0.001;
+
+pub fn is_high(x: i16) -> bool {
+    x >= GATE_THRESHOLD
+}
+
+/// The gate level for a logic value.
+pub fn gate(high: bool) -> i16 {
+    if high {
+        GATE_HIGH
+    } else {
+        0
+    }
+}
+
+/// Output gates of `GateLogic` for a single sample.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LogicOutputs {
+    pub and: i16,
+    pub or: i16,
+    pub xor: i16,
+    pub flip_flop: i16,
+}
+
+/// Logic on a pair of gates for a single channel, along with a flip-flop that toggles on every
+/// rising edge of the first gate.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GateLogic {
+    last: bool,
+    flip_flop: bool,
+}
+
+impl GateLogic {
+    pub fn process(&mut self, a: i16, b: i16) -> LogicOutputs {
+        let (a, b) = (is_high(a), is_high(b));
+        if a && !self.last {
+            self.flip_flop = !self.flip_flop;
+        }
+        self.last = a;
+        LogicOutputs {
+            and: gate(a && b),
+            or: gate(a || b),
+            xor: gate(a ^ b),
+            flip_flop: gate(self.flip_flop),
+        }
+    }
+}
+
+/// Output of `GateDetector` for a single sample.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GateState {
+    pub high: bool,
+    /// The gate went high on this sample
+    pub rising: bool,
+}
+
+/// Reads gates off a single channel without chattering on slow or noisy CV.
+///
+/// The gate goes high at `threshold`, like `is_high`, and only falls again once the input drops
+/// below `threshold - hysteresis`. After each change it holds for at least `retrigger` seconds,
+/// so that bounces on an edge don't retrigger whatever is listening.
+#[derive(Clone, Copy, Debug)]
+pub struct GateDetector {
+    threshold: i16,
+    hysteresis: i16,
+    retrigger: f32,
+    high: bool,
+    /// Seconds left before the gate may change
+    hold: f32,
+}
+
+impl Default for GateDetector {
+    fn default() -> Self {
+        GateDetector::new(GATE_THRESHOLD, GATE_HYSTERESIS, GATE_RETRIGGER)
+    }
+}
+
+impl GateDetector {
+    pub fn new(threshold: i16, hysteresis: i16, retrigger: f32) -> Self {
+        GateDetector {
+            threshold,
+            hysteresis: hysteresis.max(0),
+            retrigger,
+            high: false,
+            hold: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, x: i16, sample_rate: SampleRate) -> GateState {
+        let was_high = self.high;
+        if self.hold > 0.0 {
+            self.hold -= sample_rate.dt();
+        } else {
+            self.high = if self.high {
+                x >= self.threshold.saturating_sub(self.hysteresis)
+            } else {
+                x >= self.threshold
+            };
+            if self.high != was_high {
+                self.hold = self.retrigger;
+            }
+        }
+        GateState {
+            high: self.high,
+            rising: self.high && !was_high,
+        }
+    }
+}
+
+/// Turns a signal into a gate, with hysteresis so noise near the threshold doesn't chatter.
+///
+/// Thresholds are fractions of full scale. The output goes high once the input rises above
+/// `threshold + hysteresis / 2` and stays high until it falls below `threshold - hysteresis / 2`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Comparator {
+    high: bool,
+}
+
+impl Comparator {
+    pub fn process(&mut self, x: i16, threshold: f32, hysteresis: f32) -> bool {
+        let x = x as f32 / i16::MAX as f32;
+        if self.high {
+            self.high = x >= threshold - hysteresis / 2.0;
+        } else {
+            self.high = x > threshold + hysteresis / 2.0;
+        }
+        self.high
+    }
+}
+
+/// Output gates of `WindowComparator` for a single sample.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowOutputs {
+    pub inside: i16,
+    pub above: i16,
+    pub below: i16,
+}
+
+/// A pair of comparators reporting whether a signal is inside, above, or below a window.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowComparator {
+    low: Comparator,
+    high: Comparator,
+}
+
+impl WindowComparator {
+    pub fn process(&mut self, x: i16, low: f32, high: f32, hysteresis: f32) -> WindowOutputs {
+        let above_low = self.low.process(x, low.min(high), hysteresis);
+        let above_high = self.high.process(x, high.max(low), hysteresis);
+        WindowOutputs {
+            inside: gate(above_low && !above_high),
+            above: gate(above_high),
+            below: gate(!above_low),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gate_detector_ignores_chatter() {
+        let rate = SampleRate::default();
+        let mut detector = GateDetector::default();
+        // Noise around the threshold on a slow rise, then a bounce right after it goes high
+        let input = [0, 1100, 900, 1100, 0, 2000, 0, 2000];
+        let rising = input
+            .iter()
+            .filter(|x| detector.process(**x, rate).rising)
+            .count();
+        assert_eq!(rising, 1);
+        // Once the hold is over, it follows the input again
+        let hold = (GATE_RETRIGGER * rate.hz()) as usize;
+        let falls = (0..hold + 1).any(|_| !detector.process(0, rate).high);
+        assert!(falls);
+        let rises = (0..hold + 1).any(|_| detector.process(GATE_HIGH, rate).rising);
+        assert!(rises);
+    }
+}
@@ -0,0 +1,138 @@
+//! Math functions used in the DSP hot paths.
+//!
+//! With the `fast-math` feature these are replaced by lookup tables (generated by the build
+//! script) and rational approximations, which trade a little accuracy for far fewer cycles on
+//! hardware without a fast `libm`. Otherwise they are thin wrappers around `libm`.
+
+#[cfg(not(feature = "fast-math"))]
+use core::f32::consts::PI;
+#[cfg(not(feature = "fast-math"))]
+use libm::{powf, sinf, tanf};
+
+#[cfg(feature = "fast-math")]
+use core::mem;
+#[cfg(feature = "fast-math")]
+use libm::floorf;
+#[cfg(feature = "fast-math")]
+use zerocopy::{AsBytes, FromBytes};
+
+#[cfg(feature = "fast-math")]
+const TABLE_SIZE: usize = 1024;
+
+/// One period of a function sampled at `TABLE_SIZE` points, with the first point repeated at the
+/// end so that interpolation never has to wrap.
+#[cfg(feature = "fast-math")]
+#[derive(AsBytes, FromBytes)]
+#[repr(C)]
+struct Table {
+    vals: [f32; TABLE_SIZE + 1],
+}
+
+#[cfg(feature = "fast-math")]
+impl Table {
+    /// Linearly interpolated lookup of `x` in the range [0, 1).
+    fn lookup(&self, x: f32) -> f32 {
+        let pos = x * TABLE_SIZE as f32;
+        let idx = (pos as usize).min(TABLE_SIZE - 1);
+        let frac = pos - idx as f32;
+        self.vals[idx] + (self.vals[idx + 1] - self.vals[idx]) * frac
+    }
+}
+
+#[cfg(feature = "fast-math")]
+static SIN_TABLE: Table = unsafe {
+    mem::transmute::<[u8; mem::size_of::<Table>()], Table>(*include_bytes!("../../wt/sin.lut"))
+};
+
+#[cfg(feature = "fast-math")]
+static EXP2_TABLE: Table = unsafe {
+    mem::transmute::<[u8; mem::size_of::<Table>()], Table>(*include_bytes!("../../wt/exp2.lut"))
+};
+
+/// `sin(2 * pi * x)`, with `x` in turns rather than radians.
+///
+/// The `fast-math` version has an absolute error below 5e-6.
+#[cfg(feature = "fast-math")]
+pub fn sin2pi(x: f32) -> f32 {
+    SIN_TABLE.lookup(x - floorf(x))
+}
+
+#[cfg(not(feature = "fast-math"))]
+pub fn sin2pi(x: f32) -> f32 {
+    sinf(2.0 * PI * x)
+}
+
+/// `tan(pi * x)`, used for prewarping filter cutoffs where `x` is the cutoff over the sample
+/// rate.
+///
+/// The `fast-math` version is a Padé approximant with a relative error below 3e-5 for
+/// `x <= 0.18` (8 kHz at a 44.1 kHz sample rate) and below 3e-4 for `x <= 0.25`.
+#[cfg(feature = "fast-math")]
+pub fn tanpi(x: f32) -> f32 {
+    let x = core::f32::consts::PI * x;
+    let x2 = x * x;
+    x * (15.0 - x2) / (15.0 - 6.0 * x2)
+}
+
+#[cfg(not(feature = "fast-math"))]
+pub fn tanpi(x: f32) -> f32 {
+    tanf(PI * x)
+}
+
+/// `2^x`.
+///
+/// The `fast-math` version has a relative error below 2e-7, and saturates outside the range of
+/// normal `f32` values.
+#[cfg(feature = "fast-math")]
+pub fn exp2(x: f32) -> f32 {
+    let n = floorf(x);
+    let scale = f32::from_bits(((n.clamp(-126.0, 127.0) as i32 + 127) as u32) << 23);
+    EXP2_TABLE.lookup(x - n) * scale
+}
+
+#[cfg(not(feature = "fast-math"))]
+pub fn exp2(x: f32) -> f32 {
+    powf(2.0, x)
+}
+
+#[cfg(all(test, feature = "fast-math"))]
+mod tests {
+    use super::*;
+    use core::f32::consts::PI;
+
+    /// `n + 1` evenly spaced points from `start` to `end`.
+    fn points(start: f32, end: f32, n: usize) -> impl Iterator<Item = f32> {
+        (0..=n).map(move |i| start + (end - start) * i as f32 / n as f32)
+    }
+
+    #[test]
+    fn sin2pi_matches_libm() {
+        for x in points(-2.0, 2.0, 100_000) {
+            let err = (sin2pi(x) - libm::sinf(2.0 * PI * x)).abs();
+            assert!(err < 5e-6, "sin2pi({}) is off by {}", x, err);
+        }
+    }
+
+    #[test]
+    fn tanpi_matches_libm() {
+        for (end, bound) in [(0.18, 3e-5), (0.25, 3e-4)] {
+            for x in points(1e-4, end, 10_000) {
+                let exact = libm::tanf(PI * x);
+                let err = ((tanpi(x) - exact) / exact).abs();
+                assert!(err < bound, "tanpi({}) is off by {}", x, err);
+            }
+        }
+    }
+
+    #[test]
+    fn exp2_matches_libm() {
+        for x in points(-20.0, 20.0, 100_000) {
+            let exact = libm::powf(2.0, x);
+            let err = ((exp2(x) - exact) / exact).abs();
+            assert!(err < 2e-7, "exp2({}) is off by {}", x, err);
+        }
+        // Saturates rather than overflowing
+        assert!(exp2(200.0).is_finite());
+        assert!(exp2(-200.0) >= 0.0);
+    }
+}
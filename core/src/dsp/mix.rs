@@ -0,0 +1,215 @@
+use core::f32::consts::FRAC_PI_2;
+
+use libm::{cosf, fabsf, sinf, sqrtf, tanhf};
+
+use super::{math::exp2, sample::Sample};
+use crate::{AudioFrame, CHANNELS, STEREO_PAIRS};
+
+/// Sum all channels of a frame down to a single sample, scaled so that a full-scale channel is
+/// 1.0.
+pub fn mixdown(frame: &AudioFrame) -> f32 {
+    frame.data.iter().map(|x| x.to_f32()).sum()
+}
+
+/// Sum all channels of a frame into a stereo pair, with each channel placed at a pan position
+/// from -1 (left) to 1 (right) using an equal-power law.
+pub fn mixdown_stereo(frame: &AudioFrame, pans: &[f32; CHANNELS]) -> (f32, f32) {
+    let mut left = 0.0;
+    let mut right = 0.0;
+    for (x, p) in frame.data.iter().zip(pans) {
+        let (l, r) = pan(*x as f32, *p);
+        left += l;
+        right += r;
+    }
+    (left / i16::MAX as f32, right / i16::MAX as f32)
+}
+
+/// Sum all the stereo pairs of a frame into a single stereo pair, scaled like `mixdown`.
+pub fn mixdown_pairs(frame: &AudioFrame) -> (f32, f32) {
+    let mut left = 0.0;
+    let mut right = 0.0;
+    for pair in 0..STEREO_PAIRS {
+        let (l, r) = frame.stereo(pair);
+        left += l as f32;
+        right += r as f32;
+    }
+    (left / i16::MAX as f32, right / i16::MAX as f32)
+}
+
+/// Place a mono sample at a pan position from -1 (left) to 1 (right) using an equal-power law.
+pub fn pan(x: f32, pan: f32) -> (f32, f32) {
+    let angle = (pan.clamp(-1.0, 1.0) + 1.0) * FRAC_PI_2 / 2.0;
+    (x * cosf(angle), x * sinf(angle))
+}
+
+/// Convert left and right into mid (the sum) and side (the difference).
+pub fn mid_side_encode(left: f32, right: f32) -> (f32, f32) {
+    ((left + right) / 2.0, (left - right) / 2.0)
+}
+
+/// Convert mid and side back into left and right.
+pub fn mid_side_decode(mid: f32, side: f32) -> (f32, f32) {
+    (mid + side, mid - side)
+}
+
+/// Scale the stereo width by scaling the side signal, from 0 (mono) through 1 (unchanged) and
+/// wider above that.
+pub fn stereo_width(left: f32, right: f32, width: f32) -> (f32, f32) {
+    let (mid, side) = mid_side_encode(left, right);
+    mid_side_decode(mid, side * width.max(0.0))
+}
+
+/// Scale a CV sample by a gain from -1 (inverted) to 1, then add an offset as a fraction of full
+/// scale. The result saturates at the rails rather than wrapping.
+pub fn attenuvert(x: i16, gain: f32, offset: f32) -> i16 {
+    (x as f32 * gain + offset * i16::MAX as f32) as i16
+}
+
+/// Fraction of full scale above which `soft_limit` starts to bend the signal over
+pub const LIMIT_KNEE: f32 = 0.5;
+
+/// Pass a sample through untouched up to `LIMIT_KNEE`, then bend it over smoothly towards full
+/// scale without ever reaching it, so that a signal with too much gain squashes rather than
+/// wrapping around.
+pub fn soft_limit(x: i16) -> i16 {
+    let over = fabsf(x.to_f32()) - LIMIT_KNEE;
+    if over <= 0.0 {
+        return x;
+    }
+    let headroom = 1.0 - LIMIT_KNEE;
+    let y = LIMIT_KNEE + headroom * tanhf(over / headroom);
+    let y = if x < 0 { -y } else { y };
+    i16::from_f32_clipped(y)
+}
+
+/// Unison stacking, where the polyphony channels are split between the played voices and detuned
+/// copies of them.
+///
+/// With a stack of `n`, the first `CHANNELS / n` channels carry the played voices and every
+/// channel after that copies the voice at `channel % (CHANNELS / n)`. Copies are spread evenly
+/// across the detune range, and further out copies are pushed to the sides and turned down as
+/// the spread increases.
+#[derive(Clone, Copy, Debug)]
+pub struct Unison {
+    stack: usize,
+    detune: f32,
+    spread: f32,
+}
+
+impl Default for Unison {
+    fn default() -> Self {
+        Unison {
+            stack: 1,
+            detune: 0.0,
+            spread: 0.0,
+        }
+    }
+}
+
+impl Unison {
+    /// Set the number of copies of each voice, the total detune range in semitones, and the
+    /// spread from 0 to 1. The stack is reduced until it evenly divides the channels.
+    pub fn set_params(&mut self, stack: usize, detune: f32, spread: f32) {
+        let mut stack = stack.clamp(1, CHANNELS);
+        while !CHANNELS.is_multiple_of(stack) {
+            stack -= 1;
+        }
+        self.stack = stack;
+        self.detune = detune;
+        self.spread = spread.clamp(0.0, 1.0);
+    }
+
+    pub fn stack(&self) -> usize {
+        self.stack
+    }
+
+    /// Number of independent voices left after stacking
+    pub fn voices(&self) -> usize {
+        CHANNELS / self.stack
+    }
+
+    /// The channel that `channel` takes its pitch and gate from
+    pub fn source(&self, channel: usize) -> usize {
+        channel % self.voices()
+    }
+
+    /// Position of the copy on `channel` within its stack, from -1 to 1
+    fn offset(&self, channel: usize) -> f32 {
+        if self.stack == 1 {
+            return 0.0;
+        }
+        let copy = channel / self.voices();
+        2.0 * copy as f32 / (self.stack - 1) as f32 - 1.0
+    }
+
+    /// Frequency ratio to apply to the pitch of `channel`
+    pub fn ratio(&self, channel: usize) -> f32 {
+        exp2(self.offset(channel) * self.detune / 2.0 / 12.0)
+    }
+
+    /// Level of `channel`, normalized so that the stack is about as loud as a single voice
+    pub fn gain(&self, channel: usize) -> f32 {
+        let offset = self.offset(channel);
+        (1.0 - 0.5 * self.spread * offset * offset) / sqrtf(self.stack as f32)
+    }
+
+    /// Pan position of `channel` for use with `mixdown_stereo`
+    pub fn pan(&self, channel: usize) -> f32 {
+        self.offset(channel) * self.spread
+    }
+}
+
+/// Click-free selection between `N` sources or destinations.
+///
+/// Each slot has a gain that ramps linearly toward 1 when selected and 0 otherwise, so changing
+/// the selection fades across rather than stepping. The gains always sum to 1 once a fade has
+/// finished, and stay within 0 and 1 on the way, so this is safe for CV as well as audio.
+#[derive(Clone, Copy, Debug)]
+pub struct Crossfader<const N: usize> {
+    gains: [f32; N],
+    selected: usize,
+    rate: f32,
+}
+
+impl<const N: usize> Crossfader<N> {
+    /// Start with the first slot selected, fading between slots over `fade` samples.
+    pub fn new(fade: usize) -> Self {
+        let mut gains = [0.0; N];
+        gains[0] = 1.0;
+        Crossfader {
+            gains,
+            selected: 0,
+            rate: 1.0 / fade.max(1) as f32,
+        }
+    }
+
+    pub fn set_fade(&mut self, fade: usize) {
+        self.rate = 1.0 / fade.max(1) as f32;
+    }
+
+    /// Select a slot, wrapping around past the last one.
+    pub fn select(&mut self, slot: usize) {
+        self.selected = slot % N;
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Advance the fade by one sample and return the gain of each slot.
+    pub fn tick(&mut self) -> [f32; N] {
+        for (slot, gain) in self.gains.iter_mut().enumerate() {
+            if slot == self.selected {
+                *gain = (*gain + self.rate).min(1.0);
+            } else {
+                *gain = (*gain - self.rate).max(0.0);
+            }
+        }
+        self.gains
+    }
+
+    /// Advance the fade by one sample and mix the sources down with the current gains.
+    pub fn process(&mut self, inputs: [f32; N]) -> f32 {
+        self.tick().iter().zip(inputs).map(|(g, x)| g * x).sum()
+    }
+}
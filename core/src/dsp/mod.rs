@@ -1,2 +1,9 @@
+pub mod control;
 pub mod filters;
+pub mod logic;
+pub mod math;
+pub mod mix;
 pub mod oscillators;
+pub mod recorder;
+pub mod sample;
+pub mod triggers;
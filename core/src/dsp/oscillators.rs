@@ -1,30 +1,48 @@
 use core::{cmp::min, f32::consts::PI, mem};
 
 use fixed::types::I1F15;
-use libm::{ceilf, floorf, roundf, sinf};
+use libm::{ceilf, fabsf, floorf, roundf};
 use zerocopy::{AsBytes, FromBytes};
 
-use crate::{voct_to_frequency, SAMPLE_RATE};
+use super::math::sin2pi;
+use crate::{voct_to_frequency, SampleRate, Tuning, SAMPLE_RATE};
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone)]
 pub struct NaiveOscillator {
     level: f32,
     phase: f32,
+    sample_rate: f32,
+}
+
+impl Default for NaiveOscillator {
+    fn default() -> Self {
+        NaiveOscillator {
+            level: 0.0,
+            phase: 0.0,
+            sample_rate: SAMPLE_RATE,
+        }
+    }
 }
 
 impl NaiveOscillator {
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate.hz();
+    }
+
     pub fn process(
         &mut self,
         note: i16,
         level: i16,
         prange: f32,
         plevel: f32,
+        tuning: &Tuning,
+        notes: Option<&[f32; 128]>,
     ) -> (i16, i16, i16, i16) {
         self.level += 0.01 * (level as f32 - self.level);
 
         let a = self.level * plevel;
 
-        let sin = roundf(a * sinf(2.0 * PI * self.phase)) as i16;
+        let sin = roundf(a * sin2pi(self.phase)) as i16;
         let tri = roundf(if self.phase < 0.5 {
             a * (-1.0 + 4.0 * self.phase)
         } else {
@@ -33,7 +51,8 @@ impl NaiveOscillator {
         let saw = roundf(-a + 2.0 * a * self.phase) as i16;
         let sqr = roundf(if self.phase < 0.5 { a } else { -a }) as i16;
 
-        self.phase += voct_to_frequency(note as f32 + prange * 512.0) / SAMPLE_RATE;
+        self.phase +=
+            voct_to_frequency(note as f32 + prange * 512.0, tuning, notes) / self.sample_rate;
         while self.phase > 1.0 {
             self.phase -= 1.0;
         }
@@ -42,43 +61,60 @@ impl NaiveOscillator {
     }
 }
 
-#[derive(Copy, Clone, Default)]
+#[derive(Copy, Clone)]
 pub struct HarmOscillator {
     level: f32,
     phase: f32,
+    sample_rate: f32,
+}
+
+impl Default for HarmOscillator {
+    fn default() -> Self {
+        HarmOscillator {
+            level: 0.0,
+            phase: 0.0,
+            sample_rate: SAMPLE_RATE,
+        }
+    }
 }
 
 impl HarmOscillator {
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate.hz();
+    }
+
     pub fn process(
         &mut self,
         note: i16,
         level: i16,
         prange: f32,
         plevel: f32,
+        tuning: &Tuning,
+        notes: Option<&[f32; 128]>,
     ) -> (i16, i16, i16, i16) {
         self.level += 0.01 * (level as f32 - self.level);
 
         let a = self.level * plevel;
-        let freq = voct_to_frequency(note as f32 + prange * 512.0);
-        let sin = a * sinf(2.0 * PI * self.phase);
+        let freq = voct_to_frequency(note as f32 + prange * 512.0, tuning, notes);
+        let sin = a * sin2pi(self.phase);
         let mut tri = 0.0;
         let mut saw = 0.5;
         let mut sqr = 0.0;
-        let nend = min(floorf(SAMPLE_RATE / (2.0 * freq)) as u32, 100);
+        let nend = min(floorf(self.sample_rate / (2.0 * freq)) as u32, 100);
         for i in 1..nend {
             let n = i as f32;
             if i % 2 != 0 {
                 if ((i - 1) / 2) % 2 == 0 {
-                    tri += a * 8.0 / (PI * PI * n * n) * sinf(n * 2.0 * PI * self.phase);
+                    tri += a * 8.0 / (PI * PI * n * n) * sin2pi(n * self.phase);
                 } else {
-                    tri -= a * 8.0 / (PI * PI * n * n) * sinf(n * 2.0 * PI * self.phase);
+                    tri -= a * 8.0 / (PI * PI * n * n) * sin2pi(n * self.phase);
                 }
-                sqr += a * 4.0 / (PI * n) * sinf(n * 2.0 * PI * self.phase);
+                sqr += a * 4.0 / (PI * n) * sin2pi(n * self.phase);
             }
-            saw -= a / (PI * n) * sinf(n * 2.0 * PI * self.phase);
+            saw -= a / (PI * n) * sin2pi(n * self.phase);
         }
 
-        self.phase += freq / SAMPLE_RATE;
+        self.phase += freq / self.sample_rate;
         while self.phase > 1.0 {
             self.phase -= 1.0;
         }
@@ -98,6 +134,20 @@ impl HarmOscillator {
 pub struct WtOscillator {
     level: f32,
     phase: f32,
+    sample_rate: f32,
+    direction: f32,
+    sync_last: i16,
+    sync_prev: [f32; 4],
+    pulse_width: f32,
+}
+
+/// How an oscillator responds to a rising edge on its sync input.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum SyncMode {
+    /// Restart the waveform from the beginning
+    Hard,
+    /// Reverse the direction the waveform is played in
+    Soft,
 }
 
 // Safety: I'm not sure how to do this so that the precalculated arrays are loaded into static flash
@@ -157,40 +207,100 @@ struct WavetableFP {
     vals: [[I1F15; 2048]; 9],
 }
 
+/// Number of samples in a single cycle of a wavetable
+pub const WAVETABLE_SIZE: usize = 2048;
+
+/// A single-cycle waveform loaded at runtime, for instance through `Storage`.
+///
+/// Unlike the built-in tables, user tables are not band-limited, so strong high harmonics will
+/// alias on higher notes.
+#[derive(Clone, Debug)]
+pub struct UserWavetable {
+    pub samples: [i16; WAVETABLE_SIZE],
+}
+
+impl Default for UserWavetable {
+    fn default() -> Self {
+        UserWavetable {
+            samples: [0; WAVETABLE_SIZE],
+        }
+    }
+}
+
+/// Selection between the built-in and user wavetables for `WtOscillator::process_table`.
+#[derive(Clone, Copy)]
+pub enum Table<'a> {
+    Sin,
+    Tri,
+    Saw,
+    Sqr,
+    /// Pulse with the given fraction of the cycle high, made from the difference of two
+    /// band-limited saws
+    Pulse(f32),
+    User(&'a UserWavetable),
+}
+
+const MIN_PULSE_WIDTH: f32 = 0.02;
+
+/// Linear frequency modulation of `freq` by an audio rate `fm` input, scaled by `index`.
+///
+/// Deep enough modulation goes through zero into negative frequencies, which the `WtOscillator`
+/// handles by running its waveform backwards.
+pub fn linear_fm(freq: f32, fm: i16, index: f32) -> f32 {
+    freq * (1.0 + index * fm as f32 / i16::MAX as f32)
+}
+
+/// Band-limit level for a (possibly negative) frequency in Hz
+fn band_index(freq: f32) -> usize {
+    match fabsf(freq) {
+        f if f < 80.0 => 0,
+        f if f < 160.0 => 1,
+        f if f < 320.0 => 2,
+        f if f < 640.0 => 3,
+        f if f < 1280.0 => 4,
+        f if f < 2560.0 => 5,
+        f if f < 5120.0 => 6,
+        f if f < 10240.0 => 7,
+        _ => 8,
+    }
+}
+
 impl Default for WtOscillator {
     fn default() -> Self {
         WtOscillator {
             level: 0.0,
             phase: 0.0,
+            sample_rate: SAMPLE_RATE,
+            direction: 1.0,
+            sync_last: 0,
+            sync_prev: [0.0; 4],
+            pulse_width: 0.5,
         }
     }
 }
 
 impl WtOscillator {
+    /// Set the rate the oscillator is run at. The band-limited tables are chosen by frequency
+    /// alone, so at higher rates they are more conservative than needed.
+    pub fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate.hz();
+    }
+
     pub fn process(
         &mut self,
         note: i16,
         level: i16,
         prange: f32,
         plevel: f32,
+        tuning: &Tuning,
+        notes: Option<&[f32; 128]>,
     ) -> (i16, i16, i16, i16) {
         self.level += 0.01 * (level as f32 - self.level);
 
         let a = self.level * plevel;
-        let freq = voct_to_frequency(note as f32 + prange * 512.0);
-
-        let idx = match freq {
-            f if f < 40.0 => 0,
-            f if f < 80.0 => 0,
-            f if f < 160.0 => 1,
-            f if f < 320.0 => 2,
-            f if f < 640.0 => 3,
-            f if f < 1280.0 => 4,
-            f if f < 2560.0 => 5,
-            f if f < 5120.0 => 6,
-            f if f < 10240.0 => 7,
-            _ => 8,
-        };
+        let freq = voct_to_frequency(note as f32 + prange * 512.0, tuning, notes);
+
+        let idx = band_index(freq);
 
         let left = floorf(self.phase * 2048.0) as usize;
         let right = ceilf(self.phase * 2048.0) as usize % 2048;
@@ -201,10 +311,7 @@ impl WtOscillator {
         let saw = a * ((WTSAW).vals[idx][left] * (1.0 - frac) + (WTSAW).vals[idx][right] * frac);
         let sqr = a * ((WTSQR).vals[idx][left] * (1.0 - frac) + (WTSQR).vals[idx][right] * frac);
 
-        self.phase += freq / SAMPLE_RATE;
-        while self.phase >= 1.0 {
-            self.phase -= 1.0;
-        }
+        self.advance(freq);
         (
             roundf(sin) as i16,
             roundf(tri) as i16,
@@ -213,8 +320,174 @@ impl WtOscillator {
         )
     }
 
+    /// Set the fraction of the cycle that the square output is high, in place of a fixed 50%.
+    /// Only used by `process_sync` and `process_approx_fp`.
+    pub fn set_pulse_width(&mut self, width: f32) {
+        self.pulse_width = width.clamp(MIN_PULSE_WIDTH, 1.0 - MIN_PULSE_WIDTH);
+    }
+
+    fn square(&self) -> Table<'static> {
+        if self.pulse_width == 0.5 {
+            Table::Sqr
+        } else {
+            Table::Pulse(self.pulse_width)
+        }
+    }
+
+    /// Generate a single waveform from the selected table, where `amp` is the peak output level
+    /// and `freq` is in Hz.
+    pub fn process_table(&mut self, table: Table, amp: f32, freq: f32) -> i16 {
+        let out = self.lookup(table, freq);
+        self.advance(freq);
+        roundf(amp * out) as i16
+    }
+
+    /// Crossfade between two tables, with `position` going from 0 (only `a`) to 1 (only `b`).
+    ///
+    /// Both tables are read at the same band-limit level, so the harmonic content stays matched
+    /// throughout the sweep.
+    pub fn process_morph(&mut self, a: Table, b: Table, position: f32, amp: f32, freq: f32) -> i16 {
+        let position = position.clamp(0.0, 1.0);
+        let out = self.lookup(a, freq) * (1.0 - position) + self.lookup(b, freq) * position;
+        self.advance(freq);
+        roundf(amp * out) as i16
+    }
+
+    fn lookup(&self, table: Table, freq: f32) -> f32 {
+        self.lookup_at(table, freq, self.phase)
+    }
+
+    fn lookup_at(&self, table: Table, freq: f32, phase: f32) -> f32 {
+        let pos = phase * WAVETABLE_SIZE as f32;
+        let left = floorf(pos) as usize % WAVETABLE_SIZE;
+        let right = (left + 1) % WAVETABLE_SIZE;
+        let frac = pos - floorf(pos);
+
+        let (l, r) = match table {
+            Table::Pulse(width) => {
+                let width = width.clamp(MIN_PULSE_WIDTH, 1.0 - MIN_PULSE_WIDTH);
+                let shifted = phase + width;
+                return self.lookup_at(Table::Saw, freq, phase)
+                    - self.lookup_at(Table::Saw, freq, shifted - floorf(shifted))
+                    + 2.0 * width
+                    - 1.0;
+            }
+            Table::User(wt) => (
+                wt.samples[left] as f32 / i16::MAX as f32,
+                wt.samples[right] as f32 / i16::MAX as f32,
+            ),
+            _ => {
+                let wt = match table {
+                    Table::Sin => &WTSIN,
+                    Table::Tri => &WTTRI,
+                    Table::Saw => &WTSAW,
+                    _ => &WTSQR,
+                };
+                let idx = band_index(freq);
+                (wt.vals[idx][left], wt.vals[idx][right])
+            }
+        };
+        l * (1.0 - frac) + r * frac
+    }
+
+    fn advance(&mut self, freq: f32) {
+        self.phase += self.direction * freq / self.sample_rate;
+        while self.phase >= 1.0 {
+            self.phase -= 1.0;
+        }
+        while self.phase < 0.0 {
+            self.phase += 1.0;
+        }
+    }
+
+    /// Detect a rising edge on the sync input, returning how far into the last sample period it
+    /// happened (from 0 to 1).
+    fn sync_edge(&mut self, sync: i16) -> Option<f32> {
+        let last = self.sync_last;
+        self.sync_last = sync;
+        if last <= 0 && sync > 0 {
+            Some(last as f32 / (last as f32 - sync as f32))
+        } else {
+            None
+        }
+    }
+
+    /// Apply a sync input sample without any band-limiting, for use with the `process_approx`
+    /// functions. Returns whether a sync happened.
+    pub fn sync(&mut self, sync: i16, mode: SyncMode) -> bool {
+        if self.sync_edge(sync).is_none() {
+            return false;
+        }
+        match mode {
+            SyncMode::Hard => {
+                self.phase = 0.0;
+                self.direction = 1.0;
+            }
+            SyncMode::Soft => self.direction = -self.direction,
+        }
+        true
+    }
+
+    /// Same as `process`, but synchronized to rising edges on the `sync` input.
+    ///
+    /// The hard sync discontinuity is smoothed with a PolyBLEP on the samples to either side of
+    /// the edge, so the output is delayed by one sample. Soft sync only reverses the direction of
+    /// the oscillator, which keeps the output continuous.
+    pub fn process_sync(
+        &mut self,
+        amp: f32,
+        freq: f32,
+        sync: i16,
+        mode: SyncMode,
+    ) -> (i16, i16, i16, i16) {
+        let tables = [Table::Sin, Table::Tri, Table::Saw, self.square()];
+        let dphase = freq / self.sample_rate;
+
+        let mut out = [0.0; 4];
+        if let Some(t) = self.sync_edge(sync) {
+            // Phase at the moment of the edge, between the last sample and this one
+            let mut edge = self.phase - self.direction * (1.0 - t) * dphase;
+            edge -= floorf(edge);
+            match mode {
+                SyncMode::Hard => {
+                    self.phase = (1.0 - t) * dphase;
+                    self.phase -= floorf(self.phase);
+                    self.direction = 1.0;
+                    for (i, table) in tables.into_iter().enumerate() {
+                        let naive = self.lookup(table, freq);
+                        let h =
+                            self.lookup_at(table, freq, 0.0) - self.lookup_at(table, freq, edge);
+                        self.sync_prev[i] += h * (1.0 - t) * (1.0 - t) / 2.0;
+                        out[i] = naive - h * t * t / 2.0;
+                    }
+                }
+                SyncMode::Soft => {
+                    self.direction = -self.direction;
+                    self.phase = edge + self.direction * (1.0 - t) * dphase;
+                    self.phase -= floorf(self.phase);
+                    for (i, table) in tables.into_iter().enumerate() {
+                        out[i] = self.lookup(table, freq);
+                    }
+                }
+            }
+        } else {
+            for (i, table) in tables.into_iter().enumerate() {
+                out[i] = self.lookup(table, freq);
+            }
+        }
+        self.advance(freq);
+
+        let prev = core::mem::replace(&mut self.sync_prev, out);
+        (
+            roundf(amp * prev[0]) as i16,
+            roundf(amp * prev[1]) as i16,
+            roundf(amp * prev[2]) as i16,
+            roundf(amp * prev[3]) as i16,
+        )
+    }
+
     pub fn process_approx(&mut self, amp: f32, freq: f32) -> (i16, i16, i16, i16) {
-        let idx = match freq as u16 {
+        let idx = match fabsf(freq) as u16 {
             f if f < 40 => 0,
             f if f < 80 => 0,
             f if f < 160 => 1,
@@ -234,15 +507,18 @@ impl WtOscillator {
         let saw = amp * ((WTSAW).vals[idx][cen]);
         let sqr = amp * ((WTSQR).vals[idx][cen]);
 
-        self.phase += freq / SAMPLE_RATE * 2048.0;
+        self.phase += self.direction * freq / self.sample_rate * 2048.0;
         while self.phase >= 2048.0 {
             self.phase -= 2048.0;
         }
+        while self.phase < 0.0 {
+            self.phase += 2048.0;
+        }
         (sin as i16, tri as i16, saw as i16, sqr as i16)
     }
 
     pub fn process_approx_fp(&mut self, amp: i16, freq: f32) -> (i16, i16, i16, i16) {
-        let idx = match freq as u16 {
+        let idx = match fabsf(freq) as u16 {
             f if f < 40 => 0,
             f if f < 80 => 0,
             f if f < 160 => 1,
@@ -260,12 +536,23 @@ impl WtOscillator {
         // let sin = a * ((WTSINFP).vals[idx][cen]);
         let tri = a * ((WTTRIFP).vals[idx][cen]);
         let saw = a * ((WTSAWFP).vals[idx][cen]);
-        let sqr = a * ((WTSQRFP).vals[idx][cen]);
+        let sqr = if self.pulse_width == 0.5 {
+            a * ((WTSQRFP).vals[idx][cen])
+        } else {
+            let shifted = (cen + (self.pulse_width * 2048.0) as usize) % 2048;
+            let pulse = (WTSAWFP).vals[idx][cen].to_bits() as i32
+                - (WTSAWFP).vals[idx][shifted].to_bits() as i32
+                + ((2.0 * self.pulse_width - 1.0) * i16::MAX as f32) as i32;
+            I1F15::from_bits(((amp as i32 * pulse.clamp(-32768, 32767)) >> 15) as i16)
+        };
 
-        self.phase += freq / SAMPLE_RATE * 2048.0;
+        self.phase += self.direction * freq / self.sample_rate * 2048.0;
         while self.phase >= 2048.0 {
             self.phase -= 2048.0;
         }
+        while self.phase < 0.0 {
+            self.phase += 2048.0;
+        }
         (
             0, /*sin.to_bits()*/
             tri.to_bits(),
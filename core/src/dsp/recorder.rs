@@ -0,0 +1,281 @@
+//! Recording CV, such as a knob being turned, and looping it back in time with a clock.
+//!
+//! The recorder doesn't need to know the tempo up front: it measures the clock, and spreads the
+//! loop over however many steps its buffer holds. That lets the same engine run from a large heap
+//! buffer on the desktop and a small fixed one on a board, with the smaller buffer simply taking
+//! coarser steps that the smoothing hides.
+
+use libm::expf;
+
+use super::logic::{is_high, GateDetector};
+use crate::{SampleRate, BLOCK_SIZE};
+
+/// Clock pulses in a bar, with the clock running in quarter notes
+pub const BEATS_PER_BAR: u32 = 4;
+/// Samples per step when recording without a clock, which is one step per block
+pub const FREE_INTERVAL: u32 = BLOCK_SIZE as u32;
+/// Longest beat in seconds before the clock counts as stopped, which is 30 bpm
+pub const MAX_BEAT: f32 = 2.0;
+
+/// One sample of the jacks read by a `MotionRecorder`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RecorderInputs {
+    pub cv: i16,
+    /// Quarter note clock, whose first pulse is taken as the start of a bar
+    pub clock: i16,
+    /// A trigger arms a new recording, or ends one running without a clock
+    pub record: i16,
+    /// While high during playback, the input is written over the loop
+    pub overdub: i16,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecorderState {
+    /// Passing the input through
+    Idle,
+    /// Waiting for the start of the next bar to record
+    Armed,
+    Recording,
+    Playing,
+}
+
+/// Records an input for a number of bars of the clock and loops it back out.
+///
+/// Without a clock patched in, a recording runs from one trigger on the record input to the next
+/// (or until the buffer fills up), and loops at whatever length that was. With a clock the loop is
+/// started over on every downbeat so it stays in time as the tempo drifts.
+#[derive(Clone, Debug)]
+pub struct MotionRecorder<B> {
+    buffer: B,
+    state: RecorderState,
+    /// Steps recorded
+    len: usize,
+    /// Step being played or written
+    pos: usize,
+    /// Samples per step
+    interval: u32,
+    /// Samples into the current step
+    phase: u32,
+    clock: GateDetector,
+    record: GateDetector,
+    /// The last clock pulse, counted from the start of the bar while idle or of the loop otherwise
+    beat: u32,
+    since_clock: u32,
+    /// Samples between the last two clock pulses, or zero if there is no clock
+    period: u32,
+    out: f32,
+}
+
+/// A recorder that holds `N` steps in place, for boards without a heap.
+pub type BoundedRecorder<const N: usize> = MotionRecorder<[i16; N]>;
+
+impl<const N: usize> Default for BoundedRecorder<N> {
+    fn default() -> Self {
+        MotionRecorder::new([0; N])
+    }
+}
+
+/// A recorder with its steps on the heap, sized when it is made.
+#[cfg(feature = "std")]
+pub type HeapRecorder = MotionRecorder<Vec<i16>>;
+
+#[cfg(feature = "std")]
+impl HeapRecorder {
+    pub fn with_capacity(steps: usize) -> Self {
+        MotionRecorder::new(vec![0; steps])
+    }
+}
+
+impl<B: AsRef<[i16]> + AsMut<[i16]>> MotionRecorder<B> {
+    /// The buffer's length is how many steps a loop is spread over.
+    pub fn new(buffer: B) -> Self {
+        MotionRecorder {
+            buffer,
+            state: RecorderState::Idle,
+            len: 0,
+            pos: 0,
+            interval: FREE_INTERVAL,
+            phase: 0,
+            clock: Default::default(),
+            record: Default::default(),
+            // So that the first clock pulse is the downbeat
+            beat: BEATS_PER_BAR - 1,
+            since_clock: 0,
+            period: 0,
+            out: 0.0,
+        }
+    }
+
+    pub fn state(&self) -> RecorderState {
+        self.state
+    }
+
+    /// Forget the loop and go back to passing the input through.
+    pub fn clear(&mut self) {
+        self.state = RecorderState::Idle;
+        self.len = 0;
+        self.beat %= BEATS_PER_BAR;
+    }
+
+    /// `smoothing` is the time constant in seconds of the slew on the output, which rounds off
+    /// the steps of the recording.
+    pub fn process(
+        &mut self,
+        inputs: RecorderInputs,
+        bars: u32,
+        smoothing: f32,
+        sample_rate: SampleRate,
+    ) -> i16 {
+        let loop_beats = bars.max(1) * BEATS_PER_BAR;
+        let clock = self.clock.process(inputs.clock, sample_rate).rising;
+        let record = self.record.process(inputs.record, sample_rate).rising;
+        // A clock that has stopped no longer counts as patched in
+        let max_period = (MAX_BEAT * sample_rate.hz()) as u32;
+        if clock {
+            self.period = if self.since_clock <= max_period {
+                self.since_clock
+            } else {
+                0
+            };
+            self.since_clock = 0;
+        }
+        self.since_clock = self.since_clock.saturating_add(1);
+        if self.since_clock > max_period {
+            self.period = 0;
+        }
+
+        let mut target = inputs.cv as f32;
+        match self.state {
+            RecorderState::Idle | RecorderState::Armed => {
+                if clock {
+                    self.beat = (self.beat + 1) % BEATS_PER_BAR;
+                }
+                if record && self.state == RecorderState::Armed {
+                    self.state = RecorderState::Idle;
+                } else if record && self.period == 0 {
+                    self.start(FREE_INTERVAL);
+                } else if record {
+                    self.state = RecorderState::Armed;
+                }
+                if self.state == RecorderState::Armed && clock && self.beat == 0 {
+                    let samples = self.period as usize * loop_beats as usize;
+                    let capacity = self.buffer.as_ref().len().max(1);
+                    self.start(samples.div_ceil(capacity).max(1) as u32);
+                }
+            }
+            RecorderState::Recording => {
+                if clock && self.period > 0 {
+                    self.beat += 1;
+                }
+                if (record && self.period == 0) || (clock && self.beat >= loop_beats) {
+                    self.play();
+                }
+            }
+            RecorderState::Playing => {
+                if clock {
+                    self.beat = (self.beat + 1) % loop_beats;
+                    if self.beat == 0 {
+                        self.pos = 0;
+                        self.phase = 0;
+                    }
+                }
+                if record && self.period == 0 {
+                    self.start(FREE_INTERVAL);
+                } else if record {
+                    // The loop is whole bars, so this keeps the place in the bar
+                    self.state = RecorderState::Armed;
+                    self.beat %= BEATS_PER_BAR;
+                } else if is_high(inputs.overdub) {
+                    if self.phase == 0 {
+                        self.buffer.as_mut()[self.pos] = inputs.cv;
+                    }
+                } else {
+                    let buffer = self.buffer.as_ref();
+                    let from = buffer[self.pos] as f32;
+                    let to = buffer[(self.pos + 1) % self.len] as f32;
+                    target = from + (to - from) * self.phase as f32 / self.interval as f32;
+                }
+            }
+        }
+        if self.state == RecorderState::Recording && self.phase == 0 {
+            if self.len < self.buffer.as_ref().len() {
+                self.buffer.as_mut()[self.len] = inputs.cv;
+                self.len += 1;
+            } else {
+                self.play();
+            }
+        }
+        if matches!(
+            self.state,
+            RecorderState::Recording | RecorderState::Playing
+        ) {
+            self.phase += 1;
+            if self.phase >= self.interval {
+                self.phase = 0;
+                if self.state == RecorderState::Playing {
+                    // Without a clock to start it over, the loop wraps on its own
+                    self.pos = (self.pos + 1) % self.len;
+                }
+            }
+        }
+
+        let coeff = if smoothing > 0.0 {
+            1.0 - expf(-sample_rate.dt() / smoothing)
+        } else {
+            1.0
+        };
+        self.out += (target - self.out) * coeff;
+        self.out as i16
+    }
+
+    fn start(&mut self, interval: u32) {
+        self.state = RecorderState::Recording;
+        self.interval = interval;
+        self.len = 0;
+        self.phase = 0;
+        self.beat = 0;
+    }
+
+    fn play(&mut self) {
+        self.state = if self.len > 0 {
+            RecorderState::Playing
+        } else {
+            RecorderState::Idle
+        };
+        self.pos = 0;
+        self.phase = 0;
+        self.beat = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::logic::GATE_HIGH;
+
+    #[test]
+    fn loops_a_bar_of_the_clock() {
+        let rate = SampleRate::default();
+        let mut recorder = BoundedRecorder::<64>::default();
+        let beat = 480;
+        let bar = 4 * beat;
+        for i in 0..4 * bar {
+            let inputs = RecorderInputs {
+                // A ramp a bar long, which is only there to be heard while recording
+                cv: if i < 2 * bar { (i % bar) as i16 } else { -1000 },
+                clock: if i % beat < 10 { GATE_HIGH } else { 0 },
+                record: if i == beat + 100 { GATE_HIGH } else { 0 },
+                overdub: 0,
+            };
+            let out = recorder.process(inputs, 1, 0.0, rate);
+            // Armed during the first bar, so it records the second and plays back from the third
+            if i == bar + 1 {
+                assert_eq!(recorder.state(), RecorderState::Recording);
+            }
+            if i > 2 * bar && i < 3 * bar - 100 {
+                assert_eq!(recorder.state(), RecorderState::Playing);
+                assert!((out as i32 - i % bar).abs() <= 1, "{} at {}", out, i);
+            }
+        }
+    }
+}
@@ -0,0 +1,173 @@
+//! Converting samples between the `i16` sent over the network and the `f32` that most
+//! processing is done in.
+//!
+//! Every jack carries `SampleType` samples, and what full scale means depends on the signal:
+//!
+//! - Audio is centered on 0, and full scale (`i16::MAX`) reads as 1.0 after `to_f32`. Anything
+//!   louder has to be clipped on the way back, preferably with `from_f32_soft` so that it
+//!   squashes rather than buzzes.
+//! - Pitch CV is `SEMITONE` steps per semitone, with MIDI note 64 at 0, as in
+//!   `midi_note_to_voct`. It spans a little over five octaves each way and shouldn't go through
+//!   these conversions; use `semitones_to_sample` and `sample_to_semitones` instead.
+//! - Gates and triggers are high at `GATE_HIGH` and read as high from `GATE_THRESHOLD` up, as
+//!   in `dsp::logic`.
+//! - Other CV, like modulation or a knob sent over a jack, is scaled like audio: full scale is
+//!   1.0, and unipolar CV stays between 0 and 1.
+//!
+//! Which of these a jack carries is its `SignalLevel`, given with its name in `define_module!`.
+//!
+//! The block converters take a headroom in dB, which is where 1.0 sits below full scale. An
+//! engine that sums several inputs or resonates can work with 1.0 as its nominal level and still
+//! have room above it before the output clips.
+
+use super::logic::{GATE_HIGH, GATE_THRESHOLD};
+use crate::{softclip, AudioPacket, SampleType, BLOCK_SIZE, CHANNELS};
+
+/// Steps of pitch CV in a semitone
+pub const SEMITONE: i16 = 512;
+/// Steps of pitch CV in an octave
+pub const OCTAVE: i16 = 12 * SEMITONE;
+
+/// What a jack carries, and so what its samples mean. Patching between jacks of different levels
+/// works, but usually isn't what was meant, such as audio into a pitch input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum SignalLevel {
+    /// Centered on 0, with full scale at 1.0
+    #[default]
+    Audio,
+    /// `SEMITONE` steps per semitone, with MIDI note 64 at 0
+    Pitch,
+    /// High at `GATE_HIGH`, and read as high from `GATE_THRESHOLD` up
+    Gate,
+    /// Modulation from -1.0 to 1.0 of full scale
+    Bipolar,
+    /// Modulation from 0 to 1.0 of full scale
+    Unipolar,
+}
+
+impl SignalLevel {
+    /// The lowest and highest samples a source of this level is expected to send.
+    pub fn range(self) -> (i16, i16) {
+        match self {
+            SignalLevel::Audio | SignalLevel::Bipolar => (-i16::MAX, i16::MAX),
+            SignalLevel::Pitch => (i16::MIN, i16::MAX),
+            SignalLevel::Gate => (0, GATE_HIGH),
+            SignalLevel::Unipolar => (0, i16::MAX),
+        }
+    }
+
+    /// Whether a sample of this level reads as high, for inputs that take a gate from anything.
+    /// Only gates have a threshold of their own; everything else is high above zero.
+    pub fn is_high(self, x: i16) -> bool {
+        match self {
+            SignalLevel::Gate => x >= GATE_THRESHOLD,
+            _ => x > 0,
+        }
+    }
+}
+
+/// The pitch CV for an offset of `semitones` from MIDI note 64, saturating at the ends of the
+/// range.
+pub fn semitones_to_sample(semitones: f32) -> i16 {
+    (semitones * SEMITONE as f32) as i16
+}
+
+/// The offset in semitones from MIDI note 64 of a pitch CV sample.
+pub fn sample_to_semitones(x: i16) -> f32 {
+    x as f32 / SEMITONE as f32
+}
+
+/// Conversions between a network sample and `f32`, where full scale is 1.0.
+pub trait Sample: Copy {
+    fn to_f32(self) -> f32;
+    /// The sample for `x`, saturating at full scale.
+    fn from_f32_clipped(x: f32) -> Self;
+    /// The sample for `x`, bent over by `softclip` as it approaches full scale.
+    fn from_f32_soft(x: f32) -> Self;
+}
+
+impl Sample for i16 {
+    fn to_f32(self) -> f32 {
+        self as f32 / i16::MAX as f32
+    }
+
+    fn from_f32_clipped(x: f32) -> Self {
+        (x.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+    }
+
+    fn from_f32_soft(x: f32) -> Self {
+        Self::from_f32_clipped(softclip(x))
+    }
+}
+
+/// The gain for a level in dB, so that -6 is about half.
+pub fn db_to_gain(db: f32) -> f32 {
+    libm::powf(10.0, db / 20.0)
+}
+
+/// The level in dB of a gain, the inverse of `db_to_gain`.
+pub fn gain_to_db(gain: f32) -> f32 {
+    20.0 * libm::log10f(gain)
+}
+
+/// Convert a whole packet to `f32`, with full scale reading `headroom` dB above 1.0.
+pub fn packet_to_f32(packet: &AudioPacket, headroom: f32) -> [[f32; CHANNELS]; BLOCK_SIZE] {
+    let gain = db_to_gain(headroom);
+    packet
+        .data
+        .map(|frame| frame.data.map(|x| x.to_f32() * gain))
+}
+
+/// Convert `block` back into `packet`, the inverse of `packet_to_f32` with the same `headroom`.
+/// Samples beyond full scale are soft clipped.
+pub fn packet_from_f32(
+    block: &[[f32; CHANNELS]; BLOCK_SIZE],
+    headroom: f32,
+    packet: &mut AudioPacket,
+) {
+    let gain = db_to_gain(-headroom);
+    for (frame, samples) in packet.data.iter_mut().zip(block) {
+        for (y, x) in frame.data.iter_mut().zip(samples) {
+            *y = SampleType::from_f32_soft(x * gain);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_scale_is_one() {
+        assert_eq!(i16::MAX.to_f32(), 1.0);
+        assert_eq!(i16::from_f32_clipped(1.0), i16::MAX);
+        assert_eq!(i16::from_f32_clipped(-4.0), -i16::MAX);
+        assert_eq!(i16::from_f32_clipped(0.5), i16::MAX / 2);
+        assert!(i16::from_f32_soft(0.5) < i16::MAX / 2);
+        assert_eq!(i16::from_f32_soft(10.0), i16::MAX);
+    }
+
+    #[test]
+    fn headroom_round_trips() {
+        let packet = AudioPacket::splat(8000);
+        let block = packet_to_f32(&packet, 6.0);
+        assert!((block[0][0] - 2.0 * 8000.0 / i16::MAX as f32).abs() < 0.01);
+
+        let mut back = AudioPacket::splat(0);
+        packet_from_f32(&block, 6.0, &mut back);
+        // Soft clipping bends the level a little even this far below full scale
+        assert!((back.data[0].data[0] - 8000).abs() < 400);
+        assert!((gain_to_db(db_to_gain(-12.0)) + 12.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn pitch_is_in_semitones() {
+        assert_eq!(semitones_to_sample(12.0), OCTAVE);
+        assert_eq!(semitones_to_sample(-0.5), -SEMITONE / 2);
+        assert_eq!(semitones_to_sample(100.0), i16::MAX);
+        assert_eq!(sample_to_semitones(crate::midi_note_to_voct(69)), 5.0);
+        assert!(SignalLevel::Gate.is_high(GATE_THRESHOLD));
+        assert!(!SignalLevel::Gate.is_high(GATE_THRESHOLD - 1));
+        assert!(SignalLevel::Unipolar.is_high(1));
+    }
+}
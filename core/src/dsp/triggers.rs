@@ -0,0 +1,252 @@
+//! Trigger processing for rhythm patches: bursts, delays and chance, along with converting between
+//! gates and triggers.
+//!
+//! Triggers are read as the rising edge of a gate (see `logic::is_high`), and sent as short
+//! gates of `TRIGGER_LENGTH`.
+
+use super::logic::{gate, is_high};
+use crate::SampleRate;
+
+/// Length of a trigger sent, in seconds
+pub const TRIGGER_LENGTH: f32 = 0.001;
+/// Triggers a `TriggerDelay` can hold on to at once
+pub const MAX_PENDING: usize = 8;
+
+/// Sends a fixed number of triggers at a steady rate after each input trigger, like a drum roll
+/// or a ratchet.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Burst {
+    last: bool,
+    remaining: u32,
+    /// Seconds until the next trigger of the burst
+    until_next: f32,
+    /// Seconds left of the trigger being sent
+    pulse: f32,
+}
+
+impl Burst {
+    /// `count` triggers `1 / rate` seconds apart, the first right away. A trigger during a burst
+    /// starts it over.
+    pub fn process(&mut self, trigger: i16, count: u32, rate: f32, sample_rate: SampleRate) -> i16 {
+        let high = is_high(trigger);
+        if high && !self.last {
+            self.remaining = count;
+            self.until_next = 0.0;
+        }
+        self.last = high;
+        let period = 1.0 / rate.max(f32::MIN_POSITIVE);
+        if self.remaining > 0 && self.until_next <= 0.0 {
+            self.remaining -= 1;
+            self.until_next += period;
+            // Keep triggers apart even when the rate is faster than they are long
+            self.pulse = TRIGGER_LENGTH.min(period / 2.0);
+        }
+        let out = gate(self.pulse > 0.0);
+        self.pulse -= sample_rate.dt();
+        self.until_next -= sample_rate.dt();
+        out
+    }
+}
+
+/// Sends each input trigger again after a delay. Triggers that arrive while `MAX_PENDING` are
+/// still waiting are dropped.
+#[derive(Clone, Debug, Default)]
+pub struct TriggerDelay {
+    last: bool,
+    /// Seconds until each waiting trigger is sent
+    pending: heapless::Vec<f32, MAX_PENDING>,
+    pulse: f32,
+}
+
+impl TriggerDelay {
+    pub fn process(&mut self, trigger: i16, delay: f32, sample_rate: SampleRate) -> i16 {
+        let high = is_high(trigger);
+        if high && !self.last {
+            let _ = self.pending.push(delay.max(0.0));
+        }
+        self.last = high;
+        let due = self.pending.iter().any(|t| *t <= 0.0);
+        self.pending.retain(|t| *t > 0.0);
+        if due {
+            self.pulse = TRIGGER_LENGTH;
+        }
+        for t in self.pending.iter_mut() {
+            *t -= sample_rate.dt();
+        }
+        let out = gate(self.pulse > 0.0);
+        self.pulse -= sample_rate.dt();
+        out
+    }
+}
+
+/// Lets each input gate through with a given probability, decided on its rising edge, and
+/// blocks it otherwise.
+#[derive(Clone, Copy, Debug)]
+pub struct Chance {
+    last: bool,
+    passing: bool,
+    /// xorshift32 state, which is never zero
+    state: u32,
+}
+
+impl Default for Chance {
+    fn default() -> Self {
+        Chance::new(0x9e37_79b9)
+    }
+}
+
+impl Chance {
+    /// Channels that should roll independently need different seeds.
+    pub fn new(seed: u32) -> Self {
+        Chance {
+            last: false,
+            passing: false,
+            state: seed.max(1),
+        }
+    }
+
+    pub fn process(&mut self, trigger: i16, probability: f32) -> i16 {
+        let high = is_high(trigger);
+        if high && !self.last {
+            self.passing = self.next_unit() < probability;
+        }
+        self.last = high;
+        gate(high && self.passing)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_unit(&mut self) -> f32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        (self.state >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Turns the rising edge of a gate into a trigger of a set length.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EdgeTrigger {
+    last: bool,
+    pulse: f32,
+}
+
+impl EdgeTrigger {
+    /// `length` is in seconds, and cut short if the next edge arrives first.
+    pub fn process(&mut self, gate_in: i16, length: f32, sample_rate: SampleRate) -> i16 {
+        let high = is_high(gate_in);
+        if high && !self.last {
+            self.pulse = length;
+        }
+        self.last = high;
+        let out = gate(self.pulse > 0.0);
+        self.pulse -= sample_rate.dt();
+        out
+    }
+}
+
+/// Turns each trigger into a gate of a set length, however long the trigger was. A trigger while
+/// the gate is open holds it open for another `length`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GateStretcher {
+    last: bool,
+    /// Seconds left of the gate
+    remaining: f32,
+}
+
+impl GateStretcher {
+    pub fn process(&mut self, trigger: i16, length: f32, sample_rate: SampleRate) -> i16 {
+        let high = is_high(trigger);
+        if high && !self.last {
+            self.remaining = length;
+        }
+        self.last = high;
+        let out = gate(self.remaining > 0.0);
+        self.remaining -= sample_rate.dt();
+        out
+    }
+}
+
+/// Passes a gate through, but holds it open for at least `length` so that short triggers still
+/// open envelopes fully.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct MinimumGate {
+    last: bool,
+    /// Seconds left before the gate may close
+    remaining: f32,
+}
+
+impl MinimumGate {
+    pub fn process(&mut self, gate_in: i16, length: f32, sample_rate: SampleRate) -> i16 {
+        let high = is_high(gate_in);
+        if high && !self.last {
+            self.remaining = length;
+        }
+        self.last = high;
+        let out = gate(high || self.remaining > 0.0);
+        self.remaining -= sample_rate.dt();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dsp::logic::GATE_HIGH;
+
+    /// Count rising edges in one second of `f` fed a single trigger at the start.
+    fn count_triggers(mut f: impl FnMut(i16) -> i16) -> usize {
+        let mut last = 0;
+        let mut count = 0;
+        for i in 0..48000 {
+            let out = f(if i < 48 { GATE_HIGH } else { 0 });
+            if out != 0 && last == 0 {
+                count += 1;
+            }
+            last = out;
+        }
+        count
+    }
+
+    #[test]
+    fn burst_sends_count_triggers() {
+        let mut burst = Burst::default();
+        let rate = SampleRate::default();
+        assert_eq!(count_triggers(|x| burst.process(x, 5, 20.0, rate)), 5);
+    }
+
+    #[test]
+    fn delay_and_chance_pass_single_triggers() {
+        let mut delay = TriggerDelay::default();
+        let rate = SampleRate::default();
+        assert_eq!(count_triggers(|x| delay.process(x, 0.25, rate)), 1);
+        let mut always = Chance::default();
+        assert_eq!(count_triggers(|x| always.process(x, 1.0)), 1);
+        let mut never = Chance::default();
+        assert_eq!(count_triggers(|x| never.process(x, 0.0)), 0);
+    }
+
+    /// Samples high in one second of `f` fed a gate of `length` samples at the start.
+    fn high_samples(length: usize, mut f: impl FnMut(i16) -> i16) -> usize {
+        (0..48000)
+            .filter(|i| f(if *i < length { GATE_HIGH } else { 0 }) != 0)
+            .count()
+    }
+
+    #[test]
+    fn gates_and_triggers_have_set_lengths() {
+        // Lengths are counted down in floating point, so allow a sample either way
+        let near = |n: usize, expected: usize| n.abs_diff(expected) <= 1;
+        let rate = SampleRate::default();
+        let mut edge = EdgeTrigger::default();
+        let n = high_samples(4800, |x| edge.process(x, 0.001, rate));
+        assert!(near(n, 48));
+        let mut stretch = GateStretcher::default();
+        let n = high_samples(48, |x| stretch.process(x, 0.1, rate));
+        assert!(near(n, 4800));
+        let mut min = MinimumGate::default();
+        let n = high_samples(48, |x| min.process(x, 0.1, rate));
+        assert!(near(n, 4800));
+        let mut min = MinimumGate::default();
+        assert_eq!(high_samples(9600, |x| min.process(x, 0.1, rate)), 9600);
+    }
+}
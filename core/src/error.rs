@@ -0,0 +1,154 @@
+/// Errors returned by a `Module` and its network backend.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Error {
+    General,
+    /// A network operation failed on a particular socket
+    Network(SocketId, NetworkError),
+    /// Nothing was available to receive
+    NoData,
+    /// A jack id was out of range for the module
+    InvalidJackId(usize),
+    Parse(ParseError),
+    /// A fixed-size buffer or table has no room left
+    StorageFull,
+    /// A module can't run at this sample rate, in Hz
+    UnsupportedSampleRate(u32),
+    /// Some of a module's jacks were never added, leaving this many inputs and outputs unused
+    UnallocatedJacks {
+        inputs: usize,
+        outputs: usize,
+    },
+}
+
+/// The socket of the network backend that an error happened on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SocketId {
+    /// The network interface itself, rather than a particular socket
+    Interface,
+    /// The patch management directive socket
+    Directive,
+    Input(usize),
+    Output(usize),
+    /// The unicast socket for transfers too large for a directive
+    Control,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkError {
+    /// The interface does not have an address yet
+    Unconfigured,
+    /// The socket has no buffer space left
+    Exhausted,
+    /// The other end of the socket has gone away
+    Disconnected,
+    /// A received packet did not fit in the buffer
+    Truncated,
+    /// No usable network interface was found
+    Unavailable,
+    /// Multicast sent from this host never came back to it
+    NoLoopback,
+    /// The backend doesn't have this kind of socket
+    Unsupported,
+    #[cfg(feature = "std")]
+    Io(std::io::ErrorKind),
+    #[cfg(feature = "network-smoltcp")]
+    Smoltcp(smoltcp::Error),
+}
+
+// Written out rather than derived, since `std::io::ErrorKind` can only be logged through `Debug`
+#[cfg(feature = "defmt")]
+impl defmt::Format for NetworkError {
+    fn format(&self, f: defmt::Formatter) {
+        match self {
+            NetworkError::Unconfigured => defmt::write!(f, "Unconfigured"),
+            NetworkError::Exhausted => defmt::write!(f, "Exhausted"),
+            NetworkError::Disconnected => defmt::write!(f, "Disconnected"),
+            NetworkError::Truncated => defmt::write!(f, "Truncated"),
+            NetworkError::Unavailable => defmt::write!(f, "Unavailable"),
+            NetworkError::NoLoopback => defmt::write!(f, "NoLoopback"),
+            NetworkError::Unsupported => defmt::write!(f, "Unsupported"),
+            #[cfg(feature = "std")]
+            NetworkError::Io(kind) => defmt::write!(f, "Io({})", defmt::Debug2Format(kind)),
+            #[cfg(feature = "network-smoltcp")]
+            NetworkError::Smoltcp(e) => defmt::write!(f, "Smoltcp({})", e),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum ParseError {
+    /// A directive could not be serialized or deserialized
+    Postcard(postcard::Error),
+    /// A network address or subnet was malformed
+    Address,
+    /// A Scala scale or keyboard mapping was malformed
+    Scala,
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::General => write!(f, "general error"),
+            Error::Network(socket, e) => write!(f, "network error on {}: {}", socket, e),
+            Error::NoData => write!(f, "no data available"),
+            Error::InvalidJackId(id) => write!(f, "invalid jack id {}", id),
+            Error::Parse(e) => write!(f, "parse error: {}", e),
+            Error::StorageFull => write!(f, "storage full"),
+            Error::UnsupportedSampleRate(rate) => write!(f, "unsupported sample rate {} Hz", rate),
+            Error::UnallocatedJacks { inputs, outputs } => write!(
+                f,
+                "{} input and {} output jacks never added",
+                inputs, outputs
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for SocketId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SocketId::Interface => write!(f, "interface"),
+            SocketId::Directive => write!(f, "directive socket"),
+            SocketId::Input(id) => write!(f, "input jack {}", id),
+            SocketId::Output(id) => write!(f, "output jack {}", id),
+            SocketId::Control => write!(f, "control socket"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NetworkError::Unconfigured => write!(f, "no address configured"),
+            NetworkError::Exhausted => write!(f, "buffer exhausted"),
+            NetworkError::Disconnected => write!(f, "disconnected"),
+            NetworkError::Truncated => write!(f, "packet truncated"),
+            NetworkError::Unavailable => write!(f, "no usable interface"),
+            NetworkError::NoLoopback => write!(f, "multicast not received back"),
+            NetworkError::Unsupported => write!(f, "not supported by this backend"),
+            NetworkError::Io(kind) => write!(f, "{}", kind),
+            #[cfg(feature = "network-smoltcp")]
+            NetworkError::Smoltcp(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::Postcard(e) => write!(f, "{}", e),
+            ParseError::Address => write!(f, "malformed address"),
+            ParseError::Scala => write!(f, "malformed Scala file"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for Error {}
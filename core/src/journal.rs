@@ -0,0 +1,167 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{topology::Topology, PatchConnection, Uuid};
+
+/// Records appended before the journal is compacted down to the live patch
+const COMPACT_AFTER: usize = 256;
+/// Largest encoded record, which holds two uuids and two jack ids
+const MAX_RECORD: usize = 256;
+
+/// An append-only record on disk of every connection made on the network, kept by a desktop
+/// module standing in as the leader (see `Module::set_journal`).
+///
+/// Each acknowledged connection is appended and synced before the next poll, so that a crash
+/// loses at most the connection being made. A connection replaces any other to the same input
+/// jack, and the file is compacted down to the connections still live by writing them out to a
+/// new snapshot and renaming it over the journal. A record torn by a crash is dropped when the
+/// journal is opened again.
+pub struct PatchJournal {
+    path: PathBuf,
+    file: File,
+    live: Vec<PatchConnection>,
+    appended: usize,
+    /// Connections from the journal not yet acknowledged since it was opened
+    restoring: Vec<PatchConnection>,
+}
+
+impl PatchJournal {
+    /// Open the journal at `path`, creating it if needed. Connections already in it are
+    /// restored by the module it is handed to.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_owned();
+        let live = match File::open(&path) {
+            Ok(mut file) => {
+                let mut bytes = vec![];
+                file.read_to_end(&mut bytes)?;
+                replay(&bytes)
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => vec![],
+            Err(e) => return Err(e),
+        };
+        let file = compact(&path, &live)?;
+        Ok(PatchJournal {
+            path,
+            file,
+            restoring: live.clone(),
+            live,
+            appended: 0,
+        })
+    }
+
+    /// Number of live connections in the journal.
+    pub fn len(&self) -> usize {
+        self.live.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.live.is_empty()
+    }
+
+    /// The live patch as a graph, such as to export with `Topology::to_dot`.
+    pub fn topology(&self) -> Topology {
+        Topology::new(&self.live)
+    }
+
+    /// The live patch, as far as the journal has seen it.
+    pub(crate) fn connections(&self) -> &[PatchConnection] {
+        &self.live
+    }
+
+    /// Forget every connection, such as after the whole patch has been torn down on purpose.
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.live.clear();
+        self.restoring.clear();
+        self.file = compact(&self.path, &self.live)?;
+        self.appended = 0;
+        Ok(())
+    }
+
+    /// Note an acknowledged connection, appending it unless the journal already has it.
+    pub(crate) fn record(&mut self, connection: &PatchConnection) -> io::Result<()> {
+        self.restoring.retain(|c| !same_input(c, connection));
+        if self.live.contains(connection) {
+            return Ok(());
+        }
+        self.live.retain(|c| !same_input(c, connection));
+        self.live.push(connection.clone());
+        if self.appended >= COMPACT_AFTER {
+            self.file = compact(&self.path, &self.live)?;
+            self.appended = 0;
+            return Ok(());
+        }
+        self.file.write_all(&encode(connection)?)?;
+        self.file.sync_data()?;
+        self.appended += 1;
+        Ok(())
+    }
+
+    /// Connections still to be restored whose modules are both on the network.
+    pub(crate) fn restorable<'a>(
+        &'a self,
+        hosts: &'a [&'a Uuid],
+    ) -> impl Iterator<Item = &'a PatchConnection> + 'a {
+        self.restoring
+            .iter()
+            .filter(move |c| hosts.contains(&&c.input_uuid) && hosts.contains(&&c.output_uuid))
+    }
+
+    /// Connections still to be restored from output jack `jack_id` of module `uuid`.
+    pub(crate) fn restoring_from<'a>(
+        &'a self,
+        uuid: &'a Uuid,
+        jack_id: u32,
+    ) -> impl Iterator<Item = &'a PatchConnection> + 'a {
+        self.restoring
+            .iter()
+            .filter(move |c| &c.output_uuid == uuid && c.output_jack_id == jack_id)
+    }
+}
+
+fn same_input(a: &PatchConnection, b: &PatchConnection) -> bool {
+    a.input_uuid == b.input_uuid && a.input_jack_id == b.input_jack_id
+}
+
+/// A record is its length as two little endian bytes, followed by the connection in postcard.
+fn encode(connection: &PatchConnection) -> io::Result<Vec<u8>> {
+    let mut buf = [0; MAX_RECORD];
+    let bytes = postcard::to_slice(connection, &mut buf)
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "connection too large"))?;
+    let mut record = (bytes.len() as u16).to_le_bytes().to_vec();
+    record.extend_from_slice(bytes);
+    Ok(record)
+}
+
+/// The live patch from a journal's records, stopping at the first one that is incomplete.
+fn replay(mut bytes: &[u8]) -> Vec<PatchConnection> {
+    let mut live: Vec<PatchConnection> = vec![];
+    while bytes.len() >= 2 {
+        let len = u16::from_le_bytes([bytes[0], bytes[1]]) as usize;
+        let connection = match bytes.get(2..2 + len).map(postcard::from_bytes) {
+            Some(Ok(connection)) => connection,
+            _ => {
+                info!("Dropping torn patch journal record");
+                break;
+            }
+        };
+        live.retain(|c| !same_input(c, &connection));
+        live.push(connection);
+        bytes = &bytes[2 + len..];
+    }
+    live
+}
+
+/// Replace the journal with a snapshot of `live`, and open it to append to.
+fn compact(path: &Path, live: &[PatchConnection]) -> io::Result<File> {
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)?;
+    for connection in live {
+        file.write_all(&encode(connection)?)?;
+    }
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    OpenOptions::new().append(true).open(path)
+}
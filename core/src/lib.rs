@@ -3,22 +3,43 @@
 #[cfg(not(any(
     feature = "network-smoltcp",
     feature = "network-native",
-    feature = "network-local"
+    feature = "network-local",
+    feature = "network-ipc"
 )))]
-compile_error!("You must enable exactly one network feature");
+compile_error!("You must enable at least one network feature");
 
 #[cfg(all(
     feature = "network-smoltcp",
     feature = "network-native",
     feature = "network-local"
 ))]
-compile_error!("You must enable exactly one network feature");
+compile_error!("network-smoltcp, network-native and network-local can't all be enabled at once");
 
 #[macro_use]
 extern crate log;
 
+mod activity;
+#[cfg(feature = "async")]
+pub mod async_module;
+mod audit;
+mod bandwidth;
+mod bulk;
+mod channel_map;
+mod error;
+#[cfg(feature = "std")]
+pub mod journal;
 // mod leader_election;
+mod module_spec;
+mod overrun;
 mod ping_patch;
+mod seeded_rng;
+#[cfg(feature = "std")]
+mod solo;
+mod storage;
+pub mod time;
+#[cfg(feature = "std")]
+pub mod topology;
+pub mod tuning;
 
 #[cfg(feature = "network-native")]
 pub mod socket_native;
@@ -29,21 +50,57 @@ pub mod socket_smoltcp;
 #[cfg(feature = "network-local")]
 pub mod socket_local;
 
+#[cfg(all(feature = "network-ipc", unix))]
+pub mod socket_ipc;
+#[cfg(all(feature = "network-ipc", not(unix)))]
+compile_error!("network-ipc needs Unix domain sockets");
+
 #[macro_use]
 extern crate lazy_static;
 
 pub mod dsp;
+pub mod plugin;
 
-use core::{marker::PhantomData, mem};
+use core::{array, cmp::Ordering, marker::PhantomData, mem, ops::Index, slice};
 
+use activity::Activity;
+pub use activity::{IDLE_BLOCKS, IDLE_LEVEL};
+use audit::{Audit, Digests};
+pub use audit::{AuditMismatch, AuditReport};
+use bandwidth::{Bandwidth, Rejoin};
+use bulk::Bulk;
+pub use bulk::{ControlAddr, Transfer, TransferKind, TransferStatus, MAX_TRANSFER_SIZE};
+pub use channel_map::ChannelMap;
+use dsp::oscillators::UserWavetable;
 use heapless::String;
 // use leader_election::LeaderElection;
+pub use error::{Error, NetworkError, ParseError, SocketId};
+pub use module_spec::{
+    knob_position, knob_value, Jacks, ModuleDescription, ModuleSpec, ParamDescription, ParamSpec,
+    LABEL_LEN, MAX_DESCRIBED_PARAMS,
+};
+use overrun::Overrun;
 use palette::{Hsv, IntoColor, Srgb};
 use ping_patch::PingPatch;
 use rand_core::RngCore;
+pub use seeded_rng::SeededRng;
 use serde::{Deserialize, Serialize};
+pub use storage::{
+    ParamPreset, RamStorage, Storage, MAX_PRESET_PARAMS, PRESET_NAME_LEN, PRESET_SLOTS,
+};
+use time::{Clock, Duration, ManualClock, MonotonicTime, Ticker};
+pub use tuning::{ScaleTable, Tuning};
 use zerocopy::{AsBytes, FromBytes};
 
+#[cfg(all(feature = "channels-1", feature = "channels-16"))]
+compile_error!("Only one of the `channels-1` and `channels-16` features can be enabled");
+
+/// Number of polyphonic voices carried by each audio jack, selected by the `channels-*` features.
+#[cfg(feature = "channels-1")]
+pub const CHANNELS: usize = 1;
+#[cfg(feature = "channels-16")]
+pub const CHANNELS: usize = 16;
+#[cfg(not(any(feature = "channels-1", feature = "channels-16")))]
 pub const CHANNELS: usize = 8;
 pub const BLOCK_SIZE: usize = 48;
 pub type SampleType = i16;
@@ -51,42 +108,159 @@ pub type SampleType = i16;
 #[cfg(feature = "network-native")]
 const PREFERRED_SUBNET: &str = "10.0.0.0/8";
 
+/// Size of the receive buffer a backend needs for a single jack packet. At 16 channels an
+/// `AudioPacket` no longer fits in a standard 1500 byte MTU and is fragmented on the wire.
+#[cfg(feature = "std")]
+const JACK_BUFFER_SIZE: usize = if mem::size_of::<AudioPacket>() > 1500 {
+    mem::size_of::<AudioPacket>()
+} else {
+    1500
+};
+
 const PATCH_EP: &str = "239.0.0.0:19874";
 const JACK_PORT: u16 = 19991;
+/// Unicast port for transfers too large for a directive, on backends with only one module at each
+/// address. Modules sharing a host have the operating system pick a port instead.
+#[cfg(any(feature = "network-local", feature = "network-smoltcp"))]
+const CONTROL_PORT: u16 = 19875;
 
+const PROBE_TIMEOUT: i64 = 5000; // ms
+const MAX_MONITORS: usize = 4;
+const SILENCE_HOLDOFF: u8 = 4; // blocks
+const MAX_ALIGN_DELAY: usize = 3; // blocks
+const KEEPALIVE_INTERVAL: i64 = 100; // ms
+const SUBSCRIBE_INTERVAL: i64 = 1000; // ms
+const SUBSCRIBE_TIMEOUT: i64 = 3 * SUBSCRIBE_INTERVAL;
+const ACK_TIMEOUT: i64 = 20; // ms
+const MAX_CONNECT_RETRIES: u8 = 4;
+const MAX_CONNECTIONS: usize = 16;
+const MAX_BULK_CONNECTIONS: usize = 8;
+const WAVETABLE_CHUNK: usize = 256; // samples
+/// Self-addressed directives waiting to be handled, kept small as each takes the room of the
+/// largest directive. Sending any more before they're handled fails with `StorageFull`.
+const LOOPBACK_SIZE: usize = 4;
+/// How often a journal asks for the output jacks of connections it is restoring
+#[cfg(feature = "std")]
+const RESTORE_INTERVAL: i64 = 1000; // ms
+/// Parameter changes kept between polls, one per parameter
+const MAX_PARAM_CHANGES: usize = 8;
+/// A/B compare actions kept between polls
+const MAX_COMPARE_ACTIONS: usize = 4;
+/// Preset stores and recalls kept between polls
+const MAX_PRESET_ACTIONS: usize = 4;
+/// Most jack connections and disconnections held over for a single poll
+const MAX_JACK_EVENTS: usize = 8;
+/// Input jack color while the network is unreachable
+const OFFLINE_COLOR: Srgb<u8> = Srgb {
+    red: 32,
+    green: 0,
+    blue: 0,
+    standard: PhantomData,
+};
+/// Output jack color while the module is muted
+const MUTED_COLOR: Srgb<u8> = Srgb {
+    red: 64,
+    green: 0,
+    blue: 32,
+    standard: PhantomData,
+};
+const DEGRADED_HOLD: i64 = 1000; // ms
+/// Subscriptions are renewed less often in standby, but still inside `SUBSCRIBE_TIMEOUT` so that
+/// outputs carry on as soon as their sources wake up
+const STANDBY_SUBSCRIBE_INTERVAL: i64 = 2500; // ms
+/// Jack colors are divided by this in standby
+const STANDBY_DIM: u8 = 8;
+/// How often the leading coordinator repeats the network's tuning
+const TUNING_INTERVAL: i64 = 1000; // ms
+const RECV_BUDGET: usize = 4; // packets per input jack per poll
+const SENDER_STRIKES: u16 = 1000; // packets
+
+fn dim(color: Srgb<u8>) -> Srgb<u8> {
+    Srgb::new(
+        color.red / STANDBY_DIM,
+        color.green / STANDBY_DIM,
+        color.blue / STANDBY_DIM,
+    )
+}
+
+/// Sample rate of the audio streams on the wire, in Hz, and the only one modules run at for now.
 pub const SAMPLE_RATE: f32 = 48000.0;
 
+/// Sample rate of the audio streams in Hz. All modules on a network run at the same rate, which is
+/// passed to the DSP building blocks so that they can compute their coefficients. Only
+/// `SAMPLE_RATE` is accepted until blocks are timed separately from the millisecond clock.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SampleRate(pub u32);
+
+impl SampleRate {
+    pub fn hz(self) -> f32 {
+        self.0 as f32
+    }
+
+    /// Length of a single sample in seconds
+    pub fn dt(self) -> f32 {
+        1.0 / self.0 as f32
+    }
+
+    /// Whether a module can run at this rate. Modules process one block per millisecond of their
+    /// clock, so only the wire default keeps up for now.
+    fn is_supported(self) -> bool {
+        self == SampleRate::default()
+    }
+}
+
+impl Default for SampleRate {
+    fn default() -> Self {
+        SampleRate(SAMPLE_RATE as u32)
+    }
+}
+
 pub fn midi_note_to_voct(note: u8) -> i16 {
-    (note as i16 - 64) * 512
+    (note as i16 - 64) * dsp::sample::SEMITONE
 }
 
-pub fn voct_to_frequency(v_oct: f32) -> f32 {
-    440.0 * voct_to_freq_scale(v_oct as f32 - 5.0 * 512.0)
+pub fn voct_to_frequency(v_oct: f32, tuning: &Tuning, notes: Option<&[f32; 128]>) -> f32 {
+    if let Some(notes) = notes {
+        return tuning::table_frequency(v_oct, notes);
+    }
+    tuning.a4 * voct_to_freq_scale(v_oct as f32 - 5.0 * dsp::sample::SEMITONE as f32)
 }
 
-#[cfg(feature = "std")]
+#[cfg(feature = "fast-math")]
+pub fn voct_to_freq_scale(v_oct: f32) -> f32 {
+    dsp::math::exp2((v_oct) / dsp::sample::OCTAVE as f32)
+}
+
+#[cfg(all(feature = "std", not(feature = "fast-math")))]
 pub fn voct_to_freq_scale(v_oct: f32) -> f32 {
-    2.0_f32.powf((v_oct) / (512.0 * 12.0))
+    2.0_f32.powf((v_oct) / dsp::sample::OCTAVE as f32)
 }
 
-#[cfg(not(feature = "std"))]
+#[cfg(not(any(feature = "std", feature = "fast-math")))]
 pub fn voct_to_freq_scale(v_oct: f32) -> f32 {
     use libm::powf;
-    powf(2.0, (v_oct) / (512.0 * 12.0))
+    powf(2.0, (v_oct) / dsp::sample::OCTAVE as f32)
 }
 
 lazy_static! {
+    /// Frequencies of every MIDI note with A4 at 440 Hz, scaled by the tuning on lookup
     static ref FREQ_SCALE: [f32; 128] = {
         let mut result = [0.0; 128];
         for i in 0..128 {
-            result[i] = voct_to_frequency(midi_note_to_voct(i as u8) as f32);
+            result[i] = 440.0 * voct_to_freq_scale(midi_note_to_voct(i as u8) as f32
+                - 5.0 * dsp::sample::SEMITONE as f32);
         }
         result
     };
 }
 
-pub fn voct_to_frequency_table(v_oct: i16) -> f32 {
-    FREQ_SCALE[((v_oct >> 9) + 64) as usize]
+pub fn voct_to_frequency_table(v_oct: i16, tuning: &Tuning, notes: Option<&[f32; 128]>) -> f32 {
+    let note = ((v_oct >> 9) + 64) as usize;
+    match notes {
+        Some(notes) => notes[note],
+        None => FREQ_SCALE[note] * tuning.a4 / 440.0,
+    }
 }
 
 pub fn softclip(x: f32) -> f32 {
@@ -110,6 +284,31 @@ pub struct AudioFrame {
     pub data: [SampleType; CHANNELS],
 }
 
+/// Number of stereo streams a frame can carry. Stereo signals use pairs of channels, with the
+/// left side on the even channel and the right side on the odd channel above it.
+pub const STEREO_PAIRS: usize = CHANNELS / 2;
+
+impl AudioFrame {
+    /// The left and right samples of a stereo pair.
+    pub fn stereo(&self, pair: usize) -> (SampleType, SampleType) {
+        (self.data[2 * pair], self.data[2 * pair + 1])
+    }
+
+    pub fn set_stereo(&mut self, pair: usize, (left, right): (SampleType, SampleType)) {
+        self.data[2 * pair] = left;
+        self.data[2 * pair + 1] = right;
+    }
+
+    /// The sample on each channel, in order.
+    pub fn channels(&self) -> slice::Iter<'_, SampleType> {
+        self.data.iter()
+    }
+
+    pub fn channels_mut(&mut self) -> slice::IterMut<'_, SampleType> {
+        self.data.iter_mut()
+    }
+}
+
 #[derive(AsBytes, FromBytes, Copy, Clone, Debug)]
 #[repr(C)]
 pub struct AudioPacket {
@@ -128,8 +327,57 @@ impl AudioPacket {
     pub fn max(&self) -> f32 {
         *self.data[0].data.iter().max().unwrap_or(&0) as f32
     }
+
+    /// A packet holding the same value on every channel and frame.
+    pub fn splat(value: SampleType) -> Self {
+        AudioPacket {
+            data: [AudioFrame {
+                data: [value; CHANNELS],
+            }; BLOCK_SIZE],
+        }
+    }
+
+    pub fn is_silent(&self) -> bool {
+        self.as_bytes().iter().all(|b| *b == 0)
+    }
+
+    /// The channels with any sample further from zero than `IDLE_LEVEL`, as a bit per channel.
+    pub fn loud_channels(&self) -> u16 {
+        let mut loud = 0;
+        for frame in self.data.iter() {
+            for (c, x) in frame.data.iter().enumerate() {
+                if x.unsigned_abs() > IDLE_LEVEL as u16 {
+                    loud |= 1 << c;
+                }
+            }
+        }
+        loud
+    }
+
+    /// Each frame of the block, in order.
+    pub fn frames(&self) -> slice::Iter<'_, AudioFrame> {
+        self.data.iter()
+    }
+
+    pub fn frames_mut(&mut self) -> slice::IterMut<'_, AudioFrame> {
+        self.data.iter_mut()
+    }
+}
+
+/// Sent in place of an `AudioPacket` while an output jack is paused due to silence, so that
+/// receivers can tell a paused stream apart from packet loss.
+#[derive(AsBytes, FromBytes, Copy, Clone, Default, Debug)]
+#[repr(C)]
+struct KeepalivePacket {
+    paused: u8,
 }
 
+static SILENCE: AudioPacket = AudioPacket {
+    data: [AudioFrame {
+        data: [0; CHANNELS],
+    }; BLOCK_SIZE],
+};
+
 impl Default for AudioPacket {
     fn default() -> Self {
         AudioPacket {
@@ -138,12 +386,20 @@ impl Default for AudioPacket {
     }
 }
 
+/// Where the network is in making a patch, from everyone's held jacks (see
+/// `PollUpdate::patch_state`).
 #[derive(PartialEq, Serialize, Deserialize, Copy, Clone, Debug)]
 pub enum PatchState {
+    /// No jacks are held
     Idle,
+    /// A single input or output jack is held, waiting for the other end
     PatchEnabled,
+    /// One input and one output are held, and are being patched together
     PatchToggled,
+    /// Too many jacks are held to tell what to patch
     Blocked,
+    /// The patch couldn't be made
+    Failed,
 }
 
 #[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
@@ -185,6 +441,12 @@ struct DirectiveSetInputJack {
     uuid: Uuid,
     source: HeldOutputJack,
     connection: PatchConnection,
+    /// Logical clock of when the connection was made, or zero for a new connection
+    stamp: u32,
+    /// The coordinator flagged the connection as closing a feedback loop
+    feedback: bool,
+    /// How the channels are rearranged on the way into the input
+    channels: ChannelMap,
 }
 
 #[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
@@ -194,11 +456,46 @@ struct DirectiveSetOutputJack {
     connection: PatchConnection,
 }
 
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveSetInputJackAck {
+    uuid: Uuid,
+    connection: PatchConnection,
+    success: bool,
+    stamp: u32,
+    channels: ChannelMap,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveBulkConnect {
+    uuid: Uuid,
+    connections: heapless::Vec<DirectiveSetInputJack, MAX_BULK_CONNECTIONS>,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveStateMerge {
+    uuid: Uuid,
+    /// Term of the coordinator the sender last followed. On conflicting connections, the side of
+    /// a partition on the earlier term gives way.
+    term: u32,
+    connections: heapless::Vec<DirectiveSetInputJack, MAX_BULK_CONNECTIONS>,
+}
+
 #[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
 struct DirectiveHalt {
     uuid: Uuid,
 }
 
+/// Put module `uuid` (or every module, for "GLOBAL") in standby.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveStandby {
+    uuid: Uuid,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveWake {
+    uuid: Uuid,
+}
+
 #[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
 struct DirectiveHeartbeat {
     uuid: Uuid,
@@ -212,6 +509,7 @@ struct DirectiveHeartbeatResponse {
     term: u32,
     success: bool,
     iteration: Option<u32>,
+    /// Left out when unchanged since the last one sent
     state: Option<LocalState>,
 }
 
@@ -229,17 +527,375 @@ struct DirectiveRequestVoteResponse {
     vote_granted: bool,
 }
 
+/// How well suited a module is to coordinating the patch (see `Module::set_coordinator`), from
+/// least to most. When several modules could coordinate, the most capable one does.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum Capability {
+    /// Low-powered hardware, which should only coordinate when nothing else can
+    Embedded,
+    /// A module running on a desktop or similar host
+    Desktop,
+    /// A dedicated supervisor for the whole installation, like the manager
+    Supervisor,
+}
+
+impl Default for Capability {
+    fn default() -> Self {
+        if cfg!(feature = "std") {
+            Capability::Desktop
+        } else {
+            Capability::Embedded
+        }
+    }
+}
+
+/// What the coordinator does with a patch that would feed a module's output back into the chain
+/// leading to its own input, which then hears itself a block late (see
+/// `Module::set_feedback_policy`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FeedbackPolicy {
+    /// Make the patch without saying anything
+    Allow,
+    /// Make the patch, but flag it in the global state update (see `PollUpdate::is_feedback`)
+    #[default]
+    Warn,
+    /// Fail the patch, as if the input module had refused it
+    Refuse,
+}
+
+/// How much work a processor is asked to do, stepped down by the module while its host keeps
+/// running over the time it has for each poll (see `OverrunPolicy`). Levels are in order of
+/// falling quality.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[repr(u8)]
+pub enum Quality {
+    #[default]
+    Full,
+    /// Processors should update anything expensive less often, such as filter coefficients (see
+    /// `Quality::control_stride`)
+    Reduced,
+    /// As with `Reduced`, and every other block isn't processed at all, with the outputs held at
+    /// the end of the block before
+    Half,
+}
+
+impl Quality {
+    /// How many control-rate intervals to stretch each expensive update over (see
+    /// `dsp::control::ControlRate::next_every`).
+    pub fn control_stride(self) -> usize {
+        match self {
+            Quality::Full => 1,
+            Quality::Reduced | Quality::Half => 4,
+        }
+    }
+
+    fn lower(self) -> Self {
+        match self {
+            Quality::Full => Quality::Reduced,
+            Quality::Reduced | Quality::Half => Quality::Half,
+        }
+    }
+
+    fn higher(self) -> Self {
+        match self {
+            Quality::Full | Quality::Reduced => Quality::Full,
+            Quality::Half => Quality::Reduced,
+        }
+    }
+}
+
+/// When a module steps down its `Quality` to keep up with a host that runs over its time for
+/// each poll (see `Module::set_overrun_policy`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OverrunPolicy {
+    /// Time the host has for each poll, in µs
+    pub budget_us: u32,
+    /// Polls a second allowed over budget before stepping down a level
+    pub max_overruns: u32,
+    /// Lowest quality to step down to. `Quality::Full` never steps down at all.
+    pub lowest: Quality,
+}
+
+impl Default for OverrunPolicy {
+    fn default() -> Self {
+        OverrunPolicy {
+            budget_us: 1000,
+            max_overruns: 10,
+            lowest: Quality::Full,
+        }
+    }
+}
+
+/// What a module does when its host falls behind and polls it several times in a row to catch
+/// up (see `Module::set_catch_up`).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CatchUpPolicy {
+    /// Most blocks sent out per millisecond of real time. Blocks past this are still processed
+    /// but not sent, leaving receivers to fill in the gap rather than taking a burst.
+    pub max_polls_per_ms: u32,
+    /// Skip processing altogether while behind, with the outputs left silent and not sent.
+    pub skip_audio: bool,
+}
+
+impl Default for CatchUpPolicy {
+    fn default() -> Self {
+        CatchUpPolicy {
+            max_polls_per_ms: u32::MAX,
+            skip_audio: false,
+        }
+    }
+}
+
 #[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
 struct DirectiveGlobalStateUpdate {
     uuid: Uuid,
+    /// Rank of the coordinator that sent the update
+    capability: Capability,
+    /// Term the coordinator is leading in
+    term: u32,
+    /// Rate of the audio streams on the network, for everyone following the coordinator
+    sample_rate: SampleRate,
     patch_state: PatchState,
     input: Option<HeldInputJack>,
     output: Option<HeldOutputJack>,
+    /// The held jacks would patch a module's output back into the chain feeding it
+    feedback: bool,
+}
+
+/// Sent by a coordinator in place of a global state update it has already sent, to let everyone
+/// know it is still there.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveGlobalStateUnchanged {
+    uuid: Uuid,
+    capability: Capability,
+    term: u32,
+    sample_rate: SampleRate,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveProbeRequest {
+    uuid: Uuid,
+    jack_id: JackId,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveProbeResponse {
+    uuid: Uuid,
+    jack_id: JackId,
+    color: u16,
+    addr: [u8; 4],
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveStats {
+    uuid: Uuid,
+    output_streams: u8,
+    input_streams: u8,
+    utilization: u32,
+    iteration: u32,
+    /// Blocks between the start of the patch and this module's outputs
+    latency: u8,
+    /// Channels in use on this module's outputs, counting from the first
+    voices: u8,
+}
+
+/// A piece of a user wavetable sent to another module, which writes it to its `Storage`.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveWavetableUpload {
+    uuid: Uuid,
+    slot: u8,
+    offset: u16,
+    samples: heapless::Vec<i16, WAVETABLE_CHUNK>,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveSubscribe {
+    uuid: Uuid,
+    jack_id: JackId,
+}
+
+/// Ask every module for its view of the patch, to be answered to module `uuid`.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveAuditRequest {
+    uuid: Uuid,
+    audit: u32,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveAuditResponse {
+    uuid: Uuid,
+    requester: Uuid,
+    audit: u32,
+    inputs: Digests,
+    outputs: Digests,
+}
+
+/// How a `Module::send_set_param` value is applied to the knob it names. Values are in terms of
+/// the knob's travel rather than its units, so that a sender doesn't need to know the range.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum ParamChange {
+    /// Move the knob to a position, from 0 at the bottom of its travel to 1 at the top
+    Set(f32),
+    /// Offset the knob from where it was set by a fraction of its travel, replacing any earlier
+    /// offset. The knob itself stays where it is.
+    Modulate(f32),
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveSetParam {
+    uuid: Uuid,
+    param: u8,
+    change: ParamChange,
+}
+
+/// Silence every output of module `uuid`, or let them through again.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveSetMute {
+    uuid: Uuid,
+    mute: bool,
+}
+
+/// Solo module `uuid` in place, or let everything play again. Handled by the leader keeping the
+/// patch journal, which mutes the modules not heard through it (see `solo::Solo`).
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveSolo {
+    uuid: Uuid,
+    solo: bool,
+}
+
+/// Pass the inputs of module `uuid` straight through to its outputs, or go back to processing.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveSetBypass {
+    uuid: Uuid,
+    bypass: bool,
+}
+
+/// A step in comparing a module's knobs before and after an edit, sent with
+/// `Module::send_compare`. The stored settings are called A, and the edits made since B.
+#[derive(PartialEq, Eq, Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum CompareAction {
+    /// Keep the current knob settings as A, replacing any stored before
+    Store,
+    /// Switch between A and B
+    Compare,
+    /// Go back to A, dropping B
+    Revert,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveCompare {
+    uuid: Uuid,
+    action: CompareAction,
+}
+
+/// A change to the presets a module keeps of its own knobs in its `Storage` (see
+/// `PollUpdate::apply_presets`).
+#[derive(PartialEq, Eq, Serialize, Deserialize, Clone, Debug)]
+pub enum PresetAction {
+    /// Keep the current knob settings in a slot, under a name for the manager to show
+    Store {
+        slot: u8,
+        name: heapless::String<PRESET_NAME_LEN>,
+    },
+    /// Set the knobs to the preset in a slot
+    Recall(u8),
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveStorePreset {
+    uuid: Uuid,
+    slot: u8,
+    name: heapless::String<PRESET_NAME_LEN>,
+}
+
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveRecallPreset {
+    uuid: Uuid,
+    slot: u8,
+}
+
+/// Offer module `uuid` a transfer too large for a directive, to be sent from the control port at
+/// `addr` once it is accepted.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveBulkOffer {
+    uuid: Uuid,
+    source: Uuid,
+    transfer: u16,
+    kind: TransferKind,
+    size: u32,
+    addr: ControlAddr,
+}
+
+/// Accept a transfer offered by module `uuid`, giving the control port to send it to.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveBulkAccept {
+    uuid: Uuid,
+    transfer: u16,
+    addr: ControlAddr,
+}
+
+/// Rearrange the channels arriving on input `jack_id` of module `uuid`, for as long as it stays
+/// connected to the same output.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveSetChannelMap {
+    uuid: Uuid,
+    jack_id: JackId,
+    channels: ChannelMap,
+}
+
+/// Which change to the tuning or scale table is the latest. Each change counts on from the one
+/// last heard, and changes sent at the same time are settled by the uuid of the sender, so that
+/// every module ends up on the same one however the directives arrive.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug, Default)]
+struct TuningVersion {
+    count: u32,
+    uuid: Uuid,
+}
+
+impl TuningVersion {
+    fn next(&self, uuid: &Uuid) -> Self {
+        TuningVersion {
+            count: self.count.wrapping_add(1),
+            uuid: uuid.clone(),
+        }
+    }
+
+    fn is_newer_than(&self, other: &TuningVersion) -> bool {
+        let ahead = self.count.wrapping_sub(other.count) as i32;
+        ahead > 0 || (ahead == 0 && self.uuid > other.uuid)
+    }
+}
+
+/// Retune every module on the network. Sent by any module, and repeated by the leading
+/// coordinator for modules that join later.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveTuning {
+    version: TuningVersion,
+    tuning: Tuning,
+}
+
+/// Microtune every module on the network from a scale table, or go back to equal temperament.
+/// Sent and repeated like `DirectiveTuning`.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveScaleTable {
+    version: TuningVersion,
+    table: Option<ScaleTable>,
+}
+
+/// Ask module `uuid` what it is and what is on its front panel. The answer is too large for a
+/// directive, and goes back to module `source` as a `TransferKind::Description` transfer.
+#[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
+struct DirectiveDescribeRequest {
+    uuid: Uuid,
+    source: Uuid,
 }
 
 #[derive(PartialEq, Serialize, Deserialize, Clone, Debug)]
 enum Directive {
     SetInputJack(DirectiveSetInputJack),
+    SetInputJackAck(DirectiveSetInputJackAck),
+    BulkConnect(DirectiveBulkConnect),
+    StateMerge(DirectiveStateMerge),
     SetOutputJack(DirectiveSetOutputJack),
     Halt(DirectiveHalt),
     Heartbeat(DirectiveHeartbeat),
@@ -247,31 +903,130 @@ enum Directive {
     RequestVote(DirectiveRequestVote),
     RequestVoteResponse(DirectiveRequestVoteResponse),
     GlobalStateUpdate(DirectiveGlobalStateUpdate),
+    ProbeRequest(DirectiveProbeRequest),
+    ProbeResponse(DirectiveProbeResponse),
+    Stats(DirectiveStats),
+    Subscribe(DirectiveSubscribe),
+    WavetableUpload(DirectiveWavetableUpload),
+    Standby(DirectiveStandby),
+    Wake(DirectiveWake),
+    AuditRequest(DirectiveAuditRequest),
+    AuditResponse(DirectiveAuditResponse),
+    SetParam(DirectiveSetParam),
+    Compare(DirectiveCompare),
+    GlobalStateUnchanged(DirectiveGlobalStateUnchanged),
+    SetMute(DirectiveSetMute),
+    SetBypass(DirectiveSetBypass),
+    Solo(DirectiveSolo),
+    StorePreset(DirectiveStorePreset),
+    RecallPreset(DirectiveRecallPreset),
+    BulkOffer(DirectiveBulkOffer),
+    BulkAccept(DirectiveBulkAccept),
+    Tuning(DirectiveTuning),
+    ScaleTable(DirectiveScaleTable),
+    SetChannelMap(DirectiveSetChannelMap),
+    DescribeRequest(DirectiveDescribeRequest),
 }
 
-#[derive(Debug)]
-pub enum Error {
-    General,
-    Network,
-    NoData,
-    InvalidJackId,
-    Parse,
-    StorageFull,
+impl Directive {
+    /// Whether a directive sent by module `uuid` needs to be handled by that module too, such as
+    /// when it patches one of its outputs to one of its own inputs.
+    fn loops_back(&self, uuid: &Uuid) -> bool {
+        match self {
+            Directive::SetInputJack(set) => &set.uuid == uuid,
+            Directive::SetInputJackAck(ack) => &ack.connection.output_uuid == uuid,
+            Directive::BulkConnect(bulk) => &bulk.uuid == uuid,
+            Directive::StateMerge(merge) => merge.connections.iter().any(|c| &c.uuid == uuid),
+            Directive::ProbeRequest(req) => &req.uuid == uuid,
+            Directive::ProbeResponse(_) => true,
+            Directive::Subscribe(sub) => &sub.uuid == uuid,
+            Directive::WavetableUpload(upload) => &upload.uuid == uuid,
+            Directive::Standby(standby) => &standby.uuid == uuid || standby.uuid == "GLOBAL",
+            Directive::Wake(wake) => &wake.uuid == uuid || wake.uuid == "GLOBAL",
+            Directive::SetParam(set) => &set.uuid == uuid,
+            Directive::Compare(compare) => &compare.uuid == uuid,
+            Directive::SetMute(set) => &set.uuid == uuid,
+            Directive::SetBypass(set) => &set.uuid == uuid,
+            Directive::Solo(_) => true,
+            Directive::StorePreset(store) => &store.uuid == uuid,
+            Directive::RecallPreset(recall) => &recall.uuid == uuid,
+            Directive::Tuning(_) => true,
+            Directive::ScaleTable(_) => true,
+            // The output end of the connection may be here, keeping it to replay
+            Directive::SetChannelMap(_) => true,
+            _ => false,
+        }
+    }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct InputJackHandle(usize);
 
+/// A jack on a module somewhere on the network, such as the output on the other end of a
+/// connection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct JackPeer {
+    pub uuid: Uuid,
+    pub jack_id: u32,
+}
+
+/// A change to what an input jack is patched to, for processors with state to reset when that
+/// happens (see `PollUpdate::jack_events`).
+#[derive(Clone, Debug, PartialEq)]
+pub enum JackEvent {
+    Connected {
+        handle: InputJackHandle,
+        peer: JackPeer,
+    },
+    /// Sent before `Connected` when a jack moves from one output to another
+    Disconnected { handle: InputJackHandle },
+}
+
 #[derive(Clone, Copy)]
 pub struct OutputJackHandle(usize);
 
+/// An input that can only observe other jacks through `Module::probe`.
+///
+/// Monitor jacks take up an input slot on the network interface, but are never part of a patch:
+/// they cannot be held, are skipped by global state updates, and always report an unlit LED.
+#[derive(Clone, Copy)]
+pub struct MonitorJackHandle(usize);
+
+/// A temporary read-only subscription of a monitor jack to another module's output jack.
+struct Monitor {
+    uuid: Uuid,
+    jack_id: JackId,
+    expires: i64,
+    connected: bool,
+}
+
+/// A connection made from one of this module's output jacks that the input module has not yet
+/// acknowledged.
+struct PendingConnection {
+    directive: DirectiveSetInputJack,
+    retries: u8,
+    backoff: i64,
+    timeout: i64,
+}
+
+/// A change in the network backend that the module needs to react to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NetworkEvent {
+    /// The local address changed, and the backend has already rejoined its multicast groups on
+    /// the new one. Output jack addresses may have changed along with it.
+    AddressChanged([u8; 4]),
+    /// The physical link came up (`true`) or went down. On the way down the backend has already
+    /// given up its address, and on the way up it has started looking for a new one.
+    LinkChanged(bool),
+}
+
 /// General backend communication control.
 ///
 /// Since the backend networking can be changed to run on a host operating system or on a full
 /// network stack, this trait defines what methods are needed to be implemented to accomplish this.
 pub trait Network<const I: usize, const O: usize> {
     /// Update internal state and send/recv packets, if needed
-    fn poll(&mut self, _time: i64) -> Result<(), Error>;
+    fn poll(&mut self, time: MonotonicTime) -> Result<(), Error>;
     /// Check if socket is ready for sending
     fn can_send(&mut self) -> bool;
     /// Get bytes from the directive multicast
@@ -279,57 +1034,330 @@ pub trait Network<const I: usize, const O: usize> {
     /// Output bytes on the directive multicast
     fn send_directive(&mut self, buf: &[u8]) -> Result<(), Error>;
     /// Connect an input jack to an output endpoint
-    fn jack_connect(&mut self, input_jack_id: usize, addr: [u8; 4], time: i64)
-        -> Result<(), Error>;
-    /// Get the next group of incoming packets and number of dropped packets
-    fn dequeue_packets(&mut self, size: usize) -> ([&[u8]; I], u32);
-    /// Get memory space for all output data, to be sent on next poll
-    fn enqueue_packets(&mut self, size: usize) -> Result<[&mut [u8]; O], Error>;
+    fn jack_connect(
+        &mut self,
+        input_jack_id: usize,
+        addr: [u8; 4],
+        time: MonotonicTime,
+    ) -> Result<(), Error>;
+    /// Get the next group of incoming packets, which are empty if nothing was received
+    fn dequeue_packets(&mut self) -> [&[u8]; I];
+    /// Get memory space for all output data, to be sent on next poll. Outputs with a size of zero
+    /// are not sent, and outputs that can't be queued right now (such as when the transmit buffer
+    /// is full) are `None`.
+    fn enqueue_packets(&mut self, sizes: [usize; O]) -> Result<[Option<&mut [u8]>; O], Error>;
     /// Get multicast address for a particular jack
     fn jack_addr(&mut self, output_jack_id: usize) -> Result<[u8; 4], Error>;
     /// Disconnect an input jack
-    fn jack_disconnect(&mut self, input_jack_id: usize, time: i64) -> Result<(), Error>;
+    fn jack_disconnect(&mut self, input_jack_id: usize, time: MonotonicTime) -> Result<(), Error>;
+    /// Get the most recent change to the network since the last call, if any
+    fn take_event(&mut self) -> Option<NetworkEvent> {
+        None
+    }
+    /// Tell the backend whether the physical link is up, for hardware that reads it from its PHY
+    fn set_link_up(&mut self, _up: bool) {}
+    /// Take why each output jack's packet couldn't be sent since the last call, such as
+    /// `NetworkError::Exhausted` for a full transmit buffer. Sending carries on past a jack that
+    /// fails, so that every one of them is counted rather than only the first.
+    fn take_send_errors(&mut self) -> [Option<Error>; O] {
+        array::from_fn(|_| None)
+    }
+    /// Get the number of input jack packets that have been turned away so far
+    fn receive_stats(&self) -> ReceiveStats {
+        Default::default()
+    }
+    /// Get the unicast address and port of the control port, for payloads too large for a
+    /// directive, or `None` if the backend doesn't have one (or has no address yet)
+    fn control_addr(&mut self) -> Option<ControlAddr> {
+        None
+    }
+    /// Send a datagram to the control port of the interface at `addr`
+    fn send_control(&mut self, _addr: ControlAddr, _buf: &[u8]) -> Result<(), Error> {
+        Err(Error::Network(SocketId::Control, NetworkError::Unsupported))
+    }
+    /// Get the next datagram sent to the control port, along with the control port it came from
+    fn recv_control(&mut self, _buf: &mut [u8]) -> Result<(usize, ControlAddr), Error> {
+        Err(Error::NoData)
+    }
 }
 
-/// Module communication and state handling.
-///
-/// `Module` is responsible for handling communication between other modules on the same network on
-/// within the same process (depending on configuration), as well as manages the current state of
-/// patching and the audio packet reception and tranmission.
-///
-/// Since this portion is platform independent, with `no-std` and no allocation, users of this crate
-/// are responsible for providing the current time (in milliseconds from an arbitrary start), a
-/// source of random source, and `poll`-ing the module at regular intervals to perform network
-/// updates.
-pub struct Module<T: Network<I, O>, R: RngCore, const I: usize, const O: usize> {
-    uuid: Uuid,
-    color: u16,
-    interface: T,
-    ping_patch: PingPatch,
-    input_patch_enabled: u16,
-    output_patch_enabled: u16,
+/// Input jack packets turned away by the network interface, to keep a flood on one jack's group
+/// from starving the rest of the poll loop.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReceiveStats {
+    /// Packets from somewhere other than the output the jack is listening to
+    pub wrong_source: u32,
+    /// Polls where a jack's receive budget ran out before a packet from its sender turned up
+    pub budget_exhausted: u32,
+}
+
+/// Source filter for an input jack. Multicast groups don't say who is allowed to send to them, so
+/// the first source heard from after connecting is taken as the sender and anything else is
+/// turned away. If only other sources are heard from for a while (about a second of audio), the
+/// sender is assumed to have moved to a new address and the filter starts over.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct SourceFilter<S> {
+    sender: Option<S>,
+    strikes: u16,
+}
+
+impl<S: Copy + PartialEq> SourceFilter<S> {
+    pub(crate) const fn new() -> Self {
+        SourceFilter {
+            sender: None,
+            strikes: 0,
+        }
+    }
+
+    pub(crate) fn accept(&mut self, source: S) -> bool {
+        match self.sender {
+            Some(s) if s == source => {
+                self.strikes = 0;
+                true
+            }
+            Some(_) if self.strikes < SENDER_STRIKES => {
+                self.strikes += 1;
+                false
+            }
+            _ => {
+                self.sender = Some(source);
+                self.strikes = 0;
+                true
+            }
+        }
+    }
+
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+/// Module communication and state handling.
+///
+/// `Module` is responsible for handling communication between other modules on the same network on
+/// within the same process (depending on configuration), as well as manages the current state of
+/// patching and the audio packet reception and tranmission.
+///
+/// Since this portion is platform independent, with `no-std` and no allocation, users of this crate
+/// are responsible for providing a `Clock` (or keeping a `ManualClock` told of the time), a source
+/// of random source, and `poll`-ing the module once per `next_tick` to perform network updates. A
+/// `SeededRng` makes a run repeat exactly.
+pub struct Module<
+    T: Network<I, O>,
+    R: RngCore,
+    const I: usize,
+    const O: usize,
+    C: Clock = ManualClock,
+> {
+    uuid: Uuid,
+    color: u16,
+    interface: T,
+    ping_patch: PingPatch,
+    bandwidth: Bandwidth,
+    audit: Audit,
+    bulk: Bulk,
+    input_patch_enabled: [bool; I],
+    output_patch_enabled: [bool; O],
     dropped_packets: u32,
+    send_failures: u32,
+    send_retry: bool,
     patch_state: PatchState,
+    /// The patch being made would close a feedback loop, as of the last global state update
+    patch_feedback: bool,
+    /// Jacks held down somewhere on the network, as of the last global state update
+    held_input: Option<JackPeer>,
+    held_output: Option<JackPeer>,
+    pending_connection: Option<PendingConnection>,
+    connections: heapless::Vec<DirectiveSetInputJack, MAX_CONNECTIONS>,
+    clock: u32,
+    input_stamps: [u32; I],
     input_colors: [u16; I],
+    monitors: [Option<Monitor>; I],
+    audition: Option<MonitorJackHandle>,
+    monitor_jacks: [bool; I],
+    connected_inputs: [bool; I],
+    input_sources: [Option<DirectiveSubscribe>; I],
+    input_addrs: [[u8; 4]; I],
+    input_normals: [AudioPacket; I],
+    latency_compensation: bool,
+    /// Voices in use on the outputs, if set here rather than followed from the inputs
+    voices: Option<u8>,
+    align_history: [[AudioPacket; MAX_ALIGN_DELAY + 1]; I],
+    align_head: usize,
+    /// Inputs whose connection was flagged as closing a feedback loop
+    feedback_inputs: [bool; I],
+    /// Inputs run through a soft limiter before processing, and the limited blocks
+    limited_inputs: [bool; I],
+    limited_packets: [AudioPacket; I],
+    /// Inputs with a channel map other than `ChannelMap::Straight`, each map, and the mapped
+    /// blocks
+    mapped_inputs: [bool; I],
+    channel_maps: [ChannelMap; I],
+    mapped_packets: [AudioPacket; I],
+    activity: Activity<I>,
+    subscribe_timeout: i64,
+    tuning: Tuning,
+    tuning_version: TuningVersion,
+    scale_table: Option<ScaleTable>,
+    /// Frequency of every MIDI note from `scale_table`, worked out when it arrives
+    note_frequencies: Option<[f32; 128]>,
+    scale_table_version: TuningVersion,
+    tuning_timeout: i64,
+    standby: bool,
+    muted: bool,
+    bypassed: bool,
+    link_up: bool,
+    /// Address last reported by the network backend
+    address: Option<[u8; 4]>,
+    degraded_until: i64,
+    last_receive_stats: ReceiveStats,
+    subscribers: [i64; O],
+    free_running: bool,
+    input_paused: [bool; I],
+    outputs: [AudioPacket; O],
+    silence_suppression: bool,
+    silent_blocks: [u8; O],
+    catch_up: CatchUpPolicy,
+    overrun: Overrun,
+    /// Whether the last block was left out while running at `Quality::Half`
+    skipped_block: bool,
+    /// Where the real time comes from, and the next block due by it
+    ticker: Ticker<C>,
+    /// Real time as last read from the clock, and how many polls have happened at it
+    now: i64,
+    polls_at_now: u32,
+    wavetable_upload: Option<DirectiveWavetableUpload>,
+    param_changes: heapless::Vec<(u8, ParamChange), MAX_PARAM_CHANGES>,
+    compare_actions: heapless::Vec<CompareAction, MAX_COMPARE_ACTIONS>,
+    preset_actions: heapless::Vec<PresetAction, MAX_PRESET_ACTIONS>,
+    jack_events: heapless::Vec<JackEvent, MAX_JACK_EVENTS>,
+    /// This module's own front panel, and the last description heard from another module
+    description: Option<ModuleDescription>,
+    peer_description: Option<(Uuid, ModuleDescription)>,
+    loopback: heapless::Deque<Directive, LOOPBACK_SIZE>,
+    #[cfg(feature = "std")]
+    journal: Option<journal::PatchJournal>,
+    #[cfg(feature = "std")]
+    solo: solo::Solo,
     input_jack_handles: usize,
     output_jack_handles: usize,
     phantom: PhantomData<R>,
 }
 
 impl<T: Network<I, O>, R: RngCore, const I: usize, const O: usize> Module<T, R, I, O> {
-    pub fn new(interface: T, _rand_source: R, id: Uuid, color: u16, time: i64) -> Self {
+    /// A module for a host that counts the time itself, starting at `time`, and keeps it told of
+    /// the real time through `set_now`.
+    pub fn new(
+        interface: T,
+        rand_source: R,
+        id: Uuid,
+        color: u16,
+        time: impl Into<MonotonicTime>,
+    ) -> Self {
+        Self::with_clock(
+            interface,
+            rand_source,
+            id,
+            color,
+            ManualClock::new(time.into()),
+        )
+    }
+
+    /// Tell the module what time it really is, as opposed to the time of the block being polled,
+    /// so that it can tell when it is being polled to catch up.
+    pub fn set_now(&mut self, now: impl Into<MonotonicTime>) {
+        self.ticker.clock_mut().set(now.into());
+    }
+}
+
+impl<T: Network<I, O>, R: RngCore, const I: usize, const O: usize, C: Clock> Module<T, R, I, O, C> {
+    /// A module that reads the time off `clock`, starting from now.
+    pub fn with_clock(interface: T, _rand_source: R, id: Uuid, color: u16, clock: C) -> Self {
+        let mut ticker = Ticker::new(clock);
+        let time = ticker.clock_mut().now().as_millis();
         // let leader_election = LeaderElection::new(id.clone(), time, rand_source);
-        let ping_patch = PingPatch::new(id.clone(), time);
+        let ping_patch = PingPatch::new(id.clone(), Default::default(), time);
+        let bandwidth = Bandwidth::new(id.clone(), time);
+        let audit = Audit::new(id.clone());
+        let bulk = Bulk::new(id.clone());
         Module {
             uuid: id,
             color,
             interface,
             ping_patch,
-            input_patch_enabled: 0,
-            output_patch_enabled: 0,
+            bandwidth,
+            audit,
+            bulk,
+            input_patch_enabled: [false; I],
+            output_patch_enabled: [false; O],
             dropped_packets: 0,
+            send_failures: 0,
+            send_retry: false,
             patch_state: PatchState::Idle,
+            patch_feedback: false,
+            held_input: None,
+            held_output: None,
+            pending_connection: None,
+            connections: heapless::Vec::new(),
+            clock: 0,
+            input_stamps: [0; I],
             input_colors: [0; I],
+            monitors: [(); I].map(|_| None),
+            audition: None,
+            monitor_jacks: [false; I],
+            connected_inputs: [false; I],
+            input_sources: [(); I].map(|_| None),
+            input_addrs: [[0; 4]; I],
+            input_normals: [Default::default(); I],
+            latency_compensation: false,
+            voices: None,
+            align_history: [[Default::default(); MAX_ALIGN_DELAY + 1]; I],
+            align_head: 0,
+            feedback_inputs: [false; I],
+            limited_inputs: [false; I],
+            limited_packets: [Default::default(); I],
+            activity: Activity::new(),
+            mapped_inputs: [false; I],
+            channel_maps: [Default::default(); I],
+            mapped_packets: [Default::default(); I],
+            subscribe_timeout: time,
+            tuning: Default::default(),
+            tuning_version: Default::default(),
+            scale_table: None,
+            note_frequencies: None,
+            scale_table_version: Default::default(),
+            tuning_timeout: time + TUNING_INTERVAL,
+            standby: false,
+            muted: false,
+            bypassed: false,
+            link_up: true,
+            address: None,
+            degraded_until: time,
+            last_receive_stats: Default::default(),
+            subscribers: [i64::MIN; O],
+            free_running: false,
+            input_paused: [false; I],
+            outputs: [Default::default(); O],
+            silence_suppression: false,
+            silent_blocks: [0; O],
+            catch_up: Default::default(),
+            overrun: Default::default(),
+            skipped_block: false,
+            ticker,
+            now: time,
+            polls_at_now: 0,
+            wavetable_upload: None,
+            description: None,
+            peer_description: None,
+            param_changes: heapless::Vec::new(),
+            compare_actions: heapless::Vec::new(),
+            preset_actions: heapless::Vec::new(),
+            jack_events: heapless::Vec::new(),
+            loopback: heapless::Deque::new(),
+            #[cfg(feature = "std")]
+            journal: None,
+            #[cfg(feature = "std")]
+            solo: Default::default(),
             input_jack_handles: 0,
             output_jack_handles: 0,
             phantom: PhantomData,
@@ -346,6 +1374,29 @@ impl<T: Network<I, O>, R: RngCore, const I: usize, const O: usize> Module<T, R,
         }
     }
 
+    /// Set the signal seen on an input jack while nothing is connected to it, like a normalled
+    /// jack on hardware modules. Unconnected inputs otherwise read as silence.
+    pub fn set_input_normal(&mut self, handle: InputJackHandle, packet: AudioPacket) {
+        self.input_normals[handle.0] = packet;
+    }
+
+    /// Reserve an input slot for read-only monitoring. At most `MAX_MONITORS` can be added.
+    pub fn add_monitor_jack(&mut self) -> Result<MonitorJackHandle, Error> {
+        let monitors = self
+            .monitor_jacks
+            .iter()
+            .filter(|&&monitor| monitor)
+            .count();
+        if self.input_jack_handles == I || monitors == MAX_MONITORS {
+            Err(Error::StorageFull)
+        } else {
+            let handle = MonitorJackHandle(self.input_jack_handles);
+            self.monitor_jacks[handle.0] = true;
+            self.input_jack_handles += 1;
+            Ok(handle)
+        }
+    }
+
     pub fn add_output_jack(&mut self) -> Result<OutputJackHandle, Error> {
         if self.output_jack_handles == O {
             Err(Error::StorageFull)
@@ -356,59 +1407,454 @@ impl<T: Network<I, O>, R: RngCore, const I: usize, const O: usize> Module<T, R,
         }
     }
 
-    pub fn poll<F>(&mut self, time: i64, f: F) -> Result<PollUpdate<I, O>, Error>
+    /// Check that every one of the `I` inputs and `O` outputs has been added, and return their
+    /// handles by position. Engines call this once they've added their jacks, so that a count
+    /// that has drifted from the engine's constants fails at startup rather than leaving jacks
+    /// that are silently never read or sent. Monitor jacks count as inputs.
+    pub fn finalize_jacks(&self) -> Result<Jacks<I, O>, Error> {
+        if self.input_jack_handles != I || self.output_jack_handles != O {
+            return Err(Error::UnallocatedJacks {
+                inputs: I - self.input_jack_handles,
+                outputs: O - self.output_jack_handles,
+            });
+        }
+        Ok(Jacks {
+            inputs: core::array::from_fn(InputJackHandle),
+            outputs: core::array::from_fn(OutputJackHandle),
+        })
+    }
+
+    pub fn poll<F>(
+        &mut self,
+        time: impl Into<MonotonicTime>,
+        f: F,
+    ) -> Result<PollUpdate<I, O>, Error>
     where
         F: FnOnce(&mut ProcessBlock<I, O>),
     {
+        let time = time.into().as_millis();
         let mut input_colors: [Srgb<u8>; I] = [Default::default(); I];
         let mut output_colors: [Srgb<u8>; O] = [Default::default(); O];
-        self.interface.poll(time)?;
-        if self.can_send() {
-            let (packets, dropped) = self
-                .interface
-                .dequeue_packets(mem::size_of::<AudioPacket>());
-            self.dropped_packets += dropped;
-            let input_packets =
-                packets.map(|p| unsafe { &*(p as *const [u8] as *const AudioPacket) });
-            let output_packets = self
-                .interface
-                .enqueue_packets(mem::size_of::<AudioPacket>())
-                .unwrap()
-                .map(|p| unsafe { &mut *(p as *mut [u8] as *mut AudioPacket) });
-
-            let mut block = ProcessBlock::<I, O>::new(input_packets, output_packets);
-            let (resp, gsu) = if let Ok(d) = self.recv_directive() {
-                self.ping_patch.poll(Some(d), time)
-            } else {
-                self.ping_patch.poll(None, time)
+        let mut send_failures = self.poll_interface(time)?;
+        let mut failed = [false; O];
+        if let Some(event) = self.interface.take_event() {
+            self.process_network_event(event, time)?;
+        }
+        let online = self.can_send();
+        if online {
+            let (resp, gsu) = match self.recv_directive() {
+                Ok(Directive::ProbeRequest(req)) => {
+                    self.process_probe_request(req)?;
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::ProbeResponse(resp)) => {
+                    #[cfg(feature = "std")]
+                    self.restore_from_probe(&resp)?;
+                    self.process_probe_response(resp, time);
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::Stats(stats)) => {
+                    let uuid = stats.uuid.clone();
+                    match self.bandwidth.process_stats(stats, time) {
+                        Some(Rejoin::Restarted) => self.replay_connections(&uuid)?,
+                        Some(Rejoin::Reconnected) => self.merge_state()?,
+                        None => {}
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::StateMerge(merge)) => {
+                    for set in merge.connections {
+                        if set.uuid == self.uuid {
+                            self.process_set_input_jack(set, merge.term, time);
+                        }
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::BulkConnect(bulk)) => {
+                    if bulk.uuid == self.uuid {
+                        for set in bulk.connections {
+                            self.process_set_input_jack(set, self.ping_patch.term(), time);
+                        }
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::SetInputJack(set)) => {
+                    if set.uuid == self.uuid {
+                        self.process_set_input_jack(set, self.ping_patch.term(), time);
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::SetInputJackAck(ack)) => {
+                    #[cfg(feature = "std")]
+                    self.journal_ack(&ack);
+                    if ack.success {
+                        self.ping_patch.record_connection(&ack.connection);
+                    }
+                    self.process_set_input_jack_ack(ack);
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::Subscribe(sub)) => {
+                    if sub.uuid == self.uuid && (sub.jack_id as usize) < O {
+                        self.subscribers[sub.jack_id as usize] = time + SUBSCRIBE_TIMEOUT;
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::WavetableUpload(upload)) => {
+                    if upload.uuid == self.uuid {
+                        self.wavetable_upload = Some(upload);
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::Standby(standby)) => {
+                    if standby.uuid == self.uuid || standby.uuid == "GLOBAL" {
+                        self.set_standby(true);
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::Wake(wake)) => {
+                    if wake.uuid == self.uuid || wake.uuid == "GLOBAL" {
+                        self.set_standby(false);
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::AuditRequest(req)) => {
+                    self.process_audit_request(req)?;
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::AuditResponse(resp)) => {
+                    self.audit.process_response(resp);
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::BulkOffer(offer)) => {
+                    let addr = self.interface.control_addr();
+                    if let Some(accept) = self.bulk.process_offer(offer, addr, time) {
+                        self.send_directive(&accept)?;
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::BulkAccept(accept)) => {
+                    self.bulk.process_accept(accept);
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::SetParam(set)) => {
+                    if set.uuid == self.uuid {
+                        self.queue_param_change(set.param, set.change);
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::Compare(compare)) => {
+                    if compare.uuid == self.uuid
+                        && self.compare_actions.push(compare.action).is_err()
+                    {
+                        info!("Compare action queue full");
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::SetMute(set)) => {
+                    if set.uuid == self.uuid {
+                        self.set_mute(set.mute);
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::SetBypass(set)) => {
+                    if set.uuid == self.uuid {
+                        self.set_bypass(set.bypass);
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::Solo(solo)) => {
+                    #[cfg(feature = "std")]
+                    self.process_solo(solo)?;
+                    #[cfg(not(feature = "std"))]
+                    let _ = solo;
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::StorePreset(store)) => {
+                    let action = PresetAction::Store {
+                        slot: store.slot,
+                        name: store.name,
+                    };
+                    if store.uuid == self.uuid && self.preset_actions.push(action).is_err() {
+                        info!("Preset action queue full");
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::RecallPreset(recall)) => {
+                    if recall.uuid == self.uuid
+                        && self
+                            .preset_actions
+                            .push(PresetAction::Recall(recall.slot))
+                            .is_err()
+                    {
+                        info!("Preset action queue full");
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::Tuning(tuning)) => {
+                    // Repeats of an older tuning still on their way don't undo a newer one
+                    if tuning.version.is_newer_than(&self.tuning_version) {
+                        self.tuning_version = tuning.version;
+                        self.set_tuning(tuning.tuning);
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::ScaleTable(scale)) => {
+                    if scale.version.is_newer_than(&self.scale_table_version) {
+                        self.scale_table_version = scale.version;
+                        self.set_scale_table(scale.table);
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::SetChannelMap(set)) => {
+                    self.process_set_channel_map(set);
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(Directive::DescribeRequest(request)) => {
+                    if request.uuid == self.uuid {
+                        self.send_description(request.source);
+                    }
+                    self.ping_patch.poll(None, &self.bandwidth, time)
+                }
+                Ok(d) => self.ping_patch.poll(Some(d), &self.bandwidth, time),
+                Err(_) => self.ping_patch.poll(None, &self.bandwidth, time),
             };
             if let Some(resp) = resp {
                 self.send_directive(&resp)?;
             }
+            if let Some(stats) = self.bandwidth.poll(
+                self.active_output_streams(time),
+                self.input_streams(),
+                self.latency(),
+                self.voices(),
+                self.ping_patch.sample_rate(),
+                time,
+            ) {
+                self.send_directive(&stats)?;
+            }
+            if time >= self.subscribe_timeout {
+                self.subscribe_timeout = time
+                    + if self.standby {
+                        STANDBY_SUBSCRIBE_INTERVAL
+                    } else {
+                        SUBSCRIBE_INTERVAL
+                    };
+                for i in 0..I {
+                    if let Some(sub) = self.input_sources[i].clone() {
+                        self.send_directive(&Directive::Subscribe(sub))?;
+                    }
+                }
+            }
             if let Some(Directive::GlobalStateUpdate(gsu)) = gsu {
                 self.process_gsu(gsu, time);
             }
-            for i in 0..I {
-                let avg = block.input[i].max();
-                let c: Srgb = Hsv::new(
-                    self.input_colors[i] as f32,
-                    1.0,
-                    avg * 16.0 / i16::MAX as f32,
-                )
-                .into_color();
-                input_colors[i] = c.into_format();
-            }
-            f(&mut block);
-            for i in 0..O {
-                let avg = block.output[i].max();
-                let c: Srgb =
-                    Hsv::new(self.color as f32, 1.0, avg * 16.0 / i16::MAX as f32).into_color();
-                output_colors[i] = c.into_format();
+            self.expire_probes(time);
+            self.audition(time)?;
+            self.retry_connection(time)?;
+            #[cfg(feature = "std")]
+            self.restore_patch(time)?;
+            self.audit.poll(time);
+            if self.ping_patch.is_leading(time) && time >= self.tuning_timeout {
+                self.tuning_timeout = time + TUNING_INTERVAL;
+                self.send_directive(&Directive::Tuning(DirectiveTuning {
+                    version: self.tuning_version.clone(),
+                    tuning: self.tuning,
+                }))?;
+                self.send_directive(&Directive::ScaleTable(DirectiveScaleTable {
+                    version: self.scale_table_version.clone(),
+                    table: self.scale_table.clone(),
+                }))?;
+            }
+            if let Some(offer) = self.bulk.poll(&mut self.interface, time)? {
+                self.send_directive(&offer)?;
+            }
+            if let Some(Transfer {
+                source,
+                kind: TransferKind::Description,
+                data,
+            }) = self.bulk.received()
+            {
+                match postcard::from_bytes(data) {
+                    Ok(description) => self.peer_description = Some((source.clone(), description)),
+                    Err(e) => info!("Description from {} unreadable: {:?}", source, e),
+                }
             }
         } else {
             // self.leader_election.reset(time);
         }
-        self.interface.poll(time)?;
+
+        // Ahead of borrowing the input packets, which hold on to the normals until processing
+        let (process, send) = self.catch_up_poll(time);
+
+        // Local processing carries on while offline, with whatever isn't normalled left silent
+        let packets: [&[u8]; I] = if online {
+            self.interface.dequeue_packets()
+        } else {
+            [&[]; I]
+        };
+        let mut input_packets = [&SILENCE; I];
+        for (i, p) in packets.iter().enumerate() {
+            if !self.connected_inputs[i] {
+                input_packets[i] = &self.input_normals[i];
+            }
+            if p.len() == mem::size_of::<AudioPacket>() {
+                self.input_paused[i] = false;
+                input_packets[i] = unsafe { &*(*p as *const [u8] as *const AudioPacket) };
+            } else if let Some(keepalive) = KeepalivePacket::read_from(*p) {
+                if keepalive.paused != 0 {
+                    self.input_paused[i] = true;
+                }
+            } else if online && self.connected_inputs[i] && !self.input_paused[i] {
+                self.dropped_packets += 1;
+                self.degraded_until = time + DEGRADED_HOLD;
+            }
+        }
+        let mapped: [bool; I] =
+            array::from_fn(|i| self.mapped_inputs[i] && self.connected_inputs[i]);
+        if mapped.contains(&true) {
+            for (i, packet) in input_packets.iter().enumerate() {
+                if mapped[i] {
+                    self.channel_maps[i].apply(packet, &mut self.mapped_packets[i]);
+                }
+            }
+            for (i, packet) in input_packets.iter_mut().enumerate() {
+                if mapped[i] {
+                    *packet = &self.mapped_packets[i];
+                }
+            }
+        }
+        if self.latency_compensation {
+            let delays = self.align_delays();
+            self.align_head = (self.align_head + 1) % (MAX_ALIGN_DELAY + 1);
+            for (history, packet) in self.align_history.iter_mut().zip(input_packets) {
+                history[self.align_head] = *packet;
+            }
+            for (i, packet) in input_packets.iter_mut().enumerate() {
+                let slot =
+                    (self.align_head + MAX_ALIGN_DELAY + 1 - delays[i]) % (MAX_ALIGN_DELAY + 1);
+                *packet = &self.align_history[i][slot];
+            }
+        }
+        if self.limited_inputs.contains(&true) {
+            for (i, packet) in input_packets.iter().enumerate() {
+                if self.limited_inputs[i] {
+                    let limited = &mut self.limited_packets[i];
+                    for (out, frame) in limited.data.iter_mut().zip(packet.data.iter()) {
+                        out.data = frame.data.map(dsp::mix::soft_limit);
+                    }
+                }
+            }
+            for (i, packet) in input_packets.iter_mut().enumerate() {
+                if self.limited_inputs[i] {
+                    *packet = &self.limited_packets[i];
+                }
+            }
+        }
+        self.activity.update(&input_packets);
+        for i in 0..I {
+            if self.is_monitor(i) {
+                continue;
+            }
+            if !online {
+                input_colors[i] = OFFLINE_COLOR;
+                continue;
+            }
+            let avg = input_packets[i].max();
+            let c: Srgb = Hsv::new(
+                self.input_colors[i] as f32,
+                1.0,
+                avg * 16.0 / i16::MAX as f32,
+            )
+            .into_color();
+            input_colors[i] = c.into_format();
+        }
+        let mut active = [true; O];
+        for (i, a) in active.iter_mut().enumerate() {
+            *a = self.is_output_active(i, time);
+        }
+        let quality = self.overrun.quality();
+        let context = BlockContext {
+            time,
+            sample_rate: self.ping_patch.sample_rate(),
+            quality,
+            tuning: self.tuning,
+            note_frequencies: self.note_frequencies,
+            voices: self.voices(),
+        };
+        self.skipped_block = quality == Quality::Half && !self.skipped_block;
+        if process && self.skipped_block {
+            for packet in self.outputs.iter_mut() {
+                packet.data = [packet.data[BLOCK_SIZE - 1]; BLOCK_SIZE];
+            }
+        } else if process {
+            f(&mut ProcessBlock::<I, O>::new(
+                input_packets,
+                self.outputs.each_mut(),
+                active,
+                context,
+            )
+            .with_activity(self.activity.active()));
+        } else {
+            self.outputs = [Default::default(); O];
+        }
+        // The block is still processed while muted or bypassed, so that the module picks up
+        // where it was when let go
+        if self.muted {
+            self.outputs = [Default::default(); O];
+        } else if self.bypassed {
+            for i in 0..O {
+                self.outputs[i] = match input_packets.get(i) {
+                    Some(packet) if !self.is_monitor(i) => **packet,
+                    _ => Default::default(),
+                };
+            }
+        }
+
+        let mut sizes = [mem::size_of::<AudioPacket>(); O];
+        for i in 0..O {
+            let avg = self.outputs[i].max();
+            let c: Srgb =
+                Hsv::new(self.color as f32, 1.0, avg * 16.0 / i16::MAX as f32).into_color();
+            output_colors[i] = if self.muted {
+                MUTED_COLOR
+            } else {
+                c.into_format()
+            };
+
+            // Standby pauses every output the same way as silence does
+            if self.standby || (self.silence_suppression && self.outputs[i].is_silent()) {
+                self.silent_blocks[i] = self.silent_blocks[i].saturating_add(1);
+            } else {
+                self.silent_blocks[i] = 0;
+            }
+            if self.silent_blocks[i] > SILENCE_HOLDOFF {
+                // Announce the pause right away, then only send the occasional keepalive
+                let first = self.silent_blocks[i] == SILENCE_HOLDOFF + 1;
+                sizes[i] = if first || time % KEEPALIVE_INTERVAL == 0 {
+                    mem::size_of::<KeepalivePacket>()
+                } else {
+                    0
+                };
+            }
+            if !active[i] || !send {
+                sizes[i] = 0;
+            }
+        }
+        if online {
+            failed = self.queue_outputs(sizes)?;
+        }
+        send_failures += self.poll_interface(time)?;
+        if self.send_retry && failed.contains(&true) {
+            // The poll above has hopefully made room for whatever didn't fit the first time
+            for (size, failed) in sizes.iter_mut().zip(failed) {
+                if !failed {
+                    *size = 0;
+                }
+            }
+            failed = self.queue_outputs(sizes)?;
+            send_failures += self.poll_interface(time)?;
+        }
+        send_failures += failed.iter().filter(|&&failed| failed).count() as u32;
+        self.send_failures = self.send_failures.wrapping_add(send_failures);
         if time % 10000 == 0 && self.dropped_packets != 0 {
             info!("{} dropped packets: {:?}", self.uuid, self.dropped_packets);
             self.dropped_packets = 0;
@@ -417,168 +1863,1992 @@ impl<T: Network<I, O>, R: RngCore, const I: usize, const O: usize> Module<T, R,
         let color: Srgb<u8> = match self.patch_state {
             PatchState::Idle => Default::default(),
             PatchState::PatchEnabled => Srgb::new(255, 255, 255),
+            PatchState::PatchToggled if self.patch_feedback => Srgb::new(255, 128, 0),
             PatchState::PatchToggled => Srgb::new(255, 255, 0),
             PatchState::Blocked => Srgb::new(255, 0, 0),
+            PatchState::Failed => Srgb::new(255, 0, 255),
+        };
+        let sample_rate = self.ping_patch.sample_rate();
+        let wavetable_upload = self.wavetable_upload.take();
+        let peer_description = self.peer_description.take();
+        let param_changes = mem::take(&mut self.param_changes);
+        let compare_actions = mem::take(&mut self.compare_actions);
+        let preset_actions = mem::take(&mut self.preset_actions);
+        let jack_events = mem::take(&mut self.jack_events);
+        let patch_state = self.patch_state;
+        let patch_feedback = self.patch_feedback;
+        let held_input = self.held_input.clone();
+        let held_output = self.held_output.clone();
+        let network_state = if online {
+            NetworkState::Online
+        } else {
+            NetworkState::Offline
         };
+        let status = self.status(online, send_failures, time);
+        if self.standby {
+            input_colors = input_colors.map(dim);
+            output_colors = output_colors.map(dim);
+        }
+        let standby = self.standby;
+        let (muted, bypassed) = (self.muted, self.bypassed);
         match self.patch_state {
             PatchState::Idle => Ok(PollUpdate {
                 input_colors,
                 output_colors,
+                sample_rate,
+                wavetable_upload,
+                peer_description,
+                param_changes,
+                compare_actions,
+                preset_actions,
+                jack_events,
+                patch_state,
+                patch_feedback,
+                held_input,
+                held_output,
+                send_failures,
+                network_state,
+                status,
+                standby,
+                muted,
+                bypassed,
             }),
-            _ => Ok(PollUpdate {
-                input_colors: [color; I],
-                output_colors: [color; O],
-            }),
+            _ => {
+                for (i, c) in input_colors.iter_mut().enumerate() {
+                    if !self.is_monitor(i) {
+                        *c = color;
+                    }
+                }
+                Ok(PollUpdate {
+                    input_colors,
+                    output_colors: [color; O],
+                    sample_rate,
+                    wavetable_upload,
+                    peer_description,
+                    param_changes,
+                    compare_actions,
+                    preset_actions,
+                    jack_events,
+                    patch_state,
+                    patch_feedback,
+                    held_input,
+                    held_output,
+                    send_failures,
+                    network_state,
+                    status,
+                    standby,
+                    muted,
+                    bypassed,
+                })
+            }
         }
     }
 
-    pub fn can_send(&mut self) -> bool {
-        self.interface.can_send()
+    /// Tell the module whether its physical link is up, for hardware that can read the state of
+    /// its PHY. Assumed to be up otherwise.
+    pub fn set_link_up(&mut self, up: bool) {
+        self.link_up = up;
+        self.interface.set_link_up(up);
     }
 
-    fn recv_directive(&mut self) -> Result<Directive, Error> {
-        let mut buf = [0; 2048];
-        match self.interface.recv_directive(&mut buf) {
-            Ok(size) => match postcard::from_bytes(&buf[0..size]) {
-                Ok(out) => {
-                    trace!("<= {:?}", out);
-                    Ok(out)
-                }
-                Err(e) => {
-                    info!("Postcard Parse Error: {:?}", e);
-                    Err(Error::Parse)
+    fn status(&mut self, online: bool, send_failures: u32, time: i64) -> Status {
+        let receive_stats = self.interface.receive_stats();
+        let bandwidth = self.bandwidth_stats();
+        if send_failures != 0
+            || receive_stats != self.last_receive_stats
+            || bandwidth.network > bandwidth.budget
+        {
+            self.degraded_until = time + DEGRADED_HOLD;
+        }
+        self.last_receive_stats = receive_stats;
+
+        if !self.link_up {
+            Status::LinkDown
+        } else if !online {
+            Status::Configuring
+        } else if time < self.degraded_until || self.overrun.quality() != Quality::Full {
+            Status::Degraded
+        } else if self.bandwidth.peers() == 0 {
+            Status::Alone
+        } else {
+            Status::Healthy
+        }
+    }
+
+    /// Copy this block's outputs into the interface's transmit buffers, returning the jacks that
+    /// didn't fit.
+    fn queue_outputs(&mut self, sizes: [usize; O]) -> Result<[bool; O], Error> {
+        let keepalive = KeepalivePacket { paused: 1 };
+        let mut failed = [false; O];
+        let output_packets = self.interface.enqueue_packets(sizes)?;
+        for (i, packet) in output_packets.into_iter().enumerate() {
+            match packet {
+                Some(packet) if sizes[i] == mem::size_of::<AudioPacket>() => {
+                    packet.copy_from_slice(self.outputs[i].as_bytes())
                 }
-            },
-            Err(_) => Err(Error::NoData),
+                Some(packet) if sizes[i] != 0 => packet.copy_from_slice(keepalive.as_bytes()),
+                Some(_) => {}
+                None => failed[i] = true,
+            }
         }
+        Ok(failed)
     }
 
-    fn send_directive(&mut self, directive: &Directive) -> Result<(), Error> {
-        trace!("=> {:?}", directive);
-        let mut buf = [0; 2048];
-        match postcard::to_slice(directive, &mut buf) {
-            Ok(res) => self.interface.send_directive(res),
-            Err(e) => {
-                info!("Postcard Parse Error: {:?}", e);
-                Err(Error::Parse)
+    /// Poll the network interface, counting the jacks that failed to send instead of giving up
+    /// on the whole poll.
+    fn poll_interface(&mut self, time: i64) -> Result<u32, Error> {
+        let mut failures = match self.interface.poll(MonotonicTime::from_millis(time)) {
+            Ok(()) => 0,
+            Err(Error::Network(SocketId::Output(i), e)) => {
+                info!("Output jack {} send failed: {:?}", i, e);
+                1
+            }
+            Err(e) => return Err(e),
+        };
+        for (i, e) in self.interface.take_send_errors().iter().enumerate() {
+            if let Some(e) = e {
+                info!("Output jack {} send failed: {:?}", i, e);
+                failures += 1;
             }
         }
+        Ok(failures)
     }
 
-    pub fn send_halt(&mut self) {
-        let out = Directive::Halt(DirectiveHalt {
-            uuid: "GLOBAL".into(),
-        });
-        if let Err(e) = self.send_directive(&out) {
-            info!("Halt command failed {:?}", e);
+    /// Try queueing any outputs that didn't fit in the transmit buffer a second time, after the
+    /// interface has had a chance to send. Off by default.
+    pub fn set_send_retry(&mut self, enabled: bool) {
+        self.send_retry = enabled;
+    }
+
+    /// Total number of output packets that couldn't be sent, such as when the transmit buffer
+    /// was full.
+    pub fn send_failures(&self) -> u32 {
+        self.send_failures
+    }
+
+    /// Poll the network interface without processing a block, for backends that spread their
+    /// sends out over several polls (see `set_pacing` on the native and smoltcp interfaces).
+    pub fn poll_network(&mut self, time: impl Into<MonotonicTime>) -> Result<(), Error> {
+        self.interface.poll(time.into())
+    }
+
+    /// The next millisecond due by the module's clock, if the clock has reached it. A host that
+    /// falls behind gets every one it missed in turn, and polls once for each.
+    pub fn next_tick(&mut self) -> Option<MonotonicTime> {
+        self.ticker.next_tick()
+    }
+
+    /// How long until the next millisecond is due, for hosts that sleep in between.
+    pub fn until_next_tick(&mut self) -> Duration {
+        self.ticker.until_next()
+    }
+
+    pub fn clock_mut(&mut self) -> &mut C {
+        self.ticker.clock_mut()
+    }
+
+    /// Limit the bandwidth (in bits per second) that the multicast audio streams are allowed to
+    /// use. The module coordinating the patch refuses new connections that would exceed its
+    /// budget, on the network as a whole or on the link into the patched module.
+    pub fn set_bandwidth_budget(&mut self, budget: u32) {
+        self.bandwidth.set_budget(budget);
+    }
+
+    pub fn bandwidth_stats(&self) -> BandwidthStats {
+        self.bandwidth
+            .stats(self.input_streams(), self.ping_patch.sample_rate())
+    }
+
+    /// The address the network backend last reported taking, such as from DHCP. Backends that
+    /// are handed an address up front don't report one.
+    pub fn address(&self) -> Option<[u8; 4]> {
+        self.address
+    }
+
+    /// Whether the physical link was last reported up.
+    pub fn is_link_up(&self) -> bool {
+        self.link_up
+    }
+
+    /// Input jack packets the network interface has turned away, for spotting a flooded group.
+    pub fn receive_stats(&self) -> ReceiveStats {
+        self.interface.receive_stats()
+    }
+
+    /// Set the sample rate this module runs at. The coordinator of the patch advertises its rate
+    /// in its heartbeats and the modules following it switch over, so the rate actually in use
+    /// is reported through `PollUpdate`. Only `SAMPLE_RATE` is supported for now, since a module
+    /// processes one block per millisecond of its clock whatever the rate.
+    pub fn set_sample_rate(&mut self, rate: SampleRate) -> Result<(), Error> {
+        if !rate.is_supported() {
+            return Err(Error::UnsupportedSampleRate(rate.0));
         }
+        self.ping_patch.set_sample_rate(rate);
+        Ok(())
     }
 
-    pub fn set_input_patch_enabled(
+    pub fn sample_rate(&self) -> SampleRate {
+        self.ping_patch.sample_rate()
+    }
+
+    /// Send a wavetable to user `slot` of the `Storage` of another module. It goes as a single
+    /// transfer over the control port where the backend has one, and is otherwise split up into
+    /// directive-sized pieces.
+    pub fn upload_wavetable(
         &mut self,
-        jack_id: InputJackHandle,
-        status: bool,
+        uuid: Uuid,
+        slot: u8,
+        wavetable: &UserWavetable,
     ) -> Result<(), Error> {
-        if status {
-            self.input_patch_enabled |= 1 << jack_id.0;
-        } else {
-            self.input_patch_enabled &= !(1 << jack_id.0);
+        if self.interface.control_addr().is_some() {
+            let mut data = heapless::Vec::<u8, MAX_TRANSFER_SIZE>::new();
+            for sample in wavetable.samples.iter() {
+                data.extend_from_slice(&sample.to_le_bytes())
+                    .map_err(|_| Error::StorageFull)?;
+            }
+            return self.send_transfer(uuid, TransferKind::Wavetable(slot), &data);
         }
-        self.update_patch_state()
+        for (i, chunk) in wavetable.samples.chunks(WAVETABLE_CHUNK).enumerate() {
+            let upload = DirectiveWavetableUpload {
+                uuid: uuid.clone(),
+                slot,
+                offset: (i * WAVETABLE_CHUNK) as u16,
+                samples: heapless::Vec::from_slice(chunk).unwrap(),
+            };
+            self.send_directive(&Directive::WavetableUpload(upload))?;
+        }
+        Ok(())
     }
 
-    pub fn set_output_patch_enabled(
+    /// Send `data` to another module over the control ports of the two, for payloads too large
+    /// for a directive such as presets, descriptions and firmware. Follow it with
+    /// `transfer_status`, since only one transfer is sent at a time.
+    pub fn send_transfer(
         &mut self,
-        jack_id: OutputJackHandle,
-        status: bool,
+        uuid: Uuid,
+        kind: TransferKind,
+        data: &[u8],
     ) -> Result<(), Error> {
-        if status {
-            self.output_patch_enabled |= 1 << jack_id.0;
-        } else {
-            self.output_patch_enabled &= !(1 << jack_id.0);
-        }
-        self.update_patch_state()
+        let addr = self
+            .interface
+            .control_addr()
+            .ok_or(Error::Network(SocketId::Control, NetworkError::Unsupported))?;
+        let offer = self.bulk.start(uuid, kind, data, addr)?;
+        self.send_directive(&offer)
     }
 
-    fn update_patch_state(&mut self) -> Result<(), Error> {
-        let mut local_state: LocalState = Default::default();
-        for i in 0..I {
-            if (self.input_patch_enabled & (1 << i)) != 0 {
-                if local_state.held_input.is_none() {
-                    local_state.held_input = Some(HeldInputJack {
-                        uuid: self.uuid.clone(),
-                        id: i as u32,
-                    });
-                }
-                local_state.num_held_inputs += 1;
-            }
-        }
-        for i in 0..O {
-            if (self.output_patch_enabled & (1 << i)) != 0 {
-                if local_state.held_output.is_none() {
-                    local_state.held_output = Some(HeldOutputJack {
-                        uuid: self.uuid.clone(),
-                        id: i as u32,
-                        color: self.color,
-                        addr: self.interface.jack_addr(i)?,
-                    });
-                }
-                local_state.num_held_outputs += 1;
-            }
+    /// Send a preset to `slot` of the `Storage` of another module.
+    pub fn send_preset(&mut self, uuid: Uuid, slot: u8, preset: &ParamPreset) -> Result<(), Error> {
+        let mut buf = [0; 256];
+        match postcard::to_slice(preset, &mut buf) {
+            Ok(res) => self.send_transfer(uuid, TransferKind::Preset(slot), res),
+            Err(e) => Err(Error::Parse(ParseError::Postcard(e))),
         }
-        self.ping_patch.update_local_state(local_state);
-        Ok(())
     }
 
-    fn process_gsu(&mut self, gsu: DirectiveGlobalStateUpdate, time: i64) {
-        self.patch_state = gsu.patch_state;
-        if let Some(input) = gsu.input {
-            if input.uuid == self.uuid && gsu.patch_state == PatchState::PatchToggled {
-                if let Some(output) = gsu.output {
-                    self.toggle_input_jack(input.id as usize, output, time);
+    /// Progress of the last transfer sent by this module.
+    pub fn transfer_status(&self) -> TransferStatus {
+        self.bulk.status()
+    }
+
+    /// The transfer that finished arriving during the last poll, if any.
+    pub fn received_transfer(&self) -> Option<Transfer<'_>> {
+        self.bulk.received()
+    }
+
+    /// Write a wavetable or preset that arrived during the last poll into `storage`. Other kinds
+    /// of transfer are left to `received_transfer`.
+    pub fn store_transfer<S: Storage>(&self, storage: &mut S) -> Result<(), Error> {
+        match self.bulk.received() {
+            Some(Transfer {
+                kind: TransferKind::Wavetable(slot),
+                data,
+                ..
+            }) => {
+                let mut samples = [0; dsp::oscillators::WAVETABLE_SIZE];
+                for (sample, bytes) in samples.iter_mut().zip(data.chunks_exact(2)) {
+                    *sample = i16::from_le_bytes([bytes[0], bytes[1]]);
                 }
+                let len = (data.len() / 2).min(samples.len());
+                storage.write_wavetable(slot as usize, 0, &samples[..len])
             }
+            Some(Transfer {
+                kind: TransferKind::Preset(slot),
+                data,
+                ..
+            }) => match postcard::from_bytes(data) {
+                Ok(preset) => storage.write_preset(slot as usize, preset),
+                Err(e) => Err(Error::Parse(ParseError::Postcard(e))),
+            },
+            _ => Ok(()),
         }
     }
 
-    fn toggle_input_jack(&mut self, jack_id: usize, output: HeldOutputJack, time: i64) {
-        // For now this is just a switch rather than a toggle
-        match self.interface.jack_connect(jack_id, output.addr, time) {
-            Ok(_) => self.input_colors[jack_id] = output.color,
-            Err(e) => info!("Jack connection error: {:?}", e),
-        }
+    /// Stop sending output jacks that have been silent for a few blocks. Receivers are told
+    /// about the pause through a small keepalive packet and fill in the silence themselves.
+    pub fn set_silence_suppression(&mut self, enabled: bool) {
+        self.silence_suppression = enabled;
     }
-}
 
-pub struct ProcessBlock<'a, const I: usize, const O: usize> {
-    input: [&'a AudioPacket; I],
-    output: [&'a mut AudioPacket; O],
-}
+    /// Listen in on whichever output jack is held down on the network through monitor jack
+    /// `handle`, so that it can be heard before it is patched anywhere. The probe is made when a
+    /// single output is held with no input, and torn down once it is let go or patched.
+    pub fn set_audition(&mut self, handle: Option<MonitorJackHandle>) {
+        self.audition = handle;
+    }
 
-impl<'a, const I: usize, const O: usize> ProcessBlock<'a, I, O> {
-    pub fn new(input: [&'a AudioPacket; I], output: [&'a mut AudioPacket; O]) -> Self {
-        ProcessBlock { input, output }
+    /// How to catch up after the host falls behind the module's clock.
+    pub fn set_catch_up(&mut self, policy: CatchUpPolicy) {
+        self.catch_up = policy;
     }
 
-    pub fn get_input(&self, handle: InputJackHandle) -> &AudioPacket {
-        self.input[handle.0]
+    /// When to step down the quality of processing while the host keeps running over its time
+    /// for each poll, as reported through `report_poll_time`. Never steps down by default.
+    pub fn set_overrun_policy(&mut self, policy: OverrunPolicy) {
+        self.overrun.set_policy(policy);
     }
 
-    pub fn set_output(&mut self, handle: OutputJackHandle, data: AudioPacket) {
-        *self.output[handle.0] = data;
+    /// Tell the module how long the host's last pass through its loop took, including the poll,
+    /// for hosts that time themselves, such as hardware with a fixed 1 ms budget.
+    pub fn report_poll_time(&mut self, micros: u32) {
+        self.overrun.report(micros);
     }
 
-    pub fn get_mut_output(&mut self, handle: OutputJackHandle) -> &mut AudioPacket {
-        &mut self.output[handle.0]
+    /// Quality the module is processing at, as stepped down under the overrun policy.
+    pub fn quality(&self) -> Quality {
+        self.overrun.quality()
     }
+
+    /// Polls reported over budget since the module started.
+    pub fn overruns(&self) -> u32 {
+        self.overrun.total()
+    }
+
+    /// Whether the block at `time` is being caught up on, and whether it should be processed and
+    /// sent if so.
+    fn catch_up_poll(&mut self, time: i64) -> (bool, bool) {
+        let now = self.ticker.clock_mut().now().as_millis();
+        if now != self.now {
+            self.now = now;
+            self.polls_at_now = 0;
+        }
+        self.polls_at_now = self.polls_at_now.saturating_add(1);
+        if time < now {
+            (
+                !self.catch_up.skip_audio,
+                !self.catch_up.skip_audio && self.polls_at_now <= self.catch_up.max_polls_per_ms,
+            )
+        } else {
+            (true, true)
+        }
+    }
+
+    /// Delay inputs that reach this module sooner than others so that they all line up, such as
+    /// for a mixer with one signal patched in directly and another through an effect. Each module
+    /// advertises how many blocks its outputs are behind the start of the patch, and inputs are
+    /// held back by up to `MAX_ALIGN_DELAY` blocks to match the slowest one. Off by default.
+    pub fn set_latency_compensation(&mut self, enabled: bool) {
+        self.latency_compensation = enabled;
+    }
+
+    /// Whether the connection on an input jack was flagged by the coordinator as closing a
+    /// feedback loop (see `FeedbackPolicy::Warn`).
+    pub fn is_feedback_input(&self, handle: InputJackHandle) -> bool {
+        self.feedback_inputs[handle.0]
+    }
+
+    /// Run the connection on an input jack through a soft limiter (see `dsp::mix::soft_limit`),
+    /// so that a feedback loop with too much gain squashes at the top rather than wrapping
+    /// around. Connections flagged as closing a loop start out limited and others don't, and a
+    /// new connection on the jack starts over.
+    pub fn set_feedback_limiter(&mut self, handle: InputJackHandle, enabled: bool) {
+        self.limited_inputs[handle.0] = enabled;
+    }
+
+    /// Blocks between the start of the patch and this module's outputs, taking each hop from one
+    /// module to the next to be a block. Zero for modules with nothing patched into them.
+    pub fn latency(&self) -> u8 {
+        (0..I)
+            .filter_map(|i| self.input_latency(i))
+            .map(|latency| latency.saturating_add(1))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Latency of the source patched into an input, if it is connected.
+    fn input_latency(&self, jack_id: usize) -> Option<u8> {
+        if self.is_monitor(jack_id) || !self.connected_inputs[jack_id] {
+            return None;
+        }
+        let source = self.input_sources[jack_id].as_ref()?;
+        Some(self.bandwidth.latency(&source.uuid))
+    }
+
+    /// Set how many voices this module's outputs carry, counting from the first channel, for
+    /// sources of notes like MIDI interfaces and sequencers that only play some of them. The
+    /// count follows the connections down the patch, so that modules further on can skip
+    /// processing the unused channels (see `BlockContext::voices`). With `None`, the outputs
+    /// carry as many voices as the inputs do.
+    pub fn set_voices(&mut self, voices: Option<u8>) {
+        self.voices = voices.map(|voices| voices.clamp(1, CHANNELS as u8));
+    }
+
+    /// Voices in use on this module's outputs: as set with `set_voices`, or else the most of any
+    /// connected input. Inputs with a channel map may spread their voices over every channel, and
+    /// modules with nothing patched into them are taken to use every channel.
+    pub fn voices(&self) -> u8 {
+        if let Some(voices) = self.voices {
+            return voices;
+        }
+        (0..I)
+            .filter_map(|i| self.input_voices(i))
+            .max()
+            .unwrap_or(CHANNELS as u8)
+    }
+
+    /// Voices in use on the source patched into an input, if it is connected.
+    fn input_voices(&self, jack_id: usize) -> Option<u8> {
+        if self.is_monitor(jack_id) || !self.connected_inputs[jack_id] {
+            return None;
+        }
+        if self.mapped_inputs[jack_id] {
+            return Some(CHANNELS as u8);
+        }
+        let source = self.input_sources[jack_id].as_ref()?;
+        Some(self.bandwidth.voices(&source.uuid))
+    }
+
+    /// How many blocks to hold back each input to line it up with the slowest one.
+    fn align_delays(&self) -> [usize; I] {
+        let mut latencies = [None; I];
+        for (i, latency) in latencies.iter_mut().enumerate() {
+            *latency = self.input_latency(i);
+        }
+        let slowest = latencies.iter().flatten().max().copied().unwrap_or(0);
+        let mut delays = [0; I];
+        for (delay, latency) in delays.iter_mut().zip(latencies) {
+            if let Some(latency) = latency {
+                *delay = ((slowest - latency) as usize).min(MAX_ALIGN_DELAY);
+            }
+        }
+        delays
+    }
+
+    /// Keep processing and sending all output jacks, even if nothing is subscribed to them.
+    pub fn set_free_running(&mut self, enabled: bool) {
+        self.free_running = enabled;
+    }
+
+    /// Take on the role of permanent coordinator of the patch, for installations with a known
+    /// supervisor such as a desktop manager. The coordinator works out the global patch state
+    /// from everyone's held jacks and sends it out every heartbeat, and the other modules follow
+    /// it rather than each working it out for themselves, until it goes quiet. With more than one
+    /// coordinator, the one with the highest `Capability` leads and the others stand by.
+    pub fn set_coordinator(&mut self, enabled: bool) {
+        self.ping_patch.set_coordinator(enabled);
+    }
+
+    pub fn is_coordinator(&self) -> bool {
+        self.ping_patch.is_coordinator()
+    }
+
+    /// What to do, while coordinating, with a patch that would close a feedback loop. Loops are
+    /// found from the connections acknowledged on the network since the module started, so a
+    /// connection made before then is only seen once its output module replays it.
+    pub fn set_feedback_policy(&mut self, policy: FeedbackPolicy) {
+        self.ping_patch.set_feedback_policy(policy);
+    }
+
+    /// How this module ranks when several could coordinate the patch. Defaults to `Desktop` on
+    /// std hosts and `Embedded` otherwise.
+    pub fn set_capability(&mut self, capability: Capability) {
+        self.ping_patch.set_capability(capability);
+    }
+
+    pub fn capability(&self) -> Capability {
+        self.ping_patch.capability()
+    }
+
+    fn is_output_active(&self, jack_id: usize, time: i64) -> bool {
+        self.free_running || time < self.subscribers[jack_id]
+    }
+
+    fn active_output_streams(&self, time: i64) -> u8 {
+        (0..self.output_jack_handles)
+            .filter(|i| {
+                self.silent_blocks[*i] <= SILENCE_HOLDOFF && self.is_output_active(*i, time)
+            })
+            .count() as u8
+    }
+
+    fn input_streams(&self) -> u8 {
+        self.connected_inputs
+            .iter()
+            .filter(|&&connected| connected)
+            .count() as u8
+    }
+
+    fn connect_input_jack(
+        &mut self,
+        jack_id: usize,
+        source: DirectiveSubscribe,
+        addr: [u8; 4],
+        time: i64,
+    ) -> Result<(), Error> {
+        self.interface
+            .jack_connect(jack_id, addr, MonotonicTime::from_millis(time))?;
+        self.connected_inputs[jack_id] = true;
+        self.input_addrs[jack_id] = addr;
+        // Let the source know right away that someone is listening
+        self.input_sources[jack_id] = Some(source.clone());
+        if let Err(e) = self.send_directive(&Directive::Subscribe(source)) {
+            info!("Subscribe failed {:?}", e);
+        }
+        Ok(())
+    }
+
+    fn jack_event(&mut self, event: JackEvent) {
+        if self.jack_events.push(event).is_err() {
+            info!("Jack event queue full");
+        }
+    }
+
+    fn is_monitor(&self, jack_id: usize) -> bool {
+        self.monitor_jacks[jack_id]
+    }
+
+    pub fn can_send(&mut self) -> bool {
+        self.interface.can_send()
+    }
+
+    /// Directives are tagged with the module that sent them. Whether a module hears its own
+    /// multicast depends on the backend, so those are always dropped here and the ones it needs
+    /// are handed back through `loopback` instead, to be handled exactly once.
+    fn recv_directive(&mut self) -> Result<Directive, Error> {
+        if let Some(directive) = self.loopback.pop_front() {
+            trace!("<= {:?}", directive);
+            return Ok(directive);
+        }
+        let mut buf = [0; 2048];
+        match self.interface.recv_directive(&mut buf) {
+            Ok(size) => match postcard::from_bytes::<(Uuid, Directive)>(&buf[0..size]) {
+                Ok((origin, _)) if origin == self.uuid => Err(Error::NoData),
+                Ok((_, out)) => {
+                    trace!("<= {:?}", out);
+                    Ok(out)
+                }
+                Err(e) => {
+                    info!("Postcard Parse Error: {:?}", e);
+                    Err(Error::Parse(ParseError::Postcard(e)))
+                }
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    fn send_directive(&mut self, directive: &Directive) -> Result<(), Error> {
+        trace!("=> {:?}", directive);
+        let loops_back = directive.loops_back(&self.uuid);
+        // Everyone else would act on a directive this module never gets back, so don't send it
+        if loops_back && self.loopback.is_full() {
+            info!("Loopback queue full");
+            return Err(Error::StorageFull);
+        }
+        let mut buf = [0; 2048];
+        match postcard::to_slice(&(&self.uuid, directive), &mut buf) {
+            Ok(res) => {
+                self.interface.send_directive(res)?;
+                if loops_back {
+                    // Room was checked for above
+                    let _ = self.loopback.push_back(directive.clone());
+                }
+                Ok(())
+            }
+            Err(e) => {
+                info!("Postcard Parse Error: {:?}", e);
+                Err(Error::Parse(ParseError::Postcard(e)))
+            }
+        }
+    }
+
+    pub fn send_halt(&mut self) {
+        let out = Directive::Halt(DirectiveHalt {
+            uuid: "GLOBAL".into(),
+        });
+        if let Err(e) = self.send_directive(&out) {
+            info!("Halt command failed {:?}", e);
+        }
+        self.ping_patch.clear_patch();
+    }
+
+    /// Put every module on the network, this one included, in standby.
+    pub fn send_standby(&mut self) {
+        let out = Directive::Standby(DirectiveStandby {
+            uuid: "GLOBAL".into(),
+        });
+        if let Err(e) = self.send_directive(&out) {
+            info!("Standby command failed {:?}", e);
+        }
+    }
+
+    /// Wake every module on the network, this one included, from standby.
+    pub fn send_wake(&mut self) {
+        let out = Directive::Wake(DirectiveWake {
+            uuid: "GLOBAL".into(),
+        });
+        if let Err(e) = self.send_directive(&out) {
+            info!("Wake command failed {:?}", e);
+        }
+    }
+
+    /// Enter or leave standby on this module only.
+    ///
+    /// In standby, blocks are still processed but output jacks are paused (as with silence
+    /// suppression), jack colors are dimmed, and stats and subscriptions are sent less often.
+    /// Any patch activity wakes the module up again.
+    pub fn set_standby(&mut self, enabled: bool) {
+        if enabled == self.standby {
+            return;
+        }
+        info!(
+            "{} {} standby",
+            self.uuid,
+            if enabled { "entering" } else { "leaving" }
+        );
+        self.standby = enabled;
+        self.bandwidth.set_standby(enabled);
+        if enabled {
+            // Skip the silence holdoff and announce the pause on the next block
+            for blocks in self.silent_blocks.iter_mut() {
+                *blocks = (*blocks).max(SILENCE_HOLDOFF);
+            }
+        } else {
+            // Let sources know right away that their outputs are wanted again
+            self.subscribe_timeout = i64::MIN;
+        }
+    }
+
+    pub fn is_standby(&self) -> bool {
+        self.standby
+    }
+
+    /// Silence every output of this module without tearing down its patch. The jacks keep
+    /// sending (or pause, with silence suppression) and show `MUTED_COLOR`.
+    pub fn set_mute(&mut self, enabled: bool) {
+        if enabled != self.muted {
+            info!(
+                "{} {}",
+                self.uuid,
+                if enabled { "muted" } else { "unmuted" }
+            );
+        }
+        self.muted = enabled;
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Copy each input jack straight to the output jack with the same index instead of the
+    /// processed block, leaving any outputs past the last input silent. Mute takes precedence.
+    pub fn set_bypass(&mut self, enabled: bool) {
+        if enabled != self.bypassed {
+            info!(
+                "{} {}",
+                self.uuid,
+                if enabled { "bypassed" } else { "processing" }
+            );
+        }
+        self.bypassed = enabled;
+    }
+
+    pub fn is_bypassed(&self) -> bool {
+        self.bypassed
+    }
+
+    /// Mute or unmute module `uuid`, this one included.
+    pub fn send_mute(&mut self, uuid: Uuid, mute: bool) -> Result<(), Error> {
+        let out = Directive::SetMute(DirectiveSetMute { uuid, mute });
+        self.send_directive(&out)
+    }
+
+    /// Bypass module `uuid`, this one included, or set it processing again.
+    pub fn send_bypass(&mut self, uuid: Uuid, bypass: bool) -> Result<(), Error> {
+        let out = Directive::SetBypass(DirectiveSetBypass { uuid, bypass });
+        self.send_directive(&out)
+    }
+
+    /// Retune every module on the network, this one included.
+    pub fn send_tuning(&mut self, tuning: Tuning) -> Result<(), Error> {
+        let out = Directive::Tuning(DirectiveTuning {
+            version: self.tuning_version.next(&self.uuid),
+            tuning,
+        });
+        self.send_directive(&out)
+    }
+
+    /// The tuning last heard from the network, or the default until one arrives.
+    pub fn tuning(&self) -> Tuning {
+        self.tuning
+    }
+
+    fn set_tuning(&mut self, tuning: Tuning) {
+        if tuning != self.tuning {
+            info!("Tuning A4 to {} Hz", tuning.a4);
+        }
+        self.tuning = tuning;
+    }
+
+    /// Microtune every module on the network, this one included, from `table`, or go back to
+    /// equal temperament with `None`.
+    pub fn send_scale_table(&mut self, table: Option<ScaleTable>) -> Result<(), Error> {
+        let out = Directive::ScaleTable(DirectiveScaleTable {
+            version: self.scale_table_version.next(&self.uuid),
+            table,
+        });
+        self.send_directive(&out)
+    }
+
+    /// The scale table last heard from the network, if the instrument is microtuned.
+    pub fn scale_table(&self) -> Option<&ScaleTable> {
+        self.scale_table.as_ref()
+    }
+
+    fn set_scale_table(&mut self, table: Option<ScaleTable>) {
+        if table != self.scale_table {
+            match &table {
+                Some(table) => info!("Microtuning to {} degrees", table.degrees.len()),
+                None => info!("Back to equal temperament"),
+            }
+        }
+        self.note_frequencies = table.as_ref().map(ScaleTable::note_frequencies);
+        self.scale_table = table;
+    }
+
+    /// Rearrange the channels arriving on input `jack_id` of module `uuid`, this one included. The
+    /// map goes with the connection, so it's kept when the connection is replayed and dropped
+    /// when the jack is patched to something else.
+    pub fn send_channel_map(
+        &mut self,
+        uuid: Uuid,
+        jack_id: u32,
+        channels: ChannelMap,
+    ) -> Result<(), Error> {
+        let out = Directive::SetChannelMap(DirectiveSetChannelMap {
+            uuid,
+            jack_id,
+            channels,
+        });
+        self.send_directive(&out)
+    }
+
+    /// The channel map of the connection on an input jack.
+    pub fn channel_map(&self, handle: InputJackHandle) -> ChannelMap {
+        self.channel_maps[handle.0]
+    }
+
+    fn set_channel_map_by_id(&mut self, jack_id: usize, channels: ChannelMap) {
+        self.channel_maps[jack_id] = channels;
+        self.mapped_inputs[jack_id] = channels != ChannelMap::Straight;
+    }
+
+    /// Solo module `uuid` in place, muting every other module that isn't heard through it, or let
+    /// the solo go. Only a leader keeping the patch journal knows enough of the patch to do it.
+    pub fn send_solo(&mut self, uuid: Uuid, solo: bool) -> Result<(), Error> {
+        let out = Directive::Solo(DirectiveSolo { uuid, solo });
+        self.send_directive(&out)
+    }
+
+    /// Change parameter `param` of module `uuid`, which finds it in `PollUpdate::param_changes`.
+    pub fn send_set_param(
+        &mut self,
+        uuid: Uuid,
+        param: usize,
+        change: ParamChange,
+    ) -> Result<(), Error> {
+        let out = Directive::SetParam(DirectiveSetParam {
+            uuid,
+            param: param as u8,
+            change,
+        });
+        self.send_directive(&out)
+    }
+
+    /// Step through an A/B comparison of the knobs on module `uuid`, which finds it in
+    /// `PollUpdate::compare_actions`.
+    pub fn send_compare(&mut self, uuid: Uuid, action: CompareAction) -> Result<(), Error> {
+        let out = Directive::Compare(DirectiveCompare { uuid, action });
+        self.send_directive(&out)
+    }
+
+    /// Keep the knob settings of module `uuid` in preset `slot` of its `Storage`, named `name`.
+    /// Names longer than `PRESET_NAME_LEN` are cut short.
+    pub fn send_store_preset(&mut self, uuid: Uuid, slot: usize, name: &str) -> Result<(), Error> {
+        let mut short = heapless::String::new();
+        for c in name.chars() {
+            if short.push(c).is_err() {
+                break;
+            }
+        }
+        let out = Directive::StorePreset(DirectiveStorePreset {
+            uuid,
+            slot: slot as u8,
+            name: short,
+        });
+        self.send_directive(&out)
+    }
+
+    /// Set the knobs of module `uuid` to its preset in `slot`, which it finds in
+    /// `PollUpdate::preset_actions`.
+    pub fn send_recall_preset(&mut self, uuid: Uuid, slot: usize) -> Result<(), Error> {
+        let out = Directive::RecallPreset(DirectiveRecallPreset {
+            uuid,
+            slot: slot as u8,
+        });
+        self.send_directive(&out)
+    }
+
+    /// Keep only the latest change of each kind to a parameter, since each replaces the last.
+    fn queue_param_change(&mut self, param: u8, change: ParamChange) {
+        let same = |(p, c): &&mut (u8, ParamChange)| {
+            *p == param && mem::discriminant(c) == mem::discriminant(&change)
+        };
+        match self.param_changes.iter_mut().find(same) {
+            Some(queued) => queued.1 = change,
+            None => {
+                if self.param_changes.push((param, change)).is_err() {
+                    info!("Parameter change queue full");
+                }
+            }
+        }
+    }
+
+    /// Say what this module is and what is on its front panel, for other modules that ask with
+    /// `send_describe_request`. Modules made with `define_module!` can pass `SPEC.describe()`.
+    pub fn set_description(&mut self, description: ModuleDescription) {
+        self.description = Some(description);
+    }
+
+    /// Ask module `uuid` to describe itself. The answer turns up in
+    /// `PollUpdate::peer_description` if it has a description set, and both modules have a
+    /// control port to send it over.
+    pub fn send_describe_request(&mut self, uuid: Uuid) -> Result<(), Error> {
+        let out = Directive::DescribeRequest(DirectiveDescribeRequest {
+            uuid,
+            source: self.uuid.clone(),
+        });
+        self.send_directive(&out)
+    }
+
+    /// Answer a describe request from module `uuid`, if this module has a description.
+    fn send_description(&mut self, uuid: Uuid) {
+        let description = match &self.description {
+            Some(description) => description,
+            None => return,
+        };
+        let mut buf = [0; MAX_TRANSFER_SIZE];
+        let res = match postcard::to_slice(description, &mut buf) {
+            Ok(res) => self.send_transfer(uuid, TransferKind::Description, res),
+            Err(e) => Err(Error::Parse(ParseError::Postcard(e))),
+        };
+        // Whoever asked can always ask again
+        if let Err(e) = res {
+            info!("Description not sent: {:?}", e);
+        }
+    }
+
+    /// Other modules heard from recently, by uuid.
+    pub fn peers(&self) -> impl Iterator<Item = &Uuid> {
+        self.bandwidth
+            .hosts()
+            .filter(move |uuid| **uuid != self.uuid)
+    }
+
+    pub fn set_input_patch_enabled(
+        &mut self,
+        jack_id: InputJackHandle,
+        status: bool,
+    ) -> Result<(), Error> {
+        if status {
+            // Other modules wake up once they see the patch in a global state update
+            self.set_standby(false);
+        }
+        self.input_patch_enabled[jack_id.0] = status;
+        self.update_patch_state()
+    }
+
+    pub fn set_output_patch_enabled(
+        &mut self,
+        jack_id: OutputJackHandle,
+        status: bool,
+    ) -> Result<(), Error> {
+        if status {
+            self.set_standby(false);
+        }
+        self.output_patch_enabled[jack_id.0] = status;
+        self.update_patch_state()
+    }
+
+    fn update_patch_state(&mut self) -> Result<(), Error> {
+        let mut local_state: LocalState = Default::default();
+        for i in 0..I {
+            if self.input_patch_enabled[i] {
+                if local_state.held_input.is_none() {
+                    local_state.held_input = Some(HeldInputJack {
+                        uuid: self.uuid.clone(),
+                        id: i as u32,
+                    });
+                }
+                local_state.num_held_inputs += 1;
+            }
+        }
+        for i in 0..O {
+            if self.output_patch_enabled[i] {
+                if local_state.held_output.is_none() {
+                    local_state.held_output = Some(HeldOutputJack {
+                        uuid: self.uuid.clone(),
+                        id: i as u32,
+                        color: self.color,
+                        addr: self.interface.jack_addr(i)?,
+                    });
+                }
+                local_state.num_held_outputs += 1;
+            }
+        }
+        self.ping_patch.update_local_state(local_state);
+        Ok(())
+    }
+
+    fn process_gsu(&mut self, gsu: DirectiveGlobalStateUpdate, time: i64) {
+        self.patch_state = gsu.patch_state;
+        self.patch_feedback = gsu.feedback;
+        self.held_input = gsu.input.as_ref().map(|input| JackPeer {
+            uuid: input.uuid.clone(),
+            jack_id: input.id,
+        });
+        self.held_output = gsu.output.as_ref().map(|output| JackPeer {
+            uuid: output.uuid.clone(),
+            jack_id: output.id,
+        });
+        if gsu.patch_state != PatchState::Idle {
+            self.set_standby(false);
+        }
+        if gsu.patch_state != PatchState::PatchToggled {
+            return;
+        }
+        if let (Some(input), Some(output)) = (gsu.input, gsu.output) {
+            let set = DirectiveSetInputJack {
+                uuid: input.uuid.clone(),
+                connection: PatchConnection {
+                    input_uuid: input.uuid.clone(),
+                    input_jack_id: input.id,
+                    output_uuid: output.uuid.clone(),
+                    output_jack_id: output.id,
+                },
+                source: output,
+                stamp: 0,
+                feedback: gsu.feedback,
+                channels: Default::default(),
+            };
+            if input.uuid == self.uuid {
+                self.process_set_input_jack(set, self.ping_patch.term(), time);
+            } else if set.source.uuid == self.uuid {
+                // The input module makes the connection on its own when it sees the same update,
+                // but keep after it until it confirms
+                self.pending_connection = Some(PendingConnection {
+                    directive: set,
+                    retries: 0,
+                    backoff: ACK_TIMEOUT,
+                    timeout: time + ACK_TIMEOUT,
+                });
+            }
+        }
+    }
+
+    /// Connect an input jack as asked, by a module that last followed the coordinator of `term`.
+    fn process_set_input_jack(&mut self, set: DirectiveSetInputJack, term: u32, time: i64) {
+        let jack_id = set.connection.input_jack_id as usize;
+        if jack_id >= self.input_jack_handles {
+            return;
+        }
+        let source = DirectiveSubscribe {
+            uuid: set.source.uuid.clone(),
+            jack_id: set.source.id,
+        };
+        // Connections replayed after a restart or a network partition can conflict with what is
+        // on the jack now. The side of a partition on the later term wins, and otherwise the
+        // newest one does, with ties going to the higher source id.
+        let stale = match &self.input_sources[jack_id] {
+            Some(current) => match term.cmp(&self.ping_patch.term()) {
+                Ordering::Less => true,
+                Ordering::Greater => false,
+                Ordering::Equal => {
+                    set.stamp != 0
+                        && (set.stamp, &source.uuid) < (self.input_stamps[jack_id], &current.uuid)
+                }
+            },
+            None => false,
+        };
+        let success = if self.input_sources[jack_id].as_ref() == Some(&source)
+            && self.input_addrs[jack_id] == set.source.addr
+        {
+            // Already connected, likely a retry after a lost acknowledgement, or a replay that
+            // carries the latest channel map
+            self.set_channel_map_by_id(jack_id, set.channels);
+            true
+        } else if stale {
+            info!("Ignoring stale connection: {:?}", set.connection);
+            false
+        } else if self.toggle_input_jack(jack_id, set.source, time) {
+            self.clock = self.clock.max(set.stamp) + 1;
+            self.input_stamps[jack_id] = self.clock;
+            // A new connection starts over with the limiter in only if it closes a loop
+            self.feedback_inputs[jack_id] = set.feedback;
+            self.limited_inputs[jack_id] = set.feedback;
+            self.set_channel_map_by_id(jack_id, set.channels);
+            true
+        } else {
+            false
+        };
+        let ack = Directive::SetInputJackAck(DirectiveSetInputJackAck {
+            uuid: self.uuid.clone(),
+            connection: set.connection,
+            success,
+            stamp: self.input_stamps[jack_id],
+            channels: self.channel_maps[jack_id],
+        });
+        if let Err(e) = self.send_directive(&ack) {
+            info!("Acknowledgement failed {:?}", e);
+        }
+    }
+
+    fn process_set_input_jack_ack(&mut self, ack: DirectiveSetInputJackAck) {
+        self.clock = self.clock.max(ack.stamp);
+        // An input jack only has a single source, so a connection to the same input from
+        // anywhere else replaces ours, and one that was refused is gone
+        self.connections.retain(|c| {
+            if c.connection.input_uuid != ack.connection.input_uuid
+                || c.connection.input_jack_id != ack.connection.input_jack_id
+            {
+                true
+            } else if c.connection == ack.connection {
+                ack.success
+            } else {
+                !ack.success
+            }
+        });
+        if ack.success {
+            for c in self.connections.iter_mut() {
+                if c.connection == ack.connection {
+                    c.stamp = ack.stamp;
+                }
+            }
+        }
+        if let Some(mut pending) = self.pending_connection.take() {
+            if pending.directive.connection != ack.connection {
+                self.pending_connection = Some(pending);
+            } else if !ack.success {
+                info!("Connection refused: {:?}", ack.connection);
+                self.patch_state = PatchState::Failed;
+            } else {
+                pending.directive.stamp = ack.stamp;
+                if self.connections.push(pending.directive).is_err() {
+                    info!("Connection table full");
+                }
+            }
+        }
+        // Connections from our outputs can also be made on our behalf, such as when a journal
+        // restores the patch, and are kept to be replayed like any other
+        if ack.success
+            && ack.connection.output_uuid == self.uuid
+            && !self
+                .connections
+                .iter()
+                .any(|c| c.connection == ack.connection)
+        {
+            let id = ack.connection.output_jack_id;
+            match self.interface.jack_addr(id as usize) {
+                Ok(addr) => {
+                    let set = DirectiveSetInputJack {
+                        uuid: ack.connection.input_uuid.clone(),
+                        source: HeldOutputJack {
+                            uuid: self.uuid.clone(),
+                            id,
+                            color: self.color,
+                            addr,
+                        },
+                        connection: ack.connection,
+                        stamp: ack.stamp,
+                        feedback: false,
+                        channels: ack.channels,
+                    };
+                    if self.connections.push(set).is_err() {
+                        info!("Connection table full");
+                    }
+                }
+                Err(e) => info!("Unknown output in acknowledgement: {:?}", e),
+            }
+        }
+    }
+
+    /// Keep a journal of every connection made on the network, and restore the connections
+    /// already in it as their modules appear. Meant for a desktop module that outlives the rest
+    /// of the patch, like a manager, so that the patch comes back after it crashes and restarts.
+    #[cfg(feature = "std")]
+    pub fn set_journal(&mut self, journal: journal::PatchJournal) {
+        self.journal = Some(journal);
+    }
+
+    #[cfg(feature = "std")]
+    pub fn journal_mut(&mut self) -> Option<&mut journal::PatchJournal> {
+        self.journal.as_mut()
+    }
+
+    fn process_set_channel_map(&mut self, set: DirectiveSetChannelMap) {
+        let jack_id = set.jack_id as usize;
+        if set.uuid == self.uuid && jack_id < self.input_jack_handles && !self.is_monitor(jack_id) {
+            self.set_channel_map_by_id(jack_id, set.channels);
+        }
+        // Connections from our outputs are replayed with the map they have now
+        for c in self.connections.iter_mut() {
+            if c.connection.input_uuid == set.uuid && c.connection.input_jack_id == set.jack_id {
+                c.channels = set.channels;
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn process_solo(&mut self, solo: DirectiveSolo) -> Result<(), Error> {
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => return Ok(()),
+        };
+        let (unmute, mute) = self
+            .solo
+            .set(journal.connections(), solo.uuid.clone(), solo.solo);
+        info!(
+            "{} {}: muting {} modules, unmuting {}",
+            if solo.solo { "Soloing" } else { "Unsoloing" },
+            solo.uuid,
+            mute.len(),
+            unmute.len()
+        );
+        for uuid in unmute {
+            self.send_mute(uuid, false)?;
+        }
+        for uuid in mute {
+            self.send_mute(uuid, true)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "std")]
+    fn journal_ack(&mut self, ack: &DirectiveSetInputJackAck) {
+        if let (true, Some(journal)) = (ack.success, &mut self.journal) {
+            if let Err(e) = journal.record(&ack.connection) {
+                info!("Patch journal write failed: {}", e);
+            }
+        }
+    }
+
+    /// Ask for the current address of each output jack with a connection waiting to be
+    /// restored, once both of its modules are back on the network.
+    #[cfg(feature = "std")]
+    fn restore_patch(&mut self, time: i64) -> Result<(), Error> {
+        if time % RESTORE_INTERVAL != 0 {
+            return Ok(());
+        }
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => return Ok(()),
+        };
+        let hosts: std::vec::Vec<&Uuid> = self.bandwidth.hosts().collect();
+        let mut requests: std::vec::Vec<DirectiveProbeRequest> = std::vec::Vec::new();
+        for c in journal.restorable(&hosts) {
+            let req = DirectiveProbeRequest {
+                uuid: c.output_uuid.clone(),
+                jack_id: c.output_jack_id,
+            };
+            if !requests.contains(&req) {
+                requests.push(req);
+            }
+        }
+        for req in requests {
+            self.send_directive(&Directive::ProbeRequest(req))?;
+        }
+        Ok(())
+    }
+
+    /// Send the connections waiting on an output jack to their input modules, now that its
+    /// address is known.
+    #[cfg(feature = "std")]
+    fn restore_from_probe(&mut self, resp: &DirectiveProbeResponse) -> Result<(), Error> {
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => return Ok(()),
+        };
+        let source = HeldOutputJack {
+            uuid: resp.uuid.clone(),
+            id: resp.jack_id,
+            color: resp.color,
+            addr: resp.addr,
+        };
+        let mut bulks: std::vec::Vec<DirectiveBulkConnect> = std::vec::Vec::new();
+        for c in journal.restoring_from(&resp.uuid, resp.jack_id) {
+            let set = DirectiveSetInputJack {
+                uuid: c.input_uuid.clone(),
+                source: source.clone(),
+                connection: c.clone(),
+                stamp: 0,
+                feedback: false,
+                channels: Default::default(),
+            };
+            let bulk = match bulks.iter_mut().position(|b| b.uuid == c.input_uuid) {
+                Some(i) => &mut bulks[i],
+                None => {
+                    bulks.push(DirectiveBulkConnect {
+                        uuid: c.input_uuid.clone(),
+                        connections: heapless::Vec::new(),
+                    });
+                    bulks.last_mut().unwrap()
+                }
+            };
+            // Anything left over is asked for again on the next round
+            let _ = bulk.connections.push(set);
+        }
+        for bulk in bulks {
+            info!(
+                "Restoring {} journaled connections to {}",
+                bulk.connections.len(),
+                bulk.uuid
+            );
+            self.send_directive(&Directive::BulkConnect(bulk))?;
+        }
+        Ok(())
+    }
+
+    /// Pick up after the backend moves to a new address. Output jacks can move along with it, so
+    /// the connections from them are offered again with their new addresses, and inputs
+    /// resubscribe right away rather than waiting for their sources to time them out.
+    fn process_network_event(&mut self, event: NetworkEvent, time: i64) -> Result<(), Error> {
+        match event {
+            NetworkEvent::AddressChanged(addr) => {
+                info!("{} address changed to {:?}", self.uuid, addr);
+                self.address = Some(addr);
+                for c in self.connections.iter_mut() {
+                    c.source.addr = self.interface.jack_addr(c.source.id as usize)?;
+                }
+                self.subscribe_timeout = time;
+                self.update_patch_state()?;
+                self.merge_state()
+            }
+            NetworkEvent::LinkChanged(up) => {
+                info!("{} link {}", self.uuid, if up { "up" } else { "down" });
+                self.link_up = up;
+                if !up {
+                    self.address = None;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Check that both ends of every connection on the network agree on it, such as after
+    /// packet loss or a module crashing. Every module answers with digests of its connection
+    /// table and input jacks, and the result is available from `audit_report` after a short
+    /// while.
+    pub fn audit(&mut self, time: impl Into<MonotonicTime>) -> Result<(), Error> {
+        let (inputs, outputs) = self.audit_digests()?;
+        let req = self.audit.start(inputs, outputs, time.into().as_millis());
+        self.send_directive(&req)
+    }
+
+    /// The result of the last audit, once it has finished.
+    pub fn audit_report(&self) -> Option<&AuditReport> {
+        self.audit.report()
+    }
+
+    fn process_audit_request(&mut self, req: DirectiveAuditRequest) -> Result<(), Error> {
+        let (inputs, outputs) = self.audit_digests()?;
+        let resp = Directive::AuditResponse(DirectiveAuditResponse {
+            uuid: self.uuid.clone(),
+            requester: req.uuid,
+            audit: req.audit,
+            inputs,
+            outputs,
+        });
+        self.send_directive(&resp)
+    }
+
+    /// Digests of this module's view of its connections with each other module, from its input
+    /// jacks and from its connection table. Outputs are checked against the address they send to
+    /// now, which inputs should have followed.
+    fn audit_digests(&mut self) -> Result<(Digests, Digests), Error> {
+        let mut inputs = Digests::new();
+        for i in 0..I {
+            if self.is_monitor(i) || !self.connected_inputs[i] {
+                continue;
+            }
+            if let Some(source) = &self.input_sources[i] {
+                let connection = PatchConnection {
+                    input_uuid: self.uuid.clone(),
+                    input_jack_id: i as JackId,
+                    output_uuid: source.uuid.clone(),
+                    output_jack_id: source.jack_id,
+                };
+                audit::add_connection(&mut inputs, &source.uuid, &connection, self.input_addrs[i]);
+            }
+        }
+        let mut outputs = Digests::new();
+        for c in self.connections.iter() {
+            let addr = self.interface.jack_addr(c.source.id as usize)?;
+            audit::add_connection(&mut outputs, &c.connection.input_uuid, &c.connection, addr);
+        }
+        Ok((inputs, outputs))
+    }
+
+    /// Restore the connections from this module's outputs to a module that has just (re)joined
+    /// the network, since it loses all of its input connections on a restart.
+    fn replay_connections(&mut self, uuid: &Uuid) -> Result<(), Error> {
+        let mut bulk = DirectiveBulkConnect {
+            uuid: uuid.clone(),
+            connections: heapless::Vec::new(),
+        };
+        for set in self.connections.iter().filter(|c| &c.uuid == uuid) {
+            if bulk.connections.push(set.clone()).is_err() {
+                break;
+            }
+        }
+        if bulk.connections.is_empty() {
+            return Ok(());
+        }
+        info!(
+            "Restoring {} connections to {}",
+            bulk.connections.len(),
+            uuid
+        );
+        self.send_directive(&Directive::BulkConnect(bulk))
+    }
+
+    /// Offer the whole connection table to the network after a module reappears, such as when a
+    /// partition heals. Each input module keeps whichever connection to its jacks was made on the
+    /// later term, or else is newest, and acknowledges the result, which prunes stale entries
+    /// from every table.
+    fn merge_state(&mut self) -> Result<(), Error> {
+        for chunk in self.connections.clone().chunks(MAX_BULK_CONNECTIONS) {
+            let merge = DirectiveStateMerge {
+                uuid: self.uuid.clone(),
+                term: self.ping_patch.term(),
+                connections: heapless::Vec::from_slice(chunk).unwrap(),
+            };
+            self.send_directive(&Directive::StateMerge(merge))?;
+        }
+        Ok(())
+    }
+
+    /// Resend unacknowledged connections with exponential backoff, and give up after
+    /// `MAX_CONNECT_RETRIES` attempts.
+    fn retry_connection(&mut self, time: i64) -> Result<(), Error> {
+        let directive = match &mut self.pending_connection {
+            Some(pending) if time >= pending.timeout => {
+                if pending.retries == MAX_CONNECT_RETRIES {
+                    info!(
+                        "Connection not acknowledged: {:?}",
+                        pending.directive.connection
+                    );
+                    self.pending_connection = None;
+                    self.patch_state = PatchState::Failed;
+                    return Ok(());
+                }
+                pending.retries += 1;
+                pending.backoff *= 2;
+                pending.timeout = time + pending.backoff;
+                pending.directive.clone()
+            }
+            _ => return Ok(()),
+        };
+        self.send_directive(&Directive::SetInputJack(directive))
+    }
+
+    /// Subscribe a monitor jack to the output jack `jack_id` of module `uuid` without a patch
+    /// gesture.
+    ///
+    /// The target module answers with the multicast address of the jack, which is then connected
+    /// without changing the patch state or jack colors. The subscription is torn down after
+    /// `PROBE_TIMEOUT` ms unless `probe` is called again with the same target to renew it.
+    pub fn probe(
+        &mut self,
+        handle: MonitorJackHandle,
+        uuid: Uuid,
+        jack_id: u32,
+        time: impl Into<MonotonicTime>,
+    ) -> Result<(), Error> {
+        let time = time.into();
+        if let Some(probe) = &mut self.monitors[handle.0] {
+            if probe.uuid == uuid && probe.jack_id == jack_id && probe.connected {
+                probe.expires = time.as_millis() + PROBE_TIMEOUT;
+                return Ok(());
+            }
+        }
+        self.probe_cancel(handle, time)?;
+        self.send_directive(&Directive::ProbeRequest(DirectiveProbeRequest {
+            uuid: uuid.clone(),
+            jack_id,
+        }))?;
+        self.monitors[handle.0] = Some(Monitor {
+            uuid,
+            jack_id,
+            expires: time.as_millis() + PROBE_TIMEOUT,
+            connected: false,
+        });
+        Ok(())
+    }
+
+    /// Tear down a probe on a monitor jack, if there is one.
+    pub fn probe_cancel(
+        &mut self,
+        handle: MonitorJackHandle,
+        time: impl Into<MonotonicTime>,
+    ) -> Result<(), Error> {
+        let time = time.into();
+        if let Some(probe) = self.monitors[handle.0].take() {
+            if probe.connected {
+                self.connected_inputs[handle.0] = false;
+                self.input_sources[handle.0] = None;
+                self.interface.jack_disconnect(handle.0, time)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn process_probe_request(&mut self, req: DirectiveProbeRequest) -> Result<(), Error> {
+        if req.uuid == self.uuid && (req.jack_id as usize) < self.output_jack_handles {
+            let resp = Directive::ProbeResponse(DirectiveProbeResponse {
+                uuid: self.uuid.clone(),
+                jack_id: req.jack_id,
+                color: self.color,
+                addr: self.interface.jack_addr(req.jack_id as usize)?,
+            });
+            self.send_directive(&resp)?;
+        }
+        Ok(())
+    }
+
+    fn process_probe_response(&mut self, resp: DirectiveProbeResponse, time: i64) {
+        for i in 0..I {
+            let matched = match &self.monitors[i] {
+                Some(probe) => {
+                    !probe.connected && probe.uuid == resp.uuid && probe.jack_id == resp.jack_id
+                }
+                None => false,
+            };
+            if matched {
+                let source = DirectiveSubscribe {
+                    uuid: resp.uuid.clone(),
+                    jack_id: resp.jack_id,
+                };
+                match self.connect_input_jack(i, source, resp.addr, time) {
+                    Ok(_) => {
+                        if let Some(probe) = &mut self.monitors[i] {
+                            probe.connected = true;
+                        }
+                    }
+                    Err(e) => info!("Probe connection error: {:?}", e),
+                }
+            }
+        }
+    }
+
+    /// Follow the output jack being held on the network with the audition jack, if there is one.
+    fn audition(&mut self, time: i64) -> Result<(), Error> {
+        let handle = match self.audition {
+            Some(handle) => handle,
+            None => return Ok(()),
+        };
+        let held = match (&self.patch_state, &self.held_input, &self.held_output) {
+            (PatchState::PatchEnabled, None, Some(output)) if output.uuid != self.uuid => {
+                Some(output.clone())
+            }
+            _ => None,
+        };
+        let time = MonotonicTime::from_millis(time);
+        match (held, &self.monitors[handle.0]) {
+            // Still waiting to hear back from the module being held
+            (Some(output), Some(probe))
+                if probe.uuid == output.uuid
+                    && probe.jack_id == output.jack_id
+                    && !probe.connected =>
+            {
+                Ok(())
+            }
+            (Some(output), _) => self.probe(handle, output.uuid, output.jack_id, time),
+            (None, Some(_)) => self.probe_cancel(handle, time),
+            (None, None) => Ok(()),
+        }
+    }
+
+    fn expire_probes(&mut self, time: i64) {
+        for i in 0..I {
+            if let Some(probe) = &self.monitors[i] {
+                if time > probe.expires {
+                    info!("Probe of {}:{} timed out", probe.uuid, probe.jack_id);
+                    let time = MonotonicTime::from_millis(time);
+                    if let Err(e) = self.probe_cancel(MonitorJackHandle(i), time) {
+                        info!("Probe disconnect error: {:?}", e);
+                    }
+                }
+            }
+        }
+    }
+
+    fn toggle_input_jack(&mut self, jack_id: usize, output: HeldOutputJack, time: i64) -> bool {
+        // For now this is just a switch rather than a toggle
+        if jack_id >= self.input_jack_handles || self.is_monitor(jack_id) {
+            return false;
+        }
+        let source = DirectiveSubscribe {
+            uuid: output.uuid,
+            jack_id: output.id,
+        };
+        let previous = self.input_sources[jack_id].clone();
+        let peer = JackPeer {
+            uuid: source.uuid.clone(),
+            jack_id: source.jack_id,
+        };
+        match self.connect_input_jack(jack_id, source, output.addr, time) {
+            Ok(_) => {
+                self.input_colors[jack_id] = output.color;
+                let handle = InputJackHandle(jack_id);
+                if previous.is_some() {
+                    self.jack_event(JackEvent::Disconnected { handle });
+                }
+                self.jack_event(JackEvent::Connected { handle, peer });
+                true
+            }
+            Err(e) => {
+                info!("Jack connection error: {:?}", e);
+                false
+            }
+        }
+    }
+}
+
+/// What a processor might need to know about the block being processed, besides the audio.
+#[derive(Clone, Copy, Debug)]
+pub struct BlockContext {
+    /// Time of the poll the block is processed in, in ms
+    pub time: i64,
+    /// The sample rate the network is running at
+    pub sample_rate: SampleRate,
+    /// How much work the processor should do, stepped down while the host isn't keeping up
+    pub quality: Quality,
+    /// The network's tuning, for `voct_to_frequency` and processors that quantize
+    pub tuning: Tuning,
+    /// Frequency of every MIDI note from the network's scale table, or `None` for equal
+    /// temperament from `tuning` (see `ScaleTable::note_frequencies`)
+    pub note_frequencies: Option<[f32; 128]>,
+    /// Voices in use on the inputs, counting from the first channel (see `Module::voices`).
+    /// Processors may leave the channels above silent rather than processing them.
+    pub voices: u8,
+}
+
+/// The DSP of a module, written once and run by any frontend, whether a desktop window or an
+/// embedded board.
+///
+/// Frontends add every input and output jack in order, so processors refer to jacks by position
+/// (as with `define_module!`) through `ProcessBlock::inputs` and `ProcessBlock::outputs`. Knobs
+/// and other controls are read by the frontend and handed over as `params`.
+pub trait Processor<const I: usize, const O: usize, const P: usize> {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<I, O>,
+        params: &ParamBlock<P>,
+        context: &BlockContext,
+    );
+}
+
+pub struct ProcessBlock<'a, const I: usize, const O: usize> {
+    input: [&'a AudioPacket; I],
+    output: [&'a mut AudioPacket; O],
+    active: [bool; O],
+    channel_activity: [u16; I],
+    context: BlockContext,
+}
+
+impl<'a, const I: usize, const O: usize> ProcessBlock<'a, I, O> {
+    pub fn new(
+        input: [&'a AudioPacket; I],
+        output: [&'a mut AudioPacket; O],
+        active: [bool; O],
+        context: BlockContext,
+    ) -> Self {
+        ProcessBlock {
+            input,
+            output,
+            active,
+            channel_activity: [u16::MAX; I],
+            context,
+        }
+    }
+
+    /// Mark which channels of each input have carried anything lately, as a bit per channel.
+    /// Every channel is taken to be active otherwise.
+    pub fn with_activity(mut self, channel_activity: [u16; I]) -> Self {
+        self.channel_activity = channel_activity;
+        self
+    }
+
+    pub fn context(&self) -> BlockContext {
+        self.context
+    }
+
+    /// Every input jack, by position.
+    pub fn inputs(&self) -> [&'a AudioPacket; I] {
+        self.input
+    }
+
+    /// Every output jack, by position.
+    pub fn outputs(&mut self) -> &mut [&'a mut AudioPacket; O] {
+        &mut self.output
+    }
+
+    pub fn get_input(&self, handle: InputJackHandle) -> &AudioPacket {
+        self.input[handle.0]
+    }
+
+    pub fn get_monitor(&self, handle: MonitorJackHandle) -> &AudioPacket {
+        self.input[handle.0]
+    }
+
+    /// Whether anything is subscribed to an output jack. Processing for inactive outputs can be
+    /// skipped, since they are not sent.
+    pub fn is_output_active(&self, handle: OutputJackHandle) -> bool {
+        self.active[handle.0]
+    }
+
+    /// Whether a channel of the input jack at position `input` has been above `IDLE_LEVEL` in
+    /// the last `IDLE_BLOCKS` blocks. Processors can skip idle channels, as long as that leaves
+    /// their outputs silent for them too.
+    pub fn is_channel_active(&self, input: usize, channel: usize) -> bool {
+        self.channel_activity[input] & (1 << channel) != 0
+    }
+
+    /// The channels of the input jack at position `input` that are active, as a bit per channel.
+    pub fn active_channels(&self, input: usize) -> u16 {
+        self.channel_activity[input]
+    }
+
+    /// The channels of an input jack that are active, as a bit per channel.
+    pub fn get_input_activity(&self, handle: InputJackHandle) -> u16 {
+        self.channel_activity[handle.0]
+    }
+
+    pub fn set_output(&mut self, handle: OutputJackHandle, data: AudioPacket) {
+        *self.output[handle.0] = data;
+    }
+
+    pub fn get_mut_output(&mut self, handle: OutputJackHandle) -> &mut AudioPacket {
+        &mut self.output[handle.0]
+    }
+
+    /// Walk the frames of the input and output jacks at the given positions together, along
+    /// with the frame index for `ParamBlock::at`, for processing that maps one jack onto another.
+    pub fn zip_io<'s>(
+        &'s mut self,
+        input: usize,
+        output: usize,
+    ) -> impl Iterator<Item = (usize, &'s AudioFrame, &'s mut AudioFrame)> + 's {
+        let input: &'s AudioPacket = self.input[input];
+        input
+            .frames()
+            .zip(self.output[output].frames_mut())
+            .enumerate()
+            .map(|(i, (x, y))| (i, x, y))
+    }
+
+    /// The left and right samples of stereo pair `pair` on frame `i` of an input.
+    pub fn get_stereo_input(
+        &self,
+        handle: InputJackHandle,
+        i: usize,
+        pair: usize,
+    ) -> (SampleType, SampleType) {
+        self.input[handle.0].data[i].stereo(pair)
+    }
+
+    pub fn set_stereo_output(
+        &mut self,
+        handle: OutputJackHandle,
+        i: usize,
+        pair: usize,
+        sample: (SampleType, SampleType),
+    ) {
+        self.output[handle.0].data[i].set_stereo(pair, sample);
+    }
+}
+
+/// Parameter values for a block of processing.
+///
+/// Parameters only change between blocks, so using the new value for the whole block steps
+/// audibly. This keeps the value from the previous block as well, so that processing can ramp
+/// from one to the other across the block with `at`. Indexing gives the target value.
+#[derive(Clone, Copy, Debug)]
+pub struct ParamBlock<const P: usize> {
+    previous: [f32; P],
+    target: [f32; P],
+}
+
+impl<const P: usize> ParamBlock<P> {
+    pub fn new(values: [f32; P]) -> Self {
+        ParamBlock {
+            previous: values,
+            target: values,
+        }
+    }
+
+    /// Set the value a parameter will reach by the end of the next block.
+    pub fn set(&mut self, id: usize, value: f32) {
+        self.target[id] = value;
+    }
+
+    /// Call after processing a block, so that the next block ramps from where this one ended.
+    pub fn next_block(&mut self) {
+        self.previous = self.target;
+    }
+
+    pub fn previous(&self, id: usize) -> f32 {
+        self.previous[id]
+    }
+
+    pub fn target(&self, id: usize) -> f32 {
+        self.target[id]
+    }
+
+    /// The value of a parameter at sample `i` of the block, reaching the target on the last
+    /// sample.
+    pub fn at(&self, id: usize, i: usize) -> f32 {
+        let t = (i + 1) as f32 / BLOCK_SIZE as f32;
+        self.previous[id] + (self.target[id] - self.previous[id]) * t
+    }
+}
+
+impl<const P: usize> Index<usize> for ParamBlock<P> {
+    type Output = f32;
+
+    fn index(&self, id: usize) -> &f32 {
+        &self.target[id]
+    }
+}
+
+/// Bandwidth used by the multicast audio streams, in bits per second.
+#[derive(Clone, Copy, Debug)]
+pub struct BandwidthStats {
+    /// All streams advertised on the network
+    pub network: u32,
+    /// Streams sent and received by this module
+    pub local: u32,
+    pub budget: u32,
 }
 
 pub struct PollUpdate<const I: usize, const O: usize> {
     input_colors: [Srgb<u8>; I],
     output_colors: [Srgb<u8>; O],
+    sample_rate: SampleRate,
+    wavetable_upload: Option<DirectiveWavetableUpload>,
+    peer_description: Option<(Uuid, ModuleDescription)>,
+    param_changes: heapless::Vec<(u8, ParamChange), MAX_PARAM_CHANGES>,
+    compare_actions: heapless::Vec<CompareAction, MAX_COMPARE_ACTIONS>,
+    preset_actions: heapless::Vec<PresetAction, MAX_PRESET_ACTIONS>,
+    jack_events: heapless::Vec<JackEvent, MAX_JACK_EVENTS>,
+    patch_state: PatchState,
+    patch_feedback: bool,
+    held_input: Option<JackPeer>,
+    held_output: Option<JackPeer>,
+    send_failures: u32,
+    network_state: NetworkState,
+    status: Status,
+    standby: bool,
+    muted: bool,
+    bypassed: bool,
+}
+
+/// Overall health of a module, with a standard color for each so that every module shows the same
+/// diagnostics.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Status {
+    /// The physical link is down, such as with the cable unplugged (red)
+    LinkDown,
+    /// The link is up but the interface isn't ready yet, such as while waiting for DHCP (blue)
+    Configuring,
+    /// No other module has been heard from. Patching has no leader, so this is what losing one
+    /// looks like (cyan)
+    Alone,
+    /// Packets have recently been lost, refused or left unsent, the network is over its bandwidth
+    /// budget, or the module is running at reduced quality to keep up with its host (orange)
+    Degraded,
+    /// Green
+    Healthy,
+}
+
+impl Status {
+    pub fn color(self) -> Srgb<u8> {
+        match self {
+            Status::LinkDown => Srgb::new(255, 0, 0),
+            Status::Configuring => Srgb::new(0, 0, 255),
+            Status::Alone => Srgb::new(0, 255, 255),
+            Status::Degraded => Srgb::new(255, 128, 0),
+            Status::Healthy => Srgb::new(0, 255, 0),
+        }
+    }
+}
+
+/// Whether a module could reach the network during a poll.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetworkState {
+    /// Directives and jack packets were exchanged as usual
+    Online,
+    /// The interface wasn't ready to send (such as before DHCP finishes, or with the cable
+    /// unplugged), so the block was processed with silent inputs and nothing was sent
+    Offline,
 }
 
 impl<const I: usize, const O: usize> PollUpdate<I, O> {
+    /// Write any piece of a user wavetable received during this poll into `storage`.
+    pub fn store_wavetable<S: Storage>(&self, storage: &mut S) -> Result<(), Error> {
+        match &self.wavetable_upload {
+            Some(upload) => storage.write_wavetable(
+                upload.slot as usize,
+                upload.offset as usize,
+                &upload.samples,
+            ),
+            None => Ok(()),
+        }
+    }
+
+    /// A description of another module heard during this poll, along with its uuid (see
+    /// `Module::send_describe_request`).
+    pub fn peer_description(&self) -> Option<(&Uuid, &ModuleDescription)> {
+        self.peer_description
+            .as_ref()
+            .map(|(uuid, description)| (uuid, description))
+    }
+
+    /// Changes to this module's parameters sent by other modules since the last poll.
+    pub fn param_changes(&self) -> impl Iterator<Item = (usize, ParamChange)> + '_ {
+        self.param_changes.iter().map(|(p, c)| (*p as usize, *c))
+    }
+
+    /// A/B compare steps sent to this module since the last poll, in the order they arrived.
+    pub fn compare_actions(&self) -> impl Iterator<Item = CompareAction> + '_ {
+        self.compare_actions.iter().copied()
+    }
+
+    /// Preset stores and recalls sent to this module since the last poll, in the order they
+    /// arrived.
+    pub fn preset_actions(&self) -> impl Iterator<Item = &PresetAction> + '_ {
+        self.preset_actions.iter()
+    }
+
+    /// Carry out the preset actions of this poll on `storage`, storing the knob settings in
+    /// `params`. Returns the settings of the last preset recalled, if any, for the frontend to
+    /// set its knobs to. Recalling an empty slot does nothing.
+    pub fn apply_presets<S: Storage, const P: usize>(
+        &self,
+        storage: &mut S,
+        params: &[f32; P],
+    ) -> Result<Option<[f32; P]>, Error> {
+        let mut recalled = None;
+        for action in &self.preset_actions {
+            match action {
+                PresetAction::Store { slot, name } => {
+                    let values = params.iter().take(MAX_PRESET_PARAMS).copied().collect();
+                    let name = name.clone();
+                    storage.write_preset(*slot as usize, ParamPreset { name, values })?;
+                }
+                PresetAction::Recall(slot) => match storage.preset(*slot as usize) {
+                    Some(preset) => {
+                        // Knobs past the end of the preset stay where they are
+                        let mut values = recalled.unwrap_or(*params);
+                        for (v, p) in values.iter_mut().zip(&preset.values) {
+                            *v = *p;
+                        }
+                        recalled = Some(values);
+                    }
+                    None => info!("No preset in slot {}", slot),
+                },
+            }
+        }
+        Ok(recalled)
+    }
+
+    /// Input jacks connected or disconnected since the last poll, in the order it happened.
+    pub fn jack_events(&self) -> impl Iterator<Item = &JackEvent> + '_ {
+        self.jack_events.iter()
+    }
+
+    /// Where the network is in making a patch. The jack colors already show this, but a module
+    /// can also act on it, such as auditioning an output while it is held.
+    pub fn patch_state(&self) -> PatchState {
+        self.patch_state
+    }
+
+    /// Whether the patch being made would feed a module's output back into the chain leading to
+    /// its own input. Refused patches are flagged as well, to tell them from other failures.
+    pub fn is_feedback(&self) -> bool {
+        self.patch_feedback
+    }
+
+    /// The input jack held down somewhere on the network, if there is just the one.
+    pub fn held_input(&self) -> Option<&JackPeer> {
+        self.held_input.as_ref()
+    }
+
+    /// The output jack held down somewhere on the network, if there is just the one.
+    pub fn held_output(&self) -> Option<&JackPeer> {
+        self.held_output.as_ref()
+    }
+
+    /// The sample rate the network is running at. DSP state should be reconfigured when this
+    /// changes.
+    pub fn get_sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    /// Overall health of the module as of this poll.
+    pub fn get_status(&self) -> Status {
+        self.status
+    }
+
+    /// Standard color for the module's status, for a status light.
+    pub fn status_color(&self) -> Srgb<u8> {
+        self.status.color()
+    }
+
+    /// Whether the network was reachable during this poll. Processing carries on either way.
+    pub fn get_network_state(&self) -> NetworkState {
+        self.network_state
+    }
+
+    /// Output packets that couldn't be sent during this poll.
+    pub fn send_failures(&self) -> u32 {
+        self.send_failures
+    }
+
+    /// Whether the module is in standby, with its outputs paused and its colors dimmed.
+    pub fn is_standby(&self) -> bool {
+        self.standby
+    }
+
+    /// Whether the module's outputs are muted.
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    /// Whether the module's inputs are passed straight through to its outputs.
+    pub fn is_bypassed(&self) -> bool {
+        self.bypassed
+    }
+
     pub fn get_input_color(&self, handle: InputJackHandle) -> Srgb<u8> {
         self.input_colors[handle.0]
     }
@@ -595,4 +3865,269 @@ mod tests {
         let result = 2 + 2;
         assert_eq!(result, 4);
     }
+
+    #[cfg(feature = "network-local")]
+    #[test]
+    fn finalize_jacks_catches_missing_handles() {
+        use super::*;
+        use socket_local::LocalInterface;
+
+        let interface = LocalInterface::new().unwrap();
+        let mut module: Module<_, _, 2, 1> = Module::new(
+            interface,
+            rand::thread_rng(),
+            "Finalize".into(),
+            0,
+            MonotonicTime::default(),
+        );
+        module.add_input_jack().unwrap();
+        module.add_output_jack().unwrap();
+        assert_eq!(
+            module.finalize_jacks().err(),
+            Some(Error::UnallocatedJacks {
+                inputs: 1,
+                outputs: 0
+            })
+        );
+
+        module.add_monitor_jack().unwrap();
+        let jacks = module.finalize_jacks().unwrap();
+        assert_eq!(jacks.inputs.map(|handle| handle.0), [0, 1]);
+        assert_eq!(jacks.outputs.map(|handle| handle.0), [0]);
+    }
+
+    #[cfg(feature = "network-local")]
+    fn handled_once(uuid: &str, other: &str, multicast_loop: bool) {
+        use super::*;
+        use socket_local::LocalInterface;
+
+        let mut interface = LocalInterface::new().unwrap();
+        interface.set_multicast_loop(multicast_loop);
+        let mut module: Module<_, _, 0, 1> = Module::new(
+            interface,
+            rand::thread_rng(),
+            uuid.into(),
+            0,
+            MonotonicTime::default(),
+        );
+        let to_self = Directive::Subscribe(DirectiveSubscribe {
+            uuid: uuid.into(),
+            jack_id: 0,
+        });
+        let to_other = Directive::Subscribe(DirectiveSubscribe {
+            uuid: other.into(),
+            jack_id: 0,
+        });
+        module.send_directive(&to_self).unwrap();
+        module.send_directive(&to_other).unwrap();
+
+        // The directive bus is shared with other tests, so only look for our own. Only the one
+        // addressed to this module should come back, echoed or not.
+        let mut received = vec![];
+        for _ in 0..100 {
+            if let Ok(d) = module.recv_directive() {
+                received.push(d);
+            }
+        }
+        assert_eq!(received.iter().filter(|d| **d == to_self).count(), 1);
+        assert!(!received.contains(&to_other));
+    }
+
+    #[cfg(feature = "network-local")]
+    #[test]
+    fn full_loopback_refuses_to_send() {
+        use super::*;
+        use socket_local::LocalInterface;
+
+        let interface = LocalInterface::new().unwrap();
+        let mut module: Module<_, _, 0, 1> = Module::new(
+            interface,
+            rand::thread_rng(),
+            "Loopback".into(),
+            0,
+            MonotonicTime::default(),
+        );
+        let subscribe = |jack_id| {
+            Directive::Subscribe(DirectiveSubscribe {
+                uuid: "Loopback".into(),
+                jack_id,
+            })
+        };
+        for jack_id in 0..LOOPBACK_SIZE as u32 {
+            module.send_directive(&subscribe(jack_id)).unwrap();
+        }
+        let last = subscribe(LOOPBACK_SIZE as u32);
+        assert!(matches!(
+            module.send_directive(&last),
+            Err(Error::StorageFull)
+        ));
+
+        // Whatever was refused never went out to be handled by anyone. The directive bus is shared
+        // with other tests, so only look for our own.
+        let mut received = vec![];
+        for _ in 0..100 {
+            if let Ok(d @ Directive::Subscribe(_)) = module.recv_directive() {
+                if d.loops_back(&"Loopback".into()) {
+                    received.push(d);
+                }
+            }
+        }
+        assert_eq!(received.len(), LOOPBACK_SIZE);
+        assert!(!received.contains(&last));
+    }
+
+    #[cfg(feature = "network-local")]
+    #[test]
+    fn own_directives_with_echo() {
+        handled_once("Echo", "Echo Other", true);
+    }
+
+    #[cfg(feature = "network-local")]
+    #[test]
+    fn own_directives_without_echo() {
+        handled_once("No Echo", "No Echo Other", false);
+    }
+
+    #[test]
+    fn source_filter_follows_a_moved_sender() {
+        let mut filter = crate::SourceFilter::new();
+        assert!(filter.accept(1));
+        for _ in 0..crate::SENDER_STRIKES {
+            assert!(!filter.accept(2));
+        }
+        assert!(filter.accept(1));
+        for _ in 0..crate::SENDER_STRIKES {
+            assert!(!filter.accept(2));
+        }
+        assert!(filter.accept(2));
+        assert!(!filter.accept(1));
+    }
+
+    #[test]
+    fn processor_sees_jacks_by_position() {
+        use crate::{
+            AudioPacket, BlockContext, ParamBlock, ProcessBlock, Processor, Quality, SampleRate,
+            BLOCK_SIZE, CHANNELS,
+        };
+
+        struct Gain(i16);
+
+        impl Processor<2, 1, 1> for Gain {
+            fn process(
+                &mut self,
+                block: &mut ProcessBlock<2, 1>,
+                params: &ParamBlock<1>,
+                context: &BlockContext,
+            ) {
+                assert_eq!(context.sample_rate, SampleRate(32000));
+                let input = block.inputs();
+                let gain = self.0 * params[0] as i16;
+                *block.outputs()[0] = AudioPacket::splat(input[1].data[0].data[0] * gain);
+            }
+        }
+
+        let input = [AudioPacket::splat(1), AudioPacket::splat(3)];
+        let mut output = AudioPacket::default();
+        let context = BlockContext {
+            time: 0,
+            sample_rate: SampleRate(32000),
+            quality: Quality::Full,
+            tuning: Default::default(),
+            note_frequencies: None,
+            voices: CHANNELS as u8,
+        };
+        let mut block = ProcessBlock::new([&input[0], &input[1]], [&mut output], [true], context);
+        Gain(2).process(&mut block, &ParamBlock::new([5.0]), &context);
+        assert_eq!(output.data[BLOCK_SIZE - 1].data[CHANNELS - 1], 30);
+    }
+
+    #[test]
+    fn zip_io_pairs_frames() {
+        use crate::{
+            AudioPacket, BlockContext, ProcessBlock, Quality, SampleRate, BLOCK_SIZE, CHANNELS,
+        };
+
+        let mut input = AudioPacket::default();
+        for (i, frame) in input.frames_mut().enumerate() {
+            for (j, x) in frame.channels_mut().enumerate() {
+                *x = (i * CHANNELS + j) as i16;
+            }
+        }
+        let mut outputs = [AudioPacket::default(), AudioPacket::default()];
+        let [first, second] = &mut outputs;
+        let context = BlockContext {
+            time: 0,
+            sample_rate: SampleRate(48000),
+            quality: Quality::Full,
+            tuning: Default::default(),
+            note_frequencies: None,
+            voices: CHANNELS as u8,
+        };
+        let mut block = ProcessBlock::new([&input], [first, second], [true; 2], context);
+        let mut frames = 0;
+        for (i, x, y) in block.zip_io(0, 1) {
+            assert_eq!(i, frames);
+            for (x, y) in x.channels().zip(y.channels_mut()) {
+                *y = -*x;
+            }
+            frames += 1;
+        }
+        assert_eq!(frames, BLOCK_SIZE);
+        assert!(outputs[0].is_silent());
+        assert_eq!(outputs[1].data[2].data[1], -(2 * CHANNELS as i16 + 1));
+    }
+
+    #[test]
+    fn audit_finds_drifted_connection() {
+        use crate::{audit, Audit, Digests, Directive, DirectiveAuditResponse, PatchConnection};
+
+        let (output, input, auditor) = ("Audit Output", "Audit Input", "Auditor");
+        let connection = PatchConnection {
+            input_uuid: input.into(),
+            input_jack_id: 0,
+            output_uuid: output.into(),
+            output_jack_id: 1,
+        };
+        let digests = |peer: &str, addr| {
+            let mut digests = Digests::new();
+            audit::add_connection(&mut digests, &peer.into(), &connection, addr);
+            digests
+        };
+        let response = |uuid: &str, audit, inputs, outputs| DirectiveAuditResponse {
+            uuid: uuid.into(),
+            requester: auditor.into(),
+            audit,
+            inputs,
+            outputs,
+        };
+        let mut audit = Audit::new(auditor.into());
+
+        // Both ends agree
+        let id = match audit.start(Digests::new(), Digests::new(), 0) {
+            Directive::AuditRequest(req) => req.audit,
+            _ => unreachable!(),
+        };
+        let addr = [239, 1, 2, 3];
+        audit.process_response(response(output, id, Digests::new(), digests(input, addr)));
+        audit.process_response(response(input, id, digests(output, addr), Digests::new()));
+        audit.poll(1000);
+        let report = audit.report().unwrap();
+        assert_eq!(report.modules, 3);
+        assert!(report.mismatches.is_empty());
+
+        // The input missed the output moving to a new address
+        let id = match audit.start(Digests::new(), Digests::new(), 2000) {
+            Directive::AuditRequest(req) => req.audit,
+            _ => unreachable!(),
+        };
+        assert!(audit.report().is_none());
+        let moved = [239, 3, 2, 1];
+        audit.process_response(response(output, id, Digests::new(), digests(input, moved)));
+        audit.process_response(response(input, id, digests(output, addr), Digests::new()));
+        audit.poll(3000);
+        let report = audit.report().unwrap();
+        assert_eq!(report.mismatches.len(), 1);
+        assert_eq!(report.mismatches[0].output, output);
+        assert_eq!(report.mismatches[0].input, input);
+    }
 }
@@ -0,0 +1,295 @@
+/*! Declarative descriptions of a module's jacks and parameters.
+
+`define_module!` turns a list of jacks and knobs into the index constants, counts, and name tables
+that every engine otherwise writes out by hand, so that a desktop example and an stm32 engine can
+share one description:
+
+```ignore
+apiary_core::define_module! {
+    name: "logic",
+    color: 300,
+    inputs: {
+        A_INPUT: "Gate A" as Gate,
+        B_INPUT: "Gate B" as Gate,
+    },
+    outputs: {
+        AND_OUTPUT: "And" as Gate,
+        OR_OUTPUT: "Or" as Gate,
+    },
+    params: {
+        LEVEL_PARAM: "Level" { min: 0.0, max: 1.0, default: 1.0, unit: "", log: false },
+    },
+}
+```
+
+This defines `NAME`, `COLOR`, `A_INPUT`, `B_INPUT`, `AND_OUTPUT`, `OR_OUTPUT`, `LEVEL_PARAM`,
+`NUM_INPUTS`, `NUM_OUTPUTS` and `NUM_PARAMS` in the surrounding module, along with a `SPEC` holding
+the names, `SignalLevel`s and knob ranges and a `Jacks` type to keep the handles in. Jacks declared
+without a level carry audio.
+
+```ignore
+let jacks = Jacks::add(&mut module)?;
+let and = block.get_mut_output(jacks.outputs[AND_OUTPUT]);
+```
+*/
+
+use heapless::{String, Vec};
+use rand_core::RngCore;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dsp::sample::SignalLevel, time::Clock, Error, InputJackHandle, Module, Network,
+    OutputJackHandle,
+};
+
+/// Longest name of a module, jack, knob or unit in a `ModuleDescription`, in bytes
+pub const LABEL_LEN: usize = 20;
+/// Most knobs a `ModuleDescription` lists. Modules with more only show the first ones remotely.
+pub const MAX_DESCRIBED_PARAMS: usize = 16;
+const MAX_JACKS: usize = 16;
+
+/// Range and display of a single knob.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub unit: &'static str,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    /// Whether the knob turns through equal ratios rather than equal steps, for frequencies
+    /// and times
+    pub log: bool,
+}
+
+impl ParamSpec {
+    /// Where `value` sits along the knob's travel, as used by `ParamChange`.
+    pub fn position(&self, value: f32) -> f32 {
+        knob_position(value, self.min, self.max, self.log)
+    }
+
+    /// The value at `position` along the knob's travel, as used by `ParamChange`.
+    pub fn value(&self, position: f32) -> f32 {
+        knob_value(position, self.min, self.max, self.log)
+    }
+}
+
+/// Where `value` sits along the travel of a knob from `min` to `max`, from 0 at the bottom to 1
+/// at the top.
+pub fn knob_position(value: f32, min: f32, max: f32, log: bool) -> f32 {
+    let position = if log {
+        libm::logf(value / min) / libm::logf(max / min)
+    } else {
+        (value - min) / (max - min)
+    };
+    position.clamp(0.0, 1.0)
+}
+
+/// The value at `position` along the travel of a knob from `min` to `max`, the inverse of
+/// `knob_position`.
+pub fn knob_value(position: f32, min: f32, max: f32, log: bool) -> f32 {
+    let position = position.clamp(0.0, 1.0);
+    if log {
+        min * libm::powf(max / min, position)
+    } else {
+        min + position * (max - min)
+    }
+}
+
+/// Everything about a module's front panel that isn't specific to a platform.
+#[derive(Clone, Copy, Debug)]
+pub struct ModuleSpec<const I: usize, const O: usize, const P: usize> {
+    pub name: &'static str,
+    pub color: u16,
+    pub inputs: [&'static str; I],
+    pub outputs: [&'static str; O],
+    pub input_levels: [SignalLevel; I],
+    pub output_levels: [SignalLevel; O],
+    pub params: [ParamSpec; P],
+}
+
+impl<const I: usize, const O: usize, const P: usize> ModuleSpec<I, O, P> {
+    /// The spec in the form sent over the network, for `Module::set_description`.
+    pub fn describe(&self) -> ModuleDescription {
+        let mut description = ModuleDescription::new(self.name, self.color);
+        for name in self.inputs {
+            description.add_input(name);
+        }
+        for name in self.outputs {
+            description.add_output(name);
+        }
+        for param in self.params.iter() {
+            description.add_param(ParamDescription::new(
+                param.name,
+                param.unit,
+                param.min,
+                param.max,
+                param.default,
+                param.log,
+            ));
+        }
+        description
+    }
+}
+
+/// What kind of module is on the other end of a uuid and what is on its front panel, for
+/// building a remote control surface for it. Modules send theirs when asked with
+/// `Module::send_describe_request`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ModuleDescription {
+    /// The kind of module, like "filter"
+    pub kind: String<LABEL_LEN>,
+    pub color: u16,
+    pub inputs: Vec<String<LABEL_LEN>, MAX_JACKS>,
+    pub outputs: Vec<String<LABEL_LEN>, MAX_JACKS>,
+    pub params: Vec<ParamDescription, MAX_DESCRIBED_PARAMS>,
+}
+
+impl ModuleDescription {
+    pub fn new(kind: &str, color: u16) -> Self {
+        ModuleDescription {
+            kind: label(kind),
+            color,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            params: Vec::new(),
+        }
+    }
+
+    /// Add the next input jack. Names are cut short to `LABEL_LEN`.
+    pub fn add_input(&mut self, name: &str) {
+        self.inputs.push(label(name)).ok();
+    }
+
+    /// Add the next output jack.
+    pub fn add_output(&mut self, name: &str) {
+        self.outputs.push(label(name)).ok();
+    }
+
+    /// Add the next knob, leaving it out past `MAX_DESCRIBED_PARAMS`.
+    pub fn add_param(&mut self, param: ParamDescription) {
+        self.params.push(param).ok();
+    }
+}
+
+/// A knob in a `ModuleDescription`, the network form of a `ParamSpec`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ParamDescription {
+    pub name: String<LABEL_LEN>,
+    pub unit: String<LABEL_LEN>,
+    pub min: f32,
+    pub max: f32,
+    pub default: f32,
+    pub log: bool,
+}
+
+impl ParamDescription {
+    pub fn new(name: &str, unit: &str, min: f32, max: f32, default: f32, log: bool) -> Self {
+        ParamDescription {
+            name: label(name),
+            unit: label(unit),
+            min,
+            max,
+            default,
+            log,
+        }
+    }
+
+    /// Where `value` sits along the knob's travel, as used by `ParamChange`.
+    pub fn position(&self, value: f32) -> f32 {
+        knob_position(value, self.min, self.max, self.log)
+    }
+
+    /// The value at `position` along the knob's travel, as used by `ParamChange`.
+    pub fn value(&self, position: f32) -> f32 {
+        knob_value(position, self.min, self.max, self.log)
+    }
+}
+
+/// As much of `text` as fits in a label, cut at a character boundary.
+fn label(text: &str) -> String<LABEL_LEN> {
+    let mut label = String::new();
+    for c in text.chars() {
+        if label.push(c).is_err() {
+            break;
+        }
+    }
+    label
+}
+
+/// Handles to all of a module's jacks, in the order they were declared.
+#[derive(Clone, Copy)]
+pub struct Jacks<const I: usize, const O: usize> {
+    pub inputs: [InputJackHandle; I],
+    pub outputs: [OutputJackHandle; O],
+}
+
+impl<const I: usize, const O: usize> Jacks<I, O> {
+    /// Add every input and output jack to `module`.
+    pub fn add<T: Network<I, O>, R: RngCore, C: Clock>(
+        module: &mut Module<T, R, I, O, C>,
+    ) -> Result<Self, Error> {
+        for _ in 0..I {
+            module.add_input_jack()?;
+        }
+        for _ in 0..O {
+            module.add_output_jack()?;
+        }
+        module.finalize_jacks()
+    }
+}
+
+#[macro_export]
+macro_rules! define_module {
+    (
+        name: $name:expr,
+        color: $color:expr,
+        inputs: { $($input:ident: $input_name:literal $(as $input_level:ident)?),* $(,)? },
+        outputs: { $($output:ident: $output_name:literal $(as $output_level:ident)?),* $(,)? },
+        params: { $($param:ident: $param_name:literal {
+            min: $min:expr,
+            max: $max:expr,
+            default: $default:expr,
+            unit: $unit:expr,
+            log: $log:expr $(,)?
+        }),* $(,)? } $(,)?
+    ) => {
+        #[allow(dead_code)]
+        pub const NAME: &str = $name;
+        #[allow(dead_code)]
+        pub const COLOR: u16 = $color;
+        $crate::define_module!(@index 0; $($input),*);
+        $crate::define_module!(@index 0; $($output),*);
+        $crate::define_module!(@index 0; $($param),*);
+        pub const NUM_INPUTS: usize = <[&str]>::len(&[$($input_name),*]);
+        pub const NUM_OUTPUTS: usize = <[&str]>::len(&[$($output_name),*]);
+        pub const NUM_PARAMS: usize = <[&str]>::len(&[$($param_name),*]);
+        #[allow(dead_code)]
+        pub const SPEC: $crate::ModuleSpec<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> =
+            $crate::ModuleSpec {
+                name: $name,
+                color: $color,
+                inputs: [$($input_name),*],
+                outputs: [$($output_name),*],
+                input_levels: [$($crate::define_module!(@level $($input_level)?)),*],
+                output_levels: [$($crate::define_module!(@level $($output_level)?)),*],
+                params: [$($crate::ParamSpec {
+                    name: $param_name,
+                    unit: $unit,
+                    min: $min,
+                    max: $max,
+                    default: $default,
+                    log: $log,
+                }),*],
+            };
+        #[allow(dead_code)]
+        pub type Jacks = $crate::Jacks<NUM_INPUTS, NUM_OUTPUTS>;
+    };
+    (@level) => { $crate::dsp::sample::SignalLevel::Audio };
+    (@level $level:ident) => { $crate::dsp::sample::SignalLevel::$level };
+    (@index $i:expr;) => {};
+    (@index $i:expr; $id:ident $(, $rest:ident)*) => {
+        #[allow(dead_code)]
+        pub const $id: usize = $i;
+        $crate::define_module!(@index $i + 1; $($rest),*);
+    };
+}
@@ -0,0 +1,73 @@
+use crate::{OverrunPolicy, Quality};
+
+/// Polls counted together when deciding whether the host is keeping up, which is a second
+const OVERRUN_WINDOW: u32 = 1000;
+/// Windows in a row without a single overrun before stepping back up a level
+const RECOVER_WINDOWS: u32 = 5;
+
+/// Tracks how long the host takes for each poll against its budget, and steps the quality down
+/// while it keeps running over (see `Module::report_poll_time`).
+///
+/// Stepping down waits for a whole window of overruns so that a single slow poll, such as one
+/// that handled a burst of directives, doesn't cost anything. Stepping back up waits longer
+/// still, so that a module just over the edge doesn't flap between levels.
+#[derive(Default)]
+pub(crate) struct Overrun {
+    policy: OverrunPolicy,
+    quality: Quality,
+    polls: u32,
+    overruns: u32,
+    clean_windows: u32,
+    /// Overruns since the module started
+    total: u32,
+}
+
+impl Overrun {
+    pub(crate) fn set_policy(&mut self, policy: OverrunPolicy) {
+        self.policy = policy;
+        if self.quality > policy.lowest {
+            self.quality = policy.lowest;
+        }
+    }
+
+    pub(crate) fn quality(&self) -> Quality {
+        self.quality
+    }
+
+    pub(crate) fn total(&self) -> u32 {
+        self.total
+    }
+
+    pub(crate) fn report(&mut self, micros: u32) {
+        self.polls += 1;
+        if micros > self.policy.budget_us {
+            self.overruns += 1;
+            self.total = self.total.wrapping_add(1);
+        }
+        if self.polls < OVERRUN_WINDOW {
+            return;
+        }
+        if self.overruns > self.policy.max_overruns {
+            self.clean_windows = 0;
+            let lower = self.quality.lower().min(self.policy.lowest);
+            if lower != self.quality {
+                info!(
+                    "{} of {} polls over budget, dropping to {:?} quality",
+                    self.overruns, self.polls, lower
+                );
+                self.quality = lower;
+            }
+        } else if self.overruns == 0 && self.quality != Quality::Full {
+            self.clean_windows += 1;
+            if self.clean_windows >= RECOVER_WINDOWS {
+                self.clean_windows = 0;
+                self.quality = self.quality.higher();
+                info!("Keeping up again, back to {:?} quality", self.quality);
+            }
+        } else {
+            self.clean_windows = 0;
+        }
+        self.polls = 0;
+        self.overruns = 0;
+    }
+}
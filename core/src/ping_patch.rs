@@ -1,32 +1,73 @@
 use crate::{
-    Directive,
-    Directive::{GlobalStateUpdate, HeartbeatResponse},
-    DirectiveGlobalStateUpdate, DirectiveHeartbeatResponse, HeldInputJack, HeldOutputJack,
-    LocalState, PatchState, Uuid,
+    bandwidth::Bandwidth,
+    Capability, Directive,
+    Directive::{GlobalStateUnchanged, GlobalStateUpdate, Halt, HeartbeatResponse},
+    DirectiveGlobalStateUnchanged, DirectiveGlobalStateUpdate, DirectiveHeartbeatResponse,
+    FeedbackPolicy, HeldInputJack, HeldOutputJack, LocalState, PatchConnection, PatchState,
+    SampleRate, Uuid,
 };
 use heapless::FnvIndexMap;
 
 const HEARTBEAT_INTERVAL: i64 = 50; // ms
 const MAX_HOSTS: usize = 16;
+/// How long modules defer to a coordinator after last hearing from it
+const COORDINATOR_TIMEOUT: i64 = 4 * HEARTBEAT_INTERVAL;
+/// Heartbeats between sending state in full even when it hasn't changed, for anyone who missed it
+const REFRESH_HEARTBEATS: u32 = 4;
+/// Connections remembered for finding feedback loops, past which the oldest are forgotten
+const MAX_PATCH: usize = 32;
 
 pub(crate) struct PingPatch {
     id: Uuid,
     seen_hosts: FnvIndexMap<Uuid, Option<LocalState>, MAX_HOSTS>,
+    /// The last full state heard from each host, for heartbeats that leave it out
+    known_states: FnvIndexMap<Uuid, LocalState, MAX_HOSTS>,
     local_state: LocalState,
+    /// The last state sent in full, if this module is still sending heartbeats
+    sent_state: Option<LocalState>,
+    heartbeats_since_refresh: u32,
     heartbeat_timeout: i64,
     last_update: Option<Directive>,
+    coordinator: bool,
+    capability: Capability,
+    /// The coordinator being followed, by rank
+    leader: Option<(Capability, Uuid)>,
+    /// Until when another module is known to be coordinating the patch
+    following_until: i64,
+    /// Number of times a coordinator has taken the lead, as far as this module knows. Each side of
+    /// a network partition that loses its coordinator to the other moves on to a later term.
+    term: u32,
+    /// Whether this module was leading at the last heartbeat
+    leading: bool,
+    /// Rate of the audio streams, set locally or taken from the coordinator being followed
+    sample_rate: SampleRate,
+    /// Every connection acknowledged on the network, one per input jack
+    patch: heapless::Vec<PatchConnection, MAX_PATCH>,
+    feedback_policy: FeedbackPolicy,
 }
 
 impl PingPatch {
-    pub(crate) fn new(id: Uuid, time: i64) -> Self {
+    pub(crate) fn new(id: Uuid, capability: Capability, time: i64) -> Self {
         let seen_hosts = FnvIndexMap::<_, _, MAX_HOSTS>::new();
 
         PingPatch {
             id,
             seen_hosts,
+            known_states: FnvIndexMap::new(),
             local_state: Default::default(),
+            sent_state: None,
+            heartbeats_since_refresh: 0,
             heartbeat_timeout: HEARTBEAT_INTERVAL + time,
             last_update: None,
+            coordinator: false,
+            capability,
+            leader: None,
+            following_until: time,
+            term: 0,
+            leading: false,
+            sample_rate: Default::default(),
+            patch: heapless::Vec::new(),
+            feedback_policy: Default::default(),
         }
     }
 
@@ -38,33 +79,199 @@ impl PingPatch {
         time > self.heartbeat_timeout
     }
 
+    /// Coordinate the patch for the whole network, rather than every module working out the
+    /// global state on its own. Of several coordinators, the most capable leads and the rest
+    /// stand by.
+    pub(crate) fn set_coordinator(&mut self, enabled: bool) {
+        self.coordinator = enabled;
+    }
+
+    pub(crate) fn is_coordinator(&self) -> bool {
+        self.coordinator
+    }
+
+    /// Whether this module is coordinating the patch, rather than standing by for another.
+    pub(crate) fn is_leading(&self, time: i64) -> bool {
+        self.coordinator && time >= self.following_until
+    }
+
+    /// The term of the coordinator leading the patch, or that last led it.
+    pub(crate) fn term(&self) -> u32 {
+        self.term
+    }
+
+    pub(crate) fn set_sample_rate(&mut self, sample_rate: SampleRate) {
+        self.sample_rate = sample_rate;
+    }
+
+    pub(crate) fn sample_rate(&self) -> SampleRate {
+        self.sample_rate
+    }
+
+    pub(crate) fn set_capability(&mut self, capability: Capability) {
+        self.capability = capability;
+    }
+
+    pub(crate) fn capability(&self) -> Capability {
+        self.capability
+    }
+
+    pub(crate) fn set_feedback_policy(&mut self, policy: FeedbackPolicy) {
+        self.feedback_policy = policy;
+    }
+
+    /// Note a connection acknowledged by its input module, which replaces whatever that input
+    /// jack was connected to before.
+    pub(crate) fn record_connection(&mut self, connection: &PatchConnection) {
+        self.patch.retain(|c| {
+            c.input_uuid != connection.input_uuid || c.input_jack_id != connection.input_jack_id
+        });
+        if self.patch.is_full() {
+            self.patch.remove(0);
+        }
+        self.patch.push(connection.clone()).ok();
+    }
+
+    /// Forget every connection, such as once the whole patch has been halted.
+    pub(crate) fn clear_patch(&mut self) {
+        self.patch.clear();
+    }
+
+    /// Returns a directive to send, and a global state update to apply to this module. Patches
+    /// that would take the network over its `bandwidth` budget are refused.
     pub(crate) fn poll(
         &mut self,
         message: Option<Directive>,
+        bandwidth: &Bandwidth,
         time: i64,
     ) -> (Option<Directive>, Option<Directive>) {
-        if let Some(HeartbeatResponse(resp)) = message {
-            if resp.uuid != self.id {
-                self.seen_hosts.insert(resp.uuid, resp.state).unwrap();
-            }
-        }
         let mut ping = None;
         let mut gsu = None;
+        match message {
+            Some(HeartbeatResponse(resp)) if resp.uuid != self.id => {
+                let state = match resp.state {
+                    Some(state) => {
+                        self.remember_state(&resp.uuid, &state);
+                        Some(state)
+                    }
+                    None => self.known_states.get(&resp.uuid).cloned(),
+                };
+                self.seen_hosts.insert(resp.uuid, state).unwrap();
+            }
+            Some(GlobalStateUpdate(update))
+                if update.uuid != self.id
+                    && self.follow(update.capability, &update.uuid, update.term, time) =>
+            {
+                self.adopt_sample_rate(update.sample_rate);
+                let update = Some(GlobalStateUpdate(update));
+                if update != self.last_update {
+                    self.last_update = update.clone();
+                    gsu = update;
+                }
+            }
+            // Only worth following if the update it stands in for was heard
+            Some(GlobalStateUnchanged(unchanged))
+                if unchanged.uuid != self.id
+                    && matches!(&self.last_update, Some(GlobalStateUpdate(u)) if u.uuid == unchanged.uuid)
+                    && self.follow(unchanged.capability, &unchanged.uuid, unchanged.term, time) =>
+            {
+                self.adopt_sample_rate(unchanged.sample_rate);
+            }
+            Some(Halt(_)) => self.clear_patch(),
+            _ => {}
+        }
         if self.heartbeat_timer_elapsed(time) {
             self.reset_heartbeat_timer(time);
-            gsu = self.check_global_state_update();
+            let leading = self.is_leading(time);
+            if leading && !self.leading {
+                self.term += 1;
+                info!("Leading the patch in term {}", self.term);
+            }
+            self.leading = leading;
+            if time >= self.following_until {
+                gsu = self.check_global_state_update(bandwidth);
+            }
+            let refresh = self.heartbeats_since_refresh >= REFRESH_HEARTBEATS;
+            self.heartbeats_since_refresh += 1;
+            if leading {
+                // Sent every heartbeat to let everyone know the coordinator is still there, but
+                // only in full when it changes
+                ping = if gsu.is_some() || refresh {
+                    self.heartbeats_since_refresh = 0;
+                    self.last_update.clone()
+                } else {
+                    Some(self.global_state_unchanged())
+                };
+            }
             self.seen_hosts.clear();
             if self.local_state.num_held_inputs + self.local_state.num_held_outputs > 0 {
                 self.seen_hosts
                     .insert(self.id.clone(), Some(self.local_state.clone()))
                     .unwrap();
-                ping = Some(self.heartbeat_response_success(0, 0));
+                if !leading {
+                    let changed = self.sent_state.as_ref() != Some(&self.local_state);
+                    if changed || refresh {
+                        self.heartbeats_since_refresh = 0;
+                        self.sent_state = Some(self.local_state.clone());
+                    }
+                    ping = Some(self.heartbeat_response_success(0, 0, changed || refresh));
+                }
+            } else {
+                // Start over in full the next time a jack is held
+                self.sent_state = None;
             }
         }
         (ping, gsu)
     }
 
-    fn check_global_state_update(&mut self) -> Option<Directive> {
+    /// Follow the coordinator `uuid` if it is the highest ranked one heard from, ties going to
+    /// the higher id. Coordinators only follow those that outrank them, and everyone carries on
+    /// from the latest term they've seen led.
+    fn follow(&mut self, capability: Capability, uuid: &Uuid, term: u32, time: i64) -> bool {
+        let rank = (capability, uuid.clone());
+        let outranks_leader = match &self.leader {
+            Some(leader) if time < self.following_until => rank >= *leader,
+            _ => true,
+        };
+        let outranks_self = !self.coordinator || rank > (self.capability, self.id.clone());
+        if outranks_leader && outranks_self {
+            self.leader = Some(rank);
+            self.following_until = time + COORDINATOR_TIMEOUT;
+            self.term = self.term.max(term);
+            true
+        } else {
+            false
+        }
+    }
+
+    fn adopt_sample_rate(&mut self, sample_rate: SampleRate) {
+        if sample_rate == self.sample_rate {
+            return;
+        }
+        if sample_rate.is_supported() {
+            info!("Switching to sample rate {} Hz", sample_rate.0);
+            self.sample_rate = sample_rate;
+        } else {
+            warn!(
+                "Coordinator runs at unsupported sample rate {} Hz",
+                sample_rate.0
+            );
+        }
+    }
+
+    fn remember_state(&mut self, uuid: &Uuid, state: &LocalState) {
+        if self
+            .known_states
+            .insert(uuid.clone(), state.clone())
+            .is_err()
+        {
+            // Hosts that have gone quiet are heard from again in full soon enough
+            self.known_states.clear();
+            self.known_states.insert(uuid.clone(), state.clone()).ok();
+        }
+    }
+
+    fn check_global_state_update(&mut self, bandwidth: &Bandwidth) -> Option<Directive> {
         let mut input_jack = None;
         let mut output_jack = None;
         let mut input_jack_count = 0;
@@ -80,14 +287,32 @@ impl PingPatch {
             output_jack_count += local_state.num_held_outputs;
         }
 
+        let feedback = match (&input_jack, &output_jack) {
+            (Some(input), Some(output)) => {
+                self.feedback_policy != FeedbackPolicy::Allow && self.feeds_back(input, output)
+            }
+            _ => false,
+        };
+        let admitted = match (&input_jack, &output_jack) {
+            (Some(input), Some(output)) => self.admits(input, output, bandwidth),
+            _ => true,
+        };
+        let toggled = if (feedback && self.feedback_policy == FeedbackPolicy::Refuse) || !admitted {
+            PatchState::Failed
+        } else {
+            PatchState::PatchToggled
+        };
         let update = Some(match (input_jack_count, output_jack_count) {
-            (0, 0) => self.gsu(PatchState::Idle, None, None),
-            (1, 0) => self.gsu(PatchState::PatchEnabled, input_jack, None),
-            (0, 1) => self.gsu(PatchState::PatchEnabled, None, output_jack),
-            (1, 1) => self.gsu(PatchState::PatchToggled, input_jack, output_jack),
-            _ => self.gsu(PatchState::Blocked, None, None),
+            (0, 0) => self.gsu(PatchState::Idle, None, None, false),
+            (1, 0) => self.gsu(PatchState::PatchEnabled, input_jack, None, false),
+            (0, 1) => self.gsu(PatchState::PatchEnabled, None, output_jack, false),
+            (1, 1) => self.gsu(toggled, input_jack, output_jack, feedback),
+            _ => self.gsu(PatchState::Blocked, None, None, false),
         });
         if update != self.last_update {
+            if !admitted {
+                warn!("Refusing patch: over bandwidth budget");
+            }
             info!("Sending global update: {:?}", update);
             self.last_update = update.clone();
             update
@@ -96,13 +321,68 @@ impl PingPatch {
         }
     }
 
-    fn heartbeat_response_success(&self, term: u32, iteration: u32) -> Directive {
+    /// Whether the network has room for patching `output` into `input`. Undoing a connection
+    /// always fits, and one that replaces another on the input jack or takes an output that is
+    /// already streaming doesn't add to the link or the network respectively.
+    fn admits(
+        &self,
+        input: &HeldInputJack,
+        output: &HeldOutputJack,
+        bandwidth: &Bandwidth,
+    ) -> bool {
+        let mut replaces = false;
+        let mut streaming = false;
+        for c in &self.patch {
+            let same_input = c.input_uuid == input.uuid && c.input_jack_id == input.id;
+            let same_output = c.output_uuid == output.uuid && c.output_jack_id == output.id;
+            if same_input && same_output {
+                return true;
+            }
+            replaces |= same_input;
+            streaming |= same_output;
+        }
+        bandwidth.admit(&input.uuid, !replaces, !streaming, self.sample_rate)
+    }
+
+    /// Whether patching `output` into `input` would close a loop, with the output's module
+    /// already fed by the input's through any number of modules (or being the same module). The
+    /// connection the patch replaces on the input jack doesn't count.
+    fn feeds_back(&self, input: &HeldInputJack, output: &HeldOutputJack) -> bool {
+        let mut reached: heapless::Vec<&Uuid, { MAX_PATCH + 1 }> = heapless::Vec::new();
+        reached.push(&input.uuid).ok();
+        let mut next = 0;
+        while let Some(&uuid) = reached.get(next) {
+            if *uuid == output.uuid {
+                return true;
+            }
+            for c in &self.patch {
+                let replaced = c.input_uuid == input.uuid && c.input_jack_id == input.id;
+                if c.output_uuid == *uuid && !replaced && !reached.contains(&&c.input_uuid) {
+                    // Every module past the first is the input of a different connection
+                    reached.push(&c.input_uuid).ok();
+                }
+            }
+            next += 1;
+        }
+        false
+    }
+
+    fn heartbeat_response_success(&self, term: u32, iteration: u32, with_state: bool) -> Directive {
         HeartbeatResponse(DirectiveHeartbeatResponse {
             uuid: self.id.clone(),
             term,
             success: true,
             iteration: Some(iteration),
-            state: Some(self.local_state.clone()),
+            state: with_state.then(|| self.local_state.clone()),
+        })
+    }
+
+    fn global_state_unchanged(&self) -> Directive {
+        GlobalStateUnchanged(DirectiveGlobalStateUnchanged {
+            uuid: self.id.clone(),
+            capability: self.capability,
+            term: self.term,
+            sample_rate: self.sample_rate,
         })
     }
 
@@ -111,12 +391,17 @@ impl PingPatch {
         patch_state: PatchState,
         input: Option<HeldInputJack>,
         output: Option<HeldOutputJack>,
+        feedback: bool,
     ) -> Directive {
         GlobalStateUpdate(DirectiveGlobalStateUpdate {
             uuid: self.id.clone(),
+            capability: self.capability,
+            term: self.term,
+            sample_rate: self.sample_rate,
             patch_state,
             input,
             output,
+            feedback,
         })
     }
 
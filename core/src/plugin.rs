@@ -0,0 +1,173 @@
+/*! C ABI for loading processors from dynamic libraries.
+
+A crate built as a `cdylib` can export any `Processor` with `export_processor!`, and a host can
+load it, run it in place of a built in processor, and load it again after it has been rebuilt.
+Only plain C types cross the boundary: jacks are passed as arrays of pointers to `AudioPacket`,
+which has the same layout on both sides as long as they were built with the same `channels-*`
+feature. A plugin and its host don't need to be built by the same compiler, but they do need to
+agree on `PLUGIN_ABI_VERSION` and on the packet layout in `PluginInfo`, which the host checks
+before calling anything else.
+
+```ignore
+#[derive(Default)]
+struct Gain;
+
+impl Processor<1, 1, 1> for Gain { ... }
+
+apiary_core::export_processor!(Gain, 1, 1, 1);
+```
+*/
+
+use core::ffi::c_void;
+
+use crate::{AudioPacket, Quality, Tuning};
+
+/// Changed whenever the signature of any of the entry points below changes.
+pub const PLUGIN_ABI_VERSION: u32 = 5;
+
+/// Name of the `PluginInfoFn` entry point.
+pub const PLUGIN_INFO_SYMBOL: &[u8] = b"apiary_plugin_info\0";
+/// Name of the `PluginCreateFn` entry point.
+pub const PLUGIN_CREATE_SYMBOL: &[u8] = b"apiary_plugin_create\0";
+/// Name of the `PluginDestroyFn` entry point.
+pub const PLUGIN_DESTROY_SYMBOL: &[u8] = b"apiary_plugin_destroy\0";
+/// Name of the `PluginProcessFn` entry point.
+pub const PLUGIN_PROCESS_SYMBOL: &[u8] = b"apiary_plugin_process\0";
+
+/// What a plugin needs from its host, checked before anything else is called.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PluginInfo {
+    pub abi_version: u32,
+    /// `CHANNELS` and `BLOCK_SIZE` the plugin was built with, which set the size of every
+    /// `AudioPacket` it reads and writes
+    pub channels: u32,
+    pub block_size: u32,
+    pub num_inputs: u32,
+    pub num_outputs: u32,
+    pub num_params: u32,
+}
+
+/// Describe the plugin.
+pub type PluginInfoFn = unsafe extern "C" fn() -> PluginInfo;
+/// Create a new instance of the processor, returning its state.
+pub type PluginCreateFn = unsafe extern "C" fn() -> *mut c_void;
+/// Free the state returned by `PluginCreateFn`.
+pub type PluginDestroyFn = unsafe extern "C" fn(state: *mut c_void);
+/// Process one block. `inputs`, `outputs` and the two param arrays point to exactly as many
+/// entries as the plugin asked for in its `PluginInfo`, with params ramping from `previous` to
+/// `target` over the block as with `ParamBlock`. `quality` is the module's `Quality` as given by
+/// `quality_to_abi`. `tuning` is the network's tuning as in `BlockContext::tuning`, and
+/// `note_frequencies` points to the frequency of each of the 128 MIDI notes as in
+/// `BlockContext::note_frequencies`, or is null for equal temperament. `voices` is as in `BlockContext::voices`. Returns 0
+/// on success, or -1 if the processor panicked.
+pub type PluginProcessFn = unsafe extern "C" fn(
+    state: *mut c_void,
+    inputs: *const *const AudioPacket,
+    outputs: *const *mut AudioPacket,
+    previous: *const f32,
+    target: *const f32,
+    time: i64,
+    sample_rate: u32,
+    quality: u8,
+    tuning: Tuning,
+    note_frequencies: *const f32,
+    voices: u8,
+) -> i32;
+
+/// A `Quality` as passed to `PluginProcessFn`, counting up from 0 for `Quality::Full`.
+pub fn quality_to_abi(quality: Quality) -> u8 {
+    quality as u8
+}
+
+/// The `Quality` passed to `PluginProcessFn`, taking anything unknown as the lowest.
+pub fn quality_from_abi(quality: u8) -> Quality {
+    match quality {
+        0 => Quality::Full,
+        1 => Quality::Reduced,
+        _ => Quality::Half,
+    }
+}
+
+/// Export a `Processor` with a `Default` constructor through the plugin entry points. Only one
+/// processor can be exported from each library, which needs `std`.
+#[macro_export]
+macro_rules! export_processor {
+    ($processor:ty, $inputs:expr, $outputs:expr, $params:expr) => {
+        #[no_mangle]
+        pub extern "C" fn apiary_plugin_info() -> $crate::plugin::PluginInfo {
+            $crate::plugin::PluginInfo {
+                abi_version: $crate::plugin::PLUGIN_ABI_VERSION,
+                channels: $crate::CHANNELS as u32,
+                block_size: $crate::BLOCK_SIZE as u32,
+                num_inputs: $inputs as u32,
+                num_outputs: $outputs as u32,
+                num_params: $params as u32,
+            }
+        }
+
+        #[no_mangle]
+        pub extern "C" fn apiary_plugin_create() -> *mut ::core::ffi::c_void {
+            let processor: Box<$processor> = Box::new(Default::default());
+            Box::into_raw(processor) as *mut ::core::ffi::c_void
+        }
+
+        /// # Safety
+        ///
+        /// `state` must have come from `apiary_plugin_create` and not been destroyed already.
+        #[no_mangle]
+        pub unsafe extern "C" fn apiary_plugin_destroy(state: *mut ::core::ffi::c_void) {
+            drop(Box::from_raw(state as *mut $processor));
+        }
+
+        /// # Safety
+        ///
+        /// `state` must have come from `apiary_plugin_create`, and every pointer must be valid
+        /// for the number of jacks and params given in `apiary_plugin_info`.
+        #[no_mangle]
+        pub unsafe extern "C" fn apiary_plugin_process(
+            state: *mut ::core::ffi::c_void,
+            inputs: *const *const $crate::AudioPacket,
+            outputs: *const *mut $crate::AudioPacket,
+            previous: *const f32,
+            target: *const f32,
+            time: i64,
+            sample_rate: u32,
+            quality: u8,
+            tuning: $crate::Tuning,
+            note_frequencies: *const f32,
+            voices: u8,
+        ) -> i32 {
+            use $crate::Processor;
+
+            let processor = &mut *(state as *mut $processor);
+            let input: [&$crate::AudioPacket; $inputs] =
+                ::core::array::from_fn(|i| &**inputs.add(i));
+            let output: [&mut $crate::AudioPacket; $outputs] =
+                ::core::array::from_fn(|i| &mut **outputs.add(i));
+            let mut params =
+                $crate::ParamBlock::<$params>::new(::core::array::from_fn(|i| *previous.add(i)));
+            for i in 0..$params {
+                params.set(i, *target.add(i));
+            }
+            let context = $crate::BlockContext {
+                time,
+                sample_rate: $crate::SampleRate(sample_rate),
+                quality: $crate::plugin::quality_from_abi(quality),
+                tuning,
+                note_frequencies: note_frequencies.cast::<[f32; 128]>().as_ref().copied(),
+                voices,
+            };
+            let mut block = $crate::ProcessBlock::new(input, output, [true; $outputs], context);
+            // Unwinding into the host would abort it, and losing the patch to a bug in a plugin
+            // being worked on is what reloading is meant to avoid
+            let res = ::std::panic::catch_unwind(::std::panic::AssertUnwindSafe(|| {
+                processor.process(&mut block, &params, &context)
+            }));
+            match res {
+                Ok(()) => 0,
+                Err(_) => -1,
+            }
+        }
+    };
+}
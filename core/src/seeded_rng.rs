@@ -0,0 +1,67 @@
+use rand_core::{impls, Error, RngCore, SeedableRng};
+
+use crate::Uuid;
+
+/// A small deterministic random source, for runs that have to make the same choices every time,
+/// such as simulations, property tests, or reproducing a problem on a board without relying on
+/// its hardware generator. Not suitable for anything that needs to be unpredictable.
+///
+/// This is SplitMix64, which is fast, needs no allocation, and gives usable output from any
+/// seed, including zero.
+#[derive(Clone, Debug)]
+pub struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub fn new(seed: u64) -> Self {
+        SeededRng { state: seed }
+    }
+
+    /// Seed from a hash of the module's uuid, so each module gets its own sequence that stays
+    /// the same from one boot to the next.
+    pub fn from_uuid(uuid: &Uuid) -> Self {
+        let hash = uuid
+            .as_bytes()
+            .iter()
+            .fold(0xcbf2_9ce4_8422_2325, |hash: u64, b| {
+                (hash ^ *b as u64).wrapping_mul(0x0000_0100_0000_01b3)
+            });
+        SeededRng::new(hash)
+    }
+}
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        (self.next_u64() >> 32) as u32
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for SeededRng {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        SeededRng::new(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(seed: u64) -> Self {
+        SeededRng::new(seed)
+    }
+}
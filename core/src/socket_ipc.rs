@@ -0,0 +1,321 @@
+/*! Unix domain socket interface.
+
+Connects modules running in separate processes on one machine, for development and CI on hosts
+where multicast isn't available. Each multicast group is a directory under a shared root (by
+default `apiary` in the temporary directory, or `$APIARY_IPC_DIR`), and joining a group binds a
+datagram socket inside it. Sending to a group sends a copy to every socket in its directory, so
+the file system takes the place of a broker. Sockets left behind by a process that exited without
+cleaning up are removed the first time a send to them is refused.
+*/
+
+use std::{
+    collections::{hash_map::DefaultHasher, HashMap},
+    env, fs,
+    hash::{Hash, Hasher},
+    io, mem,
+    os::unix::net::{SocketAddr, UnixDatagram},
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rand::{thread_rng, Rng};
+
+use crate::{
+    time::MonotonicTime, AudioPacket, Error, Network, NetworkError, ReceiveStats, SocketId,
+    SourceFilter, JACK_BUFFER_SIZE, RECV_BUDGET,
+};
+
+/// Group that directives are sent to, as with the multicast backends.
+const PATCH_ADDR: [u8; 4] = [239, 0, 0, 0];
+/// How long the list of sockets in a group is trusted before the directory is read again
+const MEMBER_REFRESH: i64 = 250; // ms
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+fn io_error(socket: SocketId) -> impl FnOnce(io::Error) -> Error {
+    move |e| Error::Network(socket, NetworkError::Io(e.kind()))
+}
+
+/// Stand-in for a packet's source address, since the source filter wants something `Copy`.
+fn source_id(addr: &SocketAddr) -> Option<u64> {
+    let mut hasher = DefaultHasher::new();
+    addr.as_pathname()?.hash(&mut hasher);
+    Some(hasher.finish())
+}
+
+/// A socket bound inside a group's directory, which is removed again when the socket is dropped.
+struct Member {
+    socket: UnixDatagram,
+    path: PathBuf,
+}
+
+impl Member {
+    fn join(root: &Path, addr: [u8; 4], name: &str, socket_id: SocketId) -> Result<Self, Error> {
+        let dir = group_dir(root, addr);
+        fs::create_dir_all(&dir).map_err(io_error(socket_id))?;
+        let path = dir.join(name);
+        // A socket with our name can only be left over from an earlier process with the same pid
+        let _ = fs::remove_file(&path);
+        let socket = UnixDatagram::bind(&path).map_err(io_error(socket_id))?;
+        socket.set_nonblocking(true).map_err(io_error(socket_id))?;
+        Ok(Member { socket, path })
+    }
+}
+
+impl Drop for Member {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn group_dir(root: &Path, addr: [u8; 4]) -> PathBuf {
+    root.join(format!("{}.{}.{}.{}", addr[0], addr[1], addr[2], addr[3]))
+}
+
+pub struct IpcInterface<const I: usize, const O: usize> {
+    root: PathBuf,
+    name: String,
+    multicast_loop: bool,
+    directive: Member,
+    input_members: Vec<Option<Member>>,
+    input_senders: [SourceFilter<u64>; I],
+    recv_budget: usize,
+    receive_stats: ReceiveStats,
+    output_addrs: Vec<[u8; 4]>,
+    members: HashMap<[u8; 4], (i64, Vec<PathBuf>)>,
+    time: i64,
+    input_buffers: [[u8; JACK_BUFFER_SIZE]; I],
+    output_buffer: Vec<u8>,
+    enq_sizes: [usize; O],
+}
+
+impl<const I: usize, const O: usize> IpcInterface<I, O> {
+    /// Open the interface under `$APIARY_IPC_DIR`, or `apiary` in the temporary directory if that
+    /// isn't set.
+    pub fn new() -> Result<Self, Error> {
+        let root = match env::var_os("APIARY_IPC_DIR") {
+            Some(dir) => PathBuf::from(dir),
+            None => env::temp_dir().join("apiary"),
+        };
+        Self::with_dir(root)
+    }
+
+    /// Open the interface under `root`. Only modules sharing a root can see each other.
+    pub fn with_dir(root: impl Into<PathBuf>) -> Result<Self, Error> {
+        let root = root.into();
+        let name = format!(
+            "{}-{}",
+            process::id(),
+            NEXT_ID.fetch_add(1, Ordering::Relaxed)
+        );
+        let directive = Member::join(&root, PATCH_ADDR, &name, SocketId::Directive)?;
+        info!("IPC interface {} under {:?}", name, root);
+
+        let mut rng = thread_rng();
+        let mut output_addrs = vec![];
+        for _ in 0..O {
+            output_addrs.push([
+                239,
+                rng.gen_range(0..255),
+                rng.gen_range(0..255),
+                rng.gen_range(0..255),
+            ]);
+        }
+        let mut input_members = vec![];
+        for _ in 0..I {
+            input_members.push(None);
+        }
+        Ok(IpcInterface {
+            root,
+            name,
+            multicast_loop: true,
+            directive,
+            input_members,
+            input_senders: [SourceFilter::new(); I],
+            recv_budget: RECV_BUDGET,
+            receive_stats: Default::default(),
+            output_addrs,
+            members: HashMap::new(),
+            time: 0,
+            input_buffers: [[0; JACK_BUFFER_SIZE]; I],
+            output_buffer: vec![0; O * mem::size_of::<AudioPacket>()],
+            enq_sizes: [0; O],
+        })
+    }
+
+    /// Choose whether directives sent from this interface are also received by it, like
+    /// `IP_MULTICAST_LOOP` on a real socket. On by default.
+    pub fn set_multicast_loop(&mut self, enabled: bool) {
+        self.multicast_loop = enabled;
+    }
+
+    /// Set how many packets are read from each input jack per poll while looking for one from
+    /// its sender. Defaults to 4.
+    pub fn set_recv_budget(&mut self, packets: usize) {
+        self.recv_budget = packets.max(1);
+    }
+
+    /// Send a packet to every socket in the group `addr`, other than our own directive socket if
+    /// `skip_self` is set. As with UDP, a receiver that has fallen behind just misses the packet.
+    fn send(&mut self, addr: [u8; 4], buf: &[u8], skip_self: bool, socket_id: SocketId) {
+        let (refreshed, paths) = self
+            .members
+            .entry(addr)
+            .or_insert((self.time - MEMBER_REFRESH, Vec::new()));
+        if self.time - *refreshed >= MEMBER_REFRESH || self.time < *refreshed {
+            *refreshed = self.time;
+            *paths = match fs::read_dir(group_dir(&self.root, addr)) {
+                Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).collect(),
+                Err(_) => Vec::new(),
+            };
+        }
+        paths.retain(|path| {
+            if skip_self && *path == self.directive.path {
+                return true;
+            }
+            match self.directive.socket.send_to(buf, path) {
+                Ok(_) => true,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => true,
+                Err(e)
+                    if e.kind() == io::ErrorKind::ConnectionRefused
+                        || e.kind() == io::ErrorKind::NotFound =>
+                {
+                    info!("Removing stale socket {:?}", path);
+                    let _ = fs::remove_file(path);
+                    false
+                }
+                Err(e) => {
+                    info!("Send error on {}: {:?}", socket_id, e);
+                    true
+                }
+            }
+        });
+    }
+
+    /// Receive the next packet on a jack from its sender, turning away at most `recv_budget`
+    /// packets from anywhere else along the way.
+    fn jack_recv(&mut self, jack_id: usize) -> Result<usize, Error> {
+        let member = match self.input_members.get(jack_id) {
+            Some(Some(member)) => member,
+            Some(None) => return Err(Error::NoData),
+            None => return Err(Error::InvalidJackId(jack_id)),
+        };
+        for _ in 0..self.recv_budget {
+            let (size, addr) = match member.socket.recv_from(&mut self.input_buffers[jack_id]) {
+                Ok(res) => res,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Err(Error::NoData),
+                Err(e) => return Err(io_error(SocketId::Input(jack_id))(e)),
+            };
+            match source_id(&addr) {
+                Some(source) if self.input_senders[jack_id].accept(source) => return Ok(size),
+                _ => self.receive_stats.wrong_source += 1,
+            }
+        }
+        self.receive_stats.budget_exhausted += 1;
+        Err(Error::NoData)
+    }
+}
+
+impl<const I: usize, const O: usize> Network<I, O> for IpcInterface<I, O> {
+    fn can_send(&mut self) -> bool {
+        true
+    }
+
+    fn recv_directive(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        match self.directive.socket.recv(buf) {
+            Ok(size) => Ok(size),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(Error::NoData),
+            Err(e) => Err(io_error(SocketId::Directive)(e)),
+        }
+    }
+
+    fn send_directive(&mut self, buf: &[u8]) -> Result<(), Error> {
+        self.send(PATCH_ADDR, buf, !self.multicast_loop, SocketId::Directive);
+        Ok(())
+    }
+
+    fn jack_connect(
+        &mut self,
+        jack_id: usize,
+        addr: [u8; 4],
+        time: MonotonicTime,
+    ) -> Result<(), Error> {
+        self.jack_disconnect(jack_id, time)?;
+        let name = format!("{}-in{}", self.name, jack_id);
+        let member = Member::join(&self.root, addr, &name, SocketId::Input(jack_id))?;
+        self.input_members[jack_id] = Some(member);
+        Ok(())
+    }
+
+    fn jack_addr(&mut self, jack_id: usize) -> Result<[u8; 4], Error> {
+        match self.output_addrs.get(jack_id) {
+            Some(res) => Ok(*res),
+            None => Err(Error::InvalidJackId(jack_id)),
+        }
+    }
+
+    fn jack_disconnect(&mut self, jack_id: usize, _time: MonotonicTime) -> Result<(), Error> {
+        match self.input_members.get_mut(jack_id) {
+            Some(v) => {
+                *v = None;
+                self.input_senders[jack_id].reset();
+                Ok(())
+            }
+            None => Err(Error::InvalidJackId(jack_id)),
+        }
+    }
+
+    fn poll(&mut self, time: MonotonicTime) -> Result<(), Error> {
+        self.time = time.as_millis();
+        let mut offset = 0;
+        for i in 0..O {
+            let size = self.enq_sizes[i];
+            if size == 0 {
+                continue;
+            }
+            let buf = mem::take(&mut self.output_buffer);
+            self.send(
+                self.output_addrs[i],
+                &buf[offset..offset + size],
+                false,
+                SocketId::Output(i),
+            );
+            self.output_buffer = buf;
+            offset += size;
+        }
+        self.enq_sizes = [0; O];
+        Ok(())
+    }
+
+    fn receive_stats(&self) -> ReceiveStats {
+        self.receive_stats
+    }
+
+    fn dequeue_packets(&mut self) -> [&[u8]; I] {
+        let mut sizes = [0; I];
+        for (jack_id, size) in sizes.iter_mut().enumerate() {
+            if let Ok(recv_size) = self.jack_recv(jack_id) {
+                *size = recv_size;
+            }
+        }
+        let mut res: [&[u8]; I] = [&[]; I];
+        for (i, buf) in self.input_buffers.iter().enumerate() {
+            res[i] = &buf[0..sizes[i]];
+        }
+        res
+    }
+
+    fn enqueue_packets(&mut self, sizes: [usize; O]) -> Result<[Option<&mut [u8]>; O], Error> {
+        if sizes.iter().sum::<usize>() > self.output_buffer.len() {
+            return Err(Error::StorageFull);
+        }
+        self.enq_sizes = sizes;
+        let mut rest = &mut self.output_buffer[..];
+        Ok(sizes.map(|size| {
+            let (chunk, tail) = mem::take(&mut rest).split_at_mut(size);
+            rest = tail;
+            Some(chunk)
+        }))
+    }
+}
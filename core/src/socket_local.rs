@@ -3,36 +3,186 @@
 Processes local messages using pub/sub for testing and wasm
  */
 use std::{
+    array,
     collections::HashMap,
     iter::zip,
+    mem,
     sync::{
+        atomic::{AtomicUsize, Ordering},
         mpsc::{sync_channel, Receiver, SyncSender, TryRecvError, TrySendError},
         Arc, Mutex,
     },
 };
 
-use rand::{thread_rng, Rng};
+use rand::{rngs::StdRng, thread_rng, Rng, SeedableRng};
 
-use crate::{Error, Network};
+use crate::{
+    time::MonotonicTime, AudioPacket, ControlAddr, Error, Network, NetworkError, ReceiveStats,
+    SocketId, SourceFilter, CONTROL_PORT, JACK_BUFFER_SIZE, RECV_BUDGET,
+};
+
+/// A packet along with the id of the interface that sent it.
+type Message = (usize, Vec<u8>);
 
 lazy_static! {
-    static ref SENDERS: Arc<Mutex<HashMap<[u8; 4], Vec<SyncSender<Vec<u8>>>>>> =
+    static ref SENDERS: Arc<Mutex<HashMap<[u8; 4], Vec<(usize, SyncSender<Message>)>>>> =
         Arc::new(Mutex::new(HashMap::new()));
 }
 
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Address that directives are sent to, for impairing the patch traffic.
+pub const PATCH_ADDR: [u8; 4] = [239, 0, 0, 0];
+
+/// Stand-in unicast address for the control port of the interface `id`. Every interface has an
+/// address of its own, so the port is always the same.
+fn control_addr(id: usize) -> ControlAddr {
+    ControlAddr {
+        addr: [127, 1, (id >> 8) as u8, id as u8],
+        port: CONTROL_PORT,
+    }
+}
+
+/// How packets to one address are mistreated on the way into an interface, to stand in for a
+/// real network. Chances are from 0 to 1, and the default is a perfect network.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Impairment {
+    /// Chance of a packet being lost
+    pub drop: f32,
+    /// Chance of a packet arriving twice
+    pub duplicate: f32,
+    /// Chance of a packet swapping places with the one before it
+    pub reorder: f32,
+    /// Delay added to every packet, in ms
+    pub latency: i64,
+    /// Extra delay drawn evenly between zero and this for each packet, in ms
+    pub jitter: i64,
+}
+
+impl Impairment {
+    fn apply(&self, msg: Message, pending: &mut Vec<(i64, Message)>, rng: &mut StdRng, now: i64) {
+        if rng.gen::<f32>() < self.drop {
+            return;
+        }
+        let copies = if rng.gen::<f32>() < self.duplicate {
+            2
+        } else {
+            1
+        };
+        for _ in 0..copies {
+            let due = now + self.latency + rng.gen_range(0..=self.jitter.max(0));
+            pending.push((due, msg.clone()));
+            let n = pending.len();
+            if n > 1 && rng.gen::<f32>() < self.reorder {
+                let (head, tail) = pending.split_at_mut(n - 1);
+                mem::swap(&mut head[n - 2].1, &mut tail[0].1);
+            }
+        }
+    }
+}
+
+/// Shared handle to the impairments an interface applies, by address, so they can be changed
+/// while a `Module` owns the interface.
+#[derive(Clone, Debug, Default)]
+pub struct Impairments(Arc<Mutex<HashMap<[u8; 4], Impairment>>>);
+
+impl Impairments {
+    /// Impair packets arriving from `addr` (a jack address or `PATCH_ADDR`), or arriving at
+    /// `addr` for the interface's own control address, or stop impairing them with `None`.
+    pub fn set(&self, addr: [u8; 4], impairment: Option<Impairment>) {
+        let mut map = self.0.lock().unwrap();
+        match impairment {
+            Some(impairment) => map.insert(addr, impairment),
+            None => map.remove(&addr),
+        };
+    }
+
+    fn get(&self, addr: [u8; 4]) -> Option<Impairment> {
+        self.0.lock().unwrap().get(&addr).copied()
+    }
+}
+
+/// Receiving end of a subscription to one address.
+struct Link {
+    addr: [u8; 4],
+    rx: Receiver<Message>,
+    pending: Vec<(i64, Message)>,
+}
+
+impl Link {
+    fn new(addr: [u8; 4], rx: Receiver<Message>) -> Self {
+        Link {
+            addr,
+            rx,
+            pending: vec![],
+        }
+    }
+
+    fn try_recv(
+        &mut self,
+        impairments: &Impairments,
+        rng: &mut StdRng,
+        now: i64,
+    ) -> Result<Message, TryRecvError> {
+        let impairment = impairments.get(self.addr);
+        if impairment.is_none() && self.pending.is_empty() {
+            return self.rx.try_recv();
+        }
+        let impairment = impairment.unwrap_or_default();
+        loop {
+            match self.rx.try_recv() {
+                Ok(msg) => impairment.apply(msg, &mut self.pending, rng, now),
+                Err(TryRecvError::Empty) => break,
+                Err(e) if self.pending.is_empty() => return Err(e),
+                Err(_) => break,
+            }
+        }
+        // Earliest due first, and in arrival order for packets due at the same time
+        match self
+            .pending
+            .iter()
+            .enumerate()
+            .filter(|(_, (due, _))| *due <= now)
+            .min_by_key(|(i, (due, _))| (*due, *i))
+        {
+            Some((i, _)) => Ok(self.pending.remove(i).1),
+            None => Err(TryRecvError::Empty),
+        }
+    }
+}
+
 pub struct LocalInterface<const I: usize, const O: usize> {
-    rx_directive: Receiver<Vec<u8>>,
-    rx_jacks: Vec<Option<Receiver<Vec<u8>>>>,
+    id: usize,
+    multicast_loop: bool,
+    rx_directive: Link,
+    rx_control: Link,
+    rx_jacks: Vec<Option<Link>>,
+    impairments: Impairments,
+    rng: StdRng,
+    time: i64,
+    tx_capacity: Option<usize>,
+    tx_blocked: bool,
+    input_senders: [SourceFilter<usize>; I],
+    recv_budget: usize,
+    receive_stats: ReceiveStats,
     output_addrs: Vec<[u8; 4]>,
-    input_buffers: [[u8; 1500]; I],
-    output_buffer: [u8; 10000],
-    enq_size: usize,
+    input_buffers: [[u8; JACK_BUFFER_SIZE]; I],
+    output_buffer: Vec<u8>,
+    enq_sizes: [usize; O],
+    send_errors: [Option<Error>; O],
 }
 
 impl<const I: usize, const O: usize> LocalInterface<I, O> {
     pub fn new() -> Option<Self> {
+        Self::with_seed(thread_rng().gen())
+    }
+
+    /// Open an interface that picks its jack addresses and impairments from `seed`, so that a
+    /// test sees the same ones on every run. Interfaces on the same network need seeds of their
+    /// own, or their jacks share addresses.
+    pub fn with_seed(seed: u64) -> Option<Self> {
         let (tx, rx) = sync_channel(50);
-        let mut rng = thread_rng();
+        let mut rng = StdRng::seed_from_u64(seed);
         let mut output_addrs = vec![];
         for _ in 0..O {
             output_addrs.push([
@@ -46,58 +196,140 @@ impl<const I: usize, const O: usize> LocalInterface<I, O> {
         for _ in 0..I {
             rx_jacks.push(None);
         }
+        let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        let (control_tx, control_rx) = sync_channel(50);
         let mut senders = SENDERS.lock().unwrap();
-        let key = [239, 0, 0, 0];
-        senders.entry(key).or_insert(vec![]).push(tx);
+        senders.entry(PATCH_ADDR).or_insert(vec![]).push((id, tx));
+        senders.insert(control_addr(id).addr, vec![(id, control_tx)]);
         Some(LocalInterface {
-            rx_directive: rx,
+            id,
+            multicast_loop: true,
+            rx_directive: Link::new(PATCH_ADDR, rx),
+            rx_control: Link::new(control_addr(id).addr, control_rx),
             rx_jacks,
+            impairments: Default::default(),
+            rng,
+            time: 0,
+            tx_capacity: None,
+            tx_blocked: false,
+            input_senders: [SourceFilter::new(); I],
+            recv_budget: RECV_BUDGET,
+            receive_stats: Default::default(),
             output_addrs,
-            input_buffers: [[0; 1500]; I],
-            output_buffer: [0; 10000],
-            enq_size: 0,
+            input_buffers: [[0; JACK_BUFFER_SIZE]; I],
+            output_buffer: vec![0; O * mem::size_of::<AudioPacket>()],
+            enq_sizes: [0; O],
+            send_errors: array::from_fn(|_| None),
         })
     }
 
+    /// Choose whether directives sent from this interface are also received by it, like
+    /// `IP_MULTICAST_LOOP` on a real socket. On by default.
+    pub fn set_multicast_loop(&mut self, enabled: bool) {
+        self.multicast_loop = enabled;
+    }
+
+    /// Handle for impairing the packets this interface receives, which stays usable after the
+    /// interface is handed to a `Module`.
+    pub fn impairments(&self) -> Impairments {
+        self.impairments.clone()
+    }
+
+    /// Seed the random choices made by impairments, so that a test sees the same packets lost
+    /// and delayed on every run.
+    pub fn set_impairment_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Limit how many jack packets can be queued between polls, like the transmit ring of an
+    /// embedded interface, so that a full ring can be tested. `None` (the default) is unlimited.
+    pub fn set_tx_capacity(&mut self, packets: Option<usize>) {
+        self.tx_capacity = packets;
+    }
+
+    /// Fail every jack packet when it is sent rather than when it is queued, like a socket
+    /// returning `WouldBlock` with its send buffer full. Off by default.
+    pub fn set_tx_blocked(&mut self, blocked: bool) {
+        self.tx_blocked = blocked;
+    }
+
+    /// Set how many packets are read from each input jack per poll while looking for one from
+    /// its sender. Defaults to 4.
+    pub fn set_recv_budget(&mut self, packets: usize) {
+        self.recv_budget = packets.max(1);
+    }
+
+    /// Receive the next packet on a jack from its sender, turning away at most `recv_budget`
+    /// packets from anywhere else along the way.
     fn jack_recv(&mut self, jack_id: usize) -> Result<usize, Error> {
-        match self.rx_jacks.get(jack_id) {
-            Some(Some(rx)) => match rx.try_recv() {
-                Ok(vbuf) => {
-                    let n = vbuf.len();
-                    if n > self.input_buffers[jack_id].len() {
-                        Err(Error::Network)
-                    } else {
-                        for (b, v) in zip(self.input_buffers[jack_id].iter_mut(), vbuf) {
-                            *b = v;
-                        }
-                        Ok(n)
-                    }
+        for _ in 0..self.recv_budget {
+            let (source, vbuf) = self.jack_try_recv(jack_id)?;
+            if self.input_senders[jack_id].accept(source) {
+                let n = vbuf.len();
+                if n > self.input_buffers[jack_id].len() {
+                    return Err(Error::Network(
+                        SocketId::Input(jack_id),
+                        NetworkError::Truncated,
+                    ));
+                }
+                for (b, v) in zip(self.input_buffers[jack_id].iter_mut(), vbuf) {
+                    *b = v;
                 }
+                return Ok(n);
+            }
+            self.receive_stats.wrong_source += 1;
+        }
+        self.receive_stats.budget_exhausted += 1;
+        Err(Error::NoData)
+    }
+
+    fn jack_try_recv(&mut self, jack_id: usize) -> Result<Message, Error> {
+        match self.rx_jacks.get_mut(jack_id) {
+            Some(Some(link)) => match link.try_recv(&self.impairments, &mut self.rng, self.time) {
+                Ok(msg) => Ok(msg),
                 Err(TryRecvError::Empty) => Err(Error::NoData),
-                Err(TryRecvError::Disconnected) => Err(Error::Network),
+                Err(TryRecvError::Disconnected) => Err(Error::Network(
+                    SocketId::Input(jack_id),
+                    NetworkError::Disconnected,
+                )),
             },
             Some(None) => Err(Error::NoData),
-            None => Err(Error::InvalidJackId),
+            None => Err(Error::InvalidJackId(jack_id)),
         }
     }
 
-    fn jack_send(&mut self, jack_id: usize, size: usize) -> Result<(), Error> {
+    fn jack_send(&mut self, jack_id: usize, offset: usize, size: usize) -> Result<(), Error> {
+        if self.tx_blocked {
+            return Err(Error::Network(
+                SocketId::Output(jack_id),
+                NetworkError::Exhausted,
+            ));
+        }
         send(
             self.jack_addr(jack_id)?,
-            &self.output_buffer[jack_id * size..(jack_id + 1) * size],
+            &self.output_buffer[offset..offset + size],
+            self.id,
+            false,
         );
         Ok(())
     }
 }
 
-fn send(key: [u8; 4], buf: &[u8]) {
+/// Deliver a packet from the interface `from` to everything subscribed to `key`, other than the
+/// sender itself if `skip_self` is set.
+fn send(key: [u8; 4], buf: &[u8], from: usize, skip_self: bool) {
     let mut senders = SENDERS.lock().unwrap();
     let vbuf = Vec::from(buf);
     if let Some(val) = senders.get_mut(&key) {
-        val.retain(|tx| match tx.try_send(vbuf.clone()) {
-            Ok(_) => true,
-            Err(TrySendError::Full(_)) => true,
-            Err(TrySendError::Disconnected(_)) => false,
+        val.retain(|(id, tx)| {
+            if skip_self && *id == from {
+                return true;
+            }
+            match tx.try_send((from, vbuf.clone())) {
+                Ok(_) => true,
+                Err(TrySendError::Full(_)) => true,
+                Err(TrySendError::Disconnected(_)) => false,
+            }
         });
     }
 }
@@ -108,11 +340,14 @@ impl<const I: usize, const O: usize> Network<I, O> for LocalInterface<I, O> {
     }
 
     fn recv_directive(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        match self.rx_directive.try_recv() {
-            Ok(vbuf) => {
+        match self
+            .rx_directive
+            .try_recv(&self.impairments, &mut self.rng, self.time)
+        {
+            Ok((_, vbuf)) => {
                 let n = vbuf.len();
                 if n > buf.len() {
-                    Err(Error::Network)
+                    Err(Error::Network(SocketId::Directive, NetworkError::Truncated))
                 } else {
                     for (b, v) in zip(buf, vbuf) {
                         *b = v;
@@ -121,92 +356,149 @@ impl<const I: usize, const O: usize> Network<I, O> for LocalInterface<I, O> {
                 }
             }
             Err(TryRecvError::Empty) => Err(Error::NoData),
-            Err(TryRecvError::Disconnected) => Err(Error::Network),
+            Err(TryRecvError::Disconnected) => Err(Error::Network(
+                SocketId::Directive,
+                NetworkError::Disconnected,
+            )),
         }
     }
 
     fn send_directive(&mut self, buf: &[u8]) -> Result<(), Error> {
-        send([239, 0, 0, 0], buf);
+        send(PATCH_ADDR, buf, self.id, !self.multicast_loop);
         Ok(())
     }
 
-    fn jack_connect(&mut self, jack_id: usize, addr: [u8; 4], _time: i64) -> Result<(), Error> {
+    fn control_addr(&mut self) -> Option<ControlAddr> {
+        Some(control_addr(self.id))
+    }
+
+    fn send_control(&mut self, addr: ControlAddr, buf: &[u8]) -> Result<(), Error> {
+        send(addr.addr, buf, self.id, false);
+        Ok(())
+    }
+
+    fn recv_control(&mut self, buf: &mut [u8]) -> Result<(usize, ControlAddr), Error> {
+        match self
+            .rx_control
+            .try_recv(&self.impairments, &mut self.rng, self.time)
+        {
+            Ok((from, vbuf)) => {
+                let n = vbuf.len();
+                if n > buf.len() {
+                    return Err(Error::Network(SocketId::Control, NetworkError::Truncated));
+                }
+                buf[..n].copy_from_slice(&vbuf);
+                Ok((n, control_addr(from)))
+            }
+            Err(TryRecvError::Empty) => Err(Error::NoData),
+            Err(TryRecvError::Disconnected) => Err(Error::Network(
+                SocketId::Control,
+                NetworkError::Disconnected,
+            )),
+        }
+    }
+
+    fn jack_connect(
+        &mut self,
+        jack_id: usize,
+        addr: [u8; 4],
+        _time: MonotonicTime,
+    ) -> Result<(), Error> {
         let (tx, rx) = sync_channel(2);
         match self.rx_jacks.get_mut(jack_id) {
             Some(v) => {
-                *v = Some(rx);
+                *v = Some(Link::new(addr, rx));
+                self.input_senders[jack_id].reset();
                 let mut senders = SENDERS.lock().unwrap();
-                senders.entry(addr).or_insert(vec![]).push(tx);
+                senders.entry(addr).or_insert(vec![]).push((self.id, tx));
                 Ok(())
             }
-            None => Err(Error::InvalidJackId),
+            None => Err(Error::InvalidJackId(jack_id)),
         }
     }
 
     fn jack_addr(&mut self, jack_id: usize) -> Result<[u8; 4], Error> {
         match self.output_addrs.get(jack_id) {
             Some(res) => Ok(*res),
-            None => Err(Error::InvalidJackId),
+            None => Err(Error::InvalidJackId(jack_id)),
         }
     }
 
-    fn jack_disconnect(&mut self, jack_id: usize, _time: i64) -> Result<(), Error> {
+    fn jack_disconnect(&mut self, jack_id: usize, _time: MonotonicTime) -> Result<(), Error> {
         match self.rx_jacks.get_mut(jack_id) {
             Some(v) => {
                 *v = None;
+                self.input_senders[jack_id].reset();
                 Ok(())
             }
-            None => Err(Error::InvalidJackId),
+            None => Err(Error::InvalidJackId(jack_id)),
         }
     }
 
-    fn poll(&mut self, _time: i64) -> Result<(), Error> {
-        if self.enq_size == 0 {
-            Ok(())
-        } else {
-            for i in 0..O {
-                match self.jack_send(i, self.enq_size) {
-                    Ok(_) => {}
-                    Err(e) => {
-                        info!("Jack send error: {:?}", e);
-                        return Err(Error::Network);
-                    }
-                }
+    fn poll(&mut self, time: MonotonicTime) -> Result<(), Error> {
+        self.time = time.as_millis();
+        let mut offset = 0;
+        for i in 0..O {
+            let size = self.enq_sizes[i];
+            if size == 0 {
+                continue;
             }
-            Ok(())
+            if let Err(e) = self.jack_send(i, offset, size) {
+                info!("Jack send error: {:?}", e);
+                self.send_errors[i] = Some(e);
+            }
+            offset += size;
         }
+        self.enq_sizes = [0; O];
+        Ok(())
     }
 
-    fn dequeue_packets(&mut self, size: usize) -> ([&[u8]; I], u32) {
-        let mut dropped_packets = 0;
-        for jack_id in 0..I {
-            match self.jack_recv(jack_id) {
-                Ok(recv_size) if recv_size == size => {}
-                _ => {
-                    self.input_buffers[jack_id] = [0; 1500];
-                    dropped_packets += 1;
-                }
+    fn take_send_errors(&mut self) -> [Option<Error>; O] {
+        mem::replace(&mut self.send_errors, array::from_fn(|_| None))
+    }
+
+    fn receive_stats(&self) -> ReceiveStats {
+        self.receive_stats
+    }
+
+    fn dequeue_packets(&mut self) -> [&[u8]; I] {
+        let mut sizes = [0; I];
+        for (jack_id, size) in sizes.iter_mut().enumerate() {
+            if let Ok(recv_size) = self.jack_recv(jack_id) {
+                *size = recv_size;
             }
         }
-        let mut res: [Option<&[u8]>; I] = [(); I].map(|_| None);
+        let mut res: [&[u8]; I] = [&[]; I];
         for (i, buf) in self.input_buffers.iter().enumerate() {
-            res[i] = Some(&buf[0..size]);
+            res[i] = &buf[0..sizes[i]];
         }
-        (res.map(|c| c.unwrap()), dropped_packets)
+        res
     }
 
-    fn enqueue_packets(&mut self, size: usize) -> Result<[&mut [u8]; O], Error> {
-        if size * O > self.output_buffer.len() {
+    fn enqueue_packets(&mut self, sizes: [usize; O]) -> Result<[Option<&mut [u8]>; O], Error> {
+        if sizes.iter().sum::<usize>() > self.output_buffer.len() {
             return Err(Error::StorageFull);
         }
-        self.enq_size = size;
-        let mut res: [Option<&mut [u8]>; O] = [(); O].map(|_| None);
-        for (i, chunk) in self.output_buffer[0..size * O]
-            .chunks_exact_mut(size)
-            .enumerate()
-        {
-            res[i] = Some(chunk);
+        let mut room = self.tx_capacity.unwrap_or(O);
+        let accepted = sizes.map(|size| {
+            if size == 0 {
+                true
+            } else if room > 0 {
+                room -= 1;
+                true
+            } else {
+                false
+            }
+        });
+        for i in 0..O {
+            self.enq_sizes[i] = if accepted[i] { sizes[i] } else { 0 };
         }
-        Ok(res.map(|c| c.unwrap()))
+        let mut accepted = accepted.into_iter();
+        let mut rest = &mut self.output_buffer[..];
+        Ok(self.enq_sizes.map(|size| {
+            let (chunk, tail) = mem::take(&mut rest).split_at_mut(size);
+            rest = tail;
+            accepted.next().unwrap().then_some(chunk)
+        }))
     }
 }
@@ -3,7 +3,8 @@
 This module provides communication (via the `Network` trait) using the native socket interface within the host operating system.
 */
 
-use core::mem::MaybeUninit;
+use core::array;
+use core::mem::{self, MaybeUninit};
 use core::str::FromStr;
 use ipnet::Ipv4Net;
 use local_ip_address::list_afinet_netifas;
@@ -12,81 +13,336 @@ use socket2::{Domain, Protocol, Socket, Type};
 use std::io;
 use std::net::IpAddr::V4;
 use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use crate::{Error, Network, JACK_PORT, PATCH_EP, PREFERRED_SUBNET};
+use crate::{
+    time::MonotonicTime, AudioPacket, ControlAddr, Error, Network, NetworkError, NetworkEvent,
+    ParseError, ReceiveStats, SocketId, SourceFilter, JACK_BUFFER_SIZE, JACK_PORT, PATCH_EP,
+    PREFERRED_SUBNET, RECV_BUDGET,
+};
+
+/// How often to check that the local address hasn't changed under us
+const REVALIDATE_INTERVAL: i64 = 2000; // ms
+/// How long `self_test` waits to hear its own multicast
+const SELF_TEST_TIMEOUT: Duration = Duration::from_millis(500);
 
 impl From<local_ip_address::Error> for Error {
     fn from(_: local_ip_address::Error) -> Self {
-        Error::Network
+        Error::Network(SocketId::Interface, NetworkError::Unavailable)
     }
 }
 
 impl From<ipnet::AddrParseError> for Error {
     fn from(_: ipnet::AddrParseError) -> Self {
-        Error::Parse
+        Error::Parse(ParseError::Address)
     }
 }
 
 impl From<std::net::AddrParseError> for Error {
     fn from(_: std::net::AddrParseError) -> Self {
-        Error::Parse
+        Error::Parse(ParseError::Address)
     }
 }
 
 impl From<std::io::Error> for Error {
-    fn from(_: std::io::Error) -> Self {
-        Error::Network
+    fn from(e: std::io::Error) -> Self {
+        Error::Network(SocketId::Interface, NetworkError::Io(e.kind()))
+    }
+}
+
+fn io_error(socket: SocketId) -> impl FnOnce(io::Error) -> Error {
+    move |e| Error::Network(socket, NetworkError::Io(e.kind()))
+}
+
+/// Open a nonblocking UDP socket that can share its port with the other modules on this host.
+fn open_socket(socket_id: SocketId) -> Result<Socket, Error> {
+    let socket =
+        Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP)).map_err(io_error(socket_id))?;
+    // The socket allows address reuse, which may be a security concern. However, we are
+    // exclusively looking at UDP multicasts in this protocol.
+    socket
+        .set_reuse_address(true)
+        .map_err(io_error(socket_id))?;
+    // On macOS and the BSDs only SO_REUSEPORT lets more than one socket bind the same port
+    #[cfg(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "freebsd",
+        target_os = "openbsd",
+        target_os = "netbsd",
+        target_os = "dragonfly"
+    ))]
+    socket.set_reuse_port(true).map_err(io_error(socket_id))?;
+    socket.set_nonblocking(true).map_err(io_error(socket_id))?;
+    Ok(socket)
+}
+
+/// Open the unicast control port, which unlike the multicast sockets belongs to a single module.
+/// Each module on a host gets a port of its own from the operating system, which it hands out
+/// along with its address in bulk transfer directives.
+fn open_control_socket() -> Option<(Socket, u16)> {
+    let open = || -> io::Result<(Socket, u16)> {
+        let socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
+        socket.bind(&SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)).into())?;
+        socket.set_nonblocking(true)?;
+        let port = socket
+            .local_addr()?
+            .as_socket()
+            .map_or(0, |addr| addr.port());
+        Ok((socket, port))
+    };
+    match open() {
+        Ok(res) => Some(res),
+        Err(e) => {
+            info!("No control port: {}", e);
+            None
+        }
+    }
+}
+
+/// The address to bind sockets to for receiving multicast on `local_addr`. Windows wants the
+/// interface address itself, while elsewhere that would filter out everything sent to a group,
+/// so the interface is only picked out by the multicast joins.
+fn bind_addr(local_addr: Ipv4Addr) -> Ipv4Addr {
+    if cfg!(windows) {
+        local_addr
+    } else {
+        Ipv4Addr::UNSPECIFIED
+    }
+}
+
+/// Look up the index of the interface with address `addr`.
+#[cfg(windows)]
+fn interface_index(addr: Ipv4Addr) -> Option<u32> {
+    use windows_sys::Win32::NetworkManagement::IpHelper::{GetIpAddrTable, MIB_IPADDRTABLE};
+
+    let mut size = 0;
+    // The first call only reports how big the table is
+    unsafe { GetIpAddrTable(core::ptr::null_mut(), &mut size, 0) };
+    let mut buf = vec![0u32; (size as usize + 3) / 4];
+    let table = buf.as_mut_ptr() as *mut MIB_IPADDRTABLE;
+    if unsafe { GetIpAddrTable(table, &mut size, 0) } != 0 {
+        return None;
+    }
+    // Safety: the table was filled in above, with `dwNumEntries` rows following the header
+    let rows = unsafe {
+        core::slice::from_raw_parts((*table).table.as_ptr(), (*table).dwNumEntries as usize)
+    };
+    rows.iter()
+        .find(|row| Ipv4Addr::from(row.dwAddr.to_ne_bytes()) == addr)
+        .map(|row| row.dwIndex)
+}
+
+/// Join `group` on the interface with `local_addr`. Windows can pick the wrong interface when
+/// joining by address on machines where several share a subnet, so it joins by index instead.
+fn join(socket: &Socket, group: &Ipv4Addr, local_addr: &Ipv4Addr) -> io::Result<()> {
+    #[cfg(windows)]
+    if let Some(index) = interface_index(*local_addr) {
+        return socket.join_multicast_v4_n(group, &socket2::InterfaceIndexOrAddress::Index(index));
+    }
+    socket.join_multicast_v4(group, local_addr)
+}
+
+fn leave(socket: &Socket, group: &Ipv4Addr, local_addr: &Ipv4Addr) -> io::Result<()> {
+    #[cfg(windows)]
+    if let Some(index) = interface_index(*local_addr) {
+        return socket.leave_multicast_v4_n(group, &socket2::InterfaceIndexOrAddress::Index(index));
+    }
+    socket.leave_multicast_v4(group, local_addr)
+}
+
+/// Find the address of the network interface called `name`, or otherwise the last one in the
+/// preferred subnet, falling back to all interfaces.
+fn find_local_addr(name: Option<&str>) -> Result<Ipv4Addr, Error> {
+    let ips = list_afinet_netifas()?;
+    match name {
+        Some(name) => ips
+            .into_iter()
+            .find_map(|(n, ip)| match ip {
+                V4(addr) if n == name => Some(addr),
+                _ => None,
+            })
+            .ok_or(Error::Network(
+                SocketId::Interface,
+                NetworkError::Unavailable,
+            )),
+        None => {
+            let preferred_subnet: Ipv4Net = PREFERRED_SUBNET.parse()?;
+            let mut local_addr = Ipv4Addr::UNSPECIFIED;
+            for (name, ip) in ips {
+                if let V4(addr) = ip {
+                    trace!("Found IP address: {:?} {:?}", name, addr);
+                    if preferred_subnet.contains(&addr) {
+                        local_addr = addr;
+                    }
+                }
+            }
+            Ok(local_addr)
+        }
     }
 }
 
 pub struct NativeInterface<const I: usize, const O: usize> {
     patch_socket: Socket,
     patch_ep: SocketAddrV4,
+    /// The unicast control port, and the port number the operating system gave it
+    control_socket: Option<(Socket, u16)>,
     input_sockets: Vec<Socket>,
     input_groups: Vec<Option<Ipv4Addr>>,
     output_eps: Vec<SocketAddrV4>,
     local_addr: Ipv4Addr,
-    input_buffers: [[u8; 1500]; I],
-    output_buffer: [u8; 10000],
-    enq_size: usize,
+    interface_name: Option<String>,
+    multicast_ttl: u32,
+    multicast_loop: bool,
+    revalidate_timeout: i64,
+    event: Option<NetworkEvent>,
+    input_buffers: [[u8; JACK_BUFFER_SIZE]; I],
+    output_buffer: Vec<u8>,
+    enq_sizes: [usize; O],
+    next_send: usize,
+    /// Why each output jack's packet couldn't be sent, until taken by the module
+    send_errors: [Option<Error>; O],
+    pacing: Option<usize>,
+    input_senders: [SourceFilter<Ipv4Addr>; I],
+    recv_budget: usize,
+    receive_stats: ReceiveStats,
 }
 
 impl<const I: usize, const O: usize> NativeInterface<I, O> {
+    /// Open the interface on an address in the preferred subnet, or on all interfaces if there
+    /// isn't one.
     pub fn new() -> Result<Self, Error> {
-        let ips = list_afinet_netifas()?;
-        let preferred_subnet: Ipv4Net = PREFERRED_SUBNET.parse()?;
-        let mut local_addr = Ipv4Addr::UNSPECIFIED;
-        for (name, ip) in ips {
-            if let V4(addr) = ip {
-                info!("Found IP address: {:?} {:?}", name, addr);
-                if preferred_subnet.contains(&addr) {
-                    local_addr = addr;
+        Self::open(find_local_addr(None)?, None)
+    }
+
+    /// Open the interface on the network interface called `name` (such as "eth0"), for machines
+    /// with more than one in the preferred subnet.
+    pub fn with_interface(name: &str) -> Result<Self, Error> {
+        Self::open(find_local_addr(Some(name))?, Some(name.into()))
+    }
+
+    /// Set how many routers the directive and jack multicasts can cross. Defaults to 1, which
+    /// keeps them on the local network.
+    pub fn set_multicast_ttl(&mut self, ttl: u32) -> Result<(), Error> {
+        self.multicast_ttl = ttl;
+        self.patch_socket
+            .set_multicast_ttl_v4(ttl)
+            .map_err(io_error(SocketId::Interface))
+    }
+
+    /// Choose whether multicasts sent from this interface are also received by it. On by
+    /// default.
+    pub fn set_multicast_loop(&mut self, enabled: bool) -> Result<(), Error> {
+        self.multicast_loop = enabled;
+        self.patch_socket
+            .set_multicast_loop_v4(enabled)
+            .map_err(io_error(SocketId::Interface))
+    }
+
+    /// Send at most `jacks_per_poll` jack packets each time the interface is polled, leaving the
+    /// rest for later polls instead of sending every jack back-to-back. `None` (the default)
+    /// sends everything at once.
+    pub fn set_pacing(&mut self, jacks_per_poll: Option<usize>) {
+        self.pacing = jacks_per_poll.map(|n| n.max(1));
+    }
+
+    /// Set how many packets are read from each input jack per poll while looking for one from
+    /// its sender. Defaults to 4.
+    pub fn set_recv_budget(&mut self, packets: usize) {
+        self.recv_budget = packets.max(1);
+    }
+
+    /// Send up to `count` of the enqueued jack packets that haven't gone out yet. A jack that
+    /// can't be sent is noted in `send_errors` and the rest carry on.
+    fn send_pending(&mut self, mut count: usize) {
+        let mut offset: usize = self.enq_sizes[..self.next_send.min(O)].iter().sum();
+        while self.next_send < O && count > 0 {
+            let i = self.next_send;
+            let size = self.enq_sizes[i];
+            self.next_send += 1;
+            if size == 0 {
+                continue;
+            }
+            match self.patch_socket.send_to(
+                &self.output_buffer[offset..offset + size],
+                &self.output_eps[i].into(),
+            ) {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    self.send_errors[i] =
+                        Some(Error::Network(SocketId::Output(i), NetworkError::Exhausted));
+                }
+                Err(e) => {
+                    info!("Jack send error: {:?}", e);
+                    self.send_errors[i] = Some(io_error(SocketId::Output(i))(e));
                 }
             }
+            offset += size;
+            count -= 1;
         }
-        info!("Using local address {:?}", local_addr);
+    }
 
-        let patch_ep = SocketAddrV4::from_str(PATCH_EP)?;
-        let patch_socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
-        let address = SocketAddr::from((local_addr, patch_ep.port())).into();
+    /// Check that multicast sent from this interface comes back to it, which is the first thing to
+    /// go wrong with a firewall or the wrong interface. Problems are logged along with what to
+    /// look at, and anything else already waiting on the directive socket is discarded.
+    pub fn self_test(&mut self) -> Result<(), Error> {
+        if self.local_addr.is_unspecified() {
+            warn!(
+                "No address in {} found, so the operating system picks the interface. Choose one \
+                 with NativeInterface::with_interface if nothing is heard from other modules.",
+                PREFERRED_SUBNET
+            );
+        }
+        let multicast_loop = self.multicast_loop;
+        self.set_multicast_loop(true)?;
+        let result = self.loopback_probe();
+        self.set_multicast_loop(multicast_loop)?;
+        match &result {
+            Ok(()) => info!("Self test passed on {:?}", self.local_addr),
+            Err(Error::Network(_, NetworkError::NoLoopback)) => warn!(
+                "Nothing sent to {} came back within {:?}. Check that the firewall allows UDP on \
+                 ports {} and {} and that {:?} is up and supports multicast.",
+                self.patch_ep,
+                SELF_TEST_TIMEOUT,
+                self.patch_ep.port(),
+                JACK_PORT,
+                self.local_addr
+            ),
+            Err(e) => warn!(
+                "Could not send multicast to {}: {}. Check that there is a route to it from \
+                 {:?}.",
+                self.patch_ep, e, self.local_addr
+            ),
+        }
+        result
+    }
 
-        // The socket allows address reuse, which may be a security concern. However, we are
-        // exclusively looking at UDP multicasts in this protocol.
-        patch_socket.set_reuse_address(true)?;
-        patch_socket.set_nonblocking(true)?;
-        patch_socket.bind(&address)?;
-        patch_socket.join_multicast_v4(patch_ep.ip(), &local_addr)?;
+    fn loopback_probe(&mut self) -> Result<(), Error> {
+        let mut probe = *b"apiary self test 0000";
+        let nonce: u32 = thread_rng().gen();
+        probe[17..].copy_from_slice(&nonce.to_be_bytes());
+        self.patch_socket
+            .send_to(&probe, &self.patch_ep.into())
+            .map_err(io_error(SocketId::Directive))?;
 
-        let mut input_sockets = vec![];
-        for _ in 0..I {
-            let input_socket = Socket::new(Domain::IPV4, Type::DGRAM, Some(Protocol::UDP))?;
-            input_socket.set_reuse_address(true)?;
-            input_socket.set_nonblocking(true)?;
-            let input_address = SocketAddr::from((local_addr, JACK_PORT)).into();
-            input_socket.bind(&input_address)?;
-            input_sockets.push(input_socket);
+        let start = Instant::now();
+        let mut buf = [0; 2048];
+        while start.elapsed() < SELF_TEST_TIMEOUT {
+            match self.recv_directive(&mut buf) {
+                Ok(size) if buf[..size] == probe => return Ok(()),
+                Ok(_) => {}
+                Err(Error::NoData) => thread::sleep(Duration::from_millis(1)),
+                Err(e) => return Err(e),
+            }
         }
+        Err(Error::Network(
+            SocketId::Directive,
+            NetworkError::NoLoopback,
+        ))
+    }
 
+    fn open(local_addr: Ipv4Addr, interface_name: Option<String>) -> Result<Self, Error> {
         // For now we just pick a random address in the multicast range for local testing purposes,
         // but ideally this will likely be some function of the interface address for devices that
         // all have their own ip (for instance, 10.0.42.69 => 239.42.69.(1,2, ...)). Source-specific
@@ -101,23 +357,116 @@ impl<const I: usize, const O: usize> NativeInterface<I, O> {
                 rng.gen_range(0..255),
             );
             let ep = SocketAddrV4::new(addr, JACK_PORT);
-            patch_socket.join_multicast_v4(&addr, &local_addr)?;
             info!("Jack endpoint: {:?}", ep);
             output_eps.push(ep);
         }
 
+        let patch_ep = SocketAddrV4::from_str(PATCH_EP)?;
+        let (patch_socket, input_sockets) = Self::bind(local_addr, &patch_ep, &output_eps)?;
+
         Ok(NativeInterface {
             patch_socket,
             patch_ep,
+            control_socket: open_control_socket(),
             input_sockets,
             input_groups: vec![None; I],
             output_eps,
             local_addr,
-            input_buffers: [[0; 1500]; I],
-            output_buffer: [0; 10000],
-            enq_size: 0,
+            interface_name,
+            multicast_ttl: 1,
+            multicast_loop: true,
+            revalidate_timeout: 0,
+            event: None,
+            input_buffers: [[0; JACK_BUFFER_SIZE]; I],
+            output_buffer: vec![0; O * mem::size_of::<AudioPacket>()],
+            enq_sizes: [0; O],
+            next_send: O,
+            send_errors: array::from_fn(|_| None),
+            pacing: None,
+            input_senders: [SourceFilter::new(); I],
+            recv_budget: RECV_BUDGET,
+            receive_stats: Default::default(),
         })
     }
+
+    /// Open the directive and input jack sockets on `local_addr`, and join the directive and
+    /// output jack groups.
+    fn bind(
+        local_addr: Ipv4Addr,
+        patch_ep: &SocketAddrV4,
+        output_eps: &[SocketAddrV4],
+    ) -> Result<(Socket, Vec<Socket>), Error> {
+        info!("Using local address {:?}", local_addr);
+
+        let patch_socket = open_socket(SocketId::Directive)?;
+        let address = SocketAddr::from((bind_addr(local_addr), patch_ep.port())).into();
+        patch_socket
+            .bind(&address)
+            .map_err(io_error(SocketId::Directive))?;
+        // Send from the chosen interface too, rather than whichever one the routing table picks
+        if !local_addr.is_unspecified() {
+            patch_socket
+                .set_multicast_if_v4(&local_addr)
+                .map_err(io_error(SocketId::Directive))?;
+        }
+        join(&patch_socket, patch_ep.ip(), &local_addr).map_err(io_error(SocketId::Directive))?;
+
+        let mut input_sockets = vec![];
+        for i in 0..I {
+            let input_socket = open_socket(SocketId::Input(i))?;
+            let input_address = SocketAddr::from((bind_addr(local_addr), JACK_PORT)).into();
+            input_socket
+                .bind(&input_address)
+                .map_err(io_error(SocketId::Input(i)))?;
+            input_sockets.push(input_socket);
+        }
+
+        for (i, ep) in output_eps.iter().enumerate() {
+            join(&patch_socket, ep.ip(), &local_addr).map_err(io_error(SocketId::Output(i)))?;
+        }
+        Ok((patch_socket, input_sockets))
+    }
+
+    /// Check every so often that the local address is still the one in use. A DHCP renewal or
+    /// moving between networks can change it, which silently stops multicast from arriving, so
+    /// the sockets are then reopened on the new address and rejoin all of their groups.
+    fn revalidate(&mut self, time: i64) -> Result<(), Error> {
+        if time < self.revalidate_timeout {
+            return Ok(());
+        }
+        self.revalidate_timeout = time + REVALIDATE_INTERVAL;
+        let local_addr = match find_local_addr(self.interface_name.as_deref()) {
+            Ok(addr) => addr,
+            // The interface may only be gone for a moment, so keep trying with the old sockets
+            Err(_) => return Ok(()),
+        };
+        if local_addr == self.local_addr {
+            return Ok(());
+        }
+        info!(
+            "Local address changed from {:?} to {:?}",
+            self.local_addr, local_addr
+        );
+        let (patch_socket, input_sockets) =
+            Self::bind(local_addr, &self.patch_ep, &self.output_eps)?;
+        patch_socket
+            .set_multicast_ttl_v4(self.multicast_ttl)
+            .map_err(io_error(SocketId::Interface))?;
+        patch_socket
+            .set_multicast_loop_v4(self.multicast_loop)
+            .map_err(io_error(SocketId::Interface))?;
+        for (i, group) in self.input_groups.iter().enumerate() {
+            if let Some(group) = group {
+                join(&input_sockets[i], group, &local_addr)
+                    .map_err(io_error(SocketId::Input(i)))?;
+            }
+        }
+        self.patch_socket = patch_socket;
+        self.input_sockets = input_sockets;
+        self.local_addr = local_addr;
+        self.event = Some(NetworkEvent::AddressChanged(local_addr.octets()));
+        Ok(())
+    }
 }
 
 impl<const I: usize, const O: usize> Network<I, O> for NativeInterface<I, O> {
@@ -131,7 +480,8 @@ impl<const I: usize, const O: usize> Network<I, O> for NativeInterface<I, O> {
         let buf = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
         match self.patch_socket.recv_from(buf) {
             Ok((size, _)) => Ok(size),
-            Err(_) => Err(Error::NoData),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(Error::NoData),
+            Err(e) => Err(io_error(SocketId::Directive)(e)),
         }
     }
 
@@ -139,56 +489,111 @@ impl<const I: usize, const O: usize> Network<I, O> for NativeInterface<I, O> {
         match self.patch_socket.send_to(buf, &self.patch_ep.into()) {
             Ok(_) => Ok(()),
             Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
-            Err(_) => Err(Error::Network),
+            Err(e) => Err(io_error(SocketId::Directive)(e)),
+        }
+    }
+
+    fn control_addr(&mut self) -> Option<ControlAddr> {
+        // Other modules can't be told where to find an interface the operating system picks
+        match &self.control_socket {
+            Some((_, port)) if !self.local_addr.is_unspecified() => Some(ControlAddr {
+                addr: self.local_addr.octets(),
+                port: *port,
+            }),
+            _ => None,
         }
     }
 
-    fn jack_connect(&mut self, jack_id: usize, addr: [u8; 4], time: i64) -> Result<(), Error> {
+    fn send_control(&mut self, addr: ControlAddr, buf: &[u8]) -> Result<(), Error> {
+        let (socket, _) = self
+            .control_socket
+            .as_ref()
+            .ok_or(Error::Network(SocketId::Control, NetworkError::Unsupported))?;
+        let ep = SocketAddrV4::new(addr.addr.into(), addr.port);
+        match socket.send_to(buf, &ep.into()) {
+            Ok(_) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(io_error(SocketId::Control)(e)),
+        }
+    }
+
+    fn recv_control(&mut self, buf: &mut [u8]) -> Result<(usize, ControlAddr), Error> {
+        let (socket, _) = self.control_socket.as_ref().ok_or(Error::NoData)?;
+        // Safety: the `recv` implementation promises not to write uninitialised
+        // bytes to the `buf`fer, so this casting is safe.
+        let buf = unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) };
+        match socket.recv_from(buf) {
+            Ok((size, src)) => match src.as_socket_ipv4() {
+                Some(src) => Ok((
+                    size,
+                    ControlAddr {
+                        addr: src.ip().octets(),
+                        port: src.port(),
+                    },
+                )),
+                None => Err(Error::NoData),
+            },
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => Err(Error::NoData),
+            Err(e) => Err(io_error(SocketId::Control)(e)),
+        }
+    }
+
+    fn jack_connect(
+        &mut self,
+        jack_id: usize,
+        addr: [u8; 4],
+        time: MonotonicTime,
+    ) -> Result<(), Error> {
         if jack_id >= self.input_sockets.len() {
-            return Err(Error::InvalidJackId);
+            return Err(Error::InvalidJackId(jack_id));
         }
         self.jack_disconnect(jack_id, time)?;
         let address = addr.into();
-        self.input_sockets[jack_id].join_multicast_v4(&address, &self.local_addr)?;
+        join(&self.input_sockets[jack_id], &address, &self.local_addr)
+            .map_err(io_error(SocketId::Input(jack_id)))?;
         self.input_groups[jack_id] = Some(address);
+        self.input_senders[jack_id].reset();
         Ok(())
     }
 
     fn jack_addr(&mut self, jack_id: usize) -> Result<[u8; 4], Error> {
         if jack_id >= self.output_eps.len() {
-            return Err(Error::InvalidJackId);
+            return Err(Error::InvalidJackId(jack_id));
         }
         Ok(self.output_eps[jack_id].ip().octets())
     }
 
-    fn jack_disconnect(&mut self, jack_id: usize, _time: i64) -> Result<(), Error> {
+    fn jack_disconnect(&mut self, jack_id: usize, _time: MonotonicTime) -> Result<(), Error> {
         if jack_id >= self.input_sockets.len() {
-            return Err(Error::InvalidJackId);
+            return Err(Error::InvalidJackId(jack_id));
         }
         if let Some(old_addr) = self.input_groups[jack_id] {
-            self.input_sockets[jack_id].leave_multicast_v4(&old_addr, &self.local_addr)?;
+            leave(&self.input_sockets[jack_id], &old_addr, &self.local_addr)
+                .map_err(io_error(SocketId::Input(jack_id)))?;
             self.input_groups[jack_id] = None;
         }
+        self.input_senders[jack_id].reset();
         Ok(())
     }
 
-    fn enqueue_packets(&mut self, size: usize) -> Result<[&mut [u8]; O], Error> {
-        if size * O > self.output_buffer.len() {
+    fn enqueue_packets(&mut self, sizes: [usize; O]) -> Result<[Option<&mut [u8]>; O], Error> {
+        if sizes.iter().sum::<usize>() > self.output_buffer.len() {
             return Err(Error::StorageFull);
         }
-        self.enq_size = size;
-        let mut res: [Option<&mut [u8]>; O] = [(); O].map(|_| None);
-        for (i, chunk) in self.output_buffer[0..size * O]
-            .chunks_exact_mut(size)
-            .enumerate()
-        {
-            res[i] = Some(chunk);
-        }
-        Ok(res.map(|c| c.unwrap()))
+        // Anything still waiting from the last block goes out now rather than being overwritten
+        self.send_pending(O);
+        self.enq_sizes = sizes;
+        self.next_send = 0;
+        let mut rest = &mut self.output_buffer[..];
+        Ok(sizes.map(|size| {
+            let (chunk, tail) = mem::take(&mut rest).split_at_mut(size);
+            rest = tail;
+            Some(chunk)
+        }))
     }
 
-    fn dequeue_packets(&mut self, size: usize) -> ([&[u8]; I], u32) {
-        let mut dropped_packets = 0;
+    fn dequeue_packets(&mut self) -> [&[u8]; I] {
+        let mut sizes = [0; I];
         for jack_id in 0..I {
             // Safety: the `recv` implementation promises not to write uninitialised
             // bytes to the `buf`fer, so this casting is safe.
@@ -196,39 +601,54 @@ impl<const I: usize, const O: usize> Network<I, O> for NativeInterface<I, O> {
                 &mut *(&mut self.input_buffers[jack_id][..] as *mut [u8]
                     as *mut [MaybeUninit<u8>])
             };
-            match self.input_sockets[jack_id].recv_from(buf) {
-                Ok((recv_size, _)) if recv_size == size => {}
-                _ => {
-                    self.input_buffers[jack_id] = [0; 1500];
-                    dropped_packets += 1;
+            // Every socket on the port gets the groups joined anywhere on the host, so one that
+            // isn't connected throws away whatever turns up rather than passing it on
+            if self.input_groups[jack_id].is_none() {
+                while self.input_sockets[jack_id].recv_from(buf).is_ok() {}
+                continue;
+            }
+            let mut read = 0;
+            let mut accepted = false;
+            while read < self.recv_budget && !accepted {
+                let (recv_size, src) = match self.input_sockets[jack_id].recv_from(buf) {
+                    Ok(res) => res,
+                    Err(_) => break,
+                };
+                read += 1;
+                let src = src.as_socket_ipv4().map(|a| *a.ip());
+                if src.map_or(false, |src| self.input_senders[jack_id].accept(src)) {
+                    sizes[jack_id] = recv_size;
+                    accepted = true;
+                } else {
+                    self.receive_stats.wrong_source += 1;
                 }
             }
+            if read == self.recv_budget && !accepted {
+                self.receive_stats.budget_exhausted += 1;
+            }
         }
-        let mut res: [Option<&[u8]>; I] = [(); I].map(|_| None);
+        let mut res: [&[u8]; I] = [&[]; I];
         for (i, buf) in self.input_buffers.iter().enumerate() {
-            res[i] = Some(&buf[0..size]);
-        }
-        (res.map(|c| c.unwrap()), dropped_packets)
-    }
-
-    fn poll(&mut self, _time: i64) -> Result<(), Error> {
-        if self.enq_size == 0 {
-            Ok(())
-        } else {
-            for i in 0..O {
-                match self.patch_socket.send_to(
-                    &self.output_buffer[i * self.enq_size..(i + 1) * self.enq_size],
-                    &self.output_eps[i].into(),
-                ) {
-                    Ok(_) => {}
-                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
-                    Err(e) => {
-                        info!("Jack send error: {:?}", e);
-                        return Err(Error::Network);
-                    }
-                }
-            }
-            Ok(())
+            res[i] = &buf[0..sizes[i]];
         }
+        res
+    }
+
+    fn take_event(&mut self) -> Option<NetworkEvent> {
+        self.event.take()
+    }
+
+    fn receive_stats(&self) -> ReceiveStats {
+        self.receive_stats
+    }
+
+    fn poll(&mut self, time: MonotonicTime) -> Result<(), Error> {
+        self.revalidate(time.as_millis())?;
+        self.send_pending(self.pacing.unwrap_or(O));
+        Ok(())
+    }
+
+    fn take_send_errors(&mut self) -> [Option<Error>; O] {
+        mem::replace(&mut self.send_errors, array::from_fn(|_| None))
     }
 }
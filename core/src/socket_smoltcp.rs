@@ -3,6 +3,8 @@
 This module provides communication (via the `Network` trait) and basic network management using a `smoltcp`-based network stack, for devices that do not otherwise provide one.
 */
 
+use core::array;
+use core::mem;
 use core::str::FromStr;
 
 use itertools::izip;
@@ -17,7 +19,28 @@ use smoltcp::{
     wire::{EthernetAddress, IpAddress, IpCidr, IpEndpoint, Ipv4Address, Ipv4Cidr},
 };
 
-use crate::{Error, Network, JACK_PORT};
+use crate::{
+    time::MonotonicTime, AudioPacket, ControlAddr, Error, Network, NetworkError, NetworkEvent,
+    ReceiveStats, SocketId, SourceFilter, CONTROL_PORT, JACK_PORT, RECV_BUDGET,
+};
+
+/// Jack socket payload storage, with room for at least four audio packets.
+const JACK_PAYLOAD_SIZE: usize = if 4 * mem::size_of::<AudioPacket>() > 4096 {
+    4 * mem::size_of::<AudioPacket>()
+} else {
+    4096
+};
+
+/// Room for one audio packet per output jack, for holding packets back while pacing.
+const STAGE_SIZE: usize = mem::size_of::<AudioPacket>();
+
+fn instant(time: MonotonicTime) -> Instant {
+    Instant::from_millis(time.as_millis())
+}
+
+fn smoltcp_error(socket: SocketId) -> impl FnOnce(smoltcp::Error) -> Error {
+    move |e| Error::Network(socket, NetworkError::Smoltcp(e))
+}
 
 // Until const generics are stabilized, with
 // #![feature(const_generics)]
@@ -33,14 +56,19 @@ pub struct SmoltcpStorage<'a, const I: usize, const O: usize, const N: usize> {
     server_rx_payload_buffer: [u8; 2048],
     server_tx_metadata_buffer: [UdpPacketMetadata; 32],
     server_tx_payload_buffer: [u8; 4096],
+    control_rx_metadata_buffer: [UdpPacketMetadata; 8],
+    control_rx_payload_buffer: [u8; 4096],
+    control_tx_metadata_buffer: [UdpPacketMetadata; 8],
+    control_tx_payload_buffer: [u8; 4096],
     input_jack_rx_metadata_buffers: [[UdpPacketMetadata; 16]; I],
-    input_jack_rx_payload_buffers: [[u8; 4096]; I],
+    input_jack_rx_payload_buffers: [[u8; JACK_PAYLOAD_SIZE]; I],
     input_jack_tx_metadata_buffers: [[UdpPacketMetadata; 0]; I],
     input_jack_tx_payload_buffers: [[u8; 0]; I],
     output_jack_rx_metadata_buffers: [[UdpPacketMetadata; 0]; O],
     output_jack_rx_payload_buffers: [[u8; 0]; O],
     output_jack_tx_metadata_buffers: [[UdpPacketMetadata; 16]; O],
-    output_jack_tx_payload_buffers: [[u8; 4096]; O],
+    output_jack_tx_payload_buffers: [[u8; JACK_PAYLOAD_SIZE]; O],
+    output_jack_stage_buffers: [[u8; STAGE_SIZE]; O],
 }
 
 impl<const I: usize, const O: usize, const N: usize> Default for SmoltcpStorage<'_, I, O, N> {
@@ -55,14 +83,19 @@ impl<const I: usize, const O: usize, const N: usize> Default for SmoltcpStorage<
             server_rx_payload_buffer: [0; 2048],
             server_tx_metadata_buffer: [UdpPacketMetadata::EMPTY; 32],
             server_tx_payload_buffer: [0; 4096],
+            control_rx_metadata_buffer: [UdpPacketMetadata::EMPTY; 8],
+            control_rx_payload_buffer: [0; 4096],
+            control_tx_metadata_buffer: [UdpPacketMetadata::EMPTY; 8],
+            control_tx_payload_buffer: [0; 4096],
             input_jack_rx_metadata_buffers: [[UdpPacketMetadata::EMPTY; 16]; I],
-            input_jack_rx_payload_buffers: [[0; 4096]; I],
+            input_jack_rx_payload_buffers: [[0; JACK_PAYLOAD_SIZE]; I],
             input_jack_tx_metadata_buffers: [[UdpPacketMetadata::EMPTY; 0]; I],
             input_jack_tx_payload_buffers: [[0; 0]; I],
             output_jack_rx_metadata_buffers: [[UdpPacketMetadata::EMPTY; 0]; O],
             output_jack_rx_payload_buffers: [[0; 0]; O],
             output_jack_tx_metadata_buffers: [[UdpPacketMetadata::EMPTY; 16]; O],
-            output_jack_tx_payload_buffers: [[0; 4096]; O],
+            output_jack_tx_payload_buffers: [[0; JACK_PAYLOAD_SIZE]; O],
+            output_jack_stage_buffers: [[0; STAGE_SIZE]; O],
         }
     }
 }
@@ -78,12 +111,23 @@ pub struct SmoltcpInterface<
     dhcp_handle: SocketHandle,
     dhcp_configured: bool,
     server_handle: SocketHandle,
+    control_handle: SocketHandle,
     broadcast_endpoint: IpEndpoint,
     input_jack_handles: [SocketHandle; I],
     input_jack_endpoints: [Option<IpEndpoint>; I],
     output_jack_handles: [SocketHandle; O],
     output_jack_endpoints: [IpEndpoint; O],
-    empty_packet: [u8; 1500],
+    event: Option<NetworkEvent>,
+    link_up: bool,
+    stage_buffers: &'a mut [[u8; STAGE_SIZE]; O],
+    staged: [usize; O],
+    next_send: usize,
+    /// Why each output jack's staged packet couldn't be sent, until taken by the module
+    send_errors: [Option<Error>; O],
+    pacing: Option<usize>,
+    input_senders: [SourceFilter<IpAddress>; I],
+    recv_budget: usize,
+    receive_stats: ReceiveStats,
 }
 
 impl<'a, DeviceT, const I: usize, const O: usize, const N: usize>
@@ -123,6 +167,18 @@ where
         );
         let server_handle = iface.add_socket(server_socket);
 
+        let control_socket = UdpSocket::new(
+            UdpSocketBuffer::new(
+                &mut storage.control_rx_metadata_buffer[..],
+                &mut storage.control_rx_payload_buffer[..],
+            ),
+            UdpSocketBuffer::new(
+                &mut storage.control_tx_metadata_buffer[..],
+                &mut storage.control_tx_payload_buffer[..],
+            ),
+        );
+        let control_handle = iface.add_socket(control_socket);
+
         let mut input_jack_handles: [SocketHandle; I] = [Default::default(); I];
 
         let mut i = 0;
@@ -163,12 +219,89 @@ where
             dhcp_handle,
             dhcp_configured: false,
             server_handle,
+            control_handle,
             broadcast_endpoint,
             input_jack_handles,
             output_jack_handles,
             input_jack_endpoints: [None; I],
             output_jack_endpoints: [IpEndpoint::UNSPECIFIED; O],
-            empty_packet: [0; 1500],
+            event: None,
+            link_up: true,
+            stage_buffers: &mut storage.output_jack_stage_buffers,
+            staged: [0; O],
+            next_send: O,
+            send_errors: array::from_fn(|_| None),
+            pacing: None,
+            input_senders: [SourceFilter::new(); I],
+            recv_budget: RECV_BUDGET,
+            receive_stats: Default::default(),
+        }
+    }
+
+    /// Hand at most `jacks_per_poll` jack packets to the device each time the interface is
+    /// polled, instead of all of them back-to-back, so that a module with several outputs doesn't
+    /// overrun the transmit ring. The rest are held until later polls, so call
+    /// `Module::poll_network` a few times between blocks. `None` (the default) turns this off.
+    pub fn set_pacing(&mut self, jacks_per_poll: Option<usize>) {
+        self.pacing = jacks_per_poll.map(|n| n.max(1));
+    }
+
+    /// Set how many packets are read from each input jack per poll while looking for one from
+    /// its sender. Defaults to 4.
+    pub fn set_recv_budget(&mut self, packets: usize) {
+        self.recv_budget = packets.max(1);
+    }
+
+    /// Hold the next block of jack packets back in the stage buffers. Jacks that can't be sent
+    /// (with no endpoint or DHCP lease yet) are `None`, as they are without pacing.
+    fn stage_packets(&mut self, sizes: [usize; O]) -> Result<[Option<&mut [u8]>; O], Error> {
+        if let Some(i) = sizes.iter().position(|&size| size > STAGE_SIZE) {
+            return Err(Error::Network(SocketId::Output(i), NetworkError::Exhausted));
+        }
+        // Anything still waiting from the last block goes out now rather than being overwritten
+        self.send_staged(O);
+        let sendable: [bool; O] = array::from_fn(|i| {
+            sizes[i] == 0 || (self.dhcp_configured && self.output_jack_endpoints[i].is_specified())
+        });
+        for i in 0..O {
+            self.staged[i] = if sendable[i] { sizes[i] } else { 0 };
+        }
+        self.next_send = 0;
+        let mut bufs = self.stage_buffers.iter_mut();
+        let mut sendable = sendable.into_iter();
+        Ok(sizes.map(|size| {
+            let buf = &mut bufs.next().unwrap()[..size];
+            sendable.next().unwrap().then_some(buf)
+        }))
+    }
+
+    /// Move up to `count` of the staged jack packets into their sockets. A jack whose socket has
+    /// no room is noted in `send_errors` and the rest carry on.
+    fn send_staged(&mut self, mut count: usize) {
+        while self.next_send < O && count > 0 {
+            let i = self.next_send;
+            self.next_send += 1;
+            let size = self.staged[i];
+            if size == 0 {
+                continue;
+            }
+            let socket = self
+                .iface
+                .get_socket::<UdpSocket>(self.output_jack_handles[i]);
+            let sent = if socket.can_send() {
+                socket
+                    .send_slice(
+                        &self.stage_buffers[i][..size],
+                        self.output_jack_endpoints[i],
+                    )
+                    .map_err(smoltcp_error(SocketId::Output(i)))
+            } else {
+                Err(Error::Network(SocketId::Output(i), NetworkError::Exhausted))
+            };
+            if let Err(e) = sent {
+                self.send_errors[i] = Some(e);
+            }
+            count -= 1;
         }
     }
 
@@ -179,7 +312,7 @@ where
         });
     }
 
-    fn dhcp_poll(&mut self, time: i64) {
+    fn dhcp_poll(&mut self, time: MonotonicTime) {
         let event = self
             .iface
             .get_socket::<Dhcpv4Socket>(self.dhcp_handle)
@@ -218,7 +351,7 @@ where
 
                 match self
                     .iface
-                    .join_multicast_group(self.broadcast_endpoint.addr, Instant::from_millis(time))
+                    .join_multicast_group(self.broadcast_endpoint.addr, instant(time))
                 {
                     Ok(sent) => info!(
                         "Address added to patch management and sent: {:?} {}",
@@ -227,10 +360,7 @@ where
                     Err(e) => info!("Multicast join failed: {}", e),
                 }
                 for ep in self.output_jack_endpoints {
-                    match self
-                        .iface
-                        .join_multicast_group(ep.addr, Instant::from_millis(time))
-                    {
+                    match self.iface.join_multicast_group(ep.addr, instant(time)) {
                         Ok(sent) => info!(
                             "Address added to multicast and sent: {:?} {}",
                             ep.addr, sent
@@ -239,15 +369,21 @@ where
                     }
                 }
                 self.dhcp_configured = true;
+                // The output jack addresses follow the interface address
+                self.event = Some(NetworkEvent::AddressChanged(addr.0));
             }
             Some(Dhcpv4Event::Deconfigured) => {
                 info!("DHCP lost config!");
-                self.set_ipv4_addr(Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0));
-                self.iface.routes_mut().remove_default_ipv4_route();
-                self.dhcp_configured = false;
+                self.deconfigure();
             }
         }
     }
+
+    fn deconfigure(&mut self) {
+        self.set_ipv4_addr(Ipv4Cidr::new(Ipv4Address::UNSPECIFIED, 0));
+        self.iface.routes_mut().remove_default_ipv4_route();
+        self.dhcp_configured = false;
+    }
 }
 
 impl<'a, DeviceT, const I: usize, const O: usize, const N: usize> Network<I, O>
@@ -255,33 +391,76 @@ impl<'a, DeviceT, const I: usize, const O: usize, const N: usize> Network<I, O>
 where
     DeviceT: for<'d> Device<'d>,
 {
-    fn poll(&mut self, time: i64) -> Result<(), Error> {
-        match self.iface.poll(Instant::from_millis(time)) {
+    fn poll(&mut self, time: MonotonicTime) -> Result<(), Error> {
+        if !self.link_up {
+            return Ok(());
+        }
+        if let Some(count) = self.pacing {
+            self.send_staged(count);
+        }
+        match self.iface.poll(instant(time)) {
             Ok(_) => {
                 self.dhcp_poll(time);
                 if self.dhcp_configured {
                     let socket = self.iface.get_socket::<UdpSocket>(self.server_handle);
                     if !socket.is_open() {
                         info!("Opening UDP listener socket");
-                        if let Err(_) = socket.bind(self.broadcast_endpoint.port) {
-                            return Err(Error::Network);
-                        }
+                        socket
+                            .bind(self.broadcast_endpoint.port)
+                            .map_err(smoltcp_error(SocketId::Directive))?;
+                    }
+                    let socket = self.iface.get_socket::<UdpSocket>(self.control_handle);
+                    if !socket.is_open() {
+                        socket
+                            .bind(CONTROL_PORT)
+                            .map_err(smoltcp_error(SocketId::Control))?;
                     }
                     let mut port = 30000;
-                    for h in self.output_jack_handles {
+                    for (i, h) in self.output_jack_handles.into_iter().enumerate() {
                         let socket = self.iface.get_socket::<UdpSocket>(h);
                         if !socket.is_open() {
-                            if let Err(_) = socket.bind(port) {
-                                return Err(Error::Network);
-                            }
+                            socket
+                                .bind(port)
+                                .map_err(smoltcp_error(SocketId::Output(i)))?;
                             port += 1;
                         }
                     }
                 }
                 Ok(())
             }
-            Err(_) => Err(Error::Network),
+            Err(e) => Err(smoltcp_error(SocketId::Interface)(e)),
+        }
+    }
+
+    fn take_event(&mut self) -> Option<NetworkEvent> {
+        self.event.take()
+    }
+
+    fn take_send_errors(&mut self) -> [Option<Error>; O] {
+        mem::replace(&mut self.send_errors, array::from_fn(|_| None))
+    }
+
+    fn set_link_up(&mut self, up: bool) {
+        if up == self.link_up {
+            return;
         }
+        self.link_up = up;
+        if up {
+            info!("Link up");
+        } else {
+            // Whatever DHCP handed out may not be ours by the time the cable is back
+            info!("Link down");
+            self.deconfigure();
+        }
+        // Start looking for an address again as soon as the link is back
+        self.iface
+            .get_socket::<Dhcpv4Socket>(self.dhcp_handle)
+            .reset();
+        self.event = Some(NetworkEvent::LinkChanged(up));
+    }
+
+    fn receive_stats(&self) -> ReceiveStats {
+        self.receive_stats
     }
 
     fn can_send(&mut self) -> bool {
@@ -295,7 +474,7 @@ where
         if socket.can_recv() && self.dhcp_configured {
             match socket.recv_slice(buf) {
                 Ok((size, _)) => Ok(size),
-                Err(_) => Err(Error::Network),
+                Err(e) => Err(smoltcp_error(SocketId::Directive)(e)),
             }
         } else {
             Err(Error::NoData)
@@ -306,47 +485,114 @@ where
         let socket = self.iface.get_socket::<UdpSocket>(self.server_handle);
         if socket.can_send() && self.dhcp_configured {
             match socket.send_slice(buf, self.broadcast_endpoint) {
-                Err(_) => Err(Error::Network),
+                Err(e) => Err(smoltcp_error(SocketId::Directive)(e)),
                 Ok(_) => Ok(()),
             }
+        } else if !self.dhcp_configured {
+            Err(Error::Network(
+                SocketId::Directive,
+                NetworkError::Unconfigured,
+            ))
         } else {
-            Err(Error::Network)
+            Err(Error::Network(SocketId::Directive, NetworkError::Exhausted))
+        }
+    }
+
+    fn control_addr(&mut self) -> Option<ControlAddr> {
+        if !self.dhcp_configured {
+            return None;
+        }
+        // The board only ever runs the one module, so the port can be fixed
+        self.iface
+            .ip_addrs()
+            .iter()
+            .find_map(|cidr| match cidr.address() {
+                IpAddress::Ipv4(addr) if !addr.is_unspecified() => Some(ControlAddr {
+                    addr: addr.0,
+                    port: CONTROL_PORT,
+                }),
+                _ => None,
+            })
+    }
+
+    fn send_control(&mut self, addr: ControlAddr, buf: &[u8]) -> Result<(), Error> {
+        if !self.dhcp_configured {
+            return Err(Error::Network(
+                SocketId::Control,
+                NetworkError::Unconfigured,
+            ));
+        }
+        let ep = IpEndpoint::new(IpAddress::Ipv4(Ipv4Address(addr.addr)), addr.port);
+        let socket = self.iface.get_socket::<UdpSocket>(self.control_handle);
+        socket
+            .send_slice(buf, ep)
+            .map_err(smoltcp_error(SocketId::Control))
+    }
+
+    fn recv_control(&mut self, buf: &mut [u8]) -> Result<(usize, ControlAddr), Error> {
+        let socket = self.iface.get_socket::<UdpSocket>(self.control_handle);
+        if !socket.can_recv() || !self.dhcp_configured {
+            return Err(Error::NoData);
+        }
+        match socket.recv_slice(buf) {
+            Ok((
+                size,
+                IpEndpoint {
+                    addr: IpAddress::Ipv4(addr),
+                    port,
+                },
+            )) => Ok((size, ControlAddr { addr: addr.0, port })),
+            Ok(_) => Err(Error::NoData),
+            Err(e) => Err(smoltcp_error(SocketId::Control)(e)),
         }
     }
 
-    fn jack_connect(&mut self, jack_id: usize, addr: [u8; 4], time: i64) -> Result<(), Error> {
+    fn jack_connect(
+        &mut self,
+        jack_id: usize,
+        addr: [u8; 4],
+        time: MonotonicTime,
+    ) -> Result<(), Error> {
         let address = Ipv4Address::from_bytes(&addr);
-        let t = Instant::from_millis(time);
+        let t = instant(time);
         let ep = IpEndpoint::new(IpAddress::Ipv4(address), JACK_PORT);
         self.jack_disconnect(jack_id, time)?;
         info!(
             "Input jack {}: Joining group {:?} and opening socket",
             jack_id, ep
         );
-        if let Err(_) = self.iface.join_multicast_group(ep.addr, t) {
-            return Err(Error::Network);
-        }
+        self.iface
+            .join_multicast_group(ep.addr, t)
+            .map_err(smoltcp_error(SocketId::Input(jack_id)))?;
         self.input_jack_endpoints[jack_id] = Some(ep);
+        self.input_senders[jack_id].reset();
         let jack_socket = self
             .iface
             .get_socket::<UdpSocket>(self.input_jack_handles[jack_id]);
-        jack_socket.bind(ep).or(Err(Error::Network))
+        jack_socket
+            .bind(ep)
+            .map_err(smoltcp_error(SocketId::Input(jack_id)))
     }
 
-    fn enqueue_packets(&mut self, size: usize) -> Result<[&mut [u8]; O], Error> {
+    fn enqueue_packets(&mut self, sizes: [usize; O]) -> Result<[Option<&mut [u8]>; O], Error> {
+        if self.pacing.is_some() {
+            return self.stage_packets(sizes);
+        }
         let mut res: [Option<&mut [u8]>; O] = [(); O].map(|_| None);
         for (h, s) in self.iface.sockets_mut() {
             match s {
                 Socket::Udp(s) => {
                     for i in 0..O {
                         if self.output_jack_handles[i] == h {
-                            if s.can_send()
+                            if sizes[i] == 0 {
+                                res[i] = Some(&mut []);
+                            } else if s.can_send()
                                 && self.dhcp_configured
                                 && self.output_jack_endpoints[i].is_specified()
                             {
-                                match s.send(size, self.output_jack_endpoints[i]) {
+                                match s.send(sizes[i], self.output_jack_endpoints[i]) {
                                     Ok(b) => res[i] = Some(b),
-                                    _ => return Err(Error::Network),
+                                    Err(e) => info!("Output jack {}: {:?}", i, e),
                                 }
                             }
                             break;
@@ -356,27 +602,37 @@ where
                 _ => {}
             };
         }
-        Ok(res.map(|s| s.unwrap()))
+        Ok(res)
     }
 
-    fn dequeue_packets(&mut self, size: usize) -> ([&[u8]; I], u32) {
-        let mut dropped_packets = 0;
-        let mut res = [&self.empty_packet[0..size]; I];
+    fn dequeue_packets(&mut self) -> [&[u8]; I] {
+        let mut res: [&[u8]; I] = [&[]; I];
         for (h, s) in self.iface.sockets_mut() {
             match s {
                 Socket::Udp(s) => {
                     for i in 0..I {
                         if self.input_jack_handles[i] == h {
-                            if s.can_recv() && self.dhcp_configured {
-                                if let Ok((buf, _)) = s.recv() {
-                                    if buf.len() == size {
+                            if !self.dhcp_configured {
+                                break;
+                            }
+                            let mut read = 0;
+                            while read < self.recv_budget {
+                                let source = match s.peek() {
+                                    Ok((_, ep)) => ep.addr,
+                                    Err(_) => break,
+                                };
+                                read += 1;
+                                if self.input_senders[i].accept(source) {
+                                    if let Ok((buf, _)) = s.recv() {
                                         res[i] = buf;
-                                    } else {
-                                        dropped_packets += 1;
                                     }
+                                    break;
                                 }
-                            } else {
-                                dropped_packets += 1;
+                                let _ = s.recv();
+                                self.receive_stats.wrong_source += 1;
+                            }
+                            if read == self.recv_budget && res[i].is_empty() {
+                                self.receive_stats.budget_exhausted += 1;
                             }
                             break;
                         }
@@ -385,7 +641,7 @@ where
                 _ => {}
             };
         }
-        (res, dropped_packets)
+        res
     }
 
     fn jack_addr(&mut self, jack_id: usize) -> Result<[u8; 4], Error> {
@@ -393,15 +649,15 @@ where
             .addr
             .as_bytes()
             .try_into()
-            .or(Err(Error::InvalidJackId))
+            .or(Err(Error::InvalidJackId(jack_id)))
     }
 
-    fn jack_disconnect(&mut self, jack_id: usize, time: i64) -> Result<(), Error> {
-        let t = Instant::from_millis(time);
+    fn jack_disconnect(&mut self, jack_id: usize, time: MonotonicTime) -> Result<(), Error> {
+        let t = instant(time);
         if let Some(old_ep) = self.input_jack_endpoints[jack_id] {
-            if let Err(_) = self.iface.leave_multicast_group(old_ep.addr, t) {
-                return Err(Error::Network);
-            }
+            self.iface
+                .leave_multicast_group(old_ep.addr, t)
+                .map_err(smoltcp_error(SocketId::Input(jack_id)))?;
             info!("Input jack {}: Leaving group", jack_id);
         }
         let jack_socket = self
@@ -410,6 +666,7 @@ where
         if jack_socket.is_open() {
             jack_socket.close();
         }
+        self.input_senders[jack_id].reset();
         Ok(())
     }
 }
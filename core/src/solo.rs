@@ -0,0 +1,119 @@
+use crate::{PatchConnection, Uuid};
+
+/// Solo-in-place, as worked out by the leader keeping the patch journal.
+///
+/// Muting is per module, so soloing a module keeps everything it is heard through and everything
+/// it is made from: the modules upstream of it, and the ones downstream on the way to the audio
+/// interface. Every other module sending audio anywhere is muted until the solo is let go. This
+/// also mutes anything modulating the modules downstream, which can't be told apart from audio.
+#[derive(Default)]
+pub(crate) struct Solo {
+    soloed: Option<Uuid>,
+    /// Modules muted for the solo, to be unmuted again afterwards
+    muted: Vec<Uuid>,
+}
+
+impl Solo {
+    /// Solo `uuid`, replacing any other solo, or let it go. Returns the modules to unmute and
+    /// those to mute, in that order.
+    pub(crate) fn set(
+        &mut self,
+        connections: &[PatchConnection],
+        uuid: Uuid,
+        solo: bool,
+    ) -> (Vec<Uuid>, Vec<Uuid>) {
+        let muted = if solo {
+            let muted = mutes(connections, &uuid);
+            self.soloed = Some(uuid);
+            muted
+        } else if self.soloed.as_ref() == Some(&uuid) {
+            self.soloed = None;
+            vec![]
+        } else {
+            return (vec![], vec![]);
+        };
+        let unmute = self
+            .muted
+            .iter()
+            .filter(|m| !muted.contains(m))
+            .cloned()
+            .collect();
+        let mute = muted
+            .iter()
+            .filter(|m| !self.muted.contains(m))
+            .cloned()
+            .collect();
+        self.muted = muted;
+        (unmute, mute)
+    }
+}
+
+/// Every module sending audio that is neither upstream nor downstream of `soloed`.
+fn mutes(connections: &[PatchConnection], soloed: &Uuid) -> Vec<Uuid> {
+    let upstream = walk(connections, soloed, |c| (&c.input_uuid, &c.output_uuid));
+    let downstream = walk(connections, soloed, |c| (&c.output_uuid, &c.input_uuid));
+    let mut muted: Vec<Uuid> = vec![];
+    for c in connections {
+        let uuid = &c.output_uuid;
+        if !upstream.contains(uuid) && !downstream.contains(uuid) && !muted.contains(uuid) {
+            muted.push(uuid.clone());
+        }
+    }
+    muted
+}
+
+/// Modules reachable from `start` by following `step` from one end of a connection to the other.
+fn walk<F>(connections: &[PatchConnection], start: &Uuid, step: F) -> Vec<Uuid>
+where
+    F: Fn(&PatchConnection) -> (&Uuid, &Uuid),
+{
+    let mut seen = vec![start.clone()];
+    let mut next = 0;
+    while next < seen.len() {
+        for c in connections {
+            let (from, to) = step(c);
+            if from == &seen[next] && !seen.contains(to) {
+                seen.push(to.clone());
+            }
+        }
+        next += 1;
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn patch(output: &str, input: &str) -> PatchConnection {
+        PatchConnection {
+            input_uuid: input.into(),
+            input_jack_id: 0,
+            output_uuid: output.into(),
+            output_jack_id: 0,
+        }
+    }
+
+    #[test]
+    fn solo_mutes_other_sources_of_the_mix() {
+        // Two voices into a mixer and out, with an lfo on the first voice
+        let connections = [
+            patch("Lfo", "Voice 1"),
+            patch("Voice 1", "Mixer"),
+            patch("Voice 2", "Mixer"),
+            patch("Mixer", "Audio Interface"),
+        ];
+        let mut solo = Solo::default();
+        let (unmute, mute) = solo.set(&connections, "Voice 1".into(), true);
+        assert!(unmute.is_empty());
+        assert_eq!(mute, [Uuid::from("Voice 2")]);
+
+        let (unmute, mute) = solo.set(&connections, "Voice 2".into(), true);
+        assert_eq!(unmute, [Uuid::from("Voice 2")]);
+        assert_eq!(mute, [Uuid::from("Lfo"), Uuid::from("Voice 1")]);
+
+        let (unmute, mute) = solo.set(&connections, "Voice 2".into(), false);
+        assert_eq!(unmute, [Uuid::from("Lfo"), Uuid::from("Voice 1")]);
+        assert!(mute.is_empty());
+    }
+}
@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    dsp::oscillators::{UserWavetable, WAVETABLE_SIZE},
+    Error,
+};
+
+/// Preset slots kept by each module
+pub const PRESET_SLOTS: usize = 8;
+/// Longest preset name, in bytes
+pub const PRESET_NAME_LEN: usize = 16;
+/// Most parameters a preset holds, beyond which the rest of a module's knobs aren't kept
+pub const MAX_PRESET_PARAMS: usize = 32;
+
+/// A snapshot of a module's own knobs, stored with `Module::send_store_preset` and recalled with
+/// `Module::send_recall_preset`. Values are in the knobs' units, as in `ParamBlock`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ParamPreset {
+    pub name: heapless::String<PRESET_NAME_LEN>,
+    pub values: heapless::Vec<f32, MAX_PRESET_PARAMS>,
+}
+
+/// Storage for user data uploaded over the network, like wavetables, and for presets of a
+/// module's knobs. Hardware modules can back this with flash so that the data survives a restart.
+pub trait Storage {
+    /// Write `samples` into the user wavetable `slot`, starting at sample `offset`
+    fn write_wavetable(&mut self, slot: usize, offset: usize, samples: &[i16])
+        -> Result<(), Error>;
+    fn wavetable(&self, slot: usize) -> Option<&UserWavetable>;
+    /// Keep `preset` in `slot`, replacing whatever was there
+    fn write_preset(&mut self, slot: usize, preset: ParamPreset) -> Result<(), Error>;
+    /// The preset in `slot`, if one has been stored
+    fn preset(&self, slot: usize) -> Option<&ParamPreset>;
+}
+
+/// `Storage` that only lives in memory, with room for `N` wavetables and `PRESET_SLOTS` presets.
+pub struct RamStorage<const N: usize> {
+    wavetables: [UserWavetable; N],
+    presets: [Option<ParamPreset>; PRESET_SLOTS],
+}
+
+impl<const N: usize> Default for RamStorage<N> {
+    fn default() -> Self {
+        RamStorage {
+            wavetables: [(); N].map(|_| Default::default()),
+            presets: Default::default(),
+        }
+    }
+}
+
+impl<const N: usize> Storage for RamStorage<N> {
+    fn write_wavetable(
+        &mut self,
+        slot: usize,
+        offset: usize,
+        samples: &[i16],
+    ) -> Result<(), Error> {
+        let end = offset + samples.len();
+        match self.wavetables.get_mut(slot) {
+            Some(table) if end <= WAVETABLE_SIZE => {
+                table.samples[offset..end].copy_from_slice(samples);
+                Ok(())
+            }
+            _ => Err(Error::StorageFull),
+        }
+    }
+
+    fn wavetable(&self, slot: usize) -> Option<&UserWavetable> {
+        self.wavetables.get(slot)
+    }
+
+    fn write_preset(&mut self, slot: usize, preset: ParamPreset) -> Result<(), Error> {
+        match self.presets.get_mut(slot) {
+            Some(stored) => {
+                *stored = Some(preset);
+                Ok(())
+            }
+            None => Err(Error::StorageFull),
+        }
+    }
+
+    fn preset(&self, slot: usize) -> Option<&ParamPreset> {
+        self.presets.get(slot)?.as_ref()
+    }
+}
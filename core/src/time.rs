@@ -0,0 +1,255 @@
+//! Time as seen by a module, counted in milliseconds from whenever its host started.
+//!
+//! `MonotonicTime` is a point in time and `Duration` a span between two, so that a timeout can't
+//! be passed where a time is expected or two times added together. A module reads the real time
+//! off its `Clock`, and hosts that keep their own count of milliseconds go through
+//! `MonotonicTime::from_millis`.
+
+use core::ops::{Add, AddAssign, Sub, SubAssign};
+
+/// A point in time, in milliseconds since the host started.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MonotonicTime(i64);
+
+/// A span of time between two `MonotonicTime`s, in milliseconds.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Duration(i64);
+
+impl MonotonicTime {
+    pub const fn from_millis(millis: i64) -> Self {
+        MonotonicTime(millis)
+    }
+
+    pub const fn as_millis(self) -> i64 {
+        self.0
+    }
+
+    /// Time elapsed on the host since `start`.
+    #[cfg(feature = "std")]
+    pub fn since(start: std::time::Instant) -> Self {
+        MonotonicTime(start.elapsed().as_millis() as i64)
+    }
+
+    /// Whether this time falls on a multiple of `period`, for work done every so often.
+    pub fn is_multiple_of(self, period: Duration) -> bool {
+        period.0 != 0 && self.0 % period.0 == 0
+    }
+}
+
+impl Duration {
+    pub const fn from_millis(millis: i64) -> Self {
+        Duration(millis)
+    }
+
+    pub const fn from_secs(secs: i64) -> Self {
+        Duration(secs * 1000)
+    }
+
+    pub const fn as_millis(self) -> i64 {
+        self.0
+    }
+}
+
+impl From<MonotonicTime> for i64 {
+    fn from(time: MonotonicTime) -> Self {
+        time.0
+    }
+}
+
+impl From<core::time::Duration> for Duration {
+    fn from(duration: core::time::Duration) -> Self {
+        Duration(duration.as_millis() as i64)
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> From<fugit::Duration<u32, NOM, DENOM>> for Duration {
+    fn from(duration: fugit::Duration<u32, NOM, DENOM>) -> Self {
+        Duration(duration.to_millis() as i64)
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> From<fugit::Duration<u64, NOM, DENOM>> for Duration {
+    fn from(duration: fugit::Duration<u64, NOM, DENOM>) -> Self {
+        Duration(duration.to_millis() as i64)
+    }
+}
+
+#[cfg(feature = "fugit")]
+impl<const NOM: u32, const DENOM: u32> From<fugit::Instant<u64, NOM, DENOM>> for MonotonicTime {
+    fn from(instant: fugit::Instant<u64, NOM, DENOM>) -> Self {
+        MonotonicTime(instant.duration_since_epoch().to_millis() as i64)
+    }
+}
+
+impl Add<Duration> for MonotonicTime {
+    type Output = MonotonicTime;
+
+    fn add(self, rhs: Duration) -> MonotonicTime {
+        MonotonicTime(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign<Duration> for MonotonicTime {
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub<Duration> for MonotonicTime {
+    type Output = MonotonicTime;
+
+    fn sub(self, rhs: Duration) -> MonotonicTime {
+        MonotonicTime(self.0 - rhs.0)
+    }
+}
+
+impl SubAssign<Duration> for MonotonicTime {
+    fn sub_assign(&mut self, rhs: Duration) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl Sub for MonotonicTime {
+    type Output = Duration;
+
+    fn sub(self, rhs: MonotonicTime) -> Duration {
+        Duration(self.0 - rhs.0)
+    }
+}
+
+impl Add for Duration {
+    type Output = Duration;
+
+    fn add(self, rhs: Duration) -> Duration {
+        Duration(self.0 + rhs.0)
+    }
+}
+
+impl AddAssign for Duration {
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs.0;
+    }
+}
+
+impl Sub for Duration {
+    type Output = Duration;
+
+    fn sub(self, rhs: Duration) -> Duration {
+        Duration(self.0 - rhs.0)
+    }
+}
+
+/// Where a host gets the current time from.
+pub trait Clock {
+    fn now(&mut self) -> MonotonicTime;
+}
+
+/// A clock that only moves when it is set, for hosts that count the time themselves.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ManualClock(MonotonicTime);
+
+impl ManualClock {
+    pub fn new(now: MonotonicTime) -> Self {
+        ManualClock(now)
+    }
+
+    pub fn set(&mut self, now: MonotonicTime) {
+        self.0 = now;
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&mut self) -> MonotonicTime {
+        self.0
+    }
+}
+
+/// The host's monotonic clock, counted from when this was created.
+#[cfg(feature = "std")]
+pub struct StdClock(std::time::Instant);
+
+#[cfg(feature = "std")]
+impl StdClock {
+    pub fn new() -> Self {
+        StdClock(std::time::Instant::now())
+    }
+}
+
+#[cfg(feature = "std")]
+impl Default for StdClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now(&mut self) -> MonotonicTime {
+        MonotonicTime::since(self.0)
+    }
+}
+
+/// Hands out every millisecond from when it was created up to a clock's current time, so that
+/// a host which falls behind catches up with one poll per millisecond rather than skipping any.
+///
+/// ```ignore
+/// let mut ticker = Ticker::new(StdClock::new());
+/// loop {
+///     while let Some(time) = ticker.next_tick() {
+///         module.poll(time, |block| { ... })?;
+///     }
+/// }
+/// ```
+pub struct Ticker<C> {
+    clock: C,
+    next: MonotonicTime,
+}
+
+impl<C: Clock> Ticker<C> {
+    pub fn new(mut clock: C) -> Self {
+        let next = clock.now();
+        Ticker { clock, next }
+    }
+
+    /// The next millisecond due, if the clock has reached it.
+    pub fn next_tick(&mut self) -> Option<MonotonicTime> {
+        if self.next > self.clock.now() {
+            return None;
+        }
+        let time = self.next;
+        self.next += Duration::from_millis(1);
+        Some(time)
+    }
+
+    /// How long until the next millisecond is due, for hosts that sleep in between.
+    pub fn until_next(&mut self) -> Duration {
+        core::cmp::max(self.next - self.clock.now(), Duration::default())
+    }
+
+    /// How far the ticks handed out have fallen behind the clock.
+    pub fn lag(&mut self) -> Duration {
+        self.clock.now() - self.next
+    }
+
+    pub fn clock_mut(&mut self) -> &mut C {
+        &mut self.clock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticker_catches_up_one_millisecond_at_a_time() {
+        let mut ticker = Ticker::new(ManualClock::new(MonotonicTime::from_millis(10)));
+        assert_eq!(ticker.next_tick(), Some(MonotonicTime::from_millis(10)));
+        assert_eq!(ticker.next_tick(), None);
+        ticker.clock_mut().set(MonotonicTime::from_millis(13));
+        assert_eq!(ticker.lag(), Duration::from_millis(2));
+        let ticks: Vec<_> = core::iter::from_fn(|| ticker.next_tick()).collect();
+        assert_eq!(ticks, [11, 12, 13].map(MonotonicTime::from_millis));
+    }
+}
@@ -0,0 +1,98 @@
+//! The patch as a graph of modules, for documenting a patch or looking over a tangled one in
+//! another tool.
+//!
+//! Jacks are only known on the network by their index, so edges are labelled with jack ids rather
+//! than the names shown on each module.
+
+use std::fmt::Write;
+
+use crate::{PatchConnection, Uuid};
+
+/// A connection from an output jack of one module to an input jack of another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Edge {
+    pub output: Uuid,
+    pub output_jack: u32,
+    pub input: Uuid,
+    pub input_jack: u32,
+}
+
+/// Every connection in the patch, as seen by the leader (see `journal::PatchJournal::topology`).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Topology {
+    pub edges: Vec<Edge>,
+}
+
+impl Topology {
+    pub(crate) fn new(connections: &[PatchConnection]) -> Self {
+        let edges = connections
+            .iter()
+            .map(|c| Edge {
+                output: c.output_uuid.clone(),
+                output_jack: c.output_jack_id,
+                input: c.input_uuid.clone(),
+                input_jack: c.input_jack_id,
+            })
+            .collect();
+        Topology { edges }
+    }
+
+    /// Every module with a connection, in the order first seen.
+    pub fn modules(&self) -> Vec<&Uuid> {
+        let mut modules: Vec<&Uuid> = vec![];
+        for edge in &self.edges {
+            for uuid in [&edge.output, &edge.input] {
+                if !modules.contains(&uuid) {
+                    modules.push(uuid);
+                }
+            }
+        }
+        modules
+    }
+
+    /// The patch in Graphviz DOT, to be drawn with `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph patch {\n    rankdir=LR;\n");
+        for uuid in self.modules() {
+            let _ = writeln!(out, "    \"{}\";", escape(uuid));
+        }
+        for edge in &self.edges {
+            let _ = writeln!(
+                out,
+                "    \"{}\" -> \"{}\" [label=\"{} → {}\"];",
+                escape(&edge.output),
+                escape(&edge.input),
+                edge.output_jack,
+                edge.input_jack
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    /// The patch as a Mermaid flowchart, which renders inline in Markdown on most forges.
+    pub fn to_mermaid(&self) -> String {
+        let modules = self.modules();
+        let node = |uuid: &Uuid| modules.iter().position(|m| *m == uuid).unwrap_or(0);
+        let mut out = String::from("flowchart LR\n");
+        for (i, uuid) in modules.iter().enumerate() {
+            let _ = writeln!(out, "    m{}[\"{}\"]", i, escape(uuid));
+        }
+        for edge in &self.edges {
+            let _ = writeln!(
+                out,
+                "    m{} -- \"{} → {}\" --> m{}",
+                node(&edge.output),
+                edge.output_jack,
+                edge.input_jack,
+                node(&edge.input)
+            );
+        }
+        out
+    }
+}
+
+/// Module names are free text, so keep quotes from ending a label early.
+fn escape(uuid: &Uuid) -> String {
+    uuid.replace('"', "'")
+}
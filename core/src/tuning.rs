@@ -0,0 +1,228 @@
+//! The tuning shared by every module on the network, so the whole instrument can be retuned at
+//! once.
+//!
+//! Any module can send a new tuning with `Module::send_tuning`, and the leading coordinator sends
+//! its own out regularly so that modules joining later pick it up. Changes are numbered, so a
+//! repeat still on its way never undoes a newer one. Each module keeps the tuning it last heard
+//! and hands it to processors in `BlockContext::tuning`, which they pass on to
+//! `voct_to_frequency` and the oscillators built on it.
+//!
+//! For scales other than twelve equal steps, a `ScaleTable` (usually read from Scala `.scl` and
+//! `.kbm` files) can be sent with `Module::send_scale_table`. A module works out the frequency of
+//! every MIDI note when a table arrives and hands them to processors in
+//! `BlockContext::note_frequencies`. While there are some, each MIDI note plays the frequency the
+//! table gives it, with pitch CV between notes gliding evenly from one to the next. Quantizers snapping to whole semitones of pitch CV land on the table's notes.
+
+use libm::{floorf, log2f, powf, roundf};
+use serde::{Deserialize, Serialize};
+
+use crate::{dsp::sample::SEMITONE, Error, ParseError};
+
+/// Most degrees a `ScaleTable` holds, and most keys in its keyboard mapping
+pub const MAX_SCALE_DEGREES: usize = 64;
+
+/// Every note of the octave
+pub const CHROMATIC: u16 = 0xfff;
+/// The notes of the major scale, from its root
+pub const MAJOR: u16 = 0b1010_1011_0101;
+/// The notes of the natural minor scale, from its root
+pub const MINOR: u16 = 0b0101_1010_1101;
+
+/// A reference pitch, and a scale for quantizers to snap to. Laid out as in C, so that it can be
+/// handed to plugins (see `plugin::PluginProcessFn`).
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Tuning {
+    /// Frequency of A4, MIDI note 69, in Hz
+    pub a4: f32,
+    /// Pitch class the scale starts from, 0 for C through 11 for B
+    pub root: u8,
+    /// The notes of the scale, as a bit per semitone above the root
+    pub scale: u16,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            a4: 440.0,
+            root: 0,
+            scale: CHROMATIC,
+        }
+    }
+}
+
+impl Tuning {
+    /// Whether the note `semitones` from MIDI note 64 is in the scale.
+    pub fn in_scale(&self, semitones: i32) -> bool {
+        let class = (semitones + 64 - self.root as i32).rem_euclid(12);
+        self.scale & (1 << class) != 0
+    }
+
+    /// Snap pitch CV to the nearest note of the scale, or leave it alone if the scale is empty.
+    pub fn quantize(&self, v_oct: i16) -> i16 {
+        let note = roundf(v_oct as f32 / SEMITONE as f32) as i32;
+        // Search outwards, taking the lower note of a tie
+        for offset in 0..=6 {
+            for semitones in [note - offset, note + offset] {
+                if self.in_scale(semitones) {
+                    let v_oct = semitones * SEMITONE as i32;
+                    return v_oct.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+                }
+            }
+        }
+        v_oct
+    }
+}
+
+/// A scale of any number of notes to the octave, or to some other period, and the keys that play
+/// it, as in a Scala scale and keyboard mapping.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ScaleTable {
+    /// Cents above the base note of every degree after the first, in order. The last is the
+    /// period the scale repeats at, usually 1200.
+    pub degrees: heapless::Vec<f32, MAX_SCALE_DEGREES>,
+    /// The degree played by each key from the base note on, repeating every `mapping.len()` keys.
+    /// `None` leaves a key unmapped, and it plays the key below. Empty for a key per degree.
+    pub mapping: heapless::Vec<Option<u8>, MAX_SCALE_DEGREES>,
+    /// The degree that the mapping moves up by each time it repeats
+    pub octave_degree: u8,
+    /// MIDI note that plays the first degree
+    pub base_note: u8,
+    /// MIDI note tuned to `reference_freq`
+    pub reference_note: u8,
+    pub reference_freq: f32,
+}
+
+impl ScaleTable {
+    /// Read a Scala `.scl` scale, played a degree per key from middle C with A4 at 440 Hz.
+    pub fn from_scl(scl: &str) -> Result<Self, Error> {
+        let mut lines = scala_lines(scl);
+        // The description comes first, and may be blank
+        lines.next().ok_or(Error::Parse(ParseError::Scala))?;
+        let count: usize = parse_field(lines.next())?;
+        let mut degrees = heapless::Vec::new();
+        for _ in 0..count {
+            let line = lines.next().ok_or(Error::Parse(ParseError::Scala))?;
+            let cents = parse_pitch(line).ok_or(Error::Parse(ParseError::Scala))?;
+            degrees.push(cents).or(Err(Error::StorageFull))?;
+        }
+        if degrees.is_empty() {
+            return Err(Error::Parse(ParseError::Scala));
+        }
+        Ok(ScaleTable {
+            degrees,
+            mapping: heapless::Vec::new(),
+            octave_degree: 0,
+            base_note: 60,
+            reference_note: 69,
+            reference_freq: 440.0,
+        })
+    }
+
+    /// Play the scale from the keys given by a Scala `.kbm` keyboard mapping.
+    pub fn with_kbm(mut self, kbm: &str) -> Result<Self, Error> {
+        let mut lines = scala_lines(kbm);
+        let size: usize = parse_field(lines.next())?;
+        // The first and last keys retuned are left to the synthesizers
+        let _first: u8 = parse_field(lines.next())?;
+        let _last: u8 = parse_field(lines.next())?;
+        self.base_note = parse_field(lines.next())?;
+        self.reference_note = parse_field(lines.next())?;
+        self.reference_freq = parse_field(lines.next())?;
+        self.octave_degree = parse_field(lines.next())?;
+        self.mapping.clear();
+        for _ in 0..size {
+            let degree = match lines.next() {
+                Some("x") => None,
+                field => Some(parse_field(field)?),
+            };
+            self.mapping.push(degree).or(Err(Error::StorageFull))?;
+        }
+        Ok(self)
+    }
+
+    /// Cents of `degree` above the base note, carrying on into the periods above and below.
+    fn degree_cents(&self, degree: i32) -> f32 {
+        let len = self.degrees.len() as i32;
+        let period = self.degrees[self.degrees.len() - 1];
+        let step = degree.rem_euclid(len);
+        let above = if step == 0 {
+            0.0
+        } else {
+            self.degrees[step as usize - 1]
+        };
+        degree.div_euclid(len) as f32 * period + above
+    }
+
+    /// Cents of MIDI note `note` above the base note.
+    fn note_cents(&self, note: i32) -> f32 {
+        let key = note - self.base_note as i32;
+        if self.mapping.is_empty() {
+            return self.degree_cents(key);
+        }
+        let len = self.mapping.len() as i32;
+        let octave = match self.octave_degree {
+            0 => self.degrees.len() as i32,
+            degree => degree as i32,
+        };
+        // Unmapped keys play the nearest mapped key below, or the base note if there is none
+        let index = key.rem_euclid(len);
+        let degree = (0..=index)
+            .rev()
+            .find_map(|i| self.mapping[i as usize])
+            .unwrap_or(0);
+        self.degree_cents(key.div_euclid(len) * octave + degree as i32)
+    }
+
+    /// Frequency of MIDI note `note` in Hz.
+    pub fn frequency(&self, note: i32) -> f32 {
+        let cents = self.note_cents(note) - self.note_cents(self.reference_note as i32);
+        self.reference_freq * powf(2.0, cents / 1200.0)
+    }
+
+    /// Frequency of every MIDI note in Hz, as handed to processors in
+    /// `BlockContext::note_frequencies`.
+    pub fn note_frequencies(&self) -> [f32; 128] {
+        core::array::from_fn(|note| self.frequency(note as i32))
+    }
+}
+
+/// The lines of a Scala file that aren't comments, trimmed.
+fn scala_lines(text: &str) -> impl Iterator<Item = &str> {
+    text.lines()
+        .filter(|line| !line.starts_with('!'))
+        .map(|line| line.trim())
+}
+
+/// The first word of a line, parsed. Scala files allow anything after it.
+fn parse_field<T: core::str::FromStr>(line: Option<&str>) -> Result<T, Error> {
+    line.and_then(|line| line.split_whitespace().next())
+        .and_then(|field| field.parse().ok())
+        .ok_or(Error::Parse(ParseError::Scala))
+}
+
+/// A pitch in a `.scl` file in cents, which are written with a period, or as a ratio like `3/2`
+/// or a whole number like `2`.
+fn parse_pitch(line: &str) -> Option<f32> {
+    let field = line.split_whitespace().next()?;
+    if field.contains('.') {
+        return field.parse().ok();
+    }
+    let (num, den) = match field.split_once('/') {
+        Some((num, den)) => (num.parse::<f32>().ok()?, den.parse::<f32>().ok()?),
+        None => (field.parse::<f32>().ok()?, 1.0),
+    };
+    if num <= 0.0 || den <= 0.0 {
+        return None;
+    }
+    Some(1200.0 * log2f(num / den))
+}
+
+/// The frequency of pitch CV `v_oct` from the `notes` of a scale table, gliding evenly from one
+/// note to the next.
+pub(crate) fn table_frequency(v_oct: f32, notes: &[f32; 128]) -> f32 {
+    let note = (v_oct / SEMITONE as f32 + 64.0).clamp(0.0, 127.0);
+    let below = (floorf(note) as usize).min(126);
+    let (low, high) = (notes[below], notes[below + 1]);
+    low * powf(high / low, note - below as f32)
+}
@@ -0,0 +1,68 @@
+//! Telling processors which channels of their inputs have gone idle.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    socket_local::LocalInterface, time::MonotonicTime, AudioFrame, AudioPacket, InputJackHandle,
+    Module, BLOCK_SIZE, CHANNELS, IDLE_BLOCKS, IDLE_LEVEL,
+};
+use rand::rngs::ThreadRng;
+
+/// A block with only the first channel playing
+fn first_channel(level: i16) -> AudioPacket {
+    let mut frame = AudioFrame::default();
+    frame.data[0] = level;
+    AudioPacket {
+        data: [frame; BLOCK_SIZE],
+    }
+}
+
+type Consumer = Module<LocalInterface<1, 0>, ThreadRng, 1, 0>;
+
+/// Poll the block `time` ms in, returning the activity the processor was handed
+fn activity(module: &mut Consumer, input: InputJackHandle, time: i64) -> u16 {
+    let mut active = 0;
+    module
+        .poll(MonotonicTime::from_millis(time), |block| {
+            active = block.get_input_activity(input)
+        })
+        .unwrap();
+    active
+}
+
+#[test]
+fn quiet_channels_go_idle() {
+    let mut module: Consumer = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Activity Module".into(),
+        0,
+        MonotonicTime::default(),
+    );
+    let input = module.add_input_jack().unwrap();
+
+    // Nothing has been heard yet
+    assert_eq!(activity(&mut module, input, 0), 0);
+
+    module.set_input_normal(input, first_channel(1000));
+    assert_eq!(activity(&mut module, input, 1), 1);
+
+    // Quiet enough to count as silence, but only once it has lasted a while
+    module.set_input_normal(input, first_channel(IDLE_LEVEL));
+    let idle_at = 1 + IDLE_BLOCKS as i64;
+    for time in 2..idle_at {
+        assert_eq!(activity(&mut module, input, time), 1);
+    }
+    assert_eq!(activity(&mut module, input, idle_at), 0);
+}
+
+#[test]
+fn loud_channels_are_found() {
+    assert_eq!(first_channel(-1000).loud_channels(), 1);
+    assert_eq!(first_channel(IDLE_LEVEL).loud_channels(), 0);
+    assert_eq!(
+        AudioPacket::splat(IDLE_LEVEL + 1)
+            .loud_channels()
+            .count_ones(),
+        CHANNELS as u32
+    );
+}
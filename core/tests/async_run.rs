@@ -0,0 +1,75 @@
+//! Patching two modules together while they run as futures on one runtime thread.
+#![cfg(all(feature = "async", feature = "network-local"))]
+
+use std::{cell::Cell, ops::ControlFlow};
+
+use apiary_core::{socket_local::LocalInterface, time::StdClock, AudioPacket, Module};
+use palette::Srgb;
+use rand::rngs::ThreadRng;
+
+mod common;
+use common::PATCH_TIMEOUT;
+
+type ClockedModule<const I: usize, const O: usize> =
+    Module<LocalInterface<I, O>, ThreadRng, I, O, StdClock>;
+
+/// A module that keeps its own time off the host's clock.
+fn clocked_module<const I: usize, const O: usize>(name: &str) -> ClockedModule<I, O> {
+    Module::with_clock(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        name.into(),
+        0,
+        StdClock::new(),
+    )
+}
+
+#[tokio::test(flavor = "current_thread")]
+async fn patch_and_pass_audio_on_one_runtime() {
+    let mut producer: ClockedModule<0, 1> = clocked_module("Async Producer");
+    let mut consumer: ClockedModule<1, 0> = clocked_module("Async Consumer");
+    let output = producer.add_output_jack().unwrap();
+    let input = consumer.add_input_jack().unwrap();
+    producer.set_output_patch_enabled(output, true).unwrap();
+    consumer.set_input_patch_enabled(input, true).unwrap();
+
+    let mut polls = 0;
+    let received = Cell::new(false);
+    let produce = producer.run(
+        |block| block.set_output(output, AudioPacket::splat(i16::MAX / 2)),
+        |module, update| {
+            polls += 1;
+            // Let go of the jack as soon as a person would see the patch made
+            if update.get_output_color(output) == Srgb::new(255, 255, 0) {
+                module.set_output_patch_enabled(output, false).unwrap();
+            }
+            if polls < PATCH_TIMEOUT.as_millis() {
+                ControlFlow::Continue(())
+            } else {
+                ControlFlow::Break(())
+            }
+        },
+    );
+    let consume = consumer.run(
+        |block| received.set(received.get() || block.get_input(input).max() > 0.0),
+        |module, update| {
+            if update.get_input_color(input) == Srgb::new(255, 255, 0) {
+                module.set_input_patch_enabled(input, false).unwrap();
+            }
+            if received.get() {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        },
+    );
+    let timeout = tokio::time::sleep(std::time::Duration::from_millis(
+        2 * PATCH_TIMEOUT.as_millis() as u64,
+    ));
+    tokio::select! {
+        res = consume => res.unwrap(),
+        _ = produce => {}
+        _ = timeout => {}
+    }
+    assert!(received.get(), "no audio arrived at the input");
+}
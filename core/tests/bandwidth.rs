@@ -0,0 +1,38 @@
+//! Patches refused for taking the network over its bandwidth budget.
+#![cfg(feature = "network-local")]
+
+use apiary_core::time::Duration;
+use palette::Srgb;
+
+mod common;
+use common::{module, LocalPair, Pair, Rig, PATCH_TIMEOUT};
+
+/// Less than a single stream takes, in bits/s
+const TINY_BUDGET: u32 = 1000;
+
+#[test]
+fn patch_over_budget_is_refused() {
+    let mut pair: LocalPair<1, 0> = Pair::new(module("Budget Source"), module("Budget Sink"));
+    pair.producer.set_bandwidth_budget(TINY_BUDGET);
+    pair.consumer.set_bandwidth_budget(TINY_BUDGET);
+
+    let failed = Srgb::new(255, 0, 255);
+    pair.hold(true);
+    let start = pair.time();
+    while pair.step() != failed {
+        assert!(
+            pair.time() - start < PATCH_TIMEOUT,
+            "patch was never refused"
+        );
+    }
+    pair.hold(false);
+    pair.settle(Duration::from_millis(100));
+    assert_eq!(pair.received, None);
+    assert_eq!(pair.consumer.bandwidth_stats().local, 0);
+
+    // With room for the stream the same patch goes through
+    pair.producer.set_bandwidth_budget(u32::MAX);
+    pair.consumer.set_bandwidth_budget(u32::MAX);
+    pair.patch(|p, held| p.hold(held));
+    pair.wait("no audio arrived at the input", |p| p.received.is_some());
+}
@@ -0,0 +1,104 @@
+//! Transfers too large for a directive, sent between the control ports of two modules.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    dsp::oscillators::{UserWavetable, WAVETABLE_SIZE},
+    socket_local::{Impairment, LocalInterface},
+    time::MonotonicTime,
+    Module, Network, ParamPreset, RamStorage, Storage, TransferKind, TransferStatus,
+};
+
+/// Longest a transfer is allowed to take, in ms
+const TRANSFER_TIMEOUT: i64 = 3000;
+
+type Peer = Module<LocalInterface<0, 0>, rand::rngs::ThreadRng, 0, 0>;
+
+fn modules(name: &str, lossy: bool) -> (Peer, Peer) {
+    let mut sender_interface = LocalInterface::with_seed(1).unwrap();
+    let mut receiver_interface = LocalInterface::with_seed(2).unwrap();
+    if lossy {
+        let lossy = Impairment {
+            drop: 0.2,
+            reorder: 0.1,
+            ..Default::default()
+        };
+        for interface in [&mut sender_interface, &mut receiver_interface] {
+            let addr = interface.control_addr().unwrap().addr;
+            interface.impairments().set(addr, Some(lossy));
+        }
+    }
+    let sender = Module::new(
+        sender_interface,
+        rand::thread_rng(),
+        format!("{} Sender", name).as_str().into(),
+        0,
+        MonotonicTime::default(),
+    );
+    let receiver = Module::new(
+        receiver_interface,
+        rand::thread_rng(),
+        format!("{} Receiver", name).as_str().into(),
+        0,
+        MonotonicTime::default(),
+    );
+    (sender, receiver)
+}
+
+#[test]
+fn wavetable_upload_over_control_port() {
+    let (mut sender, mut receiver) = modules("Wavetable", false);
+    let mut wavetable = UserWavetable::default();
+    for (i, sample) in wavetable.samples.iter_mut().enumerate() {
+        *sample = (i as i16).wrapping_mul(37);
+    }
+    sender
+        .upload_wavetable("Wavetable Receiver".into(), 1, &wavetable)
+        .unwrap();
+
+    let mut storage = RamStorage::<2>::default();
+    let mut stored = false;
+    for time in (0..TRANSFER_TIMEOUT).map(MonotonicTime::from_millis) {
+        sender.poll(time, |_| {}).unwrap();
+        receiver.poll(time, |_| {}).unwrap();
+        if let Some(transfer) = receiver.received_transfer() {
+            assert_eq!(transfer.kind, TransferKind::Wavetable(1));
+            assert_eq!(transfer.data.len(), 2 * WAVETABLE_SIZE);
+            receiver.store_transfer(&mut storage).unwrap();
+            stored = true;
+        }
+        if stored && sender.transfer_status() == TransferStatus::Sent {
+            break;
+        }
+    }
+    assert!(stored, "wavetable never arrived");
+    assert_eq!(sender.transfer_status(), TransferStatus::Sent);
+    assert_eq!(storage.wavetable(1).unwrap().samples, wavetable.samples);
+}
+
+#[test]
+fn preset_transfer_survives_packet_loss() {
+    let (mut sender, mut receiver) = modules("Preset", true);
+    let preset = ParamPreset {
+        name: "Lossy".into(),
+        values: [0.25, -3.0, 440.0].iter().copied().collect(),
+    };
+    sender
+        .send_preset("Preset Receiver".into(), 2, &preset)
+        .unwrap();
+    // Only one transfer is sent at a time
+    assert!(sender
+        .send_transfer("Preset Receiver".into(), TransferKind::Description, &[])
+        .is_err());
+
+    let mut storage = RamStorage::<0>::default();
+    for time in (0..TRANSFER_TIMEOUT).map(MonotonicTime::from_millis) {
+        sender.poll(time, |_| {}).unwrap();
+        receiver.poll(time, |_| {}).unwrap();
+        receiver.store_transfer(&mut storage).unwrap();
+        if sender.transfer_status() == TransferStatus::Sent {
+            break;
+        }
+    }
+    assert_eq!(sender.transfer_status(), TransferStatus::Sent);
+    assert_eq!(storage.preset(2), Some(&preset));
+}
@@ -0,0 +1,79 @@
+//! Polling a module to catch up after its host falls behind, or that keeps falling behind.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    socket_local::LocalInterface,
+    time::{Duration, MonotonicTime},
+    CatchUpPolicy, Module, OverrunPolicy, Quality, Status,
+};
+
+#[test]
+fn skip_audio_while_catching_up() {
+    let mut module: Module<LocalInterface<0, 1>, _, 0, 1> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Catch Up Module".into(),
+        0,
+        MonotonicTime::default(),
+    );
+    module.add_output_jack().unwrap();
+    module.set_catch_up(CatchUpPolicy {
+        skip_audio: true,
+        ..Default::default()
+    });
+
+    // The host stalled at 10 ms, and only gets around to polling again at 20 ms
+    let mut processed = vec![];
+    module.set_now(MonotonicTime::from_millis(20));
+    for time in (10..=20).map(MonotonicTime::from_millis) {
+        module.poll(time, |_| processed.push(time)).unwrap();
+    }
+    assert_eq!(processed, [MonotonicTime::from_millis(20)]);
+}
+
+#[test]
+fn overruns_step_down_quality() {
+    let mut module: Module<LocalInterface<0, 1>, _, 0, 1> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Overrun Module".into(),
+        0,
+        MonotonicTime::default(),
+    );
+    module.add_output_jack().unwrap();
+    module.set_overrun_policy(OverrunPolicy {
+        lowest: Quality::Half,
+        ..Default::default()
+    });
+
+    // A second of every poll running over steps down one level at a time
+    let mut time = MonotonicTime::default();
+    let mut seen = vec![];
+    for quality in [Quality::Reduced, Quality::Half] {
+        for _ in 0..1000 {
+            module.report_poll_time(1500);
+        }
+        assert_eq!(module.quality(), quality);
+        let update = module
+            .poll(time, |block| seen.push(block.context().quality))
+            .unwrap();
+        assert_eq!(update.get_status(), Status::Degraded);
+        time += Duration::from_millis(1);
+    }
+
+    // Only every other block is processed at half quality
+    for _ in 0..4 {
+        module
+            .poll(time, |block| seen.push(block.context().quality))
+            .unwrap();
+        time += Duration::from_millis(1);
+    }
+    assert_eq!(seen, [Quality::Reduced, Quality::Half, Quality::Half]);
+
+    // And it takes a while of keeping up to step back up
+    for _ in 0..5000 {
+        module.report_poll_time(500);
+    }
+    assert_eq!(module.quality(), Quality::Reduced);
+    assert_eq!(module.overruns(), 2000);
+}
@@ -0,0 +1,62 @@
+//! Rearranging the channels of a connection on the way into an input jack.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{AudioFrame, AudioPacket, ChannelMap, BLOCK_SIZE, CHANNELS};
+
+mod common;
+use common::{module, LocalPair, Pair, Rig};
+
+/// A block with each channel at a level of its own
+fn numbered() -> AudioPacket {
+    let frame = AudioFrame {
+        data: core::array::from_fn(|c| 100 * (c as i16 + 1)),
+    };
+    AudioPacket {
+        data: [frame; BLOCK_SIZE],
+    }
+}
+
+/// Whether the first frame into the consumer is `expected`.
+fn arrived(pair: &LocalPair<1, 0>, expected: [i16; CHANNELS]) -> bool {
+    pair.seen.data[0].data == expected
+}
+
+#[test]
+fn channel_maps_apply_to_the_connection() {
+    let mut pair: LocalPair<1, 0> =
+        Pair::new(module("Channels Producer"), module("Channels Consumer"));
+    pair.packet = numbered();
+    pair.patch(|p, held| p.hold(held));
+
+    let straight = numbered().data[0].data;
+    pair.wait("channels never arrived", |p| arrived(p, straight));
+    assert_eq!(pair.consumer.channel_map(pair.input), ChannelMap::Straight);
+
+    // Anyone can set the map of a connection, here the producer
+    pair.producer
+        .send_channel_map("Channels Consumer".into(), 0, ChannelMap::Broadcast)
+        .unwrap();
+    pair.wait("channels were never broadcast", |p| {
+        arrived(p, [100; CHANNELS])
+    });
+    assert_eq!(pair.consumer.channel_map(pair.input), ChannelMap::Broadcast);
+
+    pair.producer
+        .send_channel_map("Channels Consumer".into(), 0, ChannelMap::Rotate(1))
+        .unwrap();
+    let rotated = core::array::from_fn(|c| straight[(c + CHANNELS - 1) % CHANNELS]);
+    pair.wait("channels were never rotated", |p| arrived(p, rotated));
+}
+
+#[test]
+fn explicit_maps_pick_source_channels() {
+    let mut reversed = [0; CHANNELS];
+    for (c, source) in reversed.iter_mut().enumerate() {
+        *source = (CHANNELS - 1 - c) as u8;
+    }
+    let mut output = AudioPacket::splat(0);
+    ChannelMap::Map(reversed).apply(&numbered(), &mut output);
+    assert_eq!(output.data[BLOCK_SIZE - 1].data[0], 100 * CHANNELS as i16);
+    assert_eq!(output.data[0].data[CHANNELS - 1], 100);
+    assert_eq!(ChannelMap::Rotate(CHANNELS as u8 + 1).source(1), 0);
+}
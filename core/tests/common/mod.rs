@@ -0,0 +1,172 @@
+//! Modules for the tests to patch together, and polling them in step.
+// Each test binary only uses some of this
+#![allow(dead_code)]
+
+#[cfg(feature = "network-local")]
+use apiary_core::socket_local::LocalInterface;
+use apiary_core::{
+    time::{Duration, MonotonicTime},
+    AudioPacket, InputJackHandle, JackEvent, Module, Network, OutputJackHandle,
+};
+use palette::Srgb;
+use rand::rngs::ThreadRng;
+
+/// Longest a single patch is allowed to take
+pub const PATCH_TIMEOUT: Duration = Duration::from_secs(1);
+/// Time between polls
+pub const BLOCK: Duration = Duration::from_millis(1);
+
+#[cfg(feature = "network-local")]
+pub type TestModule<const I: usize, const O: usize> = Module<LocalInterface<I, O>, ThreadRng, I, O>;
+
+#[cfg(feature = "network-local")]
+pub fn module<const I: usize, const O: usize>(name: &str) -> TestModule<I, O> {
+    module_on(LocalInterface::new().unwrap(), name)
+}
+
+/// A module on an interface set up by the test, such as one with impairments or another backend.
+pub fn module_on<N: Network<I, O>, const I: usize, const O: usize>(
+    interface: N,
+    name: &str,
+) -> Module<N, ThreadRng, I, O> {
+    Module::new(
+        interface,
+        rand::thread_rng(),
+        name.into(),
+        0,
+        MonotonicTime::default(),
+    )
+}
+
+/// A handful of modules patched together, polled a block at a time.
+pub trait Rig: Sized {
+    /// Poll every module once, returning the color of a jack that shows the patch state. Every
+    /// module shows it, so any jack is as good as another.
+    fn step(&mut self) -> Srgb<u8>;
+
+    /// Time of the next block to poll.
+    fn time(&self) -> MonotonicTime;
+
+    /// Longest a single patch is allowed to take. Rigs on networks that lose packets allow for
+    /// the retries.
+    fn timeout(&self) -> Duration {
+        PATCH_TIMEOUT
+    }
+
+    /// Hold down a pair of jacks with `hold` until the patch is made.
+    fn patch(&mut self, hold: impl Fn(&mut Self, bool)) {
+        let toggled = Srgb::new(255, 255, 0);
+        // Wait for the last patch to clear first
+        while self.step() == toggled {}
+        hold(self, true);
+        let start = self.time();
+        while self.step() != toggled {
+            assert!(
+                self.time() - start < self.timeout(),
+                "patch was never toggled"
+            );
+        }
+        hold(self, false);
+    }
+
+    /// Keep polling until `done`, failing with `what` if that takes longer than a patch may.
+    fn wait(&mut self, what: &str, done: impl Fn(&Self) -> bool) {
+        let start = self.time();
+        while !done(self) {
+            assert!(self.time() - start < self.timeout(), "{}", what);
+            self.step();
+        }
+    }
+
+    /// Keep polling for `time`.
+    fn settle(&mut self, time: Duration) {
+        for _ in 0..time.as_millis() {
+            self.step();
+        }
+    }
+}
+
+/// A producer sending a steady packet out of its output, and a consumer listening on an input.
+pub struct Pair<P: Network<0, 1>, C: Network<I, O>, const I: usize, const O: usize> {
+    pub producer: Module<P, ThreadRng, 0, 1>,
+    pub output: OutputJackHandle,
+    pub consumer: Module<C, ThreadRng, I, O>,
+    pub input: InputJackHandle,
+    pub time: MonotonicTime,
+    /// Sent by the producer every block, a steady level unless the test sets another
+    pub packet: AudioPacket,
+    /// The last block into the consumer's input
+    pub seen: AudioPacket,
+    /// Poll that audio first arrived at the input in
+    pub received: Option<MonotonicTime>,
+    /// Connections and disconnections reported for the consumer's inputs
+    pub events: Vec<JackEvent>,
+    pub timeout: Duration,
+}
+
+#[cfg(feature = "network-local")]
+pub type LocalPair<const I: usize, const O: usize> =
+    Pair<LocalInterface<0, 1>, LocalInterface<I, O>, I, O>;
+
+impl<P: Network<0, 1>, C: Network<I, O>, const I: usize, const O: usize> Pair<P, C, I, O> {
+    /// Add an output jack to `producer` and the next input jack to `consumer` to patch together.
+    pub fn new(
+        mut producer: Module<P, ThreadRng, 0, 1>,
+        mut consumer: Module<C, ThreadRng, I, O>,
+    ) -> Self {
+        Pair {
+            output: producer.add_output_jack().unwrap(),
+            input: consumer.add_input_jack().unwrap(),
+            producer,
+            consumer,
+            time: MonotonicTime::default(),
+            packet: AudioPacket::splat(i16::MAX / 2),
+            seen: AudioPacket::splat(0),
+            received: None,
+            events: vec![],
+            timeout: PATCH_TIMEOUT,
+        }
+    }
+
+    pub fn hold(&mut self, held: bool) {
+        self.producer
+            .set_output_patch_enabled(self.output, held)
+            .unwrap();
+        self.consumer
+            .set_input_patch_enabled(self.input, held)
+            .unwrap();
+    }
+}
+
+impl<P: Network<0, 1>, C: Network<I, O>, const I: usize, const O: usize> Rig for Pair<P, C, I, O> {
+    fn step(&mut self) -> Srgb<u8> {
+        let (output, packet) = (self.output, self.packet);
+        let color = self
+            .producer
+            .poll(self.time, |block| block.set_output(output, packet))
+            .unwrap()
+            .get_output_color(output);
+        let (input, time) = (self.input, self.time);
+        let (seen, received) = (&mut self.seen, &mut self.received);
+        let update = self
+            .consumer
+            .poll(self.time, |block| {
+                *seen = *block.get_input(input);
+                if received.is_none() && seen.max() > 0.0 {
+                    *received = Some(time);
+                }
+            })
+            .unwrap();
+        self.events.extend(update.jack_events().cloned());
+        self.time += BLOCK;
+        color
+    }
+
+    fn time(&self) -> MonotonicTime {
+        self.time
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
@@ -0,0 +1,65 @@
+//! Describing a module with `define_module!` and adding its jacks from the description.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    dsp::sample::SignalLevel, socket_local::LocalInterface, time::MonotonicTime, AudioPacket,
+    Module,
+};
+
+mod gain {
+    apiary_core::define_module! {
+        name: "gain",
+        color: 90,
+        inputs: {
+            IN_INPUT: "Input",
+            CV_INPUT: "Gain CV" as Unipolar,
+        },
+        outputs: {
+            OUT_OUTPUT: "Output",
+        },
+        params: {
+            GAIN_PARAM: "Gain" { min: 0.0, max: 2.0, default: 1.0, unit: "", log: false },
+            SLEW_PARAM: "Slew" { min: 1.0, max: 1000.0, default: 10.0, unit: "ms", log: true },
+        },
+    }
+}
+
+use gain::*;
+
+#[test]
+fn constants_follow_declaration_order() {
+    assert_eq!((IN_INPUT, CV_INPUT, NUM_INPUTS), (0, 1, 2));
+    assert_eq!((OUT_OUTPUT, NUM_OUTPUTS), (0, 1));
+    assert_eq!((GAIN_PARAM, SLEW_PARAM, NUM_PARAMS), (0, 1, 2));
+    assert_eq!((NAME, COLOR), ("gain", 90));
+    assert_eq!(SPEC.inputs, ["Input", "Gain CV"]);
+    assert_eq!(
+        SPEC.input_levels,
+        [SignalLevel::Audio, SignalLevel::Unipolar]
+    );
+    assert_eq!(SPEC.output_levels, [SignalLevel::Audio]);
+    assert_eq!(SPEC.params[SLEW_PARAM].unit, "ms");
+    assert!(SPEC.params[SLEW_PARAM].log);
+}
+
+#[test]
+fn jacks_are_added_in_order() {
+    let mut module: Module<_, _, NUM_INPUTS, NUM_OUTPUTS> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Defined Module".into(),
+        COLOR,
+        MonotonicTime::default(),
+    );
+    let jacks = Jacks::add(&mut module).unwrap();
+    assert!(module.add_input_jack().is_err());
+    assert!(module.add_output_jack().is_err());
+
+    module.set_input_normal(jacks.inputs[CV_INPUT], AudioPacket::splat(100));
+    module
+        .poll(MonotonicTime::default(), |block| {
+            assert_eq!(block.get_input(jacks.inputs[IN_INPUT]).max(), 0.0);
+            assert_eq!(block.get_input(jacks.inputs[CV_INPUT]).max(), 100.0);
+        })
+        .unwrap();
+}
@@ -0,0 +1,77 @@
+//! Finding out what another module on the network is, for remote control surfaces.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{time::MonotonicTime, ModuleDescription, ParamDescription};
+
+mod common;
+use common::{module, TestModule};
+
+mod gain {
+    apiary_core::define_module! {
+        name: "gain",
+        color: 90,
+        inputs: {
+            IN_INPUT: "Input",
+            CV_INPUT: "Gain CV" as Unipolar,
+        },
+        outputs: {
+            OUT_OUTPUT: "Output",
+        },
+        params: {
+            GAIN_PARAM: "Gain" { min: 0.0, max: 2.0, default: 1.0, unit: "", log: false },
+        },
+    }
+}
+
+#[test]
+fn modules_describe_themselves() {
+    let mut manager: TestModule<0, 0> = module("Describe Manager");
+    let mut gain: TestModule<2, 1> = module("Describe Gain");
+    gain.set_description(gain::SPEC.describe());
+    let mut silent: TestModule<0, 0> = module("Describe Silent");
+
+    manager
+        .send_describe_request("Describe Gain".into())
+        .unwrap();
+    manager
+        .send_describe_request("Describe Silent".into())
+        .unwrap();
+    let mut heard = Vec::new();
+    for time in (0..200).map(MonotonicTime::from_millis) {
+        gain.poll(time, |_| {}).unwrap();
+        silent.poll(time, |_| {}).unwrap();
+        let update = manager.poll(time, |_| {}).unwrap();
+        if let Some((uuid, description)) = update.peer_description() {
+            heard.push((uuid.clone(), description.clone()));
+        }
+    }
+    // Modules without a description stay quiet
+    assert_eq!(heard.len(), 1);
+    let (uuid, description) = &heard[0];
+    assert_eq!(uuid.as_str(), "Describe Gain");
+    assert_eq!(description.kind.as_str(), "gain");
+    let inputs: Vec<&str> = description
+        .inputs
+        .iter()
+        .map(|name| name.as_str())
+        .collect();
+    assert_eq!(inputs, ["Input", "Gain CV"]);
+    assert_eq!(description.outputs[gain::OUT_OUTPUT].as_str(), "Output");
+    assert_eq!(description.params[gain::GAIN_PARAM].position(1.5), 0.75);
+}
+
+#[test]
+fn long_labels_are_cut_short() {
+    let mut description = ModuleDescription::new("A module with a very long name", 0);
+    description.add_param(ParamDescription::new(
+        "Resonance amount ééé",
+        "",
+        0.0,
+        1.0,
+        0.5,
+        false,
+    ));
+    assert_eq!(description.kind.as_str(), "A module with a very");
+    // Cut at a character boundary rather than partway through one
+    assert_eq!(description.params[0].name.as_str(), "Resonance amount é");
+}
@@ -0,0 +1,28 @@
+//! Patching two modules over `IpcInterface`, the same way as over `LocalInterface` in
+//! `patching.rs` but through Unix domain sockets.
+#![cfg(all(feature = "network-ipc", unix))]
+
+use std::{env, fs, process};
+
+use apiary_core::{socket_ipc::IpcInterface, time::Duration};
+
+mod common;
+use common::{module_on, Pair, Rig};
+
+/// Longest the whole patch is allowed to take, in ms. Joining a group takes up to a quarter of a
+/// second to be noticed by senders.
+const PATCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[test]
+fn patch_and_pass_audio_between_sockets() {
+    let root = env::temp_dir().join(format!("apiary-test-{}", process::id()));
+    let mut pair = Pair::<_, _, 1, 0>::new(
+        module_on(IpcInterface::with_dir(&root).unwrap(), "IPC Producer"),
+        module_on(IpcInterface::with_dir(&root).unwrap(), "IPC Consumer"),
+    );
+    pair.timeout = PATCH_TIMEOUT;
+    pair.patch(|p, held| p.hold(held));
+    pair.wait("no audio arrived at the input", |p| p.received.is_some());
+    drop(pair);
+    let _ = fs::remove_dir_all(&root);
+}
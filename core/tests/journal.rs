@@ -0,0 +1,78 @@
+//! Bringing back a patch from a leader's journal after every module on the network restarts.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    journal::PatchJournal, time::MonotonicTime, AudioPacket, InputJackHandle, OutputJackHandle,
+};
+
+mod common;
+use common::{module, TestModule};
+
+/// Longest the patch is allowed to take to be made, or to come back, in ms
+const RESTORE_TIMEOUT: i64 = 5000;
+
+/// A leader keeping the journal at `path`, and a pair of modules to patch together.
+fn network(
+    path: &std::path::Path,
+) -> (
+    TestModule<0, 0>,
+    TestModule<0, 1>,
+    OutputJackHandle,
+    TestModule<1, 0>,
+    InputJackHandle,
+) {
+    let mut leader = module("Journal Leader");
+    leader.set_journal(PatchJournal::open(path).unwrap());
+    let mut producer = module("Journal Producer");
+    let output = producer.add_output_jack().unwrap();
+    let mut consumer = module("Journal Consumer");
+    let input = consumer.add_input_jack().unwrap();
+    (leader, producer, output, consumer, input)
+}
+
+#[test]
+fn journal_restores_patch_after_restart() {
+    let path = std::env::temp_dir().join(format!("apiary-journal-{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let (mut leader, mut producer, output, mut consumer, input) = network(&path);
+    producer.set_output_patch_enabled(output, true).unwrap();
+    consumer.set_input_patch_enabled(input, true).unwrap();
+    for time in (0..RESTORE_TIMEOUT).map(MonotonicTime::from_millis) {
+        leader.poll(time, |_| {}).unwrap();
+        producer.poll(time, |_| {}).unwrap();
+        consumer.poll(time, |_| {}).unwrap();
+        if leader.journal_mut().unwrap().len() == 1 {
+            break;
+        }
+    }
+    assert_eq!(leader.journal_mut().unwrap().len(), 1);
+    let topology = leader.journal_mut().unwrap().topology();
+    assert!(topology
+        .to_dot()
+        .contains("\"Journal Producer\" -> \"Journal Consumer\""));
+    assert!(topology.to_mermaid().contains("m0 -- \"0 → 0\" --> m1"));
+    drop((leader, producer, consumer));
+
+    // Everything comes back with new jack addresses, and nobody touches a jack
+    let (mut leader, mut producer, output, mut consumer, input) = network(&path);
+    let mut received = false;
+    for time in (0..RESTORE_TIMEOUT).map(MonotonicTime::from_millis) {
+        leader.poll(time, |_| {}).unwrap();
+        producer
+            .poll(time, |block| {
+                block.set_output(output, AudioPacket::splat(i16::MAX / 2))
+            })
+            .unwrap();
+        consumer
+            .poll(time, |block| {
+                received |= block.get_input(input).max() > 0.0;
+            })
+            .unwrap();
+        if received {
+            break;
+        }
+    }
+    let _ = std::fs::remove_file(&path);
+    assert!(received, "patch was not restored from the journal");
+}
@@ -0,0 +1,119 @@
+//! A mixer-like module with one input patched straight from a source and another through an
+//! effect in between, lining the two back up with latency compensation.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    time::{Duration, MonotonicTime},
+    AudioPacket, InputJackHandle, OutputJackHandle,
+};
+use palette::Srgb;
+
+mod common;
+use common::{module, Rig, TestModule, BLOCK};
+
+/// Long enough for the latencies to make it around in the stats of every module
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+struct Patch {
+    source: TestModule<0, 1>,
+    source_out: OutputJackHandle,
+    effect: TestModule<1, 1>,
+    effect_in: InputJackHandle,
+    effect_out: OutputJackHandle,
+    mixer: TestModule<2, 0>,
+    direct_in: InputJackHandle,
+    effect_return: InputJackHandle,
+    time: MonotonicTime,
+    /// What the mixer last saw on its direct and effect inputs
+    seen: (i16, i16),
+}
+
+impl Patch {
+    fn new() -> Self {
+        let mut source: TestModule<0, 1> = module("Alignment Source");
+        let mut effect: TestModule<1, 1> = module("Alignment Effect");
+        let mut mixer: TestModule<2, 0> = module("Alignment Mixer");
+        Patch {
+            source_out: source.add_output_jack().unwrap(),
+            effect_in: effect.add_input_jack().unwrap(),
+            effect_out: effect.add_output_jack().unwrap(),
+            direct_in: mixer.add_input_jack().unwrap(),
+            effect_return: mixer.add_input_jack().unwrap(),
+            source,
+            effect,
+            mixer,
+            time: MonotonicTime::default(),
+            seen: (0, 0),
+        }
+    }
+}
+
+impl Rig for Patch {
+    /// Modules further down the patch go first so that each hop takes a block, as it would
+    /// between separate pieces of hardware.
+    fn step(&mut self) -> Srgb<u8> {
+        let (direct_in, effect_return) = (self.direct_in, self.effect_return);
+        let seen = &mut self.seen;
+        self.mixer
+            .poll(self.time, |block| {
+                *seen = (
+                    block.get_input(direct_in).data[0].data[0],
+                    block.get_input(effect_return).data[0].data[0],
+                );
+            })
+            .unwrap();
+        let (effect_in, effect_out) = (self.effect_in, self.effect_out);
+        self.effect
+            .poll(self.time, |block| {
+                let packet = *block.get_input(effect_in);
+                block.set_output(effect_out, packet);
+            })
+            .unwrap();
+        // The source counts blocks, so the mixer can tell how far behind each input is
+        let (source_out, count) = (self.source_out, self.time.as_millis() as i16 + 1);
+        let color = self
+            .source
+            .poll(self.time, |block| {
+                block.set_output(source_out, AudioPacket::splat(count))
+            })
+            .unwrap()
+            .get_output_color(source_out);
+        self.time += BLOCK;
+        color
+    }
+
+    fn time(&self) -> MonotonicTime {
+        self.time
+    }
+}
+
+#[test]
+fn inputs_line_up_with_compensation() {
+    let mut patch = Patch::new();
+    patch.patch(|p, on| {
+        p.source.set_output_patch_enabled(p.source_out, on).unwrap();
+        p.effect.set_input_patch_enabled(p.effect_in, on).unwrap();
+    });
+    patch.patch(|p, on| {
+        p.source.set_output_patch_enabled(p.source_out, on).unwrap();
+        p.mixer.set_input_patch_enabled(p.direct_in, on).unwrap();
+    });
+    patch.patch(|p, on| {
+        p.effect.set_output_patch_enabled(p.effect_out, on).unwrap();
+        p.mixer
+            .set_input_patch_enabled(p.effect_return, on)
+            .unwrap();
+    });
+    patch.settle(SETTLE_TIME);
+
+    let (direct, through_effect) = patch.seen;
+    assert_eq!(direct - through_effect, 1);
+    assert_eq!(patch.effect.latency(), 1);
+    assert_eq!(patch.mixer.latency(), 2);
+
+    patch.mixer.set_latency_compensation(true);
+    patch.settle(SETTLE_TIME);
+    let (direct, through_effect) = patch.seen;
+    assert!(direct > 0);
+    assert_eq!(direct, through_effect);
+}
@@ -0,0 +1,46 @@
+//! Patching over a `LocalInterface` that loses, repeats, reorders and delays packets, so the
+//! patch relies on the acknowledgements and retries to get through. This lives apart from
+//! `patching.rs` so that the two patches don't see each other's held jacks.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    socket_local::{Impairment, LocalInterface, PATCH_ADDR},
+    time::Duration,
+    Network,
+};
+
+mod common;
+use common::{module_on, LocalPair, Pair, Rig};
+
+/// Longest the whole patch is allowed to take
+const PATCH_TIMEOUT: Duration = Duration::from_secs(3);
+
+const LOSSY: Impairment = Impairment {
+    drop: 0.2,
+    duplicate: 0.1,
+    reorder: 0.1,
+    latency: 1,
+    jitter: 4,
+};
+
+#[test]
+fn patch_over_lossy_network() {
+    let mut producer_interface = LocalInterface::new().unwrap();
+    let mut consumer_interface = LocalInterface::new().unwrap();
+    producer_interface.set_impairment_seed(1);
+    consumer_interface.set_impairment_seed(2);
+    producer_interface
+        .impairments()
+        .set(PATCH_ADDR, Some(LOSSY));
+    let consumer_impairments = consumer_interface.impairments();
+    consumer_impairments.set(PATCH_ADDR, Some(LOSSY));
+    consumer_impairments.set(producer_interface.jack_addr(0).unwrap(), Some(LOSSY));
+
+    let mut pair: LocalPair<1, 0> = Pair::new(
+        module_on(producer_interface, "Lossy Producer"),
+        module_on(consumer_interface, "Lossy Consumer"),
+    );
+    pair.timeout = PATCH_TIMEOUT;
+    pair.patch(|p, held| p.hold(held));
+    pair.wait("no audio arrived at the input", |p| p.received.is_some());
+}
@@ -0,0 +1,91 @@
+//! Muting and bypassing a module from elsewhere on the network, without touching its patch.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{socket_local::LocalInterface, time::MonotonicTime, AudioPacket, Module};
+use palette::Srgb;
+
+fn controller() -> Module<LocalInterface<0, 0>, rand::rngs::ThreadRng, 0, 0> {
+    Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Mute Controller".into(),
+        0,
+        MonotonicTime::default(),
+    )
+}
+
+#[test]
+fn mute_silences_outputs_until_unmuted() {
+    let mut controller = controller();
+    let mut module: Module<LocalInterface<0, 1>, _, 0, 1> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Mute Module".into(),
+        60,
+        MonotonicTime::default(),
+    );
+    let output = module.add_output_jack().unwrap();
+    module.set_free_running(true);
+
+    let poll = |module: &mut Module<_, _, 0, 1>, time| {
+        module
+            .poll(time, |block| {
+                block.set_output(output, AudioPacket::splat(i16::MAX))
+            })
+            .unwrap()
+    };
+    let lit = poll(&mut module, MonotonicTime::from_millis(0)).get_output_color(output);
+
+    controller.send_mute("Mute Module".into(), true).unwrap();
+    for time in (1..10).map(MonotonicTime::from_millis) {
+        controller.poll(time, |_| {}).unwrap();
+        poll(&mut module, time);
+    }
+    assert!(module.is_muted());
+    let update = poll(&mut module, MonotonicTime::from_millis(10));
+    assert!(update.is_muted());
+    assert_ne!(update.get_output_color(output), lit);
+
+    controller.send_mute("Mute Module".into(), false).unwrap();
+    for time in (11..20).map(MonotonicTime::from_millis) {
+        controller.poll(time, |_| {}).unwrap();
+        poll(&mut module, time);
+    }
+    let update = poll(&mut module, MonotonicTime::from_millis(20));
+    assert!(!update.is_muted());
+    assert_eq!(update.get_output_color(output), lit);
+}
+
+#[test]
+fn bypass_passes_inputs_through() {
+    let mut controller = controller();
+    let mut module: Module<LocalInterface<1, 1>, _, 1, 1> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Bypass Module".into(),
+        60,
+        MonotonicTime::default(),
+    );
+    let input = module.add_input_jack().unwrap();
+    let output = module.add_output_jack().unwrap();
+    module.set_input_normal(input, AudioPacket::splat(i16::MAX));
+    module.set_free_running(true);
+
+    // Processing leaves the output silent, so any light on it came through the bypass
+    let poll = |module: &mut Module<_, _, 1, 1>, time| module.poll(time, |_| {}).unwrap();
+    assert_eq!(
+        poll(&mut module, MonotonicTime::from_millis(0)).get_output_color(output),
+        Srgb::new(0, 0, 0)
+    );
+
+    controller
+        .send_bypass("Bypass Module".into(), true)
+        .unwrap();
+    for time in (1..10).map(MonotonicTime::from_millis) {
+        controller.poll(time, |_| {}).unwrap();
+        poll(&mut module, time);
+    }
+    let update = poll(&mut module, MonotonicTime::from_millis(10));
+    assert!(update.is_bypassed());
+    assert_ne!(update.get_output_color(output), Srgb::new(0, 0, 0));
+}
@@ -0,0 +1,244 @@
+//! What every `Network` backend has to do for a `Module` to work on top of it, checked the same
+//! way against each one. A new backend proves itself by passing `conformance` a way to open
+//! interfaces on a shared network.
+#![cfg(any(
+    feature = "network-local",
+    feature = "network-native",
+    all(feature = "network-ipc", unix)
+))]
+
+use std::{mem, thread};
+
+use apiary_core::{
+    time::{Duration, MonotonicTime},
+    AudioPacket, Error, Network,
+};
+
+/// How long to wait for something sent to arrive, polling once a ms. Joining a group over IPC
+/// takes up to a quarter of a second to be noticed by senders.
+const ARRIVAL_TIMEOUT: Duration = Duration::from_secs(3);
+/// Polls to keep sending after a disconnect before anything arriving counts as a failure, for
+/// packets already on their way
+const DRAIN_POLLS: i64 = 50;
+
+#[cfg(feature = "network-local")]
+#[test]
+fn local_interface_conforms() {
+    use apiary_core::socket_local::LocalInterface;
+    conformance::<_, 2, 2>(|| LocalInterface::new().unwrap());
+}
+
+#[cfg(all(feature = "network-ipc", unix))]
+#[test]
+fn ipc_interface_conforms() {
+    use apiary_core::socket_ipc::IpcInterface;
+    let root = std::env::temp_dir().join(format!("apiary-conformance-{}", std::process::id()));
+    conformance::<_, 2, 2>(|| IpcInterface::with_dir(&root).unwrap());
+    let _ = std::fs::remove_dir_all(&root);
+}
+
+/// Multicast over the loopback has to work on the test machine, so this passes without checking
+/// anything where it doesn't.
+#[cfg(feature = "network-native")]
+#[test]
+fn native_interface_conforms() {
+    use apiary_core::socket_native::NativeInterface;
+    let mut probe = NativeInterface::<0, 0>::new().unwrap();
+    if probe.self_test().is_err() {
+        return;
+    }
+    conformance::<_, 2, 2>(|| NativeInterface::new().unwrap());
+}
+
+/// Check a backend with interfaces from `make`, which all have to be on the same network. Needs
+/// at least one input and one output jack.
+fn conformance<N, const I: usize, const O: usize>(mut make: impl FnMut() -> N)
+where
+    N: Network<I, O>,
+{
+    assert!(I > 0 && O > 0, "conformance needs an input and an output");
+    let mut a = make();
+    let mut b = make();
+    let mut time = MonotonicTime::default();
+    jack_addresses(&mut a);
+    invalid_jacks(&mut a);
+    directive_round_trip(&mut a, &mut b, &mut time);
+    unconnected_jacks_are_empty(&mut a, &mut b, &mut time);
+    jack_send_and_receive(&mut a, &mut b, &mut time);
+    jack_disconnect(&mut a, &mut b, &mut time);
+    control_round_trip(&mut a, &mut b, &mut time);
+}
+
+/// Every output jack has a multicast address of its own, which doesn't change.
+fn jack_addresses<N: Network<I, O>, const I: usize, const O: usize>(a: &mut N) {
+    let addrs: Vec<[u8; 4]> = (0..O).map(|i| a.jack_addr(i).unwrap()).collect();
+    for (i, addr) in addrs.iter().enumerate() {
+        assert!(
+            (224..=239).contains(&addr[0]),
+            "jack {} address {:?} isn't multicast",
+            i,
+            addr
+        );
+        assert_eq!(a.jack_addr(i).unwrap(), *addr);
+    }
+}
+
+/// Jack ids past the end are refused rather than panicking.
+fn invalid_jacks<N: Network<I, O>, const I: usize, const O: usize>(a: &mut N) {
+    let addr = a.jack_addr(0).unwrap();
+    let time = MonotonicTime::default();
+    assert!(matches!(a.jack_addr(O), Err(Error::InvalidJackId(id)) if id == O));
+    assert!(matches!(a.jack_connect(I, addr, time), Err(Error::InvalidJackId(id)) if id == I));
+    assert!(matches!(a.jack_disconnect(I, time), Err(Error::InvalidJackId(id)) if id == I));
+    // Disconnecting a jack that was never connected is fine
+    a.jack_disconnect(0, time).unwrap();
+}
+
+/// A directive sent by one interface arrives whole at the other, and back at the sender too
+/// while multicast loop is on (the default everywhere).
+fn directive_round_trip<N: Network<I, O>, const I: usize, const O: usize>(
+    a: &mut N,
+    b: &mut N,
+    time: &mut MonotonicTime,
+) {
+    let directive = format!("conformance directive {}", rand::random::<u64>()).into_bytes();
+    a.send_directive(&directive).unwrap();
+    let mut heard = [false; 2];
+    let mut buf = [0; 2048];
+    let end = *time + ARRIVAL_TIMEOUT;
+    while heard != [true; 2] && *time < end {
+        for (heard, interface) in heard.iter_mut().zip([&mut *a, &mut *b]) {
+            interface.poll(*time).unwrap();
+            // Directives from anything else on the network are skipped over
+            loop {
+                match interface.recv_directive(&mut buf) {
+                    Ok(size) => *heard |= buf[..size] == directive[..],
+                    Err(Error::NoData) => break,
+                    Err(e) => panic!("directive receive failed: {:?}", e),
+                }
+            }
+        }
+        tick(time);
+    }
+    assert_eq!(
+        heard, [true; 2],
+        "directive didn't arrive (sender, receiver)"
+    );
+}
+
+/// Nothing arrives on a jack that isn't connected, whatever else is being sent.
+fn unconnected_jacks_are_empty<N: Network<I, O>, const I: usize, const O: usize>(
+    a: &mut N,
+    b: &mut N,
+    time: &mut MonotonicTime,
+) {
+    for _ in 0..DRAIN_POLLS {
+        send_block(a, 1, *time);
+        b.poll(*time).unwrap();
+        assert!(b.dequeue_packets().iter().all(|p| p.is_empty()));
+        tick(time);
+    }
+}
+
+/// Blocks sent on an output arrive intact on an input connected to its address, and only on
+/// that input.
+fn jack_send_and_receive<N: Network<I, O>, const I: usize, const O: usize>(
+    a: &mut N,
+    b: &mut N,
+    time: &mut MonotonicTime,
+) {
+    b.jack_connect(0, a.jack_addr(0).unwrap(), *time).unwrap();
+    let end = *time + ARRIVAL_TIMEOUT;
+    let mut received = None;
+    while received.is_none() && *time < end {
+        send_block(a, 2, *time);
+        b.poll(*time).unwrap();
+        let packets = b.dequeue_packets();
+        assert!(packets[1..].iter().all(|p| p.is_empty()));
+        if !packets[0].is_empty() {
+            received = Some(packets[0].to_vec());
+        }
+        tick(time);
+    }
+    let received = received.expect("nothing arrived on the connected jack");
+    assert_eq!(received, block(2));
+}
+
+/// A disconnected input stops receiving once whatever was on its way has arrived, and can be
+/// connected again.
+fn jack_disconnect<N: Network<I, O>, const I: usize, const O: usize>(
+    a: &mut N,
+    b: &mut N,
+    time: &mut MonotonicTime,
+) {
+    b.jack_disconnect(0, *time).unwrap();
+    for i in 0..2 * DRAIN_POLLS {
+        send_block(a, 3, *time);
+        b.poll(*time).unwrap();
+        let packets = b.dequeue_packets();
+        if i >= DRAIN_POLLS {
+            assert!(
+                packets.iter().all(|p| p.is_empty()),
+                "received after disconnect"
+            );
+        }
+        tick(time);
+    }
+    jack_send_and_receive(a, b, time);
+}
+
+/// Interfaces in the same process, like modules sharing a host, each get a control port of their
+/// own where the backend has them, and a datagram sent to one arrives from the other's.
+fn control_round_trip<N: Network<I, O>, const I: usize, const O: usize>(
+    a: &mut N,
+    b: &mut N,
+    time: &mut MonotonicTime,
+) {
+    let (a_addr, b_addr) = match (a.control_addr(), b.control_addr()) {
+        (Some(a_addr), Some(b_addr)) => (a_addr, b_addr),
+        _ => return,
+    };
+    assert_ne!(a_addr, b_addr, "control ports are shared");
+    let datagram = format!("conformance control {}", rand::random::<u64>()).into_bytes();
+    a.send_control(b_addr, &datagram).unwrap();
+    let end = *time + ARRIVAL_TIMEOUT;
+    let mut buf = [0; 2048];
+    let mut received = None;
+    while received.is_none() && *time < end {
+        b.poll(*time).unwrap();
+        match b.recv_control(&mut buf) {
+            Ok((size, from)) => received = Some((buf[..size].to_vec(), from)),
+            Err(Error::NoData) => {}
+            Err(e) => panic!("control receive failed: {:?}", e),
+        }
+        tick(time);
+    }
+    assert_eq!(
+        received.expect("nothing arrived on the control port"),
+        (datagram, a_addr)
+    );
+}
+
+/// A whole audio packet on output 0, filled with `fill` so it can be told apart from the others.
+fn block(fill: u8) -> Vec<u8> {
+    vec![fill; mem::size_of::<AudioPacket>()]
+}
+
+fn send_block<N: Network<I, O>, const I: usize, const O: usize>(
+    a: &mut N,
+    fill: u8,
+    time: MonotonicTime,
+) {
+    let mut sizes = [0; O];
+    sizes[0] = mem::size_of::<AudioPacket>();
+    // A full transmit buffer just leaves this block out
+    if let Some(buf) = &mut a.enqueue_packets(sizes).unwrap()[0] {
+        buf.copy_from_slice(&block(fill));
+    }
+    a.poll(time).unwrap();
+}
+
+fn tick(time: &mut MonotonicTime) {
+    *time += Duration::from_millis(1);
+    thread::sleep(std::time::Duration::from_millis(1));
+}
@@ -0,0 +1,263 @@
+//! Patching two modules together over `LocalInterface`, from holding down their jacks to audio
+//! arriving at the input.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    time::{Duration, MonotonicTime},
+    AudioPacket, Capability, FeedbackPolicy, InputJackHandle, JackEvent, JackPeer,
+    OutputJackHandle, PatchState,
+};
+use palette::Srgb;
+
+mod common;
+use common::{module, LocalPair, Pair, Rig, TestModule, BLOCK, PATCH_TIMEOUT};
+
+#[test]
+fn patch_and_pass_audio() {
+    let mut pair: LocalPair<1, 0> = Pair::new(module("Producer"), module("Consumer"));
+    // Jacks light up yellow once the patch is made, and are let go of as soon as a person would
+    // see it
+    pair.patch(|p, held| p.hold(held));
+    let toggled = pair.time() - BLOCK;
+    pair.wait("no audio arrived at the input", |p| p.received.is_some());
+
+    assert!(toggled <= pair.received.unwrap());
+    assert_eq!(
+        pair.events,
+        [JackEvent::Connected {
+            handle: pair.input,
+            peer: JackPeer {
+                uuid: "Producer".into(),
+                jack_id: 0,
+            },
+        }]
+    );
+}
+
+#[test]
+fn patch_through_a_coordinator() {
+    patch_with_coordinators(vec![coordinator("Coordinator", Capability::Supervisor)]);
+}
+
+#[test]
+fn held_output_is_seen_across_the_network() {
+    let mut producer: TestModule<0, 1> = module("Held Producer");
+    let mut listener: TestModule<0, 0> = module("Held Listener");
+    let output = producer.add_output_jack().unwrap();
+    producer.set_output_patch_enabled(output, true).unwrap();
+
+    let mut held = None;
+    for time in (0..PATCH_TIMEOUT.as_millis()).map(MonotonicTime::from_millis) {
+        producer.poll(time, |_| {}).unwrap();
+        let update = listener.poll(time, |_| {}).unwrap();
+        if update.patch_state() == PatchState::PatchEnabled {
+            held = update.held_output().cloned();
+            break;
+        }
+    }
+    assert_eq!(
+        held,
+        Some(JackPeer {
+            uuid: "Held Producer".into(),
+            jack_id: 0,
+        })
+    );
+}
+
+/// Holding an output is enough for a module auditioning the network to hear it, and letting go
+/// tears the probe back down.
+#[test]
+fn held_output_is_auditioned() {
+    let mut producer: TestModule<0, 1> = module("Audition Producer");
+    let mut listener: TestModule<1, 0> = module("Audition Listener");
+    let output = producer.add_output_jack().unwrap();
+    let monitor = listener.add_monitor_jack().unwrap();
+    listener.set_audition(Some(monitor));
+    producer.set_output_patch_enabled(output, true).unwrap();
+
+    let mut heard = false;
+    let mut time = MonotonicTime::default();
+    while time < MonotonicTime::default() + PATCH_TIMEOUT && !heard {
+        producer
+            .poll(time, |block| {
+                block.set_output(output, AudioPacket::splat(i16::MAX / 2))
+            })
+            .unwrap();
+        listener
+            .poll(time, |block| heard |= block.inputs()[0].max() > 0.0)
+            .unwrap();
+        time += BLOCK;
+    }
+    assert!(heard, "held output was not auditioned");
+
+    producer.set_output_patch_enabled(output, false).unwrap();
+    let end = time + PATCH_TIMEOUT;
+    while time < end && heard {
+        producer
+            .poll(time, |block| {
+                block.set_output(output, AudioPacket::splat(i16::MAX / 2))
+            })
+            .unwrap();
+        heard = false;
+        listener
+            .poll(time, |block| heard |= block.inputs()[0].max() > 0.0)
+            .unwrap();
+        time += BLOCK;
+    }
+    assert!(!heard, "audition outlived the held output");
+}
+
+/// Modules with more jacks than fit in a `u16` patch and poll like any other, with the last
+/// jack a monitor.
+#[test]
+fn patch_into_a_wide_module() {
+    let mut consumer: TestModule<20, 20> = module("Wide Consumer");
+    for _ in 0..18 {
+        consumer.add_input_jack().unwrap();
+    }
+    let mut pair: LocalPair<20, 20> = Pair::new(module("Wide Producer"), consumer);
+    pair.consumer.add_monitor_jack().unwrap();
+    for _ in 0..20 {
+        pair.consumer.add_output_jack().unwrap();
+    }
+
+    pair.patch(|p, held| p.hold(held));
+    pair.wait("no audio arrived at the input", |p| p.received.is_some());
+}
+
+/// The embedded coordinator stands by for the supervisor, rather than the two fighting over
+/// the patch.
+#[test]
+fn patch_through_competing_coordinators() {
+    patch_with_coordinators(vec![
+        coordinator("Embedded Coordinator", Capability::Embedded),
+        coordinator("Supervisor Coordinator", Capability::Supervisor),
+    ]);
+}
+
+fn coordinator(name: &str, capability: Capability) -> TestModule<0, 0> {
+    let mut coordinator = module(name);
+    coordinator.set_coordinator(true);
+    coordinator.set_capability(capability);
+    coordinator
+}
+
+/// A producer and consumer patched while `coordinators` run the global state.
+struct Coordinated {
+    coordinators: Vec<TestModule<0, 0>>,
+    pair: LocalPair<1, 0>,
+}
+
+impl Rig for Coordinated {
+    fn step(&mut self) -> Srgb<u8> {
+        for coordinator in self.coordinators.iter_mut() {
+            coordinator.poll(self.pair.time, |_| {}).unwrap();
+        }
+        self.pair.step()
+    }
+
+    fn time(&self) -> MonotonicTime {
+        self.pair.time
+    }
+}
+
+fn patch_with_coordinators(coordinators: Vec<TestModule<0, 0>>) {
+    let mut rig = Coordinated {
+        coordinators,
+        pair: Pair::new(
+            module("Coordinated Producer"),
+            module("Coordinated Consumer"),
+        ),
+    };
+    // Let the other modules hear from the coordinator before touching any jacks
+    rig.settle(Duration::from_millis(100));
+    rig.patch(|r, held| r.pair.hold(held));
+    rig.wait("no audio arrived at the input", |r| {
+        r.pair.received.is_some()
+    });
+}
+
+/// A module with one input and one output, for patching in a loop
+type Looped = (TestModule<1, 1>, InputJackHandle, OutputJackHandle);
+
+/// Two looped modules and a coordinator deciding on the patches between them.
+struct Loop {
+    coordinator: TestModule<0, 0>,
+    modules: [Looped; 2],
+    time: MonotonicTime,
+    /// The patch state last decided on, and whether it was flagged as feedback
+    decided: Option<(PatchState, bool)>,
+}
+
+impl Loop {
+    /// Hold the output of one module and the input of another (by position in `modules`) until
+    /// the coordinator decides on the patch, then let go and let the network settle.
+    fn patch(&mut self, (from, to): (usize, usize)) -> (PatchState, bool) {
+        self.decided = None;
+        self.hold((from, to), true);
+        self.wait("patch was never decided on", |l| l.decided.is_some());
+        self.hold((from, to), false);
+        self.settle(Duration::from_millis(200));
+        self.decided.unwrap()
+    }
+
+    fn hold(&mut self, (from, to): (usize, usize), held: bool) {
+        let (module, _, output) = &mut self.modules[from];
+        module.set_output_patch_enabled(*output, held).unwrap();
+        let (module, input, _) = &mut self.modules[to];
+        module.set_input_patch_enabled(*input, held).unwrap();
+    }
+}
+
+impl Rig for Loop {
+    fn step(&mut self) -> Srgb<u8> {
+        self.coordinator.poll(self.time, |_| {}).unwrap();
+        let mut color = Default::default();
+        for (module, _, output) in self.modules.iter_mut() {
+            let update = module.poll(self.time, |_| {}).unwrap();
+            if self.decided.is_none()
+                && matches!(
+                    update.patch_state(),
+                    PatchState::PatchToggled | PatchState::Failed
+                )
+            {
+                self.decided = Some((update.patch_state(), update.is_feedback()));
+            }
+            color = update.get_output_color(*output);
+        }
+        self.time += BLOCK;
+        color
+    }
+
+    fn time(&self) -> MonotonicTime {
+        self.time
+    }
+}
+
+/// Patching a module's output back around to its own input is flagged by default, with the
+/// connection that closes the loop limited, and refused when the coordinator is told to.
+#[test]
+fn feedback_is_flagged_or_refused() {
+    let mut rig = Loop {
+        coordinator: coordinator("Feedback Coordinator", Capability::Supervisor),
+        modules: ["Feedback A", "Feedback B"].map(|name| {
+            let mut module = module(name);
+            let input = module.add_input_jack().unwrap();
+            let output = module.add_output_jack().unwrap();
+            (module, input, output)
+        }),
+        time: MonotonicTime::default(),
+        decided: None,
+    };
+    rig.settle(Duration::from_millis(200));
+
+    assert_eq!(rig.patch((0, 1)), (PatchState::PatchToggled, false));
+    assert_eq!(rig.patch((1, 0)), (PatchState::PatchToggled, true));
+    // Only the connection closing the loop is limited
+    let [(a, a_input, _), (b, b_input, _)] = &rig.modules;
+    assert!(a.is_feedback_input(*a_input));
+    assert!(!b.is_feedback_input(*b_input));
+
+    rig.coordinator.set_feedback_policy(FeedbackPolicy::Refuse);
+    assert_eq!(rig.patch((0, 0)), (PatchState::Failed, true));
+}
@@ -0,0 +1,94 @@
+//! Calling a processor through the entry points generated by `export_processor!`, as a host
+//! would after loading it from a library.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    export_processor,
+    plugin::{quality_to_abi, PluginInfo, PluginProcessFn, PLUGIN_ABI_VERSION},
+    AudioPacket, BlockContext, ParamBlock, ProcessBlock, Processor, Quality, Tuning, BLOCK_SIZE,
+    CHANNELS,
+};
+
+#[derive(Default)]
+struct Offset {
+    blocks: i16,
+}
+
+impl Processor<2, 1, 1> for Offset {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<2, 1>,
+        params: &ParamBlock<1>,
+        context: &BlockContext,
+    ) {
+        assert_eq!(context.sample_rate.0, 48000);
+        assert_eq!(context.quality, Quality::Reduced);
+        assert_eq!(context.tuning.a4, 432.0);
+        assert_eq!(context.note_frequencies.map(|notes| notes[69]), Some(432.0));
+        assert_eq!(context.voices, 1);
+        assert!(params[0] >= 0.0, "negative offset");
+        self.blocks += 1;
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            output[0].data[i].data[0] =
+                input[1].data[i].data[0] + params.at(0, i) as i16 + self.blocks;
+        }
+    }
+}
+
+export_processor!(Offset, 2, 1, 1);
+
+fn run(state: *mut std::ffi::c_void, offset: (f32, f32), output: &mut AudioPacket) -> i32 {
+    let process: PluginProcessFn = apiary_plugin_process;
+    let input = [AudioPacket::splat(1), AudioPacket::splat(100)];
+    let inputs = [&input[0] as *const _, &input[1] as *const _];
+    let outputs = [output as *mut _];
+    let notes = [432.0_f32; 128];
+    unsafe {
+        process(
+            state,
+            inputs.as_ptr(),
+            outputs.as_ptr(),
+            &offset.0,
+            &offset.1,
+            0,
+            48000,
+            quality_to_abi(Quality::Reduced),
+            Tuning {
+                a4: 432.0,
+                ..Default::default()
+            },
+            notes.as_ptr(),
+            1,
+        )
+    }
+}
+
+#[test]
+fn processor_runs_through_the_c_abi() {
+    assert_eq!(
+        apiary_plugin_info(),
+        PluginInfo {
+            abi_version: PLUGIN_ABI_VERSION,
+            channels: CHANNELS as u32,
+            block_size: BLOCK_SIZE as u32,
+            num_inputs: 2,
+            num_outputs: 1,
+            num_params: 1,
+        }
+    );
+
+    let state = apiary_plugin_create();
+    let mut output = AudioPacket::default();
+    assert_eq!(run(state, (0.0, 10.0), &mut output), 0);
+    // Params ramp over the block and the processor keeps its state between calls
+    assert_eq!(output.data[0].data[0], 100 + 1);
+    assert_eq!(output.data[BLOCK_SIZE - 1].data[0], 100 + 10 + 1);
+    assert_eq!(run(state, (10.0, 10.0), &mut output), 0);
+    assert_eq!(output.data[0].data[0], 100 + 10 + 2);
+
+    // A panic is reported instead of unwinding into the host
+    assert_eq!(run(state, (-1.0, -1.0), &mut output), -1);
+    unsafe { apiary_plugin_destroy(state) };
+}
@@ -0,0 +1,20 @@
+//! Modules only run at the rate their block clock keeps up with.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{Error, SampleRate};
+
+mod common;
+use common::{module, TestModule};
+
+#[test]
+fn only_the_wire_rate_is_supported() {
+    let mut module: TestModule<0, 0> = module("Sample Rate");
+    for rate in [44100, 96000] {
+        assert!(matches!(
+            module.set_sample_rate(SampleRate(rate)),
+            Err(Error::UnsupportedSampleRate(r)) if r == rate
+        ));
+    }
+    assert_eq!(module.sample_rate(), SampleRate::default());
+    module.set_sample_rate(SampleRate::default()).unwrap();
+}
@@ -0,0 +1,77 @@
+//! Polling a module whose transmit buffer can't take all of its outputs at once, the way a busy
+//! embedded interface's ring fills up.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    socket_local::LocalInterface, time::MonotonicTime, AudioPacket, Module, OutputJackHandle,
+};
+use rand::rngs::ThreadRng;
+
+const BLOCKS: i64 = 100;
+const OUTPUTS: usize = 3;
+
+type SaturatedModule = Module<LocalInterface<0, OUTPUTS>, ThreadRng, 0, OUTPUTS>;
+
+/// An interface with room to queue only one of its outputs between polls.
+fn saturated_interface() -> LocalInterface<0, OUTPUTS> {
+    let mut interface = LocalInterface::new().unwrap();
+    interface.set_tx_capacity(Some(1));
+    interface
+}
+
+/// A free running module sending out of every output on `interface`.
+fn saturated_module(
+    interface: LocalInterface<0, OUTPUTS>,
+    name: &str,
+    send_retry: bool,
+) -> (SaturatedModule, Vec<OutputJackHandle>) {
+    let mut module = Module::new(
+        interface,
+        rand::thread_rng(),
+        name.into(),
+        60,
+        MonotonicTime::default(),
+    );
+    let outputs = (0..OUTPUTS)
+        .map(|_| module.add_output_jack().unwrap())
+        .collect();
+    module.set_free_running(true);
+    module.set_send_retry(send_retry);
+    (module, outputs)
+}
+
+/// Poll for a while, checking that every poll comes back with `expected` failures.
+fn poll_saturated(module: &mut SaturatedModule, outputs: &[OutputJackHandle], expected: u32) {
+    for time in (0..BLOCKS).map(MonotonicTime::from_millis) {
+        let update = module
+            .poll(time, |block| {
+                for &output in outputs {
+                    block.set_output(output, AudioPacket::splat(1000));
+                }
+            })
+            .unwrap();
+        assert_eq!(update.send_failures(), expected, "at {:?}", time);
+    }
+    assert_eq!(module.send_failures(), expected * BLOCKS as u32);
+}
+
+#[test]
+fn full_transmit_buffer_is_counted() {
+    let (mut module, outputs) = saturated_module(saturated_interface(), "Saturated", false);
+    poll_saturated(&mut module, &outputs, OUTPUTS as u32 - 1);
+}
+
+#[test]
+fn retry_sends_another_output() {
+    let (mut module, outputs) = saturated_module(saturated_interface(), "Saturated Retry", true);
+    poll_saturated(&mut module, &outputs, OUTPUTS as u32 - 2);
+}
+
+/// A socket that takes every packet but can't send any of them counts each jack, not each poll.
+#[test]
+fn blocked_sends_are_counted() {
+    let mut interface = LocalInterface::new().unwrap();
+    interface.set_tx_blocked(true);
+    let (mut module, outputs) = saturated_module(interface, "Saturated Blocked", false);
+    poll_saturated(&mut module, &outputs, OUTPUTS as u32);
+}
@@ -0,0 +1,33 @@
+//! Runs that are seeded make the same random choices every time.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{socket_local::LocalInterface, Network, SeededRng, Uuid};
+use rand_core::RngCore;
+
+#[test]
+fn seeded_rng_repeats() {
+    let mut a = SeededRng::new(7);
+    let mut b = SeededRng::new(7);
+    let first: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+    assert_eq!(first, (0..8).map(|_| b.next_u64()).collect::<Vec<_>>());
+    assert_ne!(first[0], SeededRng::new(8).next_u64());
+
+    let uuid: Uuid = "seeded".into();
+    assert_eq!(
+        SeededRng::from_uuid(&uuid).next_u32(),
+        SeededRng::from_uuid(&uuid).next_u32()
+    );
+    assert_ne!(
+        SeededRng::from_uuid(&uuid).next_u32(),
+        SeededRng::from_uuid(&"other".into()).next_u32()
+    );
+}
+
+#[test]
+fn seeded_interface_picks_the_same_jack_addresses() {
+    let mut a = LocalInterface::<0, 2>::with_seed(3).unwrap();
+    let mut b = LocalInterface::<0, 2>::with_seed(3).unwrap();
+    for i in 0..2 {
+        assert_eq!(a.jack_addr(i).unwrap(), b.jack_addr(i).unwrap());
+    }
+}
@@ -0,0 +1,170 @@
+//! Changing another module's knobs over the network.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    socket_local::LocalInterface, time::MonotonicTime, CompareAction, Module, ParamChange,
+    ParamSpec, PresetAction, RamStorage, Storage,
+};
+
+#[test]
+fn set_param_reaches_only_its_module() {
+    let mut matrix: Module<LocalInterface<0, 0>, _, 0, 0> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "SetParam Matrix".into(),
+        0,
+        MonotonicTime::default(),
+    );
+    let mut target: Module<LocalInterface<0, 0>, _, 0, 0> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "SetParam Target".into(),
+        0,
+        MonotonicTime::default(),
+    );
+
+    matrix
+        .send_set_param("SetParam Target".into(), 2, ParamChange::Set(0.25))
+        .unwrap();
+    matrix
+        .send_set_param("SetParam Target".into(), 1, ParamChange::Modulate(0.5))
+        .unwrap();
+    matrix
+        .send_set_param("SetParam Target".into(), 1, ParamChange::Modulate(-0.5))
+        .unwrap();
+    matrix
+        .send_set_param("SetParam Other".into(), 0, ParamChange::Set(1.0))
+        .unwrap();
+
+    let mut changes = vec![];
+    for time in (0..20).map(MonotonicTime::from_millis) {
+        matrix.poll(time, |_| {}).unwrap();
+        let update = target.poll(time, |_| {}).unwrap();
+        changes.extend(update.param_changes());
+    }
+    assert_eq!(
+        changes,
+        vec![
+            (2, ParamChange::Set(0.25)),
+            (1, ParamChange::Modulate(0.5)),
+            (1, ParamChange::Modulate(-0.5))
+        ]
+    );
+}
+
+#[test]
+fn compare_actions_arrive_in_order() {
+    let mut manager: Module<LocalInterface<0, 0>, _, 0, 0> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Compare Manager".into(),
+        0,
+        MonotonicTime::default(),
+    );
+    let mut target: Module<LocalInterface<0, 0>, _, 0, 0> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Compare Target".into(),
+        0,
+        MonotonicTime::default(),
+    );
+
+    for action in [
+        CompareAction::Store,
+        CompareAction::Compare,
+        CompareAction::Revert,
+    ] {
+        manager
+            .send_compare("Compare Target".into(), action)
+            .unwrap();
+    }
+    manager
+        .send_compare("Compare Other".into(), CompareAction::Store)
+        .unwrap();
+
+    let mut actions = vec![];
+    for time in (0..20).map(MonotonicTime::from_millis) {
+        manager.poll(time, |_| {}).unwrap();
+        let update = target.poll(time, |_| {}).unwrap();
+        actions.extend(update.compare_actions());
+    }
+    assert_eq!(
+        actions,
+        vec![
+            CompareAction::Store,
+            CompareAction::Compare,
+            CompareAction::Revert
+        ]
+    );
+}
+
+#[test]
+fn presets_are_stored_and_recalled() {
+    let mut manager: Module<LocalInterface<0, 0>, _, 0, 0> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Preset Manager".into(),
+        0,
+        MonotonicTime::default(),
+    );
+    let mut target: Module<LocalInterface<0, 0>, _, 0, 0> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Preset Target".into(),
+        0,
+        MonotonicTime::default(),
+    );
+    let mut storage: RamStorage<0> = Default::default();
+    let mut knobs = [0.25, 3.0];
+
+    manager
+        .send_store_preset("Preset Target".into(), 1, "Lead")
+        .unwrap();
+    for time in (0..20).map(MonotonicTime::from_millis) {
+        manager.poll(time, |_| {}).unwrap();
+        let update = target.poll(time, |_| {}).unwrap();
+        assert_eq!(update.apply_presets(&mut storage, &knobs).unwrap(), None);
+    }
+    assert_eq!(storage.preset(1).unwrap().name, "Lead");
+
+    knobs = [1.0, 1.0];
+    // The empty slot is passed over
+    manager
+        .send_recall_preset("Preset Target".into(), 1)
+        .unwrap();
+    manager
+        .send_recall_preset("Preset Target".into(), 2)
+        .unwrap();
+    let mut actions = vec![];
+    let mut recalled = None;
+    for time in (20..40).map(MonotonicTime::from_millis) {
+        manager.poll(time, |_| {}).unwrap();
+        let update = target.poll(time, |_| {}).unwrap();
+        actions.extend(update.preset_actions().cloned());
+        recalled = update
+            .apply_presets(&mut storage, &knobs)
+            .unwrap()
+            .or(recalled);
+    }
+    assert_eq!(
+        actions,
+        vec![PresetAction::Recall(1), PresetAction::Recall(2)]
+    );
+    assert_eq!(recalled, Some([0.25, 3.0]));
+}
+
+#[test]
+fn knob_positions_follow_the_taper() {
+    let spec = ParamSpec {
+        name: "Cutoff",
+        unit: "Hz",
+        min: 20.0,
+        max: 20000.0,
+        default: 1000.0,
+        log: true,
+    };
+    assert!((spec.value(0.5) - 632.46).abs() < 0.1);
+    assert!((spec.position(spec.value(0.3)) - 0.3).abs() < 1e-5);
+    assert_eq!(spec.position(0.0), 0.0);
+    assert_eq!(spec.value(2.0), 20000.0);
+}
@@ -0,0 +1,50 @@
+//! Putting a network of modules in standby, and waking them back up by patching.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{socket_local::LocalInterface, time::MonotonicTime, AudioPacket, Module};
+
+#[test]
+fn standby_pauses_outputs_until_patched() {
+    let mut controller: Module<LocalInterface<0, 0>, _, 0, 0> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Standby Controller".into(),
+        0,
+        MonotonicTime::default(),
+    );
+    let mut module: Module<LocalInterface<0, 1>, _, 0, 1> = Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        "Standby Module".into(),
+        60,
+        MonotonicTime::default(),
+    );
+    let output = module.add_output_jack().unwrap();
+    module.set_free_running(true);
+
+    let poll = |module: &mut Module<_, _, 0, 1>, time| {
+        module
+            .poll(time, |block| {
+                block.set_output(output, AudioPacket::splat(i16::MAX))
+            })
+            .unwrap()
+    };
+    let lit = poll(&mut module, MonotonicTime::from_millis(0)).get_output_color(output);
+
+    controller.send_standby();
+    for time in (1..10).map(MonotonicTime::from_millis) {
+        controller.poll(time, |_| {}).unwrap();
+        poll(&mut module, time);
+    }
+    assert!(controller.is_standby());
+    assert!(module.is_standby());
+    let update = poll(&mut module, MonotonicTime::from_millis(10));
+    assert!(update.is_standby());
+    assert!(update.get_output_color(output).red < lit.red);
+
+    // Holding a jack wakes the module right away
+    module.set_output_patch_enabled(output, true).unwrap();
+    assert!(!module.is_standby());
+    module.set_output_patch_enabled(output, false).unwrap();
+    assert!(!poll(&mut module, MonotonicTime::from_millis(11)).is_standby());
+}
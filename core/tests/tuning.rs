@@ -0,0 +1,174 @@
+//! Retuning every module on the network at once.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    socket_local::LocalInterface, time::MonotonicTime, tuning::MAJOR, voct_to_frequency,
+    voct_to_frequency_table, Module, ScaleTable, Tuning,
+};
+
+/// Just intonation pentatonic, from a Scala file
+const PENTATONIC: &str = "! pentatonic.scl
+!
+Just pentatonic
+ 5
+!
+ 9/8
+ 5/4
+ 3/2
+ 5/3
+ 2/1
+";
+
+/// The pentatonic scale on the white keys, skipping F and B, with A4 at 440 Hz
+const WHITE_KEYS: &str = "! white.kbm
+12
+0
+127
+60
+69
+440.0
+5
+! C to B
+0
+x
+1
+x
+2
+x
+x
+3
+x
+4
+x
+x
+";
+
+fn close(a: f32, b: f32) -> bool {
+    (a - b).abs() < 1e-4 * b
+}
+
+type Peer = Module<LocalInterface<0, 0>, rand::rngs::ThreadRng, 0, 0>;
+
+fn module(name: &str) -> Peer {
+    Module::new(
+        LocalInterface::new().unwrap(),
+        rand::thread_rng(),
+        name.into(),
+        0,
+        MonotonicTime::default(),
+    )
+}
+
+#[test]
+fn tuning_reaches_every_module() {
+    let mut coordinator = module("Tuning Coordinator");
+    coordinator.set_coordinator(true);
+    let mut keyboard = module("Tuning Keyboard");
+
+    let tuning = Tuning {
+        a4: 432.0,
+        root: 2,
+        scale: MAJOR,
+    };
+    keyboard.send_tuning(tuning).unwrap();
+    for time in (0..20).map(MonotonicTime::from_millis) {
+        coordinator.poll(time, |_| {}).unwrap();
+        keyboard.poll(time, |_| {}).unwrap();
+    }
+    assert_eq!(coordinator.tuning(), tuning);
+    assert_eq!(keyboard.tuning(), tuning);
+    assert!((voct_to_frequency(5.0 * 512.0, &keyboard.tuning(), None) - 432.0).abs() < 0.01);
+
+    // A module starting later hears it from the coordinator, and keeps its own tuning until then
+    let mut late = module("Tuning Late");
+    assert_eq!(late.tuning(), Tuning::default());
+    assert!((voct_to_frequency(5.0 * 512.0, &late.tuning(), None) - 440.0).abs() < 0.01);
+    for time in (20..2000).map(MonotonicTime::from_millis) {
+        coordinator.poll(time, |_| {}).unwrap();
+        keyboard.poll(time, |_| {}).unwrap();
+        late.poll(time, |_| {}).unwrap();
+        if late.tuning() == tuning {
+            break;
+        }
+    }
+    assert_eq!(late.tuning(), tuning);
+
+    // A scale table takes over from A4 until it's taken away again, reaching processors whole
+    let table = ScaleTable::from_scl(PENTATONIC).unwrap();
+    keyboard.send_scale_table(Some(table.clone())).unwrap();
+    let mut notes = None;
+    for time in (2000..2020).map(MonotonicTime::from_millis) {
+        coordinator.poll(time, |_| {}).unwrap();
+        keyboard.poll(time, |_| {}).unwrap();
+        late.poll(time, |block| notes = block.context().note_frequencies)
+            .unwrap();
+    }
+    assert_eq!(late.scale_table(), Some(&table));
+    assert_eq!(notes, Some(table.note_frequencies()));
+    let notes = notes.as_ref();
+    assert!(close(
+        voct_to_frequency(-4.0 * 512.0, &tuning, notes),
+        132.0
+    ));
+    assert!(close(
+        voct_to_frequency_table(-4 * 512, &tuning, notes),
+        132.0
+    ));
+    assert!(close(
+        voct_to_frequency(-3.5 * 512.0, &tuning, notes),
+        132.0 * 1.125_f32.sqrt()
+    ));
+
+    keyboard.send_scale_table(None).unwrap();
+    let mut notes = Some([0.0; 128]);
+    for time in (2020..2040).map(MonotonicTime::from_millis) {
+        coordinator
+            .poll(time, |block| notes = block.context().note_frequencies)
+            .unwrap();
+        keyboard.poll(time, |_| {}).unwrap();
+    }
+    assert_eq!(coordinator.scale_table(), None);
+    assert_eq!(notes, None);
+    assert!(close(voct_to_frequency(5.0 * 512.0, &tuning, None), 432.0));
+}
+
+#[test]
+fn scala_files_parse() {
+    let scale = ScaleTable::from_scl(PENTATONIC).unwrap();
+    assert_eq!(scale.degrees.len(), 5);
+    assert!(close(scale.degrees[4], 1200.0));
+    // Middle C plays the first degree, and A4 the fifth degree an octave up
+    assert!(close(scale.frequency(60), 132.0));
+    assert!(close(scale.frequency(63), 198.0));
+    assert!(close(scale.frequency(69), 440.0));
+
+    let mapped = scale.with_kbm(WHITE_KEYS).unwrap();
+    assert!(close(mapped.frequency(69), 440.0));
+    assert!(close(mapped.frequency(60), 264.0));
+    assert!(close(mapped.frequency(62), 297.0));
+    // Unmapped keys play the key below
+    assert!(close(mapped.frequency(61), 264.0));
+    assert!(close(mapped.frequency(72), 528.0));
+    assert!(close(mapped.frequency(48), 132.0));
+
+    assert!(ScaleTable::from_scl("Empty\n 0\n").is_err());
+    assert!(ScaleTable::from_scl("Short\n 3\n 100.0\n").is_err());
+}
+
+#[test]
+fn quantize_snaps_to_scale() {
+    // D major: D E F# G A B C#
+    let d_major = Tuning {
+        root: 2,
+        scale: MAJOR,
+        ..Default::default()
+    };
+    // MIDI note 64 is E, in the scale
+    assert_eq!(d_major.quantize(0), 0);
+    // F snaps down to E rather than up to F#, and a little below F# still reaches it
+    assert_eq!(d_major.quantize(512), 0);
+    assert_eq!(d_major.quantize(2 * 512 - 100), 2 * 512);
+    // C snaps down to B
+    assert_eq!(d_major.quantize(-4 * 512), -5 * 512);
+    assert_eq!(Tuning::default().quantize(3 * 512 + 10), 3 * 512);
+}
@@ -0,0 +1,112 @@
+//! The number of voices in use following the patch down from a source of notes.
+#![cfg(feature = "network-local")]
+
+use apiary_core::{
+    time::{Duration, MonotonicTime},
+    AudioPacket, ChannelMap, InputJackHandle, OutputJackHandle, CHANNELS,
+};
+use palette::Srgb;
+
+mod common;
+use common::{module, Rig, TestModule, BLOCK};
+
+/// Long enough for the voice counts to make it around in the stats of every module
+const SETTLE_TIME: Duration = Duration::from_secs(3);
+
+struct Patch {
+    source: TestModule<0, 1>,
+    source_out: OutputJackHandle,
+    filter: TestModule<1, 1>,
+    filter_in: InputJackHandle,
+    filter_out: OutputJackHandle,
+    output: TestModule<1, 0>,
+    output_in: InputJackHandle,
+    time: MonotonicTime,
+    /// Voices handed to the output module's processor in the last block
+    seen: u8,
+}
+
+impl Patch {
+    fn new() -> Self {
+        let mut source: TestModule<0, 1> = module("Voices Source");
+        let mut filter: TestModule<1, 1> = module("Voices Filter");
+        let mut output: TestModule<1, 0> = module("Voices Output");
+        Patch {
+            source_out: source.add_output_jack().unwrap(),
+            filter_in: filter.add_input_jack().unwrap(),
+            filter_out: filter.add_output_jack().unwrap(),
+            output_in: output.add_input_jack().unwrap(),
+            source,
+            filter,
+            output,
+            time: MonotonicTime::default(),
+            seen: 0,
+        }
+    }
+}
+
+impl Rig for Patch {
+    fn step(&mut self) -> Srgb<u8> {
+        let seen = &mut self.seen;
+        self.output
+            .poll(self.time, |block| *seen = block.context().voices)
+            .unwrap();
+        let (filter_in, filter_out) = (self.filter_in, self.filter_out);
+        self.filter
+            .poll(self.time, |block| {
+                let packet = *block.get_input(filter_in);
+                block.set_output(filter_out, packet);
+            })
+            .unwrap();
+        let source_out = self.source_out;
+        let color = self
+            .source
+            .poll(self.time, |block| {
+                block.set_output(source_out, AudioPacket::splat(100))
+            })
+            .unwrap()
+            .get_output_color(source_out);
+        self.time += BLOCK;
+        color
+    }
+
+    fn time(&self) -> MonotonicTime {
+        self.time
+    }
+}
+
+#[test]
+fn voices_follow_the_patch() {
+    let mut patch = Patch::new();
+    // Nothing is known about unpatched modules
+    assert_eq!(patch.filter.voices(), CHANNELS as u8);
+
+    patch.source.set_voices(Some(1));
+    patch.patch(|p, on| {
+        p.source.set_output_patch_enabled(p.source_out, on).unwrap();
+        p.filter.set_input_patch_enabled(p.filter_in, on).unwrap();
+    });
+    patch.patch(|p, on| {
+        p.filter.set_output_patch_enabled(p.filter_out, on).unwrap();
+        p.output.set_input_patch_enabled(p.output_in, on).unwrap();
+    });
+    patch.settle(SETTLE_TIME);
+    assert_eq!(patch.filter.voices(), 1);
+    assert_eq!(patch.output.voices(), 1);
+    assert_eq!(patch.seen, 1);
+
+    // Spreading the voices over every channel on the way in uses them all from there on
+    patch
+        .source
+        .send_channel_map("Voices Filter".into(), 0, ChannelMap::Broadcast)
+        .unwrap();
+    patch.settle(SETTLE_TIME);
+    assert_eq!(patch.filter.voices(), CHANNELS as u8);
+    assert_eq!(patch.seen, CHANNELS as u8);
+
+    // Counts out of range are clamped to the channels there are
+    patch.filter.set_voices(Some(0));
+    assert_eq!(patch.filter.voices(), 1);
+    patch.filter.set_voices(None);
+    assert_eq!(patch.filter.voices(), CHANNELS as u8);
+}
@@ -0,0 +1,129 @@
+use apiary_core::{
+    define_module, dsp::logic::GateDetector, BlockContext, ParamBlock, ProcessBlock, Processor,
+    SampleType, BLOCK_SIZE, CHANNELS,
+};
+
+define_module! {
+    name: "envelope",
+    color: 0,
+    inputs: {
+        GATE_INPUT: "Gate" as Gate,
+    },
+    outputs: {
+        LEVEL_OUTPUT: "Level" as Unipolar,
+    },
+    params: {
+        DELAY_PARAM: "Delay" { min: 0.001, max: 4.0, default: 0.0, unit: " s", log: true },
+        ATTACK_PARAM: "Attack" { min: 0.001, max: 20.0, default: 0.5, unit: " s", log: true },
+        HOLD_PARAM: "Hold" { min: 0.001, max: 4.0, default: 0.0, unit: " s", log: true },
+        DECAY_PARAM: "Decay" { min: 0.001, max: 20.0, default: 0.5, unit: " s", log: true },
+        SUSTAIN_PARAM: "Sustain" { min: 0.0, max: 1.0, default: 0.5, unit: "", log: false },
+        RELEASE_PARAM: "Release" { min: 0.001, max: 20.0, default: 0.5, unit: " s", log: true },
+    },
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Stage {
+    Delay,
+    Attack,
+    Hold,
+    Decay,
+    Sustain,
+    Release,
+}
+
+pub struct Envelope {
+    gates: [GateDetector; CHANNELS],
+    stage: [Stage; CHANNELS],
+    frame_counter: i64,
+    start: [i64; CHANNELS],
+    level: [f32; CHANNELS],
+}
+
+impl Default for Envelope {
+    fn default() -> Self {
+        Envelope {
+            gates: Default::default(),
+            stage: [Stage::Release; CHANNELS],
+            frame_counter: 0,
+            start: [0; CHANNELS],
+            level: [0.0; CHANNELS],
+        }
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Envelope {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        let active = block.active_channels(GATE_INPUT);
+        let input = block.inputs()[GATE_INPUT];
+        let output = &mut block.outputs()[LEVEL_OUTPUT];
+        let dt = context.sample_rate.dt();
+        for i in 0..BLOCK_SIZE {
+            self.frame_counter += 1;
+            for j in 0..CHANNELS {
+                // Nothing to do once released all the way with no gate coming in
+                if active & (1 << j) == 0 && self.level[j] == 0.0 {
+                    output.data[i].data[j] = 0;
+                    continue;
+                }
+                let gate = self.gates[j].process(input.data[i].data[j], context.sample_rate);
+                if !gate.high {
+                    self.stage[j] = Stage::Release;
+                    let step = params[SUSTAIN_PARAM] / params[RELEASE_PARAM] * dt;
+                    self.level[j] = (self.level[j] - step).clamp(0.0, 1.0);
+                } else {
+                    if self.stage[j] == Stage::Release {
+                        self.stage[j] = Stage::Delay;
+                        self.start[j] = self.frame_counter;
+                    }
+                    if self.stage[j] == Stage::Delay {
+                        if self.frame_counter >= self.start[j] + (params[DELAY_PARAM] / dt) as i64 {
+                            self.stage[j] = Stage::Attack;
+                            self.start[j] = self.frame_counter;
+                        } else {
+                            self.level[j] = 0.0;
+                        }
+                    }
+                    if self.stage[j] == Stage::Attack {
+                        if self.frame_counter >= self.start[j] + (params[ATTACK_PARAM] / dt) as i64
+                        {
+                            self.stage[j] = Stage::Hold;
+                            self.start[j] = self.frame_counter;
+                        } else {
+                            let step = 1.0 / params[ATTACK_PARAM] * dt;
+                            self.level[j] = (self.level[j] + step).clamp(0.0, 1.0);
+                        }
+                    }
+                    if self.stage[j] == Stage::Hold {
+                        if self.frame_counter >= self.start[j] + (params[HOLD_PARAM] / dt) as i64 {
+                            self.stage[j] = Stage::Decay;
+                            self.start[j] = self.frame_counter;
+                        } else {
+                            self.level[j] = 1.0;
+                        }
+                    }
+                    if self.stage[j] == Stage::Decay {
+                        if self.frame_counter >= self.start[j] + (params[DECAY_PARAM] / dt) as i64 {
+                            self.stage[j] = Stage::Sustain;
+                            self.start[j] = self.frame_counter;
+                        } else {
+                            let step = (1.0 - params[SUSTAIN_PARAM]) / params[DECAY_PARAM] * dt;
+                            self.level[j] =
+                                (self.level[j] - step).clamp(params[SUSTAIN_PARAM], 1.0);
+                        }
+                    }
+                    if self.stage[j] == Stage::Sustain {
+                        self.level[j] = params[SUSTAIN_PARAM];
+                    }
+                }
+                output.data[i].data[j] =
+                    (self.level[j] * SampleType::MAX as f32 * 0.9) as SampleType;
+            }
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use apiary_core::{
+    define_module,
+    dsp::{
+        control::ControlRate,
+        filters::{LadderFilterFP, LinearTrap, TrapCoefficients},
+        sample::Sample,
+    },
+    softclip, voct_to_freq_scale, BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE,
+    CHANNELS,
+};
+
+define_module! {
+    name: "filter",
+    color: 220,
+    inputs: {
+        IN_INPUT: "Audio",
+        KEY_INPUT: "Key Track" as Pitch,
+        CONTOUR_INPUT: "Contour" as Bipolar,
+    },
+    outputs: {
+        LPF_OUTPUT: "Lowpass Filter",
+        HPF_OUTPUT: "Highpass Filter",
+        BPF_OUTPUT: "Bandpass Filter",
+        NOTCH_OUTPUT: "Notch Filter",
+    },
+    params: {
+        FREQ_PARAM: "Cutoff" { min: 20.0, max: 8000.0, default: 4000.0, unit: " Hz", log: true },
+        RES_PARAM: "Resonance" { min: 0.0, max: 1.0, default: 0.75, unit: "", log: false },
+        CONTOUR_PARAM: "Contour" { min: 0.0, max: 100.0, default: 0.0, unit: "%", log: false },
+        DRIVE_PARAM: "Drive" { min: 1.0, max: 10.0, default: 1.0, unit: "", log: true },
+    },
+}
+
+/// Samples between filter coefficient updates.
+const CONTROL_INTERVAL: usize = 8;
+
+/// A transistor ladder lowpass, with the other responses from a state variable filter.
+#[derive(Default)]
+pub struct Filter {
+    ladders: [LadderFilterFP; CHANNELS],
+    filters: [LinearTrap; CHANNELS],
+    coefficients: [ControlRate<TrapCoefficients, CONTROL_INTERVAL>; CHANNELS],
+    /// Channels that were silent on every output in the last block
+    quiet: u16,
+    approx: bool,
+}
+
+impl Filter {
+    /// Take the lowpass from the state variable filter too, for boards too slow to run the
+    /// ladder alongside it on every channel.
+    pub fn approx(mut self) -> Self {
+        self.approx = true;
+        self
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Filter {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        // Processing time is too slow on the boards to calculate coefficients every audio frame,
+        // so they are updated at control rate and interpolated in between, and less often still
+        // when the module is falling behind
+        let stride = context.quality.control_stride();
+        // Only the voices in use upstream are filtered, along with any channel that has either
+        // something coming in or a tail still ringing out. The rest are left silent, so a channel
+        // that has gone quiet won't start self-oscillating again until something comes in.
+        let active = block.active_channels(IN_INPUT) | !self.quiet;
+        let skip: [bool; CHANNELS] =
+            core::array::from_fn(|j| j >= context.voices as usize || active & (1 << j) == 0);
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            for (j, &skip) in skip.iter().enumerate() {
+                if skip {
+                    for packet in output.iter_mut() {
+                        packet.data[i].data[j] = 0;
+                    }
+                    continue;
+                }
+                let filter = &mut self.filters[j];
+                let ladder = &mut self.ladders[j];
+                let approx = self.approx;
+                let key = input[KEY_INPUT].data[i].data[j] as f32;
+                let contour = input[CONTOUR_INPUT].data[i].data[j].to_f32();
+                filter.set_coefficients(self.coefficients[j].next_every(stride, || {
+                    let cutoff = params.at(FREQ_PARAM, i)
+                        * voct_to_freq_scale(
+                            key + contour * params.at(CONTOUR_PARAM, i) / 100.0
+                                * 512.0
+                                * 12.0
+                                * 4.0,
+                        );
+                    let resonance = params.at(RES_PARAM, i);
+                    let resonance = resonance * resonance * 10.0;
+                    if !approx {
+                        ladder.set_params(cutoff, resonance);
+                    }
+                    filter.coefficients(cutoff, resonance)
+                }));
+                let drive = params.at(DRIVE_PARAM, i);
+                let out =
+                    filter.process_all(softclip(drive * input[IN_INPUT].data[i].data[j].to_f32()));
+                output[LPF_OUTPUT].data[i].data[j] = if approx {
+                    i16::from_f32_soft(out.lowpass)
+                } else {
+                    ladder.set_drive(drive);
+                    ladder.process(input[IN_INPUT].data[i].data[j])
+                };
+                for (jack, v) in [
+                    (HPF_OUTPUT, out.highpass),
+                    (BPF_OUTPUT, out.bandpass),
+                    (NOTCH_OUTPUT, out.notch),
+                ] {
+                    output[jack].data[i].data[j] = i16::from_f32_soft(v);
+                }
+            }
+        }
+        self.quiet = !output
+            .iter()
+            .fold(0, |loud, packet| loud | packet.loud_channels());
+    }
+}
@@ -0,0 +1,11 @@
+/*! The DSP of the modules that run both on the desktop and on the boards.
+
+Each engine is a `Processor` with its jacks and knobs declared by `define_module!`, and knows
+nothing about where it runs. The desktop examples put them behind a window, and the stm32 crate
+behind the switches and pots of a front panel, so the two don't drift apart.
+*/
+#![no_std]
+
+pub mod envelope;
+pub mod filter;
+pub mod oscillator;
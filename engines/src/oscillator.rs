@@ -0,0 +1,207 @@
+use apiary_core::{
+    define_module,
+    dsp::{
+        mix::Unison,
+        oscillators::{linear_fm, SyncMode, Table, WtOscillator},
+    },
+    voct_to_frequency_table, BlockContext, ParamBlock, ProcessBlock, Processor, BLOCK_SIZE,
+    CHANNELS,
+};
+
+define_module! {
+    name: "oscillator",
+    color: 125,
+    inputs: {
+        IN_INPUT: "Input" as Pitch,
+        LEVEL_INPUT: "Level" as Unipolar,
+        POSITION_INPUT: "Position" as Bipolar,
+        SYNC_INPUT: "Sync" as Gate,
+        FM_INPUT: "FM",
+        WIDTH_INPUT: "Pulse Width" as Bipolar,
+    },
+    outputs: {
+        SIN_OUTPUT: "Sin",
+        TRI_OUTPUT: "Tri",
+        SAW_OUTPUT: "Saw",
+        SQR_OUTPUT: "Sqr",
+        MORPH_OUTPUT: "Morph",
+    },
+    params: {
+        LEVEL_PARAM: "Level" { min: 0.0, max: 1.0, default: 1.0, unit: "", log: false },
+        RANGE_PARAM: "Range" {
+            min: -12.0,
+            max: 12.0,
+            default: 0.0,
+            unit: " semitones",
+            log: false,
+        },
+        POSITION_PARAM: "Position" { min: 0.0, max: 1.0, default: 0.0, unit: "", log: false },
+        SYNC_PARAM: "Hard/Soft Sync" { min: 0.0, max: 1.0, default: 0.0, unit: "", log: false },
+        FM_INDEX_PARAM: "FM Index" { min: 0.0, max: 4.0, default: 0.0, unit: "", log: false },
+        WIDTH_PARAM: "Pulse Width" { min: 0.0, max: 1.0, default: 0.5, unit: "", log: false },
+        UNISON_PARAM: "Unison" {
+            min: 1.0,
+            max: CHANNELS as f32,
+            default: 1.0,
+            unit: " voices",
+            log: false,
+        },
+        DETUNE_PARAM: "Detune" {
+            min: 0.0,
+            max: 1.0,
+            default: 0.2,
+            unit: " semitones",
+            log: false,
+        },
+        SPREAD_PARAM: "Spread" { min: 0.0, max: 1.0, default: 0.5, unit: "", log: false },
+    },
+}
+
+// The morph output sweeps through these tables as the position goes from 0 to 1
+const MORPH_TABLES: [Table<'static>; 4] = [Table::Sin, Table::Tri, Table::Saw, Table::Sqr];
+
+pub struct Oscillator {
+    osc: [WtOscillator; CHANNELS],
+    morph: [WtOscillator; CHANNELS],
+    unison: Unison,
+    level: f32,
+    approx: bool,
+}
+
+impl Default for Oscillator {
+    fn default() -> Self {
+        Oscillator {
+            osc: [Default::default(); CHANNELS],
+            morph: [Default::default(); CHANNELS],
+            unison: Default::default(),
+            level: 0.0,
+            approx: false,
+        }
+    }
+}
+
+impl Oscillator {
+    /// Run the fixed point approximations in place of the band-limited tables, for boards too
+    /// slow to run the full oscillator on every channel.
+    ///
+    /// Only the tri, saw and square outputs are made, without unison, FM or band-limited sync,
+    /// and the pulse width follows the knob alone.
+    pub fn approx(mut self) -> Self {
+        self.approx = true;
+        self
+    }
+
+    fn process_approx(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+        sync_mode: SyncMode,
+    ) {
+        for osc in self.osc.iter_mut() {
+            osc.set_pulse_width(params[WIDTH_PARAM]);
+        }
+        let active = block.active_channels(LEVEL_INPUT);
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            for j in 0..CHANNELS {
+                output[SIN_OUTPUT].data[i].data[j] = 0;
+                output[MORPH_OUTPUT].data[i].data[j] = 0;
+                if active & (1 << j) == 0 {
+                    output[TRI_OUTPUT].data[i].data[j] = 0;
+                    output[SAW_OUTPUT].data[i].data[j] = 0;
+                    output[SQR_OUTPUT].data[i].data[j] = 0;
+                    continue;
+                }
+                let level = input[LEVEL_INPUT].data[i].data[j] >> 1;
+                let freq = voct_to_frequency_table(
+                    input[IN_INPUT].data[i].data[j],
+                    &context.tuning,
+                    context.note_frequencies.as_ref(),
+                );
+                self.osc[j].sync(input[SYNC_INPUT].data[i].data[j], sync_mode);
+                let (_, tri, saw, sqr) = self.osc[j].process_approx_fp(level, freq);
+                output[TRI_OUTPUT].data[i].data[j] = tri;
+                output[SAW_OUTPUT].data[i].data[j] = saw;
+                output[SQR_OUTPUT].data[i].data[j] = sqr;
+            }
+        }
+    }
+}
+
+impl Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Oscillator {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        let sync_mode = if params[SYNC_PARAM] < 0.5 {
+            SyncMode::Hard
+        } else {
+            SyncMode::Soft
+        };
+        if self.approx {
+            self.process_approx(block, params, context, sync_mode);
+            return;
+        }
+        self.unison.set_params(
+            params[UNISON_PARAM] as usize,
+            params[DETUNE_PARAM],
+            params[SPREAD_PARAM],
+        );
+        // Channels with nothing on their level input would only make silence
+        let silent: [bool; CHANNELS] =
+            core::array::from_fn(|j| !block.is_channel_active(LEVEL_INPUT, self.unison.source(j)));
+        let input = block.inputs();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            self.level += 0.0025 * (params[LEVEL_PARAM] - self.level);
+            for (j, &silent) in silent.iter().enumerate() {
+                if silent {
+                    for packet in output.iter_mut() {
+                        packet.data[i].data[j] = 0;
+                    }
+                    continue;
+                }
+                // Idle channels play detuned copies of the voices when stacked in unison
+                let src = self.unison.source(j);
+                let level = input[LEVEL_INPUT].data[i].data[src] as f32 * self.unison.gain(j);
+                let sync = input[SYNC_INPUT].data[i].data[src];
+                let freq = linear_fm(
+                    voct_to_frequency_table(
+                        input[IN_INPUT].data[i].data[src],
+                        &context.tuning,
+                        context.note_frequencies.as_ref(),
+                    ),
+                    input[FM_INPUT].data[i].data[src],
+                    params.at(FM_INDEX_PARAM, i),
+                ) * self.unison.ratio(j);
+                self.osc[j].set_pulse_width(
+                    params.at(WIDTH_PARAM, i)
+                        + input[WIDTH_INPUT].data[i].data[src] as f32 / i16::MAX as f32,
+                );
+                let (sin, tri, saw, sqr) = self.osc[j].process_sync(level, freq, sync, sync_mode);
+                output[SIN_OUTPUT].data[i].data[j] = sin;
+                output[TRI_OUTPUT].data[i].data[j] = tri;
+                output[SAW_OUTPUT].data[i].data[j] = saw;
+                output[SQR_OUTPUT].data[i].data[j] = sqr;
+
+                let position = (params.at(POSITION_PARAM, i)
+                    + input[POSITION_INPUT].data[i].data[src] as f32 / i16::MAX as f32)
+                    .clamp(0.0, 1.0)
+                    * (MORPH_TABLES.len() - 1) as f32;
+                let idx = (position as usize).min(MORPH_TABLES.len() - 2);
+                self.morph[j].sync(sync, sync_mode);
+                output[MORPH_OUTPUT].data[i].data[j] = self.morph[j].process_morph(
+                    MORPH_TABLES[idx],
+                    MORPH_TABLES[idx + 1],
+                    position - idx as f32,
+                    level,
+                    freq,
+                );
+            }
+        }
+    }
+}
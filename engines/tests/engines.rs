@@ -0,0 +1,103 @@
+//! The shared engines run on their own, without a module or frontend around them.
+
+use apiary_core::{
+    AudioPacket, BlockContext, ParamBlock, ProcessBlock, Processor, Quality, BLOCK_SIZE, CHANNELS,
+};
+use apiary_engines::{envelope, filter, oscillator};
+
+fn context() -> BlockContext {
+    BlockContext {
+        time: 0,
+        sample_rate: Default::default(),
+        quality: Quality::Full,
+        tuning: Default::default(),
+        note_frequencies: None,
+        voices: CHANNELS as u8,
+    }
+}
+
+/// A block with only the first channel held at `level`
+fn first_channel(level: i16) -> AudioPacket {
+    let mut packet = AudioPacket::default();
+    for frame in packet.data.iter_mut() {
+        frame.data[0] = level;
+    }
+    packet
+}
+
+#[test]
+fn envelope_opens_with_the_gate() {
+    let mut engine = envelope::Envelope::default();
+    let params = ParamBlock::new(envelope::SPEC.params.map(|p| p.default));
+    let gate = first_channel(i16::MAX);
+    let mut level = AudioPacket::default();
+    for _ in 0..10 {
+        let mut block = ProcessBlock::new([&gate], [&mut level], [true], context());
+        engine.process(&mut block, &params, &context());
+    }
+    assert!(level.data[BLOCK_SIZE - 1].data[0] > 0);
+    assert_eq!(level.data[BLOCK_SIZE - 1].data[1], 0);
+}
+
+#[test]
+fn filter_passes_low_frequencies() {
+    let mut values = filter::SPEC.params.map(|p| p.default);
+    values[filter::RES_PARAM] = 0.0;
+    let params = ParamBlock::new(values);
+    let input = first_channel(10000);
+    let silence = AudioPacket::default();
+    // The ladder lowpass, and the state variable one that stands in for it on the boards
+    for mut engine in [
+        filter::Filter::default(),
+        filter::Filter::default().approx(),
+    ] {
+        let mut outputs = [AudioPacket::default(); filter::NUM_OUTPUTS];
+        for _ in 0..50 {
+            let [lpf, hpf, bpf, notch] = &mut outputs;
+            let mut block = ProcessBlock::new(
+                [&input, &silence, &silence],
+                [lpf, hpf, bpf, notch],
+                [true; filter::NUM_OUTPUTS],
+                context(),
+            )
+            .with_activity([1, 0, 0]);
+            engine.process(&mut block, &params, &context());
+        }
+        let last =
+            |output: usize, channel: usize| outputs[output].data[BLOCK_SIZE - 1].data[channel];
+        assert!(last(filter::LPF_OUTPUT, 0) > 5000);
+        assert!(last(filter::HPF_OUTPUT, 0).abs() < 1000);
+        // Channels with nothing coming in are left silent
+        assert_eq!(last(filter::LPF_OUTPUT, 1), 0);
+    }
+}
+
+#[test]
+fn approximate_oscillator_skips_the_wavetables() {
+    let mut engine = oscillator::Oscillator::default().approx();
+    let params = ParamBlock::new(oscillator::SPEC.params.map(|p| p.default));
+    let silence = AudioPacket::default();
+    let level = first_channel(i16::MAX);
+    let mut outputs = [AudioPacket::default(); oscillator::NUM_OUTPUTS];
+    for _ in 0..10 {
+        let [sin, tri, saw, sqr, morph] = &mut outputs;
+        let mut block = ProcessBlock::new(
+            [&silence, &level, &silence, &silence, &silence, &silence],
+            [sin, tri, saw, sqr, morph],
+            [true; oscillator::NUM_OUTPUTS],
+            context(),
+        )
+        .with_activity([0, 1, 0, 0, 0, 0]);
+        engine.process(&mut block, &params, &context());
+    }
+    let playing = |output: usize, channel: usize| {
+        outputs[output]
+            .data
+            .iter()
+            .any(|frame| frame.data[channel] != 0)
+    };
+    assert!(playing(oscillator::TRI_OUTPUT, 0));
+    assert!(!playing(oscillator::SIN_OUTPUT, 0));
+    assert!(!playing(oscillator::MORPH_OUTPUT, 0));
+    assert!(!playing(oscillator::TRI_OUTPUT, 1));
+}
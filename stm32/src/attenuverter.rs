@@ -0,0 +1,148 @@
+use apiary_core::{
+    dsp::mix::attenuvert, time::Clock, BlockContext, InputJackHandle, Module, Network,
+    OutputJackHandle, ParamBlock, PollUpdate, ProcessBlock, Processor, BLOCK_SIZE, CHANNELS,
+};
+use embedded_hal::digital::v2::InputPin;
+use palette::Srgb;
+use rand_core::RngCore;
+
+use crate::ui::{ParamConditioner, Switch, Taper};
+
+const NUM_LANES: usize = 4;
+
+pub const NUM_INPUTS: usize = NUM_LANES;
+pub const NUM_OUTPUTS: usize = NUM_LANES + 1;
+pub const NUM_PARAMS: usize = 2 * NUM_LANES;
+pub const COLOR: u16 = 40;
+pub const NAME: &str = "attenuverter";
+
+pub struct AttenuverterPins<S> {
+    pub in1: S,
+    pub in2: S,
+    pub in3: S,
+    pub in4: S,
+    pub output: S,
+}
+
+pub struct Attenuverter<S> {
+    in1: Switch<S>,
+    in2: Switch<S>,
+    in3: Switch<S>,
+    in4: Switch<S>,
+    output: Switch<S>,
+    jack_inputs: [InputJackHandle; NUM_LANES],
+    jack_outputs: [OutputJackHandle; NUM_LANES],
+    jack_mix: OutputJackHandle,
+    // Gains on the first four knobs, then offsets on the last four
+    knobs: [ParamConditioner; NUM_PARAMS],
+}
+
+impl<S: InputPin> Attenuverter<S> {
+    pub fn new<T, R, C>(
+        pins: AttenuverterPins<S>,
+        module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS, C>,
+    ) -> Self
+    where
+        T: Network<NUM_INPUTS, NUM_OUTPUTS>,
+        R: RngCore,
+        C: Clock,
+    {
+        Attenuverter {
+            in1: Switch::new(pins.in1),
+            in2: Switch::new(pins.in2),
+            in3: Switch::new(pins.in3),
+            in4: Switch::new(pins.in4),
+            output: Switch::new(pins.output),
+            jack_inputs: [(); NUM_LANES].map(|_| module.add_input_jack().unwrap()),
+            jack_outputs: [(); NUM_LANES].map(|_| module.add_output_jack().unwrap()),
+            jack_mix: module.add_output_jack().unwrap(),
+            knobs: [(); NUM_PARAMS].map(|_| ParamConditioner::new(-1.0, 1.0, Taper::Linear)),
+        }
+    }
+
+    pub fn poll_ui<T, R, C>(&mut self, module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS, C>)
+    where
+        T: Network<NUM_INPUTS, NUM_OUTPUTS>,
+        R: RngCore,
+        C: Clock,
+    {
+        self.in1.debounce();
+        self.in2.debounce();
+        self.in3.debounce();
+        self.in4.debounce();
+        self.output.debounce();
+
+        if self.in1.changed()
+            || self.in2.changed()
+            || self.in3.changed()
+            || self.in4.changed()
+            || self.output.changed()
+        {
+            // There are only switches for the inputs and the mix, so a lane's own output is
+            // patched by holding the output switch along with the lane's input switch
+            let lanes = [
+                (self.in1.just_pressed(), self.in1.pressed()),
+                (self.in2.just_pressed(), self.in2.pressed()),
+                (self.in3.just_pressed(), self.in3.pressed()),
+                (self.in4.just_pressed(), self.in4.pressed()),
+            ];
+            let mut chord = false;
+            for (lane, (just_pressed, pressed)) in lanes.into_iter().enumerate() {
+                let lane_output = (just_pressed && self.output.pressed())
+                    || (pressed && self.output.just_pressed());
+                chord |= lane_output;
+                module
+                    .set_input_patch_enabled(self.jack_inputs[lane], just_pressed && !lane_output)
+                    .unwrap();
+                module
+                    .set_output_patch_enabled(self.jack_outputs[lane], lane_output)
+                    .unwrap();
+            }
+            module
+                .set_output_patch_enabled(self.jack_mix, self.output.just_pressed() && !chord)
+                .unwrap();
+        }
+    }
+
+    pub fn set_params(&mut self, adc: &mut [u16; 8], params: &mut ParamBlock<NUM_PARAMS>) {
+        for (i, knob) in self.knobs.iter_mut().enumerate() {
+            params.set(i, knob.update(adc[i]));
+        }
+    }
+
+    pub fn get_light_data(&self, update: PollUpdate<NUM_INPUTS, NUM_OUTPUTS>) -> [Srgb<u8>; 5] {
+        [
+            update.get_input_color(self.jack_inputs[0]),
+            update.get_input_color(self.jack_inputs[1]),
+            update.get_input_color(self.jack_inputs[2]),
+            update.get_input_color(self.jack_inputs[3]),
+            update.get_output_color(self.jack_mix),
+        ]
+    }
+}
+
+impl<S> Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Attenuverter<S> {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
+    ) {
+        for i in 0..BLOCK_SIZE {
+            for j in 0..CHANNELS {
+                let mut mix = 0;
+                for lane in 0..NUM_LANES {
+                    let out = attenuvert(
+                        block.get_input(self.jack_inputs[lane]).data[i].data[j],
+                        params.at(lane, i),
+                        params.at(NUM_LANES + lane, i),
+                    );
+                    block.get_mut_output(self.jack_outputs[lane]).data[i].data[j] = out;
+                    mix += out as i32;
+                }
+                block.get_mut_output(self.jack_mix).data[i].data[j] =
+                    mix.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Runs an engine on the desktop in place of the board, with the front panel emulated from the
+//! terminal (see `apiary::emulator` for the commands):
+//!
+//! ```text
+//! cargo run --bin emulator --no-default-features --features emulator --target <host triple>
+//! ```
+
+use log::info;
+use simple_logger::SimpleLogger;
+use std::{fmt::Write, sync::mpsc::TryRecvError, thread};
+
+use apiary::emulator::{self, Panel};
+use apiary::ui::{ParamCompare, ParamPresets};
+use apiary_core::{
+    socket_native::NativeInterface,
+    time::{Duration, StdClock},
+    Module, ModuleDescription, ParamBlock, Processor, RamStorage, Uuid,
+};
+
+use apiary::filter as engine;
+use apiary::filter::{Filter, FilterPins};
+// use apiary::oscillator as engine;
+// use apiary::oscillator::{Oscillator, OscillatorPins};
+// use apiary::envelope as engine;
+// use apiary::envelope::{Envelope, EnvelopePins};
+// use apiary::attenuverter as engine;
+// use apiary::attenuverter::{Attenuverter, AttenuverterPins};
+// use apiary::logic as engine;
+// use apiary::logic::{Logic, LogicPins};
+// use apiary::recorder as engine;
+// use apiary::recorder::{Recorder, RecorderPins};
+
+/// How often the lights are checked for changes to print, so that meters don't flood the terminal
+const LIGHT_INTERVAL: Duration = Duration::from_millis(100);
+
+fn main() {
+    SimpleLogger::new()
+        .with_level(log::LevelFilter::Info)
+        .without_timestamps()
+        .init()
+        .unwrap();
+
+    // Several emulators can run side by side, so tell them apart by process
+    let mut uuid = Uuid::default();
+    write!(uuid, "emulator:{}:{}", engine::NAME, std::process::id()).unwrap();
+    info!("Emulating {} as {}", engine::NAME, uuid);
+
+    let mut module: Module<_, _, { engine::NUM_INPUTS }, { engine::NUM_OUTPUTS }, _> =
+        Module::with_clock(
+            NativeInterface::new().unwrap(),
+            rand::thread_rng(),
+            uuid.clone(),
+            engine::COLOR,
+            StdClock::new(),
+        );
+    module.set_description(ModuleDescription::new(engine::NAME, engine::COLOR));
+
+    // Switches are numbered in the order they're wired up here
+    let mut panel = Panel::default();
+    let filter_pins = FilterPins {
+        input: panel.switch(),
+        key_track: panel.switch(),
+        contour: panel.switch(),
+        output: panel.switch(),
+    };
+    let mut en = Filter::new(filter_pins, &mut module);
+    // let oscillator_pins = OscillatorPins {
+    //     input: panel.switch(),
+    //     level: panel.switch(),
+    //     tri: panel.switch(),
+    //     saw: panel.switch(),
+    //     sqr: panel.switch(),
+    // };
+    // let mut en = Oscillator::new(oscillator_pins, &mut module);
+    // let envelope_pins = EnvelopePins {
+    //     gate: panel.switch(),
+    //     level: panel.switch(),
+    // };
+    // let mut en = Envelope::new(envelope_pins, &mut module);
+    // let attenuverter_pins = AttenuverterPins {
+    //     in1: panel.switch(),
+    //     in2: panel.switch(),
+    //     in3: panel.switch(),
+    //     in4: panel.switch(),
+    //     output: panel.switch(),
+    // };
+    // let mut en = Attenuverter::new(attenuverter_pins, &mut module);
+    // let logic_pins = LogicPins {
+    //     a: panel.switch(),
+    //     b: panel.switch(),
+    //     and: panel.switch(),
+    //     or: panel.switch(),
+    //     xor: panel.switch(),
+    // };
+    // let mut en = Logic::new(logic_pins, &mut module);
+    // let recorder_pins = RecorderPins {
+    //     cv: panel.switch(),
+    //     clock: panel.switch(),
+    //     record: panel.switch(),
+    //     overdub: panel.switch(),
+    //     output: panel.switch(),
+    // };
+    // let mut en = Recorder::new(recorder_pins, &mut module);
+
+    module.finalize_jacks().unwrap();
+    let mut params = ParamBlock::new([0.0; engine::NUM_PARAMS]);
+    let mut compare: ParamCompare<{ engine::NUM_PARAMS }> = Default::default();
+    let mut storage: RamStorage<0> = Default::default();
+    let mut presets: ParamPresets<{ engine::NUM_PARAMS }> = Default::default();
+    let mut lights = None;
+
+    let commands = emulator::read_commands();
+
+    loop {
+        match commands.try_recv() {
+            Ok(command) => panel.apply(command),
+            Err(TryRecvError::Empty) => {}
+            Err(TryRecvError::Disconnected) => break,
+        }
+
+        // The same steps as each cycle on the board, with the pots read straight off the panel
+        while let Some(time) = module.next_tick() {
+            en.poll_ui(&mut module);
+            match module.poll(time, |block| {
+                let context = block.context();
+                en.process(block, &params, &context);
+                params.next_block();
+            }) {
+                Ok(update) => {
+                    for action in update.compare_actions() {
+                        compare.apply(action, &params);
+                    }
+                    let knobs = core::array::from_fn(|i| params.target(i));
+                    match update.apply_presets(&mut storage, &knobs) {
+                        Ok(Some(values)) => presets.recall(values),
+                        Ok(None) => {}
+                        Err(e) => info!("Preset error: {:?}", e),
+                    }
+                    let light_data = en.get_light_data(update);
+                    if time.is_multiple_of(LIGHT_INTERVAL) && lights != Some(light_data) {
+                        info!("Lights: {:?}", light_data);
+                        lights = Some(light_data);
+                    }
+                }
+                Err(e) => info!("Data send error: {:?}", e),
+            }
+
+            let mut adc = panel.pots;
+            en.set_params(&mut adc, &mut params);
+            presets.hold(&mut params);
+            compare.hold(&mut params);
+        }
+        let wait = module.until_next_tick().as_millis() as u64;
+        thread::sleep(std::time::Duration::from_millis(wait));
+    }
+}
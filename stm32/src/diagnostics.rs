@@ -0,0 +1,133 @@
+//! Bring-up checks for a new board, run in place of its engine.
+//!
+//! Every output jack carries a known signal, inputs with anything on them are echoed back out,
+//! the LEDs are stepped through each color one at a time, and the state of the network is logged
+//! every second. Start it by holding the user button through reset, or build with the
+//! `diagnostics` feature.
+
+use apiary_core::{
+    time::{Clock, MonotonicTime},
+    AudioFrame, BlockContext, Module, Network, ParamBlock, ProcessBlock, Processor, Status,
+    BLOCK_SIZE, CHANNELS,
+};
+use core::f32::consts::PI;
+use libm::sinf;
+use palette::Srgb;
+use rand_core::RngCore;
+
+/// Frequency of the test tone on the even outputs, in Hz
+const TEST_TONE: f32 = 1000.0;
+/// Peak of the test tone as a fraction of full scale, which is -6 dBFS
+const TONE_LEVEL: f32 = 0.5;
+/// Period of the full scale ramp on the odd outputs, in seconds, slow enough to follow on a meter
+const RAMP_PERIOD: f32 = 4.0;
+/// Inputs peaking above this since the last report are echoed to the output with the same index
+const ECHO_THRESHOLD: i16 = 64;
+/// How long each LED stays lit, in ms
+const LED_STEP: i64 = 250;
+const NUM_LIGHTS: usize = 5;
+const LED_COLORS: [Srgb<u8>; 4] = [
+    Srgb::new(255, 0, 0),
+    Srgb::new(0, 255, 0),
+    Srgb::new(0, 0, 255),
+    Srgb::new(255, 255, 255),
+];
+
+pub struct Diagnostics<const I: usize> {
+    tone_phase: f32,
+    ramp_phase: f32,
+    /// Loudest sample on each input since the last report
+    peaks: [i16; I],
+    status: Status,
+}
+
+impl<const I: usize> Default for Diagnostics<I> {
+    fn default() -> Self {
+        Diagnostics {
+            tone_phase: 0.0,
+            ramp_phase: 0.0,
+            peaks: [0; I],
+            status: Status::LinkDown,
+        }
+    }
+}
+
+impl<const I: usize> Diagnostics<I> {
+    /// One LED lit at a time in each color in turn, so that a dead color or LEDs chained in the
+    /// wrong order stand out. The status is kept for the next report.
+    pub fn light_data(&mut self, time: MonotonicTime, status: Status) -> [Srgb<u8>; NUM_LIGHTS] {
+        self.status = status;
+        let step = (i64::from(time) / LED_STEP) as usize;
+        let mut lights = [Srgb::new(0, 0, 0); NUM_LIGHTS];
+        lights[step % NUM_LIGHTS] = LED_COLORS[(step / NUM_LIGHTS) % LED_COLORS.len()];
+        lights
+    }
+
+    /// Log everything known about the network and the inputs, and start the input peaks over.
+    pub fn report<T, R, C, const O: usize>(&mut self, module: &Module<T, R, I, O, C>)
+    where
+        T: Network<I, O>,
+        R: RngCore,
+        C: Clock,
+    {
+        info!("Diagnostics: {:?}", self.status);
+        info!("  link up:       {}", module.is_link_up());
+        match module.address() {
+            Some(addr) => info!("  address:       {:?}", addr),
+            None => info!("  address:       waiting for DHCP"),
+        }
+        info!("  peers:         {}", module.peers().count());
+        info!("  coordinator:   {}", module.is_coordinator());
+        info!("  sample rate:   {:?}", module.sample_rate());
+        info!("  latency:       {} blocks", module.latency());
+        info!("  send failures: {}", module.send_failures());
+        info!("  receive:       {:?}", module.receive_stats());
+        info!("  bandwidth:     {:?}", module.bandwidth_stats());
+        info!("  input peaks:   {:?}", self.peaks);
+        self.peaks = [0; I];
+    }
+}
+
+impl<const I: usize, const O: usize, const P: usize> Processor<I, O, P> for Diagnostics<I> {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<I, O>,
+        _params: &ParamBlock<P>,
+        context: &BlockContext,
+    ) {
+        let input = block.inputs();
+        for (peak, packet) in self.peaks.iter_mut().zip(input.iter()) {
+            for frame in packet.data.iter() {
+                for x in frame.data.iter() {
+                    *peak = (*peak).max(x.saturating_abs());
+                }
+            }
+        }
+        let echo = self.peaks.map(|peak| peak > ECHO_THRESHOLD);
+        let dt = context.sample_rate.dt();
+        let output = block.outputs();
+        for i in 0..BLOCK_SIZE {
+            let tone = (TONE_LEVEL * sinf(2.0 * PI * self.tone_phase) * i16::MAX as f32) as i16;
+            let ramp = ((2.0 * self.ramp_phase - 1.0) * i16::MAX as f32) as i16;
+            self.tone_phase += TEST_TONE * dt;
+            if self.tone_phase >= 1.0 {
+                self.tone_phase -= 1.0;
+            }
+            self.ramp_phase += dt / RAMP_PERIOD;
+            if self.ramp_phase >= 1.0 {
+                self.ramp_phase -= 1.0;
+            }
+            for (k, packet) in output.iter_mut().enumerate() {
+                packet.data[i] = match input.get(k) {
+                    Some(echoed) if echo[k] => echoed.data[i],
+                    _ => {
+                        let level = if k % 2 == 0 { tone } else { ramp };
+                        AudioFrame {
+                            data: [level; CHANNELS],
+                        }
+                    }
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+//! Stands in for the front panel of the board on the desktop, so the engines can be run and
+//! played with over the native network before they're flashed.
+//!
+//! The switches and pots are driven by typing commands into the terminal:
+//!
+//! ```text
+//! press 0        hold down the first switch of the engine
+//! release 0      and let go of it again
+//! pot 2 0.75     turn the third pot three quarters of the way up
+//! quit
+//! ```
+
+use core::{cell::Cell, convert::Infallible, str::FromStr};
+use embedded_hal::digital::v2::InputPin;
+use std::{
+    io::{self, BufRead},
+    rc::Rc,
+    sync::mpsc::{channel, Receiver},
+    thread,
+};
+
+use crate::ui::{ADC_HIGH, ADC_LOW};
+
+/// Pots on the board, one per channel in the ADC sequence
+pub const NUM_POTS: usize = 8;
+
+/// A switch on the emulated front panel, which reads high while it's released just like the
+/// board's pulled up GPIOs.
+#[derive(Clone, Default)]
+pub struct SimSwitch {
+    pressed: Rc<Cell<bool>>,
+}
+
+impl InputPin for SimSwitch {
+    type Error = Infallible;
+
+    fn is_high(&self) -> Result<bool, Infallible> {
+        Ok(!self.pressed.get())
+    }
+
+    fn is_low(&self) -> Result<bool, Infallible> {
+        Ok(self.pressed.get())
+    }
+}
+
+pub enum Command {
+    Press(usize),
+    Release(usize),
+    /// Turn a pot to a position between 0 and 1
+    Pot(usize, f32),
+}
+
+impl FromStr for Command {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let command = words.next();
+        let index = words
+            .next()
+            .and_then(|word| word.parse().ok())
+            .ok_or("expected a switch or pot number");
+        match command {
+            Some("press") => Ok(Command::Press(index?)),
+            Some("release") => Ok(Command::Release(index?)),
+            Some("pot") => {
+                let position: f32 = words
+                    .next()
+                    .and_then(|word| word.parse().ok())
+                    .ok_or("expected a position from 0 to 1")?;
+                Ok(Command::Pot(index?, position.clamp(0.0, 1.0)))
+            }
+            _ => Err("expected press, release, pot or quit"),
+        }
+    }
+}
+
+/// The switches and pots of the emulated front panel.
+pub struct Panel {
+    switches: Vec<SimSwitch>,
+    /// Raw ADC readings of the pots, as they're handed to the engine
+    pub pots: [u16; NUM_POTS],
+}
+
+impl Default for Panel {
+    fn default() -> Self {
+        Panel {
+            switches: Vec::new(),
+            // Start every pot at the middle of its travel
+            pots: [(ADC_LOW + ADC_HIGH) / 2; NUM_POTS],
+        }
+    }
+}
+
+impl Panel {
+    /// Add another switch, numbered in the order they're added.
+    pub fn switch(&mut self) -> SimSwitch {
+        let switch = SimSwitch::default();
+        self.switches.push(switch.clone());
+        switch
+    }
+
+    pub fn apply(&mut self, command: Command) {
+        match command {
+            Command::Press(i) | Command::Release(i) => match self.switches.get(i) {
+                Some(switch) => switch.pressed.set(matches!(command, Command::Press(_))),
+                None => info!("There are only {} switches", self.switches.len()),
+            },
+            Command::Pot(i, position) => match self.pots.get_mut(i) {
+                Some(pot) => *pot = ADC_LOW + (position * (ADC_HIGH - ADC_LOW) as f32) as u16,
+                None => info!("There are only {} pots", NUM_POTS),
+            },
+        }
+    }
+}
+
+/// Read commands from the terminal until `quit` or the end of input, when the receiver is
+/// disconnected.
+pub fn read_commands() -> Receiver<Command> {
+    let (tx, rx) = channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            match line.trim() {
+                "" => continue,
+                "quit" => break,
+                command => match command.parse() {
+                    Ok(command) => {
+                        if tx.send(command).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => info!("{}: {}", command, e),
+                },
+            }
+        }
+    });
+    rx
+}
@@ -1,66 +1,76 @@
-use core::iter::zip;
-
 use apiary_core::{
-    dsp::filters::LinearTrap, softclip, voct_to_freq_scale, AudioPacket, InputJackHandle, Module,
-    Network, OutputJackHandle, PollUpdate, ProcessBlock, CHANNELS,
+    time::Clock, BlockContext, Module, Network, ParamBlock, PollUpdate, ProcessBlock, Processor,
+};
+use apiary_engines::filter::{
+    self as engine, Jacks, BPF_OUTPUT, CONTOUR_INPUT, CONTOUR_PARAM, FREQ_PARAM, HPF_OUTPUT,
+    IN_INPUT, KEY_INPUT, LPF_OUTPUT, NOTCH_OUTPUT, RES_PARAM, SPEC,
 };
-use itertools::izip;
-use libm::{log10f, powf};
+use embedded_hal::digital::v2::InputPin;
 use palette::Srgb;
 use rand_core::RngCore;
-use stm32f4xx_hal::gpio;
 
-use crate::ui::Switch;
+use crate::ui::{ParamConditioner, Switch, Taper};
+
+pub use apiary_engines::filter::{COLOR, NAME, NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS};
 
-pub const NUM_INPUTS: usize = 3;
-pub const NUM_OUTPUTS: usize = 1;
-pub const COLOR: u16 = 220;
-pub const NAME: &str = "filter";
+/// The responses picked between with the fourth knob, in order of its travel
+const RESPONSES: [usize; 4] = [LPF_OUTPUT, BPF_OUTPUT, HPF_OUTPUT, NOTCH_OUTPUT];
 
-pub struct FilterPins {
-    pub input: gpio::Pin<'C', 8>,
-    pub key_track: gpio::Pin<'C', 9>,
-    pub contour: gpio::Pin<'D', 12>,
-    pub output: gpio::Pin<'D', 13>,
+pub struct FilterPins<S> {
+    pub input: S,
+    pub key_track: S,
+    pub contour: S,
+    pub output: S,
 }
 
-pub struct Filter {
-    input: Switch<'C', 8>,
-    key_track: Switch<'C', 9>,
-    contour: Switch<'D', 12>,
-    output: Switch<'D', 13>,
-    filters: [LinearTrap; CHANNELS],
-    jack_input: InputJackHandle,
-    jack_key_track: InputJackHandle,
-    jack_contour: InputJackHandle,
-    jack_output: OutputJackHandle,
-    params: [f32; 3],
+pub struct Filter<S> {
+    input: Switch<S>,
+    key_track: Switch<S>,
+    contour: Switch<S>,
+    output: Switch<S>,
+    engine: engine::Filter,
+    jacks: Jacks,
+    knobs: [ParamConditioner; 4],
+    /// The output patched by the single output switch
+    response: usize,
 }
 
-impl Filter {
-    pub fn new<T, R>(pins: FilterPins, module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS>) -> Self
+impl<S: InputPin> Filter<S> {
+    pub fn new<T, R, C>(
+        pins: FilterPins<S>,
+        module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS, C>,
+    ) -> Self
     where
         T: Network<NUM_INPUTS, NUM_OUTPUTS>,
         R: RngCore,
+        C: Clock,
     {
+        module.set_description(SPEC.describe());
         Filter {
             input: Switch::new(pins.input),
             key_track: Switch::new(pins.key_track),
             contour: Switch::new(pins.contour),
             output: Switch::new(pins.output),
-            filters: Default::default(),
-            jack_input: module.add_input_jack().unwrap(),
-            jack_key_track: module.add_input_jack().unwrap(),
-            jack_contour: module.add_input_jack().unwrap(),
-            jack_output: module.add_output_jack().unwrap(),
-            params: [0.0; 3],
+            engine: engine::Filter::default().approx(),
+            jacks: Jacks::add(module).unwrap(),
+            knobs: [
+                ParamConditioner::new(20.0, 8000.0, Taper::Log),
+                ParamConditioner::new(0.0, 1.0, Taper::Linear),
+                ParamConditioner::new(0.0, 100.0, Taper::Linear),
+                // Split the travel evenly between the responses, without waiting on smoothing
+                ParamConditioner::new(0.0, 4.0, Taper::Linear)
+                    .deadband(0)
+                    .smoothing(1.0),
+            ],
+            response: LPF_OUTPUT,
         }
     }
 
-    pub fn poll_ui<T, R>(&mut self, module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS>)
+    pub fn poll_ui<T, R, C>(&mut self, module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS, C>)
     where
         T: Network<NUM_INPUTS, NUM_OUTPUTS>,
         R: RngCore,
+        C: Clock,
     {
         self.input.debounce();
         self.key_track.debounce();
@@ -97,66 +107,60 @@ impl Filter {
             || self.output.changed()
         {
             module
-                .set_input_patch_enabled(self.jack_input, self.input.just_pressed())
+                .set_input_patch_enabled(self.jacks.inputs[IN_INPUT], self.input.just_pressed())
                 .unwrap();
             module
-                .set_input_patch_enabled(self.jack_key_track, self.key_track.just_pressed())
+                .set_input_patch_enabled(
+                    self.jacks.inputs[KEY_INPUT],
+                    self.key_track.just_pressed(),
+                )
                 .unwrap();
             module
-                .set_input_patch_enabled(self.jack_contour, self.contour.just_pressed())
+                .set_input_patch_enabled(
+                    self.jacks.inputs[CONTOUR_INPUT],
+                    self.contour.just_pressed(),
+                )
                 .unwrap();
             module
-                .set_output_patch_enabled(self.jack_output, self.output.just_pressed())
+                .set_output_patch_enabled(
+                    self.jacks.outputs[self.response],
+                    self.output.just_pressed(),
+                )
                 .unwrap();
         }
     }
 
-    pub fn process(&mut self, block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>) {
-        // Processing time is too slow to do this every audio frame...
-        for i in 0..CHANNELS {
-            self.filters[i].set_params(
-                self.params[0]
-                    * voct_to_freq_scale(
-                        block.get_input(self.jack_key_track).data[0].data[i] as f32
-                            + block.get_input(self.jack_contour).data[0].data[i] as f32
-                                / i16::MAX as f32
-                                * self.params[2]
-                                * 512.0
-                                * 12.0
-                                * 4.0,
-                    ),
-                self.params[1],
-            );
+    pub fn set_params(&mut self, adc: &mut [u16; 8], params: &mut ParamBlock<NUM_PARAMS>) {
+        // Knobs the panel doesn't have stay where the engine starts them
+        for (i, spec) in SPEC.params.iter().enumerate() {
+            params.set(i, spec.default);
         }
-        let mut output: AudioPacket = Default::default();
-        for (fin, fout) in zip(
-            block.get_input(self.jack_input).data,
-            output.data.iter_mut(),
-        ) {
-            for (iin, iout, filter) in
-                izip!(fin.data, fout.data.iter_mut(), self.filters.iter_mut())
-            {
-                *iout = (softclip(filter.process(iin as f32 / i16::MAX as f32)) * i16::MAX as f32)
-                    as i16;
-            }
-        }
-        block.set_output(self.jack_output, output);
-    }
-
-    pub fn set_params(&mut self, adc: &mut [u16; 8]) {
-        self.params[0] += 0.01
-            * (20.0 * powf(10.0, (adc[0] as f32 / 4096.0) * log10f(8000.0 / 20.0))
-                - self.params[0]);
-        self.params[1] = powf(adc[1] as f32 / 4096.0, 2.0) * 10.0;
-        self.params[2] = adc[2] as f32 / 4096.0;
+        params.set(FREQ_PARAM, self.knobs[0].update(adc[0]));
+        params.set(RES_PARAM, self.knobs[1].update(adc[1]));
+        params.set(CONTOUR_PARAM, self.knobs[2].update(adc[2]));
+        // Every response has its own jack, and the fourth knob picks which one the output switch
+        // patches
+        let response = self.knobs[3].update(adc[3]) as usize;
+        self.response = RESPONSES[response.min(RESPONSES.len() - 1)];
     }
 
     pub fn get_light_data(&self, update: PollUpdate<NUM_INPUTS, NUM_OUTPUTS>) -> [Srgb<u8>; 4] {
         [
-            update.get_input_color(self.jack_key_track),
-            update.get_input_color(self.jack_contour),
-            update.get_input_color(self.jack_input),
-            update.get_output_color(self.jack_output),
+            update.get_input_color(self.jacks.inputs[KEY_INPUT]),
+            update.get_input_color(self.jacks.inputs[CONTOUR_INPUT]),
+            update.get_input_color(self.jacks.inputs[IN_INPUT]),
+            update.get_output_color(self.jacks.outputs[self.response]),
         ]
     }
 }
+
+impl<S> Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Filter<S> {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        self.engine.process(block, params, context);
+    }
+}
@@ -0,0 +1,451 @@
+use panic_semihosting as _;
+
+use stm32f4xx_hal::{
+    adc::{
+        config::{AdcConfig, Clock, Continuous, Dma, SampleTime, Scan, Sequence},
+        Adc,
+    },
+    dma::{config, StreamsTuple, Transfer},
+    gpio::{GpioExt, NoPin},
+    pac::{CorePeripherals, Peripherals},
+    prelude::*,
+    rcc::{Clocks, RccExt},
+    signature::Uid,
+    spi::Spi,
+};
+
+use core::{fmt::Debug, fmt::Write, hash::Hash};
+use cortex_m::peripheral::{DCB, DWT};
+use fugit::RateExtU32;
+use hash32::{FnvHasher, Hasher};
+
+use stm32_eth::{EthPins, RingEntry};
+
+#[cfg(feature = "seeded-rng")]
+use apiary_core::SeededRng;
+use apiary_core::{
+    socket_smoltcp::SmoltcpInterface,
+    time::{self, Duration, MonotonicTime},
+    CatchUpPolicy, Module, ModuleDescription, OverrunPolicy, ParamBlock, Processor, Quality,
+    RamStorage, Status, Uuid,
+};
+
+use crate::filter as engine;
+use crate::filter::{Filter, FilterPins};
+// use crate::oscillator as engine;
+// use crate::oscillator::{Oscillator, OscillatorPins};
+// use crate::envelope as engine;
+// use crate::envelope::{Envelope, EnvelopePins};
+// use crate::attenuverter as engine;
+// use crate::attenuverter::{Attenuverter, AttenuverterPins};
+// use crate::logic as engine;
+// use crate::logic::{Logic, LogicPins};
+// use crate::recorder as engine;
+// use crate::recorder::{Recorder, RecorderPins};
+
+use crate::apa102::{self, Apa102};
+use crate::diagnostics::Diagnostics;
+use crate::serial_logger;
+use crate::ui::{ParamCompare, ParamPresets};
+
+/// Address of the LAN8742A PHY on the Nucleo board
+const PHY_ADDR: u8 = 0;
+/// PHY basic status register
+const PHY_BSR: u8 = 1;
+const PHY_BSR_LINK_UP: u16 = 1 << 2;
+const PHY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// How often the module is polled in standby, when there is no audio to keep up with
+const STANDBY_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+pub fn start() -> ! {
+    let p = Peripherals::take().unwrap();
+    let cp = CorePeripherals::take().unwrap();
+
+    let rcc = p.RCC.constrain();
+    let clocks = rcc
+        .cfgr
+        .use_hse(8.MHz())
+        .sysclk(168.MHz())
+        .require_pll48clk()
+        .freeze();
+
+    let gpioa = p.GPIOA.split();
+    let gpiob = p.GPIOB.split();
+    let gpioc = p.GPIOC.split();
+    let gpiod = p.GPIOD.split();
+    let gpiof = p.GPIOF.split();
+    let gpiog = p.GPIOG.split();
+
+    serial_logger::init(gpiod.pd8, p.USART3, p.DMA1, &clocks);
+
+    #[cfg(not(feature = "seeded-rng"))]
+    let rand_source = p.RNG.constrain(&clocks);
+
+    let sck = gpioc.pc10.into_alternate();
+    let miso = NoPin;
+    let mosi = gpioc.pc12.into_alternate();
+
+    let spi = Spi::new(p.SPI3, (sck, miso, mosi), apa102::MODE, 32.MHz(), &clocks);
+    let mut apa = Apa102::new(spi).pixel_order(apa102::PixelOrder::RBG);
+    apa.set_intensity(8);
+
+    info!("Enabling ethernet...");
+    let eth_pins = EthPins {
+        ref_clk: gpioa.pa1,
+        crs: gpioa.pa7,
+        tx_en: gpiog.pg11,
+        tx_d0: gpiog.pg13,
+        tx_d1: gpiob.pb13,
+        rx_d0: gpioc.pc4,
+        rx_d1: gpioc.pc5,
+    };
+
+    let mut rx_ring: [RingEntry<_>; 16] = Default::default();
+    let mut tx_ring: [RingEntry<_>; 16] = Default::default();
+    // The PHY's management interface, for reading the link state
+    let mut mdio = gpioa.pa2.into_alternate::<11>();
+    let mut mdc = gpioc.pc1.into_alternate::<11>();
+    let (mut eth_dma, mut eth_mac) = stm32_eth::new(
+        p.ETHERNET_MAC,
+        p.ETHERNET_MMC,
+        p.ETHERNET_DMA,
+        &mut rx_ring[..],
+        &mut tx_ring[..],
+        clocks,
+        eth_pins,
+    )
+    .unwrap();
+
+    // There's no need to wait for the link here, since the PHY is polled from the main loop and
+    // DHCP starts over whenever the link comes up
+    let mut cycle_timer = p.TIM5.counter_us(&clocks);
+
+    // Derive the mac address and module id from the unique device id
+    let mut s = FnvHasher::default();
+    Uid::get().hash(&mut s);
+    let val = s.finish32();
+    let bval = val.to_ne_bytes();
+    let mac = [0x00, 0x00, bval[0], bval[1], bval[2], bval[3]];
+
+    info!("Setting mac address to: {:?}", mac);
+
+    let mut uuid = Uuid::default();
+    write!(uuid, "hardware:{}:{:#08x}", engine::NAME, val).unwrap();
+
+    #[cfg(feature = "seeded-rng")]
+    let rand_source = SeededRng::from_uuid(&uuid);
+
+    let mut storage = Default::default();
+    let mut interface = SmoltcpInterface::<
+        _,
+        { engine::NUM_INPUTS },
+        { engine::NUM_OUTPUTS },
+        { engine::NUM_INPUTS + engine::NUM_OUTPUTS + 1 },
+    >::new(&mut eth_dma, mac, &mut storage);
+    // Spread the output jacks over the wait for the next cycle instead of sending them all at once
+    interface.set_pacing(Some(1));
+    let clock = CycleClock::new(cp.DCB, cp.DWT, &clocks);
+    let mut module: Module<_, _, { engine::NUM_INPUTS }, { engine::NUM_OUTPUTS }, _> =
+        Module::with_clock(interface, rand_source, uuid.clone(), engine::COLOR, clock);
+    #[cfg(feature = "silence-suppression")]
+    module.set_silence_suppression(true);
+    // Overrun cycles are caught up on without sending a burst of packets
+    module.set_catch_up(CatchUpPolicy {
+        max_polls_per_ms: 2,
+        skip_audio: false,
+    });
+    // Engines made with `define_module!` replace this with a description of their front panel
+    module.set_description(ModuleDescription::new(engine::NAME, engine::COLOR));
+    // Cycles that keep running over shed work instead, rather than glitching at random
+    module.set_overrun_policy(OverrunPolicy {
+        lowest: Quality::Half,
+        ..Default::default()
+    });
+
+    // The switches read high through their pull-ups until pressed, and erasing the pin numbers
+    // lets any of them stand in for any other
+    let filter_pins = FilterPins {
+        input: gpioc.pc8.into_pull_up_input().erase(),
+        key_track: gpioc.pc9.into_pull_up_input().erase(),
+        contour: gpiod.pd12.into_pull_up_input().erase(),
+        output: gpiod.pd13.into_pull_up_input().erase(),
+    };
+    let mut en = Filter::new(filter_pins, &mut module);
+    // let oscillator_pins = OscillatorPins {
+    //     input: gpioc.pc7.into_pull_up_input().erase(),
+    //     level: gpioc.pc8.into_pull_up_input().erase(),
+    //     tri: gpioc.pc9.into_pull_up_input().erase(),
+    //     saw: gpiod.pd12.into_pull_up_input().erase(),
+    //     sqr: gpiod.pd13.into_pull_up_input().erase(),
+    // };
+    // let mut en = Oscillator::new(oscillator_pins, &mut module);
+    // let envelope_pins = EnvelopePins {
+    //     gate: gpiod.pd12.into_pull_up_input().erase(),
+    //     level: gpiod.pd13.into_pull_up_input().erase(),
+    // };
+    // let mut en = Envelope::new(envelope_pins, &mut module);
+    // let attenuverter_pins = AttenuverterPins {
+    //     in1: gpioc.pc7.into_pull_up_input().erase(),
+    //     in2: gpioc.pc8.into_pull_up_input().erase(),
+    //     in3: gpioc.pc9.into_pull_up_input().erase(),
+    //     in4: gpiod.pd12.into_pull_up_input().erase(),
+    //     output: gpiod.pd13.into_pull_up_input().erase(),
+    // };
+    // let mut en = Attenuverter::new(attenuverter_pins, &mut module);
+    // let logic_pins = LogicPins {
+    //     a: gpioc.pc7.into_pull_up_input().erase(),
+    //     b: gpioc.pc8.into_pull_up_input().erase(),
+    //     and: gpioc.pc9.into_pull_up_input().erase(),
+    //     or: gpiod.pd12.into_pull_up_input().erase(),
+    //     xor: gpiod.pd13.into_pull_up_input().erase(),
+    // };
+    // let mut en = Logic::new(logic_pins, &mut module);
+    // let recorder_pins = RecorderPins {
+    //     cv: gpioc.pc7.into_pull_up_input().erase(),
+    //     clock: gpioc.pc8.into_pull_up_input().erase(),
+    //     record: gpioc.pc9.into_pull_up_input().erase(),
+    //     overdub: gpiod.pd12.into_pull_up_input().erase(),
+    //     output: gpiod.pd13.into_pull_up_input().erase(),
+    // };
+    // let mut en = Recorder::new(recorder_pins, &mut module);
+
+    // An engine that adds fewer jacks than its counts would otherwise leave them dead
+    module.finalize_jacks().unwrap();
+    // Knobs are read by the frontend and handed to the engine each block, as on the desktop
+    let mut params = ParamBlock::new([0.0; engine::NUM_PARAMS]);
+    let mut compare: ParamCompare<{ engine::NUM_PARAMS }> = Default::default();
+    // Presets of the knobs are only kept until the next restart, until there's flash storage
+    let mut storage: RamStorage<0> = Default::default();
+    let mut presets: ParamPresets<{ engine::NUM_PARAMS }> = Default::default();
+
+    info!("Sockets created");
+
+    // ADC3 GPIO Configuration
+    // PA0/WKUP ------> ADC3_IN0
+    // PF7      ------> ADC3_IN5
+    // PF8      ------> ADC3_IN6
+    // PF9      ------> ADC3_IN7
+    // PF10     ------> ADC3_IN8
+    // PF3      ------> ADC3_IN9
+    // PF4      ------> ADC3_IN14
+    // PF5      ------> ADC3_IN15
+
+    let adc_config = AdcConfig::default()
+        .dma(Dma::Continuous)
+        .clock(Clock::Pclk2_div_8)
+        .scan(Scan::Enabled)
+        .continuous(Continuous::Single);
+    let adc_dma_config = config::DmaConfig::default()
+        .double_buffer(false)
+        .memory_increment(true);
+
+    let mut adc = Adc::adc3(p.ADC3, true, adc_config);
+    let st = SampleTime::Cycles_480;
+    adc.configure_channel(&gpioa.pa0.into_analog(), Sequence::One, st);
+    adc.configure_channel(&gpiof.pf7.into_analog(), Sequence::Two, st);
+    adc.configure_channel(&gpiof.pf8.into_analog(), Sequence::Three, st);
+    adc.configure_channel(&gpiof.pf9.into_analog(), Sequence::Four, st);
+    adc.configure_channel(&gpiof.pf10.into_analog(), Sequence::Five, st);
+    adc.configure_channel(&gpiof.pf3.into_analog(), Sequence::Six, st);
+    adc.configure_channel(&gpiof.pf4.into_analog(), Sequence::Seven, st);
+    adc.configure_channel(&gpiof.pf5.into_analog(), Sequence::Eight, st);
+
+    let init_adc_buffer = cortex_m::singleton!(: [u16; 8] = [0; 8]).unwrap();
+    let mut adc_transfer = Transfer::init_peripheral_to_memory(
+        StreamsTuple::new(p.DMA2).0,
+        adc,
+        init_adc_buffer,
+        None,
+        adc_dma_config,
+    );
+
+    adc_transfer.start(|adc| adc.start_conversion());
+    let mut adc_buffer = cortex_m::singleton!(: [u16; 8] = [0; 8]).unwrap();
+    adc_buffer = adc_transfer.next_transfer(adc_buffer).unwrap().0;
+    info!("ADC current sample: {:?}", adc_buffer);
+
+    // Holding the user button through reset runs the board checks instead of the engine
+    let user_button = gpioc.pc13.into_pull_down_input();
+    let mut diag = (cfg!(feature = "diagnostics") || user_button.is_high()).then(|| {
+        info!("Starting diagnostics");
+        Diagnostics::default()
+    });
+
+    info!("Starting main loop");
+
+    let mut time = MonotonicTime::default();
+    let mut last_stats: Stats = Default::default();
+    let mut curr_stats: Stats = Default::default();
+    cycle_timer.start(100.millis()).unwrap();
+
+    loop {
+        // We need to have each update occur as close as possible to the 1 ms mark, however (at
+        // least with the serial monitor on), some cycles will end up taking longer. The module's
+        // clock hands out the missed milliseconds straight away so that they're caught up on.
+        time = loop {
+            if let Some(time) = module.next_tick() {
+                break time;
+            }
+            // Nothing is paced out while the outputs are paused in standby
+            if module.is_standby() {
+                continue;
+            }
+            if let Err(e) = module.poll_network(time) {
+                info!("Network poll error: {:?}", e);
+            }
+        };
+        cycle_timer.start(100.millis()).unwrap();
+        curr_stats.total.tic(cycle_timer.now());
+        let start = cycle_timer.now();
+
+        if time.is_multiple_of(PHY_POLL_INTERVAL) {
+            let bsr = eth_mac.smi(&mut mdio, &mut mdc).read(PHY_ADDR, PHY_BSR);
+            module.set_link_up(bsr & PHY_BSR_LINK_UP != 0);
+        }
+
+        if module.is_standby() && !time.is_multiple_of(STANDBY_POLL_INTERVAL) {
+            continue;
+        }
+
+        curr_stats.ui.tic(cycle_timer.now());
+        en.poll_ui(&mut module);
+        curr_stats.ui.toc(cycle_timer.now());
+
+        curr_stats.poll.tic(cycle_timer.now());
+        match module.poll(time, |block| {
+            curr_stats.process.tic(cycle_timer.now());
+            let context = block.context();
+            match &mut diag {
+                Some(d) => d.process(block, &params, &context),
+                None => en.process(block, &params, &context),
+            }
+            params.next_block();
+            curr_stats.process.toc(cycle_timer.now());
+        }) {
+            Ok(update) => {
+                for action in update.compare_actions() {
+                    compare.apply(action, &params);
+                }
+                let knobs = core::array::from_fn(|i| params.target(i));
+                match update.apply_presets(&mut storage, &knobs) {
+                    Ok(Some(values)) => presets.recall(values),
+                    Ok(None) => {}
+                    Err(e) => info!("Preset error: {:?}", e),
+                }
+                let status = update.get_status();
+                let mut light_data = match &mut diag {
+                    Some(d) => d.light_data(time, status),
+                    None => en.get_light_data(update),
+                };
+                // With no network at all the jack colors don't mean anything, so show why instead
+                // (except under diagnostics, which tests the LEDs without a network)
+                if diag.is_none() && matches!(status, Status::LinkDown | Status::Configuring) {
+                    light_data = light_data.map(|_| status.color());
+                }
+                apa.write(light_data.iter().cloned()).unwrap();
+            }
+            Err(e) => info!("Data send error: {:?}", e),
+        }
+        curr_stats.poll.toc(cycle_timer.now());
+
+        curr_stats.adc.tic(cycle_timer.now());
+        adc_transfer.start(|adc| adc.start_conversion());
+        adc_buffer = adc_transfer.next_transfer(adc_buffer).unwrap().0;
+        en.set_params(adc_buffer, &mut params);
+        presets.hold(&mut params);
+        compare.hold(&mut params);
+        curr_stats.adc.toc(cycle_timer.now());
+
+        if time.is_multiple_of(Duration::from_secs(1)) {
+            info!("total, max (us): {:?}", last_stats);
+            if module.quality() != Quality::Full {
+                info!(
+                    "Running at {:?} quality, {} overruns",
+                    module.quality(),
+                    module.overruns()
+                );
+            }
+            info!("ADC current sample: {:?}", adc_buffer);
+            if let Some(d) = &mut diag {
+                d.report(&module);
+            }
+            last_stats = curr_stats;
+            curr_stats = Default::default();
+        }
+        curr_stats.total.toc(cycle_timer.now());
+        module.report_poll_time((cycle_timer.now() - start).to_micros());
+    }
+}
+
+/// The module's clock on the board, counted off the core's cycle counter. The counter wraps
+/// every 25 s or so at full speed, so it has to be read more often than that to keep up.
+struct CycleClock {
+    cycles_per_ms: u64,
+    count: u32,
+    cycles: u64,
+}
+
+impl CycleClock {
+    fn new(mut dcb: DCB, mut dwt: DWT, clocks: &Clocks) -> Self {
+        dcb.enable_trace();
+        dwt.enable_cycle_counter();
+        CycleClock {
+            cycles_per_ms: clocks.sysclk().to_kHz().into(),
+            count: DWT::cycle_count(),
+            cycles: 0,
+        }
+    }
+}
+
+impl time::Clock for CycleClock {
+    fn now(&mut self) -> MonotonicTime {
+        let count = DWT::cycle_count();
+        self.cycles += u64::from(count.wrapping_sub(self.count));
+        self.count = count;
+        MonotonicTime::from_millis((self.cycles / self.cycles_per_ms) as i64)
+    }
+}
+
+#[derive(Default)]
+struct Stats {
+    ui: StatTimer,
+    process: StatTimer,
+    poll: StatTimer,
+    adc: StatTimer,
+    total: StatTimer,
+}
+
+#[derive(Default)]
+struct StatTimer {
+    begin: Option<fugit::Instant<u32, 1_u32, 1000000_u32>>,
+    total: i64,
+    max: i64,
+}
+
+impl StatTimer {
+    fn tic(&mut self, time: fugit::Instant<u32, 1_u32, 1000000_u32>) {
+        self.begin = Some(time);
+    }
+
+    fn toc(&mut self, time: fugit::Instant<u32, 1_u32, 1000000_u32>) {
+        if let Some(begin) = self.begin {
+            let diff = (time - begin).to_micros() as i64;
+            self.total += diff;
+            if diff > self.max {
+                self.max = diff;
+            }
+        }
+    }
+}
+
+impl Debug for Stats {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Stats")
+            .field("ui", &(self.ui.total / 1000, self.ui.max))
+            .field("process", &(self.process.total / 1000, self.process.max))
+            .field("poll", &(self.poll.total / 1000, self.poll.max))
+            .field("adc", &(self.adc.total / 1000, self.adc.max))
+            .field("total", &(self.total.total / 1000, self.total.max))
+            .finish()
+    }
+}
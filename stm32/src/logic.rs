@@ -0,0 +1,152 @@
+use apiary_core::{
+    define_module, dsp::logic::GateLogic, time::Clock, BlockContext, Module, Network, ParamBlock,
+    PollUpdate, ProcessBlock, Processor, BLOCK_SIZE, CHANNELS,
+};
+use embedded_hal::digital::v2::InputPin;
+use palette::Srgb;
+use rand_core::RngCore;
+
+use crate::ui::Switch;
+
+define_module! {
+    name: "logic",
+    color: 300,
+    inputs: {
+        A_INPUT: "Gate A" as Gate,
+        B_INPUT: "Gate B" as Gate,
+    },
+    outputs: {
+        AND_OUTPUT: "And" as Gate,
+        OR_OUTPUT: "Or" as Gate,
+        XOR_OUTPUT: "Xor" as Gate,
+        FLIP_FLOP_OUTPUT: "Flip-Flop" as Gate,
+    },
+    params: {},
+}
+
+pub struct LogicPins<S> {
+    pub a: S,
+    pub b: S,
+    pub and: S,
+    pub or: S,
+    pub xor: S,
+}
+
+pub struct Logic<S> {
+    a: Switch<S>,
+    b: Switch<S>,
+    and: Switch<S>,
+    or: Switch<S>,
+    xor: Switch<S>,
+    logic: [GateLogic; CHANNELS],
+    jacks: Jacks,
+}
+
+impl<S: InputPin> Logic<S> {
+    pub fn new<T, R, C>(
+        pins: LogicPins<S>,
+        module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS, C>,
+    ) -> Self
+    where
+        T: Network<NUM_INPUTS, NUM_OUTPUTS>,
+        R: RngCore,
+        C: Clock,
+    {
+        module.set_description(SPEC.describe());
+        Logic {
+            a: Switch::new(pins.a),
+            b: Switch::new(pins.b),
+            and: Switch::new(pins.and),
+            or: Switch::new(pins.or),
+            xor: Switch::new(pins.xor),
+            logic: Default::default(),
+            jacks: Jacks::add(module).unwrap(),
+        }
+    }
+
+    pub fn poll_ui<T, R, C>(&mut self, module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS, C>)
+    where
+        T: Network<NUM_INPUTS, NUM_OUTPUTS>,
+        R: RngCore,
+        C: Clock,
+    {
+        self.a.debounce();
+        self.b.debounce();
+        self.and.debounce();
+        self.or.debounce();
+        self.xor.debounce();
+
+        if self.a.changed()
+            || self.b.changed()
+            || self.and.changed()
+            || self.or.changed()
+            || self.xor.changed()
+        {
+            // There is no switch for the flip-flop output, so it is patched by holding both the
+            // and and xor switches
+            let flip_flop = (self.and.just_pressed() && self.xor.pressed())
+                || (self.xor.just_pressed() && self.and.pressed());
+            module
+                .set_input_patch_enabled(self.jacks.inputs[A_INPUT], self.a.just_pressed())
+                .unwrap();
+            module
+                .set_input_patch_enabled(self.jacks.inputs[B_INPUT], self.b.just_pressed())
+                .unwrap();
+            module
+                .set_output_patch_enabled(
+                    self.jacks.outputs[AND_OUTPUT],
+                    self.and.just_pressed() && !flip_flop,
+                )
+                .unwrap();
+            module
+                .set_output_patch_enabled(self.jacks.outputs[OR_OUTPUT], self.or.just_pressed())
+                .unwrap();
+            module
+                .set_output_patch_enabled(
+                    self.jacks.outputs[XOR_OUTPUT],
+                    self.xor.just_pressed() && !flip_flop,
+                )
+                .unwrap();
+            module
+                .set_output_patch_enabled(self.jacks.outputs[FLIP_FLOP_OUTPUT], flip_flop)
+                .unwrap();
+        }
+    }
+
+    pub fn set_params(&mut self, _adc: &mut [u16; 8], _params: &mut ParamBlock<NUM_PARAMS>) {}
+
+    pub fn get_light_data(&self, update: PollUpdate<NUM_INPUTS, NUM_OUTPUTS>) -> [Srgb<u8>; 5] {
+        [
+            update.get_input_color(self.jacks.inputs[A_INPUT]),
+            update.get_input_color(self.jacks.inputs[B_INPUT]),
+            update.get_output_color(self.jacks.outputs[AND_OUTPUT]),
+            update.get_output_color(self.jacks.outputs[OR_OUTPUT]),
+            update.get_output_color(self.jacks.outputs[XOR_OUTPUT]),
+        ]
+    }
+}
+
+impl<S> Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Logic<S> {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        _params: &ParamBlock<NUM_PARAMS>,
+        _context: &BlockContext,
+    ) {
+        for i in 0..BLOCK_SIZE {
+            for j in 0..CHANNELS {
+                let out = self.logic[j].process(
+                    block.get_input(self.jacks.inputs[A_INPUT]).data[i].data[j],
+                    block.get_input(self.jacks.inputs[B_INPUT]).data[i].data[j],
+                );
+                block.get_mut_output(self.jacks.outputs[AND_OUTPUT]).data[i].data[j] = out.and;
+                block.get_mut_output(self.jacks.outputs[OR_OUTPUT]).data[i].data[j] = out.or;
+                block.get_mut_output(self.jacks.outputs[XOR_OUTPUT]).data[i].data[j] = out.xor;
+                block
+                    .get_mut_output(self.jacks.outputs[FLIP_FLOP_OUTPUT])
+                    .data[i]
+                    .data[j] = out.flip_flop;
+            }
+        }
+    }
+}
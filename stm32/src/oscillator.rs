@@ -1,70 +1,70 @@
 use apiary_core::{
-    dsp::oscillators::WtOscillator, voct_to_frequency_table, InputJackHandle, Module, Network,
-    OutputJackHandle, PollUpdate, ProcessBlock, BLOCK_SIZE, CHANNELS,
+    time::Clock, AudioPacket, BlockContext, Module, Network, ParamBlock, PollUpdate, ProcessBlock,
+    Processor,
 };
+use apiary_engines::oscillator::{
+    self as engine, Jacks, IN_INPUT, LEVEL_INPUT, SAW_OUTPUT, SPEC, SQR_OUTPUT, SYNC_INPUT,
+    TRI_OUTPUT, WIDTH_PARAM,
+};
+use embedded_hal::digital::v2::InputPin;
 use palette::Srgb;
 use rand_core::RngCore;
-use stm32f4xx_hal::gpio;
 
-use crate::ui::Switch;
+use crate::ui::{ParamConditioner, Switch, Taper};
 
-pub const NUM_INPUTS: usize = 2;
-pub const NUM_OUTPUTS: usize = 3;
-pub const COLOR: u16 = 125;
-pub const NAME: &str = "oscillator";
+pub use apiary_engines::oscillator::{COLOR, NAME, NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS};
 
-pub struct OscillatorPins {
-    pub input: gpio::Pin<'C', 7>,
-    pub level: gpio::Pin<'C', 8>,
-    pub tri: gpio::Pin<'C', 9>,
-    pub saw: gpio::Pin<'D', 12>,
-    pub sqr: gpio::Pin<'D', 13>,
+pub struct OscillatorPins<S> {
+    pub input: S,
+    pub level: S,
+    pub tri: S,
+    pub saw: S,
+    pub sqr: S,
 }
 
-pub struct Oscillator {
-    input: Switch<'C', 7>,
-    level: Switch<'C', 8>,
-    tri: Switch<'C', 9>,
-    saw: Switch<'D', 12>,
-    sqr: Switch<'D', 13>,
-    osc: [WtOscillator; CHANNELS],
-    jack_input: InputJackHandle,
-    jack_level: InputJackHandle,
-    jack_tri: OutputJackHandle,
-    jack_saw: OutputJackHandle,
-    jack_sqr: OutputJackHandle,
-    // params: [f32; 3],
+pub struct Oscillator<S> {
+    input: Switch<S>,
+    level: Switch<S>,
+    tri: Switch<S>,
+    saw: Switch<S>,
+    sqr: Switch<S>,
+    engine: engine::Oscillator,
+    jacks: Jacks,
+    width: ParamConditioner,
 }
 
-impl Oscillator {
-    pub fn new<T, R>(
-        pins: OscillatorPins,
-        module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS>,
+impl<S: InputPin> Oscillator<S> {
+    pub fn new<T, R, C>(
+        pins: OscillatorPins<S>,
+        module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS, C>,
     ) -> Self
     where
         T: Network<NUM_INPUTS, NUM_OUTPUTS>,
         R: RngCore,
+        C: Clock,
     {
+        module.set_description(SPEC.describe());
+        let jacks = Jacks::add(module).unwrap();
+        // With nothing patched in, the oscillator runs at full level
+        module.set_input_normal(jacks.inputs[LEVEL_INPUT], AudioPacket::splat(i16::MAX));
         Oscillator {
             input: Switch::new(pins.input),
             level: Switch::new(pins.level),
             tri: Switch::new(pins.tri),
             saw: Switch::new(pins.saw),
             sqr: Switch::new(pins.sqr),
-            osc: Default::default(),
-            jack_input: module.add_input_jack().unwrap(),
-            jack_level: module.add_input_jack().unwrap(),
-            jack_tri: module.add_output_jack().unwrap(),
-            jack_saw: module.add_output_jack().unwrap(),
-            jack_sqr: module.add_output_jack().unwrap(),
-            // params: [0.0; 3],
+            // Band-limited sync and the wavetables are too slow to run on every channel here
+            engine: engine::Oscillator::default().approx(),
+            jacks,
+            width: ParamConditioner::new(0.0, 1.0, Taper::Linear),
         }
     }
 
-    pub fn poll_ui<T, R>(&mut self, module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS>)
+    pub fn poll_ui<T, R, C>(&mut self, module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS, C>)
     where
         T: Network<NUM_INPUTS, NUM_OUTPUTS>,
         R: RngCore,
+        C: Clock,
     {
         self.input.debounce();
         self.level.debounce();
@@ -78,47 +78,63 @@ impl Oscillator {
             || self.saw.changed()
             || self.sqr.changed()
         {
+            // There is no switch for the sync input, so it is patched by holding both the input
+            // and level switches
+            let sync = (self.input.just_pressed() && self.level.pressed())
+                || (self.level.just_pressed() && self.input.pressed());
+            module
+                .set_input_patch_enabled(
+                    self.jacks.inputs[IN_INPUT],
+                    self.input.just_pressed() && !sync,
+                )
+                .unwrap();
             module
-                .set_input_patch_enabled(self.jack_input, self.input.just_pressed())
+                .set_input_patch_enabled(
+                    self.jacks.inputs[LEVEL_INPUT],
+                    self.level.just_pressed() && !sync,
+                )
                 .unwrap();
             module
-                .set_input_patch_enabled(self.jack_level, self.level.just_pressed())
+                .set_input_patch_enabled(self.jacks.inputs[SYNC_INPUT], sync)
                 .unwrap();
             module
-                .set_output_patch_enabled(self.jack_tri, self.tri.just_pressed())
+                .set_output_patch_enabled(self.jacks.outputs[TRI_OUTPUT], self.tri.just_pressed())
                 .unwrap();
             module
-                .set_output_patch_enabled(self.jack_saw, self.saw.just_pressed())
+                .set_output_patch_enabled(self.jacks.outputs[SAW_OUTPUT], self.saw.just_pressed())
                 .unwrap();
             module
-                .set_output_patch_enabled(self.jack_sqr, self.sqr.just_pressed())
+                .set_output_patch_enabled(self.jacks.outputs[SQR_OUTPUT], self.sqr.just_pressed())
                 .unwrap();
         }
     }
 
-    pub fn process(&mut self, block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>) {
-        for i in 0..BLOCK_SIZE {
-            for j in 0..CHANNELS {
-                let lev = block.get_input(self.jack_level).data[i].data[j] >> 1;
-                let freq =
-                    voct_to_frequency_table(block.get_input(self.jack_input).data[i].data[j]);
-                let (_, tri, saw, sqr) = self.osc[j].process_approx_fp(lev, freq);
-                block.get_mut_output(self.jack_tri).data[i].data[j] = tri;
-                block.get_mut_output(self.jack_saw).data[i].data[j] = saw;
-                block.get_mut_output(self.jack_sqr).data[i].data[j] = sqr;
-            }
+    pub fn set_params(&mut self, adc: &mut [u16; 8], params: &mut ParamBlock<NUM_PARAMS>) {
+        // Knobs the panel doesn't have stay where the engine starts them
+        for (i, spec) in SPEC.params.iter().enumerate() {
+            params.set(i, spec.default);
         }
+        params.set(WIDTH_PARAM, self.width.update(adc[0]));
     }
 
-    pub fn set_params(&mut self, _adc: &mut [u16; 8]) {}
-
     pub fn get_light_data(&self, update: PollUpdate<NUM_INPUTS, NUM_OUTPUTS>) -> [Srgb<u8>; 5] {
         [
-            update.get_input_color(self.jack_input),
-            update.get_input_color(self.jack_level),
-            update.get_output_color(self.jack_tri),
-            update.get_output_color(self.jack_saw),
-            update.get_output_color(self.jack_sqr),
+            update.get_input_color(self.jacks.inputs[IN_INPUT]),
+            update.get_input_color(self.jacks.inputs[LEVEL_INPUT]),
+            update.get_output_color(self.jacks.outputs[TRI_OUTPUT]),
+            update.get_output_color(self.jacks.outputs[SAW_OUTPUT]),
+            update.get_output_color(self.jacks.outputs[SQR_OUTPUT]),
         ]
     }
 }
+
+impl<S> Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Oscillator<S> {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        self.engine.process(block, params, context);
+    }
+}
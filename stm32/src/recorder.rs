@@ -0,0 +1,162 @@
+use apiary_core::{
+    define_module,
+    dsp::recorder::{BoundedRecorder, RecorderInputs},
+    time::Clock,
+    BlockContext, Module, Network, ParamBlock, PollUpdate, ProcessBlock, Processor, BLOCK_SIZE,
+    CHANNELS,
+};
+use embedded_hal::digital::v2::InputPin;
+use palette::Srgb;
+use rand_core::RngCore;
+
+use crate::ui::{ParamConditioner, Switch, Taper};
+
+/// Steps in each channel's loop. The recorders live on the stack, so this is kept to 8 kB for
+/// eight channels, and longer loops just take coarser steps.
+const STEPS: usize = 512;
+
+define_module! {
+    name: "recorder",
+    color: 190,
+    inputs: {
+        CV_INPUT: "CV" as Bipolar,
+        CLOCK_INPUT: "Clock" as Gate,
+        RECORD_INPUT: "Record" as Gate,
+        OVERDUB_INPUT: "Overdub" as Gate,
+    },
+    outputs: {
+        CV_OUTPUT: "CV" as Bipolar,
+    },
+    params: {
+        BARS_PARAM: "Bars" { min: 1.0, max: 16.0, default: 4.0, unit: "", log: false },
+        SMOOTHING_PARAM: "Smoothing" { min: 0.0001, max: 0.5, default: 0.005, unit: "s", log: true },
+    },
+}
+
+pub struct RecorderPins<S> {
+    pub cv: S,
+    pub clock: S,
+    pub record: S,
+    pub overdub: S,
+    pub output: S,
+}
+
+pub struct Recorder<S> {
+    cv: Switch<S>,
+    clock: Switch<S>,
+    record: Switch<S>,
+    overdub: Switch<S>,
+    output: Switch<S>,
+    knobs: [ParamConditioner; NUM_PARAMS],
+    recorders: [BoundedRecorder<STEPS>; CHANNELS],
+    jacks: Jacks,
+}
+
+impl<S: InputPin> Recorder<S> {
+    pub fn new<T, R, C>(
+        pins: RecorderPins<S>,
+        module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS, C>,
+    ) -> Self
+    where
+        T: Network<NUM_INPUTS, NUM_OUTPUTS>,
+        R: RngCore,
+        C: Clock,
+    {
+        module.set_description(SPEC.describe());
+        Recorder {
+            cv: Switch::new(pins.cv),
+            clock: Switch::new(pins.clock),
+            record: Switch::new(pins.record),
+            overdub: Switch::new(pins.overdub),
+            output: Switch::new(pins.output),
+            knobs: [
+                ParamConditioner::new(1.0, 16.0, Taper::Linear),
+                ParamConditioner::new(0.0001, 0.5, Taper::Log),
+            ],
+            recorders: Default::default(),
+            jacks: Jacks::add(module).unwrap(),
+        }
+    }
+
+    pub fn poll_ui<T, R, C>(&mut self, module: &mut Module<T, R, NUM_INPUTS, NUM_OUTPUTS, C>)
+    where
+        T: Network<NUM_INPUTS, NUM_OUTPUTS>,
+        R: RngCore,
+        C: Clock,
+    {
+        self.cv.debounce();
+        self.clock.debounce();
+        self.record.debounce();
+        self.overdub.debounce();
+        self.output.debounce();
+
+        if self.cv.changed()
+            || self.clock.changed()
+            || self.record.changed()
+            || self.overdub.changed()
+            || self.output.changed()
+        {
+            module
+                .set_input_patch_enabled(self.jacks.inputs[CV_INPUT], self.cv.just_pressed())
+                .unwrap();
+            module
+                .set_input_patch_enabled(self.jacks.inputs[CLOCK_INPUT], self.clock.just_pressed())
+                .unwrap();
+            module
+                .set_input_patch_enabled(
+                    self.jacks.inputs[RECORD_INPUT],
+                    self.record.just_pressed(),
+                )
+                .unwrap();
+            module
+                .set_input_patch_enabled(
+                    self.jacks.inputs[OVERDUB_INPUT],
+                    self.overdub.just_pressed(),
+                )
+                .unwrap();
+            module
+                .set_output_patch_enabled(self.jacks.outputs[CV_OUTPUT], self.output.just_pressed())
+                .unwrap();
+        }
+    }
+
+    pub fn set_params(&mut self, adc: &mut [u16; 8], params: &mut ParamBlock<NUM_PARAMS>) {
+        for (i, knob) in self.knobs.iter_mut().enumerate() {
+            params.set(i, knob.update(adc[i]));
+        }
+    }
+
+    pub fn get_light_data(&self, update: PollUpdate<NUM_INPUTS, NUM_OUTPUTS>) -> [Srgb<u8>; 5] {
+        [
+            update.get_input_color(self.jacks.inputs[CV_INPUT]),
+            update.get_input_color(self.jacks.inputs[CLOCK_INPUT]),
+            update.get_input_color(self.jacks.inputs[RECORD_INPUT]),
+            update.get_input_color(self.jacks.inputs[OVERDUB_INPUT]),
+            update.get_output_color(self.jacks.outputs[CV_OUTPUT]),
+        ]
+    }
+}
+
+impl<S> Processor<NUM_INPUTS, NUM_OUTPUTS, NUM_PARAMS> for Recorder<S> {
+    fn process(
+        &mut self,
+        block: &mut ProcessBlock<NUM_INPUTS, NUM_OUTPUTS>,
+        params: &ParamBlock<NUM_PARAMS>,
+        context: &BlockContext,
+    ) {
+        for i in 0..BLOCK_SIZE {
+            let bars = params.at(BARS_PARAM, i).round() as u32;
+            let smoothing = params.at(SMOOTHING_PARAM, i);
+            for j in 0..CHANNELS {
+                let inputs = RecorderInputs {
+                    cv: block.get_input(self.jacks.inputs[CV_INPUT]).data[i].data[j],
+                    clock: block.get_input(self.jacks.inputs[CLOCK_INPUT]).data[i].data[j],
+                    record: block.get_input(self.jacks.inputs[RECORD_INPUT]).data[i].data[j],
+                    overdub: block.get_input(self.jacks.inputs[OVERDUB_INPUT]).data[i].data[j],
+                };
+                block.get_mut_output(self.jacks.outputs[CV_OUTPUT]).data[i].data[j] =
+                    self.recorders[j].process(inputs, bars, smoothing, context.sample_rate);
+            }
+        }
+    }
+}
@@ -1,20 +1,26 @@
-use stm32f4xx_hal::gpio::{self, Output};
+use apiary_core::{CompareAction, ParamBlock};
+use embedded_hal::digital::v2::{InputPin, OutputPin};
+use libm::powf;
 
-pub struct Switch<const P: char, const N: u8> {
-    pin: gpio::Pin<P, N>,
+/// A front panel switch, on any pin that reads high while it's released (on the board, a GPIO
+/// with its pull-up on).
+pub struct Switch<P> {
+    pin: P,
     switch_state: u8,
 }
 
-impl<const P: char, const N: u8> Switch<P, N> {
-    pub fn new(pin: gpio::Pin<P, N>) -> Switch<P, N> {
+impl<P: InputPin> Switch<P> {
+    pub fn new(pin: P) -> Switch<P> {
         Switch {
-            pin: pin.into_pull_up_input(),
+            pin,
             switch_state: 0xff,
         }
     }
 
     pub fn debounce(&mut self) {
-        self.switch_state = (self.switch_state << 1) | (self.pin.is_high() as u8);
+        // A pin that can't be read is taken as released
+        let high = self.pin.is_high().unwrap_or(true);
+        self.switch_state = (self.switch_state << 1) | (high as u8);
     }
 
     pub fn released(&self) -> bool {
@@ -34,25 +40,209 @@ impl<const P: char, const N: u8> Switch<P, N> {
     }
 }
 
-pub struct Led<const P: char, const N: u8> {
-    pin: gpio::Pin<P, N, Output>,
+pub struct Led<P> {
+    pin: P,
     led_state: bool,
 }
 
-impl<const P: char, const N: u8> Led<P, N> {
-    pub fn new(pin: gpio::Pin<P, N>) -> Led<P, N> {
+impl<P: OutputPin> Led<P> {
+    pub fn new(pin: P) -> Led<P> {
         Led {
-            pin: pin.into_push_pull_output(),
+            pin,
             led_state: true,
         }
     }
 
     pub fn toggle(&mut self) {
-        if self.led_state {
-            self.pin.set_high();
+        // There's nothing to be done about a pin that can't be set
+        let _ = if self.led_state {
+            self.pin.set_high()
         } else {
-            self.pin.set_low();
-        }
+            self.pin.set_low()
+        };
         self.led_state = !self.led_state;
     }
 }
+
+/// ADC readings at the ends of pot travel, calibrated against the current board.
+pub(crate) const ADC_LOW: u16 = 0;
+pub(crate) const ADC_HIGH: u16 = 4095;
+
+/// How a conditioned knob position maps onto the parameter range.
+#[derive(Clone, Copy)]
+pub enum Taper {
+    Linear,
+    /// Equal ratios per turn, for frequencies and times. The minimum must be above zero.
+    Log,
+    /// Square law, which approximates an audio taper pot and gives finer control at the low end.
+    Audio,
+}
+
+/// Turns raw ADC readings from a pot into a parameter value.
+///
+/// Each reading is despiked with a median of the last three, scaled by the calibrated ADC range
+/// between `ADC_LOW` and `ADC_HIGH`, snapped to the ends within the deadband, smoothed, and
+/// finally mapped onto the parameter range with the taper.
+pub struct ParamConditioner {
+    min: f32,
+    max: f32,
+    taper: Taper,
+    deadband: u16,
+    smoothing: f32,
+    history: [u16; 3],
+    position: Option<f32>,
+}
+
+impl ParamConditioner {
+    pub fn new(min: f32, max: f32, taper: Taper) -> Self {
+        ParamConditioner {
+            min,
+            max,
+            taper,
+            deadband: 32,
+            smoothing: 0.01,
+            history: [0; 3],
+            position: None,
+        }
+    }
+
+    /// Set how many counts from either end of the travel still read as the end.
+    pub fn deadband(mut self, deadband: u16) -> Self {
+        self.deadband = deadband;
+        self
+    }
+
+    /// Set the one-pole smoothing coefficient applied per reading, where 1 is no smoothing.
+    pub fn smoothing(mut self, smoothing: f32) -> Self {
+        self.smoothing = smoothing;
+        self
+    }
+
+    /// Feed a new ADC reading and return the updated parameter value.
+    pub fn update(&mut self, adc: u16) -> f32 {
+        // Start from the first reading rather than sweeping up from zero at power on
+        if self.position.is_none() {
+            self.history = [adc; 3];
+        }
+        self.history = [self.history[1], self.history[2], adc];
+        let [a, b, c] = self.history;
+        let median = a.max(b).min(a.min(b).max(c));
+
+        let low = ADC_LOW + self.deadband;
+        let high = ADC_HIGH.saturating_sub(self.deadband).max(low + 1);
+        let target = (median.clamp(low, high) - low) as f32 / (high - low) as f32;
+
+        let position = match self.position {
+            Some(position) => position + self.smoothing * (target - position),
+            None => target,
+        };
+        self.position = Some(position);
+        self.value()
+    }
+
+    /// The current parameter value.
+    pub fn value(&self) -> f32 {
+        let x = self.position.unwrap_or(0.0);
+        match self.taper {
+            Taper::Linear => self.min + (self.max - self.min) * x,
+            Taper::Log => self.min * powf(self.max / self.min, x),
+            Taper::Audio => self.min + (self.max - self.min) * x * x,
+        }
+    }
+}
+
+/// A/B comparison of the knob settings, driven by `CompareAction`s from the network.
+///
+/// Pots can't be moved to match a stored setting, so A is held in place of the knob readings
+/// while it is compared or reverted to, and the knobs take over again on the next `Compare`.
+#[derive(Default)]
+pub struct ParamCompare<const P: usize> {
+    stored: Option<[f32; P]>,
+    holding: bool,
+}
+
+impl<const P: usize> ParamCompare<P> {
+    pub fn apply(&mut self, action: CompareAction, params: &ParamBlock<P>) {
+        match action {
+            CompareAction::Store => {
+                let mut stored = [0.0; P];
+                for (i, v) in stored.iter_mut().enumerate() {
+                    *v = params.target(i);
+                }
+                self.stored = Some(stored);
+                self.holding = false;
+            }
+            CompareAction::Compare => self.holding = self.stored.is_some() && !self.holding,
+            CompareAction::Revert => self.holding = self.stored.is_some(),
+        }
+    }
+
+    /// Put A back over the knob readings while it is being held. Call after reading the knobs.
+    pub fn hold(&self, params: &mut ParamBlock<P>) {
+        if let (true, Some(stored)) = (self.holding, &self.stored) {
+            for (i, v) in stored.iter().enumerate() {
+                params.set(i, *v);
+            }
+        }
+    }
+}
+
+/// How far a knob is turned from where it was when a preset was recalled before it takes over
+/// again, as a fraction of its value (or of 1, for knobs with small values).
+const PICKUP: f32 = 0.05;
+
+/// Knob settings recalled from a preset slot, driven by `PresetAction`s from the network.
+///
+/// As with `ParamCompare` the pots can't be moved to match, so each recalled setting is held in
+/// place of its knob reading until that knob is turned, and then the knob picks up from there.
+pub struct ParamPresets<const P: usize> {
+    recalled: [Option<f32>; P],
+    /// Knob readings when the preset came in, taken on the first hold after it
+    pickup: [Option<f32>; P],
+}
+
+impl<const P: usize> Default for ParamPresets<P> {
+    fn default() -> Self {
+        ParamPresets {
+            recalled: [None; P],
+            pickup: [None; P],
+        }
+    }
+}
+
+impl<const P: usize> ParamPresets<P> {
+    pub fn recall(&mut self, values: [f32; P]) {
+        self.recalled = values.map(Some);
+        self.pickup = [None; P];
+    }
+
+    /// Put the recalled settings over the knob readings of knobs that haven't been turned since.
+    /// Call after reading the knobs.
+    pub fn hold(&mut self, params: &mut ParamBlock<P>) {
+        for i in 0..P {
+            if let Some(value) = self.recalled[i] {
+                let reading = params.target(i);
+                let at = *self.pickup[i].get_or_insert(reading);
+                if (reading - at).abs() > PICKUP * at.abs().max(1.0) {
+                    self.recalled[i] = None;
+                } else {
+                    params.set(i, value);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conditioner_starts_at_the_first_reading() {
+        let mut knob = ParamConditioner::new(0.0, 1.0, Taper::Linear).deadband(0);
+        let value = knob.update(ADC_HIGH);
+        assert_eq!(value, 1.0);
+        // A single spike is taken out by the median
+        assert_eq!(knob.update(ADC_LOW), 1.0);
+    }
+}